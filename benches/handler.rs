@@ -0,0 +1,124 @@
+use std::hint::black_box;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use parking_lot::RwLock;
+use redis_starter_rust::redis::{
+    acl::{AccessControl, DEFAULT_USER},
+    clients::{ClientRegistry, ClientState},
+    cmd::{Command, GetArg, SetArg},
+    config::ServerConfig,
+    handler::CommandHandler,
+    memory::MemoryTracker,
+    persistence::PersistenceState,
+    resp::BulkString,
+    store::Store,
+};
+
+const KEYS: usize = 10_000;
+
+fn new_handler(store: Arc<Store>) -> CommandHandler {
+    CommandHandler::new(
+        store,
+        Arc::new(ServerConfig::default()),
+        Arc::new(PersistenceState::new()),
+        Arc::new(MemoryTracker::default()),
+        Arc::new(RwLock::new(AccessControl::new())),
+        Arc::new(ClientRegistry::new()),
+        None,
+    )
+}
+
+fn keys() -> Vec<BulkString> {
+    (0..KEYS)
+        .map(|i| BulkString::from(format!("key:{i:09}")))
+        .collect()
+}
+
+fn set(key: &BulkString) -> Command {
+    Command::Set(SetArg {
+        key: key.clone(),
+        value: "value".into(),
+        expiry: None,
+        condition: None,
+        get: false,
+    })
+}
+
+fn get(key: &BulkString) -> Command {
+    Command::Get(GetArg { key: key.clone() })
+}
+
+fn handler(c: &mut Criterion) {
+    let keys = keys();
+    let mut handler = new_handler(Arc::new(Store::default()));
+    let mut client = ClientState::new(1, Some(DEFAULT_USER.to_string()));
+    for key in &keys {
+        handler.handle(set(key), &mut client).unwrap();
+    }
+
+    let mut group = c.benchmark_group("handler");
+    let mut i = 0;
+    group.bench_function("set", |b| {
+        b.iter(|| {
+            i = (i + 1) % KEYS;
+            handler
+                .handle(black_box(set(&keys[i])), &mut client)
+                .unwrap()
+        })
+    });
+    group.bench_function("get", |b| {
+        b.iter(|| {
+            i = (i + 1) % KEYS;
+            handler
+                .handle(black_box(get(&keys[i])), &mut client)
+                .unwrap()
+        })
+    });
+    group.finish();
+}
+
+/// Measures SET on one handler while other threads hammer the same store, which shows how
+/// much the store's locking costs under contention.
+fn contended(c: &mut Criterion) {
+    let keys = Arc::new(keys());
+    let store = Arc::new(Store::default());
+    let mut handler = new_handler(store.clone());
+    let mut client = ClientState::new(1, Some(DEFAULT_USER.to_string()));
+
+    let mut group = c.benchmark_group("handler/contended");
+    for writers in [1, 3] {
+        group.bench_function(format!("set/{writers}"), |b| {
+            thread::scope(|scope| {
+                let done = Arc::new(AtomicBool::new(false));
+                for t in 0..writers {
+                    let (mut handler, keys, done) =
+                        (new_handler(store.clone()), keys.clone(), done.clone());
+                    scope.spawn(move || {
+                        let mut client = ClientState::new(t + 2, Some(DEFAULT_USER.to_string()));
+                        let mut i = t as usize;
+                        while !done.load(Ordering::Relaxed) {
+                            i = (i + 7) % KEYS;
+                            handler.handle(set(&keys[i]), &mut client).unwrap();
+                        }
+                    });
+                }
+
+                let mut i = 0;
+                b.iter(|| {
+                    i = (i + 1) % KEYS;
+                    handler
+                        .handle(black_box(set(&keys[i])), &mut client)
+                        .unwrap()
+                });
+                done.store(true, Ordering::Relaxed);
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, handler, contended);
+criterion_main!(benches);
@@ -0,0 +1,97 @@
+use std::time::{Duration, Instant};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use redis_starter_rust::redis::{Redis, RedisConfig};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::runtime::Runtime;
+
+/// Requests written before reading their replies, like `redis-benchmark -P 16`.
+const PIPELINE: usize = 16;
+
+const SET: &[u8] = b"*3\r\n$3\r\nSET\r\n$13\r\nkey:000000001\r\n$5\r\nvalue\r\n";
+const GET: &[u8] = b"*2\r\n$3\r\nGET\r\n$13\r\nkey:000000001\r\n";
+
+/// Sends `requests` copies of `request` over the connection and waits for every reply,
+/// which are all `reply_len` bytes long.
+async fn round_trips(stream: &mut TcpStream, request: &[u8], reply_len: usize, requests: u64) {
+    let batch = request.repeat(PIPELINE);
+    let mut replies = vec![0; reply_len * PIPELINE];
+    for _ in 0..requests.div_ceil(PIPELINE as u64) {
+        stream.write_all(&batch).await.unwrap();
+        stream.read_exact(&mut replies).await.unwrap();
+    }
+}
+
+/// Runs `iters` requests spread over the connections, returning how long they took.
+fn run(
+    rt: &Runtime,
+    conns: &mut [TcpStream],
+    request: &'static [u8],
+    reply_len: usize,
+    iters: u64,
+) -> Duration {
+    rt.block_on(async {
+        let per_conn = iters.div_ceil(conns.len() as u64);
+        let start = Instant::now();
+        let tasks: Vec<_> = conns
+            .iter_mut()
+            .map(|stream| round_trips(stream, request, reply_len, per_conn))
+            .collect();
+        join_all(tasks).await;
+        start.elapsed()
+    })
+}
+
+/// Polls every future to completion on the current task so the connections don't need to
+/// be moved into spawned tasks between iterations.
+async fn join_all<F: std::future::Future<Output = ()>>(futures: Vec<F>) {
+    let mut futures: Vec<_> = futures.into_iter().map(Box::pin).collect();
+    std::future::poll_fn(|cx| {
+        futures.retain_mut(|f| f.as_mut().poll(cx).is_pending());
+        if futures.is_empty() {
+            std::task::Poll::Ready(())
+        } else {
+            std::task::Poll::Pending
+        }
+    })
+    .await
+}
+
+fn loopback(c: &mut Criterion) {
+    let rt = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    let server = rt.block_on(Redis::spawn(RedisConfig::default())).unwrap();
+    let addr = server.addr();
+
+    let mut group = c.benchmark_group("loopback");
+    group.throughput(Throughput::Elements(1));
+    for clients in [1, 8] {
+        let mut conns: Vec<_> = rt.block_on(async {
+            let mut conns = Vec::new();
+            for _ in 0..clients {
+                let stream = TcpStream::connect(addr).await.unwrap();
+                stream.set_nodelay(true).unwrap();
+                conns.push(stream);
+            }
+            conns
+        });
+
+        group.bench_function(BenchmarkId::new("ping", clients), |b| {
+            b.iter_custom(|iters| run(&rt, &mut conns, b"*1\r\n$4\r\nPING\r\n", 7, iters))
+        });
+        group.bench_function(BenchmarkId::new("set", clients), |b| {
+            b.iter_custom(|iters| run(&rt, &mut conns, SET, 5, iters))
+        });
+        group.bench_function(BenchmarkId::new("get", clients), |b| {
+            b.iter_custom(|iters| run(&rt, &mut conns, GET, 11, iters))
+        });
+    }
+    group.finish();
+    drop(server);
+}
+
+criterion_group!(benches, loopback);
+criterion_main!(benches);
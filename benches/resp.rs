@@ -0,0 +1,46 @@
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use redis_starter_rust::redis::resp::{Array, BulkString, Value};
+
+/// A `SET key value` request with a value of `len` bytes.
+fn set_request(len: usize) -> Value {
+    Value::Array(Array::new(vec![
+        Value::BulkString("SET".into()),
+        Value::BulkString("key:000000001".into()),
+        Value::BulkString(BulkString::from(vec![b'x'; len])),
+    ]))
+}
+
+fn encode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("resp/encode");
+    for len in [16, 1024, 64 * 1024] {
+        let value = set_request(len);
+        let mut buf = Vec::with_capacity(len + 64);
+        value.encode(&mut buf).unwrap();
+        group.throughput(Throughput::Bytes(buf.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(len), &value, |b, value| {
+            b.iter(|| {
+                buf.clear();
+                black_box(value).encode(&mut buf).unwrap();
+            })
+        });
+    }
+    group.finish();
+}
+
+fn decode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("resp/decode");
+    for len in [16, 1024, 64 * 1024] {
+        let mut buf = Vec::new();
+        set_request(len).encode(&mut buf).unwrap();
+        group.throughput(Throughput::Bytes(buf.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(len), &buf, |b, buf| {
+            b.iter(|| Value::decode(black_box(buf)).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, encode, decode);
+criterion_main!(benches);
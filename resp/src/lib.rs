@@ -0,0 +1,1614 @@
+//! The Redis Serialization Protocol (RESP), with no dependency on std so that it can be
+//! used on its own, e.g. in a browser through WebAssembly or on an embedded client.
+//!
+//! Values are encoded into a [`Sink`]. With the default `std` feature every `io::Write` is
+//! one, without it a `Vec<u8>` or a fixed [`SliceSink`].
+#![no_std]
+
+extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
+
+use alloc::{
+    borrow::ToOwned,
+    format,
+    string::{FromUtf8Error, String, ToString},
+    vec,
+    vec::Vec,
+};
+use core::{
+    fmt::{self, Display},
+    num::ParseIntError,
+    str::FromStr,
+};
+
+use bytes::Bytes;
+use derive_more::{Display, Into};
+use thiserror::Error;
+
+/// EncodeError is returned by Encoder when there are any issues with encoding.
+#[derive(Debug, Error)]
+pub enum EncodeError {
+    /// Io is returned if there are problems with writing to `io::Write`.
+    #[cfg(feature = "std")]
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    /// Full is returned if a fixed-size sink has no room left for the value.
+    #[error("sink is full")]
+    Full,
+
+    /// Format is returned if a value fails to format itself, which shouldn't happen.
+    #[error("format error")]
+    Format,
+}
+
+/// Destination for encoded bytes, which may refuse them, e.g. because it is full.
+pub trait Sink {
+    /// Writes all of the bytes, or fails without a guarantee on how many were written.
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), EncodeError>;
+
+    /// Writes formatted text, which is what `write!` calls.
+    fn write_fmt(&mut self, args: fmt::Arguments<'_>) -> Result<(), EncodeError> {
+        struct Adapter<'a, S: ?Sized> {
+            sink: &'a mut S,
+            error: Option<EncodeError>,
+        }
+
+        impl<S: Sink + ?Sized> fmt::Write for Adapter<'_, S> {
+            fn write_str(&mut self, s: &str) -> fmt::Result {
+                self.sink.write_bytes(s.as_bytes()).map_err(|e| {
+                    self.error = Some(e);
+                    fmt::Error
+                })
+            }
+        }
+
+        let mut adapter = Adapter {
+            sink: self,
+            error: None,
+        };
+        fmt::write(&mut adapter, args).map_err(|_| adapter.error.unwrap_or(EncodeError::Format))
+    }
+}
+
+#[cfg(feature = "std")]
+impl<W: std::io::Write + ?Sized> Sink for W {
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), EncodeError> {
+        self.write_all(bytes)?;
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl Sink for Vec<u8> {
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), EncodeError> {
+        self.extend_from_slice(bytes);
+        Ok(())
+    }
+}
+
+/// A sink over a fixed buffer, for when there is nowhere to allocate.
+#[derive(Debug)]
+pub struct SliceSink<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl<'a> SliceSink<'a> {
+    pub fn new(buf: &'a mut [u8]) -> Self {
+        Self { buf, len: 0 }
+    }
+
+    /// Returns the bytes written so far.
+    pub fn written(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+}
+
+impl Sink for SliceSink<'_> {
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), EncodeError> {
+        let end = self.len + bytes.len();
+        if end > self.buf.len() {
+            return Err(EncodeError::Full);
+        }
+        self.buf[self.len..end].copy_from_slice(bytes);
+        self.len = end;
+        Ok(())
+    }
+}
+
+#[enum_delegate::register]
+trait Encoder {
+    fn _encode(&self, buf: &mut impl Sink) -> Result<(), EncodeError>;
+}
+
+#[derive(Debug, Clone, Error)]
+pub enum DecodeError {
+    #[error("empty bytes")]
+    EmptyBytes,
+
+    #[error("invalid format")]
+    InvalidFormat,
+
+    /// The bytes end before the value does, so more of them have to be read first.
+    #[error("incomplete value")]
+    Incomplete,
+
+    #[error("length mismatch, given {given_len}, actual {actual_len}")]
+    LenMismatch { given_len: usize, actual_len: usize },
+
+    #[error("unknown type {first_byte}")]
+    UnknownType { first_byte: u8 },
+
+    #[error(transparent)]
+    ParseInt(#[from] ParseIntError),
+
+    #[error(transparent)]
+    FromUtf8(#[from] FromUtf8Error),
+}
+
+trait Decoder {
+    fn _decode(buf: &[u8]) -> Result<(Self, usize), DecodeError>
+    where
+        Self: Sized;
+}
+
+/// A valid starting token from Redis Serialization Protocol
+#[derive(Debug, Eq, PartialEq, Clone)]
+#[repr(u8)]
+pub enum Token {
+    Star = b'*',       // Array
+    Dollar = b'$',     // BulkString
+    Plus = b'+',       // SimpleString
+    Minus = b'-',      // SimpleError
+    Colon = b':',      // Integer
+    Greater = b'>',    // Push
+    Percent = b'%',    // Map
+    Tilde = b'~',      // Set
+    Underscore = b'_', // Null
+}
+
+impl From<Token> for char {
+    fn from(val: Token) -> Self {
+        val as u8 as char
+    }
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let c: char = self.clone().into();
+        write!(f, "{c}")
+    }
+}
+
+impl Token {
+    pub fn from(c: char) -> Option<Self> {
+        match c {
+            '*' => Some(Self::Star),
+            '$' => Some(Self::Dollar),
+            '+' => Some(Self::Plus),
+            '-' => Some(Self::Minus),
+            ':' => Some(Self::Colon),
+            '>' => Some(Self::Greater),
+            '%' => Some(Self::Percent),
+            '~' => Some(Self::Tilde),
+            '_' => Some(Self::Underscore),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Into, Display)]
+pub struct SimpleString {
+    s: String,
+}
+
+impl From<&SimpleString> for String {
+    fn from(val: &SimpleString) -> Self {
+        val.s.clone()
+    }
+}
+
+impl From<String> for SimpleString {
+    fn from(value: String) -> Self {
+        Self::new(value)
+    }
+}
+
+impl From<&str> for SimpleString {
+    fn from(value: &str) -> Self {
+        Self::new(value.to_owned())
+    }
+}
+
+impl Encoder for SimpleString {
+    /// Encodes string formatted as `b"+<string>\r\n"`.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(())` if there are no issues with encoding and writing.
+    /// - `EncodeError::...` if there were encoding errors, see the enum variants in order
+    ///   to understand what is the specific error.
+    fn _encode(&self, buf: &mut impl Sink) -> Result<(), EncodeError> {
+        write!(buf, "{}{}\r\n", Token::Plus, self.s)?;
+        Ok(())
+    }
+}
+
+impl Decoder for SimpleString {
+    /// Decodes bytes into SimpleString.
+    /// Expects input to be in the form of `b"+<string>\r\n..."`.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok((SimpleString, usize))` if there are no issues with decoding. The usize represents total bytes read
+    ///   from the buffer while decoding.
+    /// - `DecodeError::...` if there were some decoding errors, see the enum variants in order to
+    ///   understand what is the specific error.
+    fn _decode(buf: &[u8]) -> Result<(Self, usize), DecodeError>
+    where
+        Self: Sized,
+    {
+        let (s, size) = decode_to_string(buf)?;
+        Ok((s.into(), size))
+    }
+}
+
+impl SimpleString {
+    pub fn new(s: String) -> Self {
+        Self { s }
+    }
+
+    /// Returns SimpleString as string.
+    pub fn as_str(&self) -> &str {
+        &self.s
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Display, Into)]
+pub struct SimpleError {
+    s: String,
+}
+
+impl From<&SimpleError> for String {
+    fn from(val: &SimpleError) -> Self {
+        val.s.clone()
+    }
+}
+
+impl From<String> for SimpleError {
+    fn from(value: String) -> Self {
+        Self::new(value)
+    }
+}
+
+impl From<&str> for SimpleError {
+    fn from(value: &str) -> Self {
+        Self::new(value.to_owned())
+    }
+}
+
+impl Encoder for SimpleError {
+    /// Encodes SimpleError formatted as `b"-<string>\r\n`.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(())` if there are no issues with encoding and writing.
+    /// - `EncodeError::...` if there were encoding errors, see the enum variants in order
+    ///   to understand what is the specific error.
+    fn _encode(&self, buf: &mut impl Sink) -> Result<(), EncodeError> {
+        write!(buf, "{}{}\r\n", Token::Minus, self.s)?;
+        Ok(())
+    }
+}
+
+impl Decoder for SimpleError {
+    /// Decodes bytes into SimpleError.
+    /// Expects input to be in the form of `b"-<string>\r\n..."`.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok((SimpleError, usize))` if there are no issues with decoding. The usize represents total bytes read
+    ///   from the buffer while decoding.
+    /// - `DecodeError::...` if there were some decoding errors, see the enum variants in order to
+    ///   understand what is the specific error.
+    fn _decode(buf: &[u8]) -> Result<(Self, usize), DecodeError>
+    where
+        Self: Sized,
+    {
+        let (s, size) = decode_to_string(buf)?;
+        Ok((s.into(), size))
+    }
+}
+
+impl SimpleError {
+    pub fn new(s: String) -> Self {
+        Self { s }
+    }
+
+    /// Returns SimpleError as string.
+    pub fn as_str(&self) -> &str {
+        &self.s
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Display, Into)]
+pub struct Integer {
+    i: i64,
+}
+
+impl From<&Integer> for i64 {
+    fn from(val: &Integer) -> Self {
+        val.i
+    }
+}
+
+impl From<i64> for Integer {
+    fn from(value: i64) -> Self {
+        Self::new(value)
+    }
+}
+
+impl Encoder for Integer {
+    /// Encodes Integer formatted as `b":<integer>\r\n"`.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(())` if there are no issues with encoding and writing.
+    /// - `EncodeError::...` if there were encoding errors, see the enum variants in order
+    ///   to understand what is the specific error.
+    fn _encode(&self, buf: &mut impl Sink) -> Result<(), EncodeError> {
+        write!(buf, "{}{}\r\n", Token::Colon, self.i)?;
+        Ok(())
+    }
+}
+
+impl Decoder for Integer {
+    /// Decodes bytes into Integer.
+    /// Expects input to be in the form of `b":<integer>\r\n..."`.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok((Integer, usize))` if there are no issues with decoding. The usize represents total bytes read
+    ///   from the buffer while decoding.
+    /// - `DecodeError::...` if there were some decoding errors, see the enum variants in order to
+    ///   understand what is the specific error.
+    fn _decode(buf: &[u8]) -> Result<(Self, usize), DecodeError>
+    where
+        Self: Sized,
+    {
+        let (i, size) = decode_to_i64(buf)?;
+        Ok((i.into(), size))
+    }
+}
+
+impl Integer {
+    pub fn new(i: i64) -> Self {
+        Self { i }
+    }
+
+    /// Returns Integer as int64.
+    pub fn as_int(&self) -> i64 {
+        self.i
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BulkString {
+    /// Reference counted, so cloning a value e.g. to reply with it doesn't copy it.
+    bytes: Option<Bytes>,
+}
+
+impl Display for BulkString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.bytes.as_deref())
+    }
+}
+
+impl From<Vec<u8>> for BulkString {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self::new(bytes)
+    }
+}
+
+impl From<Bytes> for BulkString {
+    fn from(bytes: Bytes) -> Self {
+        Self { bytes: Some(bytes) }
+    }
+}
+
+impl From<&str> for BulkString {
+    fn from(s: &str) -> Self {
+        Self::new(s.as_bytes().to_vec())
+    }
+}
+
+impl From<String> for BulkString {
+    fn from(s: String) -> Self {
+        Self::new(s.into_bytes())
+    }
+}
+
+impl FromStr for BulkString {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self::from(s))
+    }
+}
+
+impl Encoder for BulkString {
+    /// Encodes BulkString formatted as `b"$<len>\r\n<data>\r\n"`
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(())` if there are no issues with encoding and writing.
+    /// - `EncodeError::...` if there were encoding errors, see the enum variants in order
+    ///   to understand what is the specific error.
+    fn _encode(&self, buf: &mut impl Sink) -> Result<(), EncodeError> {
+        let bytes = match &self.bytes {
+            Some(b) => b,
+            None => {
+                // Null BulkString
+                write!(buf, "{}-1\r\n", Token::Dollar)?;
+                return Ok(());
+            }
+        };
+
+        write!(buf, "{}{}\r\n", Token::Dollar, bytes.len())?;
+        buf.write_bytes(bytes)?;
+        write!(buf, "\r\n")?;
+        Ok(())
+    }
+}
+
+impl Decoder for BulkString {
+    /// Decodes bytes into BulkString.
+    /// Expects input to be in the form of `b"$<len>\r\n<data>\r\n..."`.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok((BulkString, usize))` if there are no issues with decoding. The usize represents total bytes read
+    ///   from the buffer while decoding.
+    /// - `DecodeError::...` if there were some decoding errors, see the enum variants in order to
+    ///   understand what is the specific error.
+    fn _decode(buf: &[u8]) -> Result<(Self, usize), DecodeError>
+    where
+        Self: Sized,
+    {
+        // Consume `b"$<len>\r\n"`
+        let (bulk_str_len, bytes_consumed) = decode_to_i64(buf)?;
+        if bulk_str_len < 0 {
+            return Ok((BulkString::null(), bytes_consumed));
+        }
+
+        // Consume `<data>\r\n`, reading by length since the data may contain CRLFs.
+        let rest = &buf[bytes_consumed..];
+        let len = bulk_str_len as usize;
+        if rest.len() < len + 2 {
+            return Err(DecodeError::Incomplete);
+        }
+        if &rest[len..len + 2] != b"\r\n" {
+            return Err(DecodeError::LenMismatch {
+                actual_len: read_until_crlf(rest).map_or(rest.len(), |(data, _)| data.len()),
+                given_len: len,
+            });
+        }
+        // The only copy of the data, clones afterwards share it.
+        Ok((
+            Bytes::copy_from_slice(&rest[..len]).into(),
+            bytes_consumed + len + 2,
+        ))
+    }
+}
+
+impl BulkString {
+    pub fn new(bytes: Vec<u8>) -> Self {
+        Self {
+            bytes: Some(bytes.into()),
+        }
+    }
+
+    pub fn null() -> Self {
+        Self { bytes: None }
+    }
+
+    /// Returns BulkString as bytes.
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        self.bytes.as_deref()
+    }
+
+    /// Returns BulkString as string if it can be encoded into a string.
+    /// Otherwise returns None.
+    pub fn as_str(&self) -> Option<String> {
+        if let Some(bytes) = self.as_bytes() {
+            return String::from_utf8(bytes.to_vec()).ok();
+        }
+
+        None
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Array {
+    values: Option<Vec<Value>>,
+}
+
+impl Display for Array {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.values)
+    }
+}
+
+impl From<Vec<Value>> for Array {
+    fn from(values: Vec<Value>) -> Self {
+        Self::new(values)
+    }
+}
+
+impl Array {
+    pub fn new(values: Vec<Value>) -> Self {
+        Self {
+            values: Some(values),
+        }
+    }
+
+    pub fn null() -> Self {
+        Self { values: None }
+    }
+
+    /// Returns list of Values contained in the Array.
+    pub fn values(&self) -> Option<&[Value]> {
+        match &self.values {
+            Some(values) => Some(values),
+            None => None,
+        }
+    }
+}
+
+impl Encoder for Array {
+    /// Encodes Array formatted as`b"$<size>\r\n<element_1>\r\n<element2>\r\n..."`.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(())` if there are no issues with encoding and writing.
+    /// - `EncodeError::...` if there were encoding errors, see the enum variants in order
+    ///   to understand what is the specific error.
+    fn _encode(&self, buf: &mut impl Sink) -> Result<(), EncodeError> {
+        let values = match &self.values {
+            Some(v) => v,
+            None => {
+                write!(buf, "{}-1\r\n", Token::Star)?;
+                return Ok(());
+            }
+        };
+
+        write!(buf, "{}{}\r\n", Token::Star, values.len())?;
+        for val in values {
+            val._encode(buf)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Decoder for Array {
+    /// Decodes bytes into Array.
+    /// Expects input to be in the form of `b"$<size>\r\n<element_1>\r\n<element2>\r\n..."`.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok((Array, usize))` if there are no issues with decoding. The usize represents total bytes read
+    ///   from the buffer while decoding.
+    /// - `DecodeError::...` if there were some decoding errors, see the enum variants in order to
+    ///   understand what is the specific error.
+    fn _decode(buf: &[u8]) -> Result<(Self, usize), DecodeError>
+    where
+        Self: Sized,
+    {
+        // Consume `b"$<size>\r\n"`
+        let (arr_size, mut bytes_consumed) = decode_to_i64(buf)?;
+        if arr_size < 0 {
+            return Ok((Array::null(), bytes_consumed));
+        }
+
+        // Consume the rest of elements
+        let mut values = vec![];
+        for _ in 0..arr_size {
+            let (val, len) = Value::decode_with_len(&buf[bytes_consumed..])?;
+            values.push(val);
+            bytes_consumed += len;
+        }
+
+        Ok((Array::from(values), bytes_consumed))
+    }
+}
+
+/// Out-of-band data sent by the server in RESP3, like an Array but never a reply to a command.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Push {
+    values: Vec<Value>,
+}
+
+impl Display for Push {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.values)
+    }
+}
+
+impl From<Vec<Value>> for Push {
+    fn from(values: Vec<Value>) -> Self {
+        Self::new(values)
+    }
+}
+
+impl Push {
+    pub fn new(values: Vec<Value>) -> Self {
+        Self { values }
+    }
+
+    /// Returns list of Values contained in the Push.
+    pub fn values(&self) -> &[Value] {
+        &self.values
+    }
+}
+
+impl Encoder for Push {
+    /// Encodes Push formatted as `b"><size>\r\n<element_1>\r\n<element2>\r\n..."`.
+    fn _encode(&self, buf: &mut impl Sink) -> Result<(), EncodeError> {
+        write!(buf, "{}{}\r\n", Token::Greater, self.values.len())?;
+        for val in &self.values {
+            val._encode(buf)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Decoder for Push {
+    /// Decodes bytes into Push.
+    /// Expects input to be in the form of `b"><size>\r\n<element_1>\r\n<element2>\r\n..."`.
+    fn _decode(buf: &[u8]) -> Result<(Self, usize), DecodeError>
+    where
+        Self: Sized,
+    {
+        let (size, mut bytes_consumed) = decode_to_i64(buf)?;
+        if size < 0 {
+            return Err(DecodeError::InvalidFormat);
+        }
+
+        let mut values = vec![];
+        for _ in 0..size {
+            let (val, len) = Value::decode_with_len(&buf[bytes_consumed..])?;
+            values.push(val);
+            bytes_consumed += len;
+        }
+
+        Ok((Push::new(values), bytes_consumed))
+    }
+}
+
+/// Pairs of keys and values in RESP3, which RESP2 sends as an Array of every key followed by
+/// its value.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Map {
+    pairs: Vec<(Value, Value)>,
+}
+
+impl Display for Map {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.pairs)
+    }
+}
+
+impl From<Vec<(Value, Value)>> for Map {
+    fn from(pairs: Vec<(Value, Value)>) -> Self {
+        Self::new(pairs)
+    }
+}
+
+impl Map {
+    pub fn new(pairs: Vec<(Value, Value)>) -> Self {
+        Self { pairs }
+    }
+
+    /// Returns the pairs of keys and values contained in the Map.
+    pub fn pairs(&self) -> &[(Value, Value)] {
+        &self.pairs
+    }
+}
+
+impl Encoder for Map {
+    /// Encodes Map formatted as `b"%<size>\r\n<key_1><value_1><key_2><value_2>..."`, where the
+    /// size is the number of pairs.
+    fn _encode(&self, buf: &mut impl Sink) -> Result<(), EncodeError> {
+        write!(buf, "{}{}\r\n", Token::Percent, self.pairs.len())?;
+        for (key, val) in &self.pairs {
+            key._encode(buf)?;
+            val._encode(buf)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Decoder for Map {
+    /// Decodes bytes into Map.
+    /// Expects input to be in the form of `b"%<size>\r\n<key_1><value_1><key_2><value_2>..."`.
+    fn _decode(buf: &[u8]) -> Result<(Self, usize), DecodeError>
+    where
+        Self: Sized,
+    {
+        let (size, mut bytes_consumed) = decode_to_i64(buf)?;
+        if size < 0 {
+            return Err(DecodeError::InvalidFormat);
+        }
+
+        let mut pairs = vec![];
+        for _ in 0..size {
+            let (key, len) = Value::decode_with_len(&buf[bytes_consumed..])?;
+            bytes_consumed += len;
+            let (val, len) = Value::decode_with_len(&buf[bytes_consumed..])?;
+            bytes_consumed += len;
+            pairs.push((key, val));
+        }
+
+        Ok((Map::new(pairs), bytes_consumed))
+    }
+}
+
+/// Elements without order nor duplicates in RESP3, which RESP2 sends as an Array.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Set {
+    values: Vec<Value>,
+}
+
+impl Display for Set {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.values)
+    }
+}
+
+impl From<Vec<Value>> for Set {
+    fn from(values: Vec<Value>) -> Self {
+        Self::new(values)
+    }
+}
+
+impl Set {
+    pub fn new(values: Vec<Value>) -> Self {
+        Self { values }
+    }
+
+    /// Returns list of Values contained in the Set.
+    pub fn values(&self) -> &[Value] {
+        &self.values
+    }
+}
+
+impl Encoder for Set {
+    /// Encodes Set formatted as `b"~<size>\r\n<element_1>\r\n<element2>\r\n..."`.
+    fn _encode(&self, buf: &mut impl Sink) -> Result<(), EncodeError> {
+        write!(buf, "{}{}\r\n", Token::Tilde, self.values.len())?;
+        for val in &self.values {
+            val._encode(buf)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Decoder for Set {
+    /// Decodes bytes into Set.
+    /// Expects input to be in the form of `b"~<size>\r\n<element_1>\r\n<element2>\r\n..."`.
+    fn _decode(buf: &[u8]) -> Result<(Self, usize), DecodeError>
+    where
+        Self: Sized,
+    {
+        let (size, mut bytes_consumed) = decode_to_i64(buf)?;
+        if size < 0 {
+            return Err(DecodeError::InvalidFormat);
+        }
+
+        let mut values = vec![];
+        for _ in 0..size {
+            let (val, len) = Value::decode_with_len(&buf[bytes_consumed..])?;
+            values.push(val);
+            bytes_consumed += len;
+        }
+
+        Ok((Set::new(values), bytes_consumed))
+    }
+}
+
+/// The single null of RESP3, which RESP2 sends as a null BulkString or Array.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Null;
+
+impl Display for Null {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "null")
+    }
+}
+
+impl Encoder for Null {
+    /// Encodes Null formatted as `b"_\r\n"`.
+    fn _encode(&self, buf: &mut impl Sink) -> Result<(), EncodeError> {
+        write!(buf, "{}\r\n", Token::Underscore)?;
+        Ok(())
+    }
+}
+
+impl Decoder for Null {
+    /// Decodes bytes into Null.
+    /// Expects input to be in the form of `b"_\r\n"`.
+    fn _decode(buf: &[u8]) -> Result<(Self, usize), DecodeError>
+    where
+        Self: Sized,
+    {
+        let (s, bytes_consumed) = decode_to_string(buf)?;
+        if !s.is_empty() {
+            return Err(DecodeError::InvalidFormat);
+        }
+
+        Ok((Null, bytes_consumed))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Display)]
+#[enum_delegate::implement(Encoder)]
+pub enum Value {
+    SimpleString(SimpleString),
+    SimpleError(SimpleError),
+    Integer(Integer),
+    BulkString(BulkString),
+    Array(Array),
+    Push(Push),
+    Map(Map),
+    Set(Set),
+    Null(Null),
+}
+
+impl Value {
+    /// Encodes the Value by writing its RESP byte-form into writers.
+    /// See the respective enum variants for the exact RESP format.
+    ///
+    /// # Arguments
+    ///
+    /// - `buf`: A mutable reference to a [`Sink`], e.g. anything implementing `io::Write`. The
+    ///   bytes will be written into this sink.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(())` if there are no problems with the encoding and writing.
+    /// - `EncodeError::...` if there were encoding errors, see the enum variants in order
+    ///   to understand what is the specific error.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use redis_resp as resp;
+    ///
+    /// let value = resp::Value::BulkString(resp::BulkString::from("Something"));
+    /// let mut buf = Vec::new();
+    /// match value.encode(&mut buf) {
+    ///   Ok(_) => println!("All good!"),
+    ///   Err(e) => println!("Oh no, something wrong: {e}"),
+    /// }
+    /// ```
+    ///
+    /// In the above example, we create a BulkString Value and encode it into a Vec.
+    pub fn encode(&self, buf: &mut impl Sink) -> Result<(), EncodeError> {
+        self._encode(buf)
+    }
+
+    /// Decodes the bytes according to RESP into Value.
+    ///
+    /// # Arguments
+    ///
+    /// - `buf`: A reference to the bytes to be decoded, which should be in RESP format.
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(Value)` if there are no problems with the decoding. The `Value` represents the decoded
+    ///   value of the bytes.
+    /// - `DecodeError::...` if there were some decoding errors, see the enum variants in order to
+    ///   understand what is the specific error.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use redis_resp as resp;
+    ///
+    /// let bytes = b"$4\r\nYeah\r\n";
+    /// match resp::Value::decode(bytes) {
+    ///   Ok(val) => println!("All good: {val}"),
+    ///   Err(e) => println!("Oh no, something went wrong: {e}"),
+    /// }
+    /// ```
+    ///
+    /// In the above example, we have a BulkString Value in byte-form and we decode it.
+    pub fn decode(buf: &[u8]) -> Result<Self, DecodeError> {
+        if buf.is_empty() {
+            return Err(DecodeError::EmptyBytes);
+        }
+        let (val, _) = Self::decode_with_len(buf)?;
+        Ok(val)
+    }
+
+    /// Decodes the first value in the bytes, returning it and the number of bytes it took.
+    ///
+    /// Returns `DecodeError::Incomplete` if the bytes end before the value does, e.g. when
+    /// reading from a stream that hasn't delivered all of it yet.
+    pub fn decode_with_len(buf: &[u8]) -> Result<(Self, usize), DecodeError> {
+        if buf.is_empty() {
+            return Err(DecodeError::Incomplete);
+        }
+
+        // Get first byte and match type.
+        // We already checked that buffer length is greater than 0, so can just unwrap.
+        let first_byte = *buf.first().unwrap();
+        match Token::from(first_byte as char) {
+            Some(Token::Plus) => {
+                let (s, size) = SimpleString::_decode(buf)?;
+                Ok((Value::SimpleString(s), size))
+            }
+
+            Some(Token::Minus) => {
+                let (s, size) = SimpleError::_decode(buf)?;
+                Ok((Value::SimpleError(s), size))
+            }
+
+            Some(Token::Colon) => {
+                let (i, size) = Integer::_decode(buf)?;
+                Ok((Value::Integer(i), size))
+            }
+
+            Some(Token::Dollar) => {
+                let (bs, size) = BulkString::_decode(buf)?;
+                Ok((Value::BulkString(bs), size))
+            }
+
+            Some(Token::Star) => {
+                let (arr, size) = Array::_decode(buf)?;
+                Ok((Value::Array(arr), size))
+            }
+
+            Some(Token::Greater) => {
+                let (push, size) = Push::_decode(buf)?;
+                Ok((Value::Push(push), size))
+            }
+
+            Some(Token::Percent) => {
+                let (map, size) = Map::_decode(buf)?;
+                Ok((Value::Map(map), size))
+            }
+
+            Some(Token::Tilde) => {
+                let (set, size) = Set::_decode(buf)?;
+                Ok((Value::Set(set), size))
+            }
+
+            Some(Token::Underscore) => {
+                let (null, size) = Null::_decode(buf)?;
+                Ok((Value::Null(null), size))
+            }
+
+            _ => Err(DecodeError::UnknownType { first_byte }),
+        }
+    }
+
+    pub fn simple_string(&self) -> Option<&SimpleString> {
+        match self {
+            Self::SimpleString(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn simple_error(&self) -> Option<&SimpleError> {
+        match self {
+            Self::SimpleError(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn integer(&self) -> Option<&Integer> {
+        match self {
+            Self::Integer(i) => Some(i),
+            _ => None,
+        }
+    }
+
+    pub fn bulk_string(&self) -> Option<&BulkString> {
+        match self {
+            Self::BulkString(bs) => Some(bs),
+            _ => None,
+        }
+    }
+
+    pub fn array(&self) -> Option<&Array> {
+        match self {
+            Self::Array(arr) => Some(arr),
+            _ => None,
+        }
+    }
+
+    pub fn push(&self) -> Option<&Push> {
+        match self {
+            Self::Push(push) => Some(push),
+            _ => None,
+        }
+    }
+
+    pub fn map(&self) -> Option<&Map> {
+        match self {
+            Self::Map(map) => Some(map),
+            _ => None,
+        }
+    }
+
+    pub fn set(&self) -> Option<&Set> {
+        match self {
+            Self::Set(set) => Some(set),
+            _ => None,
+        }
+    }
+
+    /// Returns whether the Value is a null of either protocol: Null, or a null BulkString
+    /// or Array.
+    pub fn is_null(&self) -> bool {
+        match self {
+            Self::Null(_) => true,
+            Self::BulkString(bs) => bs.as_bytes().is_none(),
+            Self::Array(array) => array.values().is_none(),
+            _ => false,
+        }
+    }
+
+    /// Converts the Value, nested ones included, for a connection speaking RESP version
+    /// `protover`. RESP2 has no null, map nor set, so nulls become null BulkStrings, maps
+    /// Arrays of every key followed by its value, and sets Arrays. RESP3 has a single
+    /// null, which null BulkStrings and Arrays become.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use redis_resp as resp;
+    ///
+    /// let null = resp::Value::BulkString(resp::BulkString::null());
+    /// assert_eq!(null.into_protocol(3), resp::Value::Null(resp::Null));
+    /// ```
+    pub fn into_protocol(self, protover: u8) -> Self {
+        let resp3 = protover >= 3;
+        let convert = |values: Vec<Value>| -> Vec<Value> {
+            values
+                .into_iter()
+                .map(|value| value.into_protocol(protover))
+                .collect()
+        };
+        match self {
+            Self::BulkString(bs) if resp3 && bs.as_bytes().is_none() => Self::Null(Null),
+            Self::Array(Array { values: None }) if resp3 => Self::Null(Null),
+            Self::Array(Array {
+                values: Some(values),
+            }) => Self::Array(convert(values).into()),
+            Self::Push(push) => Self::Push(convert(push.values).into()),
+            Self::Map(map) if resp3 => Self::Map(
+                map.pairs
+                    .into_iter()
+                    .map(|(key, val)| (key.into_protocol(protover), val.into_protocol(protover)))
+                    .collect::<Vec<_>>()
+                    .into(),
+            ),
+            Self::Map(map) => Self::Array(
+                convert(
+                    map.pairs
+                        .into_iter()
+                        .flat_map(|(key, val)| [key, val])
+                        .collect(),
+                )
+                .into(),
+            ),
+            Self::Set(set) if resp3 => Self::Set(convert(set.values).into()),
+            Self::Set(set) => Self::Array(convert(set.values).into()),
+            Self::Null(_) if !resp3 => Self::BulkString(BulkString::null()),
+            value => value,
+        }
+    }
+
+    /// Renders the Value for people to read, like redis-cli: bulk strings are quoted with
+    /// the bytes that aren't printable escaped, and the elements of arrays are numbered,
+    /// nested ones being indented under their number. Pairs of maps are numbered with `#`,
+    /// e.g. `1# "field" => "value"`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use redis_resp as resp;
+    ///
+    /// let value = resp::Value::Array(resp::Array::new(vec![
+    ///     resp::Value::BulkString("GET".into()),
+    ///     resp::Value::BulkString("my key".into()),
+    /// ]));
+    /// assert_eq!(value.to_pretty_string(), "1) \"GET\"\n2) \"my key\"");
+    /// ```
+    pub fn to_pretty_string(&self) -> String {
+        let entries: Vec<(Option<&Value>, &Value)> = match self {
+            Self::SimpleString(s) => return s.as_str().to_string(),
+            Self::SimpleError(e) => return format!("(error) {}", e.as_str()),
+            Self::Integer(i) => return format!("(integer) {}", i.as_int()),
+            Self::BulkString(bs) => return quote(bs),
+            Self::Array(array) => match array.values() {
+                Some(values) => values.iter().map(|value| (None, value)).collect(),
+                None => return "(nil)".to_string(),
+            },
+            Self::Null(_) => return "(nil)".to_string(),
+            Self::Push(push) => push.values().iter().map(|value| (None, value)).collect(),
+            Self::Set(set) => set.values().iter().map(|value| (None, value)).collect(),
+            Self::Map(map) => map
+                .pairs()
+                .iter()
+                .map(|(key, value)| (Some(key), value))
+                .collect(),
+        };
+        if entries.is_empty() {
+            return match self {
+                Self::Map(_) => "(empty hash)",
+                _ => "(empty array)",
+            }
+            .to_string();
+        }
+
+        let width = entries.len().to_string().len();
+        let mut lines = vec![];
+        for (i, (key, value)) in entries.into_iter().enumerate() {
+            let index = match key {
+                Some(key) => format!("{:>width$}# {} => ", i + 1, key.to_pretty_string()),
+                None => format!("{:>width$}) ", i + 1),
+            };
+            for (j, line) in value.to_pretty_string().lines().enumerate() {
+                let prefix = if j == 0 {
+                    index.clone()
+                } else {
+                    " ".repeat(index.len())
+                };
+                lines.push(format!("{prefix}{line}"));
+            }
+        }
+        lines.join("\n")
+    }
+}
+
+/// Quotes the bulk string, escaping bytes that aren't printable.
+fn quote(bs: &BulkString) -> String {
+    let Some(bytes) = bs.as_bytes() else {
+        return "(nil)".to_string();
+    };
+    let mut s = String::from("\"");
+    for &b in bytes {
+        match b {
+            b'\\' => s.push_str("\\\\"),
+            b'"' => s.push_str("\\\""),
+            b'\n' => s.push_str("\\n"),
+            b'\r' => s.push_str("\\r"),
+            b'\t' => s.push_str("\\t"),
+            0x07 => s.push_str("\\a"),
+            0x08 => s.push_str("\\b"),
+            b if b.is_ascii_graphic() || b == b' ' => s.push(b as char),
+            b => s.push_str(&format!("\\x{b:02x}")),
+        }
+    }
+    s.push('"');
+    s
+}
+
+/// Expects input to be in the form of `b"x<string>\r\n..."`, where x is the type of the RESP.
+///
+/// # Returns
+///
+/// - `Ok((String, usize))` if no decoding errors. The `usize` represents total bytes read.
+/// - `DecodeError::...` if there were some decoding errors, see the enum variants in order to
+///   understand what is the specific error.
+fn decode_to_string(bytes: &[u8]) -> Result<(String, usize), DecodeError> {
+    if let Some((b, size)) = read_until_crlf(bytes) {
+        let s = String::from_utf8(b[1..].into())?;
+        return Ok((s, size));
+    }
+
+    Err(DecodeError::Incomplete)
+}
+
+/// Expects input to be in the form of `b"x<i64>\r\n..."`, where x is the type of the RESP.
+///
+/// # Returns
+///
+/// - `Ok((i64, usize))` if no decoding errors. The `usize` represents total bytes read.
+/// - `DecodeError::...` if there were some decoding errors, see the enum variants in order to
+///   understand what is the specific error.
+fn decode_to_i64(bytes: &[u8]) -> Result<(i64, usize), DecodeError> {
+    let (s, size) = decode_to_string(bytes)?;
+
+    Ok((s.parse::<i64>()?, size))
+}
+
+/// Read until the first CRLF.
+///
+/// # Returns
+///
+/// - `Some((&[u8], usize))` if there is a CRLF. The tuple represents the part of the
+///   buffer read and total bytes read.
+/// - `None` if there are no CRLFs in the bytes.
+fn read_until_crlf(buffer: &[u8]) -> Option<(&[u8], usize)> {
+    for i in 1..buffer.len() {
+        if buffer[i - 1] == b'\r' && buffer[i] == b'\n' {
+            return Some((&buffer[0..(i - 1)], i + 1));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod util_test {
+    use super::*;
+
+    #[test]
+    fn read_until_crlf_ok() {
+        match read_until_crlf(b"$4\r\nOK22\r\n") {
+            Some((bytes, len)) => {
+                assert_eq!(bytes, b"$4");
+                assert_eq!(len, 4);
+            }
+            None => panic!("Unexpected read until crlf error"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod decoder_test {
+    use super::*;
+
+    #[test]
+    fn decode_simple_string() {
+        let resp = Value::decode(b"+OK\r\n").expect("Decode simple string unexpected error");
+        match resp {
+            Value::SimpleString(s) => assert_eq!(s.as_str(), "OK"),
+            any => panic!("Wrong type for decode simple string: {:?}", any),
+        }
+    }
+
+    #[test]
+    fn decode_simple_error() {
+        let resp =
+            Value::decode(b"-ERR something\r\n").expect("Decode simple error unexpected error");
+        match resp {
+            Value::SimpleError(s) => assert_eq!(s.as_str(), "ERR something"),
+            any => panic!("Wrong type for decode simple error: {:?}", any),
+        }
+    }
+
+    #[test]
+    fn decode_integer() {
+        let resp = Value::decode(b":123\r\n").expect("Decode integer unexpected error");
+        match resp {
+            Value::Integer(i) => assert_eq!(i.as_int(), 123),
+            any => panic!("Wrong type for decode integer: {:?}", any),
+        }
+    }
+
+    #[test]
+    fn decode_bulk_string() {
+        let resp = Value::decode(b"$4\r\nHell\r\n").expect("Decode bulk string unexpected error");
+        match resp {
+            Value::BulkString(bs) => assert_eq!(bs.as_bytes(), Some("Hell".as_bytes())),
+            any => panic!("Wrong type for decode bulk string: {:?}", any),
+        }
+    }
+
+    #[test]
+    fn decode_bulk_string_mismatch_len() {
+        let err = Value::decode(b"$3\r\nHell\r\n").expect_err("Decode bulk string no error");
+        match err {
+            DecodeError::LenMismatch { .. } => (),
+            any => panic!("Wrong error for decode bulk string: {:?}", any),
+        }
+    }
+
+    #[test]
+    fn decode_bulk_string_with_crlf() {
+        let (resp, len) = Value::decode_with_len(b"$4\r\na\r\nb\r\n:1\r\n")
+            .expect("Decode bulk string unexpected error");
+        assert_eq!(resp, Value::BulkString("a\r\nb".into()));
+        assert_eq!(len, 10);
+    }
+
+    #[test]
+    fn decode_incomplete() {
+        for buf in [
+            &b"$5\r\nHel"[..],
+            b"$5\r\nHello\r",
+            b"*2\r\n:1\r\n",
+            b"*2\r\n:1\r\n+Ye",
+            b":12",
+        ] {
+            let err = Value::decode(buf).expect_err("Decode incomplete no error");
+            assert!(matches!(err, DecodeError::Incomplete), "{buf:?}: {err:?}");
+        }
+    }
+
+    #[test]
+    fn decode_bulk_string_parse_len_error() {
+        let err = Value::decode(b"$Liberty\r\n").expect_err("Decode bulk string no error");
+        match err {
+            DecodeError::ParseInt(_) => (),
+            any => panic!("Wrong error for decode bulk string: {:?}", any),
+        }
+    }
+
+    #[test]
+    fn decode_array() {
+        let resp = Value::decode(b"*2\r\n:12\r\n+Yea\r\n").expect("Decode array unexpected error");
+        match resp {
+            Value::Array(arr) => {
+                let mut iter = arr.values().unwrap().iter();
+                assert_eq!(iter.next().unwrap().integer().unwrap().as_int(), 12);
+                assert_eq!(
+                    iter.next().unwrap().simple_string().unwrap().as_str(),
+                    "Yea"
+                );
+            }
+            _ => panic!("Wrong type for decode array"),
+        }
+    }
+
+    #[test]
+    fn decode_push() {
+        let resp = Value::decode(b">2\r\n$10\r\ninvalidate\r\n*1\r\n$3\r\nkey\r\n")
+            .expect("Decode push unexpected error");
+        let push = resp.push().expect("Wrong type for decode push");
+
+        let mut buf = vec![];
+        resp.encode(&mut buf).expect("Encode push unexpected error");
+        assert_eq!(buf, b">2\r\n$10\r\ninvalidate\r\n*1\r\n$3\r\nkey\r\n");
+        assert_eq!(push.values()[0], Value::BulkString("invalidate".into()));
+    }
+
+    #[test]
+    fn decode_map() {
+        let bytes = b"%2\r\n$1\r\na\r\n:1\r\n$1\r\nb\r\n*1\r\n$1\r\nc\r\n";
+        let resp = Value::decode(bytes).expect("Decode map unexpected error");
+        let map = resp.map().expect("Wrong type for decode map");
+        assert_eq!(
+            map.pairs()[1],
+            (
+                Value::BulkString("b".into()),
+                Value::Array(Array::new(vec![Value::BulkString("c".into())]))
+            )
+        );
+
+        let mut buf = vec![];
+        resp.encode(&mut buf).expect("Encode map unexpected error");
+        assert_eq!(buf, bytes);
+    }
+
+    #[test]
+    fn decode_set_and_null() {
+        let bytes = b"~2\r\n$1\r\na\r\n_\r\n";
+        let resp = Value::decode(bytes).expect("Decode set unexpected error");
+        let set = resp.set().expect("Wrong type for decode set");
+        assert_eq!(
+            set.values(),
+            [Value::BulkString("a".into()), Value::Null(Null)]
+        );
+
+        let mut buf = vec![];
+        resp.encode(&mut buf).expect("Encode set unexpected error");
+        assert_eq!(buf, bytes);
+        assert!(matches!(
+            Value::decode(b"_x\r\n"),
+            Err(DecodeError::InvalidFormat)
+        ));
+    }
+
+    #[test]
+    fn clone_bulk_string_shares_bytes() {
+        let bs = BulkString::from(vec![b'a'; 1024]);
+        let cloned = bs.clone();
+
+        assert_eq!(
+            bs.as_bytes().unwrap().as_ptr(),
+            cloned.as_bytes().unwrap().as_ptr()
+        );
+    }
+
+    #[test]
+    fn decode_array_nested() {
+        let resp = Value::decode(
+            b"*2\r\n*3\r\n:12\r\n+Yea\r\n-Oopsie\r\n*2\r\n$5\r\nHello\r\n$4\r\nGGWP\r\n",
+        )
+        .expect("Decode array unexpected error");
+        match resp {
+            Value::Array(arr) => {
+                let first_values = arr
+                    .values()
+                    .unwrap()
+                    .first()
+                    .unwrap()
+                    .array()
+                    .unwrap()
+                    .values()
+                    .unwrap();
+                assert_eq!(
+                    first_values.first().unwrap().integer().unwrap().as_int(),
+                    12
+                );
+                assert_eq!(
+                    first_values
+                        .get(1)
+                        .unwrap()
+                        .simple_string()
+                        .unwrap()
+                        .as_str(),
+                    "Yea"
+                );
+                assert_eq!(
+                    first_values
+                        .get(2)
+                        .unwrap()
+                        .simple_error()
+                        .unwrap()
+                        .as_str(),
+                    "Oopsie"
+                );
+
+                let second_values = arr
+                    .values()
+                    .unwrap()
+                    .get(1)
+                    .unwrap()
+                    .array()
+                    .unwrap()
+                    .values()
+                    .unwrap();
+                assert_eq!(
+                    second_values
+                        .first()
+                        .unwrap()
+                        .bulk_string()
+                        .unwrap()
+                        .as_bytes(),
+                    Some("Hello".as_bytes())
+                );
+                assert_eq!(
+                    second_values
+                        .get(1)
+                        .unwrap()
+                        .bulk_string()
+                        .unwrap()
+                        .as_bytes(),
+                    Some("GGWP".as_bytes())
+                );
+            }
+            _ => panic!("Wrong type for decode array"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod encoder_test {
+    use super::*;
+
+    #[test]
+    fn encode_into_slice() {
+        let value = Value::Array(Array::new(vec![
+            Value::BulkString("GET".into()),
+            Value::BulkString("key".into()),
+        ]));
+
+        let mut buf = [0; 22];
+        let mut sink = SliceSink::new(&mut buf);
+        value.encode(&mut sink).expect("Encode unexpected error");
+        assert_eq!(sink.written(), b"*2\r\n$3\r\nGET\r\n$3\r\nkey\r\n");
+
+        let mut buf = [0; 21];
+        let err = value
+            .encode(&mut SliceSink::new(&mut buf))
+            .expect_err("Encode no error");
+        assert!(matches!(err, EncodeError::Full));
+    }
+
+    #[test]
+    fn encode_by_protocol() {
+        let bulk = |s: &str| Value::BulkString(s.into());
+        let value = Value::Array(Array::new(vec![
+            Value::BulkString(BulkString::null()),
+            Value::Array(Array::null()),
+            Value::Map(Map::new(vec![(bulk("a"), bulk("1"))])),
+            Value::Set(Set::new(vec![bulk("b")])),
+        ]));
+        let encode = |value: Value| {
+            let mut buf = vec![];
+            value.encode(&mut buf).expect("Encode unexpected error");
+            buf
+        };
+
+        assert_eq!(
+            encode(value.clone().into_protocol(3)),
+            b"*4\r\n_\r\n_\r\n%1\r\n$1\r\na\r\n$1\r\n1\r\n~1\r\n$1\r\nb\r\n"
+        );
+        assert_eq!(
+            encode(value.into_protocol(2)),
+            b"*4\r\n$-1\r\n*-1\r\n*2\r\n$1\r\na\r\n$1\r\n1\r\n*1\r\n$1\r\nb\r\n"
+        );
+        assert_eq!(
+            Value::Null(Null).into_protocol(2),
+            Value::BulkString(BulkString::null())
+        );
+    }
+}
+
+#[cfg(test)]
+mod pretty_test {
+    use super::*;
+
+    #[test]
+    fn pretty_scalars() {
+        assert_eq!(
+            Value::SimpleString(SimpleString::from("OK")).to_pretty_string(),
+            "OK"
+        );
+        assert_eq!(
+            Value::SimpleError(SimpleError::from("ERR nope")).to_pretty_string(),
+            "(error) ERR nope"
+        );
+        assert_eq!(
+            Value::Integer(Integer::new(-3)).to_pretty_string(),
+            "(integer) -3"
+        );
+        assert_eq!(
+            Value::BulkString(BulkString::null()).to_pretty_string(),
+            "(nil)"
+        );
+        assert_eq!(
+            Value::BulkString(b"a \"b\"\n\x01".to_vec().into()).to_pretty_string(),
+            r#""a \"b\"\n\x01""#
+        );
+        assert_eq!(Value::Array(Array::null()).to_pretty_string(), "(nil)");
+        assert_eq!(
+            Value::Array(Array::new(vec![])).to_pretty_string(),
+            "(empty array)"
+        );
+    }
+
+    #[test]
+    fn pretty_nested_arrays() {
+        let mut values: Vec<Value> = (1..=9).map(|i| Value::Integer(Integer::new(i))).collect();
+        values.push(Value::Array(Array::new(vec![
+            Value::BulkString("a".into()),
+            Value::BulkString("b".into()),
+        ])));
+        let pretty = Value::Array(Array::new(values)).to_pretty_string();
+        let lines: Vec<&str> = pretty.lines().collect();
+        assert_eq!(lines[0], " 1) (integer) 1");
+        assert_eq!(lines[9], "10) 1) \"a\"");
+        assert_eq!(lines[10], "    2) \"b\"");
+    }
+
+    #[test]
+    fn pretty_maps() {
+        let map = Value::Map(Map::new(vec![
+            (Value::BulkString("a".into()), Value::BulkString("1".into())),
+            (
+                Value::BulkString("b".into()),
+                Value::Integer(Integer::new(2)),
+            ),
+        ]));
+        assert_eq!(
+            map.to_pretty_string(),
+            "1# \"a\" => \"1\"\n2# \"b\" => (integer) 2"
+        );
+        assert_eq!(
+            Value::Map(Map::new(vec![])).to_pretty_string(),
+            "(empty hash)"
+        );
+    }
+}
@@ -0,0 +1,171 @@
+use std::io::{self, Write};
+
+use thiserror::Error;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt};
+
+use crate::redis::client::RedisClient;
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum SplitError {
+    #[error("Invalid argument(s)")]
+    UnbalancedQuotes,
+}
+
+/// Prompts for commands on `input`, runs them on the server and writes their replies to
+/// `output`, until the input ends or reads `quit` or `exit`.
+pub async fn run(
+    mut client: RedisClient,
+    input: impl AsyncBufRead + Unpin,
+    mut output: impl Write,
+) -> io::Result<()> {
+    let prompt = format!("{}> ", client.addr());
+    let mut lines = input.lines();
+    loop {
+        write!(output, "{prompt}")?;
+        output.flush()?;
+        let Some(line) = lines.next_line().await? else {
+            break;
+        };
+
+        let args = match split_args(&line) {
+            Ok(args) if args.is_empty() => continue,
+            Ok(args) => args,
+            Err(e) => {
+                writeln!(output, "{e}")?;
+                continue;
+            }
+        };
+        if args.len() == 1 && [&b"quit"[..], b"exit"].contains(&&*args[0].to_ascii_lowercase()) {
+            break;
+        }
+        // Error replies of the server come back as `ClientError::Server`.
+        let reply = match client.command(args).await {
+            Ok(reply) => reply.to_pretty_string(),
+            Err(e) => format!("(error) {e}"),
+        };
+        writeln!(output, "{reply}")?;
+    }
+    Ok(())
+}
+
+/// Splits a line of inline syntax into arguments, like redis-cli: arguments are separated
+/// by spaces, and may be quoted to hold spaces. Double quotes take the escapes `\n`, `\r`,
+/// `\t`, `\b`, `\a`, `\"`, `\\` and `\xHH`, single quotes only `\'`.
+pub fn split_args(line: &str) -> Result<Vec<Vec<u8>>, SplitError> {
+    let mut args = vec![];
+    let mut bytes = line.as_bytes().iter().copied().peekable();
+    loop {
+        while bytes.next_if(u8::is_ascii_whitespace).is_some() {}
+        let Some(first) = bytes.next() else {
+            return Ok(args);
+        };
+
+        let mut arg = vec![];
+        match first {
+            b'"' => loop {
+                match bytes.next().ok_or(SplitError::UnbalancedQuotes)? {
+                    b'"' => break,
+                    b'\\' => {
+                        let escaped = bytes.next().ok_or(SplitError::UnbalancedQuotes)?;
+                        let hex = |b: Option<u8>| b.and_then(|b| (b as char).to_digit(16));
+                        arg.push(match escaped {
+                            b'n' => b'\n',
+                            b'r' => b'\r',
+                            b't' => b'\t',
+                            b'b' => 0x08,
+                            b'a' => 0x07,
+                            b'x' => {
+                                let mut lookahead = bytes.clone();
+                                match (hex(lookahead.next()), hex(lookahead.next())) {
+                                    (Some(hi), Some(lo)) => {
+                                        bytes = lookahead;
+                                        (hi * 16 + lo) as u8
+                                    }
+                                    _ => b'x',
+                                }
+                            }
+                            b => b,
+                        });
+                    }
+                    b => arg.push(b),
+                }
+            },
+            b'\'' => loop {
+                match bytes.next().ok_or(SplitError::UnbalancedQuotes)? {
+                    b'\'' => break,
+                    b'\\' if bytes.peek() == Some(&b'\'') => arg.push(bytes.next().unwrap()),
+                    b => arg.push(b),
+                }
+            },
+            b => {
+                arg.push(b);
+                while let Some(b) = bytes.next_if(|b| !b.is_ascii_whitespace()) {
+                    arg.push(b);
+                }
+            }
+        }
+        // A closing quote must end the argument.
+        if bytes.peek().is_some_and(|b| !b.is_ascii_whitespace()) {
+            return Err(SplitError::UnbalancedQuotes);
+        }
+        args.push(arg);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::redis::{Redis, RedisConfig};
+
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        split_args(line)
+            .expect("Split args unexpected error")
+            .into_iter()
+            .map(|arg| String::from_utf8(arg).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn split_inline_args() {
+        assert_eq!(args("  set key  value "), ["set", "key", "value"]);
+        assert_eq!(args(r#"set "a key" 'it\'s'"#), ["set", "a key", "it's"]);
+        assert_eq!(
+            args(r#""line\nbreak" "\x41\x4" ''"#),
+            ["line\nbreak", "Ax4", ""]
+        );
+        assert_eq!(args(""), Vec::<String>::new());
+
+        assert_eq!(split_args(r#"get "key"#), Err(SplitError::UnbalancedQuotes));
+        assert_eq!(
+            split_args(r#"get "key"x"#),
+            Err(SplitError::UnbalancedQuotes)
+        );
+    }
+
+    #[tokio::test]
+    async fn run_commands_from_input() {
+        let server = Redis::spawn(RedisConfig::default())
+            .await
+            .expect("Spawn unexpected error");
+        let client = RedisClient::connect(server.addr())
+            .await
+            .expect("Connect unexpected error");
+        let input = "set key \"hello world\"\n\nget key\nnosuchcommand\nquit\nget key\n";
+        let mut output = vec![];
+
+        run(client, input.as_bytes(), &mut output)
+            .await
+            .expect("Run unexpected error");
+        let prompt = format!("{}> ", server.addr());
+        let output = String::from_utf8(output).unwrap();
+        let replies: Vec<&str> = output.split(&prompt).collect();
+        assert_eq!(replies[1], "OK\n");
+        assert_eq!(replies[2], "");
+        assert_eq!(replies[3], "\"hello world\"\n");
+        assert!(replies[4].starts_with("(error) ERR unknown command"));
+        // Nothing runs after quit.
+        assert_eq!(replies.len(), 6);
+        assert_eq!(replies[5], "");
+    }
+}
@@ -0,0 +1,85 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Detaches the process from the terminal: forks so the parent can exit, starts a new
+/// session and redirects stdin, stdout and stderr to /dev/null.
+///
+/// Must be called before any threads are started, including the tokio runtime.
+#[cfg(unix)]
+pub fn daemonize() -> io::Result<()> {
+    // SAFETY: the process is still single threaded, so forking can't leave locks held by
+    // other threads behind in the child.
+    match unsafe { libc::fork() } {
+        -1 => return Err(io::Error::last_os_error()),
+        0 => (),
+        _ => std::process::exit(0),
+    }
+
+    // SAFETY: setsid has no preconditions, it fails if the process is a group leader which
+    // the forked child never is.
+    if unsafe { libc::setsid() } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let dev_null = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/null")?;
+    let fd = std::os::unix::io::AsRawFd::as_raw_fd(&dev_null);
+    for target in [libc::STDIN_FILENO, libc::STDOUT_FILENO, libc::STDERR_FILENO] {
+        // SAFETY: both file descriptors are valid for the duration of the call.
+        if unsafe { libc::dup2(fd, target) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn daemonize() -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "daemonize is only supported on unix",
+    ))
+}
+
+/// File holding the process ID, removed when dropped.
+#[derive(Debug)]
+pub struct PidFile {
+    path: PathBuf,
+}
+
+impl PidFile {
+    /// Writes the ID of the current process to the file.
+    pub fn create(path: &Path) -> io::Result<Self> {
+        fs::write(path, format!("{}\n", std::process::id()))?;
+        Ok(Self {
+            path: path.to_path_buf(),
+        })
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn pidfile_removed_on_drop() {
+        let path = std::env::temp_dir().join(format!("redis-test-{}.pid", std::process::id()));
+
+        let pidfile = PidFile::create(&path).expect("Create pidfile unexpected error");
+        let contents = fs::read_to_string(&path).expect("Read pidfile unexpected error");
+        assert_eq!(contents.trim(), std::process::id().to_string());
+
+        drop(pidfile);
+        assert!(!path.exists());
+    }
+}
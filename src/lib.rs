@@ -1,2 +1,9 @@
+pub mod cli;
+pub mod daemon;
+pub mod log;
 pub mod redis;
+pub mod replay;
+pub mod systemd;
+#[cfg(feature = "otel")]
+pub mod telemetry;
 mod util;
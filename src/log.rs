@@ -0,0 +1,124 @@
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use tracing::level_filters::LevelFilter;
+use tracing::{error, Event, Level, Subscriber};
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::reload;
+
+use crate::redis::config::{LogLevel, LogLevelControl};
+
+/// Formats events like Redis log lines, e.g.
+/// `1234:M 16 Oct 2024 10:00:00.123 * Ready to accept connections`.
+/// Timestamps are in UTC.
+#[derive(Debug, Clone, Copy)]
+pub struct RedisLogFormat {
+    /// `M` for a master and `S` for a replica.
+    role: char,
+}
+
+impl RedisLogFormat {
+    pub fn new(is_replica: bool) -> Self {
+        Self {
+            role: if is_replica { 'S' } else { 'M' },
+        }
+    }
+}
+
+impl<S, N> FormatEvent<S, N> for RedisLogFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        write!(
+            writer,
+            "{}:{} {} {} ",
+            std::process::id(),
+            self.role,
+            format_timestamp(SystemTime::now()),
+            level_char(event.metadata().level())
+        )?;
+        ctx.field_format().format_fields(writer.by_ref(), event)?;
+        writeln!(writer)
+    }
+}
+
+/// Changes the level of a filter wrapped in a `reload::Layer`.
+impl<S> LogLevelControl for reload::Handle<LevelFilter, S>
+where
+    S: fmt::Debug + Send + Sync + 'static,
+{
+    fn set_level(&self, level: LogLevel) {
+        if let Err(e) = self.reload(level.level_filter()) {
+            error!("Reload log level error: {e}");
+        }
+    }
+}
+
+/// Maps levels to the characters Redis uses for debug, verbose, notice and warning.
+fn level_char(level: &Level) -> char {
+    match *level {
+        Level::TRACE => '.',
+        Level::DEBUG => '-',
+        Level::INFO => '*',
+        _ => '#',
+    }
+}
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Formats the time as `16 Oct 2024 10:00:00.123`.
+fn format_timestamp(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let (year, month, day) = civil_from_days((secs / 86400) as i64);
+    let secs_of_day = secs % 86400;
+
+    format!(
+        "{day} {} {year} {:02}:{:02}:{:02}.{:03}",
+        MONTHS[month as usize - 1],
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+/// Converts days since the unix epoch into year, month and day of the proleptic Gregorian
+/// calendar, see http://howardhinnant.github.io/date_algorithms.html#civil_from_days.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + (month <= 2) as i64;
+
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn timestamp_format() {
+        let time = UNIX_EPOCH + Duration::from_millis(1_709_210_096_789);
+        assert_eq!(format_timestamp(time), "29 Feb 2024 12:34:56.789");
+    }
+}
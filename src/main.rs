@@ -1,60 +1,568 @@
-use std::net::{SocketAddr, ToSocketAddrs};
+#[cfg(feature = "json")]
+use std::fs::File;
+use std::fs::OpenOptions;
+#[cfg(feature = "json")]
+use std::io::{BufReader, BufWriter, Write};
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
+#[cfg(feature = "otel")]
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::reload;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::Layer;
 
-use redis_starter_rust::redis::{Redis, RedisConfig};
-use tracing::{error, info};
-use tracing_subscriber;
+use redis_starter_rust::cli;
+use redis_starter_rust::daemon::{self, PidFile};
+use redis_starter_rust::log::RedisLogFormat;
+use redis_starter_rust::redis::client::RedisClient;
+#[cfg(feature = "json")]
+use redis_starter_rust::redis::json::Dump;
+use redis_starter_rust::redis::{
+    config::{self, HostPort, LogLevel, LogLevelControl, ReplDisklessLoad, TlsAuthClients},
+    eviction::EvictionPolicy,
+    Redis, RedisConfig,
+};
+use redis_starter_rust::replay;
+use redis_starter_rust::systemd;
+#[cfg(feature = "otel")]
+use redis_starter_rust::telemetry;
+use tracing::{error, info, warn};
+
+#[cfg(feature = "jemalloc")]
+#[global_allocator]
+static GLOBAL: tikv_jemallocator::Jemalloc = tikv_jemallocator::Jemalloc;
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct Args {
     /// Port to listen to
     #[arg(short, long, default_value = "6379")]
-    port: String,
+    port: u16,
+
+    /// Addresses to listen to, e.g. `--bind 0.0.0.0 ::` to accept IPv4 and IPv6 on all interfaces
+    #[arg(long, num_args = 1.., value_delimiter = ' ', default_value = "127.0.0.1")]
+    bind: Vec<IpAddr>,
 
-    /// Run as replica of master host and port
-    #[arg(name = "replicaof", short, long, value_delimiter = ' ', num_args = 2, value_names=["master_host", "master_port"])]
+    /// Run as replica of the master, given as `host port`, `"host port"` or `host:port`
+    #[arg(name = "replicaof", short, long, num_args = 1..=2, value_names=["master_host", "master_port"])]
     replica_of: Option<Vec<String>>,
+
+    /// How a replica loads the RDB file of a full resync: disabled to write it to dbfilename
+    /// first, on-empty-db to load it from the link if there are no keys, swapdb to always
+    #[arg(long, default_value = "disabled")]
+    repl_diskless_load: ReplDisklessLoad,
+
+    /// Preference for promoting this server when it is a replica, lower first, 0 to never
+    /// promote it
+    #[arg(long, default_value = "100")]
+    replica_priority: u32,
+
+    /// Directory where the RDB file is stored, defaults to the current directory
+    #[arg(long)]
+    dir: Option<PathBuf>,
+
+    /// Name of the RDB file
+    #[arg(long, default_value = "dump.rdb")]
+    dbfilename: String,
+
+    /// Save the keys to the RDB file on shutdown, unless it had data that wasn't loaded
+    #[arg(long)]
+    save_on_shutdown: bool,
+
+    /// Port to accept TLS connections on, 0 to disable
+    #[arg(long, default_value = "0")]
+    tls_port: u16,
+
+    /// Certificate used by the server for TLS connections
+    #[arg(long)]
+    tls_cert_file: Option<PathBuf>,
+
+    /// Private key of the TLS certificate
+    #[arg(long)]
+    tls_key_file: Option<PathBuf>,
+
+    /// CA certificate bundle used to verify peers
+    #[arg(long)]
+    tls_ca_cert_file: Option<PathBuf>,
+
+    /// Whether TLS clients must present a certificate signed by the CA: yes, no or optional
+    #[arg(long, default_value = "no")]
+    tls_auth_clients: TlsAuthClients,
+
+    /// Connect to the master over TLS when running as a replica
+    #[arg(long)]
+    tls_replication: bool,
+
+    /// File to load ACL users from, also used by ACL LOAD and ACL SAVE
+    #[arg(long)]
+    aclfile: Option<PathBuf>,
+
+    /// Memory limit with an optional unit, e.g. `100mb`, 0 for no limit
+    #[arg(long, default_value = "0", value_parser = config::parse_memory)]
+    maxmemory: u64,
+
+    /// How keys are evicted when over maxmemory, e.g. `allkeys-lru` or `noeviction`
+    #[arg(long, default_value = "noeviction")]
+    maxmemory_policy: EvictionPolicy,
+
+    /// Run in the background, detached from the terminal
+    #[arg(long)]
+    daemonize: bool,
+
+    /// File to write the process ID to, removed on exit
+    #[arg(long)]
+    pidfile: Option<PathBuf>,
+
+    /// File to append logs to in the Redis log format, instead of standard output
+    #[arg(long)]
+    logfile: Option<PathBuf>,
+
+    /// How much to log: debug, verbose, notice, warning or nothing
+    #[arg(long, default_value = "notice")]
+    loglevel: LogLevel,
+
+    /// Seconds of idleness before TCP keepalive probes are sent to clients, 0 to disable
+    #[arg(long, default_value = "300")]
+    tcp_keepalive: u64,
+
+    /// Milliseconds a write to a client may block before the client is disconnected, 0 to
+    /// disable
+    #[arg(long, default_value = "0")]
+    write_timeout: u64,
+
+    /// Most bytes of a request a client may send before it is complete, with an optional
+    /// unit, e.g. `1gb`
+    #[arg(long, default_value = "1gb", value_parser = config::parse_memory)]
+    client_query_buffer_limit: u64,
+
+    /// Size of the queue of connections waiting to be accepted
+    #[arg(long, default_value = "511")]
+    tcp_backlog: u32,
+
+    /// Number of listeners per address sharing the port with SO_REUSEPORT, each with its own
+    /// accept loop, e.g. the number of cores
+    #[arg(long, default_value = "1")]
+    io_threads: usize,
+
+    /// Read and write client sockets through io_uring on a single threaded runtime. Needs
+    /// the `io-uring` feature on Linux
+    #[arg(long)]
+    io_uring: bool,
+
+    /// Port to serve Prometheus metrics on at `/metrics`, 0 to disable. Needs the `metrics`
+    /// feature
+    #[arg(long, default_value = "0")]
+    metrics_port: u16,
+
+    /// Port answering PING and HEALTH probes without authentication, 0 to disable
+    #[arg(long, default_value = "0")]
+    health_port: u16,
+
+    /// Run as a Redis Cluster node
+    #[arg(long)]
+    cluster_enabled: bool,
+
+    /// File inside `--dir` the cluster nodes and slots are saved to
+    #[arg(long, default_value = "nodes.conf")]
+    cluster_config_file: String,
+
+    /// Milliseconds a cluster node may not answer pings before it is marked failed
+    #[arg(long, default_value = "15000")]
+    cluster_node_timeout: u64,
+
+    /// Port of the cluster bus, 0 for the port plus 10000
+    #[arg(long, default_value = "0")]
+    cluster_port: u16,
+
+    /// File to append every command run to with its time, to be fed back with `replay`
+    #[arg(long)]
+    capture_file: Option<PathBuf>,
+
+    /// File to record write and administrative commands to, with the client that sent them
+    /// and their outcome
+    #[arg(long)]
+    audit_file: Option<PathBuf>,
+
+    /// Size with an optional unit past which the audit file is rotated, 0 to never rotate
+    #[arg(long, default_value = "0", value_parser = config::parse_memory)]
+    audit_max_size: u64,
+
+    /// Number of rotated audit files to keep
+    #[arg(long, default_value = "5")]
+    audit_keep: usize,
+
+    /// Milliseconds to delay every command by. Needs the `chaos` feature
+    #[arg(long, default_value = "0")]
+    chaos_latency_ms: u64,
+
+    /// Share of commands to fail with an error instead of running, from 0 to 1. Needs the
+    /// `chaos` feature
+    #[arg(long, default_value = "0")]
+    chaos_failure_rate: f64,
+
+    /// Seed of the injected failures, the same seed failing the same commands
+    #[arg(long, default_value = "0")]
+    chaos_seed: u64,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+// Tools run against a server instead of running one, except for import.
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Send commands typed in inline syntax to a running server and print its replies
+    Cli(ServerArgs),
+
+    /// Run the server, copying the dataset of another server and applying its writes until
+    /// it closes the link, e.g. to migrate from a stock Redis without downtime
+    #[cfg(feature = "replication")]
+    Import(ImportArgs),
+
+    /// Send the commands of a capture made with --capture-file to a running server, at the
+    /// pace they were captured at or faster
+    Replay(ReplayArgs),
+
+    /// Write every key of a running server, with its value and expiry, to a JSON file
+    #[cfg(feature = "json")]
+    DumpJson(JsonArgs),
+
+    /// Write the keys of a JSON file made by dump-json into a running server
+    #[cfg(feature = "json")]
+    LoadJson(JsonArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct ServerArgs {
+    /// Host of the server
+    #[arg(long, default_value = "127.0.0.1")]
+    host: String,
+
+    /// Port of the server
+    #[arg(short, long, default_value = "6379")]
+    port: u16,
+}
+
+#[cfg(feature = "replication")]
+#[derive(clap::Args, Debug)]
+struct ImportArgs {
+    /// Host and port of the server to import from, e.g. `127.0.0.1:6379` or `"redis.local 6379"`
+    #[arg(long)]
+    from: HostPort,
+}
+
+#[derive(clap::Args, Debug)]
+struct ReplayArgs {
+    /// Capture file to replay
+    file: PathBuf,
+
+    /// How many times faster than captured to send the commands, 0 to send them without
+    /// waiting
+    #[arg(long, default_value = "1")]
+    speed: f64,
+
+    #[command(flatten)]
+    server: ServerArgs,
+}
+
+#[cfg(feature = "json")]
+#[derive(clap::Args, Debug)]
+struct JsonArgs {
+    /// JSON file to write to or read from
+    file: PathBuf,
+
+    #[command(flatten)]
+    server: ServerArgs,
+}
+
+impl ServerArgs {
+    fn server_addr(&self) -> std::io::Result<SocketAddr> {
+        (self.host.as_str(), self.port)
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| std::io::Error::other(format!("No address for {}", self.host)))
+    }
 }
 
 impl Args {
-    fn replicate_addr(&self) -> Option<SocketAddr> {
-        match self.replica_of.clone().map(|v| v.join(":")) {
-            Some(s) => s.to_socket_addrs().unwrap().next(),
-            None => None,
-        }
+    /// The master given to `--replicaof`, its name resolved once the server starts.
+    fn replica_of(&self) -> Result<Option<HostPort>, String> {
+        self.replica_of
+            .as_ref()
+            .map(|parts| parts.join(" ").parse())
+            .transpose()
+    }
+
+    fn dir(&self) -> PathBuf {
+        self.dir
+            .clone()
+            .unwrap_or_else(|| std::env::current_dir().unwrap_or_else(|_| PathBuf::from(".")))
     }
 }
 
-#[tokio::main]
-async fn main() {
-    let args = Args::parse();
-    tracing_subscriber::fmt::init();
+fn main() {
+    let mut args = Args::parse();
+
+    let import_from = match args.command.take() {
+        #[cfg(feature = "replication")]
+        Some(Command::Import(import)) => Some(import.from),
+        Some(command) => {
+            if let Err(e) = run_command(command) {
+                eprintln!("{e}");
+                std::process::exit(1);
+            }
+            return;
+        }
+        None => None,
+    };
+
+    // Take the sockets passed by systemd before forking, as they are announced to this
+    // process ID, and before the tokio runtime starts any threads.
+    let listeners = match systemd::listen_fds() {
+        Ok(listeners) => listeners,
+        Err(e) => {
+            eprintln!("Take systemd sockets error: {e}");
+            return;
+        }
+    };
+
+    // Fork before the tokio runtime starts any threads.
+    if args.daemonize {
+        if let Err(e) = daemon::daemonize() {
+            eprintln!("Daemonize error: {e}");
+            return;
+        }
+    }
+
+    let fmt_layer = match &args.logfile {
+        Some(path) => {
+            let file = match OpenOptions::new().create(true).append(true).open(path) {
+                Ok(f) => f,
+                Err(e) => {
+                    eprintln!("Open log file {} error: {e}", path.display());
+                    return;
+                }
+            };
+            tracing_subscriber::fmt::layer()
+                .event_format(RedisLogFormat::new(args.replica_of.is_some()))
+                .with_writer(Mutex::new(file))
+                .boxed()
+        }
+        None => tracing_subscriber::fmt::layer().boxed(),
+    };
+    // Levels are filtered per layer, as tokio-console needs the runtime's trace level spans.
+    // The level of the logs can be changed with CONFIG SET loglevel.
+    let (level_filter, log_control) = reload::Layer::new(args.loglevel.level_filter());
+    let subscriber = tracing_subscriber::registry().with(fmt_layer.with_filter(level_filter));
+    #[cfg(feature = "console")]
+    let subscriber = subscriber.with(console_subscriber::spawn());
+
+    // Spans are also exported to an OTLP collector, flushed when the guard drops on exit.
+    #[cfg(feature = "otel")]
+    let _otel_guard = match telemetry::layer() {
+        Ok((otel_layer, guard)) => {
+            subscriber
+                .with(otel_layer.with_filter(LevelFilter::INFO))
+                .init();
+            guard
+        }
+        Err(e) => {
+            eprintln!("Set up OpenTelemetry error: {e}");
+            return;
+        }
+    };
+    #[cfg(not(feature = "otel"))]
+    subscriber.init();
+
+    let _pidfile = match args.pidfile.as_deref().map(PidFile::create) {
+        Some(Ok(pidfile)) => Some(pidfile),
+        Some(Err(e)) => {
+            error!("Write pidfile error: {e}");
+            return;
+        }
+        None => None,
+    };
+
+    #[cfg(all(feature = "io-uring", target_os = "linux"))]
+    if args.io_uring {
+        tokio_uring::start(run(args, import_from, listeners, Arc::new(log_control)));
+        return;
+    }
+
+    let runtime = match tokio::runtime::Runtime::new() {
+        Ok(rt) => rt,
+        Err(e) => {
+            error!("Start runtime error: {e}");
+            return;
+        }
+    };
+    runtime.block_on(run(args, import_from, listeners, Arc::new(log_control)));
+}
 
+/// Runs the server, on `listeners` instead of binding its own if systemd passed any.
+async fn run(
+    args: Args,
+    import_from: Option<HostPort>,
+    listeners: Vec<std::net::TcpListener>,
+    log_control: Arc<dyn LogLevelControl>,
+) {
     info!("Logs from your program will appear here!");
 
-    let addr = format!("127.0.0.1:{}", args.port);
-    info!("Listening to {addr}...");
-    let addr = addr.to_socket_addrs().unwrap().next().unwrap();
-
-    let redis = match Redis::init(
-        addr,
-        RedisConfig {
-            master_addr: args.replicate_addr(),
-        },
-    )
-    .await
-    {
-        Ok(r) => r,
+    let master_addr = match args.replica_of() {
+        Ok(master_addr) => master_addr,
+        Err(e) => {
+            error!("Invalid replicaof: {e}");
+            return;
+        }
+    };
+
+    let config = RedisConfig::builder()
+        .bind(args.bind.clone())
+        .port(args.port)
+        .master_addr(master_addr)
+        .repl_diskless_load(args.repl_diskless_load)
+        .replica_priority(args.replica_priority)
+        .dir(args.dir())
+        .dbfilename(args.dbfilename.clone())
+        .save_on_shutdown(args.save_on_shutdown)
+        .tls_port(args.tls_port)
+        .tls_cert_file(args.tls_cert_file.clone())
+        .tls_key_file(args.tls_key_file.clone())
+        .tls_ca_cert_file(args.tls_ca_cert_file.clone())
+        .tls_auth_clients(args.tls_auth_clients)
+        .tls_replication(args.tls_replication)
+        .import_from(import_from)
+        .aclfile(args.aclfile.clone())
+        .maxmemory(args.maxmemory)
+        .maxmemory_policy(args.maxmemory_policy)
+        .daemonize(args.daemonize)
+        .pidfile(args.pidfile.clone())
+        .logfile(args.logfile.clone())
+        .loglevel(args.loglevel)
+        .tcp_keepalive(args.tcp_keepalive)
+        .write_timeout(args.write_timeout)
+        .client_query_buffer_limit(args.client_query_buffer_limit)
+        .tcp_backlog(args.tcp_backlog)
+        .io_threads(args.io_threads)
+        .io_uring(args.io_uring)
+        .metrics_port(args.metrics_port)
+        .health_port(args.health_port)
+        .cluster_enabled(args.cluster_enabled)
+        .cluster_config_file(args.cluster_config_file.clone())
+        .cluster_node_timeout(args.cluster_node_timeout)
+        .cluster_port(args.cluster_port)
+        .capture_file(args.capture_file.clone())
+        .audit_file(args.audit_file.clone())
+        .audit_max_size(args.audit_max_size)
+        .audit_keep(args.audit_keep)
+        .chaos_latency_ms(args.chaos_latency_ms)
+        .chaos_failure_rate(args.chaos_failure_rate)
+        .chaos_seed(args.chaos_seed)
+        .build();
+    let config = match config {
+        Ok(config) => config,
+        Err(e) => {
+            error!("Configure redis error: {e}");
+            return;
+        }
+    };
+
+    let redis = if listeners.is_empty() {
+        Redis::new(config).await
+    } else {
+        info!("Serving on {} sockets passed by systemd", listeners.len());
+        Redis::adopt(listeners, config).await
+    };
+    let redis = match redis {
+        Ok(r) => r.with_log_control(log_control),
         Err(e) => {
             error!("Initialize redis error: {e}");
             return;
         }
     };
 
-    match redis.start().await {
+    notify_systemd("READY=1");
+    let shutdown = async {
+        shutdown_signal().await;
+        notify_systemd("STOPPING=1");
+    };
+    match redis.start_with_shutdown(shutdown).await {
         Ok(()) => (),
         Err(e) => error!("Start redis error: {e}"),
     }
 }
+
+/// Tells systemd about the state of the server if it was started with `Type=notify`.
+fn notify_systemd(state: &str) {
+    if let Err(e) = systemd::notify(state) {
+        warn!("Notify systemd error: {e}");
+    }
+}
+
+fn run_command(command: Command) -> Result<(), Box<dyn std::error::Error>> {
+    let runtime = tokio::runtime::Runtime::new()?;
+    runtime.block_on(async {
+        match command {
+            #[cfg(feature = "replication")]
+            Command::Import(_) => unreachable!("Import runs the server"),
+            Command::Cli(args) => {
+                let client = RedisClient::connect(args.server_addr()?).await?;
+                let stdin = tokio::io::BufReader::new(tokio::io::stdin());
+                cli::run(client, stdin, std::io::stdout()).await?;
+            }
+            Command::Replay(args) => {
+                let mut client = RedisClient::connect(args.server.server_addr()?).await?;
+                let file = tokio::io::BufReader::new(tokio::fs::File::open(&args.file).await?);
+                let replayed = replay::run(&mut client, file, args.speed).await?;
+                println!(
+                    "Replayed {} commands from {}, {} replied with an error",
+                    replayed.commands,
+                    args.file.display(),
+                    replayed.errors
+                );
+            }
+            #[cfg(feature = "json")]
+            Command::DumpJson(args) => {
+                let mut client = RedisClient::connect(args.server.server_addr()?).await?;
+                let dump = Dump::read(&mut client).await?;
+                let mut file = BufWriter::new(File::create(&args.file)?);
+                dump.to_writer(&mut file)?;
+                file.flush()?;
+                println!("Dumped {} keys to {}", dump.keys.len(), args.file.display());
+            }
+            #[cfg(feature = "json")]
+            Command::LoadJson(args) => {
+                let dump = Dump::from_reader(BufReader::new(File::open(&args.file)?))?;
+                let mut client = RedisClient::connect(args.server.server_addr()?).await?;
+                let written = dump.write(&mut client).await?;
+                println!("Loaded {written} keys from {}", args.file.display());
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Completes on Ctrl-C, or SIGTERM on unix.
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut sigterm) => tokio::select! {
+                _ = tokio::signal::ctrl_c() => (),
+                _ = sigterm.recv() => (),
+            },
+            Err(e) => {
+                error!("Install SIGTERM handler error: {e}");
+                let _ = tokio::signal::ctrl_c().await;
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = tokio::signal::ctrl_c().await;
+}
@@ -1,37 +1,104 @@
+pub mod acl;
+pub mod allocator;
+pub mod audit;
+pub mod blocking;
+pub mod capture;
+#[cfg(feature = "chaos")]
+pub mod chaos;
 pub mod client;
+pub mod clients;
+pub mod clock;
+pub mod cluster;
 pub mod cmd;
+pub mod config;
+pub mod events;
+pub mod eviction;
 pub mod handler;
+pub mod hash;
+pub mod health;
+#[cfg(feature = "replication")]
+pub mod import;
+#[cfg(feature = "json")]
+pub mod json;
+pub mod key;
+pub mod lazyfree;
+pub mod list;
+pub mod listpack;
+pub mod memory;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+pub mod persistence;
+pub mod plugin;
+pub mod rdb;
+#[cfg(feature = "replication")]
 pub mod replica;
+pub mod replication;
+pub mod reply;
 pub mod resp;
+#[cfg(feature = "scripting")]
+pub mod scripting;
 pub mod session;
+pub mod stats;
+pub mod store;
+pub mod stream;
+#[cfg(any(test, feature = "test-util"))]
+pub mod test_util;
+pub mod throttle;
+#[cfg(feature = "tls")]
+pub mod tls;
+pub mod tracking;
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+mod uring;
+pub mod zset;
 
-use std::collections::HashMap;
-use std::net::SocketAddr;
-use std::sync::{Arc, RwLock};
+use std::future::Future;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Weak};
+use std::time::Duration;
 
+use parking_lot::{Mutex, RwLock};
+use socket2::{Domain, SockRef, Socket, TcpKeepalive, Type};
 use thiserror::Error;
-use tokio::sync::{mpsc, oneshot};
-use tracing::{error, info};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, oneshot, watch};
+use tokio::task::JoinHandle;
+#[cfg(feature = "tls")]
+use tokio_rustls::server::TlsStream;
+use tracing::{debug, error, info, warn};
 
 use super::util;
 
+use self::acl::{AccessControl, AclError};
+use self::audit::AuditLog;
+use self::capture::Capture;
+#[cfg(feature = "chaos")]
+use self::chaos::Chaos;
+use self::clients::{ClientHandle, ClientRegistry, ClientState, SharedClient};
+use self::cluster::bus::ClusterBus;
+use self::cluster::{ClusterError, ClusterNode, ClusterState, BUS_PORT_OFFSET};
 use self::cmd::ParseCommandError;
+use self::config::{
+    ConfigValues, HostPort, LogLevel, LogLevelControl, ReplDisklessLoad, ServerConfig,
+    TlsAuthClients, DEFAULT_QUERY_BUFFER_LIMIT, DEFAULT_REPLICA_PRIORITY,
+};
+use self::events::KeyEventListener;
+use self::eviction::EvictionPolicy;
+use self::handler::CommandHandler;
 use self::handler::HandleCommandError;
-use self::handler::{CommandHandler, CommandHandlerConfig};
+#[cfg(feature = "replication")]
+use self::import::Import;
+use self::memory::MemoryTracker;
+use self::persistence::PersistenceState;
+use self::plugin::{CommandPlugin, PluginError};
+use self::rdb::RdbError;
+#[cfg(feature = "replication")]
 use self::replica::{Replication, ReplicationError};
-use self::session::{Request, Response, Session, SessionError};
-
-struct RequestChannel {
-    req: Request,
-    tx: oneshot::Sender<Response>,
-}
-
-impl RequestChannel {
-    fn new(req: Request) -> (Self, oneshot::Receiver<Response>) {
-        let (tx, rx) = oneshot::channel();
-        (Self { req, tx }, rx)
-    }
-}
+use self::replication::{ReplicaState, ReplicationState};
+use self::reply::Reply;
+use self::session::{Request, Session, SessionError, SessionWriter};
+use self::store::{Store, DEFAULT_SHARDS};
 
 #[derive(Debug, Error)]
 pub enum RedisError {
@@ -44,117 +111,2911 @@ pub enum RedisError {
     #[error(transparent)]
     HandleCommand(#[from] HandleCommandError),
 
+    #[cfg(feature = "replication")]
     #[error(transparent)]
     Replication(#[from] ReplicationError),
 
+    #[error("Replication is not available, rebuild with the `replication` feature")]
+    ReplicationUnavailable,
+
+    #[error("Can't import from another server while running as a replica")]
+    ImportReplica,
+
+    #[error("Invalid config: {0}")]
+    InvalidConfig(&'static str),
+
+    #[error(transparent)]
+    Acl(#[from] AclError),
+
+    #[error(transparent)]
+    Rdb(#[from] RdbError),
+
+    #[error(transparent)]
+    Cluster(#[from] ClusterError),
+
+    #[error("Port {0} is too high to derive the cluster bus port from, set cluster-port")]
+    ClusterPortOverflow(u16),
+
+    #[cfg(feature = "tls")]
+    #[error(transparent)]
+    Tls(#[from] tls::TlsError),
+
+    #[error("TLS is not available, rebuild with the `tls` feature")]
+    TlsUnavailable,
+
+    #[error("Metrics are not available, rebuild with the `metrics` feature")]
+    MetricsUnavailable,
+
+    #[error("io_uring is not available, rebuild with the `io-uring` feature on Linux")]
+    IoUringUnavailable,
+
+    #[error("Fault injection is not available, rebuild with the `chaos` feature")]
+    ChaosUnavailable,
+
     #[error(transparent)]
     TokioIo(#[from] tokio::io::Error),
 }
 
+#[cfg(feature = "tls")]
+use self::tls::{ServerTls, TlsConnector};
+
+/// Stands in for the TLS side of listeners when TLS is compiled out, so a TLS listener can
+/// never exist.
+#[cfg(not(feature = "tls"))]
+pub(crate) enum ServerTls {}
+
+/// Stands in for the connector when TLS is compiled out, so a TLS link can never exist.
+#[cfg(not(feature = "tls"))]
+#[derive(Clone)]
+pub(crate) enum TlsConnector {}
+
+/// Stands in for the stream of a TLS connection when TLS is compiled out.
+#[cfg(not(feature = "tls"))]
+type TlsStream<S> = S;
+
+/// Most replies and pushes a connection coalesces into a single write.
+const MAX_WRITE_BATCH: usize = 256;
+
+/// Writes the replies and pushes queued for a connection, from a task of its own so that a
+/// client slow to read them doesn't hold up reading its requests.
+struct ConnectionWriter<S> {
+    writer: SessionWriter<S>,
+    outbound_rx: mpsc::Receiver<Reply>,
+    config: Arc<ServerConfig>,
+    id: u64,
+    // The state holds a sender to the queue, which would then never close.
+    state: Weak<Mutex<ClientState>>,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> ConnectionWriter<S> {
+    /// Writes until the queue closes, or the client stalls for longer than the write
+    /// timeout, which closes it.
+    async fn run(mut self) -> Result<(), RedisError> {
+        let id = self.id;
+        let mut batch = Vec::new();
+        while let Some(reply) = self.outbound_rx.recv().await {
+            // Whatever else is queued by now, like replies to pipelined commands, goes out
+            // with the same write.
+            batch.push(reply);
+            while batch.len() < MAX_WRITE_BATCH {
+                match self.outbound_rx.try_recv() {
+                    Ok(reply) => batch.push(reply),
+                    Err(_) => break,
+                }
+            }
+            // A client that stopped reading would block the write forever, as opposed to one
+            // that is merely idle or slow enough to fill its queue.
+            let write_timeout = self.config.read().write_timeout;
+            let write = self.writer.send_replies(batch.drain(..));
+            if write_timeout == 0 {
+                write.await?;
+            } else if let Ok(written) =
+                tokio::time::timeout(Duration::from_millis(write_timeout), write).await
+            {
+                written?;
+            } else {
+                warn!("Closing client {id} stalled for {write_timeout}ms writing replies");
+                if let Some(state) = self.state.upgrade() {
+                    state.lock().close();
+                }
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Spawns the writer task of a connection over `S`.
+trait SpawnWriter<S> {
+    fn spawn(&self, writer: ConnectionWriter<S>) -> JoinHandle<Result<(), RedisError>>;
+}
+
+/// Spawns writers on the runtime, for streams that can move between its threads.
+#[derive(Debug, Clone, Copy)]
+struct RuntimeWriter;
+
+impl<S> SpawnWriter<S> for RuntimeWriter
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    fn spawn(&self, writer: ConnectionWriter<S>) -> JoinHandle<Result<(), RedisError>> {
+        util::spawn_named("connection writer", writer.run())
+    }
+}
+
+/// Spawns writers on the current thread, for io_uring sockets which can't leave it.
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+#[derive(Debug, Clone, Copy)]
+struct LocalWriter;
+
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+impl<S> SpawnWriter<S> for LocalWriter
+where
+    S: AsyncRead + AsyncWrite + Unpin + 'static,
+{
+    fn spawn(&self, writer: ConnectionWriter<S>) -> JoinHandle<Result<(), RedisError>> {
+        tokio::task::spawn_local(writer.run())
+    }
+}
+
+/// A bound listener, optionally wrapping accepted connections in TLS.
+struct Listener {
+    inner: TcpListener,
+    tls: Option<Arc<ServerTls>>,
+
+    /// Whether the socket I/O of accepted connections goes through io_uring.
+    uring: bool,
+}
+
 pub struct Redis {
-    /// Listen to client connections.
-    listener: tokio::net::TcpListener,
+    /// Listen to client connections, one listener per bind address and port.
+    listeners: Vec<Listener>,
 
-    /// Handles commands from client requests.
+    /// Handles commands from client requests, cloned into every connection.
     handler: CommandHandler,
 
-    /// Handles replication.
+    /// Link to our master, synced from once started.
+    #[cfg(feature = "replication")]
     replication: Option<Replication>,
+
+    /// Copies the dataset of another server once started.
+    #[cfg(feature = "replication")]
+    import: Option<Import>,
+
+    /// Serves Prometheus metrics over HTTP.
+    #[cfg(feature = "metrics")]
+    metrics_listener: Option<TcpListener>,
+
+    /// Answers health probes of load balancers and orchestrators.
+    health_listener: Option<TcpListener>,
+
+    /// Whether the keys are saved to the RDB file once shut down.
+    #[cfg(feature = "persistence")]
+    save_on_shutdown: bool,
+
+    /// Talks to the other nodes of the cluster, in cluster mode.
+    cluster_bus: Option<(TcpListener, ClusterBus)>,
 }
 
+/// Configuration of a server, made with `RedisConfig::builder` or by updating
+/// `RedisConfig::default`.
 #[derive(Debug)]
 pub struct RedisConfig {
-    pub master_addr: Option<SocketAddr>,
+    /// Addresses and port `Redis::new` listens to. `Redis::init` and `Redis::spawn` are
+    /// given theirs instead.
+    pub bind: Vec<IpAddr>,
+    pub port: u16,
+
+    /// Master to replicate, its name resolved again whenever the link is reconnected.
+    pub master_addr: Option<HostPort>,
+
+    /// Whether the RDB file of a full resync is written to `dbfilename` before loading it.
+    pub repl_diskless_load: ReplDisklessLoad,
+
+    /// Preference for promoting this server when it is a replica, lower first, 0 to never
+    /// promote it. Told to the master if not the default.
+    pub replica_priority: u32,
+    pub dir: PathBuf,
+    pub dbfilename: String,
+
+    /// Whether the keys are saved to `dbfilename` in `dir` once the server shut down, to be
+    /// loaded again on the next start.
+    pub save_on_shutdown: bool,
+
+    /// Port for TLS connections, 0 to disable TLS.
+    pub tls_port: u16,
+    pub tls_cert_file: Option<PathBuf>,
+    pub tls_key_file: Option<PathBuf>,
+    pub tls_ca_cert_file: Option<PathBuf>,
+    pub tls_auth_clients: TlsAuthClients,
+
+    /// Connect to the master over TLS.
+    pub tls_replication: bool,
+
+    /// Server to import the dataset from, syncing with it like a replica and applying its
+    /// writes until it closes the link. Connects over TLS if `tls_replication` is set.
+    pub import_from: Option<HostPort>,
+
+    /// File to load ACL users from at startup, also used by ACL LOAD and ACL SAVE.
+    pub aclfile: Option<PathBuf>,
+
+    /// Memory limit in bytes, 0 for no limit.
+    pub maxmemory: u64,
+    pub maxmemory_policy: EvictionPolicy,
+
+    /// Process options, only reported through CONFIG GET since they apply before startup.
+    pub daemonize: bool,
+    pub pidfile: Option<PathBuf>,
+    pub logfile: Option<PathBuf>,
+
+    /// How much is logged, applied to the log subscriber given to `Redis::with_log_control`.
+    pub loglevel: LogLevel,
+
+    /// Seconds of idleness before keepalive probes are sent, 0 to disable.
+    pub tcp_keepalive: u64,
+
+    /// Milliseconds a write to a client may block before it is disconnected, 0 to disable.
+    pub write_timeout: u64,
+
+    /// Most bytes of an unfinished request buffered before the client is disconnected.
+    pub client_query_buffer_limit: u64,
+
+    /// Listen backlog of every listener.
+    pub tcp_backlog: u32,
+
+    /// Number of listeners bound to every address with SO_REUSEPORT, so the kernel spreads
+    /// connections over that many accept loops. 1 binds a single listener.
+    pub io_threads: usize,
+
+    /// Read and write client sockets through io_uring. The server must then run inside
+    /// `tokio_uring::start`.
+    pub io_uring: bool,
+
+    /// Port of the HTTP endpoint serving Prometheus metrics, 0 to disable.
+    pub metrics_port: u16,
+
+    /// Port answering health probes without authentication, 0 to disable.
+    pub health_port: u16,
+
+    /// Run as a cluster node, serving every hash slot until other nodes join.
+    pub cluster_enabled: bool,
+
+    /// File inside `dir` the cluster nodes and slots are loaded from and saved to.
+    pub cluster_config_file: String,
+
+    /// Milliseconds before a node that doesn't answer pings is marked failed.
+    pub cluster_node_timeout: u64,
+
+    /// Port of the cluster bus, 0 for the client port plus 10000.
+    pub cluster_port: u16,
+
+    /// File every command run is appended to with its time, for `replay` to feed back.
+    pub capture_file: Option<PathBuf>,
+
+    /// File write and administrative commands are recorded to, with the client that sent
+    /// them and their outcome, see `AuditLog`.
+    pub audit_file: Option<PathBuf>,
+
+    /// Size in bytes past which the audit file is rotated, 0 to never rotate.
+    pub audit_max_size: u64,
+
+    /// Number of rotated audit files kept.
+    pub audit_keep: usize,
+
+    /// Milliseconds every command is delayed by, to test clients against a slow server.
+    pub chaos_latency_ms: u64,
+
+    /// Share of commands failing with an error instead of running, from 0 to 1.
+    pub chaos_failure_rate: f64,
+
+    /// Seed of the failures, so a run can be repeated.
+    pub chaos_seed: u64,
 }
 
-impl Redis {
-    pub async fn init(addr: SocketAddr, config: RedisConfig) -> Result<Self, RedisError> {
-        let listener = tokio::net::TcpListener::bind(addr).await?;
+impl Default for RedisConfig {
+    /// Returns the configuration of a standalone server with the same defaults as the
+    /// command line.
+    fn default() -> Self {
+        Self {
+            bind: vec![IpAddr::V4(Ipv4Addr::LOCALHOST)],
+            port: 6379,
+            master_addr: None,
+            repl_diskless_load: ReplDisklessLoad::Disabled,
+            replica_priority: DEFAULT_REPLICA_PRIORITY,
+            dir: PathBuf::from("."),
+            dbfilename: "dump.rdb".to_string(),
+            save_on_shutdown: false,
+            tls_port: 0,
+            tls_cert_file: None,
+            tls_key_file: None,
+            tls_ca_cert_file: None,
+            tls_auth_clients: TlsAuthClients::No,
+            tls_replication: false,
+            import_from: None,
+            aclfile: None,
+            maxmemory: 0,
+            maxmemory_policy: EvictionPolicy::NoEviction,
+            daemonize: false,
+            pidfile: None,
+            logfile: None,
+            loglevel: LogLevel::Notice,
+            tcp_keepalive: 300,
+            write_timeout: 0,
+            client_query_buffer_limit: DEFAULT_QUERY_BUFFER_LIMIT,
+            tcp_backlog: 511,
+            io_threads: 1,
+            io_uring: false,
+            metrics_port: 0,
+            health_port: 0,
+            cluster_enabled: false,
+            cluster_config_file: "nodes.conf".to_string(),
+            cluster_node_timeout: 15000,
+            cluster_port: 0,
+            capture_file: None,
+            audit_file: None,
+            audit_max_size: 0,
+            audit_keep: 5,
+            chaos_latency_ms: 0,
+            chaos_failure_rate: 0.0,
+            chaos_seed: 0,
+        }
+    }
+}
 
-        let is_replica = config.master_addr.is_some();
-        let master_repl_id_and_offset = if is_replica {
-            None
-        } else {
-            Some((util::generate_random_alphanumeric_string(40), 0))
-        };
-        let replication = if is_replica {
-            Some(Replication::init(config.master_addr.unwrap().clone(), addr.port()).await?)
-        } else {
-            None
+impl RedisConfig {
+    /// Returns a builder starting from the defaults, whose `build` validates the config.
+    pub fn builder() -> RedisConfigBuilder {
+        RedisConfigBuilder::default()
+    }
+
+    /// Returns the addresses `Redis::new` listens to.
+    pub fn bind_addrs(&self) -> Vec<SocketAddr> {
+        self.bind
+            .iter()
+            .map(|ip| SocketAddr::new(*ip, self.port))
+            .collect()
+    }
+
+    /// Checks that the options go together and that the features they need are compiled
+    /// in, before anything is bound or connected.
+    pub fn validate(&self) -> Result<(), RedisError> {
+        if self.io_uring && !cfg!(all(feature = "io-uring", target_os = "linux")) {
+            return Err(RedisError::IoUringUnavailable);
+        }
+        if self.metrics_port != 0 && !cfg!(feature = "metrics") {
+            return Err(RedisError::MetricsUnavailable);
+        }
+        if self.tls_port != 0 && !cfg!(feature = "tls") {
+            return Err(RedisError::TlsUnavailable);
+        }
+        let chaos = self.chaos_latency_ms != 0 || self.chaos_failure_rate != 0.0;
+        if chaos && !cfg!(feature = "chaos") {
+            return Err(RedisError::ChaosUnavailable);
+        }
+        if !(0.0..=1.0).contains(&self.chaos_failure_rate) {
+            return Err(RedisError::InvalidConfig(
+                "chaos-failure-rate must be between 0 and 1",
+            ));
+        }
+        let replicates = self.master_addr.is_some() || self.import_from.is_some();
+        if replicates && !cfg!(feature = "replication") {
+            return Err(RedisError::ReplicationUnavailable);
+        }
+        if self.master_addr.is_some() && self.import_from.is_some() {
+            return Err(RedisError::ImportReplica);
+        }
+        if self.io_threads == 0 {
+            return Err(RedisError::InvalidConfig("io-threads must be at least 1"));
+        }
+        if self.cluster_node_timeout == 0 {
+            return Err(RedisError::InvalidConfig(
+                "cluster-node-timeout must be greater than 0",
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Builds a `RedisConfig`, every setter setting the field of the same name.
+#[derive(Debug, Default)]
+pub struct RedisConfigBuilder {
+    config: RedisConfig,
+}
+
+impl RedisConfigBuilder {
+    pub fn bind(mut self, bind: Vec<IpAddr>) -> Self {
+        self.config.bind = bind;
+        self
+    }
+
+    pub fn port(mut self, port: u16) -> Self {
+        self.config.port = port;
+        self
+    }
+
+    pub fn master_addr(mut self, master_addr: Option<HostPort>) -> Self {
+        self.config.master_addr = master_addr;
+        self
+    }
+
+    pub fn repl_diskless_load(mut self, repl_diskless_load: ReplDisklessLoad) -> Self {
+        self.config.repl_diskless_load = repl_diskless_load;
+        self
+    }
+
+    pub fn replica_priority(mut self, replica_priority: u32) -> Self {
+        self.config.replica_priority = replica_priority;
+        self
+    }
+
+    pub fn dir(mut self, dir: PathBuf) -> Self {
+        self.config.dir = dir;
+        self
+    }
+
+    pub fn dbfilename(mut self, dbfilename: String) -> Self {
+        self.config.dbfilename = dbfilename;
+        self
+    }
+
+    pub fn save_on_shutdown(mut self, save_on_shutdown: bool) -> Self {
+        self.config.save_on_shutdown = save_on_shutdown;
+        self
+    }
+
+    pub fn tls_port(mut self, tls_port: u16) -> Self {
+        self.config.tls_port = tls_port;
+        self
+    }
+
+    pub fn tls_cert_file(mut self, tls_cert_file: Option<PathBuf>) -> Self {
+        self.config.tls_cert_file = tls_cert_file;
+        self
+    }
+
+    pub fn tls_key_file(mut self, tls_key_file: Option<PathBuf>) -> Self {
+        self.config.tls_key_file = tls_key_file;
+        self
+    }
+
+    pub fn tls_ca_cert_file(mut self, tls_ca_cert_file: Option<PathBuf>) -> Self {
+        self.config.tls_ca_cert_file = tls_ca_cert_file;
+        self
+    }
+
+    pub fn tls_auth_clients(mut self, tls_auth_clients: TlsAuthClients) -> Self {
+        self.config.tls_auth_clients = tls_auth_clients;
+        self
+    }
+
+    pub fn tls_replication(mut self, tls_replication: bool) -> Self {
+        self.config.tls_replication = tls_replication;
+        self
+    }
+
+    pub fn import_from(mut self, import_from: Option<HostPort>) -> Self {
+        self.config.import_from = import_from;
+        self
+    }
+
+    pub fn aclfile(mut self, aclfile: Option<PathBuf>) -> Self {
+        self.config.aclfile = aclfile;
+        self
+    }
+
+    pub fn maxmemory(mut self, maxmemory: u64) -> Self {
+        self.config.maxmemory = maxmemory;
+        self
+    }
+
+    pub fn maxmemory_policy(mut self, maxmemory_policy: EvictionPolicy) -> Self {
+        self.config.maxmemory_policy = maxmemory_policy;
+        self
+    }
+
+    pub fn daemonize(mut self, daemonize: bool) -> Self {
+        self.config.daemonize = daemonize;
+        self
+    }
+
+    pub fn pidfile(mut self, pidfile: Option<PathBuf>) -> Self {
+        self.config.pidfile = pidfile;
+        self
+    }
+
+    pub fn logfile(mut self, logfile: Option<PathBuf>) -> Self {
+        self.config.logfile = logfile;
+        self
+    }
+
+    pub fn loglevel(mut self, loglevel: LogLevel) -> Self {
+        self.config.loglevel = loglevel;
+        self
+    }
+
+    pub fn tcp_keepalive(mut self, tcp_keepalive: u64) -> Self {
+        self.config.tcp_keepalive = tcp_keepalive;
+        self
+    }
+
+    pub fn write_timeout(mut self, write_timeout: u64) -> Self {
+        self.config.write_timeout = write_timeout;
+        self
+    }
+
+    pub fn client_query_buffer_limit(mut self, client_query_buffer_limit: u64) -> Self {
+        self.config.client_query_buffer_limit = client_query_buffer_limit;
+        self
+    }
+
+    pub fn tcp_backlog(mut self, tcp_backlog: u32) -> Self {
+        self.config.tcp_backlog = tcp_backlog;
+        self
+    }
+
+    pub fn io_threads(mut self, io_threads: usize) -> Self {
+        self.config.io_threads = io_threads;
+        self
+    }
+
+    pub fn io_uring(mut self, io_uring: bool) -> Self {
+        self.config.io_uring = io_uring;
+        self
+    }
+
+    pub fn metrics_port(mut self, metrics_port: u16) -> Self {
+        self.config.metrics_port = metrics_port;
+        self
+    }
+
+    pub fn health_port(mut self, health_port: u16) -> Self {
+        self.config.health_port = health_port;
+        self
+    }
+
+    pub fn cluster_enabled(mut self, cluster_enabled: bool) -> Self {
+        self.config.cluster_enabled = cluster_enabled;
+        self
+    }
+
+    pub fn cluster_config_file(mut self, cluster_config_file: String) -> Self {
+        self.config.cluster_config_file = cluster_config_file;
+        self
+    }
+
+    pub fn cluster_node_timeout(mut self, cluster_node_timeout: u64) -> Self {
+        self.config.cluster_node_timeout = cluster_node_timeout;
+        self
+    }
+
+    pub fn cluster_port(mut self, cluster_port: u16) -> Self {
+        self.config.cluster_port = cluster_port;
+        self
+    }
+
+    pub fn capture_file(mut self, capture_file: Option<PathBuf>) -> Self {
+        self.config.capture_file = capture_file;
+        self
+    }
+
+    pub fn audit_file(mut self, audit_file: Option<PathBuf>) -> Self {
+        self.config.audit_file = audit_file;
+        self
+    }
+
+    pub fn audit_max_size(mut self, audit_max_size: u64) -> Self {
+        self.config.audit_max_size = audit_max_size;
+        self
+    }
+
+    pub fn audit_keep(mut self, audit_keep: usize) -> Self {
+        self.config.audit_keep = audit_keep;
+        self
+    }
+
+    pub fn chaos_latency_ms(mut self, chaos_latency_ms: u64) -> Self {
+        self.config.chaos_latency_ms = chaos_latency_ms;
+        self
+    }
+
+    pub fn chaos_failure_rate(mut self, chaos_failure_rate: f64) -> Self {
+        self.config.chaos_failure_rate = chaos_failure_rate;
+        self
+    }
+
+    pub fn chaos_seed(mut self, chaos_seed: u64) -> Self {
+        self.config.chaos_seed = chaos_seed;
+        self
+    }
+
+    /// Returns the config, or the first problem `RedisConfig::validate` finds with it.
+    pub fn build(self) -> Result<RedisConfig, RedisError> {
+        self.config.validate()?;
+        Ok(self.config)
+    }
+}
+
+/// A server running in the background, see `Redis::spawn`.
+///
+/// Dropping the handle shuts the server down without waiting for it to finish.
+#[derive(Debug)]
+pub struct ServerHandle {
+    addr: SocketAddr,
+    store: Arc<Store>,
+    shutdown: oneshot::Sender<()>,
+    task: JoinHandle<Result<(), RedisError>>,
+}
+
+impl ServerHandle {
+    /// Returns the address clients can connect to.
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Returns the keyspace of the server, e.g. to export it with `Store::snapshot_iter`.
+    pub fn store(&self) -> Arc<Store> {
+        self.store.clone()
+    }
+
+    /// Shuts the server down and waits until every connection is closed, see
+    /// `Redis::start_with_shutdown`.
+    pub async fn shutdown(self) -> Result<(), RedisError> {
+        let _ = self.shutdown.send(());
+        self.task.await.expect("Server task panicked")
+    }
+}
+
+impl Redis {
+    /// Starts a server on a port of the loopback interface picked by the OS, serving clients
+    /// on a task of the current Tokio runtime. This runs a real server inside the process,
+    /// e.g. for tests, without having to pick a free port or spawn the binary.
+    ///
+    /// `io_uring` is ignored, since io_uring connections can't be served from a spawned task.
+    pub async fn spawn(config: RedisConfig) -> Result<ServerHandle, RedisError> {
+        let config = RedisConfig {
+            io_uring: false,
+            ..config
         };
+        let redis = Self::init(vec![SocketAddr::from((Ipv4Addr::LOCALHOST, 0))], config).await?;
+        let addr = redis.local_addrs()?[0];
+        let store = redis.store();
+        let (shutdown, shutdown_rx) = oneshot::channel();
+        let task = util::spawn_named(
+            "redis",
+            redis.start_with_shutdown(async {
+                // Also shuts down if the handle is dropped.
+                let _ = shutdown_rx.await;
+            }),
+        );
 
-        Ok(Self {
-            listener,
-            handler: CommandHandler::new(
-                Arc::new(RwLock::new(HashMap::new())),
-                CommandHandlerConfig {
-                    is_replica,
-                    master_repl_id_and_offset,
-                },
-            ),
-            replication,
+        Ok(ServerHandle {
+            addr,
+            store,
+            shutdown,
+            task,
         })
     }
 
-    pub async fn start(mut self) -> Result<(), RedisError> {
-        let (reqs_ch_tx, mut reqs_ch_rx) = mpsc::channel(128);
+    /// Binds `io_threads` listeners to every address the config binds to.
+    pub async fn new(config: RedisConfig) -> Result<Self, RedisError> {
+        Self::init(config.bind_addrs(), config).await
+    }
 
-        loop {
-            tokio::select! {
-                // Handle connection
-                conn = self.listener.accept() => {
-                    let (stream, addr) = conn?;
-                    info!("Accepted new connection from {addr:?}");
-                    let reqs_ch_tx = reqs_ch_tx.clone();
-                    let session = Session::new(stream);
-                    let _ = tokio::spawn(async move {
-                        match Self::handle_connection(session, reqs_ch_tx).await {
-                            Ok(_) => (),
-                            Err(e) => error!("Error handling connection: {e}"),
-                        }
+    /// Binds `io_threads` listeners to every address in `addrs`, which should all share the
+    /// same port.
+    pub async fn init(addrs: Vec<SocketAddr>, config: RedisConfig) -> Result<Self, RedisError> {
+        Self::init_with(addrs, None, config).await
+    }
+
+    /// Serves clients on listeners bound elsewhere, e.g. passed by systemd socket
+    /// activation, instead of binding its own. The TLS, metrics and cluster bus ports are
+    /// still bound to the address of the first listener.
+    pub async fn adopt(
+        listeners: Vec<std::net::TcpListener>,
+        config: RedisConfig,
+    ) -> Result<Self, RedisError> {
+        let listeners = listeners
+            .into_iter()
+            .map(|listener| {
+                listener.set_nonblocking(true)?;
+                TcpListener::from_std(listener)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let addrs = listeners
+            .iter()
+            .map(|listener| listener.local_addr())
+            .collect::<Result<Vec<_>, _>>()?;
+        Self::init_with(addrs, Some(listeners), config).await
+    }
+
+    async fn init_with(
+        addrs: Vec<SocketAddr>,
+        adopted: Option<Vec<TcpListener>>,
+        config: RedisConfig,
+    ) -> Result<Self, RedisError> {
+        config.validate()?;
+        let uring = config.io_uring;
+        let io_threads = config.io_threads;
+        let mut listeners = Vec::new();
+        match adopted {
+            Some(adopted) => {
+                for inner in adopted {
+                    listeners.push(Listener {
+                        inner,
+                        tls: None,
+                        uring,
                     });
                 }
-
-                // Handle request from connection
-                Some(req) = reqs_ch_rx.recv() => {
-                    match self.handle_request(req).await {
-                        Ok(_) => (),
-                        Err(e) => error!("Error handling request: {e}"),
+            }
+            None => {
+                for addr in &addrs {
+                    for inner in Self::bind_shared(*addr, io_threads, config.tcp_backlog)? {
+                        listeners.push(Listener {
+                            inner,
+                            tls: None,
+                            uring,
+                        });
                     }
                 }
             }
         }
-    }
+        let server_tls = if config.tls_port != 0 {
+            Some(Arc::new(Self::server_tls(&config)?))
+        } else {
+            None
+        };
+        if let Some(server_tls) = &server_tls {
+            for addr in &addrs {
+                let addr = SocketAddr::new(addr.ip(), config.tls_port);
+                for inner in Self::bind_shared(addr, io_threads, config.tcp_backlog)? {
+                    listeners.push(Listener {
+                        inner,
+                        tls: Some(server_tls.clone()),
+                        uring,
+                    });
+                }
+            }
+        }
+        let port = addrs.first().map(|addr| addr.port()).unwrap_or_default();
+        #[cfg(feature = "metrics")]
+        let metrics_listener = match (config.metrics_port, addrs.first()) {
+            (0, _) | (_, None) => None,
+            (metrics_port, Some(addr)) => Some(Self::bind(
+                SocketAddr::new(addr.ip(), metrics_port),
+                config.tcp_backlog,
+            )?),
+        };
+        let health_listener = match (config.health_port, addrs.first()) {
+            (0, _) | (_, None) => None,
+            (health_port, Some(addr)) => Some(Self::bind(
+                SocketAddr::new(addr.ip(), health_port),
+                config.tcp_backlog,
+            )?),
+        };
 
-    async fn handle_connection(
-        mut session: Session,
-        reqs_ch_tx: mpsc::Sender<RequestChannel>,
-    ) -> Result<(), RedisError> {
-        loop {
-            let req = session.receive_request().await?;
-            if req.is_none() {
-                break;
+        let is_replica = config.master_addr.is_some();
+        let repl_state = (!is_replica).then(|| Arc::new(ReplicationState::new()));
+        #[cfg(feature = "replication")]
+        let tls_connector =
+            if config.tls_replication && (is_replica || config.import_from.is_some()) {
+                Some(Self::tls_connector(&config)?)
+            } else {
+                None
+            };
+        // The port picked by the OS if bound to port 0.
+        let cluster_addr = match listeners.first() {
+            Some(listener) => listener.inner.local_addr()?,
+            None => SocketAddr::from((Ipv4Addr::LOCALHOST, port)),
+        };
+        #[cfg(feature = "replication")]
+        let replication = match config.master_addr.clone() {
+            Some(master_addr) => {
+                let priority = config.replica_priority;
+                let priority = (priority != DEFAULT_REPLICA_PRIORITY).then_some(priority);
+                let replication = Replication::init(
+                    master_addr,
+                    cluster_addr.port(),
+                    priority,
+                    tls_connector.clone(),
+                )
+                .await?;
+                Some(replication)
             }
+            None => None,
+        };
 
-            // Send request to the request handler
-            let (req_ch, resp_rx) = RequestChannel::new(req.unwrap());
-            let _ = reqs_ch_tx.send(req_ch).await;
+        let acl = match &config.aclfile {
+            Some(path) => AccessControl::load(path)?,
+            None => AccessControl::new(),
+        };
+        let cluster_listener = if config.cluster_enabled {
+            let cport = match (config.cluster_port, port) {
+                (0, 0) => 0,
+                (0, port) => port
+                    .checked_add(BUS_PORT_OFFSET)
+                    .ok_or(RedisError::ClusterPortOverflow(port))?,
+                (cport, _) => cport,
+            };
+            Some(Self::bind(
+                SocketAddr::new(cluster_addr.ip(), cport),
+                config.tcp_backlog,
+            )?)
+        } else {
+            None
+        };
+        let cluster_file = config.dir.join(&config.cluster_config_file);
+        let capture = match &config.capture_file {
+            Some(path) => Some(Arc::new(Capture::open(path)?)),
+            None => None,
+        };
+        let audit = match &config.audit_file {
+            Some(path) => Some(Arc::new(AuditLog::open(
+                path,
+                config.audit_max_size,
+                config.audit_keep,
+            )?)),
+            None => None,
+        };
+        #[cfg(feature = "replication")]
+        let import = config
+            .import_from
+            .map(|host| Import::new(host, cluster_addr.port(), tls_connector));
 
-            // Wait for response from the request handler and send it
-            let resp = resp_rx.await.unwrap();
-            session.send_response(resp).await?;
+        #[cfg(feature = "persistence")]
+        let save_on_shutdown = config.save_on_shutdown;
+        let rdb_path = config.dir.join(&config.dbfilename);
+        let server_config = ServerConfig::new(ConfigValues {
+            port: cluster_addr.port(),
+            bind: addrs.iter().map(|addr| addr.ip()).collect(),
+            replica_of: config.master_addr,
+            replica_priority: config.replica_priority,
+            repl_diskless_load: config.repl_diskless_load,
+            dir: config.dir,
+            dbfilename: config.dbfilename,
+            tls_port: config.tls_port,
+            tls_cert_file: config.tls_cert_file,
+            tls_key_file: config.tls_key_file,
+            tls_ca_cert_file: config.tls_ca_cert_file,
+            tls_auth_clients: config.tls_auth_clients,
+            tls_replication: config.tls_replication,
+            aclfile: config.aclfile,
+            maxmemory: config.maxmemory,
+            maxmemory_policy: config.maxmemory_policy,
+            daemonize: config.daemonize,
+            pidfile: config.pidfile,
+            logfile: config.logfile,
+            loglevel: config.loglevel,
+            tcp_keepalive: config.tcp_keepalive,
+            write_timeout: config.write_timeout,
+            client_query_buffer_limit: config.client_query_buffer_limit,
+            tcp_backlog: config.tcp_backlog,
+            io_threads,
+            cluster_enabled: config.cluster_enabled,
+            cluster_config_file: config.cluster_config_file,
+            cluster_node_timeout: config.cluster_node_timeout,
+            cluster_port: config.cluster_port,
+            ..Default::default()
+        });
+        let server_config = Arc::new(server_config);
+        #[cfg(feature = "tls")]
+        if let Some(server_tls) = server_tls {
+            server_config.set_tls_control(server_tls);
         }
 
-        Ok(())
-    }
+        let store = if config.cluster_enabled {
+            Store::with_slot_index(DEFAULT_SHARDS)
+        } else {
+            Store::default()
+        };
+        let mut handler = CommandHandler::new(
+            Arc::new(store),
+            server_config.clone(),
+            Arc::new(PersistenceState::new()),
+            Arc::new(MemoryTracker::default()),
+            Arc::new(RwLock::new(acl)),
+            Arc::new(ClientRegistry::new()),
+            repl_state,
+        );
+        // A replica continues from where the file leaves its master's stream, if it can.
+        let position = Self::load_rdb(&handler, &rdb_path).await?;
+        if is_replica {
+            handler = handler.with_replica(Arc::new(ReplicaState::new(position)));
+        }
+        if let Some(capture) = capture {
+            handler = handler.with_capture(capture);
+        }
+        if let Some(audit) = audit {
+            handler = handler.with_audit(audit);
+        }
+        #[cfg(feature = "chaos")]
+        if config.chaos_latency_ms != 0 || config.chaos_failure_rate != 0.0 {
+            handler = handler.with_chaos(Arc::new(Chaos::new(
+                Duration::from_millis(config.chaos_latency_ms),
+                config.chaos_failure_rate,
+                config.chaos_seed,
+            )));
+        }
+        let cluster_bus = match cluster_listener {
+            Some(listener) => {
+                let (ip, port) = (cluster_addr.ip(), cluster_addr.port());
+                let cport = listener.local_addr()?.port();
+                let cluster = if cluster_file.exists() {
+                    ClusterState::load(&cluster_file, ip, port, cport)?
+                } else {
+                    ClusterState::new(ClusterNode::new(ip, port, cport))
+                };
+                let cluster = Arc::new(RwLock::new(cluster));
+                handler = handler.with_cluster(cluster.clone());
+                let bus = ClusterBus::new(cluster, server_config, handler.clock(), cluster_file);
+                Some((listener, bus))
+            }
+            None => None,
+        };
 
-    async fn handle_request(&mut self, req_ch: RequestChannel) -> Result<(), RedisError> {
-        // Handle request and send back response via channel
-        let RequestChannel { req, tx } = req_ch;
-        let cmd = req.as_command()?;
-        let resp: Response = self.handler.handle(cmd)?.into();
-        let _ = tx.send(resp);
+        Ok(Self {
+            listeners,
+            handler,
+            #[cfg(feature = "replication")]
+            replication,
+            #[cfg(feature = "replication")]
+            import,
+            #[cfg(feature = "metrics")]
+            metrics_listener,
+            health_listener,
+            #[cfg(feature = "persistence")]
+            save_on_shutdown,
+            cluster_bus,
+        })
+    }
+
+    /// Loads the keys of the RDB file, if there is one, returning the replication ID and
+    /// offset it records. A file that can't be parsed fails the start, like Redis, rather
+    /// than serving without its keys.
+    async fn load_rdb(
+        handler: &CommandHandler,
+        path: &Path,
+    ) -> Result<Option<(String, u64)>, RedisError> {
+        let rdb = match tokio::fs::read(path).await {
+            Ok(rdb) => rdb,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let dataset = rdb::parse(&rdb, handler.clock().now())?;
+        info!(
+            "Loaded {} keys from {}, skipped {}",
+            dataset.entries.len(),
+            path.display(),
+            dataset.skipped
+        );
+        if dataset.is_partial() {
+            warn!(
+                "{} has data that wasn't loaded, it won't be saved over",
+                path.display()
+            );
+            handler.persistence().set_partial_load(true);
+        }
+        handler.load(dataset.entries);
+        Ok(dataset.repl_id.zip(dataset.repl_offset))
+    }
+
+    /// Tells the listener about every set, delete, expiration and eviction of a key, from the
+    /// connection or background task that made it.
+    pub fn with_key_event_listener(mut self, listener: Arc<dyn KeyEventListener>) -> Self {
+        self.handler = self.handler.with_key_event_listener(listener);
+        self
+    }
+
+    /// Lets CONFIG SET loglevel change how much is logged, through the control. The level
+    /// of the config is applied right away.
+    pub fn with_log_control(self, control: Arc<dyn LogLevelControl>) -> Self {
+        self.handler.config().set_log_control(control);
+        self
+    }
+
+    /// Returns the keyspace the server serves, shared with it, e.g. to export it with
+    /// `Store::snapshot_iter` while it runs.
+    pub fn store(&self) -> Arc<Store> {
+        self.handler.store()
+    }
+
+    /// Adds a command run by the plugin, failing if a command with its name exists.
+    pub fn register_command(&mut self, plugin: Arc<dyn CommandPlugin>) -> Result<(), PluginError> {
+        self.handler.register_command(plugin)
+    }
+
+    /// Returns the addresses the listeners are bound to, which is useful to find the port
+    /// picked by the OS when binding to port 0.
+    pub fn local_addrs(&self) -> Result<Vec<SocketAddr>, RedisError> {
+        let mut addrs = self
+            .listeners
+            .iter()
+            .map(|listener| listener.inner.local_addr())
+            .collect::<Result<Vec<_>, _>>()?;
+        // Listeners sharing an address are next to each other.
+        addrs.dedup();
+        Ok(addrs)
+    }
+
+    /// Serves clients forever.
+    pub async fn start(self) -> Result<(), RedisError> {
+        self.start_with_shutdown(std::future::pending()).await
+    }
+
+    /// Serves clients until `shutdown` completes. The server then stops accepting
+    /// connections, lets every connection finish its in-flight request and returns once all
+    /// of them are closed, after saving the keys to the RDB file if `save_on_shutdown` is
+    /// set.
+    pub async fn start_with_shutdown(
+        mut self,
+        shutdown: impl Future<Output = ()>,
+    ) -> Result<(), RedisError> {
+        let (stop_tx, stop_rx) = watch::channel(false);
+        // Every accept loop and connection holds a sender and never sends, so receiving
+        // returns once they are all gone.
+        let (done_tx, mut done_rx) = mpsc::channel::<()>(1);
+
+        // Every listener gets its own accept loop, and every connection runs its own commands.
+        for listener in self.listeners.drain(..) {
+            let handler = self.handler.clone();
+            let done_tx = done_tx.clone();
+            let stop_rx = stop_rx.clone();
+            let uring = listener.uring;
+            let accept = async move {
+                match Self::accept_loop(listener, handler, done_tx, stop_rx).await {
+                    Ok(_) => (),
+                    Err(e) => error!("Error accepting connection: {e}"),
+                }
+            };
+            // io_uring connections are spawned from the accept loop as local tasks, which it
+            // must then be too.
+            if uring {
+                tokio::task::spawn_local(accept);
+            } else {
+                util::spawn_named("accept", accept);
+            }
+        }
+        drop(done_tx);
+
+        util::spawn_named(
+            "active-expire",
+            Self::active_expire_loop(self.handler.clone(), stop_rx.clone()),
+        );
 
+        if let Some((listener, bus)) = self.cluster_bus.take() {
+            util::spawn_named("cluster-bus", bus.run(listener, stop_rx.clone()));
+        }
+
+        #[cfg(feature = "replication")]
+        if let Some(replication) = self.replication.take() {
+            self.handler.spawn_link(async { Ok(replication) });
+        }
+
+        #[cfg(feature = "replication")]
+        if let Some(import) = self.import.take() {
+            let import = import.run(self.handler.clone(), stop_rx.clone());
+            util::spawn_named("import", async move {
+                if let Err(e) = import.await {
+                    error!("Error importing from master: {e}");
+                }
+            });
+        }
+
+        #[cfg(feature = "metrics")]
+        if let Some(listener) = self.metrics_listener.take() {
+            let source = self.handler.metrics_source();
+            let stop_rx = stop_rx.clone();
+            util::spawn_named("metrics", async move {
+                if let Err(e) = metrics::serve(listener, source, stop_rx).await {
+                    error!("Error serving metrics: {e}");
+                }
+            });
+        }
+
+        if let Some(listener) = self.health_listener.take() {
+            let source = self.handler.health_source();
+            let stop_rx = stop_rx.clone();
+            util::spawn_named("health", async move {
+                if let Err(e) = health::serve(listener, source, stop_rx).await {
+                    error!("Error answering health probes: {e}");
+                }
+            });
+        }
+
+        // After shutdown, wait for every accept loop and connection to stop.
+        tokio::select! {
+            _ = shutdown => {
+                info!("Shutting down, waiting for connections to finish...");
+                let _ = stop_tx.send(true);
+                #[cfg(feature = "replication")]
+                self.handler.stop_replicating();
+                done_rx.recv().await;
+            }
+            _ = done_rx.recv() => (),
+        }
+
+        #[cfg(feature = "persistence")]
+        if self.save_on_shutdown {
+            info!("Saving the final RDB snapshot before exiting.");
+            if let Err(e) = self.handler.save() {
+                error!("Error saving the final RDB snapshot: {e}");
+            }
+        }
+
+        info!("Redis is now ready to exit, bye bye...");
         Ok(())
     }
+
+    /// Binds a TCP listener to the address. IPv6 listeners only accept IPv6 connections,
+    /// so that `0.0.0.0` and `::` can be bound on the same port.
+    fn bind(addr: SocketAddr, backlog: u32) -> Result<TcpListener, RedisError> {
+        Self::bind_with(addr, backlog, false)
+    }
+
+    /// Binds `count` listeners to the address with SO_REUSEPORT, or a single one if `count`
+    /// is 1 or the platform doesn't support it. A port of 0 is picked by the OS for the first
+    /// listener and shared by the others.
+    fn bind_shared(
+        addr: SocketAddr,
+        count: usize,
+        backlog: u32,
+    ) -> Result<Vec<TcpListener>, RedisError> {
+        if count <= 1 || !cfg!(unix) {
+            return Ok(vec![Self::bind(addr, backlog)?]);
+        }
+
+        let first = Self::bind_with(addr, backlog, true)?;
+        let addr = first.local_addr()?;
+        let mut listeners = vec![first];
+        for _ in 1..count {
+            listeners.push(Self::bind_with(addr, backlog, true)?);
+        }
+        Ok(listeners)
+    }
+
+    fn bind_with(
+        addr: SocketAddr,
+        backlog: u32,
+        reuse_port: bool,
+    ) -> Result<TcpListener, RedisError> {
+        let socket = Socket::new(Domain::for_address(addr), Type::STREAM, None)?;
+        if addr.is_ipv6() {
+            socket.set_only_v6(true)?;
+        }
+        socket.set_reuse_address(true)?;
+        #[cfg(unix)]
+        socket.set_reuse_port(reuse_port)?;
+        #[cfg(not(unix))]
+        let _ = reuse_port;
+        socket.set_nonblocking(true)?;
+        socket.bind(&addr.into())?;
+        socket.listen(backlog.min(i32::MAX as u32) as i32)?;
+
+        info!("Listening to {addr}...");
+        Ok(TcpListener::from_std(socket.into())?)
+    }
+
+    #[cfg(feature = "tls")]
+    fn tls_files(config: &RedisConfig) -> tls::TlsFiles<'_> {
+        tls::TlsFiles {
+            cert_file: config.tls_cert_file.as_deref(),
+            key_file: config.tls_key_file.as_deref(),
+            ca_cert_file: config.tls_ca_cert_file.as_deref(),
+        }
+    }
+
+    #[cfg(feature = "tls")]
+    fn server_tls(config: &RedisConfig) -> Result<ServerTls, RedisError> {
+        Ok(ServerTls::new(
+            Self::tls_files(config),
+            config.tls_auth_clients,
+        )?)
+    }
+
+    #[cfg(all(feature = "tls", feature = "replication"))]
+    fn tls_connector(config: &RedisConfig) -> Result<TlsConnector, RedisError> {
+        Ok(tls::client_connector(Self::tls_files(config))?)
+    }
+
+    #[cfg(not(feature = "tls"))]
+    fn server_tls(_config: &RedisConfig) -> Result<ServerTls, RedisError> {
+        Err(RedisError::TlsUnavailable)
+    }
+
+    #[cfg(all(not(feature = "tls"), feature = "replication"))]
+    fn tls_connector(_config: &RedisConfig) -> Result<TlsConnector, RedisError> {
+        Err(RedisError::TlsUnavailable)
+    }
+
+    async fn accept_loop(
+        listener: Listener,
+        handler: CommandHandler,
+        done_tx: mpsc::Sender<()>,
+        mut stop_rx: watch::Receiver<bool>,
+    ) -> Result<(), RedisError> {
+        let (acl, config, clients) = (handler.acl(), handler.config(), handler.clients());
+        loop {
+            let (stream, addr) = tokio::select! {
+                accepted = listener.inner.accept() => accepted?,
+                _ = stop_rx.changed() => return Ok(()),
+            };
+            info!("Accepted new connection from {addr:?}");
+            if clients.len() >= config.read().maxclients {
+                Self::refuse(stream, listener.tls.is_none());
+                clients.record_rejected();
+                continue;
+            }
+            let keepalive = config.read().tcp_keepalive;
+            let (stream, local_addr) = match Self::prepare_socket(stream, keepalive) {
+                Ok(prepared) => prepared,
+                Err(e) => {
+                    // One broken socket, e.g. reset by the peer right away, mustn't stop
+                    // the listener. Only failing to accept does.
+                    warn!("Dropping connection from {addr:?}: {e}");
+                    continue;
+                }
+            };
+            let handler = handler.clone();
+            let done_tx = done_tx.clone();
+            let tls = listener.tls.clone();
+            let user = acl.read().implicit_user();
+            let client = clients.register(user, Some((addr, local_addr)));
+            let stop_rx = stop_rx.clone();
+            #[cfg(all(feature = "io-uring", target_os = "linux"))]
+            if listener.uring {
+                tokio::task::spawn_local(async move {
+                    match uring::UringStream::new(stream) {
+                        Ok(stream) => {
+                            Self::serve(stream, tls, handler, client, stop_rx, LocalWriter, done_tx)
+                                .await
+                        }
+                        Err(e) => error!("Error handling connection: {e}"),
+                    }
+                });
+                continue;
+            }
+            util::spawn_named(
+                "connection",
+                Self::serve(
+                    stream,
+                    tls,
+                    handler,
+                    client,
+                    stop_rx,
+                    RuntimeWriter,
+                    done_tx,
+                ),
+            );
+        }
+    }
+
+    /// Serves the connection until it closes, logging why if it failed. The TLS handshake
+    /// happens here, inside the connection task, so it can't stall accepting.
+    async fn serve<S, W>(
+        stream: S,
+        tls: Option<Arc<ServerTls>>,
+        handler: CommandHandler,
+        client: ClientHandle,
+        stop_rx: watch::Receiver<bool>,
+        writer: W,
+        _done: mpsc::Sender<()>,
+    ) where
+        S: AsyncRead + AsyncWrite + Unpin,
+        W: SpawnWriter<S> + SpawnWriter<TlsStream<S>>,
+    {
+        let result = match tls {
+            #[cfg(feature = "tls")]
+            Some(tls) => match tls.acceptor().accept(stream).await {
+                Ok(stream) => {
+                    let session = Session::from_stream(stream);
+                    Self::handle_connection(session, handler, client, stop_rx, writer).await
+                }
+                Err(e) => Err(e.into()),
+            },
+            #[cfg(not(feature = "tls"))]
+            Some(never) => match *never {},
+            None => {
+                let session = Session::from_stream(stream);
+                Self::handle_connection(session, handler, client, stop_rx, writer).await
+            }
+        };
+        if let Err(e) = result {
+            error!("Error handling connection: {e}");
+        }
+    }
+
+    /// Closes a connection over maxclients, first telling the client why unless the
+    /// connection needs a TLS handshake.
+    fn refuse(mut stream: TcpStream, reply: bool) {
+        warn!("Refusing connection, max number of clients reached");
+        if !reply {
+            return;
+        }
+        util::spawn_named("refuse", async move {
+            let _ = stream
+                .write_all(b"-ERR max number of clients reached\r\n")
+                .await;
+        });
+    }
+
+    /// Removes expired keys in the background, `hz` times per second, until the server stops.
+    async fn active_expire_loop(handler: CommandHandler, mut stop_rx: watch::Receiver<bool>) {
+        let clock = handler.clock();
+        loop {
+            let hz = handler.config().read().hz.max(1);
+            tokio::select! {
+                _ = clock.sleep(Duration::from_secs(1) / hz) => (),
+                _ = stop_rx.changed() => return,
+            }
+            let removed = handler.active_expire_cycle();
+            if removed > 0 {
+                debug!("Removed {removed} expired keys");
+            }
+        }
+    }
+
+    /// Tunes an accepted socket, returning it along with its local address.
+    fn prepare_socket(
+        stream: TcpStream,
+        keepalive: u64,
+    ) -> Result<(TcpStream, SocketAddr), RedisError> {
+        let stream = Self::tune_socket(stream, keepalive)?;
+        let local_addr = stream.local_addr()?;
+        Ok((stream, local_addr))
+    }
+
+    /// Disables Nagle's algorithm so replies are sent right away, and enables keepalive
+    /// probes after `keepalive` seconds of idleness to detect dead peers.
+    fn tune_socket(stream: TcpStream, keepalive: u64) -> Result<TcpStream, RedisError> {
+        stream.set_nodelay(true)?;
+        if keepalive == 0 {
+            return Ok(stream);
+        }
+
+        let time = Duration::from_secs(keepalive);
+        let params = TcpKeepalive::new().with_time(time);
+        // Like Redis, probe a few times within the interval before giving up.
+        #[cfg(any(target_os = "linux", target_os = "macos", target_os = "windows"))]
+        let params = params.with_interval((time / 3).max(Duration::from_secs(1)));
+
+        // Socket options need the std stream, which stays nonblocking through the round trip.
+        let stream = stream.into_std()?;
+        SockRef::from(&stream).set_tcp_keepalive(&params)?;
+        Ok(TcpStream::from_std(stream)?)
+    }
+
+    /// Reads requests and runs their commands. Replies and out-of-band messages like
+    /// invalidations are queued for a writer task spawned by `spawner`, so the client can
+    /// receive messages while it is idle, and is joined once the connection ends.
+    async fn handle_connection<S, W>(
+        session: Session<S>,
+        mut handler: CommandHandler,
+        mut client: ClientHandle,
+        mut stop_rx: watch::Receiver<bool>,
+        spawner: W,
+    ) -> Result<(), RedisError>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+        W: SpawnWriter<S>,
+    {
+        let (mut reader, writer) = session.split();
+        let outbound = client.sender();
+        let id = client.id();
+        let writer = spawner.spawn(ConnectionWriter {
+            writer,
+            outbound_rx: client.take_receiver().expect("Outbound receiver taken"),
+            config: handler.config(),
+            id,
+            state: Arc::downgrade(&client.state()),
+        });
+
+        let mut closed = false;
+        let result = async {
+            loop {
+                reader.set_query_buffer_limit(handler.config().read().client_query_buffer_limit);
+                // Only wait for the next request while running, an in-flight one always
+                // finishes.
+                let req = tokio::select! {
+                    req = reader.receive_request() => match req {
+                        Err(SessionError::QueryBufferLimit(len)) => {
+                            warn!("Closing client {id} that reached the query buffer limit with {len} bytes");
+                            break;
+                        }
+                        req => req?,
+                    },
+                    _ = stop_rx.changed() => break,
+                    _ = client.closed() => {
+                        closed = true;
+                        break;
+                    }
+                };
+                let Some(req) = req else {
+                    break;
+                };
+                #[cfg(feature = "chaos")]
+                if let Some(chaos) = handler.chaos() {
+                    tokio::time::sleep(chaos.latency()).await;
+                }
+
+                // Writes wait for a FAILOVER to end, then run on whichever side this server
+                // ended up on.
+                #[cfg(feature = "replication")]
+                if let Some(replication) = handler.write_pause(&req) {
+                    tokio::select! {
+                        _ = replication.failover_ended() => (),
+                        _ = stop_rx.changed() => break,
+                        _ = client.closed() => {
+                            closed = true;
+                            break;
+                        }
+                    }
+                }
+
+                let reply = Self::handle_request(&mut handler, req, &client.state());
+                handler.deliver_invalidations(client.id());
+                // The client is blocked until a deferred reply is ready, like WAIT.
+                let reply = match reply {
+                    Reply::Deferred(deferred) => tokio::select! {
+                        value = deferred.value() => Reply::from(value),
+                        _ = stop_rx.changed() => break,
+                        _ = client.closed() => {
+                            closed = true;
+                            break;
+                        }
+                    },
+                    reply => reply,
+                };
+                // Waiting for room in the queue stops reading requests until the client
+                // catches up with its replies.
+                tokio::select! {
+                    sent = outbound.send(reply) => if sent.is_err() {
+                        break;
+                    },
+                    _ = client.closed() => {
+                        closed = true;
+                        break;
+                    }
+                }
+            }
+            Ok::<(), RedisError>(())
+        }
+        .await;
+
+        // Unregistering the client and dropping the sender closes the outbound queue, so the
+        // writer exits once every queued message is written.
+        drop(client);
+        drop(outbound);
+        // A client closed for falling behind isn't waited for.
+        if closed {
+            writer.abort();
+            return result;
+        }
+        match writer.await {
+            Ok(written) => written?,
+            Err(e) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+            Err(_) => (),
+        }
+        result
+    }
+
+    /// Runs the command of the request on behalf of the client, which commands like AUTH
+    /// may change.
+    fn handle_request(handler: &mut CommandHandler, req: Request, client: &SharedClient) -> Reply {
+        #[cfg(feature = "chaos")]
+        if handler.chaos().is_some_and(|chaos| chaos.should_fail()) {
+            return HandleCommandError::InjectedFault.reply().into();
+        }
+        let mut client = client.lock();
+        let result = handler
+            .parse(&req)
+            .map_err(|e| {
+                // Calls with the wrong number of arguments count as rejected, like Redis.
+                if let ParseCommandError::WrongArity(name) = e {
+                    handler.stats().record_rejected(name);
+                }
+                HandleCommandError::from(e)
+            })
+            .and_then(|cmd| {
+                // Only commands that parse are captured, as the others never run.
+                if handler.is_capturing() {
+                    handler.record(&req);
+                }
+                handler.handle_request(cmd, &req, &mut client)
+            });
+        let reply = match result {
+            Ok(reply) => reply,
+            Err(e) => e.reply().into(),
+        };
+        // Encoded by the protocol the client speaks once the command ran, so the reply to
+        // HELLO 3 is already in RESP3.
+        reply.into_protocol(client.resp)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::ops::RangeInclusive;
+    use std::time::Instant;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::sync::oneshot;
+
+    use super::cluster::key_hash_slot;
+    use super::*;
+
+    fn test_config() -> RedisConfig {
+        RedisConfig {
+            bind: vec![IpAddr::V4(Ipv4Addr::LOCALHOST)],
+            port: 0,
+            master_addr: None,
+            repl_diskless_load: ReplDisklessLoad::Disabled,
+            replica_priority: DEFAULT_REPLICA_PRIORITY,
+            dir: PathBuf::from("."),
+            dbfilename: "dump.rdb".to_string(),
+            save_on_shutdown: false,
+            tls_port: 0,
+            tls_cert_file: None,
+            tls_key_file: None,
+            tls_ca_cert_file: None,
+            tls_auth_clients: TlsAuthClients::No,
+            tls_replication: false,
+            import_from: None,
+            aclfile: None,
+            maxmemory: 0,
+            maxmemory_policy: EvictionPolicy::NoEviction,
+            daemonize: false,
+            pidfile: None,
+            logfile: None,
+            loglevel: LogLevel::Notice,
+            tcp_keepalive: 300,
+            write_timeout: 0,
+            client_query_buffer_limit: DEFAULT_QUERY_BUFFER_LIMIT,
+            tcp_backlog: 511,
+            io_threads: 1,
+            io_uring: false,
+            metrics_port: 0,
+            health_port: 0,
+            cluster_enabled: false,
+            cluster_config_file: "nodes.conf".to_string(),
+            cluster_node_timeout: 15000,
+            cluster_port: 0,
+            capture_file: None,
+            audit_file: None,
+            audit_max_size: 0,
+            audit_keep: 5,
+            chaos_latency_ms: 0,
+            chaos_failure_rate: 0.0,
+            chaos_seed: 0,
+        }
+    }
+
+    /// Returns the config of a cluster node saving its nodes.conf to a directory of its own.
+    fn cluster_config(name: &str) -> RedisConfig {
+        let dir = std::env::temp_dir().join(format!("cluster-test-{}-{name}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("Create dir unexpected error");
+        let _ = std::fs::remove_file(dir.join("nodes.conf"));
+        RedisConfig {
+            dir,
+            cluster_enabled: true,
+            cluster_node_timeout: 1000,
+            ..test_config()
+        }
+    }
+
+    async fn cluster_command(stream: &mut TcpStream, args: &[&str]) -> String {
+        let mut req = format!("*{}\r\n", args.len());
+        for arg in args {
+            req.push_str(&format!("${}\r\n{arg}\r\n", arg.len()));
+        }
+        stream
+            .write_all(req.as_bytes())
+            .await
+            .expect("Write unexpected error");
+        let mut buf = vec![0; 4096];
+        let n = stream.read(&mut buf).await.expect("Read unexpected error");
+        String::from_utf8_lossy(&buf[..n]).into_owned()
+    }
+
+    #[test]
+    fn builder_validates_config() {
+        let config = RedisConfig::builder()
+            .port(7000)
+            .io_threads(4)
+            .build()
+            .expect("Build unexpected error");
+        assert_eq!(config.bind_addrs(), ["127.0.0.1:7000".parse().unwrap()]);
+        assert_eq!(config.io_threads, 4);
+
+        let err = RedisConfig::builder()
+            .io_threads(0)
+            .build()
+            .expect_err("Build no error");
+        assert!(matches!(err, RedisError::InvalidConfig(_)));
+        let err = RedisConfig::builder()
+            .chaos_failure_rate(1.5)
+            .build()
+            .expect_err("Build no error");
+        assert!(matches!(
+            err,
+            RedisError::InvalidConfig(_) | RedisError::ChaosUnavailable
+        ));
+        let master = Some("127.0.0.1:6379".parse().unwrap());
+        let err = RedisConfig::builder()
+            .master_addr(master.clone())
+            .import_from(master)
+            .build()
+            .expect_err("Build no error");
+        assert!(matches!(err, RedisError::ImportReplica));
+    }
+
+    #[tokio::test]
+    async fn adopt_serves_on_given_listeners() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").expect("Bind unexpected error");
+        let addr = listener.local_addr().expect("Local addr unexpected error");
+        let redis = Redis::adopt(vec![listener], test_config())
+            .await
+            .expect("Adopt unexpected error");
+        assert_eq!(
+            redis.local_addrs().expect("Local addrs unexpected error"),
+            [addr]
+        );
+        tokio::spawn(redis.start());
+
+        let mut client = client::RedisClient::connect(addr)
+            .await
+            .expect("Connect unexpected error");
+        client
+            .command(["ping"])
+            .await
+            .expect("Ping unexpected error");
+    }
+
+    #[tokio::test]
+    async fn start_with_shutdown_returns_after_shutdown() {
+        let redis = Redis::init(vec!["127.0.0.1:0".parse().unwrap()], test_config())
+            .await
+            .expect("Init redis unexpected error");
+        let addr = redis.local_addrs().expect("Local addrs unexpected error")[0];
+        let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+        let server = tokio::spawn(redis.start_with_shutdown(async {
+            let _ = shutdown_rx.await;
+        }));
+
+        let mut stream = TcpStream::connect(addr)
+            .await
+            .expect("Connect unexpected error");
+        stream
+            .write_all(b"*1\r\n$4\r\nPING\r\n")
+            .await
+            .expect("Write unexpected error");
+        let mut buf = [0; 7];
+        stream
+            .read_exact(&mut buf)
+            .await
+            .expect("Read unexpected error");
+        assert_eq!(&buf, b"+PONG\r\n");
+
+        // The idle connection is closed and the server returns
+        shutdown_tx.send(()).unwrap();
+        server
+            .await
+            .expect("Join unexpected error")
+            .expect("Start redis unexpected error");
+        assert_eq!(
+            stream.read(&mut buf).await.expect("Read unexpected error"),
+            0
+        );
+        assert!(TcpStream::connect(addr).await.is_err());
+    }
+
+    #[cfg(all(feature = "io-uring", target_os = "linux"))]
+    #[test]
+    fn serves_clients_over_io_uring() {
+        tokio_uring::start(async {
+            let config = RedisConfig {
+                io_uring: true,
+                ..test_config()
+            };
+            let redis = Redis::init(vec!["127.0.0.1:0".parse().unwrap()], config)
+                .await
+                .expect("Init redis unexpected error");
+            let addr = redis.local_addrs().expect("Local addrs unexpected error")[0];
+            let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+            let server = tokio::task::spawn_local(redis.start_with_shutdown(async {
+                let _ = shutdown_rx.await;
+            }));
+
+            let mut client = client::RedisClient::connect(addr)
+                .await
+                .expect("Connect unexpected error");
+            client
+                .set("key", "value")
+                .await
+                .expect("Set unexpected error");
+            let value = client.get("key").await.expect("Get unexpected error");
+            assert_eq!(value, Some("value".into()));
+
+            shutdown_tx.send(()).unwrap();
+            server
+                .await
+                .expect("Join unexpected error")
+                .expect("Start redis unexpected error");
+        });
+    }
+
+    #[tokio::test]
+    async fn pipelined_commands_are_answered_in_order() {
+        let handle = Redis::spawn(test_config())
+            .await
+            .expect("Spawn redis unexpected error");
+        let mut stream = TcpStream::connect(handle.addr())
+            .await
+            .expect("Connect unexpected error");
+
+        stream
+            .write_all(b"*3\r\n$3\r\nSET\r\n$1\r\nk\r\n$1\r\nv\r\n*2\r\n$3\r\nGET\r\n$1\r\nk\r\n*1\r\n$4\r\nPING\r\n")
+            .await
+            .expect("Write unexpected error");
+        let expected = b"+OK\r\n$1\r\nv\r\n+PONG\r\n";
+        let mut buf = [0; 19];
+        stream
+            .read_exact(&mut buf)
+            .await
+            .expect("Read unexpected error");
+        assert_eq!(&buf, expected);
+
+        handle.shutdown().await.expect("Shutdown unexpected error");
+    }
+
+    #[tokio::test]
+    async fn slow_reader_does_not_stall_requests() {
+        let mut handler = test_util::command_handler();
+        let value = "x".repeat(1000);
+        let set = cmd::Command::Set(cmd::SetArg {
+            key: "key".into(),
+            value: value.as_str().into(),
+            expiry: None,
+            condition: None,
+            get: false,
+        });
+        handler
+            .handle(set, &mut test_util::client_state())
+            .expect("Handle set unexpected error");
+        let store = handler.store();
+        let client = handler.clients().register(Some("default".into()), None);
+        let (_stop_tx, stop_rx) = watch::channel(false);
+        // The pipe holds a fraction of a single reply until the peer reads it.
+        let (peer, stream) = tokio::io::duplex(64);
+        let (mut peer_reader, mut peer_writer) = tokio::io::split(peer);
+        let connection = tokio::spawn(Redis::handle_connection(
+            Session::new(stream),
+            handler,
+            client,
+            stop_rx,
+            RuntimeWriter,
+        ));
+
+        let get = b"*2\r\n$3\r\nGET\r\n$3\r\nkey\r\n";
+        let mut requests = get.repeat(20);
+        requests.extend_from_slice(b"*3\r\n$3\r\nSET\r\n$6\r\nmarker\r\n$1\r\n1\r\n");
+        tokio::spawn(async move {
+            peer_writer
+                .write_all(&requests)
+                .await
+                .expect("Write unexpected error");
+            peer_writer
+        });
+
+        // Requests keep being handled while the replies to the earlier ones wait to be read.
+        tokio::time::timeout(Duration::from_secs(2), async {
+            while store.read(b"marker").get(b"marker".as_slice()).is_none() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("Requests stalled behind unread replies");
+
+        // The replies all arrive, in order, to a reader taking a few bytes at a time.
+        let reply = format!("${}\r\n{value}\r\n", value.len());
+        let expected = [reply.repeat(20).as_bytes(), b"+OK\r\n"].concat();
+        let mut received = Vec::new();
+        let mut buf = [0; 100];
+        while received.len() < expected.len() {
+            let n = peer_reader
+                .read(&mut buf)
+                .await
+                .expect("Read unexpected error");
+            assert_ne!(n, 0, "Connection closed early");
+            received.extend_from_slice(&buf[..n]);
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+        assert_eq!(received, expected);
+        connection.abort();
+    }
+
+    #[tokio::test]
+    async fn write_timeout_closes_stalled_clients() {
+        let mut handler = test_util::command_handler();
+        handler
+            .config()
+            .set(&[("write-timeout".to_string(), "100".to_string())])
+            .expect("Set config unexpected error");
+        let set = cmd::Command::Set(cmd::SetArg {
+            key: "key".into(),
+            value: "x".repeat(100).as_str().into(),
+            expiry: None,
+            condition: None,
+            get: false,
+        });
+        handler
+            .handle(set, &mut test_util::client_state())
+            .expect("Handle set unexpected error");
+        let client = handler.clients().register(None, None);
+        let (_stop_tx, stop_rx) = watch::channel(false);
+        // The pipe holds the request but only part of the reply, which is never read.
+        let (mut peer, stream) = tokio::io::duplex(32);
+        peer.write_all(b"*2\r\n$3\r\nGET\r\n$3\r\nkey\r\n")
+            .await
+            .expect("Write unexpected error");
+
+        let connection = Redis::handle_connection(
+            Session::new(stream),
+            handler,
+            client,
+            stop_rx,
+            RuntimeWriter,
+        );
+        tokio::time::timeout(Duration::from_secs(2), connection)
+            .await
+            .expect("Stalled client not closed")
+            .expect("Handle connection unexpected error");
+    }
+
+    #[tokio::test]
+    async fn query_buffer_limit_closes_clients() {
+        let handler = test_util::command_handler();
+        handler
+            .config()
+            .set(&[("client-query-buffer-limit".to_string(), "1mb".to_string())])
+            .expect("Set config unexpected error");
+        let client = handler.clients().register(None, None);
+        let (_stop_tx, stop_rx) = watch::channel(false);
+        let (mut peer, stream) = tokio::io::duplex(64 * 1024);
+
+        let connection = Redis::handle_connection(
+            Session::new(stream),
+            handler,
+            client,
+            stop_rx,
+            RuntimeWriter,
+        );
+        let connection = tokio::spawn(connection);
+        peer.write_all(b"*2\r\n$3\r\nGET\r\n$2000000\r\n")
+            .await
+            .expect("Write unexpected error");
+        // The writes fail once the connection is closed.
+        let chunk = vec![b'x'; 64 * 1024];
+        for _ in 0..32 {
+            if peer.write_all(&chunk).await.is_err() {
+                break;
+            }
+        }
+        tokio::time::timeout(Duration::from_secs(2), connection)
+            .await
+            .expect("Client past the query buffer limit not closed")
+            .expect("Connection task panicked")
+            .expect("Handle connection unexpected error");
+    }
+
+    #[tokio::test]
+    async fn maxclients_refuses_connections() {
+        let redis = Redis::init(vec!["127.0.0.1:0".parse().unwrap()], test_config())
+            .await
+            .expect("Init redis unexpected error");
+        let addr = redis.local_addrs().expect("Local addrs unexpected error")[0];
+        tokio::spawn(redis.start());
+
+        let mut first = TcpStream::connect(addr)
+            .await
+            .expect("Connect unexpected error");
+        first
+            .write_all(b"*4\r\n$6\r\nCONFIG\r\n$3\r\nSET\r\n$10\r\nmaxclients\r\n$1\r\n1\r\n")
+            .await
+            .expect("Write unexpected error");
+        let mut buf = [0; 5];
+        first
+            .read_exact(&mut buf)
+            .await
+            .expect("Read unexpected error");
+        assert_eq!(&buf, b"+OK\r\n");
+
+        let mut second = TcpStream::connect(addr)
+            .await
+            .expect("Connect unexpected error");
+        let mut reply = Vec::new();
+        second
+            .read_to_end(&mut reply)
+            .await
+            .expect("Read unexpected error");
+        assert_eq!(reply, b"-ERR max number of clients reached\r\n");
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn slow_command_does_not_stall_other_connections() {
+        let redis = Redis::init(vec!["127.0.0.1:0".parse().unwrap()], test_config())
+            .await
+            .expect("Init redis unexpected error");
+        let addr = redis.local_addrs().expect("Local addrs unexpected error")[0];
+        tokio::spawn(redis.start());
+
+        let mut sleeper = TcpStream::connect(addr)
+            .await
+            .expect("Connect unexpected error");
+        sleeper
+            .write_all(b"*3\r\n$5\r\nDEBUG\r\n$5\r\nSLEEP\r\n$1\r\n1\r\n")
+            .await
+            .expect("Write unexpected error");
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let start = Instant::now();
+        let mut stream = TcpStream::connect(addr)
+            .await
+            .expect("Connect unexpected error");
+        stream
+            .write_all(b"*1\r\n$4\r\nPING\r\n")
+            .await
+            .expect("Write unexpected error");
+        let mut buf = [0; 7];
+        stream
+            .read_exact(&mut buf)
+            .await
+            .expect("Read unexpected error");
+        assert_eq!(&buf, b"+PONG\r\n");
+        assert!(start.elapsed() < Duration::from_millis(500));
+    }
+
+    #[tokio::test]
+    async fn blpop_woken_by_push_from_another_connection() {
+        let redis = Redis::spawn(test_config())
+            .await
+            .expect("Spawn unexpected error");
+        let mut blocked = client::RedisClient::connect(redis.addr())
+            .await
+            .expect("Connect unexpected error");
+        let mut pusher = client::RedisClient::connect(redis.addr())
+            .await
+            .expect("Connect unexpected error");
+
+        let popped = tokio::spawn(async move {
+            blocked
+                .bpop(["a", "b"], cmd::ListEnd::Left, Duration::ZERO)
+                .await
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!popped.is_finished());
+
+        pusher
+            .push("b", cmd::ListEnd::Right, ["1", "2"])
+            .await
+            .expect("Push unexpected error");
+        let popped = popped
+            .await
+            .expect("Join unexpected error")
+            .expect("Pop unexpected error");
+        assert_eq!(popped, Some(("b".into(), "1".into())));
+        let rest = pusher
+            .lrange("b", 0, -1)
+            .await
+            .expect("Range unexpected error");
+        assert_eq!(rest, vec![resp::BulkString::from("2")]);
+
+        redis.shutdown().await.expect("Shutdown unexpected error");
+    }
+
+    #[tokio::test]
+    async fn io_threads_share_the_port() {
+        let redis = Redis::init(
+            vec!["127.0.0.1:0".parse().unwrap()],
+            RedisConfig {
+                io_threads: 3,
+                ..test_config()
+            },
+        )
+        .await
+        .expect("Init redis unexpected error");
+        assert_eq!(redis.listeners.len(), 3);
+        let addrs = redis.local_addrs().expect("Local addrs unexpected error");
+        assert_eq!(addrs.len(), 1);
+        tokio::spawn(redis.start());
+
+        for _ in 0..6 {
+            let mut stream = TcpStream::connect(addrs[0])
+                .await
+                .expect("Connect unexpected error");
+            stream
+                .write_all(b"*1\r\n$4\r\nPING\r\n")
+                .await
+                .expect("Write unexpected error");
+            let mut buf = [0; 7];
+            stream
+                .read_exact(&mut buf)
+                .await
+                .expect("Read unexpected error");
+            assert_eq!(&buf, b"+PONG\r\n");
+        }
+    }
+
+    /// Makes the node of the stream the owner of the slots.
+    async fn add_slots(stream: &mut TcpStream, slots: RangeInclusive<u16>) {
+        let slots: Vec<String> = slots.map(|slot| slot.to_string()).collect();
+        let mut args = vec!["CLUSTER", "ADDSLOTS"];
+        args.extend(slots.iter().map(String::as_str));
+        assert_eq!(cluster_command(stream, &args).await, "+OK\r\n");
+    }
+
+    /// Introduces the node of `a` to that of `b` and waits for them to know each other,
+    /// returning the address of `b` as in CLUSTER NODES.
+    async fn meet(a: &mut TcpStream, b: &mut TcpStream) -> String {
+        // Both buses listen on ports picked by the OS, found in the address of myself.
+        let nodes = cluster_command(b, &["CLUSTER", "NODES"]).await;
+        let b_addr = nodes
+            .split_whitespace()
+            .nth(2)
+            .expect("Address of myself")
+            .to_string();
+        let (b_port, b_cport) = b_addr.split_once(':').unwrap().1.split_once('@').unwrap();
+        let resp = cluster_command(a, &["CLUSTER", "MEET", "127.0.0.1", b_port, b_cport]).await;
+        assert_eq!(resp, "+OK\r\n");
+
+        // Both nodes learn about each other after a few pings.
+        for stream in [a, b] {
+            wait_for_cluster_info(stream, "cluster_known_nodes:2\r\n").await;
+        }
+        b_addr
+    }
+
+    async fn wait_for_cluster_info(stream: &mut TcpStream, line: &str) {
+        for _ in 0..50 {
+            let info = cluster_command(stream, &["CLUSTER", "INFO"]).await;
+            if info.contains(line) {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        panic!("CLUSTER INFO never had {line:?}");
+    }
+
+    #[tokio::test]
+    async fn cluster_mode_serves_added_slots() {
+        let server = Redis::spawn(cluster_config("slots"))
+            .await
+            .expect("Spawn unexpected error");
+        let mut stream = TcpStream::connect(server.addr())
+            .await
+            .expect("Connect unexpected error");
+        assert_eq!(
+            cluster_command(&mut stream, &["CLUSTER", "SLOTS"]).await,
+            "*0\r\n"
+        );
+        assert_eq!(
+            cluster_command(&mut stream, &["GET", "foo"]).await,
+            "-CLUSTERDOWN The cluster is down\r\n"
+        );
+
+        add_slots(&mut stream, 0..=16383).await;
+        stream
+            .write_all(b"*2\r\n$7\r\nCLUSTER\r\n$5\r\nSLOTS\r\n")
+            .await
+            .expect("Write unexpected error");
+
+        let expected = format!(
+            "*1\r\n*3\r\n:0\r\n:16383\r\n*3\r\n$9\r\n127.0.0.1\r\n:{}\r\n$40\r\n",
+            server.addr().port()
+        );
+        let mut buf = vec![0; expected.len()];
+        stream
+            .read_exact(&mut buf)
+            .await
+            .expect("Read unexpected error");
+        assert_eq!(String::from_utf8_lossy(&buf), expected);
+    }
+
+    #[tokio::test]
+    async fn cluster_mode_refuses_keys_without_full_coverage() {
+        let server = Redis::spawn(cluster_config("coverage"))
+            .await
+            .expect("Spawn unexpected error");
+        let mut stream = TcpStream::connect(server.addr())
+            .await
+            .expect("Connect unexpected error");
+
+        // "foo" hashes to slot 12182, served here while the others aren't.
+        add_slots(&mut stream, 12000..=12999).await;
+        assert!(cluster_command(&mut stream, &["CLUSTER", "INFO"])
+            .await
+            .contains("cluster_state:fail\r\n"));
+        assert_eq!(
+            cluster_command(&mut stream, &["GET", "foo"]).await,
+            "-CLUSTERDOWN The cluster is down\r\n"
+        );
+
+        let resp = cluster_command(
+            &mut stream,
+            &["CONFIG", "SET", "cluster-require-full-coverage", "no"],
+        )
+        .await;
+        assert_eq!(resp, "+OK\r\n");
+        assert!(cluster_command(&mut stream, &["CLUSTER", "INFO"])
+            .await
+            .contains("cluster_state:ok\r\n"));
+        assert_eq!(
+            cluster_command(&mut stream, &["GET", "foo"]).await,
+            "$-1\r\n"
+        );
+        assert_eq!(
+            cluster_command(&mut stream, &["GET", "bar"]).await,
+            "-CLUSTERDOWN Hash slot not served\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn cluster_nodes_meet_over_the_bus() {
+        let a_config = cluster_config("meet-a");
+        let nodes_conf = a_config.dir.join("nodes.conf");
+        let a = Redis::spawn(a_config)
+            .await
+            .expect("Spawn unexpected error");
+        let b = Redis::spawn(cluster_config("meet-b"))
+            .await
+            .expect("Spawn unexpected error");
+        let mut a_stream = TcpStream::connect(a.addr())
+            .await
+            .expect("Connect unexpected error");
+        let mut b_stream = TcpStream::connect(b.addr())
+            .await
+            .expect("Connect unexpected error");
+
+        let b_addr = meet(&mut a_stream, &mut b_stream).await;
+        let nodes = cluster_command(&mut a_stream, &["CLUSTER", "NODES"]).await;
+        assert!(nodes.contains(b_addr.as_str()));
+
+        // The config is saved by the next cron of the bus.
+        let mut saved = false;
+        for _ in 0..50 {
+            let config = std::fs::read_to_string(&nodes_conf).unwrap_or_default();
+            if config.contains(b_addr.as_str()) {
+                saved = true;
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        assert!(saved, "Node was never saved to nodes.conf");
+    }
+
+    #[tokio::test]
+    async fn cluster_nodes_redirect_each_other() {
+        let a = Redis::spawn(cluster_config("redirect-a"))
+            .await
+            .expect("Spawn unexpected error");
+        let b = Redis::spawn(cluster_config("redirect-b"))
+            .await
+            .expect("Spawn unexpected error");
+        let mut a_stream = TcpStream::connect(a.addr())
+            .await
+            .expect("Connect unexpected error");
+        let mut b_stream = TcpStream::connect(b.addr())
+            .await
+            .expect("Connect unexpected error");
+        add_slots(&mut a_stream, 0..=8191).await;
+        add_slots(&mut b_stream, 8192..=16383).await;
+        meet(&mut a_stream, &mut b_stream).await;
+
+        // Each node learns the slots of the other from its pings.
+        for stream in [&mut a_stream, &mut b_stream] {
+            wait_for_cluster_info(stream, "cluster_state:ok\r\n").await;
+        }
+        let (foo, bar) = (key_hash_slot(b"foo"), key_hash_slot(b"bar"));
+        assert!(foo > 8191 && bar <= 8191);
+        assert_eq!(
+            cluster_command(&mut a_stream, &["GET", "foo"]).await,
+            format!("-MOVED {foo} {}\r\n", b.addr())
+        );
+        assert_eq!(
+            cluster_command(&mut b_stream, &["GET", "bar"]).await,
+            format!("-MOVED {bar} {}\r\n", a.addr())
+        );
+        assert_eq!(
+            cluster_command(&mut a_stream, &["GET", "bar"]).await,
+            "$-1\r\n"
+        );
+    }
+
+    /// Reads the next request a replica sends to its master.
+    #[cfg(feature = "replication")]
+    async fn master_receive(stream: &mut TcpStream, buf: &mut bytes::BytesMut) -> Vec<String> {
+        use bytes::Buf;
+
+        loop {
+            if let Ok((value, len)) = resp::Value::decode_with_len(buf) {
+                buf.advance(len);
+                let values = value.array().and_then(|array| array.values()).unwrap();
+                return values
+                    .iter()
+                    .map(|v| v.bulk_string().and_then(|bs| bs.as_str()).unwrap())
+                    .collect();
+            }
+            let read = stream.read_buf(buf).await.expect("Read unexpected error");
+            assert!(read > 0, "Replica closed the link");
+        }
+    }
+
+    #[cfg(feature = "replication")]
+    #[tokio::test]
+    async fn import_loads_rdb_and_applies_stream() {
+        let master = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Bind unexpected error");
+        let dir = std::env::temp_dir().join(format!("import-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("Create dir unexpected error");
+        let server = Redis::spawn(RedisConfig {
+            import_from: Some(master.local_addr().unwrap().into()),
+            dir: dir.clone(),
+            ..test_config()
+        })
+        .await
+        .expect("Spawn unexpected error");
+        let (mut link, _) = master.accept().await.expect("Accept unexpected error");
+        let mut buf = bytes::BytesMut::new();
+
+        let port = server.addr().port().to_string();
+        let handshake = [
+            (vec!["PING"], "+PONG\r\n"),
+            (vec!["REPLCONF", "listening-port", &port], "+OK\r\n"),
+            (vec!["REPLCONF", "capa", "psync2"], "+OK\r\n"),
+            (vec!["PSYNC", "?", "-1"], "+FULLRESYNC 0123456789 100\r\n"),
+        ];
+        for (expected, reply) in handshake {
+            assert_eq!(master_receive(&mut link, &mut buf).await, expected);
+            link.write_all(reply.as_bytes())
+                .await
+                .expect("Write unexpected error");
+        }
+
+        let mut rdb = b"REDIS0011\xfe\x00\x00\x08from-rdb\x01x\xff".to_vec();
+        rdb.extend_from_slice(&[0; 8]);
+        let set = b"*3\r\n$3\r\nSET\r\n$8\r\nstreamed\r\n$1\r\ny\r\n";
+        let mut stream = format!("${}\r\n", rdb.len()).into_bytes();
+        stream.extend_from_slice(&rdb);
+        stream.extend_from_slice(set);
+        stream.extend_from_slice(b"*3\r\n$8\r\nREPLCONF\r\n$6\r\nGETACK\r\n$1\r\n*\r\n");
+        link.write_all(&stream)
+            .await
+            .expect("Write unexpected error");
+
+        // Acknowledgements are also sent every second, the one asked for covers the SET.
+        let expected = (100 + set.len()).to_string();
+        loop {
+            let ack = master_receive(&mut link, &mut buf).await;
+            assert_eq!(ack[..2], ["REPLCONF", "ACK"]);
+            if ack[2] == expected {
+                break;
+            }
+        }
+
+        let mut client = client::RedisClient::connect(server.addr())
+            .await
+            .expect("Connect unexpected error");
+        for (key, value) in [("from-rdb", "x"), ("streamed", "y")] {
+            let got = client.get(key).await.expect("Get unexpected error");
+            assert_eq!(got, Some(value.into()));
+        }
+        // Without repl-diskless-load the file is loaded from disk, where it stays.
+        let saved = std::fs::read(dir.join("dump.rdb")).expect("Read unexpected error");
+        assert_eq!(saved, rdb);
+        std::fs::remove_dir_all(&dir).expect("Remove dir unexpected error");
+    }
+
+    #[cfg(feature = "replication")]
+    #[tokio::test]
+    async fn replica_applies_stream_from_master() {
+        let master = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Bind unexpected error");
+        // The handshake happens while the replica starts.
+        let replica = tokio::spawn(Redis::spawn(RedisConfig {
+            master_addr: Some(master.local_addr().unwrap().into()),
+            repl_diskless_load: ReplDisklessLoad::Swapdb,
+            ..test_config()
+        }));
+        let (mut link, _) = master.accept().await.expect("Accept unexpected error");
+        let mut buf = bytes::BytesMut::new();
+        for reply in ["+PONG\r\n", "+OK\r\n", "+OK\r\n"] {
+            master_receive(&mut link, &mut buf).await;
+            link.write_all(reply.as_bytes())
+                .await
+                .expect("Write unexpected error");
+        }
+        let replica = replica
+            .await
+            .expect("Join unexpected error")
+            .expect("Spawn unexpected error");
+
+        assert_eq!(
+            master_receive(&mut link, &mut buf).await,
+            ["PSYNC", "?", "-1"]
+        );
+        let mut rdb = b"REDIS0011\xfe\x00\x00\x08from-rdb\x01x\xff".to_vec();
+        rdb.extend_from_slice(&[0; 8]);
+        let getack = b"*3\r\n$8\r\nREPLCONF\r\n$6\r\nGETACK\r\n$1\r\n*\r\n";
+        let mut stream = format!("+FULLRESYNC 0123456789 0\r\n${}\r\n", rdb.len()).into_bytes();
+        stream.extend_from_slice(&rdb);
+        stream.extend_from_slice(getack);
+        link.write_all(&stream)
+            .await
+            .expect("Write unexpected error");
+
+        // The first GETACK is answered before it is counted.
+        let ack = master_receive(&mut link, &mut buf).await;
+        assert_eq!(ack, ["REPLCONF", "ACK", "0"]);
+
+        let set = b"*3\r\n$3\r\nSET\r\n$8\r\nstreamed\r\n$1\r\ny\r\n";
+        link.write_all(&[set.as_slice(), getack].concat())
+            .await
+            .expect("Write unexpected error");
+
+        // The next one covers the first GETACK and the SET, but not itself.
+        let expected = (getack.len() + set.len()).to_string();
+        loop {
+            let ack = master_receive(&mut link, &mut buf).await;
+            assert_eq!(ack[..2], ["REPLCONF", "ACK"]);
+            if ack[2] == expected {
+                break;
+            }
+        }
+
+        let mut client = client::RedisClient::connect(replica.addr())
+            .await
+            .expect("Connect unexpected error");
+        for (key, value) in [("from-rdb", "x"), ("streamed", "y")] {
+            let got = client.get(key).await.expect("Get unexpected error");
+            assert_eq!(got, Some(value.into()));
+        }
+        replica.shutdown().await.expect("Shutdown unexpected error");
+    }
+
+    #[cfg(feature = "replication")]
+    #[tokio::test]
+    async fn psync_sends_dataset_to_replica() {
+        let master = Redis::spawn(test_config())
+            .await
+            .expect("Spawn master unexpected error");
+        let mut client = client::RedisClient::connect(master.addr())
+            .await
+            .expect("Connect unexpected error");
+        client
+            .set("key", "value")
+            .await
+            .expect("Set unexpected error");
+
+        // Importing syncs like a replica would, loading the RDB file from the link.
+        let importer = Redis::spawn(RedisConfig {
+            import_from: Some(master.addr().into()),
+            repl_diskless_load: ReplDisklessLoad::Swapdb,
+            ..test_config()
+        })
+        .await
+        .expect("Spawn importer unexpected error");
+        let mut client = client::RedisClient::connect(importer.addr())
+            .await
+            .expect("Connect unexpected error");
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while client.get("key").await.expect("Get unexpected error") != Some("value".into()) {
+            assert!(Instant::now() < deadline, "Key never synced");
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        importer
+            .shutdown()
+            .await
+            .expect("Shutdown unexpected error");
+        master.shutdown().await.expect("Shutdown unexpected error");
+    }
+
+    #[tokio::test]
+    async fn wait_for_replica_to_acknowledge_writes() {
+        let master = Redis::spawn(test_config())
+            .await
+            .expect("Spawn master unexpected error");
+        let mut client = client::RedisClient::connect(master.addr())
+            .await
+            .expect("Connect unexpected error");
+        let wait =
+            |numreplicas: &'static str, timeout: &'static str| ["wait", numreplicas, timeout];
+        let result = client.command(wait("1", "10")).await;
+        assert_eq!(
+            result.expect("Wait unexpected error"),
+            resp::Value::Integer(0.into())
+        );
+
+        let replica = Redis::spawn(RedisConfig {
+            import_from: Some(master.addr().into()),
+            repl_diskless_load: ReplDisklessLoad::Swapdb,
+            ..test_config()
+        })
+        .await
+        .expect("Spawn replica unexpected error");
+        client
+            .set("key", "value")
+            .await
+            .expect("Set unexpected error");
+        let result = client.command(wait("1", "0")).await;
+        assert_eq!(
+            result.expect("Wait unexpected error"),
+            resp::Value::Integer(1.into())
+        );
+        // Only one replica can acknowledge.
+        let result = client.command(wait("2", "10")).await;
+        assert_eq!(
+            result.expect("Wait unexpected error"),
+            resp::Value::Integer(1.into())
+        );
+
+        // The replica applied the write it acknowledged.
+        let mut replica_client = client::RedisClient::connect(replica.addr())
+            .await
+            .expect("Connect unexpected error");
+        let value = replica_client
+            .get("key")
+            .await
+            .expect("Get unexpected error");
+        assert_eq!(value, Some("value".into()));
+
+        replica.shutdown().await.expect("Shutdown unexpected error");
+        master.shutdown().await.expect("Shutdown unexpected error");
+    }
+
+    #[cfg(feature = "replication")]
+    #[tokio::test]
+    async fn failover_swaps_roles_with_replica() {
+        let master = Redis::spawn(test_config())
+            .await
+            .expect("Spawn master unexpected error");
+        let replica = Redis::spawn(RedisConfig {
+            master_addr: Some(master.addr().into()),
+            repl_diskless_load: ReplDisklessLoad::Swapdb,
+            ..test_config()
+        })
+        .await
+        .expect("Spawn replica unexpected error");
+        let connect = |server: &ServerHandle| client::RedisClient::connect(server.addr());
+        let mut master_client = connect(&master).await.expect("Connect unexpected error");
+        let mut replica_client = connect(&replica).await.expect("Connect unexpected error");
+        master_client
+            .set("key", "value")
+            .await
+            .expect("Set unexpected error");
+        let result = master_client.command(["wait", "1", "0"]).await;
+        assert_eq!(
+            result.expect("Wait unexpected error"),
+            resp::Value::Integer(1.into())
+        );
+
+        let port = replica.addr().port().to_string();
+        let failover = ["failover", "to", "127.0.0.1", &port, "timeout", "5000"];
+        let result = master_client.command(failover).await;
+        assert_eq!(
+            result.expect("Failover unexpected error"),
+            resp::Value::SimpleString("OK".into())
+        );
+
+        // The replica is promoted, then the master follows it.
+        async fn role(client: &mut client::RedisClient) -> String {
+            let info = client
+                .command(["info", "replication"])
+                .await
+                .expect("Info unexpected error");
+            let info = info
+                .bulk_string()
+                .and_then(resp::BulkString::as_str)
+                .unwrap();
+            info.lines()
+                .find_map(|line| line.strip_prefix("role:"))
+                .unwrap()
+                .to_string()
+        }
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while role(&mut replica_client).await != "master"
+            || role(&mut master_client).await != "slave"
+        {
+            assert!(Instant::now() < deadline, "Roles never swapped");
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        // Writes now go to the new master, which replicates them to the old one.
+        let result = master_client.set("other", "x").await;
+        assert!(result.is_err(), "Old master accepted a write");
+        replica_client
+            .set("other", "x")
+            .await
+            .expect("Set unexpected error");
+        assert_eq!(
+            replica_client
+                .get("key")
+                .await
+                .expect("Get unexpected error"),
+            Some("value".into())
+        );
+        while master_client
+            .get("other")
+            .await
+            .expect("Get unexpected error")
+            != Some("x".into())
+        {
+            assert!(Instant::now() < deadline, "Write never replicated");
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        master.shutdown().await.expect("Shutdown unexpected error");
+        replica.shutdown().await.expect("Shutdown unexpected error");
+    }
+
+    #[cfg(feature = "replication")]
+    #[tokio::test]
+    async fn report_master_link_down_on_health_probes() {
+        let master = Redis::spawn(test_config())
+            .await
+            .expect("Spawn master unexpected error");
+        let health_port = std::net::TcpListener::bind("127.0.0.1:0")
+            .and_then(|listener| listener.local_addr())
+            .expect("Bind unexpected error")
+            .port();
+        let replica = Redis::spawn(RedisConfig {
+            master_addr: Some(master.addr().into()),
+            repl_diskless_load: ReplDisklessLoad::Swapdb,
+            health_port,
+            ..test_config()
+        })
+        .await
+        .expect("Spawn replica unexpected error");
+
+        async fn health(port: u16) -> String {
+            let mut stream = tokio::net::TcpStream::connect(("127.0.0.1", port))
+                .await
+                .expect("Connect unexpected error");
+            stream
+                .write_all(b"HEALTH\r\n")
+                .await
+                .expect("Write unexpected error");
+            stream.shutdown().await.expect("Shutdown unexpected error");
+            let mut reply = String::new();
+            stream
+                .read_to_string(&mut reply)
+                .await
+                .expect("Read unexpected error");
+            reply
+        }
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !health(health_port).await.contains("master_link_status:up") {
+            assert!(Instant::now() < deadline, "Link never came up");
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        // Once the master is gone, the replica is no longer ready.
+        master.shutdown().await.expect("Shutdown unexpected error");
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            let reply = health(health_port).await;
+            if reply.starts_with("-MASTERDOWN ") {
+                assert!(reply.contains("master_link_status:down"), "{reply}");
+                break;
+            }
+            assert!(Instant::now() < deadline, "Link never reported down");
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        replica.shutdown().await.expect("Shutdown unexpected error");
+    }
+
+    #[tokio::test]
+    async fn encode_replies_by_protocol() {
+        let server = Redis::spawn(test_config())
+            .await
+            .expect("Spawn unexpected error");
+        let mut client = client::RedisClient::connect(server.addr())
+            .await
+            .expect("Connect unexpected error");
+        client
+            .sadd("set", ["a"])
+            .await
+            .expect("Sadd unexpected error");
+        let mut command = async |args: &[&str]| {
+            client
+                .command(args.iter().copied())
+                .await
+                .expect("Command unexpected error")
+        };
+
+        assert_eq!(
+            command(&["get", "missing"]).await,
+            resp::Value::BulkString(resp::BulkString::null())
+        );
+        assert!(command(&["smembers", "set"]).await.array().is_some());
+
+        assert!(command(&["hello", "3"]).await.map().is_some());
+        assert_eq!(
+            command(&["get", "missing"]).await,
+            resp::Value::Null(resp::Null)
+        );
+        assert_eq!(
+            command(&["smembers", "set"]).await,
+            resp::Value::Set(resp::Set::new(vec![resp::Value::BulkString("a".into())]))
+        );
+        assert_eq!(
+            command(&["hgetall", "missing"]).await,
+            resp::Value::Map(resp::Map::new(vec![]))
+        );
+
+        server.shutdown().await.expect("Shutdown unexpected error");
+    }
+
+    #[tokio::test]
+    async fn load_rdb_on_startup() {
+        use self::handler::StoredData;
+        use self::key::Key;
+
+        let dir = std::env::temp_dir().join(format!("rdb-load-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("Create dir unexpected error");
+        let now = std::time::SystemTime::now();
+        let expires = now + Duration::from_secs(60);
+        let entries = [
+            (Key::from("key"), StoredData::new("value".into(), None)),
+            (
+                Key::from("temp"),
+                StoredData::new("soon".into(), Some(expires)),
+            ),
+        ];
+        std::fs::write(dir.join("dump.rdb"), rdb::encode(entries, None, now))
+            .expect("Write unexpected error");
+
+        let server = Redis::spawn(RedisConfig {
+            dir: dir.clone(),
+            ..test_config()
+        })
+        .await
+        .expect("Spawn unexpected error");
+        let mut client = client::RedisClient::connect(server.addr())
+            .await
+            .expect("Connect unexpected error");
+        let value = client.get("temp").await.expect("Get unexpected error");
+        assert_eq!(value, Some("soon".into()));
+        let keys = client
+            .command(["keys", "*"])
+            .await
+            .expect("Keys unexpected error");
+        let resp::Value::Array(keys) = keys else {
+            panic!("Keys returned no array");
+        };
+        assert_eq!(keys.values().map(|keys| keys.len()), Some(2));
+        server.shutdown().await.expect("Shutdown unexpected error");
+
+        // A corrupt file fails the start.
+        std::fs::write(dir.join("dump.rdb"), b"REDIS0011\xfe").expect("Write unexpected error");
+        let result = Redis::spawn(RedisConfig {
+            dir: dir.clone(),
+            ..test_config()
+        })
+        .await;
+        assert!(matches!(
+            result,
+            Err(RedisError::Rdb(RdbError::UnexpectedEof))
+        ));
+        std::fs::remove_dir_all(&dir).expect("Remove dir unexpected error");
+    }
+
+    #[tokio::test]
+    async fn load_redis_7_dump_on_startup() {
+        let dir = std::env::temp_dir().join(format!("rdb-redis7-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("Create dir unexpected error");
+        // Laid out like Redis 7.2 saves `HSET myhash a 1 b hello` and `RPUSH mylist x 2 yz`,
+        // with both packed into listpacks and the checksum filled in.
+        let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/redis-7.2.rdb");
+        std::fs::copy(fixture, dir.join("dump.rdb")).expect("Copy unexpected error");
+
+        let server = Redis::spawn(RedisConfig {
+            dir: dir.clone(),
+            ..test_config()
+        })
+        .await
+        .expect("Spawn unexpected error");
+        let mut client = client::RedisClient::connect(server.addr())
+            .await
+            .expect("Connect unexpected error");
+        let mut hash = client
+            .hgetall("myhash")
+            .await
+            .expect("Hgetall unexpected error");
+        hash.sort();
+        assert_eq!(
+            hash,
+            [("a".into(), "1".into()), ("b".into(), "hello".into())]
+        );
+        let list = client
+            .lrange("mylist", 0, -1)
+            .await
+            .expect("Lrange unexpected error");
+        assert_eq!(list, ["x".into(), "2".into(), "yz".into()]);
+
+        server.shutdown().await.expect("Shutdown unexpected error");
+        std::fs::remove_dir_all(&dir).expect("Remove dir unexpected error");
+    }
+
+    #[cfg(feature = "persistence")]
+    #[tokio::test]
+    async fn save_on_shutdown_and_reload() {
+        let dir = std::env::temp_dir().join(format!("rdb-shutdown-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("Create dir unexpected error");
+        let config = || RedisConfig {
+            dir: dir.clone(),
+            save_on_shutdown: true,
+            ..test_config()
+        };
+
+        let server = Redis::spawn(config())
+            .await
+            .expect("Spawn unexpected error");
+        let mut client = client::RedisClient::connect(server.addr())
+            .await
+            .expect("Connect unexpected error");
+        client
+            .set("key", "value")
+            .await
+            .expect("Set unexpected error");
+        server.shutdown().await.expect("Shutdown unexpected error");
+        assert!(dir.join("dump.rdb").exists());
+
+        // The restarted server loads the keys saved on shutdown.
+        let server = Redis::spawn(config())
+            .await
+            .expect("Spawn unexpected error");
+        let mut client = client::RedisClient::connect(server.addr())
+            .await
+            .expect("Connect unexpected error");
+        let value = client.get("key").await.expect("Get unexpected error");
+        assert_eq!(value, Some("value".into()));
+        server.shutdown().await.expect("Shutdown unexpected error");
+        std::fs::remove_dir_all(&dir).expect("Remove dir unexpected error");
+    }
+
+    #[cfg(feature = "persistence")]
+    #[tokio::test]
+    async fn keep_rdb_file_with_other_databases() {
+        let dir = std::env::temp_dir().join(format!("rdb-partial-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("Create dir unexpected error");
+        // A key of database 1, which isn't loaded, and one of database 0.
+        let rdb =
+            b"REDIS0011\xfe\x01\x00\x03old\x01x\xfe\x00\x00\x03key\x05value\xff\0\0\0\0\0\0\0\0";
+        let path = dir.join("dump.rdb");
+        std::fs::write(&path, rdb).expect("Write unexpected error");
+
+        let server = Redis::spawn(RedisConfig {
+            dir: dir.clone(),
+            save_on_shutdown: true,
+            ..test_config()
+        })
+        .await
+        .expect("Spawn unexpected error");
+        let mut client = client::RedisClient::connect(server.addr())
+            .await
+            .expect("Connect unexpected error");
+        client
+            .set("new", "value")
+            .await
+            .expect("Set unexpected error");
+        let result = client.command(["save"]).await;
+        assert!(result.is_err(), "Saved over a partially loaded file");
+        server.shutdown().await.expect("Shutdown unexpected error");
+
+        // Neither SAVE nor the shutdown wrote over the key of database 1.
+        let saved = std::fs::read(&path).expect("Read unexpected error");
+        assert_eq!(saved, rdb);
+        std::fs::remove_dir_all(&dir).expect("Remove dir unexpected error");
+    }
+
+    #[cfg(all(feature = "replication", feature = "persistence"))]
+    #[tokio::test]
+    async fn replica_continues_after_restart() {
+        use self::handler::StoredData;
+        use self::key::Key;
+
+        let dir = std::env::temp_dir().join(format!("replica-restart-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("Create dir unexpected error");
+        let master = Redis::spawn(test_config())
+            .await
+            .expect("Spawn master unexpected error");
+        let config = || RedisConfig {
+            master_addr: Some(master.addr().into()),
+            repl_diskless_load: ReplDisklessLoad::Swapdb,
+            dir: dir.clone(),
+            save_on_shutdown: true,
+            ..test_config()
+        };
+        let mut client = client::RedisClient::connect(master.addr())
+            .await
+            .expect("Connect unexpected error");
+        let synced = |replica: &ServerHandle, key: &'static str| {
+            let addr = replica.addr();
+            async move {
+                let mut client = client::RedisClient::connect(addr)
+                    .await
+                    .expect("Connect unexpected error");
+                let deadline = Instant::now() + Duration::from_secs(5);
+                while client
+                    .get(key)
+                    .await
+                    .expect("Get unexpected error")
+                    .is_none()
+                {
+                    assert!(Instant::now() < deadline, "Key never synced");
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+            }
+        };
+
+        let replica = Redis::spawn(config())
+            .await
+            .expect("Spawn replica unexpected error");
+        client.set("a", "1").await.expect("Set unexpected error");
+        synced(&replica, "a").await;
+        replica.shutdown().await.expect("Shutdown unexpected error");
+
+        // The saved file records where the replica is in the master's stream.
+        let path = dir.join("dump.rdb");
+        let now = std::time::SystemTime::now();
+        let rdb = std::fs::read(&path).expect("Read unexpected error");
+        let mut dataset = rdb::parse(&rdb, now).expect("Parse unexpected error");
+        let repl_id = dataset.repl_id.expect("Saved no replication ID");
+        let offset = dataset.repl_offset.expect("Saved no replication offset");
+        // A key the master doesn't have survives a partial resync, unlike a full one.
+        dataset
+            .entries
+            .push((Key::from("local"), StoredData::new("x".into(), None)));
+        let rdb = rdb::encode(dataset.entries, Some((&repl_id, offset)), now);
+        std::fs::write(&path, rdb).expect("Write unexpected error");
+
+        client.set("b", "2").await.expect("Set unexpected error");
+        let replica = Redis::spawn(config())
+            .await
+            .expect("Spawn replica unexpected error");
+        synced(&replica, "b").await;
+        let mut replica_client = client::RedisClient::connect(replica.addr())
+            .await
+            .expect("Connect unexpected error");
+        for (key, value) in [("a", "1"), ("local", "x")] {
+            let got = replica_client.get(key).await.expect("Get unexpected error");
+            assert_eq!(got, Some(value.into()));
+        }
+
+        replica.shutdown().await.expect("Shutdown unexpected error");
+        master.shutdown().await.expect("Shutdown unexpected error");
+        std::fs::remove_dir_all(&dir).expect("Remove dir unexpected error");
+    }
+
+    #[cfg(feature = "chaos")]
+    #[tokio::test]
+    async fn chaos_delays_and_fails_commands() {
+        let server = Redis::spawn(RedisConfig {
+            chaos_latency_ms: 50,
+            chaos_failure_rate: 1.0,
+            ..test_config()
+        })
+        .await
+        .expect("Spawn unexpected error");
+        let mut client = client::RedisClient::connect(server.addr())
+            .await
+            .expect("Connect unexpected error");
+
+        let started = Instant::now();
+        let err = client.command(["ping"]).await.expect_err("Ping no error");
+        assert!(matches!(err, client::ClientError::Server(e) if e == "ERR injected fault"));
+        assert!(started.elapsed() >= Duration::from_millis(50));
+    }
 }
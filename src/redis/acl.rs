@@ -0,0 +1,548 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+use super::clients::ClientState;
+use super::cmd::table::{self, COMMAND_TABLE};
+use super::util;
+
+/// Name of the user every connection starts as.
+pub const DEFAULT_USER: &str = "default";
+
+/// Every ACL category known to the server.
+pub const CATEGORIES: &[&str] = &[
+    "keyspace",
+    "read",
+    "write",
+    "string",
+    "list",
+    "hash",
+    "set",
+    "sortedset",
+    "stream",
+    "admin",
+    "fast",
+    "slow",
+    "dangerous",
+    "connection",
+    "blocking",
+    "scripting",
+];
+
+/// Returns the ACL categories of the command.
+pub fn command_categories(command: &str) -> &'static [&'static str] {
+    table::lookup(command)
+        .map(|spec| spec.categories)
+        .unwrap_or(&[])
+}
+
+/// Returns the names of every command in the ACL category.
+pub fn category_commands(category: &str) -> Vec<&'static str> {
+    COMMAND_TABLE
+        .iter()
+        .filter(|spec| spec.categories.contains(&category))
+        .map(|spec| spec.name)
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum AclError {
+    #[error("Authentication required.")]
+    NoAuth,
+
+    #[error("invalid username-password pair or user is disabled.")]
+    WrongPass,
+
+    #[error("Too many failed authentication attempts, retry in {0} seconds")]
+    AuthThrottled(u64),
+
+    #[error("User {user} has no permissions to run the '{command}' command")]
+    NoPermCommand { user: String, command: String },
+
+    #[error("No permissions to access a key")]
+    NoPermKey,
+
+    #[error("Error in ACL SETUSER modifier '{0}': Syntax error")]
+    InvalidRule(String),
+
+    #[error("Unknown category '{0}'")]
+    UnknownCategory(String),
+
+    #[error("The 'default' user cannot be removed")]
+    DeleteDefaultUser,
+
+    #[error("This Redis instance is not configured to use an ACL file")]
+    NoAclFile,
+
+    #[error("ACL file line {line}: {reason}")]
+    InvalidFileLine { line: usize, reason: String },
+
+    #[error("Error accessing ACL file: {0}")]
+    File(String),
+}
+
+impl AclError {
+    /// Returns the error code prefixed to the RESP error reply.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::NoAuth => "NOAUTH",
+            Self::WrongPass => "WRONGPASS",
+            Self::NoPermCommand { .. } | Self::NoPermKey => "NOPERM",
+            _ => "ERR",
+        }
+    }
+}
+
+/// A single `+`/`-` command rule, applied in order to decide if a command is allowed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum CommandRule {
+    Command { allow: bool, name: String },
+    Category { allow: bool, name: String },
+}
+
+impl CommandRule {
+    /// Returns `Some(allow)` if the rule applies to the command in the categories.
+    fn applies(&self, command: &str, categories: &[&str]) -> Option<bool> {
+        match self {
+            Self::Command { allow, name } => (name == command).then_some(*allow),
+            Self::Category { allow, name } => {
+                (name == "all" || categories.contains(&name.as_str())).then_some(*allow)
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for CommandRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let sign = |allow: &bool| if *allow { '+' } else { '-' };
+        match self {
+            Self::Command { allow, name } => write!(f, "{}{name}", sign(allow)),
+            Self::Category { allow, name } => write!(f, "{}@{name}", sign(allow)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct User {
+    pub name: String,
+    pub enabled: bool,
+    pub nopass: bool,
+
+    /// SHA256 hex digests of the passwords.
+    pub passwords: Vec<String>,
+
+    pub key_patterns: Vec<String>,
+    pub channel_patterns: Vec<String>,
+    command_rules: Vec<CommandRule>,
+}
+
+impl User {
+    /// Returns a new user that is disabled, has no passwords and may not run anything.
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            enabled: false,
+            nopass: false,
+            passwords: vec![],
+            key_patterns: vec![],
+            channel_patterns: vec![],
+            command_rules: vec![],
+        }
+    }
+
+    /// Returns the default user, which can run everything without a password.
+    pub fn default_user() -> Self {
+        let mut user = Self::new(DEFAULT_USER);
+        for rule in ["on", "nopass", "allkeys", "allchannels", "allcommands"] {
+            user.apply_rule(rule).expect("Invalid default rule");
+        }
+        user
+    }
+
+    /// Applies a single ACL SETUSER rule such as `on`, `>password`, `~key*` or `+@read`.
+    pub fn apply_rule(&mut self, rule: &str) -> Result<(), AclError> {
+        let invalid = || AclError::InvalidRule(rule.to_string());
+
+        match rule.to_lowercase().as_str() {
+            "on" => self.enabled = true,
+            "off" => self.enabled = false,
+            "nopass" => {
+                self.nopass = true;
+                self.passwords.clear();
+            }
+            "resetpass" => {
+                self.nopass = false;
+                self.passwords.clear();
+            }
+            "allkeys" => self.key_patterns = vec!["*".to_string()],
+            "resetkeys" => self.key_patterns.clear(),
+            "allchannels" => self.channel_patterns = vec!["*".to_string()],
+            "resetchannels" => self.channel_patterns.clear(),
+            "allcommands" => self.command_rules = vec![Self::category_rule(true, "all")],
+            "nocommands" => self.command_rules.clear(),
+            "reset" => *self = Self::new(&self.name),
+            _ => {
+                let mut chars = rule.chars();
+                let prefix = chars.next().ok_or_else(invalid)?;
+                let rest = chars.as_str();
+                match prefix {
+                    '>' => {
+                        self.nopass = false;
+                        let hash = hash_password(rest);
+                        if !self.passwords.contains(&hash) {
+                            self.passwords.push(hash);
+                        }
+                    }
+                    '<' => {
+                        let hash = hash_password(rest);
+                        self.passwords.retain(|p| *p != hash);
+                    }
+                    '#' => {
+                        if rest.len() != 64 || !rest.bytes().all(|b| b.is_ascii_hexdigit()) {
+                            return Err(invalid());
+                        }
+                        self.nopass = false;
+                        self.passwords.push(rest.to_lowercase());
+                    }
+                    '!' => self.passwords.retain(|p| !p.eq_ignore_ascii_case(rest)),
+                    '~' => self.key_patterns.push(rest.to_string()),
+                    '&' => self.channel_patterns.push(rest.to_string()),
+                    '+' | '-' => {
+                        let allow = prefix == '+';
+                        let rule = match rest.strip_prefix('@') {
+                            Some(category) => {
+                                let category = category.to_lowercase();
+                                if category != "all" && !CATEGORIES.contains(&category.as_str()) {
+                                    return Err(invalid());
+                                }
+                                Self::category_rule(allow, &category)
+                            }
+                            None if !rest.is_empty() => CommandRule::Command {
+                                allow,
+                                name: rest.to_lowercase(),
+                            },
+                            None => return Err(invalid()),
+                        };
+                        self.command_rules.push(rule);
+                    }
+                    _ => return Err(invalid()),
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn category_rule(allow: bool, name: &str) -> CommandRule {
+        CommandRule::Category {
+            allow,
+            name: name.to_string(),
+        }
+    }
+
+    /// Returns true if the password matches one of the user's passwords, or the user has nopass.
+    pub fn check_password(&self, password: &str) -> bool {
+        self.nopass || self.passwords.contains(&hash_password(password))
+    }
+
+    /// Returns true if the command rules allow the command in the categories.
+    pub fn can_run(&self, command: &str, categories: &[&str]) -> bool {
+        self.command_rules.iter().fold(false, |allowed, rule| {
+            rule.applies(command, categories).unwrap_or(allowed)
+        })
+    }
+
+    /// Returns true if the key matches any of the key patterns.
+    pub fn can_access_key(&self, key: &[u8]) -> bool {
+        self.key_patterns
+            .iter()
+            .any(|pattern| util::glob_match(pattern.as_bytes(), key))
+    }
+
+    /// Returns the command rules as a single space separated string, e.g. `+@all -set`.
+    pub fn commands_description(&self) -> String {
+        if self.command_rules.is_empty() {
+            return "-@all".to_string();
+        }
+        self.command_rules
+            .iter()
+            .map(|rule| rule.to_string())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Returns the flags of the user, e.g. `on` and `nopass`.
+    pub fn flags(&self) -> Vec<&'static str> {
+        let mut flags = vec![if self.enabled { "on" } else { "off" }];
+        if self.nopass {
+            flags.push("nopass");
+        }
+        flags
+    }
+
+    /// Returns the user in the form of ACL LIST, which is also valid ACL SETUSER rules.
+    pub fn describe(&self) -> String {
+        let mut parts = vec![format!("user {}", self.name)];
+        parts.extend(self.flags().iter().map(|f| f.to_string()));
+        parts.extend(self.passwords.iter().map(|p| format!("#{p}")));
+        parts.extend(self.key_patterns.iter().map(|p| format!("~{p}")));
+        parts.extend(self.channel_patterns.iter().map(|p| format!("&{p}")));
+        parts.push(self.commands_description());
+        parts.join(" ")
+    }
+}
+
+fn hash_password(password: &str) -> String {
+    Sha256::digest(password.as_bytes())
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// Registry of all users.
+#[derive(Debug, Clone)]
+pub struct AccessControl {
+    users: BTreeMap<String, User>,
+}
+
+impl Default for AccessControl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AccessControl {
+    /// Returns a registry with only the default user.
+    pub fn new() -> Self {
+        let mut users = BTreeMap::new();
+        users.insert(DEFAULT_USER.to_string(), User::default_user());
+        Self { users }
+    }
+
+    pub fn user(&self, name: &str) -> Option<&User> {
+        self.users.get(name)
+    }
+
+    pub fn users(&self) -> impl Iterator<Item = &User> {
+        self.users.values()
+    }
+
+    /// Applies the rules to the user, creating it if it doesn't exist.
+    /// If any rule is invalid the user is left untouched.
+    pub fn set_user(&mut self, name: &str, rules: &[String]) -> Result<(), AclError> {
+        let mut user = self
+            .users
+            .get(name)
+            .cloned()
+            .unwrap_or_else(|| User::new(name));
+        for rule in rules {
+            user.apply_rule(rule)?;
+        }
+
+        self.users.insert(name.to_string(), user);
+        Ok(())
+    }
+
+    /// Deletes the users, returning how many existed.
+    pub fn del_users(&mut self, names: &[String]) -> Result<usize, AclError> {
+        if names.iter().any(|name| name == DEFAULT_USER) {
+            return Err(AclError::DeleteDefaultUser);
+        }
+        Ok(names
+            .iter()
+            .filter(|name| self.users.remove(*name).is_some())
+            .count())
+    }
+
+    /// Returns the user a new connection is implicitly authenticated as, which is the default
+    /// user if it is enabled and doesn't require a password.
+    pub fn implicit_user(&self) -> Option<String> {
+        self.user(DEFAULT_USER)
+            .filter(|user| user.enabled && user.nopass)
+            .map(|user| user.name.clone())
+    }
+
+    /// Parses ACL file contents, one `user <name> [rule ...]` per line as written by ACL SAVE.
+    /// Empty lines are skipped and the default user is added if the file doesn't define it.
+    pub fn parse(contents: &str) -> Result<Self, AclError> {
+        let mut users = BTreeMap::new();
+
+        for (idx, line) in contents.lines().enumerate() {
+            let invalid = |reason: String| AclError::InvalidFileLine {
+                line: idx + 1,
+                reason,
+            };
+            let mut words = line.split_whitespace();
+            match words.next() {
+                None => continue,
+                Some("user") => (),
+                Some(_) => return Err(invalid("line should start with user keyword".into())),
+            }
+
+            let name = words
+                .next()
+                .ok_or_else(|| invalid("missing user name".into()))?;
+            if users.contains_key(name) {
+                return Err(invalid(format!("duplicate user '{name}'")));
+            }
+            let mut user = User::new(name);
+            for rule in words {
+                user.apply_rule(rule).map_err(|e| invalid(e.to_string()))?;
+            }
+            users.insert(name.to_string(), user);
+        }
+
+        users
+            .entry(DEFAULT_USER.to_string())
+            .or_insert_with(User::default_user);
+        Ok(Self { users })
+    }
+
+    /// Returns every user in the format read by `parse`.
+    pub fn contents(&self) -> String {
+        self.users()
+            .map(|user| format!("{}\n", user.describe()))
+            .collect()
+    }
+
+    /// Reads the users from the ACL file.
+    pub fn load(path: &Path) -> Result<Self, AclError> {
+        let contents = fs::read_to_string(path).map_err(|e| AclError::File(e.to_string()))?;
+        Self::parse(&contents)
+    }
+
+    /// Writes every user to the ACL file, replacing it only once the write has succeeded.
+    pub fn save(&self, path: &Path) -> Result<(), AclError> {
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, self.contents())
+            .and_then(|_| fs::rename(&tmp_path, path))
+            .map_err(|e| AclError::File(e.to_string()))
+    }
+
+    /// Checks the credentials, returning the authenticated user name.
+    pub fn authenticate(&self, name: &str, password: &str) -> Result<String, AclError> {
+        match self.user(name) {
+            Some(user) if user.enabled && user.check_password(password) => Ok(user.name.clone()),
+            _ => Err(AclError::WrongPass),
+        }
+    }
+
+    /// Checks that the authenticated user may run the command of the ACL categories on the
+    /// keys.
+    pub fn check(
+        &self,
+        client: &ClientState,
+        command: &str,
+        categories: &[&str],
+        keys: &[&[u8]],
+    ) -> Result<(), AclError> {
+        let name = client.user().ok_or(AclError::NoAuth)?;
+        let user = self
+            .user(name)
+            .filter(|user| user.enabled)
+            .ok_or(AclError::NoAuth)?;
+
+        if !user.can_run(command, categories) {
+            return Err(AclError::NoPermCommand {
+                user: user.name.clone(),
+                command: command.to_string(),
+            });
+        }
+        if !keys.iter().all(|key| user.can_access_key(key)) {
+            return Err(AclError::NoPermKey);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn rules(rules: &[&str]) -> Vec<String> {
+        rules.iter().map(|r| r.to_string()).collect()
+    }
+
+    #[test]
+    fn command_rules_apply_in_order() {
+        let mut acl = AccessControl::new();
+        acl.set_user(
+            "alice",
+            &rules(&["on", "nopass", "+@all", "-set", "~cache:*"]),
+        )
+        .expect("Set user unexpected error");
+        let client = ClientState::new(1, Some("alice".into()));
+
+        assert_eq!(
+            acl.check(&client, "get", command_categories("get"), &[b"cache:1"]),
+            Ok(())
+        );
+        assert!(matches!(
+            acl.check(&client, "set", command_categories("set"), &[b"cache:1"]),
+            Err(AclError::NoPermCommand { .. })
+        ));
+        assert_eq!(
+            acl.check(&client, "get", command_categories("get"), &[b"other"]),
+            Err(AclError::NoPermKey)
+        );
+    }
+
+    #[test]
+    fn authenticate_with_password() {
+        let mut acl = AccessControl::new();
+        acl.set_user("bob", &rules(&["on", ">secret", "+@read"]))
+            .expect("Set user unexpected error");
+
+        assert_eq!(acl.authenticate("bob", "secret"), Ok("bob".to_string()));
+        assert_eq!(acl.authenticate("bob", "wrong"), Err(AclError::WrongPass));
+        assert_eq!(
+            acl.user("bob").unwrap().commands_description(),
+            "+@read".to_string()
+        );
+    }
+
+    #[test]
+    fn invalid_rule_leaves_user_untouched() {
+        let mut acl = AccessControl::new();
+        let err = acl
+            .set_user("carol", &rules(&["on", "+@nonsense"]))
+            .expect_err("Set user no error");
+
+        assert_eq!(err, AclError::InvalidRule("+@nonsense".into()));
+        assert!(acl.user("carol").is_none());
+    }
+
+    #[test]
+    fn file_contents_round_trip() {
+        let mut acl = AccessControl::new();
+        acl.set_user(
+            "alice",
+            &rules(&["on", ">secret", "~cache:*", "+@read", "-get"]),
+        )
+        .expect("Set user unexpected error");
+
+        let parsed = AccessControl::parse(&acl.contents()).expect("Parse unexpected error");
+        assert_eq!(parsed.users, acl.users);
+    }
+
+    #[test]
+    fn parse_adds_default_user() {
+        let acl =
+            AccessControl::parse("\nuser alice on nopass +@all\n").expect("Parse unexpected error");
+
+        assert_eq!(acl.user(DEFAULT_USER), Some(&User::default_user()));
+        assert!(acl.user("alice").is_some());
+    }
+
+    #[test]
+    fn parse_invalid_line() {
+        let err = AccessControl::parse("user alice on\nuser bob +@nonsense\n")
+            .expect_err("Parse no error");
+
+        assert!(matches!(err, AclError::InvalidFileLine { line: 2, .. }));
+    }
+}
@@ -0,0 +1,93 @@
+/// Index of the pseudo arena standing for all arenas in jemalloc controls.
+#[cfg(feature = "jemalloc")]
+const MALLCTL_ARENAS_ALL: u32 = 4096;
+
+/// Returns the allocator lines of INFO memory. With jemalloc these include its stats and
+/// `mem_fragmentation_ratio`, the memory resident for the process over `used`, the memory
+/// used by the dataset.
+#[cfg(feature = "jemalloc")]
+pub fn info(used: u64) -> Vec<String> {
+    use tikv_jemalloc_ctl::{epoch, stats, version};
+
+    // e.g. 5.3.0-0-g54eaed1d8b56b1aa528be3bdd1877e59c56fa90c
+    let full_version = version::read().unwrap_or_default();
+    let short_version = full_version.split('-').next().unwrap_or_default();
+    let mut info = vec![format!("mem_allocator:jemalloc-{short_version}")];
+    // The stats are only refreshed when the epoch advances.
+    if epoch::advance().is_err() {
+        return info;
+    }
+    let read = |stat: tikv_jemalloc_ctl::Result<usize>| stat.unwrap_or_default() as u64;
+    let allocated = read(stats::allocated::read());
+    let active = read(stats::active::read());
+    let resident = read(stats::resident::read());
+    info.extend([
+        format!("allocator_allocated:{allocated}"),
+        format!("allocator_active:{active}"),
+        format!("allocator_resident:{resident}"),
+        format!("allocator_frag_ratio:{:.2}", ratio(active, allocated)),
+        format!("mem_fragmentation_ratio:{:.2}", ratio(resident, used)),
+    ]);
+    info
+}
+
+#[cfg(not(feature = "jemalloc"))]
+pub fn info(_used: u64) -> Vec<String> {
+    vec!["mem_allocator:libc".to_string()]
+}
+
+#[cfg(feature = "jemalloc")]
+fn ratio(n: u64, d: u64) -> f64 {
+    if d == 0 {
+        0.0
+    } else {
+        n as f64 / d as f64
+    }
+}
+
+/// Releases the pages jemalloc keeps for later allocations back to the OS. Returns false if
+/// that failed. Other allocators have nothing to release, so it always succeeds.
+#[cfg(feature = "jemalloc")]
+pub fn purge() -> bool {
+    use std::ffi::CStr;
+    use std::ptr;
+
+    let mallctl = |name: &CStr| {
+        // SAFETY: the controls used take neither an old nor a new value.
+        let ret = unsafe {
+            tikv_jemalloc_sys::mallctl(
+                name.as_ptr(),
+                ptr::null_mut(),
+                ptr::null_mut(),
+                ptr::null_mut(),
+                0,
+            )
+        };
+        ret == 0
+    };
+    let purge = format!("arena.{MALLCTL_ARENAS_ALL}.purge\0");
+    // Pages cached by this thread are only purged once returned to the arenas.
+    mallctl(c"thread.tcache.flush")
+        && mallctl(CStr::from_bytes_with_nul(purge.as_bytes()).expect("No interior nul"))
+}
+
+#[cfg(not(feature = "jemalloc"))]
+pub fn purge() -> bool {
+    true
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn info_names_allocator() {
+        let info = info(1024);
+        assert!(info[0].starts_with("mem_allocator:"));
+        #[cfg(feature = "jemalloc")]
+        assert!(info
+            .iter()
+            .any(|line| line.starts_with("allocator_resident:")));
+        assert!(purge());
+    }
+}
@@ -0,0 +1,157 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use parking_lot::Mutex;
+use tracing::warn;
+
+use super::clients::ClientState;
+use super::resp::{BulkString, Value};
+
+/// Records every write and administrative command, with the client that sent it and how it
+/// ended, to a file of its own that is only ever appended to.
+///
+/// Each command takes a line, e.g.
+/// `time=1700000000000 id=3 addr=127.0.0.1:50000 user=default cmd=set keys="k" result=ok`,
+/// where `result` is `ok` or the error reply quoted. Values aren't recorded, as they may be
+/// sensitive. Once the file would grow past `max_size`, it is renamed with the suffix `.1`,
+/// older files moving up to `.<keep>`, and a new file is started.
+#[derive(Debug)]
+pub struct AuditLog {
+    file: Mutex<AuditFile>,
+}
+
+#[derive(Debug)]
+struct AuditFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+
+    /// Size in bytes past which the file is rotated, 0 to never rotate.
+    max_size: u64,
+
+    /// Number of rotated files kept.
+    keep: usize,
+}
+
+impl AuditLog {
+    /// Opens the file for appending, creating it if needed.
+    pub fn open(path: &Path, max_size: u64, keep: usize) -> io::Result<Self> {
+        let file = Self::append(path)?;
+        Ok(Self {
+            file: Mutex::new(AuditFile {
+                path: path.to_path_buf(),
+                size: file.metadata()?.len(),
+                file,
+                max_size,
+                keep,
+            }),
+        })
+    }
+
+    fn append(path: &Path) -> io::Result<File> {
+        OpenOptions::new().create(true).append(true).open(path)
+    }
+
+    /// Records the command `name` the client ran at `at` on `keys`, and its error reply if it
+    /// failed or was refused. Failing to write is logged rather than failing the command.
+    pub fn record(
+        &self,
+        at: SystemTime,
+        client: &ClientState,
+        name: &str,
+        keys: &[BulkString],
+        error: Option<&Value>,
+    ) {
+        let keys: Vec<String> = keys
+            .iter()
+            .map(|key| Value::BulkString(key.clone()).to_pretty_string())
+            .collect();
+        let result = match error.and_then(Value::simple_error) {
+            Some(e) => Value::BulkString(e.as_str().into()).to_pretty_string(),
+            None => "ok".to_string(),
+        };
+        let line = format!(
+            "time={} id={} addr={} user={} cmd={name} keys={} result={result}\n",
+            at.duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis(),
+            client.id(),
+            client.addr().map(|a| a.to_string()).unwrap_or_default(),
+            client.user().unwrap_or_default(),
+            keys.join(","),
+        );
+
+        let mut file = self.file.lock();
+        if let Err(e) = file.write(line.as_bytes()) {
+            warn!("Write audit file error: {e}");
+        }
+    }
+}
+
+impl AuditFile {
+    fn write(&mut self, line: &[u8]) -> io::Result<()> {
+        let len = line.len() as u64;
+        if self.max_size != 0 && self.size != 0 && self.size + len > self.max_size {
+            self.rotate()?;
+        }
+        self.file.write_all(line)?;
+        self.size += len;
+        Ok(())
+    }
+
+    /// Moves the file to `<path>.1`, shifting older files up and dropping the oldest.
+    fn rotate(&mut self) -> io::Result<()> {
+        let rotated = |n: usize| {
+            let mut name = self.path.clone().into_os_string();
+            name.push(format!(".{n}"));
+            PathBuf::from(name)
+        };
+        if self.keep == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            for n in (1..self.keep).rev() {
+                if rotated(n).exists() {
+                    fs::rename(rotated(n), rotated(n + 1))?;
+                }
+            }
+            fs::rename(&self.path, rotated(1))?;
+        }
+        self.file = AuditLog::append(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn rotates_past_max_size() {
+        let dir = std::env::temp_dir().join(format!("audit-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).expect("Create dir unexpected error");
+        let path = dir.join("audit.log");
+        let client = ClientState::new(7, Some("default".into()));
+        let audit = AuditLog::open(&path, 60, 2).expect("Open unexpected error");
+
+        let error = Value::SimpleError("READONLY You can't write".into());
+        for (key, error) in [("a", None), ("b", Some(&error)), ("c", None), ("d", None)] {
+            audit.record(UNIX_EPOCH, &client, "set", &[key.into()], error);
+        }
+
+        let read = |path: &Path| fs::read_to_string(path).expect("Read unexpected error");
+        let line = |key, result| {
+            format!("time=0 id=7 addr= user=default cmd=set keys=\"{key}\" result={result}\n")
+        };
+        assert_eq!(
+            read(&dir.join("audit.log.2")),
+            line("b", "\"READONLY You can't write\"")
+        );
+        assert_eq!(read(&dir.join("audit.log.1")), line("c", "ok"));
+        assert_eq!(read(&path), line("d", "ok"));
+        fs::remove_dir_all(&dir).expect("Remove dir unexpected error");
+    }
+}
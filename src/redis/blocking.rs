@@ -0,0 +1,109 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+use tokio::sync::Notify;
+
+use super::key::Key;
+
+/// The keys clients are blocked on, e.g. by BLPOP, so that pushing to one of them wakes the
+/// clients to try again.
+#[derive(Debug, Default)]
+pub struct BlockedKeys {
+    keys: Mutex<HashMap<Key, Vec<Arc<Notify>>>>,
+}
+
+impl BlockedKeys {
+    /// Blocks a client on the keys until the returned guard is dropped.
+    pub fn block<'a>(self: &Arc<Self>, keys: impl IntoIterator<Item = &'a [u8]>) -> Blocked {
+        let notify = Arc::new(Notify::new());
+        let keys: Vec<Key> = keys.into_iter().map(Key::new).collect();
+        {
+            let mut blocked = self.keys.lock();
+            for key in &keys {
+                blocked.entry(key.clone()).or_default().push(notify.clone());
+            }
+        }
+        Blocked {
+            registry: self.clone(),
+            keys,
+            notify,
+        }
+    }
+
+    /// Wakes every client blocked on the key. A client that isn't waiting yet wakes right
+    /// away once it does.
+    pub fn wake(&self, key: &[u8]) {
+        let blocked = self.keys.lock();
+        for notify in blocked.get(key).into_iter().flatten() {
+            notify.notify_one();
+        }
+    }
+
+    /// Returns the number of keys clients are blocked on.
+    pub fn len(&self) -> usize {
+        self.keys.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// A client blocked on keys, unblocked when dropped.
+#[derive(Debug)]
+pub struct Blocked {
+    registry: Arc<BlockedKeys>,
+    keys: Vec<Key>,
+    notify: Arc<Notify>,
+}
+
+impl Blocked {
+    /// Waits until one of the keys is pushed to, which may have happened since the client
+    /// blocked or last woke.
+    pub async fn woken(&self) {
+        self.notify.notified().await;
+    }
+}
+
+impl Drop for Blocked {
+    fn drop(&mut self) {
+        let mut blocked = self.registry.keys.lock();
+        for key in &self.keys {
+            let Some(notifies) = blocked.get_mut(key.as_bytes()) else {
+                continue;
+            };
+            notifies.retain(|notify| !Arc::ptr_eq(notify, &self.notify));
+            if notifies.is_empty() {
+                blocked.remove(key.as_bytes());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn wake_blocked_client() {
+        let registry = Arc::new(BlockedKeys::default());
+        let blocked = registry.block([b"a".as_slice(), b"b".as_slice()]);
+        assert_eq!(registry.len(), 2);
+
+        // Nothing wakes a client blocked on other keys.
+        registry.wake(b"c");
+        let woken = tokio::time::timeout(Duration::from_millis(10), blocked.woken()).await;
+        assert!(woken.is_err());
+
+        // A push before the client waits isn't missed.
+        registry.wake(b"b");
+        let woken = tokio::time::timeout(Duration::from_millis(10), blocked.woken()).await;
+        assert!(woken.is_ok());
+
+        drop(blocked);
+        assert!(registry.is_empty());
+    }
+}
@@ -0,0 +1,132 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, LineWriter, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use parking_lot::Mutex;
+use tracing::warn;
+
+use super::resp::{BulkString, Value};
+use super::session::Request;
+
+/// Written in place of the arguments that carry credentials, as Redis does for MONITOR
+/// and SLOWLOG.
+const REDACTED: &str = "\"(redacted)\"";
+
+/// Appends every command the server runs to a file, to be fed back with `crate::replay`.
+///
+/// Each command takes a line: the unix time in microseconds it ran at, then its arguments
+/// quoted like redis-cli prints bulk strings, e.g. `1700000000000000 "SET" "key" "a\nb"`.
+/// Lines are written out as they are recorded, so a capture survives a crash. Passwords
+/// never make it to the file, see `credentials`.
+#[derive(Debug)]
+pub struct Capture {
+    file: Mutex<LineWriter<File>>,
+}
+
+impl Capture {
+    /// Opens the file for appending, creating it if needed.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            file: Mutex::new(LineWriter::new(file)),
+        })
+    }
+
+    /// Records the request as run at `at`. Failing to write is logged rather than failing
+    /// the command.
+    pub fn record(&self, at: SystemTime, req: &Request) {
+        let Some(args) = req.value().array().and_then(|array| array.values()) else {
+            return;
+        };
+        let micros = at
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_micros();
+        let args: Vec<_> = args
+            .iter()
+            .filter(|arg| arg.bulk_string().is_some())
+            .collect();
+        let redacted = credentials(&args);
+        let mut line = micros.to_string();
+        for (i, arg) in args.iter().enumerate() {
+            line.push(' ');
+            match redacted[i] {
+                true => line.push_str(REDACTED),
+                false => line.push_str(&arg.to_pretty_string()),
+            }
+        }
+        line.push('\n');
+
+        let mut file = self.file.lock();
+        if let Err(e) = file.write_all(line.as_bytes()) {
+            warn!("Write capture file error: {e}");
+        }
+    }
+}
+
+/// Returns which of the arguments of a command, its name first, carry credentials: every
+/// argument of AUTH, the user name and password following AUTH in HELLO, and the rules of
+/// ACL SETUSER, since any of them may set a password.
+fn credentials(args: &[&Value]) -> Vec<bool> {
+    let is = |i: usize, name: &str| {
+        args.get(i)
+            .and_then(|arg| arg.bulk_string())
+            .and_then(BulkString::as_bytes)
+            .is_some_and(|arg| arg.eq_ignore_ascii_case(name.as_bytes()))
+    };
+    let mut redacted = vec![false; args.len()];
+    if is(0, "auth") {
+        redacted[1..].fill(true);
+    } else if is(0, "hello") {
+        // HELLO [protover [AUTH username password] [SETNAME clientname]]
+        let mut i = 2;
+        while i < args.len() {
+            if is(i, "auth") {
+                let end = (i + 3).min(args.len());
+                redacted[i + 1..end].fill(true);
+                i += 3;
+            } else {
+                i += if is(i, "setname") { 2 } else { 1 };
+            }
+        }
+    } else if is(0, "acl") && is(1, "setuser") && args.len() > 3 {
+        redacted[3..].fill(true);
+    }
+    redacted
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::test_util;
+    use super::*;
+
+    #[test]
+    fn redact_credentials() {
+        let path = std::env::temp_dir().join(format!("capture-redact-{}.log", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let capture = Capture::open(&path).expect("Open unexpected error");
+        let commands = [
+            vec!["AUTH", "user", "secret"],
+            vec!["hello", "3", "auth", "user", "secret", "setname", "app"],
+            vec!["ACL", "SETUSER", "user", "on", ">secret"],
+            vec!["SET", "key", "value"],
+        ];
+        for command in commands {
+            capture.record(UNIX_EPOCH, &test_util::command(command).into());
+        }
+
+        let written = std::fs::read_to_string(&path).expect("Read unexpected error");
+        assert!(!written.contains("secret"), "{written}");
+        assert_eq!(
+            written,
+            concat!(
+                "0 \"AUTH\" \"(redacted)\" \"(redacted)\"\n",
+                "0 \"hello\" \"3\" \"auth\" \"(redacted)\" \"(redacted)\" \"setname\" \"app\"\n",
+                "0 \"ACL\" \"SETUSER\" \"user\" \"(redacted)\" \"(redacted)\"\n",
+                "0 \"SET\" \"key\" \"value\"\n",
+            )
+        );
+        std::fs::remove_file(&path).expect("Remove unexpected error");
+    }
+}
@@ -0,0 +1,60 @@
+use std::time::Duration;
+
+use parking_lot::Mutex;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// Faults injected into every command, to test how clients, replicas and failover cope
+/// with a slow or failing server.
+///
+/// Failures are drawn from a generator seeded with `seed`, so commands sent in the same
+/// order fail the same way on every run.
+#[derive(Debug)]
+pub struct Chaos {
+    latency: Duration,
+    failure_rate: f64,
+    rng: Mutex<StdRng>,
+}
+
+impl Chaos {
+    /// Returns faults delaying every command by `latency` and failing `failure_rate` of
+    /// them, from 0 for none to 1 for all.
+    pub fn new(latency: Duration, failure_rate: f64, seed: u64) -> Self {
+        Self {
+            latency,
+            failure_rate,
+            rng: Mutex::new(StdRng::seed_from_u64(seed)),
+        }
+    }
+
+    /// Delay before a command runs, zero for none.
+    pub fn latency(&self) -> Duration {
+        self.latency
+    }
+
+    /// Returns true if the next command should fail instead of running.
+    pub fn should_fail(&self) -> bool {
+        if self.failure_rate <= 0.0 {
+            return false;
+        }
+        let mut rng = self.rng.lock();
+        rng.gen_bool(self.failure_rate.min(1.0))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn failures_repeat_with_seed() {
+        let draws = |chaos: Chaos| (0..100).map(|_| chaos.should_fail()).collect::<Vec<_>>();
+        let failures = draws(Chaos::new(Duration::ZERO, 0.3, 7));
+        assert_eq!(failures, draws(Chaos::new(Duration::ZERO, 0.3, 7)));
+        assert!(failures.iter().any(|&failed| failed));
+        assert!(!failures.iter().all(|&failed| failed));
+
+        assert!(!draws(Chaos::new(Duration::ZERO, 0.0, 7)).contains(&true));
+        assert!(!draws(Chaos::new(Duration::ZERO, 1.0, 7)).contains(&false));
+    }
+}
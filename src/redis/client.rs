@@ -1,6 +1,29 @@
+use std::fmt;
+use std::net::SocketAddr;
+use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use parking_lot::Mutex;
 use thiserror::Error;
+use tokio::net::TcpStream;
+use tracing::warn;
 
-use super::session::SessionError;
+use super::cmd::{
+    Append, AppendArg, BPop, BPopArg, Del, DelArg, Echo, EchoArg, Exists, ExistsArg, Expire,
+    ExpireArg, ExpireTime, Get, GetArg, GetRange, GetRangeArg, HDel, HDelArg, HFieldArg, HGet,
+    HGetAll, HKeyArg, HSet, HSetArg, Keys, KeysArg, LRange, LRangeArg, ListEnd, MGet, MGetArg,
+    MSet, MSetArg, Persist, PersistArg, Ping, PingArg, Pttl, PttlArg, Push, PushArg, SAdd, SKeyArg,
+    SMembers, SMembersArg, Set, SetArg, SetRange, SetRangeArg, Strlen, StrlenArg, Unlink, ZAdd,
+    ZAddArg, ZRange, ZRangeArg, ZRangeBy,
+};
+#[cfg(feature = "replication")]
+use super::cmd::{ReplConf, ReplConfArg};
+use super::config::HostPort;
+use super::resp::{Array, BulkString, Value};
+use super::session::{Request, Responder, Response, Session, SessionError};
+use super::TlsConnector;
 
 #[derive(Debug, Error)]
 pub enum ClientError {
@@ -10,6 +33,1155 @@ pub enum ClientError {
     #[error("Invalid response from server")]
     InvalidResponse,
 
+    /// Error reply from the server, e.g. `ERR unknown command`.
+    #[error("{0}")]
+    Server(String),
+
     #[error(transparent)]
     Session(#[from] SessionError),
+
+    #[error(transparent)]
+    TokioIo(#[from] tokio::io::Error),
+}
+
+/// How a client reconnects once its connection is lost.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reconnect {
+    /// Connection attempts before giving up, 0 to never reconnect.
+    pub attempts: u32,
+
+    /// Wait before the second attempt, doubled after every failed attempt.
+    pub backoff: Duration,
+}
+
+impl Default for Reconnect {
+    fn default() -> Self {
+        Self {
+            attempts: 3,
+            backoff: Duration::from_millis(100),
+        }
+    }
+}
+
+/// A connection to a Redis server with a typed method per command, e.g. `get` and `set`.
+/// Commands without one can be sent with `command`.
+///
+/// The connection is dropped after any error, since the replies may no longer line up with
+/// the requests, and reconnected by the next command. Commands aren't sent again after a
+/// lost connection, as the server may have run them already.
+pub struct RedisClient {
+    addr: SocketAddr,
+
+    /// Name of the server if connected by it, resolved again on every reconnect.
+    host: Option<HostPort>,
+    tls: Option<TlsConnector>,
+    reconnect: Reconnect,
+    session: Option<Session>,
+}
+
+impl fmt::Debug for RedisClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RedisClient")
+            .field("addr", &self.addr)
+            .field("host", &self.host)
+            .field("tls", &self.tls.is_some())
+            .field("reconnect", &self.reconnect)
+            .field("connected", &self.is_connected())
+            .finish()
+    }
+}
+
+impl RedisClient {
+    /// Connects to the server at `addr`.
+    pub async fn connect(addr: SocketAddr) -> Result<Self, ClientError> {
+        Self::connect_with(addr, None).await
+    }
+
+    /// Connects to the server at `addr`, over TLS if a connector is given.
+    pub(crate) async fn connect_with(
+        addr: SocketAddr,
+        tls: Option<TlsConnector>,
+    ) -> Result<Self, ClientError> {
+        let mut client = Self {
+            addr,
+            host: None,
+            tls,
+            reconnect: Reconnect::default(),
+            session: None,
+        };
+        client.session = Some(Self::open(addr, client.tls.as_ref()).await?);
+        Ok(client)
+    }
+
+    /// Connects to the server named `host`, over TLS if a connector is given. Failing to
+    /// resolve or connect is retried like a reconnect, and every reconnect resolves the
+    /// name again, so the client follows the server to a new address.
+    #[cfg(feature = "replication")]
+    pub(crate) async fn connect_host(
+        host: HostPort,
+        tls: Option<TlsConnector>,
+    ) -> Result<Self, ClientError> {
+        let reconnect = Reconnect::default();
+        let (session, addr) = Self::reopen(Target::Host(&host), tls.as_ref(), reconnect).await?;
+        Ok(Self {
+            addr,
+            host: Some(host),
+            tls,
+            reconnect,
+            session: Some(session),
+        })
+    }
+
+    /// Sets how the client reconnects once its connection is lost.
+    pub fn with_reconnect(mut self, reconnect: Reconnect) -> Self {
+        self.reconnect = reconnect;
+        self
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Returns false once the connection is lost, until the next command reconnects.
+    pub fn is_connected(&self) -> bool {
+        self.session.is_some()
+    }
+
+    /// Sends the command made of `args` and returns its reply, turning error replies into
+    /// `ClientError::Server`.
+    pub async fn command<T: Into<BulkString>>(
+        &mut self,
+        args: impl IntoIterator<Item = T>,
+    ) -> Result<Value, ClientError> {
+        let parts = args
+            .into_iter()
+            .map(|arg| Value::BulkString(arg.into()))
+            .collect();
+        self.send(Value::Array(Array::new(parts))).await
+    }
+
+    /// Expects `PONG`.
+    pub async fn ping(&mut self) -> Result<(), ClientError> {
+        Ping::client(self).ping(PingArg { msg: None }).await?;
+        Ok(())
+    }
+
+    /// Returns the message, as echoed by the server.
+    pub async fn echo(&mut self, msg: impl Into<BulkString>) -> Result<BulkString, ClientError> {
+        let msg = msg.into();
+        Echo::client(self)
+            .echo(EchoArg { msg: msg.clone() })
+            .await?;
+        Ok(msg)
+    }
+
+    /// Returns the value of the key, or None if it doesn't exist.
+    pub async fn get(
+        &mut self,
+        key: impl Into<BulkString>,
+    ) -> Result<Option<BulkString>, ClientError> {
+        let reply = self
+            .send(Get::command_value(GetArg { key: key.into() }))
+            .await?;
+        match reply {
+            value if value.is_null() => Ok(None),
+            Value::BulkString(value) => Ok(Some(value)),
+            _ => Err(ClientError::InvalidResponse),
+        }
+    }
+
+    pub async fn set(
+        &mut self,
+        key: impl Into<BulkString>,
+        value: impl Into<BulkString>,
+    ) -> Result<(), ClientError> {
+        self.set_with(SetArg {
+            key: key.into(),
+            value: value.into(),
+            expiry: None,
+            condition: None,
+            get: false,
+        })
+        .await
+    }
+
+    /// Sets the key to expire after `expiry`, rounded down to milliseconds.
+    pub async fn set_px(
+        &mut self,
+        key: impl Into<BulkString>,
+        value: impl Into<BulkString>,
+        expiry: Duration,
+    ) -> Result<(), ClientError> {
+        self.set_with(SetArg {
+            key: key.into(),
+            value: value.into(),
+            expiry: Some(expiry.into()),
+            condition: None,
+            get: false,
+        })
+        .await
+    }
+
+    async fn set_with(&mut self, arg: SetArg) -> Result<(), ClientError> {
+        match self.send(Set::command_value(arg)).await? {
+            Value::SimpleString(s) if s.as_str() == "OK" => Ok(()),
+            _ => Err(ClientError::InvalidResponse),
+        }
+    }
+
+    /// Sets every key to its value.
+    pub async fn mset<K: Into<BulkString>, V: Into<BulkString>>(
+        &mut self,
+        pairs: impl IntoIterator<Item = (K, V)>,
+    ) -> Result<(), ClientError> {
+        let arg = MSetArg {
+            pairs: pairs
+                .into_iter()
+                .map(|(key, value)| (key.into(), value.into()))
+                .collect(),
+        };
+        match self.send(MSet::command_value(arg)).await? {
+            Value::SimpleString(s) if s.as_str() == "OK" => Ok(()),
+            _ => Err(ClientError::InvalidResponse),
+        }
+    }
+
+    /// Returns the value of each key, `None` for keys that don't hold a string.
+    pub async fn mget<T: Into<BulkString>>(
+        &mut self,
+        keys: impl IntoIterator<Item = T>,
+    ) -> Result<Vec<Option<BulkString>>, ClientError> {
+        let arg = MGetArg {
+            keys: keys.into_iter().map(Into::into).collect(),
+        };
+        let reply = self.send(MGet::command_value(arg)).await?;
+        let values = reply
+            .array()
+            .and_then(Array::values)
+            .ok_or(ClientError::InvalidResponse)?;
+        values
+            .iter()
+            .map(|value| {
+                let value = value.bulk_string()?;
+                Some(value.as_bytes().is_some().then(|| value.clone()))
+            })
+            .collect::<Option<_>>()
+            .ok_or(ClientError::InvalidResponse)
+    }
+
+    /// Pushes the elements to the end of the list, returning its new length.
+    pub async fn push<T: Into<BulkString>>(
+        &mut self,
+        key: impl Into<BulkString>,
+        end: ListEnd,
+        elements: impl IntoIterator<Item = T>,
+    ) -> Result<i64, ClientError> {
+        let arg = PushArg {
+            key: key.into(),
+            elements: elements.into_iter().map(Into::into).collect(),
+            end,
+        };
+        Self::integer(self.send(Push::command_value(arg)).await?)
+    }
+
+    /// Pops an element from the end of the first of the lists that isn't empty, blocking
+    /// until one is pushed to for up to `timeout`, or forever if zero. Returns the key and
+    /// the element, `None` if the timeout passed.
+    pub async fn bpop<T: Into<BulkString>>(
+        &mut self,
+        keys: impl IntoIterator<Item = T>,
+        end: ListEnd,
+        timeout: Duration,
+    ) -> Result<Option<(BulkString, BulkString)>, ClientError> {
+        let arg = BPopArg {
+            keys: keys.into_iter().map(Into::into).collect(),
+            timeout,
+            end,
+        };
+        let reply = self.send(BPop::command_value(arg)).await?;
+        let array = reply.array().ok_or(ClientError::InvalidResponse)?;
+        match array.values() {
+            None => Ok(None),
+            Some([Value::BulkString(key), Value::BulkString(element)]) => {
+                Ok(Some((key.clone(), element.clone())))
+            }
+            Some(_) => Err(ClientError::InvalidResponse),
+        }
+    }
+
+    /// Returns the elements of the list from `start` to `stop`, both inclusive.
+    pub async fn lrange(
+        &mut self,
+        key: impl Into<BulkString>,
+        start: i64,
+        stop: i64,
+    ) -> Result<Vec<BulkString>, ClientError> {
+        let arg = LRangeArg {
+            key: key.into(),
+            start,
+            stop,
+        };
+        let reply = self.send(LRange::command_value(arg)).await?;
+        let values = reply
+            .array()
+            .and_then(Array::values)
+            .ok_or(ClientError::InvalidResponse)?;
+        values
+            .iter()
+            .map(|value| value.bulk_string().cloned())
+            .collect::<Option<_>>()
+            .ok_or(ClientError::InvalidResponse)
+    }
+
+    /// Sets the fields of the hash to their values, returning how many of the fields are new.
+    pub async fn hset<F: Into<BulkString>, V: Into<BulkString>>(
+        &mut self,
+        key: impl Into<BulkString>,
+        pairs: impl IntoIterator<Item = (F, V)>,
+    ) -> Result<i64, ClientError> {
+        let arg = HSetArg {
+            key: key.into(),
+            pairs: pairs
+                .into_iter()
+                .map(|(field, value)| (field.into(), value.into()))
+                .collect(),
+        };
+        Self::integer(self.send(HSet::command_value(arg)).await?)
+    }
+
+    /// Returns the value of the field of the hash, `None` if it doesn't exist.
+    pub async fn hget(
+        &mut self,
+        key: impl Into<BulkString>,
+        field: impl Into<BulkString>,
+    ) -> Result<Option<BulkString>, ClientError> {
+        let arg = HFieldArg {
+            key: key.into(),
+            field: field.into(),
+        };
+        match self.send(HGet::command_value(arg)).await? {
+            value if value.is_null() => Ok(None),
+            Value::BulkString(value) => Ok(Some(value)),
+            _ => Err(ClientError::InvalidResponse),
+        }
+    }
+
+    /// Removes the fields from the hash, returning how many of them existed.
+    pub async fn hdel<T: Into<BulkString>>(
+        &mut self,
+        key: impl Into<BulkString>,
+        fields: impl IntoIterator<Item = T>,
+    ) -> Result<i64, ClientError> {
+        let arg = HDelArg {
+            key: key.into(),
+            fields: fields.into_iter().map(Into::into).collect(),
+        };
+        Self::integer(self.send(HDel::command_value(arg)).await?)
+    }
+
+    /// Returns every field of the hash with its value, in no particular order.
+    pub async fn hgetall(
+        &mut self,
+        key: impl Into<BulkString>,
+    ) -> Result<Vec<(BulkString, BulkString)>, ClientError> {
+        let arg = HKeyArg { key: key.into() };
+        let reply = self.send(HGetAll::command_value(arg)).await?;
+        let pairs: Vec<(&Value, &Value)> = match &reply {
+            Value::Map(map) => map
+                .pairs()
+                .iter()
+                .map(|(field, value)| (field, value))
+                .collect(),
+            Value::Array(array) => array
+                .values()
+                .ok_or(ClientError::InvalidResponse)?
+                .chunks_exact(2)
+                .map(|pair| (&pair[0], &pair[1]))
+                .collect(),
+            _ => return Err(ClientError::InvalidResponse),
+        };
+        pairs
+            .into_iter()
+            .map(|(field, value)| {
+                Some((field.bulk_string()?.clone(), value.bulk_string()?.clone()))
+            })
+            .collect::<Option<_>>()
+            .ok_or(ClientError::InvalidResponse)
+    }
+
+    /// Adds the members to the set, returning how many of them are new.
+    pub async fn sadd<T: Into<BulkString>>(
+        &mut self,
+        key: impl Into<BulkString>,
+        members: impl IntoIterator<Item = T>,
+    ) -> Result<i64, ClientError> {
+        let arg = SMembersArg {
+            key: key.into(),
+            members: members.into_iter().map(Into::into).collect(),
+        };
+        Self::integer(self.send(SAdd::command_value(arg)).await?)
+    }
+
+    /// Returns every member of the set, in no particular order.
+    pub async fn smembers(
+        &mut self,
+        key: impl Into<BulkString>,
+    ) -> Result<Vec<BulkString>, ClientError> {
+        let arg = SKeyArg { key: key.into() };
+        let reply = self.send(SMembers::command_value(arg)).await?;
+        let values = match &reply {
+            Value::Set(set) => set.values(),
+            reply => reply
+                .array()
+                .and_then(Array::values)
+                .ok_or(ClientError::InvalidResponse)?,
+        };
+        values
+            .iter()
+            .map(|value| value.bulk_string().cloned())
+            .collect::<Option<_>>()
+            .ok_or(ClientError::InvalidResponse)
+    }
+
+    /// Adds the members with their scores to the sorted set, returning how many of them are
+    /// new.
+    pub async fn zadd<T: Into<BulkString>>(
+        &mut self,
+        key: impl Into<BulkString>,
+        members: impl IntoIterator<Item = (f64, T)>,
+    ) -> Result<i64, ClientError> {
+        let arg = ZAddArg {
+            key: key.into(),
+            conditions: vec![],
+            changed: false,
+            incr: false,
+            members: members
+                .into_iter()
+                .map(|(score, member)| (score, member.into()))
+                .collect(),
+        };
+        Self::integer(self.send(ZAdd::command_value(arg)).await?)
+    }
+
+    /// Returns the members of the sorted set from `start` to `stop` in rank, both
+    /// inclusive, with their scores.
+    pub async fn zrange(
+        &mut self,
+        key: impl Into<BulkString>,
+        start: i64,
+        stop: i64,
+    ) -> Result<Vec<(BulkString, f64)>, ClientError> {
+        let arg = ZRangeArg {
+            key: key.into(),
+            by: ZRangeBy::Index { start, stop },
+            rev: false,
+            limit: None,
+            with_scores: true,
+        };
+        let reply = self.send(ZRange::command_value(arg)).await?;
+        let values = reply
+            .array()
+            .and_then(Array::values)
+            .ok_or(ClientError::InvalidResponse)?;
+        values
+            .chunks(2)
+            .map(|pair| {
+                let member = pair[0].bulk_string()?.clone();
+                let score = pair.get(1)?.bulk_string()?.as_str()?.parse().ok()?;
+                Some((member, score))
+            })
+            .collect::<Option<_>>()
+            .ok_or(ClientError::InvalidResponse)
+    }
+
+    /// Increments the integer value of the key, returning the new value.
+    pub async fn incr(&mut self, key: impl Into<BulkString>) -> Result<i64, ClientError> {
+        let key: BulkString = key.into();
+        Self::integer(self.command([BulkString::from("INCR"), key]).await?)
+    }
+
+    /// Appends to the string at the key, returning its new length.
+    pub async fn append(
+        &mut self,
+        key: impl Into<BulkString>,
+        value: impl Into<BulkString>,
+    ) -> Result<i64, ClientError> {
+        let arg = AppendArg {
+            key: key.into(),
+            value: value.into(),
+        };
+        Self::integer(self.send(Append::command_value(arg)).await?)
+    }
+
+    pub async fn strlen(&mut self, key: impl Into<BulkString>) -> Result<i64, ClientError> {
+        let arg = StrlenArg { key: key.into() };
+        Self::integer(self.send(Strlen::command_value(arg)).await?)
+    }
+
+    /// Returns the bytes between `start` and `end`, both inclusive.
+    pub async fn getrange(
+        &mut self,
+        key: impl Into<BulkString>,
+        start: i64,
+        end: i64,
+    ) -> Result<BulkString, ClientError> {
+        let arg = GetRangeArg {
+            key: key.into(),
+            start,
+            end,
+        };
+        match self.send(GetRange::command_value(arg)).await? {
+            Value::BulkString(value) => Ok(value),
+            _ => Err(ClientError::InvalidResponse),
+        }
+    }
+
+    /// Overwrites the string at `offset`, returning its new length.
+    pub async fn setrange(
+        &mut self,
+        key: impl Into<BulkString>,
+        offset: i64,
+        value: impl Into<BulkString>,
+    ) -> Result<i64, ClientError> {
+        let arg = SetRangeArg {
+            key: key.into(),
+            offset,
+            value: value.into(),
+        };
+        Self::integer(self.send(SetRange::command_value(arg)).await?)
+    }
+
+    /// Deletes the keys, returning how many existed.
+    pub async fn del<T: Into<BulkString>>(
+        &mut self,
+        keys: impl IntoIterator<Item = T>,
+    ) -> Result<i64, ClientError> {
+        let arg = DelArg {
+            keys: keys.into_iter().map(Into::into).collect(),
+        };
+        Self::integer(self.send(Del::command_value(arg)).await?)
+    }
+
+    /// Returns how many of the keys exist.
+    pub async fn exists<T: Into<BulkString>>(
+        &mut self,
+        keys: impl IntoIterator<Item = T>,
+    ) -> Result<i64, ClientError> {
+        let arg = ExistsArg {
+            keys: keys.into_iter().map(Into::into).collect(),
+        };
+        Self::integer(self.send(Exists::command_value(arg)).await?)
+    }
+
+    /// Sets when the key expires, returning whether it exists.
+    pub async fn expire(
+        &mut self,
+        key: impl Into<BulkString>,
+        time: ExpireTime,
+    ) -> Result<bool, ClientError> {
+        let arg = ExpireArg {
+            key: key.into(),
+            time,
+            conditions: vec![],
+        };
+        Ok(Self::integer(self.send(Expire::command_value(arg)).await?)? == 1)
+    }
+
+    /// Removes the expiry of the key, returning whether it had one.
+    pub async fn persist(&mut self, key: impl Into<BulkString>) -> Result<bool, ClientError> {
+        let arg = PersistArg { key: key.into() };
+        Ok(Self::integer(self.send(Persist::command_value(arg)).await?)? == 1)
+    }
+
+    /// Like [`RedisClient::del`], but the server frees the values in the background.
+    pub async fn unlink<T: Into<BulkString>>(
+        &mut self,
+        keys: impl IntoIterator<Item = T>,
+    ) -> Result<i64, ClientError> {
+        let arg = DelArg {
+            keys: keys.into_iter().map(Into::into).collect(),
+        };
+        Self::integer(self.send(Unlink::command_value(arg)).await?)
+    }
+
+    /// Returns the keys matching the glob-style pattern.
+    pub async fn keys(
+        &mut self,
+        pattern: impl Into<BulkString>,
+    ) -> Result<Vec<BulkString>, ClientError> {
+        let reply = self
+            .send(Keys::command_value(KeysArg {
+                pattern: pattern.into(),
+            }))
+            .await?;
+        let values = reply
+            .array()
+            .and_then(Array::values)
+            .ok_or(ClientError::InvalidResponse)?;
+        values
+            .iter()
+            .map(|value| value.bulk_string().cloned())
+            .collect::<Option<_>>()
+            .ok_or(ClientError::InvalidResponse)
+    }
+
+    /// Returns the milliseconds the key has left to live, -1 if it has no expiry and -2 if
+    /// it doesn't exist.
+    pub async fn pttl(&mut self, key: impl Into<BulkString>) -> Result<i64, ClientError> {
+        Self::integer(
+            self.send(Pttl::command_value(PttlArg { key: key.into() }))
+                .await?,
+        )
+    }
+
+    /// Expects `OK`.
+    #[cfg(feature = "replication")]
+    pub async fn replconf(&mut self, arg: ReplConfArg) -> Result<(), ClientError> {
+        ReplConf::client(self).replconf(arg).await?;
+        Ok(())
+    }
+
+    /// Asks to continue the master's replication stream past the offset of the replication
+    /// ID given, or for a full resync without one. What the master sends next follows on the
+    /// connection, see `into_session`.
+    #[cfg(feature = "replication")]
+    pub(crate) async fn psync(
+        &mut self,
+        position: Option<(&str, u64)>,
+    ) -> Result<Resync, ClientError> {
+        // The offset asked for is that of the first byte missing.
+        let (repl_id, offset) = match position {
+            Some((repl_id, offset)) => (repl_id.to_string(), (offset + 1).to_string()),
+            None => ("?".to_string(), "-1".to_string()),
+        };
+        let reply = self
+            .command(["PSYNC".to_string(), repl_id.clone(), offset])
+            .await?;
+        let resync = match &reply {
+            Value::SimpleString(s) => s.as_str().to_string(),
+            _ => return Err(ClientError::InvalidResponse),
+        };
+        match resync.split(' ').collect::<Vec<_>>()[..] {
+            ["FULLRESYNC", repl_id, offset] => {
+                let offset = offset.parse().map_err(|_| ClientError::InvalidResponse)?;
+                Ok(Resync::Full(repl_id.to_string(), offset))
+            }
+            // Masters before PSYNC2 keep the ID.
+            ["CONTINUE"] if position.is_some() => Ok(Resync::Continue(repl_id)),
+            ["CONTINUE", repl_id] if position.is_some() => {
+                Ok(Resync::Continue(repl_id.to_string()))
+            }
+            _ => Err(ClientError::InvalidResponse),
+        }
+    }
+
+    /// Returns the connection, e.g. to read what a master sends after PSYNC, or `None` if it
+    /// was lost.
+    #[cfg(feature = "replication")]
+    pub(crate) fn into_session(self) -> Option<Session> {
+        self.session
+    }
+
+    /// Subscribes to the channels, turning the connection into one that only receives
+    /// messages.
+    #[cfg(feature = "pubsub")]
+    pub async fn subscribe<T: Into<BulkString>>(
+        mut self,
+        channels: impl IntoIterator<Item = T>,
+    ) -> Result<Subscription, ClientError> {
+        let channels: Vec<BulkString> = channels.into_iter().map(Into::into).collect();
+        if channels.is_empty() {
+            return Err(ClientError::InvalidArg);
+        }
+
+        let args = std::iter::once(BulkString::from("SUBSCRIBE")).chain(channels.iter().cloned());
+        // The server confirms every channel separately.
+        let mut reply = self.command(args).await?;
+        let mut session = self.session.take().expect("Session after command");
+        for (i, channel) in channels.iter().enumerate() {
+            if i > 0 {
+                reply = session.receive_response().await?.into();
+            }
+            let parts = Subscription::parts(&reply)?;
+            if parts.first().and_then(|kind| kind.as_bytes()) != Some(b"subscribe")
+                || parts.get(1) != Some(channel)
+            {
+                return Err(ClientError::InvalidResponse);
+            }
+        }
+
+        Ok(Subscription { session })
+    }
+
+    async fn send(&mut self, value: Value) -> Result<Value, ClientError> {
+        match self.respond(value.into()).await?.into() {
+            Value::SimpleError(e) => Err(ClientError::Server(e.as_str().to_string())),
+            value => Ok(value),
+        }
+    }
+
+    fn integer(reply: Value) -> Result<i64, ClientError> {
+        reply
+            .integer()
+            .map(|i| i.as_int())
+            .ok_or(ClientError::InvalidResponse)
+    }
+
+    /// Returns the session, reconnecting if the connection was lost.
+    async fn session(&mut self) -> Result<&mut Session, tokio::io::Error> {
+        if self.session.is_none() {
+            let target = match &self.host {
+                Some(host) => Target::Host(host),
+                None => Target::Addr(self.addr),
+            };
+            let (session, addr) = Self::reopen(target, self.tls.as_ref(), self.reconnect).await?;
+            self.addr = addr;
+            self.session = Some(session);
+        }
+        Ok(self.session.as_mut().expect("Session just connected"))
+    }
+
+    async fn reopen(
+        target: Target<'_>,
+        tls: Option<&TlsConnector>,
+        reconnect: Reconnect,
+    ) -> Result<(Session, SocketAddr), tokio::io::Error> {
+        if reconnect.attempts == 0 {
+            return Err(tokio::io::ErrorKind::NotConnected.into());
+        }
+        let mut backoff = reconnect.backoff;
+        let mut attempt = 1;
+        loop {
+            let opened = async {
+                let addr = match target {
+                    Target::Addr(addr) => addr,
+                    Target::Host(host) => host.resolve().await?,
+                };
+                Ok((Self::open(addr, tls).await?, addr))
+            };
+            match opened.await {
+                Ok(opened) => return Ok(opened),
+                Err(e) if attempt >= reconnect.attempts => return Err(e),
+                Err(e) => {
+                    warn!("Error reconnecting to {target}, attempt {attempt}: {e}");
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                    attempt += 1;
+                }
+            }
+        }
+    }
+
+    async fn open(
+        addr: SocketAddr,
+        tls: Option<&TlsConnector>,
+    ) -> Result<Session, tokio::io::Error> {
+        let stream = TcpStream::connect(addr).await?;
+        stream.set_nodelay(true)?;
+        match tls {
+            #[cfg(feature = "tls")]
+            Some(connector) => {
+                let server_name = addr.ip().into();
+                Ok(Session::new(connector.connect(server_name, stream).await?))
+            }
+            #[cfg(not(feature = "tls"))]
+            Some(never) => match *never {},
+            None => Ok(Session::new(stream)),
+        }
+    }
+}
+
+#[async_trait]
+impl Responder for RedisClient {
+    async fn respond(&mut self, req: Request) -> Result<Response, SessionError> {
+        let result = self.session().await?.send_request_and_wait_reply(req).await;
+        if result.is_err() {
+            self.session = None;
+        }
+        result
+    }
+}
+
+/// What a client connects to.
+#[derive(Debug, Clone, Copy)]
+enum Target<'a> {
+    Addr(SocketAddr),
+
+    /// A host name, resolved on every attempt.
+    Host(&'a HostPort),
+}
+
+impl fmt::Display for Target<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Addr(addr) => write!(f, "{addr}"),
+            Self::Host(host) => write!(f, "{host}"),
+        }
+    }
+}
+
+/// How a master answered PSYNC, see `RedisClient::psync`.
+#[cfg(feature = "replication")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Resync {
+    /// The replication ID and offset of the dataset in the RDB file that follows.
+    Full(String, u64),
+
+    /// The replication ID of the master, whose stream follows from the offset asked for.
+    Continue(String),
+}
+
+/// A message published to a channel the client is subscribed to.
+#[cfg(feature = "pubsub")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    pub channel: BulkString,
+    pub payload: BulkString,
+}
+
+/// A connection subscribed to channels, see `RedisClient::subscribe`.
+#[cfg(feature = "pubsub")]
+#[derive(Debug)]
+pub struct Subscription {
+    session: Session,
+}
+
+#[cfg(feature = "pubsub")]
+impl Subscription {
+    /// Waits for the next message, skipping other replies like subscription confirmations.
+    pub async fn next_message(&mut self) -> Result<Message, ClientError> {
+        loop {
+            let value: Value = self.session.receive_response().await?.into();
+            let parts = Self::parts(&value)?;
+            if let [kind, channel, payload] = parts.as_slice() {
+                if kind.as_bytes() == Some(b"message") {
+                    return Ok(Message {
+                        channel: channel.clone(),
+                        payload: payload.clone(),
+                    });
+                }
+            }
+        }
+    }
+
+    /// Returns the bulk strings of a pub/sub reply, which is an array in RESP2 and a push in
+    /// RESP3. The subscriber count of confirmations is left out.
+    fn parts(value: &Value) -> Result<Vec<BulkString>, ClientError> {
+        let values = match value {
+            Value::Array(array) => array.values().unwrap_or_default(),
+            Value::Push(push) => push.values(),
+            Value::SimpleError(e) => return Err(ClientError::Server(e.as_str().to_string())),
+            _ => return Err(ClientError::InvalidResponse),
+        };
+        Ok(values
+            .iter()
+            .filter_map(|value| value.bulk_string().cloned())
+            .collect())
+    }
+}
+
+/// Connections to one server shared by many tasks, which take a connection for as long as
+/// they need it instead of connecting every time.
+#[derive(Debug, Clone)]
+pub struct ClientPool {
+    inner: Arc<PoolInner>,
+}
+
+#[derive(Debug)]
+struct PoolInner {
+    addr: SocketAddr,
+    reconnect: Reconnect,
+
+    /// Most connections kept open while no task uses them.
+    max_idle: usize,
+    idle: Mutex<Vec<RedisClient>>,
+}
+
+impl ClientPool {
+    pub fn new(addr: SocketAddr, max_idle: usize) -> Self {
+        Self::with_reconnect(addr, max_idle, Reconnect::default())
+    }
+
+    pub fn with_reconnect(addr: SocketAddr, max_idle: usize, reconnect: Reconnect) -> Self {
+        Self {
+            inner: Arc::new(PoolInner {
+                addr,
+                reconnect,
+                max_idle,
+                idle: Mutex::new(Vec::new()),
+            }),
+        }
+    }
+
+    /// Takes an idle connection, or opens a new one if there is none. The connection goes
+    /// back to the pool once the returned client is dropped.
+    pub async fn get(&self) -> Result<PooledClient, ClientError> {
+        let idle = self.inner.idle.lock().pop();
+        let client = match idle {
+            Some(client) => client,
+            None => RedisClient::connect(self.inner.addr)
+                .await?
+                .with_reconnect(self.inner.reconnect),
+        };
+        Ok(PooledClient {
+            client: Some(client),
+            pool: self.inner.clone(),
+        })
+    }
+
+    /// Returns the number of connections waiting to be reused.
+    pub fn idle(&self) -> usize {
+        self.inner.idle.lock().len()
+    }
+}
+
+/// A client taken from a `ClientPool`.
+#[derive(Debug)]
+pub struct PooledClient {
+    client: Option<RedisClient>,
+    pool: Arc<PoolInner>,
+}
+
+impl Deref for PooledClient {
+    type Target = RedisClient;
+
+    fn deref(&self) -> &RedisClient {
+        self.client.as_ref().expect("Client returned to pool")
+    }
+}
+
+impl DerefMut for PooledClient {
+    fn deref_mut(&mut self) -> &mut RedisClient {
+        self.client.as_mut().expect("Client returned to pool")
+    }
+}
+
+impl Drop for PooledClient {
+    /// Keeps the connection for the next task unless it was lost or the pool is full.
+    fn drop(&mut self) {
+        let Some(client) = self.client.take().filter(RedisClient::is_connected) else {
+            return;
+        };
+        let mut idle = self.pool.idle.lock();
+        if idle.len() < self.pool.max_idle {
+            idle.push(client);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::{Redis, RedisConfig};
+    use super::*;
+
+    #[tokio::test]
+    async fn commands() {
+        let server = Redis::spawn(RedisConfig::default())
+            .await
+            .expect("Spawn unexpected error");
+        let mut client = RedisClient::connect(server.addr())
+            .await
+            .expect("Connect unexpected error");
+
+        client.ping().await.expect("Ping unexpected error");
+        let msg = client.echo("hello").await.expect("Echo unexpected error");
+        assert_eq!(msg, BulkString::from("hello"));
+        assert_eq!(client.get("key").await.expect("Get unexpected error"), None);
+        client
+            .set("key", "value")
+            .await
+            .expect("Set unexpected error");
+        assert_eq!(
+            client.get("key").await.expect("Get unexpected error"),
+            Some("value".into())
+        );
+        assert_eq!(
+            client.keys("k*").await.expect("Keys unexpected error"),
+            vec![BulkString::from("key")]
+        );
+        assert_eq!(client.pttl("key").await.expect("Pttl unexpected error"), -1);
+
+        let added = client
+            .hset("hash", [("a", "1"), ("b", "2")])
+            .await
+            .expect("Hset unexpected error");
+        assert_eq!(added, 2);
+        assert_eq!(
+            client
+                .hget("hash", "b")
+                .await
+                .expect("Hget unexpected error"),
+            Some("2".into())
+        );
+        assert_eq!(
+            client
+                .hdel("hash", ["a"])
+                .await
+                .expect("Hdel unexpected error"),
+            1
+        );
+        assert_eq!(
+            client
+                .hgetall("hash")
+                .await
+                .expect("Hgetall unexpected error"),
+            vec![(BulkString::from("b"), BulkString::from("2"))]
+        );
+        let added = client
+            .sadd("set", ["a", "a"])
+            .await
+            .expect("Sadd unexpected error");
+        assert_eq!(added, 1);
+        assert_eq!(
+            client
+                .smembers("set")
+                .await
+                .expect("Smembers unexpected error"),
+            vec![BulkString::from("a")]
+        );
+        let added = client
+            .zadd("zset", [(2.0, "b"), (1.5, "a"), (3.0, "b")])
+            .await
+            .expect("Zadd unexpected error");
+        assert_eq!(added, 2);
+        assert_eq!(
+            client
+                .zrange("zset", 0, -1)
+                .await
+                .expect("Zrange unexpected error"),
+            vec![(BulkString::from("a"), 1.5), (BulkString::from("b"), 3.0)]
+        );
+
+        let err = client
+            .command(["NOSUCHCOMMAND"])
+            .await
+            .expect_err("Command no error");
+        assert!(matches!(err, ClientError::Server(e) if e.starts_with("ERR unknown command")));
+        // An error reply doesn't drop the connection.
+        assert!(client.is_connected());
+    }
+
+    #[tokio::test]
+    async fn reconnects_after_connection_lost() {
+        let server = Redis::spawn(RedisConfig::default())
+            .await
+            .expect("Spawn unexpected error");
+        let addr = server.addr();
+        let mut client = RedisClient::connect(addr)
+            .await
+            .expect("Connect unexpected error")
+            .with_reconnect(Reconnect {
+                attempts: 5,
+                backoff: Duration::from_millis(10),
+            });
+        client.ping().await.expect("Ping unexpected error");
+
+        server.shutdown().await.expect("Shutdown unexpected error");
+        client.ping().await.expect_err("Ping no error");
+        assert!(!client.is_connected());
+
+        let redis = Redis::init(vec![addr], RedisConfig::default())
+            .await
+            .expect("Init unexpected error");
+        tokio::spawn(redis.start());
+        client.ping().await.expect("Ping unexpected error");
+        assert!(client.is_connected());
+    }
+
+    #[cfg(feature = "replication")]
+    #[tokio::test]
+    async fn connect_host_resolves_host() {
+        let server = Redis::spawn(RedisConfig::default())
+            .await
+            .expect("Spawn unexpected error");
+        let host = HostPort::new("127.0.0.1", server.addr().port());
+        let mut client = RedisClient::connect_host(host, None)
+            .await
+            .expect("Connect unexpected error");
+        assert_eq!(client.addr().port(), server.addr().port());
+        client.ping().await.expect("Ping unexpected error");
+    }
+
+    #[cfg(feature = "pubsub")]
+    #[tokio::test]
+    async fn subscribe_receives_messages() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Bind unexpected error");
+        let addr = listener.local_addr().expect("Local addr unexpected error");
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await?;
+            let mut buf = [0; 512];
+            let read = stream.read(&mut buf).await?;
+            assert_eq!(
+                &buf[..read],
+                b"*3\r\n$9\r\nSUBSCRIBE\r\n$1\r\na\r\n$1\r\nb\r\n"
+            );
+            stream
+                .write_all(b"*3\r\n$9\r\nsubscribe\r\n$1\r\na\r\n:1\r\n")
+                .await?;
+            stream
+                .write_all(
+                    concat!(
+                        "*3\r\n$9\r\nsubscribe\r\n$1\r\nb\r\n:2\r\n",
+                        "*3\r\n$7\r\nmessage\r\n$1\r\nb\r\n$5\r\nhello\r\n",
+                    )
+                    .as_bytes(),
+                )
+                .await?;
+            Ok::<_, tokio::io::Error>(stream)
+        });
+
+        let client = RedisClient::connect(addr)
+            .await
+            .expect("Connect unexpected error");
+        let mut subscription = client
+            .subscribe(["a", "b"])
+            .await
+            .expect("Subscribe unexpected error");
+        let message = subscription
+            .next_message()
+            .await
+            .expect("Next message unexpected error");
+        assert_eq!(
+            message,
+            Message {
+                channel: "b".into(),
+                payload: "hello".into()
+            }
+        );
+        server
+            .await
+            .expect("Join unexpected error")
+            .expect("Server unexpected error");
+    }
+
+    #[tokio::test]
+    async fn pool_reuses_connections() {
+        let server = Redis::spawn(RedisConfig::default())
+            .await
+            .expect("Spawn unexpected error");
+        let pool = ClientPool::new(server.addr(), 1);
+
+        let mut first = pool.get().await.expect("Get unexpected error");
+        let second = pool.get().await.expect("Get unexpected error");
+        first
+            .set("key", "value")
+            .await
+            .expect("Set unexpected error");
+        drop(first);
+        drop(second);
+        // Only one connection is kept.
+        assert_eq!(pool.idle(), 1);
+
+        let mut client = pool.get().await.expect("Get unexpected error");
+        assert_eq!(pool.idle(), 0);
+        assert_eq!(
+            client.get("key").await.expect("Get unexpected error"),
+            Some("value".into())
+        );
+    }
 }
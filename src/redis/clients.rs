@@ -0,0 +1,481 @@
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+use parking_lot::{Mutex, RwLock};
+use tokio::sync::{mpsc, Notify};
+use tracing::warn;
+
+use super::cmd::Command;
+use super::reply::Reply;
+use super::resp::Value;
+use super::tracking::TrackingOptions;
+
+/// Messages a connection can have waiting to be written. A connection's own replies wait for
+/// room, which stops it reading requests while it is behind, but a client that lets pushes
+/// from other connections pile up past this is disconnected.
+pub const OUTBOUND_QUEUE_LEN: usize = 4096;
+
+/// Flags describing the role of a connection.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ClientFlags {
+    /// The connection is the link to our master.
+    pub master: bool,
+
+    /// The connection belongs to one of our replicas.
+    pub replica: bool,
+
+    /// The connection is closed once the pending reply is written.
+    pub close_after_reply: bool,
+
+    /// The next command may access a slot this node is importing, set by ASKING.
+    pub asking: bool,
+}
+
+/// State of a single client connection, owned by its connection task and shared with the
+/// registry so that commands like CLIENT LIST can see every client.
+#[derive(Debug)]
+pub struct ClientState {
+    id: u64,
+    name: Option<String>,
+    addr: Option<SocketAddr>,
+    laddr: Option<SocketAddr>,
+
+    /// Selected database index.
+    pub db: usize,
+    pub flags: ClientFlags,
+
+    /// Name of the authenticated user, `None` until the client authenticates.
+    user: Option<String>,
+
+    /// Protocol version negotiated with HELLO.
+    pub resp: u8,
+
+    created_at: Instant,
+    last_command_at: Instant,
+    last_command: Option<&'static str>,
+
+    /// Channels and patterns the client is subscribed to.
+    pub channels: HashSet<String>,
+    pub patterns: HashSet<String>,
+
+    /// Commands queued since MULTI, `None` outside a transaction.
+    pub multi: Option<Vec<Command>>,
+
+    /// Client-side caching options, `None` if tracking is off.
+    pub tracking: Option<TrackingOptions>,
+
+    /// Whether to track the keys read by the next command, set by CLIENT CACHING.
+    pub caching: Option<bool>,
+
+    /// Priority a replica told with REPLCONF priority, `None` if it didn't.
+    pub replica_priority: Option<u32>,
+
+    /// Port a replica told it listens to with REPLCONF listening-port, `None` if it didn't.
+    pub listening_port: Option<u16>,
+
+    /// Queue of messages to be written to the connection.
+    outbound: Option<mpsc::Sender<Reply>>,
+
+    /// Wakes the connection task to close the connection.
+    close: Arc<Notify>,
+}
+
+impl ClientState {
+    pub fn new(id: u64, user: Option<String>) -> Self {
+        let now = Instant::now();
+        Self {
+            id,
+            name: None,
+            addr: None,
+            laddr: None,
+            db: 0,
+            flags: ClientFlags::default(),
+            user,
+            resp: 2,
+            created_at: now,
+            last_command_at: now,
+            last_command: None,
+            channels: HashSet::new(),
+            patterns: HashSet::new(),
+            multi: None,
+            tracking: None,
+            caching: None,
+            replica_priority: None,
+            listening_port: None,
+            outbound: None,
+            close: Arc::new(Notify::new()),
+        }
+    }
+
+    /// Sets the peer and local address of the connection.
+    pub fn with_addrs(mut self, addr: SocketAddr, laddr: SocketAddr) -> Self {
+        self.addr = Some(addr);
+        self.laddr = Some(laddr);
+        self
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    pub fn set_name(&mut self, name: Option<String>) {
+        self.name = name;
+    }
+
+    pub fn addr(&self) -> Option<SocketAddr> {
+        self.addr
+    }
+
+    pub fn laddr(&self) -> Option<SocketAddr> {
+        self.laddr
+    }
+
+    /// Returns the name of the authenticated user, if any.
+    pub fn user(&self) -> Option<&str> {
+        self.user.as_deref()
+    }
+
+    pub fn set_user(&mut self, user: String) {
+        self.user = Some(user);
+    }
+
+    pub fn created_at(&self) -> Instant {
+        self.created_at
+    }
+
+    pub fn last_command_at(&self) -> Instant {
+        self.last_command_at
+    }
+
+    /// Returns the name of the last command the client sent.
+    pub fn last_command(&self) -> Option<&'static str> {
+        self.last_command
+    }
+
+    /// Records that the client sent the command just now.
+    pub fn touch(&mut self, command: &'static str) {
+        self.last_command_at = Instant::now();
+        self.last_command = Some(command);
+    }
+
+    /// Returns true if the client is inside a MULTI block.
+    pub fn in_multi(&self) -> bool {
+        self.multi.is_some()
+    }
+
+    /// Returns true if the client is subscribed to any channel or pattern.
+    pub fn in_pubsub(&self) -> bool {
+        !self.channels.is_empty() || !self.patterns.is_empty()
+    }
+
+    /// Sends a message to the connection outside of the request and reply flow.
+    /// Returns false if the connection is gone, or is being closed because its outbound
+    /// queue is full.
+    pub fn push(&self, value: Value) -> bool {
+        let Some(tx) = &self.outbound else {
+            return false;
+        };
+        try_queue(self.id, tx, &self.close, value.into())
+    }
+
+    /// Returns a link to send messages to the connection without locking its state, `None`
+    /// if it isn't registered.
+    pub fn link(&self) -> Option<ClientLink> {
+        let outbound = self.outbound.as_ref()?.downgrade();
+        Some(ClientLink {
+            id: self.id,
+            outbound,
+            close: self.close.clone(),
+        })
+    }
+
+    /// Closes the connection once its current command is done.
+    pub fn close(&self) {
+        self.close.notify_one();
+    }
+
+    /// Returns the flags in the CLIENT LIST format, `N` if no flag is set.
+    pub fn flags_string(&self) -> String {
+        let flags = [
+            (self.flags.master, 'M'),
+            (self.flags.replica, 'S'),
+            (self.in_pubsub(), 'P'),
+            (self.in_multi(), 'x'),
+            (self.flags.close_after_reply, 'c'),
+            (self.tracking.is_some(), 't'),
+        ];
+        let s: String = flags
+            .into_iter()
+            .filter(|(set, _)| *set)
+            .map(|(_, c)| c)
+            .collect();
+        if s.is_empty() {
+            "N".to_string()
+        } else {
+            s
+        }
+    }
+
+    /// Returns the client as a single line of `field=value` pairs, as used by CLIENT INFO
+    /// and CLIENT LIST.
+    pub fn info_line(&self) -> String {
+        let addr = |addr: Option<SocketAddr>| addr.map(|a| a.to_string()).unwrap_or_default();
+        format!(
+            "id={} addr={} laddr={} name={} age={} idle={} flags={} db={} sub={} psub={} \
+             multi={} cmd={} user={} resp={}",
+            self.id,
+            addr(self.addr),
+            addr(self.laddr),
+            self.name().unwrap_or_default(),
+            self.created_at.elapsed().as_secs(),
+            self.last_command_at.elapsed().as_secs(),
+            self.flags_string(),
+            self.db,
+            self.channels.len(),
+            self.patterns.len(),
+            self.multi.as_ref().map(|q| q.len() as i64).unwrap_or(-1),
+            self.last_command.unwrap_or("NULL"),
+            self.user().unwrap_or_default(),
+            self.resp,
+        )
+    }
+}
+
+/// Queues a message for the connection, closing it if its queue is full.
+fn try_queue(id: u64, tx: &mpsc::Sender<Reply>, close: &Notify, reply: Reply) -> bool {
+    match tx.try_send(reply) {
+        Ok(()) => true,
+        Err(mpsc::error::TrySendError::Full(_)) => {
+            warn!("Closing client {id}, its outbound queue is full");
+            close.notify_one();
+            false
+        }
+        Err(mpsc::error::TrySendError::Closed(_)) => false,
+    }
+}
+
+/// Sends messages to a connection like `ClientState::push`, but can be kept and used
+/// without locking the client, e.g. to stream writes to a replica while the writer is
+/// locked. It doesn't keep the outbound queue open once the connection is gone.
+#[derive(Debug, Clone)]
+pub struct ClientLink {
+    id: u64,
+    outbound: mpsc::WeakSender<Reply>,
+    close: Arc<Notify>,
+}
+
+impl ClientLink {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Queues the reply, returning false if the connection is gone or is being closed.
+    pub fn send(&self, reply: Reply) -> bool {
+        let Some(tx) = self.outbound.upgrade() else {
+            return false;
+        };
+        try_queue(self.id, &tx, &self.close, reply)
+    }
+
+    /// Closes the connection once its current command is done, like `ClientState::close`.
+    pub fn close(&self) {
+        self.close.notify_one();
+    }
+
+    /// Returns whether the connection is gone.
+    pub fn is_closed(&self) -> bool {
+        self.outbound.upgrade().is_none_or(|tx| tx.is_closed())
+    }
+}
+
+pub type SharedClient = Arc<Mutex<ClientState>>;
+
+/// Every connected client by id.
+#[derive(Debug)]
+pub struct ClientRegistry {
+    next_id: AtomicU64,
+    clients: RwLock<HashMap<u64, SharedClient>>,
+    rejected: AtomicU64,
+}
+
+impl Default for ClientRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ClientRegistry {
+    pub fn new() -> Self {
+        Self {
+            // Like Redis, ids start at 1.
+            next_id: AtomicU64::new(1),
+            clients: RwLock::new(HashMap::new()),
+            rejected: AtomicU64::new(0),
+        }
+    }
+
+    /// Registers a new client with a unique id. The client is unregistered when the returned
+    /// handle is dropped.
+    pub fn register(
+        self: &Arc<Self>,
+        user: Option<String>,
+        addrs: Option<(SocketAddr, SocketAddr)>,
+    ) -> ClientHandle {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (outbound_tx, outbound_rx) = mpsc::channel(OUTBOUND_QUEUE_LEN);
+        let mut state = ClientState::new(id, user);
+        state.outbound = Some(outbound_tx.clone());
+        let close = state.close.clone();
+        if let Some((addr, laddr)) = addrs {
+            state = state.with_addrs(addr, laddr);
+        }
+
+        let state = Arc::new(Mutex::new(state));
+        self.clients.write().insert(id, state.clone());
+        ClientHandle {
+            id,
+            state,
+            outbound_tx,
+            outbound_rx: Some(outbound_rx),
+            close,
+            registry: self.clone(),
+        }
+    }
+
+    /// Returns the client with the id, if connected.
+    pub fn get(&self, id: u64) -> Option<SharedClient> {
+        self.clients.read().get(&id).cloned()
+    }
+
+    /// Returns every connected client ordered by id.
+    pub fn all(&self) -> Vec<SharedClient> {
+        let clients = self.clients.read();
+        let mut ids: Vec<&u64> = clients.keys().collect();
+        ids.sort();
+        ids.into_iter().map(|id| clients[id].clone()).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.clients.read().len()
+    }
+
+    /// Returns the number of clients registered since startup.
+    pub fn total_registered(&self) -> u64 {
+        self.next_id.load(Ordering::Relaxed) - 1
+    }
+
+    /// Records a connection refused because of maxclients.
+    pub fn record_rejected(&self) {
+        self.rejected.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Returns the number of connections refused since startup.
+    pub fn total_rejected(&self) -> u64 {
+        self.rejected.load(Ordering::Relaxed)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// A registered client, removed from the registry on drop.
+#[derive(Debug)]
+pub struct ClientHandle {
+    id: u64,
+    state: SharedClient,
+
+    /// Outbound queue of the connection, replies and pushes alike go through it so that
+    /// they are written in order.
+    outbound_tx: mpsc::Sender<Reply>,
+    outbound_rx: Option<mpsc::Receiver<Reply>>,
+
+    close: Arc<Notify>,
+
+    registry: Arc<ClientRegistry>,
+}
+
+impl ClientHandle {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn state(&self) -> SharedClient {
+        self.state.clone()
+    }
+
+    /// Returns a sender to the outbound queue of the connection.
+    pub fn sender(&self) -> mpsc::Sender<Reply> {
+        self.outbound_tx.clone()
+    }
+
+    /// Completes once the connection should be closed, see `ClientState::close`.
+    pub async fn closed(&self) {
+        self.close.notified().await
+    }
+
+    /// Takes the receiving end of the outbound queue, which is drained by the task writing
+    /// to the connection. The queue closes once the client is dropped and unregistered.
+    pub fn take_receiver(&mut self) -> Option<mpsc::Receiver<Reply>> {
+        self.outbound_rx.take()
+    }
+}
+
+impl Drop for ClientHandle {
+    fn drop(&mut self) {
+        self.registry.clients.write().remove(&self.id);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn register_assigns_unique_ids_and_unregisters_on_drop() {
+        let registry = Arc::new(ClientRegistry::new());
+        let first = registry.register(None, None);
+        let second = registry.register(Some("default".into()), None);
+
+        assert_ne!(first.id(), second.id());
+        assert_eq!(registry.len(), 2);
+        assert_eq!(
+            registry.get(second.id()).unwrap().lock().user(),
+            Some("default")
+        );
+
+        let line = registry.all()[1].lock().info_line();
+        assert!(line.starts_with(&format!("id={} addr= laddr= name= ", second.id())));
+        assert!(line.contains(" flags=N db=0 sub=0 psub=0 multi=-1 cmd=NULL user=default"));
+
+        drop(first);
+        assert_eq!(registry.len(), 1);
+        assert_eq!(registry.all()[0].lock().id(), second.id());
+    }
+
+    #[tokio::test]
+    async fn push_closes_client_with_full_queue() {
+        let registry = Arc::new(ClientRegistry::new());
+        let mut client = registry.register(None, None);
+        let _rx = client.take_receiver();
+        {
+            let state = client.state();
+            let state = state.lock();
+            for _ in 0..OUTBOUND_QUEUE_LEN {
+                assert!(state.push(Value::SimpleString("message".into())));
+            }
+            assert!(!state.push(Value::SimpleString("message".into())));
+        }
+
+        tokio::time::timeout(std::time::Duration::from_secs(1), client.closed())
+            .await
+            .expect("Client not closed");
+    }
+}
@@ -0,0 +1,107 @@
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use async_trait::async_trait;
+use parking_lot::Mutex;
+use tokio::sync::Notify;
+
+/// Source of the current time for key expiry, so that tests can move time forward instead
+/// of waiting for keys to expire.
+#[async_trait]
+pub trait Clock: Send + Sync + fmt::Debug {
+    fn now(&self) -> SystemTime;
+
+    /// Waits until `duration` has passed on this clock.
+    async fn sleep(&self, duration: Duration);
+}
+
+/// Returns the system clock, which the server uses unless told otherwise.
+pub fn system() -> Arc<dyn Clock> {
+    Arc::new(SystemClock)
+}
+
+/// The wall clock, sleeping on the Tokio timer.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+#[async_trait]
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    async fn sleep(&self, duration: Duration) {
+        tokio::time::sleep(duration).await
+    }
+}
+
+/// A clock that only moves when told to, waking whatever sleeps past the new time.
+#[derive(Debug)]
+pub struct TestClock {
+    now: Mutex<SystemTime>,
+    advanced: Notify,
+}
+
+impl Default for TestClock {
+    fn default() -> Self {
+        Self::new(SystemTime::now())
+    }
+}
+
+impl TestClock {
+    /// Returns a clock stopped at `now`.
+    pub fn new(now: SystemTime) -> Self {
+        Self {
+            now: Mutex::new(now),
+            advanced: Notify::new(),
+        }
+    }
+
+    /// Moves the clock forward by `duration`.
+    pub fn advance(&self, duration: Duration) {
+        *self.now.lock() += duration;
+        self.advanced.notify_waiters();
+    }
+}
+
+#[async_trait]
+impl Clock for TestClock {
+    fn now(&self) -> SystemTime {
+        *self.now.lock()
+    }
+
+    async fn sleep(&self, duration: Duration) {
+        let until = self.now() + duration;
+        loop {
+            // Registered before checking the time so that an advance in between isn't missed.
+            let advanced = self.advanced.notified();
+            if self.now() >= until {
+                return;
+            }
+            advanced.await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_clock_sleeps_until_advanced() {
+        let clock = Arc::new(TestClock::default());
+        let start = clock.now();
+
+        let sleeper = clock.clone();
+        let sleep = tokio::spawn(async move { sleeper.sleep(Duration::from_secs(10)).await });
+        tokio::task::yield_now().await;
+        clock.advance(Duration::from_secs(5));
+        tokio::task::yield_now().await;
+        assert!(!sleep.is_finished());
+
+        clock.advance(Duration::from_secs(5));
+        sleep.await.expect("Join unexpected error");
+        assert_eq!(clock.now(), start + Duration::from_secs(10));
+    }
+}
@@ -0,0 +1,1098 @@
+pub mod bus;
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::ops::RangeInclusive;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rand::Rng;
+use thiserror::Error;
+use tracing::{info, warn};
+
+use self::bus::{Message, MessageKind};
+
+/// Number of hash slots the keyspace is split into.
+pub const SLOT_COUNT: usize = 16384;
+
+/// Length of a node ID, in hex digits.
+const NODE_ID_LEN: usize = 40;
+
+/// Offset of the cluster bus port from the client port, unless it is configured.
+pub const BUS_PORT_OFFSET: u16 = 10000;
+
+#[derive(Debug, Error)]
+pub enum ClusterError {
+    #[error("Invalid cluster config at line {0}")]
+    InvalidConfig(usize),
+
+    #[error("Cluster config has no line for this node")]
+    NoMyself,
+
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+/// Why a command can't run on this node, telling the client where to send it instead.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum RedirectError {
+    /// The slot is served by another node.
+    #[error("{slot} {}:{}", addr.ip(), addr.port())]
+    Moved { slot: u16, addr: SocketAddr },
+
+    /// The slot is being migrated and the keys may already be on the target node, which
+    /// only serves them after ASKING.
+    #[error("{slot} {}:{}", addr.ip(), addr.port())]
+    Ask { slot: u16, addr: SocketAddr },
+
+    #[error("Keys in request don't hash to the same slot")]
+    CrossSlot,
+
+    /// Some of the keys were already migrated and some weren't.
+    #[error("Multiple keys request during rehashing of slot")]
+    TryAgain,
+
+    #[error("Hash slot not served")]
+    Unserved,
+
+    /// Some slot isn't served, or is served by a failed node.
+    #[error("The cluster is down")]
+    Down,
+}
+
+/// Why CLUSTER ADDSLOTS, DELSLOTS or SETSLOT can't change a slot.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum SlotError {
+    #[error("Slot {0} is already busy")]
+    Busy(u16),
+
+    #[error("Slot {0} is already unassigned")]
+    Unassigned(u16),
+
+    #[error("Slot {0} specified multiple times")]
+    Repeated(u16),
+
+    #[error("I don't know about node {0}")]
+    UnknownNode(String),
+
+    #[error("I'm already the owner of hash slot {0}")]
+    AlreadyOwner(u16),
+
+    #[error("I'm not the owner of hash slot {0}")]
+    NotOwner(u16),
+
+    #[error("Can't migrate hash slot {0} to or from myself")]
+    Myself(u16),
+
+    #[error(
+        "Can't assign hashslot {0} to a different node while I still hold keys for this hash slot."
+    )]
+    HoldingKeys(u16),
+}
+
+impl RedirectError {
+    /// Returns the error code prefixed to the RESP error reply.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Moved { .. } => "MOVED",
+            Self::Ask { .. } => "ASK",
+            Self::CrossSlot => "CROSSSLOT",
+            Self::TryAgain => "TRYAGAIN",
+            Self::Unserved | Self::Down => "CLUSTERDOWN",
+        }
+    }
+}
+
+/// Lookup table of CRC16-XMODEM (polynomial 0x1021, initial value 0), the checksum keys are
+/// hashed with.
+const CRC16_TABLE: [u16; 256] = crc16_table();
+
+const fn crc16_table() -> [u16; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = (i as u16) << 8;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+pub fn crc16(bytes: &[u8]) -> u16 {
+    bytes.iter().fold(0, |crc, &b| {
+        (crc << 8) ^ CRC16_TABLE[((crc >> 8) as u8 ^ b) as usize]
+    })
+}
+
+/// Returns the slot of a key. If the key has a hash tag, i.e. a non-empty part between its
+/// first `{` and the `}` after it, only the tag is hashed, so keys sharing a tag share a slot.
+pub fn key_hash_slot(key: &[u8]) -> u16 {
+    let tag = key.iter().position(|&b| b == b'{').and_then(|open| {
+        let rest = &key[open + 1..];
+        match rest.iter().position(|&b| b == b'}') {
+            Some(0) | None => None,
+            Some(close) => Some(&rest[..close]),
+        }
+    });
+    crc16(tag.unwrap_or(key)) & (SLOT_COUNT as u16 - 1)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClusterNode {
+    pub id: String,
+    pub ip: IpAddr,
+    pub port: u16,
+
+    /// Port of the cluster bus.
+    pub cport: u16,
+
+    /// Epoch of the node's claim on its slots. Nodes disagreeing on the owner of a slot
+    /// settle on the claim with the higher epoch.
+    pub config_epoch: u64,
+
+    /// Whether the node hasn't been heard from for longer than the node timeout.
+    pub failed: bool,
+}
+
+impl ClusterNode {
+    /// Returns a node with a new random ID.
+    pub fn new(ip: IpAddr, port: u16, cport: u16) -> Self {
+        let mut rng = rand::thread_rng();
+        let id = (0..NODE_ID_LEN)
+            .map(|_| char::from_digit(rng.gen_range(0..16), 16).unwrap())
+            .collect();
+        Self {
+            id,
+            ip,
+            port,
+            cport,
+            config_epoch: 0,
+            failed: false,
+        }
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        SocketAddr::new(self.ip, self.port)
+    }
+
+    pub fn bus_addr(&self) -> SocketAddr {
+        SocketAddr::new(self.ip, self.cport)
+    }
+}
+
+/// A contiguous range of slots, both ends included.
+pub type SlotRange = RangeInclusive<u16>;
+
+/// The nodes of the cluster, and which of them serves every slot.
+#[derive(Debug)]
+pub struct ClusterState {
+    /// Every known node, this one first.
+    nodes: Vec<ClusterNode>,
+
+    /// Index in `nodes` of the owner of every slot.
+    slots: Box<[Option<usize>]>,
+
+    /// Slots this node is moving to, and taking over from, the node at the index.
+    migrating: HashMap<u16, usize>,
+    importing: HashMap<u16, usize>,
+
+    /// When every other node was last heard from, by ID.
+    last_seen: HashMap<String, SystemTime>,
+
+    /// Addresses of cluster buses to introduce this node to, taken by the cluster bus.
+    meets: Vec<SocketAddr>,
+
+    /// Whether the nodes or slots changed since the config was last saved.
+    dirty: bool,
+
+    current_epoch: u64,
+}
+
+impl ClusterState {
+    /// Returns a cluster of only this node, which serves no slots until they are added.
+    pub fn new(myself: ClusterNode) -> Self {
+        Self {
+            nodes: vec![myself],
+            slots: vec![None; SLOT_COUNT].into_boxed_slice(),
+            migrating: HashMap::new(),
+            importing: HashMap::new(),
+            last_seen: HashMap::new(),
+            meets: vec![],
+            dirty: true,
+            current_epoch: 0,
+        }
+    }
+
+    /// Loads the cluster from a config written by `save`, this node now being reachable at
+    /// the given address and bus port.
+    pub fn load(path: &Path, ip: IpAddr, port: u16, cport: u16) -> Result<Self, ClusterError> {
+        let mut cluster = Self::from_config(&fs::read_to_string(path)?)?;
+        let myself = &mut cluster.nodes[0];
+        (myself.ip, myself.port, myself.cport) = (ip, port, cport);
+        Ok(cluster)
+    }
+
+    /// Writes the nodes and slots to the file, replacing it at once so a crash can't leave
+    /// it half written.
+    pub fn save(config: &str, path: &Path) -> io::Result<()> {
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, config)?;
+        fs::rename(tmp, path)
+    }
+
+    /// Returns the config file, the lines of CLUSTER NODES followed by the epoch.
+    pub fn config(&self) -> String {
+        let mut config = self.node_lines().join("\n");
+        config.push_str(&format!(
+            "\nvars currentEpoch {} lastVoteEpoch 0\n",
+            self.current_epoch
+        ));
+        config
+    }
+
+    fn from_config(config: &str) -> Result<Self, ClusterError> {
+        let mut nodes = vec![];
+        let mut owned = vec![];
+        let mut myself = None;
+        let mut current_epoch = 0;
+        for (i, line) in config.lines().enumerate() {
+            let invalid = || ClusterError::InvalidConfig(i + 1);
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match fields.as_slice() {
+                [] => continue,
+                ["vars", vars @ ..] => {
+                    for pair in vars.chunks(2) {
+                        if let ["currentEpoch", epoch] = pair {
+                            current_epoch = epoch.parse().map_err(|_| invalid())?;
+                        }
+                    }
+                }
+                [id, addr, flags, _master, _ping, _pong, epoch, _link, slots @ ..] => {
+                    let (addr, cport) = addr.split_once('@').ok_or_else(invalid)?;
+                    let (ip, port) = addr.rsplit_once(':').ok_or_else(invalid)?;
+                    let flags: Vec<&str> = flags.split(',').collect();
+                    if flags.contains(&"myself") {
+                        myself = Some(nodes.len());
+                    }
+                    nodes.push(ClusterNode {
+                        id: id.to_string(),
+                        ip: ip.parse().map_err(|_| invalid())?,
+                        port: port.parse().map_err(|_| invalid())?,
+                        cport: cport.parse().map_err(|_| invalid())?,
+                        config_epoch: epoch.parse().map_err(|_| invalid())?,
+                        failed: flags.contains(&"fail"),
+                    });
+                    let ranges = slots
+                        .iter()
+                        .map(|range| parse_slot_range(range).ok_or_else(invalid))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    owned.push(ranges);
+                }
+                _ => return Err(invalid()),
+            }
+        }
+
+        // This node comes first.
+        let myself = myself.ok_or(ClusterError::NoMyself)?;
+        nodes.swap(0, myself);
+        owned.swap(0, myself);
+        let mut slots = vec![None; SLOT_COUNT].into_boxed_slice();
+        for (index, ranges) in owned.into_iter().enumerate() {
+            for slot in ranges.into_iter().flatten() {
+                slots[slot as usize] = Some(index);
+            }
+        }
+
+        Ok(Self {
+            nodes,
+            slots,
+            migrating: HashMap::new(),
+            importing: HashMap::new(),
+            last_seen: HashMap::new(),
+            meets: vec![],
+            dirty: false,
+            current_epoch,
+        })
+    }
+
+    pub fn myself(&self) -> &ClusterNode {
+        &self.nodes[0]
+    }
+
+    pub fn nodes(&self) -> &[ClusterNode] {
+        &self.nodes
+    }
+
+    pub fn current_epoch(&self) -> u64 {
+        self.current_epoch
+    }
+
+    /// Returns the node serving the slot, if any.
+    pub fn owner(&self, slot: u16) -> Option<&ClusterNode> {
+        self.slots
+            .get(slot as usize)
+            .copied()
+            .flatten()
+            .map(|i| &self.nodes[i])
+    }
+
+    /// Makes `node` the owner of the slots, adding it to the known nodes if it is new.
+    pub fn assign(&mut self, slots: SlotRange, node: ClusterNode) {
+        let index = self.node_index(node);
+        for slot in slots {
+            self.slots[slot as usize] = Some(index);
+        }
+        self.dirty = true;
+    }
+
+    /// Makes this node the owner of the slots, none of which may have an owner yet.
+    pub fn add_slots(&mut self, slots: &[u16]) -> Result<(), SlotError> {
+        self.check_slots(slots, Option::is_none, SlotError::Busy)?;
+        for &slot in slots {
+            self.slots[slot as usize] = Some(0);
+        }
+        self.dirty = true;
+        Ok(())
+    }
+
+    /// Leaves the slots without an owner, all of which must have one.
+    pub fn del_slots(&mut self, slots: &[u16]) -> Result<(), SlotError> {
+        self.check_slots(slots, Option::is_some, SlotError::Unassigned)?;
+        for &slot in slots {
+            self.slots[slot as usize] = None;
+        }
+        self.dirty = true;
+        Ok(())
+    }
+
+    /// Checks that every slot has an owner or not as `valid` wants, and is given once.
+    fn check_slots(
+        &self,
+        slots: &[u16],
+        valid: impl Fn(&Option<usize>) -> bool,
+        invalid: fn(u16) -> SlotError,
+    ) -> Result<(), SlotError> {
+        let mut seen = vec![false; SLOT_COUNT];
+        for &slot in slots {
+            if !valid(&self.slots[slot as usize]) {
+                return Err(invalid(slot));
+            }
+            if std::mem::replace(&mut seen[slot as usize], true) {
+                return Err(SlotError::Repeated(slot));
+            }
+        }
+        Ok(())
+    }
+
+    /// Asks the cluster bus to introduce this node to the node with the bus address.
+    pub fn meet(&mut self, addr: SocketAddr) {
+        self.meets.push(addr);
+    }
+
+    pub fn take_meets(&mut self) -> Vec<SocketAddr> {
+        std::mem::take(&mut self.meets)
+    }
+
+    /// Returns whether the nodes or slots changed since the last call, and need saving.
+    pub fn take_dirty(&mut self) -> bool {
+        std::mem::take(&mut self.dirty)
+    }
+
+    /// Returns a message about this node, its slots, and the other nodes it knows.
+    pub fn message(&self, kind: MessageKind) -> Message {
+        let myself = self.myself();
+        Message {
+            kind,
+            current_epoch: self.current_epoch,
+            sender: myself.clone(),
+            slots: self
+                .slot_ranges()
+                .into_iter()
+                .filter(|(_, owner)| owner.id == myself.id)
+                .map(|(range, _)| range)
+                .collect(),
+            gossip: self.nodes[1..].to_vec(),
+        }
+    }
+
+    /// Updates the cluster with a message from the node at `peer_ip`, received at `now`.
+    ///
+    /// Senders that aren't known yet are only added by MEET, or the PONG answering one.
+    /// The sender's claims on slots win over claims with a lower config epoch, and slots it
+    /// no longer claims are left without an owner. Claims with the same epoch can't be
+    /// settled, so when the sender shares this node's epoch, the one of the two with the
+    /// smaller ID moves to a new epoch, like Redis does on a config epoch collision. Nodes
+    /// the sender knows of are added.
+    pub fn receive(&mut self, msg: &Message, peer_ip: IpAddr, now: SystemTime) {
+        let mut sender = msg.sender.clone();
+        if sender.id == self.myself().id {
+            return;
+        }
+        if sender.ip.is_unspecified() {
+            sender.ip = peer_ip;
+        }
+        sender.failed = false;
+        let index = match self.nodes.iter().position(|node| node.id == sender.id) {
+            Some(index) => index,
+            None if msg.kind != MessageKind::Ping => {
+                info!("Node {} joined the cluster", sender.id);
+                self.dirty = true;
+                self.node_index(sender.clone())
+            }
+            None => return,
+        };
+        if self.nodes[index] != sender {
+            if self.nodes[index].failed {
+                info!("Node {} is reachable again", sender.id);
+            }
+            self.nodes[index] = sender.clone();
+            self.dirty = true;
+        }
+        self.last_seen.insert(sender.id.clone(), now);
+        if msg.current_epoch > self.current_epoch {
+            self.current_epoch = msg.current_epoch;
+            self.dirty = true;
+        }
+        if sender.config_epoch == self.myself().config_epoch && sender.id > self.myself().id {
+            self.current_epoch += 1;
+            self.nodes[0].config_epoch = self.current_epoch;
+            self.dirty = true;
+            info!(
+                "Config epoch collision with node {}, moved to epoch {}",
+                sender.id, self.current_epoch
+            );
+        }
+
+        let mut claimed = vec![false; SLOT_COUNT];
+        for slot in msg.slots.iter().cloned().flatten() {
+            claimed[slot as usize] = true;
+        }
+        for (slot, owner) in self.slots.iter_mut().enumerate() {
+            let update = match *owner {
+                Some(owner) if owner == index => !claimed[slot],
+                Some(owner) => {
+                    claimed[slot] && self.nodes[owner].config_epoch < sender.config_epoch
+                }
+                None => claimed[slot],
+            };
+            if update {
+                *owner = claimed[slot].then_some(index);
+                self.dirty = true;
+            }
+        }
+
+        for node in &msg.gossip {
+            let known = node.id == self.myself().id || self.nodes.iter().any(|n| n.id == node.id);
+            if !known {
+                info!("Node {} joined the cluster", node.id);
+                self.node_index(ClusterNode {
+                    failed: false,
+                    ..node.clone()
+                });
+                self.dirty = true;
+            }
+        }
+    }
+
+    /// Marks the nodes that haven't been heard from for longer than `timeout` as failed.
+    /// Nodes that were never heard from are timed from the first call.
+    pub fn mark_failures(&mut self, now: SystemTime, timeout: Duration) {
+        for node in &mut self.nodes[1..] {
+            let seen = *self.last_seen.entry(node.id.clone()).or_insert(now);
+            let failed = now.duration_since(seen).unwrap_or_default() > timeout;
+            if failed && !node.failed {
+                warn!("Marking node {} as failing", node.id);
+            }
+            if failed != node.failed {
+                node.failed = failed;
+                self.dirty = true;
+            }
+        }
+    }
+
+    /// Marks a slot of this node as moving to the node with the ID. Keys that are gone from
+    /// this node are looked up on the target with an ASK redirect.
+    pub fn set_migrating(&mut self, slot: u16, target: &str) -> Result<(), SlotError> {
+        if self.slots[slot as usize] != Some(0) {
+            return Err(SlotError::NotOwner(slot));
+        }
+        let index = self.other_node(slot, target)?;
+        self.migrating.insert(slot, index);
+        Ok(())
+    }
+
+    /// Marks a slot as moving from the node with the ID to this node, which serves it to
+    /// clients that sent ASKING.
+    pub fn set_importing(&mut self, slot: u16, source: &str) -> Result<(), SlotError> {
+        if self.slots[slot as usize] == Some(0) {
+            return Err(SlotError::AlreadyOwner(slot));
+        }
+        let index = self.other_node(slot, source)?;
+        self.importing.insert(slot, index);
+        Ok(())
+    }
+
+    /// Makes the node with the ID the owner of the slot, ending its migration. This node
+    /// can only give a slot away once `keys` of it are left here.
+    ///
+    /// Taking over an importing slot bumps the config epoch of this node, so its claim wins
+    /// over that of the node it was taken from.
+    pub fn set_owner(&mut self, slot: u16, id: &str, keys: usize) -> Result<(), SlotError> {
+        let index = self
+            .nodes
+            .iter()
+            .position(|node| node.id == id)
+            .ok_or_else(|| SlotError::UnknownNode(id.to_string()))?;
+        if self.slots[slot as usize] == Some(0) && index != 0 && keys > 0 {
+            return Err(SlotError::HoldingKeys(slot));
+        }
+        if index != 0 {
+            self.migrating.remove(&slot);
+        } else if self.importing.remove(&slot).is_some() {
+            self.current_epoch += 1;
+            self.nodes[0].config_epoch = self.current_epoch;
+            info!(
+                "Took over slot {slot}, config epoch bumped to {}",
+                self.current_epoch
+            );
+        }
+        self.slots[slot as usize] = Some(index);
+        self.dirty = true;
+        Ok(())
+    }
+
+    /// Returns the index of another known node a slot moves to or from.
+    fn other_node(&self, slot: u16, id: &str) -> Result<usize, SlotError> {
+        match self.nodes.iter().position(|node| node.id == id) {
+            Some(0) => Err(SlotError::Myself(slot)),
+            Some(index) => Ok(index),
+            None => Err(SlotError::UnknownNode(id.to_string())),
+        }
+    }
+
+    /// Ends the migration of a slot in either direction.
+    pub fn set_stable(&mut self, slot: u16) {
+        self.migrating.remove(&slot);
+        self.importing.remove(&slot);
+    }
+
+    /// Returns whether the cluster is up: no slot is served by a failed node, and with
+    /// `require_full_coverage` every slot is served.
+    pub fn is_ok(&self, require_full_coverage: bool) -> bool {
+        self.slots.iter().all(|owner| match owner {
+            Some(owner) => !self.nodes[*owner].failed,
+            None => !require_full_coverage,
+        })
+    }
+
+    /// Checks that this node can run a command on the keys, `asking` being whether the
+    /// client sent ASKING right before it. `exists` tells whether a key is in the store.
+    /// No keys are served while the cluster is down, see `is_ok`.
+    pub fn route(
+        &self,
+        keys: &[&[u8]],
+        asking: bool,
+        require_full_coverage: bool,
+        exists: impl Fn(&[u8]) -> bool,
+    ) -> Result<(), RedirectError> {
+        let Some((first, rest)) = keys.split_first() else {
+            return Ok(());
+        };
+        let slot = key_hash_slot(first);
+        if rest.iter().any(|key| key_hash_slot(key) != slot) {
+            return Err(RedirectError::CrossSlot);
+        }
+        if !self.is_ok(require_full_coverage) {
+            return Err(RedirectError::Down);
+        }
+        if asking && self.importing.contains_key(&slot) {
+            return Ok(());
+        }
+
+        match self.slots[slot as usize] {
+            None => Err(RedirectError::Unserved),
+            Some(0) => match self.migrating.get(&slot) {
+                Some(&target) => {
+                    let missing = keys.iter().filter(|key| !exists(key)).count();
+                    if missing == 0 {
+                        Ok(())
+                    } else if missing == keys.len() {
+                        Err(RedirectError::Ask {
+                            slot,
+                            addr: self.nodes[target].addr(),
+                        })
+                    } else {
+                        Err(RedirectError::TryAgain)
+                    }
+                }
+                None => Ok(()),
+            },
+            Some(owner) => Err(RedirectError::Moved {
+                slot,
+                addr: self.nodes[owner].addr(),
+            }),
+        }
+    }
+
+    /// Returns the index of the node in `nodes`, adding it if it is new.
+    fn node_index(&mut self, node: ClusterNode) -> usize {
+        match self.nodes.iter().position(|n| n.id == node.id) {
+            Some(index) => index,
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        }
+    }
+
+    /// Returns every range of slots with an owner, with the owner.
+    pub fn slot_ranges(&self) -> Vec<(SlotRange, &ClusterNode)> {
+        let mut ranges: Vec<(SlotRange, usize)> = vec![];
+        for (slot, owner) in self.slots.iter().enumerate() {
+            let Some(owner) = *owner else {
+                continue;
+            };
+            let slot = slot as u16;
+            match ranges.last_mut() {
+                Some((range, last)) if *last == owner && *range.end() + 1 == slot => {
+                    *range = *range.start()..=slot;
+                }
+                _ => ranges.push((slot..=slot, owner)),
+            }
+        }
+        ranges
+            .into_iter()
+            .map(|(range, owner)| (range, &self.nodes[owner]))
+            .collect()
+    }
+
+    /// Returns the slot ranges of every node, in the order of `nodes`. Nodes without slots
+    /// have no ranges.
+    pub fn shards(&self) -> Vec<(&ClusterNode, Vec<SlotRange>)> {
+        let ranges = self.slot_ranges();
+        self.nodes
+            .iter()
+            .map(|node| {
+                let owned = ranges
+                    .iter()
+                    .filter(|(_, owner)| owner.id == node.id)
+                    .map(|(range, _)| range.clone())
+                    .collect();
+                (node, owned)
+            })
+            .collect()
+    }
+
+    /// Returns the lines of CLUSTER INFO, the state depending on `require_full_coverage`
+    /// as in `is_ok`.
+    pub fn info(&self, require_full_coverage: bool) -> Vec<String> {
+        let assigned = self.slots.iter().filter(|owner| owner.is_some()).count();
+        let failed = self
+            .slots
+            .iter()
+            .filter(|owner| owner.is_some_and(|owner| self.nodes[owner].failed))
+            .count();
+        let size = self.shards().iter().filter(|(_, r)| !r.is_empty()).count();
+        let state = if self.is_ok(require_full_coverage) {
+            "ok"
+        } else {
+            "fail"
+        };
+
+        vec![
+            format!("cluster_state:{state}"),
+            format!("cluster_slots_assigned:{assigned}"),
+            format!("cluster_slots_ok:{}", assigned - failed),
+            "cluster_slots_pfail:0".to_string(),
+            format!("cluster_slots_fail:{failed}"),
+            format!("cluster_known_nodes:{}", self.nodes.len()),
+            format!("cluster_size:{size}"),
+            format!("cluster_current_epoch:{}", self.current_epoch),
+            format!("cluster_my_epoch:{}", self.myself().config_epoch),
+        ]
+    }
+
+    /// Returns a line for every node as in CLUSTER NODES, this node first.
+    pub fn node_lines(&self) -> Vec<String> {
+        self.shards()
+            .into_iter()
+            .enumerate()
+            .map(|(i, (node, ranges))| {
+                let flags = match (i, node.failed) {
+                    (0, _) => "myself,master",
+                    (_, true) => "master,fail",
+                    (_, false) => "master",
+                };
+                let pong = self
+                    .last_seen
+                    .get(&node.id)
+                    .and_then(|seen| seen.duration_since(UNIX_EPOCH).ok())
+                    .map(|seen| seen.as_millis())
+                    .unwrap_or(0);
+                let link = if node.failed {
+                    "disconnected"
+                } else {
+                    "connected"
+                };
+                let mut line = format!(
+                    "{} {}:{}@{} {flags} - 0 {pong} {} {link}",
+                    node.id, node.ip, node.port, node.cport, node.config_epoch
+                );
+                for range in ranges {
+                    match (range.start(), range.end()) {
+                        (start, end) if start == end => line.push_str(&format!(" {start}")),
+                        (start, end) => line.push_str(&format!(" {start}-{end}")),
+                    }
+                }
+                line
+            })
+            .collect()
+    }
+}
+
+/// Parses a slot, or a range of slots such as `0-8191`.
+fn parse_slot_range(s: &str) -> Option<SlotRange> {
+    let (start, end) = s.split_once('-').unwrap_or((s, s));
+    let (start, end) = (start.parse().ok()?, end.parse().ok()?);
+    (start <= end && (end as usize) < SLOT_COUNT).then_some(start..=end)
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    fn node(port: u16) -> ClusterNode {
+        ClusterNode::new(
+            IpAddr::V4(Ipv4Addr::LOCALHOST),
+            port,
+            port + BUS_PORT_OFFSET,
+        )
+    }
+
+    #[test]
+    fn crc16_check_value() {
+        assert_eq!(crc16(b"123456789"), 0x31c3);
+    }
+
+    #[test]
+    fn hash_slots() {
+        assert_eq!(key_hash_slot(b"foo"), 12182);
+        assert_eq!(key_hash_slot(b"somekey"), 11058);
+        assert_eq!(key_hash_slot(b""), 0);
+
+        // Only the tag is hashed.
+        assert_eq!(key_hash_slot(b"{user1000}.following"), 3443);
+        assert_eq!(
+            key_hash_slot(b"{user1000}.followers"),
+            key_hash_slot(b"user1000")
+        );
+        // Only the first tag counts.
+        assert_eq!(key_hash_slot(b"foo{bar}{zap}"), key_hash_slot(b"bar"));
+        // Empty or unclosed tags hash the whole key.
+        assert_eq!(key_hash_slot(b"foo{}{bar}"), crc16(b"foo{}{bar}") % 16384);
+        assert_eq!(key_hash_slot(b"foo{bar"), crc16(b"foo{bar") % 16384);
+        assert_eq!(key_hash_slot(b"{{bar}}zap"), key_hash_slot(b"{bar"));
+    }
+
+    #[test]
+    fn node_ids_are_hex() {
+        let id = node(7000).id;
+        assert_eq!(id.len(), NODE_ID_LEN);
+        assert!(id.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_ne!(id, node(7000).id);
+    }
+
+    fn all_slots() -> Vec<u16> {
+        (0..SLOT_COUNT as u16).collect()
+    }
+
+    #[test]
+    fn slot_ranges_and_shards() {
+        let myself = node(7000);
+        let mut cluster = ClusterState::new(myself.clone());
+        assert_eq!(cluster.slot_ranges(), vec![]);
+        assert!(cluster
+            .info(true)
+            .contains(&"cluster_state:fail".to_string()));
+        cluster.add_slots(&all_slots()).unwrap();
+        assert_eq!(cluster.slot_ranges(), vec![(0..=16383, &myself)]);
+        assert!(cluster.info(true).contains(&"cluster_state:ok".to_string()));
+
+        let other = node(7001);
+        cluster.assign(100..=199, other.clone());
+        assert_eq!(cluster.owner(150), Some(&other));
+        assert_eq!(cluster.owner(200), Some(&myself));
+        assert_eq!(
+            cluster.slot_ranges(),
+            vec![
+                (0..=99, &myself),
+                (100..=199, &other),
+                (200..=16383, &myself)
+            ]
+        );
+        assert_eq!(
+            cluster.shards(),
+            vec![
+                (&myself, vec![0..=99, 200..=16383]),
+                (&other, vec![100..=199])
+            ]
+        );
+        assert!(cluster
+            .info(true)
+            .contains(&"cluster_known_nodes:2".to_string()));
+        assert!(cluster.info(true).contains(&"cluster_size:2".to_string()));
+    }
+
+    #[test]
+    fn route_redirects() {
+        let mut cluster = ClusterState::new(node(7000));
+        let other = node(7001);
+        let (foo, bar) = (key_hash_slot(b"foo"), key_hash_slot(b"bar"));
+        cluster.add_slots(&all_slots()).unwrap();
+        cluster.assign(foo..=foo, other.clone());
+        let exists = |_: &[u8]| true;
+
+        assert_eq!(cluster.route(&[], false, true, exists), Ok(()));
+        assert_eq!(cluster.route(&[b"bar"], false, true, exists), Ok(()));
+        assert_eq!(
+            cluster.route(&[b"foo"], false, true, exists),
+            Err(RedirectError::Moved {
+                slot: foo,
+                addr: other.addr()
+            })
+        );
+        assert_eq!(
+            cluster
+                .route(&[b"foo"], false, true, exists)
+                .unwrap_err()
+                .to_string(),
+            "12182 127.0.0.1:7001"
+        );
+        assert_eq!(
+            cluster.route(&[b"bar", b"foo"], false, true, exists),
+            Err(RedirectError::CrossSlot)
+        );
+        assert_eq!(
+            cluster.route(&[b"{bar}.a", b"{bar}.b"], false, true, exists),
+            Ok(())
+        );
+
+        // Importing slots are only served after ASKING.
+        cluster.set_importing(foo, &other.id).unwrap();
+        assert!(cluster.route(&[b"foo"], false, true, exists).is_err());
+        assert_eq!(cluster.route(&[b"foo"], true, true, exists), Ok(()));
+
+        // Keys of migrating slots are only served while they are still here.
+        cluster.set_migrating(bar, &other.id).unwrap();
+        let exists = |key: &[u8]| key == b"{bar}.here";
+        assert_eq!(cluster.route(&[b"{bar}.here"], false, true, exists), Ok(()));
+        assert_eq!(
+            cluster.route(&[b"{bar}.gone"], false, true, exists),
+            Err(RedirectError::Ask {
+                slot: bar,
+                addr: other.addr()
+            })
+        );
+        assert_eq!(
+            cluster.route(&[b"{bar}.here", b"{bar}.gone"], false, true, exists),
+            Err(RedirectError::TryAgain)
+        );
+        cluster.set_stable(bar);
+        assert_eq!(cluster.route(&[b"{bar}.gone"], false, true, exists), Ok(()));
+    }
+
+    #[test]
+    fn route_refuses_keys_while_down() {
+        let mut cluster = ClusterState::new(node(7000));
+        let exists = |_: &[u8]| true;
+        let slot = key_hash_slot(b"foo");
+        cluster.add_slots(&[slot]).unwrap();
+
+        assert_eq!(
+            cluster.route(&[b"foo"], false, true, exists),
+            Err(RedirectError::Down)
+        );
+        assert_eq!(cluster.route(&[], false, true, exists), Ok(()));
+        assert!(cluster
+            .info(false)
+            .contains(&"cluster_state:ok".to_string()));
+        assert_eq!(cluster.route(&[b"foo"], false, false, exists), Ok(()));
+        assert_eq!(
+            cluster.route(&[b"bar"], false, false, exists),
+            Err(RedirectError::Unserved)
+        );
+    }
+
+    #[test]
+    fn add_del_and_set_slots() {
+        let myself = node(7000);
+        let other = node(7001);
+        let mut cluster = ClusterState::new(myself.clone());
+        cluster.add_slots(&[0, 1, 2]).unwrap();
+        assert_eq!(cluster.owner(1), Some(&myself));
+        assert_eq!(cluster.add_slots(&[3, 2]), Err(SlotError::Busy(2)));
+        assert_eq!(cluster.add_slots(&[3, 3]), Err(SlotError::Repeated(3)));
+        assert_eq!(cluster.owner(3), None);
+        cluster.del_slots(&[2]).unwrap();
+        assert_eq!(cluster.owner(2), None);
+        assert_eq!(cluster.del_slots(&[2]), Err(SlotError::Unassigned(2)));
+
+        // Slots only move between known nodes.
+        assert_eq!(
+            cluster.set_migrating(0, &other.id),
+            Err(SlotError::UnknownNode(other.id.clone()))
+        );
+        cluster.assign(10..=10, other.clone());
+        assert_eq!(
+            cluster.set_migrating(0, &myself.id),
+            Err(SlotError::Myself(0))
+        );
+        assert_eq!(
+            cluster.set_migrating(10, &other.id),
+            Err(SlotError::NotOwner(10))
+        );
+        assert_eq!(
+            cluster.set_importing(0, &other.id),
+            Err(SlotError::AlreadyOwner(0))
+        );
+
+        // Giving a slot away needs its keys to be gone first.
+        cluster.set_migrating(0, &other.id).unwrap();
+        assert_eq!(
+            cluster.set_owner(0, &other.id, 1),
+            Err(SlotError::HoldingKeys(0))
+        );
+        cluster.set_owner(0, &other.id, 0).unwrap();
+        assert_eq!(cluster.owner(0), Some(&other));
+        assert!(cluster.migrating.is_empty());
+
+        // Taking one over bumps the config epoch.
+        cluster.set_importing(10, &other.id).unwrap();
+        cluster.set_owner(10, &myself.id, 0).unwrap();
+        assert_eq!(cluster.owner(10).unwrap().id, myself.id);
+        assert!(cluster.importing.is_empty());
+        assert_eq!(cluster.myself().config_epoch, 1);
+        assert_eq!(cluster.current_epoch(), 1);
+    }
+
+    #[test]
+    fn config_round_trip() {
+        let mut cluster = ClusterState::new(node(7000));
+        let other = node(7001);
+        cluster.assign(100..=199, other.clone());
+        cluster.assign(300..=300, other.clone());
+        cluster.current_epoch = 3;
+
+        let loaded = ClusterState::from_config(&cluster.config()).expect("Load config");
+        assert_eq!(loaded.nodes(), cluster.nodes());
+        assert_eq!(loaded.slot_ranges(), cluster.slot_ranges());
+        assert_eq!(loaded.current_epoch(), 3);
+
+        assert!(matches!(
+            ClusterState::from_config("not a node\n"),
+            Err(ClusterError::InvalidConfig(1))
+        ));
+    }
+
+    #[test]
+    fn conflicting_claims_converge() {
+        let now = SystemTime::now();
+        let ip = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let mut a = ClusterState::new(node(7000));
+        let mut b = ClusterState::new(node(7001));
+        a.add_slots(&(0..=99).collect::<Vec<_>>()).unwrap();
+        b.add_slots(&(50..=149).collect::<Vec<_>>()).unwrap();
+
+        b.receive(&a.message(MessageKind::Meet), ip, now);
+        a.receive(&b.message(MessageKind::Pong), ip, now);
+        for _ in 0..2 {
+            b.receive(&a.message(MessageKind::Ping), ip, now);
+            a.receive(&b.message(MessageKind::Pong), ip, now);
+        }
+
+        // The node with the smaller ID moved to a new epoch, winning the shared slots.
+        let (a_id, b_id) = (a.myself().id.clone(), b.myself().id.clone());
+        let winner = if a_id < b_id { &a_id } else { &b_id };
+        assert_ne!(a.myself().config_epoch, b.myself().config_epoch);
+        assert_eq!(a.current_epoch(), 1);
+        assert_eq!(b.current_epoch(), 1);
+        for (slots, owner) in [(0..=49, &a_id), (50..=99, winner), (100..=149, &b_id)] {
+            for slot in slots {
+                assert_eq!(&a.owner(slot).unwrap().id, owner);
+                assert_eq!(&b.owner(slot).unwrap().id, owner);
+            }
+        }
+    }
+
+    #[test]
+    fn receive_and_mark_failures() {
+        let now = SystemTime::now();
+        let timeout = Duration::from_secs(15);
+        // This node has the greater ID, so it keeps its epoch on a collision.
+        let mut cluster = ClusterState::new(ClusterNode {
+            id: "b".repeat(NODE_ID_LEN),
+            ..node(7000)
+        });
+        cluster.add_slots(&all_slots()).unwrap();
+        let mut other = ClusterNode {
+            id: "a".repeat(NODE_ID_LEN),
+            ..node(7001)
+        };
+        let stranger = node(7002);
+        let mut msg = Message {
+            kind: MessageKind::Ping,
+            current_epoch: 2,
+            sender: other.clone(),
+            slots: vec![0..=99],
+            gossip: vec![stranger.clone()],
+        };
+
+        // Pings of unknown nodes are ignored, a MEET adds the sender and its gossip.
+        cluster.receive(&msg, IpAddr::V4(Ipv4Addr::LOCALHOST), now);
+        assert_eq!(cluster.nodes().len(), 1);
+        msg.kind = MessageKind::Meet;
+        cluster.receive(&msg, IpAddr::V4(Ipv4Addr::LOCALHOST), now);
+        assert_eq!(cluster.nodes().len(), 3);
+        assert_eq!(cluster.current_epoch(), 2);
+        // Both claim the slots with the same config epoch, so they stay here.
+        assert_eq!(cluster.owner(0).unwrap().id, cluster.myself().id);
+        assert_eq!(cluster.myself().config_epoch, 0);
+
+        other.config_epoch = 1;
+        msg.sender = other.clone();
+        cluster.receive(&msg, IpAddr::V4(Ipv4Addr::LOCALHOST), now);
+        assert_eq!(cluster.owner(0), Some(&other));
+        assert_eq!(cluster.owner(100).unwrap().id, cluster.myself().id);
+
+        // Slots the sender stops claiming are left without an owner.
+        msg.slots = vec![0..=49];
+        cluster.receive(&msg, IpAddr::V4(Ipv4Addr::LOCALHOST), now);
+        assert_eq!(cluster.owner(50), None);
+
+        cluster.mark_failures(now, timeout);
+        assert!(cluster.nodes().iter().all(|node| !node.failed));
+        let later = now + timeout + Duration::from_secs(1);
+        cluster.mark_failures(later, timeout);
+        assert!(cluster.nodes()[1..].iter().all(|node| node.failed));
+        assert!(cluster
+            .info(true)
+            .contains(&"cluster_state:fail".to_string()));
+
+        // Hearing from a node again clears its failure.
+        cluster.receive(&msg, IpAddr::V4(Ipv4Addr::LOCALHOST), later);
+        assert!(!cluster.nodes()[1].failed);
+    }
+}
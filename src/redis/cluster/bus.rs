@@ -0,0 +1,428 @@
+//! The cluster bus, over which nodes ping each other on a separate port to find out which
+//! nodes are up and which slots they serve.
+//!
+//! Messages are RESP arrays, each PING or MEET answered with a PONG:
+//!
+//! ```text
+//! [kind, current epoch, sender, [slot range start, end, ...], [node, ...]]
+//! ```
+//!
+//! where every node is `[id, ip, port, bus port, config epoch, "ok" | "fail"]`.
+
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use parking_lot::RwLock;
+use thiserror::Error;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tracing::{debug, error};
+
+use super::super::super::util;
+use super::super::clock::Clock;
+use super::super::config::ServerConfig;
+use super::super::resp::{BulkString, Integer, Value};
+use super::super::session::{Session, SessionError};
+use super::{ClusterNode, ClusterState, SlotRange};
+
+/// How often the bus looks for new nodes, failed nodes and changes to save.
+const CRON_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Most time between two pings of the same node.
+const MAX_PING_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Error)]
+pub enum BusError {
+    #[error("Invalid cluster bus message")]
+    InvalidMessage,
+
+    #[error("Timed out talking to {0}")]
+    Timeout(SocketAddr),
+
+    #[error(transparent)]
+    Session(#[from] SessionError),
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageKind {
+    Ping,
+    Pong,
+    /// A PING that also asks the receiver to add the sender to its nodes.
+    Meet,
+}
+
+impl MessageKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Ping => "PING",
+            Self::Pong => "PONG",
+            Self::Meet => "MEET",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    pub kind: MessageKind,
+    pub current_epoch: u64,
+    pub sender: ClusterNode,
+
+    /// Slots the sender serves.
+    pub slots: Vec<SlotRange>,
+
+    /// Other nodes the sender knows.
+    pub gossip: Vec<ClusterNode>,
+}
+
+impl From<&Message> for Value {
+    fn from(msg: &Message) -> Self {
+        let slots = msg
+            .slots
+            .iter()
+            .flat_map(|range| [integer(*range.start() as u64), integer(*range.end() as u64)])
+            .collect::<Vec<_>>();
+        let gossip = msg.gossip.iter().map(node_value).collect::<Vec<_>>();
+        let v = vec![
+            Value::BulkString(msg.kind.as_str().into()),
+            integer(msg.current_epoch),
+            node_value(&msg.sender),
+            Value::Array(slots.into()),
+            Value::Array(gossip.into()),
+        ];
+        Value::Array(v.into())
+    }
+}
+
+impl TryFrom<Value> for Message {
+    type Error = BusError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        let values = array(&value)?;
+        let [kind, current_epoch, sender, slots, gossip] = values else {
+            return Err(BusError::InvalidMessage);
+        };
+        let kind = match string(kind)?.as_str() {
+            "PING" => MessageKind::Ping,
+            "PONG" => MessageKind::Pong,
+            "MEET" => MessageKind::Meet,
+            _ => return Err(BusError::InvalidMessage),
+        };
+        let slots = array(slots)?
+            .chunks(2)
+            .map(|range| match range {
+                [start, end] => {
+                    let (start, end) = (number(start)?, number(end)?);
+                    let valid = start <= end && (end as usize) < super::SLOT_COUNT;
+                    valid
+                        .then_some(start as u16..=end as u16)
+                        .ok_or(BusError::InvalidMessage)
+                }
+                _ => Err(BusError::InvalidMessage),
+            })
+            .collect::<Result<_, _>>()?;
+        let gossip = array(gossip)?
+            .iter()
+            .map(parse_node)
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            kind,
+            current_epoch: number(current_epoch)?,
+            sender: parse_node(sender)?,
+            slots,
+            gossip,
+        })
+    }
+}
+
+fn node_value(node: &ClusterNode) -> Value {
+    let v = vec![
+        Value::BulkString(node.id.as_str().into()),
+        Value::BulkString(node.ip.to_string().into()),
+        integer(node.port as u64),
+        integer(node.cport as u64),
+        integer(node.config_epoch),
+        Value::BulkString(if node.failed { "fail" } else { "ok" }.into()),
+    ];
+    Value::Array(v.into())
+}
+
+fn parse_node(value: &Value) -> Result<ClusterNode, BusError> {
+    let [id, ip, port, cport, config_epoch, health] = array(value)? else {
+        return Err(BusError::InvalidMessage);
+    };
+    let to_port = |value| u16::try_from(number(value)?).map_err(|_| BusError::InvalidMessage);
+
+    Ok(ClusterNode {
+        id: string(id)?,
+        ip: string(ip)?.parse().map_err(|_| BusError::InvalidMessage)?,
+        port: to_port(port)?,
+        cport: to_port(cport)?,
+        config_epoch: number(config_epoch)?,
+        failed: string(health)? == "fail",
+    })
+}
+
+fn array(value: &Value) -> Result<&[Value], BusError> {
+    value
+        .array()
+        .and_then(|array| array.values())
+        .ok_or(BusError::InvalidMessage)
+}
+
+fn string(value: &Value) -> Result<String, BusError> {
+    value
+        .bulk_string()
+        .and_then(BulkString::as_str)
+        .ok_or(BusError::InvalidMessage)
+}
+
+fn number(value: &Value) -> Result<u64, BusError> {
+    match value {
+        Value::Integer(i) if i.as_int() >= 0 => Ok(i.as_int() as u64),
+        _ => Err(BusError::InvalidMessage),
+    }
+}
+
+fn integer(i: u64) -> Value {
+    Value::Integer(Integer::new(i as i64))
+}
+
+/// Keeps this node in touch with the rest of the cluster: answers the pings of other
+/// nodes, pings every known node, marks nodes that stop answering as failed, and saves
+/// the cluster config whenever it changes.
+#[derive(Debug, Clone)]
+pub struct ClusterBus {
+    cluster: Arc<RwLock<ClusterState>>,
+    config: Arc<ServerConfig>,
+    clock: Arc<dyn Clock>,
+
+    /// File the nodes and slots are saved to.
+    config_file: PathBuf,
+}
+
+impl ClusterBus {
+    pub fn new(
+        cluster: Arc<RwLock<ClusterState>>,
+        config: Arc<ServerConfig>,
+        clock: Arc<dyn Clock>,
+        config_file: PathBuf,
+    ) -> Self {
+        Self {
+            cluster,
+            config,
+            clock,
+            config_file,
+        }
+    }
+
+    /// Serves the bus on the listener until `stop_rx` changes.
+    pub async fn run(self, listener: TcpListener, mut stop_rx: watch::Receiver<bool>) {
+        util::spawn_named(
+            "cluster-bus-accept",
+            self.clone().accept_loop(listener, stop_rx.clone()),
+        );
+
+        // Every other node gets a link, a task pinging it for as long as the server runs.
+        let mut links: HashMap<String, JoinHandle<()>> = HashMap::new();
+        let mut interval = tokio::time::interval(CRON_INTERVAL);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => self.cron(&mut links),
+                _ = stop_rx.changed() => break,
+            }
+        }
+        for link in links.values() {
+            link.abort();
+        }
+    }
+
+    fn cron(&self, links: &mut HashMap<String, JoinHandle<()>>) {
+        let (meets, peers, config) = {
+            let mut cluster = self.cluster();
+            cluster.mark_failures(self.clock.now(), self.node_timeout());
+            let peers: Vec<String> = cluster.nodes()[1..]
+                .iter()
+                .map(|node| node.id.clone())
+                .collect();
+            let config = cluster.take_dirty().then(|| cluster.config());
+            (cluster.take_meets(), peers, config)
+        };
+
+        if let Some(config) = config {
+            if let Err(e) = ClusterState::save(&config, &self.config_file) {
+                error!("Save cluster config error: {e}");
+            }
+        }
+        for addr in meets {
+            let bus = self.clone();
+            util::spawn_named("cluster-meet", async move {
+                if let Err(e) = bus.meet(addr).await {
+                    error!("Meet node at {addr} error: {e}");
+                }
+            });
+        }
+        links.retain(|_, link| !link.is_finished());
+        for id in peers {
+            links
+                .entry(id.clone())
+                .or_insert_with(|| util::spawn_named("cluster-link", self.clone().link(id)));
+        }
+    }
+
+    async fn accept_loop(self, listener: TcpListener, mut stop_rx: watch::Receiver<bool>) {
+        loop {
+            let (stream, addr) = tokio::select! {
+                accepted = listener.accept() => match accepted {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        error!("Accept cluster bus connection error: {e}");
+                        continue;
+                    }
+                },
+                _ = stop_rx.changed() => return,
+            };
+            let bus = self.clone();
+            let stop_rx = stop_rx.clone();
+            util::spawn_named("cluster-bus-connection", async move {
+                if let Err(e) = bus.serve(stream, addr.ip(), stop_rx).await {
+                    debug!("Cluster bus connection from {addr} closed: {e}");
+                }
+            });
+        }
+    }
+
+    /// Answers the messages of another node with PONGs.
+    async fn serve(
+        &self,
+        stream: TcpStream,
+        peer_ip: IpAddr,
+        mut stop_rx: watch::Receiver<bool>,
+    ) -> Result<(), BusError> {
+        let mut session = Session::new(stream);
+        loop {
+            // Read like responses, since messages can span several reads.
+            let value = tokio::select! {
+                received = session.receive_response() => Value::from(received?),
+                _ = stop_rx.changed() => return Ok(()),
+            };
+            let msg = Message::try_from(value)?;
+            let pong = {
+                let mut cluster = self.cluster();
+                cluster.receive(&msg, peer_ip, self.clock.now());
+                cluster.message(MessageKind::Pong)
+            };
+            session.send_response(Value::from(&pong).into()).await?;
+        }
+    }
+
+    /// Pings the node with the ID until it is forgotten, reconnecting whenever the link
+    /// breaks.
+    async fn link(self, id: String) {
+        loop {
+            let addr = {
+                let cluster = self.cluster();
+                match cluster.nodes().iter().find(|node| node.id == id) {
+                    Some(node) => node.bus_addr(),
+                    None => return,
+                }
+            };
+            if let Err(e) = self.ping_loop(addr).await {
+                debug!("Cluster bus link to {addr} broke: {e}");
+            }
+            tokio::time::sleep(self.ping_interval()).await;
+        }
+    }
+
+    async fn ping_loop(&self, addr: SocketAddr) -> Result<(), BusError> {
+        let mut session = self.connect(addr).await?;
+        loop {
+            self.exchange(&mut session, addr, MessageKind::Ping).await?;
+            tokio::time::sleep(self.ping_interval()).await;
+        }
+    }
+
+    /// Introduces this node to the node with the bus address, which adds it to its nodes
+    /// and answers with its own, adding it to ours.
+    async fn meet(&self, addr: SocketAddr) -> Result<(), BusError> {
+        let mut session = self.connect(addr).await?;
+        self.exchange(&mut session, addr, MessageKind::Meet).await
+    }
+
+    async fn connect(&self, addr: SocketAddr) -> Result<Session, BusError> {
+        let stream = tokio::time::timeout(self.node_timeout(), TcpStream::connect(addr))
+            .await
+            .map_err(|_| BusError::Timeout(addr))??;
+        Ok(Session::new(stream))
+    }
+
+    /// Sends a message about this node and updates the cluster with the PONG.
+    async fn exchange(
+        &self,
+        session: &mut Session,
+        addr: SocketAddr,
+        kind: MessageKind,
+    ) -> Result<(), BusError> {
+        let msg = self.cluster().message(kind);
+        let pong = tokio::time::timeout(
+            self.node_timeout(),
+            session.send_request_and_wait_reply(Value::from(&msg).into()),
+        )
+        .await
+        .map_err(|_| BusError::Timeout(addr))??;
+        let pong = Message::try_from(Value::from(pong))?;
+        if pong.kind != MessageKind::Pong {
+            return Err(BusError::InvalidMessage);
+        }
+        self.cluster().receive(&pong, addr.ip(), self.clock.now());
+        Ok(())
+    }
+
+    fn cluster(&self) -> parking_lot::RwLockWriteGuard<'_, ClusterState> {
+        self.cluster.write()
+    }
+
+    fn node_timeout(&self) -> Duration {
+        Duration::from_millis(self.config.read().cluster_node_timeout)
+    }
+
+    /// Nodes are pinged often enough to be pinged twice within the node timeout.
+    fn ping_interval(&self) -> Duration {
+        (self.node_timeout() / 2).min(MAX_PING_INTERVAL)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    #[test]
+    fn message_round_trip() {
+        let localhost = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let mut failed = ClusterNode::new(localhost, 7001, 17001);
+        failed.failed = true;
+        let msg = Message {
+            kind: MessageKind::Meet,
+            current_epoch: 3,
+            sender: ClusterNode::new(localhost, 7000, 17000),
+            slots: vec![0..=99, 200..=200],
+            gossip: vec![failed],
+        };
+
+        let value = Value::from(&msg);
+        assert_eq!(Message::try_from(value).expect("Parse error"), msg);
+        assert!(matches!(
+            Message::try_from(Value::BulkString("PING".into())),
+            Err(BusError::InvalidMessage)
+        ));
+    }
+}
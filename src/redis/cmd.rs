@@ -8,16 +8,119 @@ pub mod get;
 pub use get::*;
 pub mod info;
 pub use info::*;
+#[cfg(feature = "replication")]
 pub mod replconf;
+#[cfg(feature = "replication")]
 pub use replconf::*;
+#[cfg(feature = "replication")]
+pub mod psync;
+#[cfg(feature = "replication")]
+pub use psync::*;
+#[cfg(feature = "replication")]
+pub mod wait;
+#[cfg(feature = "replication")]
+pub use wait::*;
+#[cfg(feature = "replication")]
+pub mod failover;
+#[cfg(feature = "replication")]
+pub use failover::*;
+#[cfg(feature = "replication")]
+pub mod replicaof;
+#[cfg(feature = "replication")]
+pub use replicaof::*;
+#[cfg(feature = "persistence")]
+pub mod save;
+#[cfg(feature = "persistence")]
+pub use save::*;
+#[cfg(feature = "persistence")]
+pub mod bgsave;
+#[cfg(feature = "persistence")]
+pub use bgsave::*;
+pub mod config;
+pub use config::*;
+pub mod acl;
+pub use acl::*;
+pub mod auth;
+pub use auth::*;
+pub mod hello;
+pub use hello::*;
+pub mod object;
+pub use object::*;
+pub mod memory;
+pub use memory::*;
+pub mod debug;
+pub use debug::*;
+pub mod command;
+pub use command::*;
+pub mod client;
+pub use client::*;
+pub mod cluster;
+pub use cluster::*;
+pub mod asking;
+pub use asking::*;
+pub mod keys;
+pub use keys::*;
+pub mod pttl;
+pub use pttl::*;
+pub mod r#type;
+pub use r#type::*;
+pub mod incr;
+pub use incr::*;
+pub mod append;
+pub use append::*;
+pub mod strlen;
+pub use strlen::*;
+pub mod getrange;
+pub use getrange::*;
+pub mod setrange;
+pub use setrange::*;
+pub mod del;
+pub use del::*;
+pub mod exists;
+pub use exists::*;
+pub mod expire;
+pub use expire::*;
+pub mod mget;
+pub use mget::*;
+pub mod mset;
+pub use mset::*;
+pub mod list;
+pub use list::*;
+pub mod blpop;
+pub use blpop::*;
+pub mod hash;
+pub use hash::*;
+pub mod sets;
+pub use sets::*;
+pub mod zset;
+pub use zset::*;
+pub mod streams;
+pub use streams::*;
+#[cfg(feature = "scripting")]
+pub mod eval;
+#[cfg(feature = "scripting")]
+pub use eval::*;
+#[cfg(feature = "scripting")]
+pub mod function;
+#[cfg(feature = "scripting")]
+pub use function::*;
+pub mod table;
 
 use thiserror::Error;
 
+use self::table::CommandSpec;
+use super::plugin::PluginArg;
 use super::resp::{Array, BulkString, DecodeError, Value};
 
 fn bulk_string_to_uint64(bs: &BulkString) -> Result<u64, ParseCommandError> {
     let s = bulk_string_to_string(bs)?;
-    Ok(s.parse::<u64>().map_err(|e| DecodeError::ParseInt(e))?)
+    Ok(s.parse::<u64>().map_err(DecodeError::ParseInt)?)
+}
+
+fn bulk_string_to_int64(bs: &BulkString) -> Result<i64, ParseCommandError> {
+    bs.as_str()
+        .and_then(|s| s.parse().ok())
+        .ok_or(ParseCommandError::NotInteger)
 }
 
 fn bulk_string_to_string(bs: &BulkString) -> Result<String, ParseCommandError> {
@@ -62,6 +165,21 @@ fn consume_args_from_iter(
     }
 }
 
+/// Like `consume_args_from_iter`, but takes every remaining arg, of which there must be at
+/// least `necessary`.
+fn consume_variadic_args_from_iter(
+    iter: &mut std::slice::Iter<'_, Value>,
+    necessary: usize,
+) -> Result<Vec<BulkString>, ParseCommandError> {
+    let args = iter
+        .map(value_to_bulk_string)
+        .collect::<Result<Vec<_>, _>>()?;
+    if args.len() < necessary {
+        return Err(ParseCommandError::WrongNumArgs);
+    }
+    Ok(args)
+}
+
 /// Available commands for Redis.
 #[derive(Debug, Clone)]
 pub enum Command {
@@ -70,7 +188,99 @@ pub enum Command {
     Info(InfoArg),
     Set(SetArg),
     Get(GetArg),
+    #[cfg(feature = "replication")]
     ReplConf(ReplConfArg),
+    #[cfg(feature = "replication")]
+    Psync(PsyncArg),
+    #[cfg(feature = "replication")]
+    Wait(WaitArg),
+    #[cfg(feature = "replication")]
+    Failover(FailoverArg),
+    #[cfg(feature = "replication")]
+    ReplicaOf(ReplicaOfArg),
+    #[cfg(feature = "persistence")]
+    Save(SaveArg),
+    #[cfg(feature = "persistence")]
+    Bgsave(BgsaveArg),
+    Config(ConfigArg),
+    Acl(AclArg),
+    Auth(AuthArg),
+    Hello(HelloArg),
+    Object(ObjectArg),
+    Memory(MemoryArg),
+    Debug(DebugArg),
+    Command(CommandArg),
+    Client(ClientArg),
+    Cluster(ClusterArg),
+    Asking(AskingArg),
+    Keys(KeysArg),
+    Pttl(PttlArg),
+    Type(TypeArg),
+    Incr(IncrArg),
+    Decr(IncrArg),
+    IncrBy(IncrArg),
+    DecrBy(IncrArg),
+    Append(AppendArg),
+    Strlen(StrlenArg),
+    GetRange(GetRangeArg),
+    SetRange(SetRangeArg),
+    Del(DelArg),
+    Unlink(DelArg),
+    Exists(ExistsArg),
+    Expire(ExpireArg),
+    PExpire(ExpireArg),
+    ExpireAt(ExpireArg),
+    PExpireAt(ExpireArg),
+    Persist(PersistArg),
+    MGet(MGetArg),
+    MSet(MSetArg),
+    MSetNx(MSetArg),
+    LPush(PushArg),
+    RPush(PushArg),
+    LPop(PopArg),
+    RPop(PopArg),
+    LLen(LLenArg),
+    LRange(LRangeArg),
+    BLPop(BPopArg),
+    BRPop(BPopArg),
+    HSet(HSetArg),
+    HGet(HFieldArg),
+    HDel(HDelArg),
+    HGetAll(HKeyArg),
+    HGetDel(HGetDelArg),
+    HGetEx(HGetExArg),
+    HExists(HFieldArg),
+    HLen(HKeyArg),
+    HIncrBy(HIncrByArg),
+    SAdd(SMembersArg),
+    SRem(SMembersArg),
+    SMembers(SKeyArg),
+    SIsMember(SIsMemberArg),
+    SCard(SKeyArg),
+    SInter(SetOpArg),
+    SUnion(SetOpArg),
+    SDiff(SetOpArg),
+    ZAdd(ZAddArg),
+    ZRange(ZRangeArg),
+    ZRangeByScore(ZRangeArg),
+    ZScore(ZMemberArg),
+    ZRank(ZMemberArg),
+    ZRem(ZRemArg),
+    ZCard(ZCardArg),
+    XRange(XRangeArg),
+    XRead(XReadArg),
+    #[cfg(feature = "scripting")]
+    Eval(EvalArg),
+    #[cfg(feature = "scripting")]
+    EvalRo(EvalArg),
+    #[cfg(feature = "scripting")]
+    FCall(EvalArg),
+    #[cfg(feature = "scripting")]
+    FCallRo(EvalArg),
+    #[cfg(feature = "scripting")]
+    Function(FunctionArg),
+    /// A command registered by the application embedding the server.
+    Plugin(PluginArg),
 }
 
 pub trait CommandArgParser {
@@ -84,17 +294,255 @@ pub enum ParseCommandError {
     #[error("Invalid command")]
     InvalidCommand,
 
-    #[error("Wrong number of arguments")]
+    #[error("unknown command '{0}'")]
+    UnknownCommand(String),
+
+    #[error("wrong number of arguments")]
     WrongNumArgs,
 
-    #[error("Invalid argument for command {0:?}")]
+    #[error("wrong number of arguments for '{0}' command")]
+    WrongArity(&'static str),
+
+    /// Argument that is not valid for the command, reported to clients as a syntax error.
+    #[error("syntax error")]
     InvalidArgument(Value),
 
+    #[error("value is not an integer or out of range")]
+    NotInteger,
+
+    #[error("increment or decrement would overflow")]
+    Overflow,
+
+    #[error("value is out of range, must be positive")]
+    NotPositive,
+
+    #[error("invalid expire time in '{0}' command")]
+    InvalidExpireTime(&'static str),
+
+    #[error("{0} options at the same time are not compatible")]
+    IncompatibleOptions(&'static str),
+
+    #[error("value is not a valid float")]
+    NotFloat,
+
+    #[error("min or max is not a float")]
+    MinMaxNotFloat,
+
+    #[error("INCR option supports a single increment-element pair")]
+    IncrMultiplePairs,
+
+    #[error("Invalid stream ID specified as stream command argument")]
+    InvalidStreamId,
+
+    #[error(
+        "Unbalanced '{0}' list of streams: for each stream key an ID or '$' must be specified."
+    )]
+    UnbalancedStreams(&'static str),
+
+    #[error("Mandatory argument FIELDS is missing or not at the right position")]
+    MissingFields,
+
+    #[error("Number of fields must be a positive integer")]
+    NumFieldsNotPositive,
+
+    #[error("The `numfields` parameter must match the number of arguments")]
+    NumFieldsMismatch,
+
+    #[error("timeout is not a float or out of range")]
+    InvalidTimeout,
+
+    #[error("timeout is negative")]
+    NegativeTimeout,
+
+    #[error("Number of keys can't be negative")]
+    NegativeNumKeys,
+
+    #[error("Number of keys can't be greater than number of args")]
+    TooManyNumKeys,
+
     #[error(transparent)]
     Decode(#[from] DecodeError),
 }
 
 impl Command {
+    /// Returns the lowercase name of the command.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Ping(_) => "ping",
+            Self::Echo(_) => "echo",
+            Self::Info(_) => "info",
+            Self::Set(_) => "set",
+            Self::Get(_) => "get",
+            #[cfg(feature = "replication")]
+            Self::ReplConf(_) => "replconf",
+            #[cfg(feature = "replication")]
+            Self::Psync(_) => "psync",
+            #[cfg(feature = "replication")]
+            Self::Wait(_) => "wait",
+            #[cfg(feature = "replication")]
+            Self::Failover(_) => "failover",
+            #[cfg(feature = "replication")]
+            Self::ReplicaOf(_) => "replicaof",
+            #[cfg(feature = "persistence")]
+            Self::Save(_) => "save",
+            #[cfg(feature = "persistence")]
+            Self::Bgsave(_) => "bgsave",
+            Self::Config(_) => "config",
+            Self::Acl(_) => "acl",
+            Self::Auth(_) => "auth",
+            Self::Hello(_) => "hello",
+            Self::Object(_) => "object",
+            Self::Memory(_) => "memory",
+            Self::Debug(_) => "debug",
+            Self::Command(_) => "command",
+            Self::Client(_) => "client",
+            Self::Cluster(_) => "cluster",
+            Self::Asking(_) => "asking",
+            Self::Keys(_) => "keys",
+            Self::Pttl(_) => "pttl",
+            Self::Type(_) => "type",
+            Self::Incr(_) => "incr",
+            Self::Decr(_) => "decr",
+            Self::IncrBy(_) => "incrby",
+            Self::DecrBy(_) => "decrby",
+            Self::Append(_) => "append",
+            Self::Strlen(_) => "strlen",
+            Self::GetRange(_) => "getrange",
+            Self::SetRange(_) => "setrange",
+            Self::Del(_) => "del",
+            Self::Unlink(_) => "unlink",
+            Self::Exists(_) => "exists",
+            Self::Expire(_) => "expire",
+            Self::PExpire(_) => "pexpire",
+            Self::ExpireAt(_) => "expireat",
+            Self::PExpireAt(_) => "pexpireat",
+            Self::Persist(_) => "persist",
+            Self::MGet(_) => "mget",
+            Self::MSet(_) => "mset",
+            Self::MSetNx(_) => "msetnx",
+            Self::LPush(_) => "lpush",
+            Self::RPush(_) => "rpush",
+            Self::LPop(_) => "lpop",
+            Self::RPop(_) => "rpop",
+            Self::LLen(_) => "llen",
+            Self::LRange(_) => "lrange",
+            Self::BLPop(_) => "blpop",
+            Self::BRPop(_) => "brpop",
+            Self::HSet(_) => "hset",
+            Self::HGet(_) => "hget",
+            Self::HDel(_) => "hdel",
+            Self::HGetAll(_) => "hgetall",
+            Self::HGetDel(_) => "hgetdel",
+            Self::HGetEx(_) => "hgetex",
+            Self::HExists(_) => "hexists",
+            Self::HLen(_) => "hlen",
+            Self::HIncrBy(_) => "hincrby",
+            Self::SAdd(_) => "sadd",
+            Self::SRem(_) => "srem",
+            Self::SMembers(_) => "smembers",
+            Self::SIsMember(_) => "sismember",
+            Self::SCard(_) => "scard",
+            Self::SInter(_) => "sinter",
+            Self::SUnion(_) => "sunion",
+            Self::SDiff(_) => "sdiff",
+            Self::ZAdd(_) => "zadd",
+            Self::ZRange(_) => "zrange",
+            Self::ZRangeByScore(_) => "zrangebyscore",
+            Self::ZScore(_) => "zscore",
+            Self::ZRank(_) => "zrank",
+            Self::ZRem(_) => "zrem",
+            Self::ZCard(_) => "zcard",
+            Self::XRange(_) => "xrange",
+            Self::XRead(_) => "xread",
+            #[cfg(feature = "scripting")]
+            Self::Eval(_) => "eval",
+            #[cfg(feature = "scripting")]
+            Self::EvalRo(_) => "eval_ro",
+            #[cfg(feature = "scripting")]
+            Self::FCall(_) => "fcall",
+            #[cfg(feature = "scripting")]
+            Self::FCallRo(_) => "fcall_ro",
+            #[cfg(feature = "scripting")]
+            Self::Function(_) => "function",
+            Self::Plugin(arg) => arg.name,
+        }
+    }
+
+    /// Returns the keys the command accesses.
+    pub fn keys(&self) -> Vec<&[u8]> {
+        self.key_args()
+            .into_iter()
+            .filter_map(BulkString::as_bytes)
+            .collect()
+    }
+
+    /// Returns the arguments holding the keys the command accesses.
+    pub fn key_args(&self) -> Vec<&BulkString> {
+        let key = match self {
+            Self::Set(arg) => &arg.key,
+            Self::Get(arg) => &arg.key,
+            Self::Pttl(arg) => &arg.key,
+            Self::Type(arg) => &arg.key,
+            Self::Incr(arg) | Self::Decr(arg) | Self::IncrBy(arg) | Self::DecrBy(arg) => &arg.key,
+            Self::Append(arg) => &arg.key,
+            Self::Strlen(arg) => &arg.key,
+            Self::GetRange(arg) => &arg.key,
+            Self::SetRange(arg) => &arg.key,
+            Self::Expire(arg) | Self::PExpire(arg) | Self::ExpireAt(arg) | Self::PExpireAt(arg) => {
+                &arg.key
+            }
+            Self::Persist(arg) => &arg.key,
+            Self::LPush(arg) | Self::RPush(arg) => &arg.key,
+            Self::LPop(arg) | Self::RPop(arg) => &arg.key,
+            Self::LLen(arg) => &arg.key,
+            Self::LRange(arg) => &arg.key,
+            Self::HSet(arg) => &arg.key,
+            Self::HGet(arg) | Self::HExists(arg) => &arg.key,
+            Self::HDel(arg) => &arg.key,
+            Self::HGetAll(arg) | Self::HLen(arg) => &arg.key,
+            Self::HGetDel(arg) => &arg.key,
+            Self::HGetEx(arg) => &arg.key,
+            Self::HIncrBy(arg) => &arg.key,
+            Self::SAdd(arg) | Self::SRem(arg) => &arg.key,
+            Self::SMembers(arg) | Self::SCard(arg) => &arg.key,
+            Self::SIsMember(arg) => &arg.key,
+            Self::ZAdd(arg) => &arg.key,
+            Self::ZRange(arg) | Self::ZRangeByScore(arg) => &arg.key,
+            Self::ZScore(arg) | Self::ZRank(arg) => &arg.key,
+            Self::ZRem(arg) => &arg.key,
+            Self::ZCard(arg) => &arg.key,
+            Self::XRange(arg) => &arg.key,
+            Self::Object(arg) => match &arg.subcommand {
+                ObjectSubcommand::Encoding(key) | ObjectSubcommand::Freq(key) => key,
+            },
+            Self::Memory(MemoryArg {
+                subcommand: MemorySubcommand::Usage { key, .. },
+            }) => key,
+            Self::Debug(DebugArg {
+                subcommand: DebugSubcommand::Object(key),
+            }) => key,
+            Self::Debug(DebugArg {
+                subcommand: DebugSubcommand::DigestValue(keys),
+            }) => return keys.iter().collect(),
+            Self::Del(arg) | Self::Unlink(arg) => return arg.keys.iter().collect(),
+            Self::Exists(arg) => return arg.keys.iter().collect(),
+            Self::MGet(arg) => return arg.keys.iter().collect(),
+            Self::BLPop(arg) | Self::BRPop(arg) => return arg.keys.iter().collect(),
+            Self::SInter(arg) | Self::SUnion(arg) | Self::SDiff(arg) => {
+                return arg.keys.iter().collect()
+            }
+            Self::MSet(arg) | Self::MSetNx(arg) => return arg.keys().collect(),
+            Self::XRead(arg) => return arg.keys().collect(),
+            #[cfg(feature = "scripting")]
+            Self::Eval(arg) | Self::EvalRo(arg) | Self::FCall(arg) | Self::FCallRo(arg) => {
+                return arg.keys.iter().collect()
+            }
+            Self::Plugin(arg) => return arg.key_args(),
+            _ => return vec![],
+        };
+        vec![key]
+    }
+
     pub fn parse(buf: &[u8]) -> Result<Self, ParseCommandError> {
         let value = Value::decode(buf)?;
         Self::try_from(value)
@@ -119,6 +567,14 @@ impl TryFrom<Value> for Command {
     type Error = ParseCommandError;
 
     fn try_from(value: Value) -> Result<Self, Self::Error> {
+        Self::try_from(&value)
+    }
+}
+
+impl TryFrom<&Value> for Command {
+    type Error = ParseCommandError;
+
+    fn try_from(value: &Value) -> Result<Self, Self::Error> {
         let arr = match value {
             Value::Array(a) => a,
             _ => return Err(ParseCommandError::InvalidCommand),
@@ -132,24 +588,134 @@ impl TryFrom<Value> for Command {
         let mut iter: std::slice::Iter<'_, Value> = values.iter();
         let cmd = Self::get_command_str_from_iter(&mut iter)?;
 
-        match cmd.to_lowercase().as_str() {
-            "ping" => Ok(Self::Ping(PingArg::parse_arg(&mut iter)?)),
-            "echo" => Ok(Self::Echo(EchoArg::parse_arg(&mut iter)?)),
-            "set" => Ok(Self::Set(SetArg::parse_arg(&mut iter)?)),
-            "get" => Ok(Self::Get(GetArg::parse_arg(&mut iter)?)),
-            "info" => Ok(Self::Info(InfoArg::parse_arg(&mut iter)?)),
-            _ => Err(ParseCommandError::InvalidCommand),
+        // Validate the argument count against the command table before parsing, so every
+        // command reports arity errors the same way.
+        let spec = table::lookup(&cmd).ok_or(ParseCommandError::UnknownCommand(cmd))?;
+        if !spec.arity_matches(values.len()) {
+            return Err(ParseCommandError::WrongArity(spec.name));
         }
+
+        Self::parse_with_spec(spec, &mut iter).map_err(|e| match e {
+            ParseCommandError::WrongNumArgs => ParseCommandError::WrongArity(spec.name),
+            e => e,
+        })
     }
 }
 
-impl Into<Value> for Command {
-    fn into(self) -> Value {
-        match self {
+impl Command {
+    fn parse_with_spec(
+        spec: &CommandSpec,
+        iter: &mut std::slice::Iter<'_, Value>,
+    ) -> Result<Self, ParseCommandError> {
+        match spec.name {
+            "ping" => Ok(Self::Ping(PingArg::parse_arg(iter)?)),
+            "echo" => Ok(Self::Echo(EchoArg::parse_arg(iter)?)),
+            "set" => Ok(Self::Set(SetArg::parse_arg(iter)?)),
+            "get" => Ok(Self::Get(GetArg::parse_arg(iter)?)),
+            "info" => Ok(Self::Info(InfoArg::parse_arg(iter)?)),
+            "config" => Ok(Self::Config(ConfigArg::parse_arg(iter)?)),
+            "acl" => Ok(Self::Acl(AclArg::parse_arg(iter)?)),
+            "auth" => Ok(Self::Auth(AuthArg::parse_arg(iter)?)),
+            "hello" => Ok(Self::Hello(HelloArg::parse_arg(iter)?)),
+            "object" => Ok(Self::Object(ObjectArg::parse_arg(iter)?)),
+            "memory" => Ok(Self::Memory(MemoryArg::parse_arg(iter)?)),
+            "debug" => Ok(Self::Debug(DebugArg::parse_arg(iter)?)),
+            "command" => Ok(Self::Command(CommandArg::parse_arg(iter)?)),
+            "client" => Ok(Self::Client(ClientArg::parse_arg(iter)?)),
+            "cluster" => Ok(Self::Cluster(ClusterArg::parse_arg(iter)?)),
+            "asking" => Ok(Self::Asking(AskingArg::parse_arg(iter)?)),
+            "keys" => Ok(Self::Keys(KeysArg::parse_arg(iter)?)),
+            "pttl" => Ok(Self::Pttl(PttlArg::parse_arg(iter)?)),
+            "type" => Ok(Self::Type(TypeArg::parse_arg(iter)?)),
+            "incr" => Ok(Self::Incr(IncrArg::parse_arg(iter)?)),
+            "decr" => Ok(Self::Decr(IncrArg::parse_decr(iter)?)),
+            "incrby" => Ok(Self::IncrBy(IncrArg::parse_incrby(iter)?)),
+            "decrby" => Ok(Self::DecrBy(IncrArg::parse_decrby(iter)?)),
+            "append" => Ok(Self::Append(AppendArg::parse_arg(iter)?)),
+            "strlen" => Ok(Self::Strlen(StrlenArg::parse_arg(iter)?)),
+            "getrange" => Ok(Self::GetRange(GetRangeArg::parse_arg(iter)?)),
+            "setrange" => Ok(Self::SetRange(SetRangeArg::parse_arg(iter)?)),
+            "del" => Ok(Self::Del(DelArg::parse_arg(iter)?)),
+            "unlink" => Ok(Self::Unlink(DelArg::parse_arg(iter)?)),
+            "exists" => Ok(Self::Exists(ExistsArg::parse_arg(iter)?)),
+            "expire" => Ok(Self::Expire(ExpireArg::parse_arg(iter)?)),
+            "pexpire" => Ok(Self::PExpire(ExpireArg::parse_pexpire(iter)?)),
+            "expireat" => Ok(Self::ExpireAt(ExpireArg::parse_expireat(iter)?)),
+            "pexpireat" => Ok(Self::PExpireAt(ExpireArg::parse_pexpireat(iter)?)),
+            "persist" => Ok(Self::Persist(PersistArg::parse_arg(iter)?)),
+            "mget" => Ok(Self::MGet(MGetArg::parse_arg(iter)?)),
+            "mset" => Ok(Self::MSet(MSetArg::parse_arg(iter)?)),
+            "msetnx" => Ok(Self::MSetNx(MSetArg::parse_msetnx(iter)?)),
+            "lpush" => Ok(Self::LPush(PushArg::parse_arg(iter)?)),
+            "rpush" => Ok(Self::RPush(PushArg::parse_rpush(iter)?)),
+            "lpop" => Ok(Self::LPop(PopArg::parse_arg(iter)?)),
+            "rpop" => Ok(Self::RPop(PopArg::parse_rpop(iter)?)),
+            "llen" => Ok(Self::LLen(LLenArg::parse_arg(iter)?)),
+            "lrange" => Ok(Self::LRange(LRangeArg::parse_arg(iter)?)),
+            "blpop" => Ok(Self::BLPop(BPopArg::parse_arg(iter)?)),
+            "brpop" => Ok(Self::BRPop(BPopArg::parse_brpop(iter)?)),
+            "hset" => Ok(Self::HSet(HSetArg::parse_arg(iter)?)),
+            "hget" => Ok(Self::HGet(HFieldArg::parse_arg(iter)?)),
+            "hdel" => Ok(Self::HDel(HDelArg::parse_arg(iter)?)),
+            "hgetall" => Ok(Self::HGetAll(HKeyArg::parse_arg(iter)?)),
+            "hgetdel" => Ok(Self::HGetDel(HGetDelArg::parse_arg(iter)?)),
+            "hgetex" => Ok(Self::HGetEx(HGetExArg::parse_arg(iter)?)),
+            "hexists" => Ok(Self::HExists(HFieldArg::parse_arg(iter)?)),
+            "hlen" => Ok(Self::HLen(HKeyArg::parse_arg(iter)?)),
+            "hincrby" => Ok(Self::HIncrBy(HIncrByArg::parse_arg(iter)?)),
+            "sadd" => Ok(Self::SAdd(SMembersArg::parse_arg(iter)?)),
+            "srem" => Ok(Self::SRem(SMembersArg::parse_arg(iter)?)),
+            "smembers" => Ok(Self::SMembers(SKeyArg::parse_arg(iter)?)),
+            "sismember" => Ok(Self::SIsMember(SIsMemberArg::parse_arg(iter)?)),
+            "scard" => Ok(Self::SCard(SKeyArg::parse_arg(iter)?)),
+            "sinter" => Ok(Self::SInter(SetOpArg::parse_arg(iter)?)),
+            "sunion" => Ok(Self::SUnion(SetOpArg::parse_sunion(iter)?)),
+            "sdiff" => Ok(Self::SDiff(SetOpArg::parse_sdiff(iter)?)),
+            "zadd" => Ok(Self::ZAdd(ZAddArg::parse_arg(iter)?)),
+            "zrange" => Ok(Self::ZRange(ZRangeArg::parse_arg(iter)?)),
+            "zrangebyscore" => Ok(Self::ZRangeByScore(ZRangeArg::parse_zrangebyscore(iter)?)),
+            "zscore" => Ok(Self::ZScore(ZMemberArg::parse_arg(iter)?)),
+            "zrank" => Ok(Self::ZRank(ZMemberArg::parse_arg(iter)?)),
+            "zrem" => Ok(Self::ZRem(ZRemArg::parse_arg(iter)?)),
+            "zcard" => Ok(Self::ZCard(ZCardArg::parse_arg(iter)?)),
+            "xrange" => Ok(Self::XRange(XRangeArg::parse_arg(iter)?)),
+            "xread" => Ok(Self::XRead(XReadArg::parse_arg(iter)?)),
+            #[cfg(feature = "replication")]
+            "replconf" => Ok(Self::ReplConf(ReplConfArg::parse_arg(iter)?)),
+            #[cfg(feature = "replication")]
+            "psync" => Ok(Self::Psync(PsyncArg::parse_arg(iter)?)),
+            #[cfg(feature = "replication")]
+            "wait" => Ok(Self::Wait(WaitArg::parse_arg(iter)?)),
+            #[cfg(feature = "replication")]
+            "failover" => Ok(Self::Failover(FailoverArg::parse_arg(iter)?)),
+            #[cfg(feature = "replication")]
+            "replicaof" => Ok(Self::ReplicaOf(ReplicaOfArg::parse_arg(iter)?)),
+            #[cfg(feature = "persistence")]
+            "save" => Ok(Self::Save(SaveArg::parse_arg(iter)?)),
+            #[cfg(feature = "persistence")]
+            "bgsave" => Ok(Self::Bgsave(BgsaveArg::parse_arg(iter)?)),
+            #[cfg(feature = "scripting")]
+            "eval" => Ok(Self::Eval(EvalArg::parse_arg(iter)?)),
+            #[cfg(feature = "scripting")]
+            "eval_ro" => Ok(Self::EvalRo(EvalArg::parse_arg(iter)?)),
+            #[cfg(feature = "scripting")]
+            "fcall" => Ok(Self::FCall(EvalArg::parse_arg(iter)?)),
+            #[cfg(feature = "scripting")]
+            "fcall_ro" => Ok(Self::FCallRo(EvalArg::parse_arg(iter)?)),
+            #[cfg(feature = "scripting")]
+            "function" => Ok(Self::Function(FunctionArg::parse_arg(iter)?)),
+            _ => Err(ParseCommandError::UnknownCommand(spec.name.to_string())),
+        }
+    }
+}
+
+impl From<Command> for Value {
+    fn from(val: Command) -> Self {
+        match val {
             Command::Ping(arg) => {
                 let mut parts = vec![Value::BulkString("PING".into())];
-                if arg.msg.is_some() {
-                    parts.push(Value::BulkString(arg.msg.unwrap()));
+                if let Some(msg) = arg.msg {
+                    parts.push(Value::BulkString(msg));
                 }
                 Value::Array(Array::new(parts))
             }
@@ -200,4 +766,24 @@ mod test {
             _ => panic!("Wrong command for echo"),
         }
     }
+
+    #[test]
+    fn parse_checks_arity_from_table() {
+        let err = Command::parse(b"*1\r\n$3\r\nGET\r\n").expect_err("Parse command no error");
+        assert_eq!(
+            err.to_string(),
+            "wrong number of arguments for 'get' command"
+        );
+
+        let err = Command::parse(b"*3\r\n$4\r\nPING\r\n$1\r\na\r\n$1\r\nb\r\n")
+            .expect_err("Parse command no error");
+        assert_eq!(
+            err.to_string(),
+            "wrong number of arguments for 'ping' command"
+        );
+
+        let err = Command::parse(b"*4\r\n$3\r\nSET\r\n$1\r\nk\r\n$1\r\nv\r\n$4\r\nNOPE\r\n")
+            .expect_err("Parse command no error");
+        assert_eq!(err.to_string(), "syntax error");
+    }
 }
@@ -0,0 +1,350 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use parking_lot::RwLock;
+
+use super::super::acl::{category_commands, AccessControl, AclError, CATEGORIES};
+use super::super::clients::ClientState;
+use super::super::resp::{Array, BulkString, Integer, SimpleString, Value};
+use super::{bulk_string_to_string, value_to_bulk_string, CommandArgParser, ParseCommandError};
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum AclSubcommand {
+    SetUser { name: String, rules: Vec<String> },
+    GetUser(String),
+    DelUser(Vec<String>),
+    List,
+    Users,
+    WhoAmI,
+    Cat(Option<String>),
+    Load,
+    Save,
+}
+
+impl AclSubcommand {
+    fn to_bulk_strings(&self) -> Vec<BulkString> {
+        let mut v: Vec<BulkString> = vec![];
+        match self {
+            Self::SetUser { name, rules } => {
+                v.push("SETUSER".into());
+                v.push(name.clone().into());
+                v.extend(rules.iter().map(|r| BulkString::from(r.clone())));
+            }
+            Self::GetUser(name) => {
+                v.push("GETUSER".into());
+                v.push(name.clone().into());
+            }
+            Self::DelUser(names) => {
+                v.push("DELUSER".into());
+                v.extend(names.iter().map(|n| BulkString::from(n.clone())));
+            }
+            Self::List => v.push("LIST".into()),
+            Self::Users => v.push("USERS".into()),
+            Self::WhoAmI => v.push("WHOAMI".into()),
+            Self::Cat(category) => {
+                v.push("CAT".into());
+                v.extend(category.iter().map(|c| BulkString::from(c.clone())));
+            }
+            Self::Load => v.push("LOAD".into()),
+            Self::Save => v.push("SAVE".into()),
+        }
+        v
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct AclArg {
+    pub subcommand: AclSubcommand,
+}
+
+impl CommandArgParser for AclArg {
+    /// ACL SETUSER username [rule ...]
+    /// ACL GETUSER username
+    /// ACL DELUSER username [username ...]
+    /// ACL LIST | USERS | WHOAMI
+    /// ACL CAT [category]
+    /// ACL LOAD | SAVE
+    fn parse_arg(iter: &mut std::slice::Iter<'_, Value>) -> Result<Self, ParseCommandError> {
+        let args = iter
+            .map(|val| value_to_bulk_string(val).and_then(|bs| bulk_string_to_string(&bs)))
+            .collect::<Result<Vec<String>, ParseCommandError>>()?;
+        let (subcommand, rest) = args.split_first().ok_or(ParseCommandError::WrongNumArgs)?;
+
+        let subcommand = match (subcommand.to_lowercase().as_str(), rest) {
+            ("setuser", [name, rules @ ..]) => AclSubcommand::SetUser {
+                name: name.clone(),
+                rules: rules.to_vec(),
+            },
+            ("getuser", [name]) => AclSubcommand::GetUser(name.clone()),
+            ("deluser", names) if !names.is_empty() => AclSubcommand::DelUser(names.to_vec()),
+            ("list", []) => AclSubcommand::List,
+            ("users", []) => AclSubcommand::Users,
+            ("whoami", []) => AclSubcommand::WhoAmI,
+            ("cat", []) => AclSubcommand::Cat(None),
+            ("cat", [category]) => AclSubcommand::Cat(Some(category.clone())),
+            ("load", []) => AclSubcommand::Load,
+            ("save", []) => AclSubcommand::Save,
+            (
+                "setuser" | "getuser" | "deluser" | "list" | "users" | "whoami" | "cat" | "load"
+                | "save",
+                _,
+            ) => return Err(ParseCommandError::WrongNumArgs),
+            _ => {
+                return Err(ParseCommandError::InvalidArgument(Value::BulkString(
+                    subcommand.clone().into(),
+                )))
+            }
+        };
+
+        Ok(Self { subcommand })
+    }
+}
+
+pub struct Acl;
+
+impl Acl {
+    /// Returns an instance of ACL command handler.
+    pub fn handler(acl: Arc<RwLock<AccessControl>>, aclfile: Option<PathBuf>) -> AclHandler {
+        AclHandler { acl, aclfile }
+    }
+
+    /// Returns ACL as a Command in the form of Value.
+    pub fn command_value(arg: AclArg) -> Value {
+        let mut v = vec![Value::BulkString("ACL".into())];
+        v.extend(
+            arg.subcommand
+                .to_bulk_strings()
+                .into_iter()
+                .map(Value::BulkString),
+        );
+
+        Value::Array(v.into())
+    }
+}
+
+#[derive(Debug)]
+pub struct AclHandler {
+    acl: Arc<RwLock<AccessControl>>,
+    aclfile: Option<PathBuf>,
+}
+
+impl AclHandler {
+    /// Manages users and reports on the ACL of the server.
+    pub fn handle(&self, arg: AclArg, client: &ClientState) -> Result<Value, AclError> {
+        let ok = Value::SimpleString(SimpleString::from("OK"));
+
+        match arg.subcommand {
+            AclSubcommand::SetUser { name, rules } => {
+                let mut acl = self.acl.write();
+                acl.set_user(&name, &rules)?;
+                Ok(ok)
+            }
+            AclSubcommand::GetUser(name) => {
+                let acl = self.acl.read();
+                let user = match acl.user(&name) {
+                    Some(user) => user,
+                    None => return Ok(Value::Array(Array::null())),
+                };
+
+                let strings = |v: Vec<String>| {
+                    Value::Array(
+                        v.into_iter()
+                            .map(|s| Value::BulkString(s.into()))
+                            .collect::<Vec<_>>()
+                            .into(),
+                    )
+                };
+                Ok(Value::Array(Array::new(vec![
+                    Value::BulkString("flags".into()),
+                    strings(user.flags().iter().map(|f| f.to_string()).collect()),
+                    Value::BulkString("passwords".into()),
+                    strings(user.passwords.clone()),
+                    Value::BulkString("commands".into()),
+                    Value::BulkString(user.commands_description().into()),
+                    Value::BulkString("keys".into()),
+                    Value::BulkString(prefixed_patterns('~', &user.key_patterns).into()),
+                    Value::BulkString("channels".into()),
+                    Value::BulkString(prefixed_patterns('&', &user.channel_patterns).into()),
+                ])))
+            }
+            AclSubcommand::DelUser(names) => {
+                let mut acl = self.acl.write();
+                let deleted = acl.del_users(&names)?;
+                Ok(Value::Integer(Integer::new(deleted as i64)))
+            }
+            AclSubcommand::List => {
+                let acl = self.acl.read();
+                Ok(bulk_string_array(acl.users().map(|user| user.describe())))
+            }
+            AclSubcommand::Users => {
+                let acl = self.acl.read();
+                Ok(bulk_string_array(acl.users().map(|user| user.name.clone())))
+            }
+            AclSubcommand::WhoAmI => Ok(match client.user() {
+                Some(user) => Value::BulkString(user.into()),
+                None => Value::BulkString(BulkString::null()),
+            }),
+            AclSubcommand::Cat(None) => {
+                Ok(bulk_string_array(CATEGORIES.iter().map(|c| c.to_string())))
+            }
+            AclSubcommand::Cat(Some(category)) => {
+                let category = category.to_lowercase();
+                if !CATEGORIES.contains(&category.as_str()) {
+                    return Err(AclError::UnknownCategory(category));
+                }
+                Ok(bulk_string_array(
+                    category_commands(&category)
+                        .into_iter()
+                        .map(|name| name.to_string()),
+                ))
+            }
+            AclSubcommand::Load => {
+                let path = self.aclfile.as_ref().ok_or(AclError::NoAclFile)?;
+                // Parse the whole file before swapping, so a bad file keeps the current users.
+                let loaded = AccessControl::load(path)?;
+                *self.acl.write() = loaded;
+                Ok(ok)
+            }
+            AclSubcommand::Save => {
+                let path = self.aclfile.as_ref().ok_or(AclError::NoAclFile)?;
+                self.acl.read().save(path)?;
+                Ok(ok)
+            }
+        }
+    }
+}
+
+fn prefixed_patterns(prefix: char, patterns: &[String]) -> String {
+    patterns
+        .iter()
+        .map(|p| format!("{prefix}{p}"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn bulk_string_array(strings: impl Iterator<Item = String>) -> Value {
+    Value::Array(Array::new(
+        strings.map(|s| Value::BulkString(s.into())).collect(),
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn command() {
+        let val = Acl::command_value(AclArg {
+            subcommand: AclSubcommand::SetUser {
+                name: "alice".into(),
+                rules: vec!["on".into(), "+@read".into()],
+            },
+        });
+
+        assert_eq!(
+            val.array().unwrap().values().unwrap().to_vec(),
+            vec![
+                Value::BulkString("ACL".into()),
+                Value::BulkString("SETUSER".into()),
+                Value::BulkString("alice".into()),
+                Value::BulkString("on".into()),
+                Value::BulkString("+@read".into()),
+            ]
+        )
+    }
+}
+
+#[cfg(test)]
+mod handler_test {
+    use super::*;
+
+    #[test]
+    fn handle_setuser_and_list() {
+        let handler = Acl::handler(Arc::new(RwLock::new(AccessControl::new())), None);
+        let client = ClientState::new(1, Some("default".into()));
+
+        handler
+            .handle(
+                AclArg {
+                    subcommand: AclSubcommand::SetUser {
+                        name: "alice".into(),
+                        rules: vec!["on".into(), "~*".into(), "+get".into()],
+                    },
+                },
+                &client,
+            )
+            .expect("Handle acl setuser unexpected error");
+
+        let resp = handler
+            .handle(
+                AclArg {
+                    subcommand: AclSubcommand::List,
+                },
+                &client,
+            )
+            .expect("Handle acl list unexpected error");
+        assert_eq!(
+            resp,
+            Value::Array(Array::new(vec![
+                Value::BulkString("user alice on ~* +get".into()),
+                Value::BulkString("user default on nopass ~* &* +@all".into()),
+            ]))
+        );
+    }
+
+    #[test]
+    fn handle_whoami() {
+        let handler = Acl::handler(Arc::new(RwLock::new(AccessControl::new())), None);
+        let resp = handler
+            .handle(
+                AclArg {
+                    subcommand: AclSubcommand::WhoAmI,
+                },
+                &ClientState::new(1, Some("default".into())),
+            )
+            .expect("Handle acl whoami unexpected error");
+
+        assert_eq!(resp, Value::BulkString("default".into()));
+    }
+
+    #[test]
+    fn handle_save_and_load() {
+        let path = std::env::temp_dir().join(format!("acl-test-{}.acl", std::process::id()));
+        let acl = Arc::new(RwLock::new(AccessControl::new()));
+        let handler = Acl::handler(acl.clone(), Some(path.clone()));
+        let client = ClientState::new(1, Some("default".into()));
+        let handle = |subcommand| {
+            handler
+                .handle(AclArg { subcommand }, &client)
+                .expect("Handle acl unexpected error")
+        };
+
+        handle(AclSubcommand::SetUser {
+            name: "alice".into(),
+            rules: vec!["on".into(), "+get".into()],
+        });
+        handle(AclSubcommand::Save);
+        handle(AclSubcommand::DelUser(vec!["alice".into()]));
+        assert!(acl.read().user("alice").is_none());
+
+        handle(AclSubcommand::Load);
+        assert!(acl.read().user("alice").is_some());
+
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn handle_load_without_aclfile() {
+        let handler = Acl::handler(Arc::new(RwLock::new(AccessControl::new())), None);
+        let err = handler
+            .handle(
+                AclArg {
+                    subcommand: AclSubcommand::Load,
+                },
+                &ClientState::new(1, None),
+            )
+            .expect_err("Handle acl load no error");
+
+        assert_eq!(err, AclError::NoAclFile);
+    }
+}
@@ -0,0 +1,152 @@
+use std::sync::Arc;
+
+use super::super::clock::Clock;
+use super::super::handler::{HandleCommandError, StoredData, StoredValue, MAX_STRING_LEN};
+use super::super::key::Key;
+use super::super::resp::{BulkString, Integer, Value};
+use super::super::store::Store;
+use super::{consume_args_from_iter, CommandArgParser, ParseCommandError};
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct AppendArg {
+    pub key: BulkString,
+    pub value: BulkString,
+}
+
+impl CommandArgParser for AppendArg {
+    /// APPEND key value
+    fn parse_arg(iter: &mut std::slice::Iter<'_, Value>) -> Result<Self, ParseCommandError> {
+        let args = consume_args_from_iter(iter, 2, 0)?;
+        Ok(Self {
+            key: args[0].clone(),
+            value: args[1].clone(),
+        })
+    }
+}
+
+pub struct Append;
+
+impl Append {
+    /// Returns an instance of APPEND command handler.
+    pub fn handler(map: Arc<Store>, clock: Arc<dyn Clock>) -> AppendHandler {
+        AppendHandler { map, clock }
+    }
+
+    /// Returns APPEND as a Command in the form of Value.
+    pub fn command_value(arg: AppendArg) -> Value {
+        let v = vec![
+            Value::BulkString("APPEND".into()),
+            Value::BulkString(arg.key),
+            Value::BulkString(arg.value),
+        ];
+        Value::Array(v.into())
+    }
+}
+
+#[derive(Debug)]
+pub struct AppendHandler {
+    map: Arc<Store>,
+    clock: Arc<dyn Clock>,
+}
+
+impl AppendHandler {
+    /// Appends the value to the string held by the key, like SET if the key doesn't exist,
+    /// and returns the length of the result. An existing key keeps its time to live.
+    pub fn handle(&self, arg: AppendArg) -> Result<Value, HandleCommandError> {
+        let key = arg.key.as_bytes().unwrap_or_default();
+        let value = arg.value.as_bytes().unwrap_or_default();
+        let now = self.clock.now();
+        let mut map = self.map.write(key);
+        let mut data = match map.get(key).filter(|data| !data.expired_at(now)) {
+            Some(data) if data.value.as_string().is_none() => {
+                return Err(HandleCommandError::WrongType)
+            }
+            Some(data) => data.clone(),
+            None => StoredData::new(StoredValue::String("".into()), None),
+        };
+
+        let current = data.value.string_bytes();
+        if current.len() + value.len() > MAX_STRING_LEN {
+            return Err(HandleCommandError::StringTooLong);
+        }
+        let appended = [current, value].concat();
+        let len = appended.len();
+        data.value = StoredValue::String(appended.into());
+        data.raw = true;
+        map.insert(Key::new(key), data);
+
+        Ok(Value::Integer(Integer::new(len as i64)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::Command;
+    use super::*;
+
+    #[test]
+    fn parse_append() {
+        let arg = AppendArg {
+            key: "key".into(),
+            value: "value".into(),
+        };
+        match Command::try_from(Append::command_value(arg.clone())) {
+            Ok(Command::Append(parsed)) => assert_eq!(parsed, arg),
+            other => panic!("Unexpected parse result {other:?}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod handler_test {
+    use std::time::Duration;
+
+    use super::super::super::clock::TestClock;
+    use super::*;
+
+    #[test]
+    fn handle_append() {
+        let clock = Arc::new(TestClock::default());
+        let deadline = clock.now() + Duration::from_secs(10);
+        let map = Arc::new(Store::from_iter([
+            (
+                Key::from("key"),
+                StoredData::new("Hello".into(), Some(deadline)),
+            ),
+            (
+                Key::from("set"),
+                StoredData::new(StoredValue::Set(["a".into()].into()), None),
+            ),
+        ]));
+        let handler = Append::handler(map.clone(), clock.clone());
+        let append = |key: &str, value: &str| {
+            handler.handle(AppendArg {
+                key: key.into(),
+                value: value.into(),
+            })
+        };
+        let data = |key: &str| map.snapshot().get(key.as_bytes()).cloned().unwrap();
+
+        assert_eq!(
+            append("key", " World").unwrap(),
+            Value::Integer(Integer::new(11))
+        );
+        assert_eq!(data("key").value, StoredValue::String("Hello World".into()));
+        assert_eq!(data("key").deadline, Some(deadline));
+
+        assert_eq!(
+            append("new", "abc").unwrap(),
+            Value::Integer(Integer::new(3))
+        );
+        assert_eq!(data("new").value, StoredValue::String("abc".into()));
+        assert!(matches!(
+            append("set", "b"),
+            Err(HandleCommandError::WrongType)
+        ));
+
+        // An expired key is replaced.
+        clock.advance(Duration::from_secs(20));
+        assert_eq!(append("key", "!").unwrap(), Value::Integer(Integer::new(1)));
+        assert_eq!(data("key").deadline, None);
+    }
+}
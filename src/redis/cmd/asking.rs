@@ -0,0 +1,81 @@
+use super::super::clients::ClientState;
+use super::super::resp::{SimpleString, Value};
+use super::{consume_args_from_iter, ClusterCommandError, CommandArgParser, ParseCommandError};
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct AskingArg;
+
+impl CommandArgParser for AskingArg {
+    /// ASKING
+    fn parse_arg(iter: &mut std::slice::Iter<'_, Value>) -> Result<Self, ParseCommandError> {
+        consume_args_from_iter(iter, 0, 0)?;
+        Ok(Self)
+    }
+}
+
+pub struct Asking;
+
+impl Asking {
+    /// Returns an instance of ASKING command handler.
+    pub fn handler(cluster_enabled: bool) -> AskingHandler {
+        AskingHandler { cluster_enabled }
+    }
+
+    /// Returns ASKING as a Command in the form of Value.
+    pub fn command_value(_arg: AskingArg) -> Value {
+        Value::Array(vec![Value::BulkString("ASKING".into())].into())
+    }
+}
+
+#[derive(Debug)]
+pub struct AskingHandler {
+    cluster_enabled: bool,
+}
+
+impl AskingHandler {
+    /// Lets the next command of the client access a slot this node is importing, after an
+    /// ASK redirect.
+    pub fn handle(&self, client: &mut ClientState) -> Result<Value, ClusterCommandError> {
+        if !self.cluster_enabled {
+            return Err(ClusterCommandError::Disabled);
+        }
+        client.flags.asking = true;
+        Ok(Value::SimpleString(SimpleString::from("OK")))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn command() {
+        let val = Asking::command_value(AskingArg);
+
+        assert_eq!(
+            val.array().unwrap().values().unwrap().to_vec(),
+            vec![Value::BulkString("ASKING".into())]
+        )
+    }
+}
+
+#[cfg(test)]
+mod handler_test {
+    use super::*;
+
+    #[test]
+    fn handle_asking() {
+        let mut client = ClientState::new(1, None);
+        assert_eq!(
+            Asking::handler(false).handle(&mut client),
+            Err(ClusterCommandError::Disabled)
+        );
+        assert!(!client.flags.asking);
+
+        let resp = Asking::handler(true)
+            .handle(&mut client)
+            .expect("Handle asking unexpected error");
+        assert_eq!(resp, Value::SimpleString(SimpleString::from("OK")));
+        assert!(client.flags.asking);
+    }
+}
@@ -0,0 +1,217 @@
+use std::sync::Arc;
+
+use parking_lot::RwLock;
+
+use super::super::acl::{AccessControl, AclError, DEFAULT_USER};
+use super::super::clients::ClientState;
+use super::super::clock::Clock;
+use super::super::resp::{BulkString, SimpleString, Value};
+use super::super::stats::CommandStats;
+use super::super::throttle::AuthThrottle;
+use super::{bulk_string_to_string, consume_args_from_iter, CommandArgParser, ParseCommandError};
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct AuthArg {
+    pub username: Option<String>,
+    pub password: String,
+}
+
+impl CommandArgParser for AuthArg {
+    /// AUTH [username] password
+    fn parse_arg(iter: &mut std::slice::Iter<'_, Value>) -> Result<Self, ParseCommandError> {
+        let args = consume_args_from_iter(iter, 1, 1)?;
+        let mut args = args
+            .iter()
+            .map(bulk_string_to_string)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let password = args.pop().unwrap();
+        let username = args.pop();
+        Ok(Self { username, password })
+    }
+}
+
+pub struct Auth;
+
+impl Auth {
+    /// Returns an instance of AUTH command handler.
+    pub fn handler(
+        acl: Arc<RwLock<AccessControl>>,
+        throttle: Arc<AuthThrottle>,
+        clock: Arc<dyn Clock>,
+        stats: Arc<CommandStats>,
+    ) -> AuthHandler {
+        AuthHandler {
+            acl,
+            throttle,
+            clock,
+            stats,
+        }
+    }
+
+    /// Returns AUTH as a Command in the form of Value.
+    pub fn command_value(arg: AuthArg) -> Value {
+        let mut v = vec![Value::BulkString("AUTH".into())];
+        if let Some(username) = arg.username {
+            v.push(Value::BulkString(BulkString::from(username)));
+        }
+        v.push(Value::BulkString(BulkString::from(arg.password)));
+
+        Value::Array(v.into())
+    }
+}
+
+#[derive(Debug)]
+pub struct AuthHandler {
+    acl: Arc<RwLock<AccessControl>>,
+    throttle: Arc<AuthThrottle>,
+    clock: Arc<dyn Clock>,
+    stats: Arc<CommandStats>,
+}
+
+impl AuthHandler {
+    /// Authenticates the connection as the user, or as the default user if no username is given.
+    /// Addresses that failed too often are refused for a while without checking the password.
+    pub fn handle(&self, arg: AuthArg, client: &mut ClientState) -> Result<Value, AclError> {
+        let now = self.clock.now();
+        let ip = client.addr().map(|addr| addr.ip());
+        if let Some(wait) = ip.and_then(|ip| self.throttle.blocked_for(ip, now)) {
+            self.stats.record_auth_failure(true);
+            return Err(AclError::AuthThrottled(wait.as_secs_f64().ceil() as u64));
+        }
+
+        let acl = self.acl.read();
+        let username = arg.username.as_deref().unwrap_or(DEFAULT_USER);
+        let user = match acl.authenticate(username, &arg.password) {
+            Ok(user) => user,
+            Err(e) => {
+                self.stats.record_auth_failure(false);
+                if let Some(ip) = ip {
+                    self.throttle.record_failure(ip, now);
+                }
+                return Err(e);
+            }
+        };
+        if let Some(ip) = ip {
+            self.throttle.record_success(ip);
+        }
+        client.set_user(user);
+
+        Ok(Value::SimpleString(SimpleString::from("OK")))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn command() {
+        let val = Auth::command_value(AuthArg {
+            username: Some("alice".into()),
+            password: "secret".into(),
+        });
+
+        assert_eq!(
+            val.array().unwrap().values().unwrap().to_vec(),
+            vec![
+                Value::BulkString("AUTH".into()),
+                Value::BulkString("alice".into()),
+                Value::BulkString("secret".into()),
+            ]
+        )
+    }
+}
+
+#[cfg(test)]
+mod handler_test {
+    use std::time::Duration;
+
+    use super::super::super::clock::{self, TestClock};
+    use super::super::super::throttle::{AUTH_BASE_DELAY, AUTH_FREE_ATTEMPTS};
+    use super::*;
+
+    fn auth_handler(clock: Arc<dyn Clock>) -> (AuthHandler, Arc<CommandStats>) {
+        let mut acl = AccessControl::new();
+        acl.set_user("alice", &["on".into(), ">secret".into()])
+            .expect("Set user unexpected error");
+        let stats = Arc::new(CommandStats::default());
+        let handler = Auth::handler(
+            Arc::new(RwLock::new(acl)),
+            Arc::new(AuthThrottle::default()),
+            clock,
+            stats.clone(),
+        );
+        (handler, stats)
+    }
+
+    fn alice(password: &str) -> AuthArg {
+        AuthArg {
+            username: Some("alice".into()),
+            password: password.into(),
+        }
+    }
+
+    #[test]
+    fn handle_auth() {
+        let (handler, _) = auth_handler(clock::system());
+        let mut client = ClientState::new(1, None);
+
+        let err = handler
+            .handle(
+                AuthArg {
+                    username: Some("alice".into()),
+                    password: "wrong".into(),
+                },
+                &mut client,
+            )
+            .expect_err("Handle auth no error");
+        assert_eq!(err, AclError::WrongPass);
+        assert_eq!(client.user(), None);
+
+        handler
+            .handle(
+                AuthArg {
+                    username: Some("alice".into()),
+                    password: "secret".into(),
+                },
+                &mut client,
+            )
+            .expect("Handle auth unexpected error");
+        assert_eq!(client.user(), Some("alice"));
+    }
+
+    #[test]
+    fn failed_auth_throttles_address() {
+        let clock = Arc::new(TestClock::default());
+        let (handler, stats) = auth_handler(clock.clone());
+        let mut client = ClientState::new(1, None).with_addrs(
+            "10.0.0.1:5000".parse().unwrap(),
+            "10.0.0.9:6379".parse().unwrap(),
+        );
+
+        for _ in 0..=AUTH_FREE_ATTEMPTS {
+            let err = handler
+                .handle(alice("wrong"), &mut client)
+                .expect_err("Handle auth no error");
+            assert_eq!(err, AclError::WrongPass);
+        }
+        // Even the right password is refused until the address waited.
+        let err = handler
+            .handle(alice("secret"), &mut client)
+            .expect_err("Handle auth no error");
+        assert_eq!(err, AclError::AuthThrottled(1));
+        assert!(stats
+            .stats_info()
+            .contains(&"auth_throttled_calls:1".to_string()));
+
+        clock.advance(AUTH_BASE_DELAY + Duration::from_millis(1));
+        handler
+            .handle(alice("secret"), &mut client)
+            .expect("Handle auth unexpected error");
+        assert_eq!(client.user(), Some("alice"));
+        assert!(stats
+            .stats_info()
+            .contains(&"acl_access_denied_auth:4".to_string()));
+    }
+}
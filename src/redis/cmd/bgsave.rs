@@ -0,0 +1,178 @@
+use std::sync::Arc;
+
+use tracing::{error, info};
+
+use super::super::clock::Clock;
+use super::super::config::ServerConfig;
+use super::super::persistence::PersistenceState;
+use super::super::rdb;
+use super::super::replication::Role;
+use super::super::resp::{SimpleString, Value};
+use super::super::store::Store;
+use super::super::util;
+use super::save::{rdb_path, snapshot};
+use super::{consume_args_from_iter, CommandArgParser, ParseCommandError, SaveError};
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct BgsaveArg;
+
+impl CommandArgParser for BgsaveArg {
+    /// BGSAVE
+    fn parse_arg(iter: &mut std::slice::Iter<'_, Value>) -> Result<Self, ParseCommandError> {
+        consume_args_from_iter(iter, 0, 0)?;
+        Ok(Self)
+    }
+}
+
+pub struct Bgsave;
+
+impl Bgsave {
+    /// Returns an instance of BGSAVE command handler.
+    pub fn handler(
+        map: Arc<Store>,
+        clock: Arc<dyn Clock>,
+        config: Arc<ServerConfig>,
+        persistence: Arc<PersistenceState>,
+        role: Option<Role>,
+    ) -> BgsaveHandler {
+        BgsaveHandler {
+            map,
+            clock,
+            config,
+            persistence,
+            role,
+        }
+    }
+
+    /// Returns BGSAVE as a Command in the form of Value.
+    pub fn command_value(_arg: BgsaveArg) -> Value {
+        Value::Array(vec![Value::BulkString("BGSAVE".into())].into())
+    }
+}
+
+#[derive(Debug)]
+pub struct BgsaveHandler {
+    map: Arc<Store>,
+    clock: Arc<dyn Clock>,
+    config: Arc<ServerConfig>,
+    persistence: Arc<PersistenceState>,
+
+    /// Side of replication the server is on, `None` if it doesn't replicate.
+    role: Option<Role>,
+}
+
+impl BgsaveHandler {
+    /// Saves a snapshot of the dataset like SAVE, but on a background task, replying right
+    /// away. Commands keep running meanwhile, and their writes are left for the next save.
+    /// How the save went shows in INFO persistence.
+    pub fn handle(&self, _arg: BgsaveArg) -> Result<Value, SaveError> {
+        if self.persistence.partial_load() {
+            return Err(SaveError::PartialLoad);
+        }
+        if !self.persistence.start_bgsave() {
+            return Err(SaveError::InProgress);
+        }
+        let dirty = self.persistence.dirty();
+        let (snapshot, repl) = snapshot(&self.map, self.role.as_ref());
+        let (path, now) = (rdb_path(&self.config), self.clock.now());
+        let persistence = self.persistence.clone();
+        util::spawn_named("bgsave", async move {
+            let saved = tokio::task::spawn_blocking(move || {
+                let repl = repl
+                    .as_ref()
+                    .map(|(repl_id, offset)| (repl_id.as_str(), *offset));
+                rdb::save(snapshot.into_entries(), repl, &path, now).map(|_| path)
+            })
+            .await
+            .expect("Background save panicked");
+            persistence.set_last_bgsave_ok(saved.is_ok());
+            match saved {
+                Ok(path) => {
+                    persistence.mark_saved(dirty);
+                    info!(
+                        "Background saving to {} terminated with success",
+                        path.display()
+                    );
+                }
+                Err(e) => error!("Background saving failed: {e}"),
+            }
+            persistence.set_bgsave_in_progress(false);
+        });
+
+        Ok(Value::SimpleString(SimpleString::from(
+            "Background saving started",
+        )))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::Command;
+    use super::*;
+
+    #[test]
+    fn parse_bgsave() {
+        match Command::try_from(Bgsave::command_value(BgsaveArg)) {
+            Ok(Command::Bgsave(parsed)) => assert_eq!(parsed, BgsaveArg),
+            other => panic!("Unexpected parse result {other:?}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod handler_test {
+    use std::time::Duration;
+
+    use super::super::super::clock;
+    use super::super::super::config::ConfigValues;
+    use super::super::super::handler::StoredData;
+    use super::super::super::key::Key;
+    use super::*;
+
+    #[tokio::test]
+    async fn handle_bgsave() {
+        let dir = std::env::temp_dir().join(format!("bgsave-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let config = Arc::new(ServerConfig::new(ConfigValues {
+            dir: dir.clone(),
+            ..Default::default()
+        }));
+        let map = Store::from_iter([(Key::from("key"), StoredData::new("value".into(), None))]);
+        let persistence = Arc::new(PersistenceState::new());
+        persistence.incr_dirty(1);
+        let handler = Bgsave::handler(
+            Arc::new(map),
+            clock::system(),
+            config.clone(),
+            persistence.clone(),
+            None,
+        );
+
+        let resp = handler
+            .handle(BgsaveArg)
+            .expect("Handle bgsave unexpected error");
+        assert_eq!(
+            resp,
+            Value::SimpleString(SimpleString::from("Background saving started"))
+        );
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while persistence.bgsave_in_progress() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("Background save never finished");
+        assert!(persistence.last_bgsave_ok());
+        assert_eq!(persistence.dirty(), 0);
+        let saved = std::fs::read(rdb_path(&config)).expect("Read unexpected error");
+        let dataset = rdb::parse(&saved, clock::system().now()).expect("Parse unexpected error");
+        assert_eq!(dataset.entries.len(), 1);
+
+        persistence.set_bgsave_in_progress(true);
+        let err = handler
+            .handle(BgsaveArg)
+            .expect_err("Handle bgsave no error");
+        assert_eq!(err, SaveError::InProgress);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,294 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::super::blocking::BlockedKeys;
+use super::super::clock::Clock;
+use super::super::handler::HandleCommandError;
+use super::super::reply::{Deferred, Reply};
+use super::super::resp::{Array, BulkString, Value};
+use super::{
+    bulk_string_to_string, consume_variadic_args_from_iter, CommandArgParser, ListEnd,
+    ParseCommandError,
+};
+
+/// Arguments of BLPOP and BRPOP.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct BPopArg {
+    pub keys: Vec<BulkString>,
+    /// How long to block for, zero to block forever.
+    pub timeout: Duration,
+    pub end: ListEnd,
+}
+
+impl CommandArgParser for BPopArg {
+    /// BLPOP key [key ...] timeout
+    fn parse_arg(iter: &mut std::slice::Iter<'_, Value>) -> Result<Self, ParseCommandError> {
+        Self::parse_with(iter, ListEnd::Left)
+    }
+}
+
+impl BPopArg {
+    /// BRPOP key [key ...] timeout
+    pub fn parse_brpop(iter: &mut std::slice::Iter<'_, Value>) -> Result<Self, ParseCommandError> {
+        Self::parse_with(iter, ListEnd::Right)
+    }
+
+    fn parse_with(
+        iter: &mut std::slice::Iter<'_, Value>,
+        end: ListEnd,
+    ) -> Result<Self, ParseCommandError> {
+        let mut keys = consume_variadic_args_from_iter(iter, 2)?;
+        let timeout = keys.pop().ok_or(ParseCommandError::WrongNumArgs)?;
+        let seconds: f64 = bulk_string_to_string(&timeout)?
+            .parse()
+            .map_err(|_| ParseCommandError::InvalidTimeout)?;
+        if seconds < 0.0 {
+            return Err(ParseCommandError::NegativeTimeout);
+        }
+        let timeout =
+            Duration::try_from_secs_f64(seconds).map_err(|_| ParseCommandError::InvalidTimeout)?;
+
+        Ok(Self { keys, timeout, end })
+    }
+}
+
+pub struct BPop;
+
+impl BPop {
+    /// Returns an instance of the handler shared by BLPOP and BRPOP, blocking clients on
+    /// `blocked` until a key is pushed to.
+    pub fn handler(clock: Arc<dyn Clock>, blocked: Arc<BlockedKeys>) -> BPopHandler {
+        BPopHandler { clock, blocked }
+    }
+
+    /// Returns BLPOP or BRPOP, whichever pops from the end of the arg, as a Command in the
+    /// form of Value.
+    pub fn command_value(arg: BPopArg) -> Value {
+        let name = match arg.end {
+            ListEnd::Left => "BLPOP",
+            ListEnd::Right => "BRPOP",
+        };
+        let mut v = vec![Value::BulkString(name.into())];
+        v.extend(arg.keys.into_iter().map(Value::BulkString));
+        v.push(Value::BulkString(
+            arg.timeout.as_secs_f64().to_string().into(),
+        ));
+        Value::Array(v.into())
+    }
+}
+
+pub struct BPopHandler {
+    clock: Arc<dyn Clock>,
+    blocked: Arc<BlockedKeys>,
+}
+
+impl BPopHandler {
+    /// Pops an element with `pop` from the first of the keys holding a list, replying with
+    /// the key and the element.
+    ///
+    /// If every key is empty, the reply is deferred until one of them is pushed to and the
+    /// pop succeeds, or until the timeout passes, in which case it is a null array. `pop`
+    /// returns `None` for a key that doesn't exist.
+    pub fn handle<F>(&self, arg: BPopArg, mut pop: F) -> Result<Reply, HandleCommandError>
+    where
+        F: FnMut(&BulkString, ListEnd) -> Result<Option<BulkString>, HandleCommandError>
+            + Send
+            + 'static,
+    {
+        if let Some(popped) = Self::pop_first(&arg, &mut pop)? {
+            return Ok(popped.into());
+        }
+
+        // Blocked before trying again, so that a push in between wakes the client.
+        let blocked = self.blocked.block(
+            arg.keys
+                .iter()
+                .map(|key| key.as_bytes().unwrap_or_default()),
+        );
+        let clock = self.clock.clone();
+        let deadline = (!arg.timeout.is_zero()).then(|| clock.now() + arg.timeout);
+        Ok(Reply::Deferred(Deferred::new(async move {
+            loop {
+                match Self::pop_first(&arg, &mut pop) {
+                    Ok(Some(popped)) => return popped,
+                    Ok(None) => (),
+                    Err(e) => return e.reply(),
+                }
+
+                let remaining = deadline.map(|deadline| {
+                    deadline
+                        .duration_since(clock.now())
+                        .unwrap_or(Duration::ZERO)
+                });
+                match remaining {
+                    Some(remaining) if remaining.is_zero() => {
+                        return Value::Array(Array::null());
+                    }
+                    Some(remaining) => tokio::select! {
+                        _ = blocked.woken() => (),
+                        _ = clock.sleep(remaining) => (),
+                    },
+                    None => blocked.woken().await,
+                }
+            }
+        })))
+    }
+
+    /// Pops from the first key that exists, returning the key and the element.
+    fn pop_first<F>(arg: &BPopArg, pop: &mut F) -> Result<Option<Value>, HandleCommandError>
+    where
+        F: FnMut(&BulkString, ListEnd) -> Result<Option<BulkString>, HandleCommandError>,
+    {
+        for key in &arg.keys {
+            if let Some(element) = pop(key, arg.end)? {
+                let pair = vec![Value::BulkString(key.clone()), Value::BulkString(element)];
+                return Ok(Some(Value::Array(Array::new(pair))));
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::super::test_util::command;
+    use super::super::Command;
+    use super::*;
+
+    #[test]
+    fn parse_blpop_and_brpop() {
+        for end in [ListEnd::Left, ListEnd::Right] {
+            let arg = BPopArg {
+                keys: vec!["a".into(), "b".into()],
+                timeout: Duration::from_millis(1500),
+                end,
+            };
+            match Command::try_from(BPop::command_value(arg.clone())) {
+                Ok(Command::BLPop(parsed) | Command::BRPop(parsed)) => assert_eq!(parsed, arg),
+                other => panic!("Unexpected parse result {other:?}"),
+            }
+        }
+
+        assert!(matches!(
+            Command::try_from(command(["BLPOP", "a", "soon"])),
+            Err(ParseCommandError::InvalidTimeout)
+        ));
+        assert!(matches!(
+            Command::try_from(command(["BLPOP", "a", "-1"])),
+            Err(ParseCommandError::NegativeTimeout)
+        ));
+        assert!(matches!(
+            Command::try_from(command(["BLPOP", "a"])),
+            Err(ParseCommandError::WrongArity("blpop"))
+        ));
+    }
+}
+
+#[cfg(test)]
+mod handler_test {
+    use super::super::super::clock::TestClock;
+    use super::super::super::store::Store;
+    use super::super::{Pop, Push, PushArg};
+    use super::*;
+
+    fn pop_from(
+        map: &Arc<Store>,
+    ) -> impl FnMut(&BulkString, ListEnd) -> Result<Option<BulkString>, HandleCommandError>
+           + Send
+           + 'static {
+        let handler = Pop::handler(map.clone(), Arc::new(TestClock::default()));
+        move |key, end| {
+            let popped = handler.pop(key.as_bytes().unwrap_or_default(), 1, end)?;
+            Ok(popped.and_then(|popped| popped.into_iter().next()))
+        }
+    }
+
+    fn push(map: &Arc<Store>, blocked: &BlockedKeys, key: &str, element: &str) {
+        let arg = PushArg {
+            key: key.into(),
+            elements: vec![element.into()],
+            end: ListEnd::Right,
+        };
+        Push::handler(map.clone(), Arc::new(TestClock::default()))
+            .handle(arg)
+            .unwrap();
+        blocked.wake(key.as_bytes());
+    }
+
+    fn pair(key: &str, element: &str) -> Value {
+        let pair = vec![
+            Value::BulkString(key.into()),
+            Value::BulkString(element.into()),
+        ];
+        Value::Array(Array::new(pair))
+    }
+
+    #[tokio::test]
+    async fn handle_bpop_without_blocking() {
+        let map = Arc::new(Store::default());
+        let blocked = Arc::new(BlockedKeys::default());
+        push(&map, &blocked, "b", "1");
+
+        let arg = BPopArg {
+            keys: vec!["a".into(), "b".into()],
+            timeout: Duration::ZERO,
+            end: ListEnd::Left,
+        };
+        let reply = BPop::handler(Arc::new(TestClock::default()), blocked.clone())
+            .handle(arg, pop_from(&map))
+            .unwrap();
+        assert_eq!(reply.into_value(), pair("b", "1"));
+        assert!(blocked.is_empty());
+    }
+
+    #[tokio::test]
+    async fn handle_bpop_woken_by_push() {
+        let map = Arc::new(Store::default());
+        let blocked = Arc::new(BlockedKeys::default());
+
+        let arg = BPopArg {
+            keys: vec!["a".into(), "b".into()],
+            timeout: Duration::ZERO,
+            end: ListEnd::Left,
+        };
+        let reply = BPop::handler(Arc::new(TestClock::default()), blocked.clone())
+            .handle(arg, pop_from(&map))
+            .unwrap();
+        let Reply::Deferred(deferred) = reply else {
+            panic!("Expected a deferred reply");
+        };
+        let popped = tokio::spawn(deferred.value());
+        tokio::task::yield_now().await;
+        assert!(!popped.is_finished());
+
+        push(&map, &blocked, "b", "1");
+        assert_eq!(popped.await.unwrap(), pair("b", "1"));
+        assert!(map.snapshot().is_empty());
+        assert!(blocked.is_empty());
+    }
+
+    #[tokio::test]
+    async fn handle_bpop_timeout() {
+        let clock = Arc::new(TestClock::default());
+        let map = Arc::new(Store::default());
+        let blocked = Arc::new(BlockedKeys::default());
+
+        let arg = BPopArg {
+            keys: vec!["a".into()],
+            timeout: Duration::from_secs(1),
+            end: ListEnd::Right,
+        };
+        let reply = BPop::handler(clock.clone(), blocked.clone())
+            .handle(arg, pop_from(&map))
+            .unwrap();
+        let Reply::Deferred(deferred) = reply else {
+            panic!("Expected a deferred reply");
+        };
+        let popped = tokio::spawn(deferred.value());
+        tokio::task::yield_now().await;
+        clock.advance(Duration::from_secs(1));
+
+        assert_eq!(popped.await.unwrap(), Value::Array(Array::null()));
+        assert!(blocked.is_empty());
+    }
+}
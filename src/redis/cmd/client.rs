@@ -0,0 +1,433 @@
+use std::sync::Arc;
+
+use thiserror::Error;
+
+use super::super::clients::{ClientRegistry, ClientState};
+use super::super::resp::{BulkString, Integer, SimpleString, Value};
+use super::super::tracking::TrackingOptions;
+use super::{
+    bulk_string_to_string, bulk_string_to_uint64, value_to_bulk_string, CommandArgParser,
+    ParseCommandError,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ClientCommandError {
+    #[error("Client names cannot contain spaces, newlines or special characters.")]
+    InvalidName,
+
+    #[error("The client ID you want redirect to does not exist")]
+    NoSuchRedirect,
+
+    #[error("PREFIX option requires BCAST mode to be enabled")]
+    PrefixWithoutBcast,
+
+    #[error("You can't use OPTIN and OPTOUT at the same time")]
+    OptInAndOptOut,
+
+    #[error("OPTIN and OPTOUT are not compatible with BCAST")]
+    OptWithBcast,
+
+    #[error("You can't switch BCAST mode on/off before disabling tracking for this client, and then re-enabling it with a different mode.")]
+    SwitchBcast,
+
+    #[error("CLIENT CACHING can be called only when the client is in tracking mode with OPTIN or OPTOUT mode enabled")]
+    CachingNotAllowed,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ClientSubcommand {
+    /// Sets the connection name, an empty name removes it.
+    SetName(BulkString),
+    GetName,
+    Id,
+    Info,
+    /// Turns client-side caching on with the options, or off if `None`.
+    Tracking(Option<TrackingOptions>),
+    /// Whether to track the keys read by the next command in OPTIN or OPTOUT mode.
+    Caching(bool),
+    GetRedir,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ClientArg {
+    pub subcommand: ClientSubcommand,
+}
+
+impl CommandArgParser for ClientArg {
+    /// CLIENT SETNAME connection-name
+    /// CLIENT GETNAME
+    /// CLIENT ID
+    /// CLIENT INFO
+    /// CLIENT TRACKING ON|OFF [REDIRECT client-id] [PREFIX prefix ...] [BCAST] [OPTIN]
+    ///   [OPTOUT] [NOLOOP]
+    /// CLIENT CACHING YES|NO
+    /// CLIENT GETREDIR
+    fn parse_arg(iter: &mut std::slice::Iter<'_, Value>) -> Result<Self, ParseCommandError> {
+        let args = iter
+            .map(value_to_bulk_string)
+            .collect::<Result<Vec<BulkString>, ParseCommandError>>()?;
+        let (subcommand, rest) = args.split_first().ok_or(ParseCommandError::WrongNumArgs)?;
+        let invalid =
+            |bs: &BulkString| ParseCommandError::InvalidArgument(Value::BulkString(bs.clone()));
+
+        let subcommand = match (
+            bulk_string_to_string(subcommand)?.to_lowercase().as_str(),
+            rest,
+        ) {
+            ("setname", [name]) => ClientSubcommand::SetName(name.clone()),
+            ("getname", []) => ClientSubcommand::GetName,
+            ("id", []) => ClientSubcommand::Id,
+            ("info", []) => ClientSubcommand::Info,
+            ("getredir", []) => ClientSubcommand::GetRedir,
+            ("caching", [mode]) => match bulk_string_to_string(mode)?.to_lowercase().as_str() {
+                "yes" => ClientSubcommand::Caching(true),
+                "no" => ClientSubcommand::Caching(false),
+                _ => return Err(invalid(mode)),
+            },
+            ("tracking", [mode, options @ ..]) => {
+                match bulk_string_to_string(mode)?.to_lowercase().as_str() {
+                    "on" => ClientSubcommand::Tracking(Some(Self::parse_tracking(options)?)),
+                    "off" => {
+                        Self::parse_tracking(options)?;
+                        ClientSubcommand::Tracking(None)
+                    }
+                    _ => return Err(invalid(mode)),
+                }
+            }
+            ("setname" | "getname" | "id" | "info" | "getredir" | "caching" | "tracking", _) => {
+                return Err(ParseCommandError::WrongNumArgs)
+            }
+            _ => {
+                return Err(ParseCommandError::InvalidArgument(Value::BulkString(
+                    subcommand.clone(),
+                )))
+            }
+        };
+
+        Ok(Self { subcommand })
+    }
+}
+
+impl ClientArg {
+    fn parse_tracking(options: &[BulkString]) -> Result<TrackingOptions, ParseCommandError> {
+        let mut tracking = TrackingOptions::default();
+        let mut iter = options.iter();
+        while let Some(option) = iter.next() {
+            match bulk_string_to_string(option)?.to_lowercase().as_str() {
+                "redirect" => {
+                    let id = iter.next().ok_or(ParseCommandError::WrongNumArgs)?;
+                    tracking.redirect = Some(bulk_string_to_uint64(id)?);
+                }
+                "prefix" => {
+                    let prefix = iter.next().ok_or(ParseCommandError::WrongNumArgs)?;
+                    tracking.prefixes.push(prefix.clone());
+                }
+                "bcast" => tracking.bcast = true,
+                "optin" => tracking.optin = true,
+                "optout" => tracking.optout = true,
+                "noloop" => tracking.noloop = true,
+                _ => {
+                    return Err(ParseCommandError::InvalidArgument(Value::BulkString(
+                        option.clone(),
+                    )))
+                }
+            }
+        }
+
+        Ok(tracking)
+    }
+}
+
+pub struct Client;
+
+impl Client {
+    /// Returns an instance of CLIENT command handler.
+    pub fn handler(clients: Arc<ClientRegistry>) -> ClientHandler {
+        ClientHandler { clients }
+    }
+
+    /// Checks a connection name set with CLIENT SETNAME or HELLO, returning `None` for the
+    /// empty name that removes it.
+    pub fn validate_name(name: &BulkString) -> Result<Option<String>, ClientCommandError> {
+        let name = name.as_str().ok_or(ClientCommandError::InvalidName)?;
+        // Like Redis, only printable ASCII without spaces is allowed.
+        if !name.chars().all(|c| c.is_ascii_graphic()) {
+            return Err(ClientCommandError::InvalidName);
+        }
+        Ok((!name.is_empty()).then_some(name))
+    }
+
+    /// Returns CLIENT as a Command in the form of Value.
+    pub fn command_value(arg: ClientArg) -> Value {
+        let mut v = vec![Value::BulkString("CLIENT".into())];
+        match arg.subcommand {
+            ClientSubcommand::SetName(name) => {
+                v.push(Value::BulkString("SETNAME".into()));
+                v.push(Value::BulkString(name));
+            }
+            ClientSubcommand::GetName => v.push(Value::BulkString("GETNAME".into())),
+            ClientSubcommand::Id => v.push(Value::BulkString("ID".into())),
+            ClientSubcommand::Info => v.push(Value::BulkString("INFO".into())),
+            ClientSubcommand::Tracking(tracking) => {
+                v.push(Value::BulkString("TRACKING".into()));
+                let Some(tracking) = tracking else {
+                    v.push(Value::BulkString("OFF".into()));
+                    return Value::Array(v.into());
+                };
+
+                v.push(Value::BulkString("ON".into()));
+                if let Some(id) = tracking.redirect {
+                    v.push(Value::BulkString("REDIRECT".into()));
+                    v.push(Value::BulkString(id.to_string().into()));
+                }
+                for prefix in tracking.prefixes {
+                    v.push(Value::BulkString("PREFIX".into()));
+                    v.push(Value::BulkString(prefix));
+                }
+                let flags = [
+                    (tracking.bcast, "BCAST"),
+                    (tracking.optin, "OPTIN"),
+                    (tracking.optout, "OPTOUT"),
+                    (tracking.noloop, "NOLOOP"),
+                ];
+                for (_, flag) in flags.into_iter().filter(|(set, _)| *set) {
+                    v.push(Value::BulkString(flag.into()));
+                }
+            }
+            ClientSubcommand::Caching(yes) => {
+                v.push(Value::BulkString("CACHING".into()));
+                v.push(Value::BulkString(if yes { "YES" } else { "NO" }.into()));
+            }
+            ClientSubcommand::GetRedir => v.push(Value::BulkString("GETREDIR".into())),
+        }
+        Value::Array(v.into())
+    }
+}
+
+#[derive(Debug)]
+pub struct ClientHandler {
+    clients: Arc<ClientRegistry>,
+}
+
+impl ClientHandler {
+    /// Reads or changes the identity of the client sending the command.
+    pub fn handle(
+        &self,
+        arg: ClientArg,
+        client: &mut ClientState,
+    ) -> Result<Value, ClientCommandError> {
+        match arg.subcommand {
+            ClientSubcommand::SetName(name) => {
+                client.set_name(Client::validate_name(&name)?);
+                Ok(Value::SimpleString(SimpleString::from("OK")))
+            }
+            ClientSubcommand::GetName => Ok(Value::BulkString(
+                client
+                    .name()
+                    .map(BulkString::from)
+                    .unwrap_or(BulkString::null()),
+            )),
+            ClientSubcommand::Id => Ok(Value::Integer(Integer::new(client.id() as i64))),
+            ClientSubcommand::Info => Ok(Value::BulkString(BulkString::from(format!(
+                "{}\n",
+                client.info_line()
+            )))),
+            ClientSubcommand::Tracking(tracking) => {
+                if let Some(tracking) = &tracking {
+                    self.validate_tracking(tracking, client)?;
+                }
+                client.tracking = tracking;
+                client.caching = None;
+                Ok(Value::SimpleString(SimpleString::from("OK")))
+            }
+            ClientSubcommand::Caching(yes) => {
+                let allowed = client.tracking.as_ref().is_some_and(|tracking| {
+                    if yes {
+                        tracking.optin
+                    } else {
+                        tracking.optout
+                    }
+                });
+                if !allowed {
+                    return Err(ClientCommandError::CachingNotAllowed);
+                }
+                client.caching = Some(yes);
+                Ok(Value::SimpleString(SimpleString::from("OK")))
+            }
+            ClientSubcommand::GetRedir => {
+                let redir = match &client.tracking {
+                    None => -1,
+                    Some(tracking) => tracking.redirect.unwrap_or(0) as i64,
+                };
+                Ok(Value::Integer(Integer::new(redir)))
+            }
+        }
+    }
+
+    fn validate_tracking(
+        &self,
+        tracking: &TrackingOptions,
+        client: &ClientState,
+    ) -> Result<(), ClientCommandError> {
+        if !tracking.prefixes.is_empty() && !tracking.bcast {
+            return Err(ClientCommandError::PrefixWithoutBcast);
+        }
+        if tracking.optin && tracking.optout {
+            return Err(ClientCommandError::OptInAndOptOut);
+        }
+        if tracking.bcast && (tracking.optin || tracking.optout) {
+            return Err(ClientCommandError::OptWithBcast);
+        }
+        if client
+            .tracking
+            .as_ref()
+            .is_some_and(|current| current.bcast != tracking.bcast)
+        {
+            return Err(ClientCommandError::SwitchBcast);
+        }
+        if let Some(id) = tracking.redirect {
+            // The client itself is locked while its command runs, so check it separately.
+            if id != client.id() && self.clients.get(id).is_none() {
+                return Err(ClientCommandError::NoSuchRedirect);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn command() {
+        let val = Client::command_value(ClientArg {
+            subcommand: ClientSubcommand::SetName("conn".into()),
+        });
+
+        assert_eq!(
+            val.array().unwrap().values().unwrap().to_vec(),
+            vec![
+                Value::BulkString("CLIENT".into()),
+                Value::BulkString("SETNAME".into()),
+                Value::BulkString("conn".into()),
+            ]
+        )
+    }
+}
+
+#[cfg(test)]
+mod handler_test {
+    use super::*;
+
+    fn handle(subcommand: ClientSubcommand, client: &mut ClientState) -> Value {
+        Client::handler(Arc::new(ClientRegistry::new()))
+            .handle(ClientArg { subcommand }, client)
+            .expect("Handle client unexpected error")
+    }
+
+    #[test]
+    fn handle_client_setname_and_getname() {
+        let mut client = ClientState::new(7, None);
+        assert_eq!(
+            handle(ClientSubcommand::GetName, &mut client),
+            Value::BulkString(BulkString::null())
+        );
+
+        handle(ClientSubcommand::SetName("conn-1".into()), &mut client);
+        assert_eq!(
+            handle(ClientSubcommand::GetName, &mut client),
+            Value::BulkString("conn-1".into())
+        );
+        assert_eq!(
+            handle(ClientSubcommand::Id, &mut client),
+            Value::Integer(Integer::new(7))
+        );
+
+        let err = Client::handler(Arc::new(ClientRegistry::new()))
+            .handle(
+                ClientArg {
+                    subcommand: ClientSubcommand::SetName("a b".into()),
+                },
+                &mut client,
+            )
+            .expect_err("Handle client setname no error");
+        assert_eq!(err, ClientCommandError::InvalidName);
+
+        handle(ClientSubcommand::SetName("".into()), &mut client);
+        assert_eq!(client.name(), None);
+    }
+
+    #[test]
+    fn handle_client_info() {
+        let mut client = ClientState::new(7, Some("default".into()));
+        let resp = handle(ClientSubcommand::Info, &mut client);
+        let info = resp.bulk_string().unwrap().as_str().unwrap();
+
+        assert!(info.starts_with("id=7 "));
+        assert!(info.ends_with(" user=default resp=2\n"));
+    }
+
+    #[test]
+    fn handle_client_tracking() {
+        let registry = Arc::new(ClientRegistry::new());
+        let handler = Client::handler(registry.clone());
+        let mut client = ClientState::new(7, None);
+        let tracking = |options: TrackingOptions, client: &mut ClientState| {
+            handler.handle(
+                ClientArg {
+                    subcommand: ClientSubcommand::Tracking(Some(options)),
+                },
+                client,
+            )
+        };
+
+        let err = tracking(
+            TrackingOptions {
+                redirect: Some(42),
+                ..Default::default()
+            },
+            &mut client,
+        )
+        .expect_err("Handle client tracking no error");
+        assert_eq!(err, ClientCommandError::NoSuchRedirect);
+
+        let err = tracking(
+            TrackingOptions {
+                prefixes: vec!["user:".into()],
+                ..Default::default()
+            },
+            &mut client,
+        )
+        .expect_err("Handle client tracking no error");
+        assert_eq!(err, ClientCommandError::PrefixWithoutBcast);
+
+        let other = registry.register(None, None);
+        tracking(
+            TrackingOptions {
+                redirect: Some(other.id()),
+                optin: true,
+                ..Default::default()
+            },
+            &mut client,
+        )
+        .expect("Handle client tracking unexpected error");
+        assert_eq!(
+            handle(ClientSubcommand::GetRedir, &mut client),
+            Value::Integer(Integer::new(other.id() as i64))
+        );
+
+        handle(ClientSubcommand::Caching(true), &mut client);
+        assert_eq!(client.caching, Some(true));
+        let err = handler
+            .handle(
+                ClientArg {
+                    subcommand: ClientSubcommand::Caching(false),
+                },
+                &mut client,
+            )
+            .expect_err("Handle client caching no error");
+        assert_eq!(err, ClientCommandError::CachingNotAllowed);
+    }
+}
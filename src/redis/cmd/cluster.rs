@@ -0,0 +1,707 @@
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+
+use parking_lot::RwLock;
+use thiserror::Error;
+
+use super::super::cluster::{
+    key_hash_slot, ClusterNode, ClusterState, SlotError, BUS_PORT_OFFSET, SLOT_COUNT,
+};
+use super::super::key::Key;
+use super::super::resp::{BulkString, Integer, SimpleString, Value};
+use super::super::store::Store;
+use super::{
+    bulk_string_to_string, bulk_string_to_uint64, consume_variadic_args_from_iter,
+    CommandArgParser, ParseCommandError,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ClusterCommandError {
+    #[error("This instance has cluster support disabled")]
+    Disabled,
+
+    #[error(transparent)]
+    Slot(#[from] SlotError),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ClusterSubcommand {
+    Info,
+    MyId,
+    Slots,
+    Shards,
+    Nodes,
+    /// Introduces this node to the node with the cluster bus address.
+    Meet(SocketAddr),
+    /// Returns the hash slot of the key.
+    KeySlot(BulkString),
+    CountKeysInSlot(u16),
+    /// Returns up to the given number of keys in the slot.
+    GetKeysInSlot(u16, u64),
+    /// Makes this node the owner of the slots, none of which may have an owner.
+    AddSlots(Vec<u16>),
+    /// Leaves the slots without an owner.
+    DelSlots(Vec<u16>),
+    SetSlot(u16, SetSlotAction),
+}
+
+/// What CLUSTER SETSLOT does with a slot, the nodes being given by ID.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum SetSlotAction {
+    /// Moves the slot from the node to this one.
+    Importing(String),
+    /// Moves the slot of this node to the node.
+    Migrating(String),
+    /// Makes the node the owner, ending a migration.
+    Node(String),
+    /// Ends a migration without changing the owner.
+    Stable,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ClusterArg {
+    pub subcommand: ClusterSubcommand,
+}
+
+impl CommandArgParser for ClusterArg {
+    /// CLUSTER INFO
+    /// CLUSTER MYID
+    /// CLUSTER SLOTS
+    /// CLUSTER SHARDS
+    /// CLUSTER NODES
+    /// CLUSTER MEET ip port [cluster-bus-port]
+    /// CLUSTER KEYSLOT key
+    /// CLUSTER COUNTKEYSINSLOT slot
+    /// CLUSTER GETKEYSINSLOT slot count
+    /// CLUSTER ADDSLOTS slot [slot ...]
+    /// CLUSTER DELSLOTS slot [slot ...]
+    /// CLUSTER SETSLOT slot <IMPORTING node-id | MIGRATING node-id | NODE node-id | STABLE>
+    fn parse_arg(iter: &mut std::slice::Iter<'_, Value>) -> Result<Self, ParseCommandError> {
+        let args = consume_variadic_args_from_iter(iter, 1)?;
+        let (subcommand, rest) = args.split_first().unwrap();
+        let invalid =
+            |bs: &BulkString| ParseCommandError::InvalidArgument(Value::BulkString(bs.clone()));
+        let port = |bs: &BulkString| {
+            let port = bulk_string_to_uint64(bs)?;
+            u16::try_from(port).map_err(|_| invalid(bs))
+        };
+        let slot = |bs: &BulkString| match bulk_string_to_uint64(bs)? {
+            slot if slot < SLOT_COUNT as u64 => Ok(slot as u16),
+            _ => Err(invalid(bs)),
+        };
+
+        let subcommand = match (
+            bulk_string_to_string(subcommand)?.to_lowercase().as_str(),
+            rest,
+        ) {
+            ("info", []) => ClusterSubcommand::Info,
+            ("myid", []) => ClusterSubcommand::MyId,
+            ("slots", []) => ClusterSubcommand::Slots,
+            ("shards", []) => ClusterSubcommand::Shards,
+            ("nodes", []) => ClusterSubcommand::Nodes,
+            ("meet", [ip, client_port, cport @ ..]) if cport.len() <= 1 => {
+                let ip: IpAddr = bulk_string_to_string(ip)?
+                    .parse()
+                    .map_err(|_| invalid(ip))?;
+                let cport = match cport.first() {
+                    Some(cport) => port(cport)?,
+                    None => port(client_port)?
+                        .checked_add(BUS_PORT_OFFSET)
+                        .ok_or_else(|| invalid(client_port))?,
+                };
+                ClusterSubcommand::Meet(SocketAddr::new(ip, cport))
+            }
+            ("keyslot", [key]) => ClusterSubcommand::KeySlot(key.clone()),
+            ("countkeysinslot", [s]) => ClusterSubcommand::CountKeysInSlot(slot(s)?),
+            ("getkeysinslot", [s, count]) => {
+                ClusterSubcommand::GetKeysInSlot(slot(s)?, bulk_string_to_uint64(count)?)
+            }
+            ("addslots", slots) if !slots.is_empty() => {
+                ClusterSubcommand::AddSlots(slots.iter().map(slot).collect::<Result<_, _>>()?)
+            }
+            ("delslots", slots) if !slots.is_empty() => {
+                ClusterSubcommand::DelSlots(slots.iter().map(slot).collect::<Result<_, _>>()?)
+            }
+            ("setslot", [s, action, id @ ..]) => {
+                let action = match (bulk_string_to_string(action)?.to_lowercase().as_str(), id) {
+                    ("importing", [id]) => SetSlotAction::Importing(bulk_string_to_string(id)?),
+                    ("migrating", [id]) => SetSlotAction::Migrating(bulk_string_to_string(id)?),
+                    ("node", [id]) => SetSlotAction::Node(bulk_string_to_string(id)?),
+                    ("stable", []) => SetSlotAction::Stable,
+                    _ => return Err(invalid(action)),
+                };
+                ClusterSubcommand::SetSlot(slot(s)?, action)
+            }
+            (
+                "info" | "myid" | "slots" | "shards" | "nodes" | "meet" | "keyslot"
+                | "countkeysinslot" | "getkeysinslot" | "addslots" | "delslots" | "setslot",
+                _,
+            ) => return Err(ParseCommandError::WrongNumArgs),
+            _ => {
+                return Err(ParseCommandError::InvalidArgument(Value::BulkString(
+                    subcommand.clone(),
+                )))
+            }
+        };
+
+        Ok(Self { subcommand })
+    }
+}
+
+pub struct Cluster;
+
+impl Cluster {
+    /// Returns an instance of CLUSTER command handler, `cluster` being `None` unless the
+    /// server runs in cluster mode.
+    pub fn handler(
+        cluster: Option<Arc<RwLock<ClusterState>>>,
+        store: Arc<Store>,
+        repl_offset: u64,
+    ) -> ClusterHandler {
+        ClusterHandler {
+            cluster,
+            store,
+            repl_offset,
+            require_full_coverage: true,
+        }
+    }
+
+    /// Returns CLUSTER as a Command in the form of Value.
+    pub fn command_value(arg: ClusterArg) -> Value {
+        let mut v = vec![Value::BulkString("CLUSTER".into())];
+        match arg.subcommand {
+            ClusterSubcommand::Info => v.push(bulk("INFO")),
+            ClusterSubcommand::MyId => v.push(bulk("MYID")),
+            ClusterSubcommand::Slots => v.push(bulk("SLOTS")),
+            ClusterSubcommand::Shards => v.push(bulk("SHARDS")),
+            ClusterSubcommand::Nodes => v.push(bulk("NODES")),
+            // The client port isn't known, so the bus port is always given.
+            ClusterSubcommand::Meet(addr) => {
+                let client_port = addr.port().saturating_sub(BUS_PORT_OFFSET);
+                v.push(bulk("MEET"));
+                v.push(bulk_ip(addr.ip()));
+                v.push(bulk(&client_port.to_string()));
+                v.push(bulk(&addr.port().to_string()));
+            }
+            ClusterSubcommand::KeySlot(key) => {
+                v.push(bulk("KEYSLOT"));
+                v.push(Value::BulkString(key));
+            }
+            ClusterSubcommand::CountKeysInSlot(slot) => {
+                v.push(bulk("COUNTKEYSINSLOT"));
+                v.push(bulk(&slot.to_string()));
+            }
+            ClusterSubcommand::GetKeysInSlot(slot, count) => {
+                v.push(bulk("GETKEYSINSLOT"));
+                v.push(bulk(&slot.to_string()));
+                v.push(bulk(&count.to_string()));
+            }
+            ClusterSubcommand::AddSlots(slots) => {
+                v.push(bulk("ADDSLOTS"));
+                v.extend(slots.iter().map(|slot| bulk(&slot.to_string())));
+            }
+            ClusterSubcommand::DelSlots(slots) => {
+                v.push(bulk("DELSLOTS"));
+                v.extend(slots.iter().map(|slot| bulk(&slot.to_string())));
+            }
+            ClusterSubcommand::SetSlot(slot, action) => {
+                v.push(bulk("SETSLOT"));
+                v.push(bulk(&slot.to_string()));
+                match action {
+                    SetSlotAction::Importing(id) => v.extend([bulk("IMPORTING"), bulk(&id)]),
+                    SetSlotAction::Migrating(id) => v.extend([bulk("MIGRATING"), bulk(&id)]),
+                    SetSlotAction::Node(id) => v.extend([bulk("NODE"), bulk(&id)]),
+                    SetSlotAction::Stable => v.push(bulk("STABLE")),
+                }
+            }
+        }
+        Value::Array(v.into())
+    }
+}
+
+#[derive(Debug)]
+pub struct ClusterHandler {
+    cluster: Option<Arc<RwLock<ClusterState>>>,
+    store: Arc<Store>,
+    repl_offset: u64,
+    require_full_coverage: bool,
+}
+
+impl ClusterHandler {
+    /// Reports the cluster as up with slots left unserved, see
+    /// `ClusterState::is_ok`.
+    pub fn with_require_full_coverage(mut self, require_full_coverage: bool) -> Self {
+        self.require_full_coverage = require_full_coverage;
+        self
+    }
+
+    /// Describes the cluster, so clients can find out which node serves which slots.
+    ///
+    /// Nodes bound to every interface are reported with the address the client connected
+    /// to, `laddr`.
+    pub fn handle(
+        &self,
+        arg: ClusterArg,
+        laddr: Option<SocketAddr>,
+    ) -> Result<Value, ClusterCommandError> {
+        let cluster = self.cluster.as_ref().ok_or(ClusterCommandError::Disabled)?;
+        let ok = || Ok(Value::SimpleString(SimpleString::from("OK")));
+        match &arg.subcommand {
+            ClusterSubcommand::Meet(addr) => {
+                // The handshake itself is done by the cluster bus.
+                cluster.write().meet(*addr);
+                return ok();
+            }
+            ClusterSubcommand::AddSlots(slots) => {
+                cluster.write().add_slots(slots)?;
+                return ok();
+            }
+            ClusterSubcommand::DelSlots(slots) => {
+                cluster.write().del_slots(slots)?;
+                return ok();
+            }
+            ClusterSubcommand::SetSlot(slot, action) => {
+                let keys = self.store.count_keys_in_slot(*slot);
+                let mut cluster = cluster.write();
+                match action {
+                    SetSlotAction::Importing(id) => cluster.set_importing(*slot, id)?,
+                    SetSlotAction::Migrating(id) => cluster.set_migrating(*slot, id)?,
+                    SetSlotAction::Node(id) => cluster.set_owner(*slot, id, keys)?,
+                    SetSlotAction::Stable => cluster.set_stable(*slot),
+                }
+                return ok();
+            }
+            _ => (),
+        }
+        // The store indexes keys by slot in cluster mode, so only these need the keys.
+        match arg.subcommand {
+            ClusterSubcommand::KeySlot(key) => {
+                return Ok(integer(key_hash_slot(&Key::from(&key)) as i64));
+            }
+            ClusterSubcommand::CountKeysInSlot(slot) => {
+                return Ok(integer(self.store.count_keys_in_slot(slot) as i64));
+            }
+            ClusterSubcommand::GetKeysInSlot(slot, count) => {
+                let count = usize::try_from(count).unwrap_or(usize::MAX);
+                let keys = self
+                    .store
+                    .keys_in_slot(slot, count)
+                    .iter()
+                    .map(|key| Value::BulkString(key.as_bytes().to_vec().into()))
+                    .collect::<Vec<_>>();
+                return Ok(Value::Array(keys.into()));
+            }
+            _ => (),
+        }
+        let cluster = cluster.read();
+        let ip = |node: &ClusterNode| match laddr {
+            Some(laddr) if node.ip.is_unspecified() => laddr.ip(),
+            _ => node.ip,
+        };
+
+        let value = match arg.subcommand {
+            ClusterSubcommand::Info => {
+                let info: String = cluster
+                    .info(self.require_full_coverage)
+                    .iter()
+                    .map(|line| format!("{line}\r\n"))
+                    .collect();
+                Value::BulkString(info.into())
+            }
+            ClusterSubcommand::MyId => Value::BulkString(cluster.myself().id.as_str().into()),
+            ClusterSubcommand::Nodes => {
+                let nodes: String = cluster
+                    .node_lines()
+                    .iter()
+                    .map(|line| format!("{line}\n"))
+                    .collect();
+                Value::BulkString(nodes.into())
+            }
+            ClusterSubcommand::Meet(_)
+            | ClusterSubcommand::KeySlot(_)
+            | ClusterSubcommand::CountKeysInSlot(_)
+            | ClusterSubcommand::GetKeysInSlot(..)
+            | ClusterSubcommand::AddSlots(_)
+            | ClusterSubcommand::DelSlots(_)
+            | ClusterSubcommand::SetSlot(..) => unreachable!("Handled above"),
+            ClusterSubcommand::Slots => {
+                let slots = cluster
+                    .slot_ranges()
+                    .into_iter()
+                    .map(|(range, node)| {
+                        let v = vec![
+                            integer(*range.start() as i64),
+                            integer(*range.end() as i64),
+                            Value::Array(
+                                vec![
+                                    bulk_ip(ip(node)),
+                                    integer(node.port as i64),
+                                    Value::BulkString(node.id.as_str().into()),
+                                ]
+                                .into(),
+                            ),
+                        ];
+                        Value::Array(v.into())
+                    })
+                    .collect::<Vec<_>>();
+                Value::Array(slots.into())
+            }
+            ClusterSubcommand::Shards => {
+                let shards = cluster
+                    .shards()
+                    .into_iter()
+                    .map(|(node, ranges)| {
+                        let slots = ranges
+                            .iter()
+                            .flat_map(|r| [integer(*r.start() as i64), integer(*r.end() as i64)])
+                            .collect::<Vec<_>>();
+                        // Only the offset of this node is known.
+                        let offset = if node.id == cluster.myself().id {
+                            self.repl_offset
+                        } else {
+                            0
+                        };
+                        let node = vec![
+                            bulk("id"),
+                            Value::BulkString(node.id.as_str().into()),
+                            bulk("port"),
+                            integer(node.port as i64),
+                            bulk("ip"),
+                            bulk_ip(ip(node)),
+                            bulk("endpoint"),
+                            bulk_ip(ip(node)),
+                            bulk("role"),
+                            bulk("master"),
+                            bulk("replication-offset"),
+                            integer(offset as i64),
+                            bulk("health"),
+                            bulk(if node.failed { "fail" } else { "online" }),
+                        ];
+                        let v = vec![
+                            bulk("slots"),
+                            Value::Array(slots.into()),
+                            bulk("nodes"),
+                            Value::Array(vec![Value::Array(node.into())].into()),
+                        ];
+                        Value::Array(v.into())
+                    })
+                    .collect::<Vec<_>>();
+                Value::Array(shards.into())
+            }
+        };
+        Ok(value)
+    }
+}
+
+fn bulk(s: &str) -> Value {
+    Value::BulkString(BulkString::from(s))
+}
+
+fn bulk_ip(ip: IpAddr) -> Value {
+    Value::BulkString(BulkString::from(ip.to_string()))
+}
+
+fn integer(i: i64) -> Value {
+    Value::Integer(Integer::new(i))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn command() {
+        let val = Cluster::command_value(ClusterArg {
+            subcommand: ClusterSubcommand::Slots,
+        });
+
+        assert_eq!(
+            val.array().unwrap().values().unwrap().to_vec(),
+            vec![
+                Value::BulkString("CLUSTER".into()),
+                Value::BulkString("SLOTS".into()),
+            ]
+        )
+    }
+
+    #[test]
+    fn parse_slot_subcommands() {
+        let parse = |args: &[&str]| {
+            let args: Vec<_> = args.iter().map(|arg| bulk(arg)).collect();
+            ClusterArg::parse_arg(&mut args.iter()).map(|arg| arg.subcommand)
+        };
+
+        assert_eq!(
+            parse(&["keyslot", "key"]).unwrap(),
+            ClusterSubcommand::KeySlot("key".into())
+        );
+        assert_eq!(
+            parse(&["COUNTKEYSINSLOT", "16383"]).unwrap(),
+            ClusterSubcommand::CountKeysInSlot(16383)
+        );
+        assert_eq!(
+            parse(&["getkeysinslot", "7", "10"]).unwrap(),
+            ClusterSubcommand::GetKeysInSlot(7, 10)
+        );
+        assert!(matches!(
+            parse(&["countkeysinslot", "16384"]),
+            Err(ParseCommandError::InvalidArgument(_))
+        ));
+        assert!(matches!(
+            parse(&["getkeysinslot", "7"]),
+            Err(ParseCommandError::WrongNumArgs)
+        ));
+
+        for subcommand in [
+            ClusterSubcommand::AddSlots(vec![0, 1, 16383]),
+            ClusterSubcommand::DelSlots(vec![5]),
+            ClusterSubcommand::SetSlot(7, SetSlotAction::Importing("a".into())),
+            ClusterSubcommand::SetSlot(7, SetSlotAction::Migrating("b".into())),
+            ClusterSubcommand::SetSlot(7, SetSlotAction::Node("c".into())),
+            ClusterSubcommand::SetSlot(7, SetSlotAction::Stable),
+        ] {
+            let value = Cluster::command_value(ClusterArg {
+                subcommand: subcommand.clone(),
+            });
+            let args = value.array().unwrap().values().unwrap();
+            assert_eq!(
+                ClusterArg::parse_arg(&mut args[1..].iter())
+                    .unwrap()
+                    .subcommand,
+                subcommand
+            );
+        }
+        assert!(matches!(
+            parse(&["addslots"]),
+            Err(ParseCommandError::WrongNumArgs)
+        ));
+        assert!(matches!(
+            parse(&["addslots", "1", "16384"]),
+            Err(ParseCommandError::InvalidArgument(_))
+        ));
+        assert!(matches!(
+            parse(&["setslot", "7", "node"]),
+            Err(ParseCommandError::InvalidArgument(_))
+        ));
+        assert!(matches!(
+            parse(&["setslot", "7", "stable", "a"]),
+            Err(ParseCommandError::InvalidArgument(_))
+        ));
+    }
+}
+
+#[cfg(test)]
+mod handler_test {
+    use std::net::Ipv4Addr;
+
+    use super::super::super::handler::StoredData;
+    use super::*;
+
+    fn handle(cluster: &Arc<RwLock<ClusterState>>, subcommand: ClusterSubcommand) -> Value {
+        let laddr = SocketAddr::from((Ipv4Addr::new(10, 0, 0, 1), 7000));
+        Cluster::handler(Some(cluster.clone()), Arc::new(Store::default()), 42)
+            .handle(ClusterArg { subcommand }, Some(laddr))
+            .expect("Handle cluster unexpected error")
+    }
+
+    #[test]
+    fn handle_cluster_disabled() {
+        let err = Cluster::handler(None, Arc::new(Store::default()), 0)
+            .handle(
+                ClusterArg {
+                    subcommand: ClusterSubcommand::Info,
+                },
+                None,
+            )
+            .expect_err("Handle cluster no error");
+        assert_eq!(err, ClusterCommandError::Disabled);
+    }
+
+    #[test]
+    fn handle_cluster() {
+        let myself = ClusterNode::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 7000, 17000);
+        let other = ClusterNode::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 7001, 17001);
+        let mut state = ClusterState::new(myself.clone());
+        state.assign(8192..=16383, other.clone());
+        let cluster = Arc::new(RwLock::new(state));
+
+        let info = handle(&cluster, ClusterSubcommand::Info);
+        let info = info.bulk_string().unwrap().as_str().unwrap();
+        assert!(info.starts_with("cluster_state:fail\r\ncluster_slots_assigned:8192\r\n"));
+        assert_eq!(
+            handle(&cluster, ClusterSubcommand::AddSlots((0..8192).collect())),
+            Value::SimpleString(SimpleString::from("OK"))
+        );
+
+        let info = handle(&cluster, ClusterSubcommand::Info);
+        let info = info.bulk_string().unwrap().as_str().unwrap();
+        assert!(info.starts_with("cluster_state:ok\r\ncluster_slots_assigned:16384\r\n"));
+        assert!(info.contains("cluster_known_nodes:2\r\n"));
+
+        assert_eq!(
+            handle(&cluster, ClusterSubcommand::MyId),
+            Value::BulkString(myself.id.as_str().into())
+        );
+
+        // The unspecified address of this node is replaced with the one connected to.
+        assert_eq!(
+            handle(&cluster, ClusterSubcommand::Slots),
+            Value::Array(
+                vec![
+                    Value::Array(
+                        vec![
+                            integer(0),
+                            integer(8191),
+                            Value::Array(
+                                vec![bulk("10.0.0.1"), integer(7000), bulk(&myself.id)].into()
+                            ),
+                        ]
+                        .into()
+                    ),
+                    Value::Array(
+                        vec![
+                            integer(8192),
+                            integer(16383),
+                            Value::Array(
+                                vec![bulk("127.0.0.1"), integer(7001), bulk(&other.id)].into()
+                            ),
+                        ]
+                        .into()
+                    ),
+                ]
+                .into()
+            )
+        );
+
+        let shards = handle(&cluster, ClusterSubcommand::Shards);
+        let shards = shards.array().unwrap().values().unwrap().to_vec();
+        assert_eq!(shards.len(), 2);
+        let shard = shards[1].array().unwrap().values().unwrap().to_vec();
+        assert_eq!(shard[0], bulk("slots"));
+        assert_eq!(
+            shard[1],
+            Value::Array(vec![integer(8192), integer(16383)].into())
+        );
+        let nodes = shard[3].array().unwrap().values().unwrap().to_vec();
+        let node = nodes[0].array().unwrap().values().unwrap().to_vec();
+        assert_eq!(
+            node[..4],
+            [bulk("id"), bulk(&other.id), bulk("port"), integer(7001)]
+        );
+        assert_eq!(node[11], integer(0));
+        let shard = shards[0].array().unwrap().values().unwrap().to_vec();
+        let nodes = shard[3].array().unwrap().values().unwrap().to_vec();
+        let node = nodes[0].array().unwrap().values().unwrap().to_vec();
+        assert_eq!(node[5], bulk("10.0.0.1"));
+        assert_eq!(node[11], integer(42));
+
+        let nodes = handle(&cluster, ClusterSubcommand::Nodes);
+        let nodes = nodes.bulk_string().unwrap().as_str().unwrap();
+        assert!(nodes.starts_with(&format!("{} 0.0.0.0:7000@17000 myself,master", myself.id)));
+        assert!(nodes.contains("127.0.0.1:7001@17001 master - 0 0 0 connected 8192-16383\n"));
+
+        let addr = SocketAddr::from((Ipv4Addr::LOCALHOST, 17002));
+        assert_eq!(
+            handle(&cluster, ClusterSubcommand::Meet(addr)),
+            Value::SimpleString(SimpleString::from("OK"))
+        );
+        assert_eq!(cluster.write().take_meets(), vec![addr]);
+    }
+
+    #[test]
+    fn handle_slot_changes() {
+        let myself = ClusterNode::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 7000, 17000);
+        let other = ClusterNode::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 7001, 17001);
+        let mut state = ClusterState::new(myself.clone());
+        state.assign(100..=100, other.clone());
+        let cluster = Arc::new(RwLock::new(state));
+        let store = Store::with_slot_index(4);
+        let key = Key::from("{user}:1");
+        let slot = key_hash_slot(b"user");
+        store
+            .write(&key)
+            .insert(key, StoredData::new("v".into(), None));
+        let handler = Cluster::handler(Some(cluster.clone()), Arc::new(store), 0);
+        let handle = |subcommand| handler.handle(ClusterArg { subcommand }, None);
+        let ok = Ok(Value::SimpleString(SimpleString::from("OK")));
+
+        assert_eq!(handle(ClusterSubcommand::AddSlots(vec![1, slot])), ok);
+        assert_eq!(
+            handle(ClusterSubcommand::AddSlots(vec![2, 1])),
+            Err(ClusterCommandError::Slot(SlotError::Busy(1)))
+        );
+        assert_eq!(handle(ClusterSubcommand::DelSlots(vec![1])), ok);
+        assert_eq!(cluster.read().owner(1), None);
+
+        let set_slot = |slot, action| handle(ClusterSubcommand::SetSlot(slot, action));
+        assert_eq!(
+            set_slot(100, SetSlotAction::Importing(other.id.clone())),
+            ok
+        );
+        assert_eq!(set_slot(100, SetSlotAction::Node(myself.id.clone())), ok);
+        assert_eq!(
+            cluster.read().owner(100),
+            Some(&ClusterNode {
+                config_epoch: 1,
+                ..myself.clone()
+            })
+        );
+
+        // The slot still holding a key can't be given away.
+        assert_eq!(
+            set_slot(slot, SetSlotAction::Migrating(other.id.clone())),
+            ok
+        );
+        let err = set_slot(slot, SetSlotAction::Node(other.id.clone())).unwrap_err();
+        assert_eq!(err, ClusterCommandError::Slot(SlotError::HoldingKeys(slot)));
+        assert_eq!(set_slot(slot, SetSlotAction::Stable), ok);
+        assert_eq!(
+            set_slot(slot, SetSlotAction::Importing("unknown".into())),
+            Err(ClusterCommandError::Slot(SlotError::AlreadyOwner(slot)))
+        );
+        assert_eq!(
+            set_slot(200, SetSlotAction::Node("unknown".into())),
+            Err(ClusterCommandError::Slot(SlotError::UnknownNode(
+                "unknown".into()
+            )))
+        );
+    }
+
+    #[test]
+    fn handle_slot_keys() {
+        let myself = ClusterNode::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 7000, 17000);
+        let cluster = Arc::new(RwLock::new(ClusterState::new(myself)));
+        let store = Store::with_slot_index(4);
+        for key in ["{user}:1", "{user}:2", "other"] {
+            let key = Key::from(key);
+            store
+                .write(&key)
+                .insert(key, StoredData::new("v".into(), None));
+        }
+        let handler = Cluster::handler(Some(cluster), Arc::new(store), 0);
+        let handle = |subcommand| {
+            handler
+                .handle(ClusterArg { subcommand }, None)
+                .expect("Handle cluster unexpected error")
+        };
+
+        let slot = key_hash_slot(b"user");
+        assert_eq!(
+            handle(ClusterSubcommand::KeySlot("{user}:1".into())),
+            integer(slot as i64)
+        );
+        assert_eq!(
+            handle(ClusterSubcommand::KeySlot("foo".into())),
+            integer(12182)
+        );
+        assert_eq!(handle(ClusterSubcommand::CountKeysInSlot(slot)), integer(2));
+        let keys = handle(ClusterSubcommand::GetKeysInSlot(slot, 10));
+        let mut keys = keys.array().unwrap().values().unwrap().to_vec();
+        keys.sort_by_key(|key| key.bulk_string().unwrap().as_str().unwrap().to_string());
+        assert_eq!(keys, [bulk("{user}:1"), bulk("{user}:2")]);
+        assert_eq!(
+            handle(ClusterSubcommand::GetKeysInSlot(slot, 0)),
+            Value::Array(vec![].into())
+        );
+    }
+}
@@ -0,0 +1,342 @@
+use thiserror::Error;
+
+use super::super::resp::{Array, BulkString, Integer, Map, SimpleString, Value};
+use super::table::{self, CommandSpec, KeySpec, COMMAND_TABLE};
+use super::{bulk_string_to_string, value_to_bulk_string, CommandArgParser, ParseCommandError};
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum CommandsError {
+    #[error("Invalid command specified")]
+    InvalidCommand,
+
+    #[error("Invalid number of arguments specified for command")]
+    InvalidNumArgs,
+
+    #[error("The command has no key arguments")]
+    NoKeys,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum CommandSubcommand {
+    /// Details of every command.
+    List,
+    Count,
+    Info(Vec<String>),
+    Docs(Vec<String>),
+    /// Extracts the keys of a full command.
+    GetKeys(Vec<BulkString>),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct CommandArg {
+    pub subcommand: CommandSubcommand,
+}
+
+impl CommandArgParser for CommandArg {
+    /// COMMAND
+    /// COMMAND COUNT
+    /// COMMAND INFO [command-name ...]
+    /// COMMAND DOCS [command-name ...]
+    /// COMMAND GETKEYS command [arg ...]
+    fn parse_arg(iter: &mut std::slice::Iter<'_, Value>) -> Result<Self, ParseCommandError> {
+        let args = iter
+            .map(value_to_bulk_string)
+            .collect::<Result<Vec<BulkString>, ParseCommandError>>()?;
+        let strings = |args: &[BulkString]| {
+            args.iter()
+                .map(bulk_string_to_string)
+                .collect::<Result<Vec<String>, ParseCommandError>>()
+        };
+
+        let subcommand = match args.split_first() {
+            None => CommandSubcommand::List,
+            Some((subcommand, rest)) => {
+                match (
+                    bulk_string_to_string(subcommand)?.to_lowercase().as_str(),
+                    rest,
+                ) {
+                    ("count", []) => CommandSubcommand::Count,
+                    ("info", names) => CommandSubcommand::Info(strings(names)?),
+                    ("docs", names) => CommandSubcommand::Docs(strings(names)?),
+                    ("getkeys", args) if !args.is_empty() => {
+                        CommandSubcommand::GetKeys(args.to_vec())
+                    }
+                    ("count" | "getkeys", _) => return Err(ParseCommandError::WrongNumArgs),
+                    _ => {
+                        return Err(ParseCommandError::InvalidArgument(Value::BulkString(
+                            subcommand.clone(),
+                        )))
+                    }
+                }
+            }
+        };
+
+        Ok(Self { subcommand })
+    }
+}
+
+pub struct Commands;
+
+impl Commands {
+    /// Returns an instance of COMMAND command handler.
+    pub fn handler() -> CommandsHandler {
+        CommandsHandler
+    }
+
+    /// Returns COMMAND as a Command in the form of Value.
+    pub fn command_value(arg: CommandArg) -> Value {
+        let mut v = vec![Value::BulkString("COMMAND".into())];
+        let mut push_all = |name: &str, args: Vec<BulkString>| {
+            v.push(Value::BulkString(name.into()));
+            v.extend(args.into_iter().map(Value::BulkString));
+        };
+        match arg.subcommand {
+            CommandSubcommand::List => (),
+            CommandSubcommand::Count => push_all("COUNT", vec![]),
+            CommandSubcommand::Info(names) => {
+                push_all("INFO", names.into_iter().map(BulkString::from).collect())
+            }
+            CommandSubcommand::Docs(names) => {
+                push_all("DOCS", names.into_iter().map(BulkString::from).collect())
+            }
+            CommandSubcommand::GetKeys(args) => push_all("GETKEYS", args),
+        }
+        Value::Array(v.into())
+    }
+}
+
+#[derive(Debug)]
+pub struct CommandsHandler;
+
+impl CommandsHandler {
+    /// Reports on the commands in the command table.
+    pub fn handle(&self, arg: CommandArg) -> Result<Value, CommandsError> {
+        match arg.subcommand {
+            CommandSubcommand::List => Ok(array(COMMAND_TABLE.iter().map(command_info).collect())),
+            CommandSubcommand::Count => {
+                Ok(Value::Integer(Integer::new(COMMAND_TABLE.len() as i64)))
+            }
+            CommandSubcommand::Info(names) => {
+                let specs: Vec<Option<&CommandSpec>> = if names.is_empty() {
+                    COMMAND_TABLE.iter().map(Some).collect()
+                } else {
+                    names.iter().map(|name| table::lookup(name)).collect()
+                };
+                Ok(array(
+                    specs
+                        .into_iter()
+                        .map(|spec| match spec {
+                            Some(spec) => command_info(spec),
+                            None => Value::Array(Array::null()),
+                        })
+                        .collect(),
+                ))
+            }
+            CommandSubcommand::Docs(names) => {
+                let specs: Vec<&CommandSpec> = if names.is_empty() {
+                    COMMAND_TABLE.iter().collect()
+                } else {
+                    names
+                        .iter()
+                        .filter_map(|name| table::lookup(name))
+                        .collect()
+                };
+                Ok(array(
+                    specs
+                        .into_iter()
+                        .flat_map(|spec| [bulk_string(spec.name), command_docs(spec)])
+                        .collect(),
+                ))
+            }
+            CommandSubcommand::GetKeys(args) => {
+                let name =
+                    bulk_string_to_string(&args[0]).map_err(|_| CommandsError::InvalidCommand)?;
+                let spec = table::lookup(&name).ok_or(CommandsError::InvalidCommand)?;
+                if !spec.arity_matches(args.len()) {
+                    return Err(CommandsError::InvalidNumArgs);
+                }
+
+                let keys: Vec<Value> = spec
+                    .key_positions_in(&args)
+                    .into_iter()
+                    .filter_map(|pos| args.get(pos))
+                    .map(|key| Value::BulkString(key.clone()))
+                    .collect();
+                if keys.is_empty() {
+                    return Err(CommandsError::NoKeys);
+                }
+                Ok(array(keys))
+            }
+        }
+    }
+}
+
+fn array(values: Vec<Value>) -> Value {
+    Value::Array(Array::new(values))
+}
+
+fn bulk_string(s: &str) -> Value {
+    Value::BulkString(s.into())
+}
+
+fn status_array(strings: impl Iterator<Item = String>) -> Value {
+    array(
+        strings
+            .map(|s| Value::SimpleString(SimpleString::from(s.as_str())))
+            .collect(),
+    )
+}
+
+/// Returns the command in the COMMAND INFO format: name, arity, flags, first key, last key,
+/// key step, ACL categories, tips, key specs and subcommands.
+fn command_info(spec: &CommandSpec) -> Value {
+    let int = |n: i64| Value::Integer(Integer::new(n));
+    array(vec![
+        bulk_string(spec.name),
+        int(spec.arity),
+        status_array(spec.flags.iter().map(|f| f.to_string())),
+        int(spec.first_key),
+        int(spec.last_key),
+        int(spec.key_step),
+        status_array(spec.categories.iter().map(|c| format!("@{c}"))),
+        array(vec![]),
+        array(spec.key_specs.iter().map(key_spec).collect()),
+        array(vec![]),
+    ])
+}
+
+/// Returns the key spec in the COMMAND INFO format, a map of its flags and how the keys
+/// are searched for and found.
+fn key_spec(spec: &KeySpec) -> Value {
+    let int = |n: usize| Value::Integer(Integer::new(n as i64));
+    let map = |pairs: Vec<(&str, Value)>| {
+        Value::Map(Map::new(
+            pairs
+                .into_iter()
+                .map(|(field, value)| (bulk_string(field), value))
+                .collect(),
+        ))
+    };
+    map(vec![
+        (
+            "flags",
+            status_array(spec.flags.iter().map(|f| f.to_string())),
+        ),
+        (
+            "begin_search",
+            map(vec![
+                ("type", bulk_string("index")),
+                ("spec", map(vec![("index", int(spec.begin_search))])),
+            ]),
+        ),
+        (
+            "find_keys",
+            map(vec![
+                ("type", bulk_string("keynum")),
+                (
+                    "spec",
+                    map(vec![
+                        ("keynumidx", int(spec.keynum_index)),
+                        ("firstkey", int(spec.first_key)),
+                        ("keystep", int(spec.key_step)),
+                    ]),
+                ),
+            ]),
+        ),
+    ])
+}
+
+/// Returns the command in the COMMAND DOCS format, a flat array of field names and values.
+fn command_docs(spec: &CommandSpec) -> Value {
+    array(vec![
+        bulk_string("summary"),
+        bulk_string(spec.summary),
+        bulk_string("group"),
+        bulk_string(spec.group),
+    ])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn command() {
+        let val = Commands::command_value(CommandArg {
+            subcommand: CommandSubcommand::Info(vec!["get".into()]),
+        });
+
+        assert_eq!(
+            val.array().unwrap().values().unwrap().to_vec(),
+            vec![
+                Value::BulkString("COMMAND".into()),
+                Value::BulkString("INFO".into()),
+                Value::BulkString("get".into()),
+            ]
+        )
+    }
+}
+
+#[cfg(test)]
+mod handler_test {
+    use super::*;
+
+    fn handle(subcommand: CommandSubcommand) -> Result<Value, CommandsError> {
+        Commands::handler().handle(CommandArg { subcommand })
+    }
+
+    #[test]
+    fn handle_info() {
+        let resp = handle(CommandSubcommand::Info(vec!["GET".into(), "nope".into()]))
+            .expect("Handle command info unexpected error");
+        let values = resp.array().unwrap().values().unwrap();
+
+        let get = values[0].array().unwrap().values().unwrap();
+        assert_eq!(get[0], Value::BulkString("get".into()));
+        assert_eq!(get[1], Value::Integer(Integer::new(2)));
+        assert_eq!(values[1], Value::Array(Array::null()));
+    }
+
+    #[test]
+    fn handle_getkeys() {
+        let resp = handle(CommandSubcommand::GetKeys(vec![
+            "SET".into(),
+            "key".into(),
+            "value".into(),
+        ]))
+        .expect("Handle command getkeys unexpected error");
+        assert_eq!(resp, array(vec![bulk_string("key")]));
+
+        let err = handle(CommandSubcommand::GetKeys(vec!["PING".into()]))
+            .expect_err("Handle command getkeys no error");
+        assert_eq!(err, CommandsError::NoKeys);
+    }
+
+    #[cfg(feature = "scripting")]
+    #[test]
+    fn handle_getkeys_by_numkeys() {
+        let getkeys = |args: &[&str]| {
+            handle(CommandSubcommand::GetKeys(
+                args.iter().map(|&arg| arg.into()).collect(),
+            ))
+        };
+        assert_eq!(
+            getkeys(&["EVAL", "return 1", "2", "a", "b", "arg"]),
+            Ok(array(vec![bulk_string("a"), bulk_string("b")]))
+        );
+        assert_eq!(
+            getkeys(&["EVAL", "return 1", "0", "arg"]),
+            Err(CommandsError::NoKeys)
+        );
+        assert_eq!(
+            getkeys(&["EVAL", "return 1", "3", "a"]),
+            Err(CommandsError::NoKeys)
+        );
+        for name in ["EVAL_RO", "FCALL", "FCALL_RO"] {
+            assert_eq!(
+                getkeys(&[name, "f", "1", "k"]),
+                Ok(array(vec![bulk_string("k")]))
+            );
+        }
+    }
+}
@@ -0,0 +1,214 @@
+use std::sync::Arc;
+
+use super::super::config::{ConfigError, ServerConfig};
+use super::super::resp::{Array, BulkString, SimpleString, Value};
+use super::{bulk_string_to_string, value_to_bulk_string, CommandArgParser, ParseCommandError};
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ConfigSubcommand {
+    Get(Vec<String>),
+    Set(Vec<(String, String)>),
+}
+
+impl ConfigSubcommand {
+    fn to_bulk_strings(&self) -> Vec<BulkString> {
+        match self {
+            Self::Get(patterns) => {
+                let mut v = vec![BulkString::from("GET")];
+                v.extend(patterns.iter().map(|p| BulkString::from(p.clone())));
+                v
+            }
+            Self::Set(pairs) => {
+                let mut v = vec![BulkString::from("SET")];
+                for (name, value) in pairs {
+                    v.push(BulkString::from(name.clone()));
+                    v.push(BulkString::from(value.clone()));
+                }
+                v
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ConfigArg {
+    pub subcommand: ConfigSubcommand,
+}
+
+impl CommandArgParser for ConfigArg {
+    /// CONFIG GET parameter [parameter ...]
+    /// CONFIG SET parameter value [parameter value ...]
+    fn parse_arg(iter: &mut std::slice::Iter<'_, Value>) -> Result<Self, ParseCommandError> {
+        let args = iter
+            .map(|val| value_to_bulk_string(val).and_then(|bs| bulk_string_to_string(&bs)))
+            .collect::<Result<Vec<String>, ParseCommandError>>()?;
+        let (subcommand, rest) = args.split_first().ok_or(ParseCommandError::WrongNumArgs)?;
+
+        let subcommand = match subcommand.to_lowercase().as_str() {
+            "get" => {
+                if rest.is_empty() {
+                    return Err(ParseCommandError::WrongNumArgs);
+                }
+                ConfigSubcommand::Get(rest.to_vec())
+            }
+            "set" => {
+                if rest.is_empty() || rest.len() % 2 != 0 {
+                    return Err(ParseCommandError::WrongNumArgs);
+                }
+                let pairs = rest
+                    .chunks(2)
+                    .map(|pair| (pair[0].clone(), pair[1].clone()))
+                    .collect();
+                ConfigSubcommand::Set(pairs)
+            }
+            _ => {
+                return Err(ParseCommandError::InvalidArgument(Value::BulkString(
+                    subcommand.clone().into(),
+                )))
+            }
+        };
+
+        Ok(Self { subcommand })
+    }
+}
+
+pub struct Config;
+
+impl Config {
+    /// Returns an instance of CONFIG command handler.
+    pub fn handler(config: Arc<ServerConfig>) -> ConfigHandler {
+        ConfigHandler { config }
+    }
+
+    /// Returns CONFIG as a Command in the form of Value.
+    pub fn command_value(arg: ConfigArg) -> Value {
+        let mut v = vec![Value::BulkString("CONFIG".into())];
+        v.extend(
+            arg.subcommand
+                .to_bulk_strings()
+                .into_iter()
+                .map(Value::BulkString),
+        );
+
+        Value::Array(v.into())
+    }
+}
+
+#[derive(Debug)]
+pub struct ConfigHandler {
+    config: Arc<ServerConfig>,
+}
+
+impl ConfigHandler {
+    /// GET returns a flat array of name and value for every parameter matching the patterns.
+    /// SET applies all the given parameters, or none of them if any is invalid.
+    pub fn handle(&self, arg: ConfigArg) -> Result<Value, ConfigError> {
+        match arg.subcommand {
+            ConfigSubcommand::Get(patterns) => {
+                let values = self
+                    .config
+                    .get(&patterns)
+                    .into_iter()
+                    .flat_map(|(name, value)| {
+                        [
+                            Value::BulkString(name.into()),
+                            Value::BulkString(value.into()),
+                        ]
+                    })
+                    .collect();
+
+                Ok(Value::Array(Array::new(values)))
+            }
+            ConfigSubcommand::Set(pairs) => {
+                self.config.set(&pairs)?;
+                Ok(Value::SimpleString(SimpleString::from("OK")))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn command() {
+        let val = Config::command_value(ConfigArg {
+            subcommand: ConfigSubcommand::Set(vec![("timeout".into(), "10".into())]),
+        });
+
+        assert_eq!(
+            val.array().unwrap().values().unwrap().to_vec(),
+            vec![
+                Value::BulkString("CONFIG".into()),
+                Value::BulkString("SET".into()),
+                Value::BulkString("timeout".into()),
+                Value::BulkString("10".into()),
+            ]
+        )
+    }
+}
+
+#[cfg(test)]
+mod handler_test {
+    use super::super::super::config::ConfigValues;
+    use super::*;
+
+    #[test]
+    fn handle_config_set_and_get() {
+        let handler = Config::handler(Arc::new(ServerConfig::default()));
+
+        let resp = handler
+            .handle(ConfigArg {
+                subcommand: ConfigSubcommand::Set(vec![("hz".into(), "20".into())]),
+            })
+            .expect("Handle config set unexpected error");
+        assert_eq!(resp, Value::SimpleString("OK".into()));
+
+        let resp = handler
+            .handle(ConfigArg {
+                subcommand: ConfigSubcommand::Get(vec!["hz".into()]),
+            })
+            .expect("Handle config get unexpected error");
+        assert_eq!(
+            resp,
+            Value::Array(Array::new(vec![
+                Value::BulkString("hz".into()),
+                Value::BulkString("20".into()),
+            ]))
+        );
+    }
+
+    #[test]
+    fn handle_config_set_immutable() {
+        let handler = Config::handler(Arc::new(ServerConfig::default()));
+
+        let err = handler
+            .handle(ConfigArg {
+                subcommand: ConfigSubcommand::Set(vec![("port".into(), "1234".into())]),
+            })
+            .expect_err("Handle config set no error");
+        assert_eq!(err, ConfigError::Immutable("port".into()));
+    }
+
+    #[test]
+    fn handle_config_get_dir() {
+        let handler = Config::handler(Arc::new(ServerConfig::new(ConfigValues {
+            dir: "/tmp/redis-files".into(),
+            ..Default::default()
+        })));
+
+        let resp = handler
+            .handle(ConfigArg {
+                subcommand: ConfigSubcommand::Get(vec!["dir".into()]),
+            })
+            .expect("Handle config get unexpected error");
+        assert_eq!(
+            resp,
+            Value::Array(Array::new(vec![
+                Value::BulkString("dir".into()),
+                Value::BulkString("/tmp/redis-files".into()),
+            ]))
+        );
+    }
+}
@@ -0,0 +1,549 @@
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rand::Rng;
+use sha1::{Digest, Sha1};
+use thiserror::Error;
+
+use super::super::clients::ClientRegistry;
+use super::super::clock::Clock;
+use super::super::config::ServerConfig;
+use super::super::handler::{StoredData, StoredValue};
+use super::super::reply::{Deferred, Reply};
+use super::super::resp::{BulkString, SimpleString, Value};
+use super::super::store::Store;
+use super::super::util;
+use super::{bulk_string_to_string, value_to_bulk_string, CommandArgParser, ParseCommandError};
+
+/// SHA1 digest of a missing key or an empty dataset.
+const EMPTY_DIGEST: [u8; 20] = [0; 20];
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum DebugError {
+    #[error("no such key")]
+    NoSuchKey,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum DebugSubcommand {
+    /// Blocks the connection for the duration.
+    Sleep(Duration),
+    Object(BulkString),
+    SetActiveExpire(bool),
+    Jmap,
+    StringmatchLen,
+
+    /// Digest of the whole dataset, independent of the order keys are stored in.
+    Digest,
+
+    /// Digest of the value of each key.
+    DigestValue(Vec<BulkString>),
+
+    /// Closes the links of every replica, to test how they reconnect.
+    #[cfg(feature = "chaos")]
+    QuickDropReplica,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct DebugArg {
+    pub subcommand: DebugSubcommand,
+}
+
+impl CommandArgParser for DebugArg {
+    /// DEBUG SLEEP seconds
+    /// DEBUG OBJECT key
+    /// DEBUG SET-ACTIVE-EXPIRE 0|1
+    /// DEBUG DIGEST-VALUE [key ...]
+    /// DEBUG JMAP | STRINGMATCH-LEN | DIGEST | QUICK-DROP-REPLICA
+    fn parse_arg(iter: &mut std::slice::Iter<'_, Value>) -> Result<Self, ParseCommandError> {
+        let args = iter
+            .map(value_to_bulk_string)
+            .collect::<Result<Vec<BulkString>, ParseCommandError>>()?;
+        let (subcommand, rest) = args.split_first().ok_or(ParseCommandError::WrongNumArgs)?;
+        let invalid =
+            |bs: &BulkString| ParseCommandError::InvalidArgument(Value::BulkString(bs.clone()));
+
+        let subcommand = match (
+            bulk_string_to_string(subcommand)?.to_lowercase().as_str(),
+            rest,
+        ) {
+            ("sleep", [seconds]) => {
+                // Negative, NaN and durations too long to represent are refused.
+                let duration = bulk_string_to_string(seconds)?
+                    .parse::<f64>()
+                    .ok()
+                    .and_then(|s| Duration::try_from_secs_f64(s).ok())
+                    .ok_or(ParseCommandError::InvalidTimeout)?;
+                DebugSubcommand::Sleep(duration)
+            }
+            ("object", [key]) => DebugSubcommand::Object(key.clone()),
+            ("set-active-expire", [flag]) => match bulk_string_to_string(flag)?.as_str() {
+                "0" => DebugSubcommand::SetActiveExpire(false),
+                "1" => DebugSubcommand::SetActiveExpire(true),
+                _ => return Err(invalid(flag)),
+            },
+            ("jmap", []) => DebugSubcommand::Jmap,
+            ("stringmatch-len", []) => DebugSubcommand::StringmatchLen,
+            ("digest", []) => DebugSubcommand::Digest,
+            ("digest-value", keys) => DebugSubcommand::DigestValue(keys.to_vec()),
+            #[cfg(feature = "chaos")]
+            ("quick-drop-replica", []) => DebugSubcommand::QuickDropReplica,
+            (
+                "sleep" | "object" | "set-active-expire" | "jmap" | "stringmatch-len" | "digest",
+                _,
+            ) => return Err(ParseCommandError::WrongNumArgs),
+            #[cfg(feature = "chaos")]
+            ("quick-drop-replica", _) => return Err(ParseCommandError::WrongNumArgs),
+            _ => return Err(invalid(subcommand)),
+        };
+
+        Ok(Self { subcommand })
+    }
+}
+
+pub struct Debug;
+
+impl Debug {
+    /// Returns an instance of DEBUG command handler.
+    pub fn handler(
+        map: Arc<Store>,
+        clock: Arc<dyn Clock>,
+        config: Arc<ServerConfig>,
+        clients: Arc<ClientRegistry>,
+    ) -> DebugHandler {
+        DebugHandler {
+            map,
+            clock,
+            config,
+            clients,
+        }
+    }
+
+    /// Returns DEBUG as a Command in the form of Value.
+    pub fn command_value(arg: DebugArg) -> Value {
+        let mut v = vec![Value::BulkString("DEBUG".into())];
+        match arg.subcommand {
+            DebugSubcommand::Sleep(duration) => {
+                v.push(Value::BulkString("SLEEP".into()));
+                v.push(Value::BulkString(duration.as_secs_f64().to_string().into()));
+            }
+            DebugSubcommand::Object(key) => {
+                v.push(Value::BulkString("OBJECT".into()));
+                v.push(Value::BulkString(key));
+            }
+            DebugSubcommand::SetActiveExpire(enabled) => {
+                v.push(Value::BulkString("SET-ACTIVE-EXPIRE".into()));
+                v.push(Value::BulkString(if enabled { "1" } else { "0" }.into()));
+            }
+            DebugSubcommand::Jmap => v.push(Value::BulkString("JMAP".into())),
+            DebugSubcommand::StringmatchLen => v.push(Value::BulkString("STRINGMATCH-LEN".into())),
+            DebugSubcommand::Digest => v.push(Value::BulkString("DIGEST".into())),
+            DebugSubcommand::DigestValue(keys) => {
+                v.push(Value::BulkString("DIGEST-VALUE".into()));
+                v.extend(keys.into_iter().map(Value::BulkString));
+            }
+            #[cfg(feature = "chaos")]
+            DebugSubcommand::QuickDropReplica => {
+                v.push(Value::BulkString("QUICK-DROP-REPLICA".into()))
+            }
+        }
+        Value::Array(v.into())
+    }
+}
+
+#[derive(Debug)]
+pub struct DebugHandler {
+    map: Arc<Store>,
+    clock: Arc<dyn Clock>,
+    config: Arc<ServerConfig>,
+    #[cfg_attr(not(feature = "chaos"), allow(dead_code))]
+    clients: Arc<ClientRegistry>,
+}
+
+impl DebugHandler {
+    /// Runs debugging subcommands. SLEEP holds back the reply, so it blocks the connection
+    /// that sent it, unlike Redis where it blocks the whole server. JMAP is accepted for
+    /// compatibility and does nothing.
+    ///
+    /// DIGEST and DIGEST-VALUE reply with hex SHA1 digests, all zeros for an empty dataset or
+    /// a missing key, so two datasets can be compared, e.g. a master and its replica.
+    pub fn handle(&self, arg: DebugArg) -> Result<Reply, DebugError> {
+        let ok = Value::SimpleString(SimpleString::from("OK"));
+
+        let value = match arg.subcommand {
+            DebugSubcommand::Sleep(duration) => {
+                let clock = self.clock.clone();
+                return Ok(Reply::Deferred(Deferred::new(async move {
+                    clock.sleep(duration).await;
+                    ok
+                })));
+            }
+            DebugSubcommand::Object(key) => {
+                let key = key.as_bytes().unwrap_or_default();
+                let map = self.map.read(key);
+                let data = map
+                    .get(key)
+                    .filter(|data| !data.expired_at(self.clock.now()))
+                    .ok_or(DebugError::NoSuchKey)?;
+                Value::SimpleString(SimpleString::from(describe_object(data)))
+            }
+            DebugSubcommand::SetActiveExpire(enabled) => {
+                self.config.set_active_expire(enabled);
+                ok
+            }
+            DebugSubcommand::Jmap => ok,
+            DebugSubcommand::StringmatchLen => {
+                stringmatch_fuzz_test();
+                Value::SimpleString(SimpleString::from(
+                    "Apparently Redis did not crash: test passed",
+                ))
+            }
+            DebugSubcommand::Digest => {
+                let now = self.clock.now();
+                let mut digest = EMPTY_DIGEST;
+                for (key, data) in self.map.snapshot().iter() {
+                    if !data.expired_at(now) {
+                        xor_digest(&mut digest, &key_digest(key, data));
+                    }
+                }
+                Value::SimpleString(SimpleString::from(hex(&digest)))
+            }
+            DebugSubcommand::DigestValue(keys) => {
+                let now = self.clock.now();
+                let digests = keys.iter().map(|key| {
+                    let key = key.as_bytes().unwrap_or_default();
+                    let digest = self
+                        .map
+                        .read(key)
+                        .get(key)
+                        .filter(|data| !data.expired_at(now))
+                        .map_or(EMPTY_DIGEST, value_digest);
+                    Value::SimpleString(SimpleString::from(hex(&digest)))
+                });
+                Value::Array(digests.collect::<Vec<_>>().into())
+            }
+            #[cfg(feature = "chaos")]
+            DebugSubcommand::QuickDropReplica => {
+                for client in self.clients.all() {
+                    let client = client.lock();
+                    if client.flags.replica {
+                        client.close();
+                    }
+                }
+                ok
+            }
+        };
+        Ok(value.into())
+    }
+}
+
+/// Returns the low level details of a value, e.g.
+/// `Value at:0x7f01 refcount:1 encoding:embstr serializedlength:6 lru:123 lru_seconds_idle:0`.
+fn describe_object(data: &StoredData) -> String {
+    let now_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0);
+    let last_access_ms = data.access.last_access_ms();
+
+    format!(
+        "Value at:{:p} refcount:1 encoding:{} serializedlength:{} lru:{} lru_seconds_idle:{}",
+        data,
+        data.encoding(),
+        data.value.heap_len(),
+        (last_access_ms / 1000) & 0xff_ffff,
+        now_ms.saturating_sub(last_access_ms) / 1000
+    )
+}
+
+/// Returns the digest of a value, covering its type, its content and whether it expires.
+/// The expiry time itself is left out, as a replica or a reload may shift it by a few
+/// milliseconds.
+///
+/// Elements of lists, sorted sets and streams are hashed in order. Those of sets and hashes
+/// are hashed one by one and mixed, since their order differs between servers.
+fn value_digest(data: &StoredData) -> [u8; 20] {
+    fn add_bytes(hasher: &mut Sha1, bytes: &[u8]) {
+        hasher.update((bytes.len() as u64).to_be_bytes());
+        hasher.update(bytes);
+    }
+    fn add(hasher: &mut Sha1, s: &BulkString) {
+        add_bytes(hasher, s.as_bytes().unwrap_or_default());
+    }
+    fn unordered<T>(
+        hasher: &mut Sha1,
+        elements: impl Iterator<Item = T>,
+        add_element: impl Fn(&mut Sha1, T),
+    ) {
+        let mut digest = EMPTY_DIGEST;
+        for element in elements {
+            let mut element_hasher = Sha1::new();
+            add_element(&mut element_hasher, element);
+            xor_digest(&mut digest, &element_hasher.finalize().into());
+        }
+        hasher.update(digest);
+    }
+
+    let mut hasher = Sha1::new();
+    hasher.update(data.value.type_name().as_bytes());
+    match &data.value {
+        StoredValue::String(s) => add(&mut hasher, s),
+        StoredValue::List(list) => list.iter().for_each(|s| add_bytes(&mut hasher, s)),
+        StoredValue::Hash(hash) => unordered(&mut hasher, hash.iter(), |hasher, (field, value)| {
+            add(hasher, &field);
+            add(hasher, &value);
+        }),
+        StoredValue::Set(set) => unordered(&mut hasher, set.iter(), add),
+        StoredValue::SortedSet(zset) => {
+            for (member, score) in zset.iter() {
+                add(&mut hasher, &member);
+                hasher.update(score.to_be_bytes());
+            }
+        }
+        StoredValue::Stream(stream) => {
+            for (id, fields) in stream.iter() {
+                hasher.update(id.ms.to_be_bytes());
+                hasher.update(id.seq.to_be_bytes());
+                for (field, value) in fields {
+                    add(&mut hasher, field);
+                    add(&mut hasher, value);
+                }
+            }
+        }
+    }
+    if data.deadline.is_some() {
+        hasher.update(b"!!expire!!");
+    }
+    hasher.finalize().into()
+}
+
+/// Returns the digest of a key with its value.
+fn key_digest(key: &[u8], data: &StoredData) -> [u8; 20] {
+    let mut hasher = Sha1::new();
+    hasher.update((key.len() as u64).to_be_bytes());
+    hasher.update(key);
+    hasher.update(value_digest(data));
+    hasher.finalize().into()
+}
+
+/// Mixes `other` into `digest`. XOR doesn't depend on the order digests are mixed in, so
+/// neither does the digest of the dataset.
+fn xor_digest(digest: &mut [u8; 20], other: &[u8; 20]) {
+    for (byte, other) in digest.iter_mut().zip(other) {
+        *byte ^= other;
+    }
+}
+
+fn hex(digest: &[u8]) -> String {
+    digest.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Matches random patterns against random strings, checking the glob matcher doesn't
+/// crash on pathological input.
+fn stringmatch_fuzz_test() {
+    const CHARSET: &[u8] = b"*?[]^-\\abc";
+    let mut rng = rand::thread_rng();
+    let mut random_bytes = |max_len: usize| -> Vec<u8> {
+        let len = rng.gen_range(0..=max_len);
+        (0..len)
+            .map(|_| CHARSET[rng.gen_range(0..CHARSET.len())])
+            .collect()
+    };
+
+    for _ in 0..1000 {
+        let pattern = random_bytes(64);
+        let s = random_bytes(64);
+        util::glob_match(&pattern, &s);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn command() {
+        let val = Debug::command_value(DebugArg {
+            subcommand: DebugSubcommand::SetActiveExpire(false),
+        });
+
+        assert_eq!(
+            val.array().unwrap().values().unwrap().to_vec(),
+            vec![
+                Value::BulkString("DEBUG".into()),
+                Value::BulkString("SET-ACTIVE-EXPIRE".into()),
+                Value::BulkString("0".into()),
+            ]
+        )
+    }
+
+    #[test]
+    fn parse_digest() {
+        let parse = |args: &[&str]| {
+            let values: Vec<Value> = args
+                .iter()
+                .map(|a| Value::BulkString((*a).into()))
+                .collect();
+            DebugArg::parse_arg(&mut values.iter())
+        };
+
+        assert_eq!(
+            parse(&["digest"]).unwrap().subcommand,
+            DebugSubcommand::Digest
+        );
+        assert_eq!(
+            parse(&["DIGEST-VALUE", "a", "b"]).unwrap().subcommand,
+            DebugSubcommand::DigestValue(vec!["a".into(), "b".into()])
+        );
+        assert_eq!(
+            parse(&["digest-value"]).unwrap().subcommand,
+            DebugSubcommand::DigestValue(vec![])
+        );
+        assert!(matches!(
+            parse(&["digest", "a"]),
+            Err(ParseCommandError::WrongNumArgs)
+        ));
+        assert!(matches!(parse(&[]), Err(ParseCommandError::WrongNumArgs)));
+    }
+
+    #[test]
+    fn parse_sleep() {
+        let parse = |seconds: &str| {
+            let values = [
+                Value::BulkString("SLEEP".into()),
+                Value::BulkString(seconds.into()),
+            ];
+            DebugArg::parse_arg(&mut values.iter()).map(|arg| arg.subcommand)
+        };
+
+        assert_eq!(
+            parse("0.5").unwrap(),
+            DebugSubcommand::Sleep(Duration::from_millis(500))
+        );
+        for seconds in ["1e30", "-1", "nan", "inf", "soon"] {
+            assert!(
+                matches!(parse(seconds), Err(ParseCommandError::InvalidTimeout)),
+                "{seconds} accepted"
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod handler_test {
+    use super::super::super::clock;
+    use super::super::super::key::Key;
+    use super::*;
+
+    #[test]
+    fn handle_object() {
+        let map = Store::from_iter([(Key::from("key"), StoredData::new("123".into(), None))]);
+        let handler = Debug::handler(
+            Arc::new(map),
+            clock::system(),
+            Arc::new(ServerConfig::default()),
+            Arc::new(ClientRegistry::new()),
+        );
+
+        let resp = handler
+            .handle(DebugArg {
+                subcommand: DebugSubcommand::Object("key".into()),
+            })
+            .expect("Handle debug object unexpected error")
+            .into_value();
+        let desc = resp.simple_string().unwrap().as_str().to_string();
+        assert!(desc.contains("encoding:int serializedlength:3"));
+
+        let err = handler
+            .handle(DebugArg {
+                subcommand: DebugSubcommand::Object("missing".into()),
+            })
+            .expect_err("Handle debug object no error");
+        assert_eq!(err, DebugError::NoSuchKey);
+    }
+
+    #[tokio::test]
+    async fn handle_sleep() {
+        let clock = Arc::new(clock::TestClock::default());
+        let handler = Debug::handler(
+            Arc::new(Store::default()),
+            clock.clone(),
+            Arc::new(ServerConfig::default()),
+            Arc::new(ClientRegistry::new()),
+        );
+
+        let reply = handler
+            .handle(DebugArg {
+                subcommand: DebugSubcommand::Sleep(Duration::from_secs(10)),
+            })
+            .expect("Handle debug sleep unexpected error");
+        let Reply::Deferred(deferred) = reply else {
+            panic!("Unexpected reply {reply:?}");
+        };
+        // The reply waits on the clock rather than blocking the thread handling it.
+        let sleep = tokio::spawn(deferred.value());
+        tokio::task::yield_now().await;
+        assert!(!sleep.is_finished());
+
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(
+            sleep.await.expect("Join unexpected error"),
+            Value::SimpleString("OK".into())
+        );
+    }
+
+    #[test]
+    fn handle_digest() {
+        let deadline = Some(SystemTime::now() + Duration::from_secs(100));
+        let entries = [
+            (Key::from("a"), StoredData::new("1".into(), None)),
+            (Key::from("b"), StoredData::new("2".into(), deadline)),
+            (Key::from("c"), StoredData::new("3".into(), None)),
+        ];
+        let digest = |map: Store, subcommand| {
+            Debug::handler(
+                Arc::new(map),
+                clock::system(),
+                Arc::new(ServerConfig::default()),
+                Arc::new(ClientRegistry::new()),
+            )
+            .handle(DebugArg { subcommand })
+            .expect("Handle debug digest unexpected error")
+            .into_value()
+        };
+        let zeros = Value::SimpleString("0".repeat(40).as_str().into());
+
+        let forward = digest(Store::from_iter(entries.clone()), DebugSubcommand::Digest);
+        let backward = digest(
+            Store::from_iter(entries.clone().into_iter().rev()),
+            DebugSubcommand::Digest,
+        );
+        assert_eq!(forward, backward);
+        assert_ne!(forward, zeros);
+        assert_eq!(digest(Store::default(), DebugSubcommand::Digest), zeros);
+
+        // Changing a value or whether it expires changes the digest.
+        let mut changed = entries.clone();
+        changed[0].1 = StoredData::new("9".into(), None);
+        assert_ne!(
+            digest(Store::from_iter(changed), DebugSubcommand::Digest),
+            forward
+        );
+        let mut changed = entries.clone();
+        changed[0].1 = StoredData::new("1".into(), deadline);
+        assert_ne!(
+            digest(Store::from_iter(changed), DebugSubcommand::Digest),
+            forward
+        );
+
+        let values = digest(
+            Store::from_iter(entries),
+            DebugSubcommand::DigestValue(vec!["a".into(), "missing".into(), "c".into()]),
+        );
+        let values = values.array().unwrap().values().unwrap().to_vec();
+        assert_eq!(values.len(), 3);
+        assert_ne!(values[0], zeros);
+        assert_eq!(values[1], zeros);
+        assert_ne!(values[0], values[2]);
+    }
+}
@@ -0,0 +1,200 @@
+use std::sync::Arc;
+
+use super::super::clock::Clock;
+use super::super::events::{KeyEvent, KeyEvents};
+use super::super::lazyfree::LazyFree;
+use super::super::resp::{BulkString, Integer, Value};
+use super::super::store::Store;
+use super::{consume_variadic_args_from_iter, CommandArgParser, ParseCommandError};
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct DelArg {
+    pub keys: Vec<BulkString>,
+}
+
+impl CommandArgParser for DelArg {
+    /// DEL key [key ...]
+    fn parse_arg(iter: &mut std::slice::Iter<'_, Value>) -> Result<Self, ParseCommandError> {
+        let keys = consume_variadic_args_from_iter(iter, 1)?;
+
+        Ok(Self { keys })
+    }
+}
+
+fn command_value(name: &str, arg: DelArg) -> Value {
+    let mut v = vec![Value::BulkString(name.into())];
+    v.extend(arg.keys.into_iter().map(Value::BulkString));
+    Value::Array(v.into())
+}
+
+pub struct Del;
+
+impl Del {
+    /// Returns an instance of DEL command handler.
+    ///
+    /// Removed values are freed on `lazyfree` if given.
+    pub fn handler(
+        map: Arc<Store>,
+        clock: Arc<dyn Clock>,
+        lazyfree: Option<Arc<LazyFree>>,
+    ) -> DelHandler {
+        DelHandler {
+            map,
+            clock,
+            lazyfree,
+            events: None,
+        }
+    }
+
+    /// Returns DEL as a Command in the form of Value.
+    pub fn command_value(arg: DelArg) -> Value {
+        command_value("DEL", arg)
+    }
+}
+
+pub struct Unlink;
+
+impl Unlink {
+    /// Returns an instance of UNLINK command handler, which is DEL always freeing the
+    /// removed values on `lazyfree`.
+    pub fn handler(map: Arc<Store>, clock: Arc<dyn Clock>, lazyfree: Arc<LazyFree>) -> DelHandler {
+        Del::handler(map, clock, Some(lazyfree))
+    }
+
+    /// Returns UNLINK as a Command in the form of Value.
+    pub fn command_value(arg: DelArg) -> Value {
+        command_value("UNLINK", arg)
+    }
+}
+
+pub struct DelHandler {
+    map: Arc<Store>,
+    clock: Arc<dyn Clock>,
+    lazyfree: Option<Arc<LazyFree>>,
+    events: Option<Arc<KeyEvents>>,
+}
+
+impl DelHandler {
+    /// Tells the listeners about removed keys.
+    pub fn with_events(mut self, events: Arc<KeyEvents>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Removes the keys, returning how many of them existed.
+    ///
+    /// Expired keys are removed too, but aren't counted. A key given more than once is only
+    /// counted the first time.
+    pub fn handle(&self, arg: DelArg) -> Value {
+        let now = self.clock.now();
+        let mut deleted = 0;
+        for key in &arg.keys {
+            let key = key.as_bytes().unwrap_or_default();
+            let Some(data) = self.map.write(key).remove(key) else {
+                continue;
+            };
+
+            let expired = data.expired_at(now);
+            if !expired {
+                deleted += 1;
+            }
+            if let Some(events) = &self.events {
+                let event = if expired {
+                    KeyEvent::Expired
+                } else {
+                    KeyEvent::Del
+                };
+                events.notify(key, event);
+            }
+            match &self.lazyfree {
+                Some(lazyfree) => lazyfree.free(data),
+                None => drop(data),
+            }
+        }
+        Value::Integer(Integer::new(deleted))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::Command;
+    use super::*;
+
+    #[test]
+    fn parse_del_and_unlink() {
+        let arg = DelArg {
+            keys: vec!["a".into(), "b".into()],
+        };
+        match Command::try_from(Del::command_value(arg.clone())) {
+            Ok(Command::Del(parsed)) => assert_eq!(parsed, arg),
+            other => panic!("Unexpected parse result {other:?}"),
+        }
+        match Command::try_from(Unlink::command_value(arg.clone())) {
+            Ok(Command::Unlink(parsed)) => assert_eq!(parsed, arg),
+            other => panic!("Unexpected parse result {other:?}"),
+        }
+
+        let empty = DelArg { keys: vec![] };
+        assert!(matches!(
+            Command::try_from(Del::command_value(empty)),
+            Err(ParseCommandError::WrongArity(_))
+        ));
+    }
+}
+
+#[cfg(test)]
+mod handler_test {
+    use std::time::Duration;
+
+    use super::super::super::clock::{self, TestClock};
+    use super::super::super::handler::StoredData;
+    use super::super::super::key::Key;
+    use super::super::super::lazyfree::LAZYFREE_THRESHOLD;
+    use super::*;
+
+    #[test]
+    fn handle_del() {
+        let clock = Arc::new(TestClock::default());
+        let map = Arc::new(Store::from_iter([
+            (Key::from("a"), StoredData::new("1".into(), None)),
+            (Key::from("b"), StoredData::new("2".into(), None)),
+            (
+                Key::from("expired"),
+                StoredData::new("3".into(), Some(clock.now())),
+            ),
+        ]));
+        clock.advance(Duration::from_millis(1));
+
+        let arg = DelArg {
+            keys: vec![
+                "a".into(),
+                "a".into(),
+                "b".into(),
+                "expired".into(),
+                "missing".into(),
+            ],
+        };
+        let resp = Del::handler(map.clone(), clock, None).handle(arg);
+
+        assert_eq!(resp, Value::Integer(Integer::new(2)));
+        assert!(map.snapshot().is_empty());
+    }
+
+    #[test]
+    fn handle_unlink_frees_lazily() {
+        let map = Arc::new(Store::from_iter([(
+            Key::from("big"),
+            StoredData::new(vec![b'x'; LAZYFREE_THRESHOLD].into(), None),
+        )]));
+        let lazyfree = Arc::new(LazyFree::new());
+
+        let arg = DelArg {
+            keys: vec!["big".into()],
+        };
+        let resp = Unlink::handler(map.clone(), clock::system(), lazyfree.clone()).handle(arg);
+
+        assert_eq!(resp, Value::Integer(Integer::new(1)));
+        assert!(map.snapshot().is_empty());
+        assert_eq!(lazyfree.pending() + lazyfree.freed(), 1);
+    }
+}
@@ -12,7 +12,7 @@ impl CommandArgParser for EchoArg {
     /// ECHO msg
     fn parse_arg(iter: &mut std::slice::Iter<'_, Value>) -> Result<Self, ParseCommandError> {
         let args = consume_args_from_iter(iter, 1, 0)?;
-        let msg = args.get(0).unwrap().clone();
+        let msg = args.first().unwrap().clone();
 
         Ok(Self { msg })
     }
@@ -100,19 +100,16 @@ mod test {
 
 #[cfg(test)]
 mod client_test {
-    use super::super::super::session::MockResponder;
+    use super::super::super::test_util::ScriptedResponder;
     use super::*;
 
-    fn new_echo_responder(expected_msg: BulkString, returned_bs: BulkString) -> MockResponder {
-        let expected_req = Request::new(Value::Array(Array::new(vec![
+    fn new_echo_responder(expected_msg: BulkString, returned_bs: BulkString) -> ScriptedResponder {
+        let expected_req = Value::Array(Array::new(vec![
             Value::BulkString("ECHO".into()),
             Value::BulkString(expected_msg),
-        ])));
+        ]));
 
-        MockResponder {
-            expected_req,
-            returned_resp: Value::BulkString(returned_bs).into(),
-        }
+        ScriptedResponder::new().expect(expected_req, Value::BulkString(returned_bs))
     }
 
     #[tokio::test]
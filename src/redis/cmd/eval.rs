@@ -0,0 +1,124 @@
+use super::super::resp::{BulkString, Value};
+use super::super::scripting::{self, ScriptCall, ScriptError};
+use super::{bulk_string_to_int64, value_to_bulk_string, CommandArgParser, ParseCommandError};
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct EvalArg {
+    /// Script run by EVAL, or the name of the function FCALL calls.
+    pub script: BulkString,
+    pub keys: Vec<BulkString>,
+    pub args: Vec<BulkString>,
+}
+
+impl CommandArgParser for EvalArg {
+    /// EVAL script numkeys [key [key ...]] [arg [arg ...]]
+    ///
+    /// EVAL_RO takes the same arguments, and FCALL and FCALL_RO a function name instead of
+    /// the script.
+    fn parse_arg(iter: &mut std::slice::Iter<'_, Value>) -> Result<Self, ParseCommandError> {
+        let script = value_to_bulk_string(iter.next().ok_or(ParseCommandError::WrongNumArgs)?)?;
+        let numkeys = value_to_bulk_string(iter.next().ok_or(ParseCommandError::WrongNumArgs)?)?;
+        let numkeys = bulk_string_to_int64(&numkeys)?;
+        let mut keys = iter
+            .map(value_to_bulk_string)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let numkeys = usize::try_from(numkeys).map_err(|_| ParseCommandError::NegativeNumKeys)?;
+        if numkeys > keys.len() {
+            return Err(ParseCommandError::TooManyNumKeys);
+        }
+        let args = keys.split_off(numkeys);
+        Ok(Self { script, keys, args })
+    }
+}
+
+pub struct Eval;
+
+impl Eval {
+    /// Returns an instance of EVAL command handler.
+    pub fn handler() -> EvalHandler {
+        EvalHandler { read_only: false }
+    }
+
+    /// Returns EVAL as a Command in the form of Value.
+    pub fn command_value(arg: EvalArg) -> Value {
+        let mut v = vec![
+            Value::BulkString("EVAL".into()),
+            Value::BulkString(arg.script),
+            Value::BulkString(arg.keys.len().to_string().into()),
+        ];
+        v.extend(arg.keys.into_iter().map(Value::BulkString));
+        v.extend(arg.args.into_iter().map(Value::BulkString));
+
+        Value::Array(v.into())
+    }
+}
+
+#[derive(Debug)]
+pub struct EvalHandler {
+    read_only: bool,
+}
+
+impl EvalHandler {
+    /// Refuses every write command the script calls, as EVAL_RO does.
+    pub fn with_read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    /// Runs the script, which runs commands with `call`.
+    pub fn handle(&self, arg: EvalArg, call: &mut ScriptCall) -> Result<Value, ScriptError> {
+        let script = arg.script.as_bytes().unwrap_or_default();
+        scripting::eval(script, &arg.keys, &arg.args, self.read_only, call)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::super::test_util;
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<EvalArg, ParseCommandError> {
+        let value = test_util::command(args.iter().copied());
+        EvalArg::parse_arg(&mut value.array().unwrap().values().unwrap().iter())
+    }
+
+    #[test]
+    fn parse_keys_and_args() {
+        assert_eq!(
+            parse(&["return 1", "1", "key", "arg"]).expect("Parse eval unexpected error"),
+            EvalArg {
+                script: "return 1".into(),
+                keys: vec!["key".into()],
+                args: vec!["arg".into()],
+            }
+        );
+        assert!(matches!(
+            parse(&["return 1", "-1"]),
+            Err(ParseCommandError::NegativeNumKeys)
+        ));
+        assert!(matches!(
+            parse(&["return 1", "2", "key"]),
+            Err(ParseCommandError::TooManyNumKeys)
+        ));
+    }
+
+    #[test]
+    fn command() {
+        let val = Eval::command_value(EvalArg {
+            script: "return 1".into(),
+            keys: vec!["key".into()],
+            args: vec![],
+        });
+
+        assert_eq!(
+            val.array().unwrap().values().unwrap().to_vec(),
+            vec![
+                Value::BulkString("EVAL".into()),
+                Value::BulkString("return 1".into()),
+                Value::BulkString("1".into()),
+                Value::BulkString("key".into()),
+            ]
+        )
+    }
+}
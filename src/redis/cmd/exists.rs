@@ -0,0 +1,122 @@
+use std::sync::Arc;
+
+use super::super::clock::Clock;
+use super::super::events::KeyEvents;
+use super::super::eviction::LfuConfig;
+use super::super::lazyfree::LazyFree;
+use super::super::resp::{BulkString, Integer, Value};
+use super::super::store::Store;
+use super::{consume_variadic_args_from_iter, CommandArgParser, Lookup, ParseCommandError};
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ExistsArg {
+    pub keys: Vec<BulkString>,
+}
+
+impl CommandArgParser for ExistsArg {
+    /// EXISTS key [key ...]
+    fn parse_arg(iter: &mut std::slice::Iter<'_, Value>) -> Result<Self, ParseCommandError> {
+        let keys = consume_variadic_args_from_iter(iter, 1)?;
+
+        Ok(Self { keys })
+    }
+}
+
+pub struct Exists;
+
+impl Exists {
+    /// Returns an instance of EXISTS command handler.
+    ///
+    /// Expired keys are freed on `lazyfree` if given.
+    pub fn handler(
+        map: Arc<Store>,
+        clock: Arc<dyn Clock>,
+        lfu: LfuConfig,
+        lazyfree: Option<Arc<LazyFree>>,
+    ) -> ExistsHandler {
+        ExistsHandler {
+            lookup: Lookup::new(map, clock, lfu, lazyfree),
+        }
+    }
+
+    /// Returns EXISTS as a Command in the form of Value.
+    pub fn command_value(arg: ExistsArg) -> Value {
+        let mut v = vec![Value::BulkString("EXISTS".into())];
+        v.extend(arg.keys.into_iter().map(Value::BulkString));
+        Value::Array(v.into())
+    }
+}
+
+pub struct ExistsHandler {
+    lookup: Lookup,
+}
+
+impl ExistsHandler {
+    /// Tells the listeners about keys found expired.
+    pub fn with_events(mut self, events: Arc<KeyEvents>) -> Self {
+        self.lookup = self.lookup.with_events(events);
+        self
+    }
+
+    /// Returns how many of the keys exist, counting a key given more than once each time.
+    pub fn handle(&self, arg: ExistsArg) -> Value {
+        let count = arg
+            .keys
+            .iter()
+            .filter(|key| {
+                self.lookup
+                    .get(key.as_bytes().unwrap_or_default())
+                    .is_some()
+            })
+            .count();
+        Value::Integer(Integer::new(count as i64))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::Command;
+    use super::*;
+
+    #[test]
+    fn parse_exists() {
+        let arg = ExistsArg {
+            keys: vec!["a".into(), "b".into()],
+        };
+        match Command::try_from(Exists::command_value(arg.clone())) {
+            Ok(Command::Exists(parsed)) => assert_eq!(parsed, arg),
+            other => panic!("Unexpected parse result {other:?}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod handler_test {
+    use std::time::Duration;
+
+    use super::super::super::clock::TestClock;
+    use super::super::super::handler::StoredData;
+    use super::super::super::key::Key;
+    use super::*;
+
+    #[test]
+    fn handle_exists() {
+        let clock = Arc::new(TestClock::default());
+        let map = Arc::new(Store::from_iter([
+            (Key::from("a"), StoredData::new("1".into(), None)),
+            (
+                Key::from("expired"),
+                StoredData::new("2".into(), Some(clock.now())),
+            ),
+        ]));
+        clock.advance(Duration::from_millis(1));
+
+        let arg = ExistsArg {
+            keys: vec!["a".into(), "a".into(), "expired".into(), "missing".into()],
+        };
+        let handler = Exists::handler(map.clone(), clock, LfuConfig::default(), None);
+
+        assert_eq!(handler.handle(arg), Value::Integer(Integer::new(2)));
+        assert!(map.snapshot().get(b"expired".as_slice()).is_none());
+    }
+}
@@ -0,0 +1,489 @@
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use super::super::clock::Clock;
+use super::super::events::{KeyEvent, KeyEvents};
+use super::super::handler::HandleCommandError;
+use super::super::lazyfree::LazyFree;
+use super::super::resp::{BulkString, Integer, Value};
+use super::super::store::Store;
+use super::{
+    bulk_string_to_int64, bulk_string_to_string, consume_args_from_iter,
+    consume_variadic_args_from_iter, CommandArgParser, ParseCommandError,
+};
+
+/// When the key expires, in the unit and from the point in time of the command it was given
+/// to.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ExpireTime {
+    /// EXPIRE: seconds from now.
+    Seconds(i64),
+    /// PEXPIRE: milliseconds from now.
+    Millis(i64),
+    /// EXPIREAT: Unix time in seconds.
+    UnixSeconds(i64),
+    /// PEXPIREAT: Unix time in milliseconds.
+    UnixMillis(i64),
+}
+
+impl ExpireTime {
+    /// Returns the lowercase name of the command taking this time.
+    pub fn command_name(&self) -> &'static str {
+        match self {
+            Self::Seconds(_) => "expire",
+            Self::Millis(_) => "pexpire",
+            Self::UnixSeconds(_) => "expireat",
+            Self::UnixMillis(_) => "pexpireat",
+        }
+    }
+
+    /// Returns the deadline this time stands for, or none if it is too far out to be held
+    /// as milliseconds since the Unix epoch.
+    pub fn deadline(&self, now: SystemTime) -> Option<SystemTime> {
+        let now_millis = now
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as i64;
+        let millis = match *self {
+            Self::Seconds(secs) => now_millis.checked_add(secs.checked_mul(1000)?)?,
+            Self::Millis(millis) => now_millis.checked_add(millis)?,
+            Self::UnixSeconds(secs) => secs.checked_mul(1000)?,
+            Self::UnixMillis(millis) => millis,
+        };
+        // Times before the epoch have passed all the same.
+        Some(UNIX_EPOCH + Duration::from_millis(millis.max(0) as u64))
+    }
+}
+
+/// Only set the deadline if the key's current one meets the condition. A key without a
+/// deadline counts as one that never expires.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ExpireCondition {
+    /// The key has no deadline.
+    Nx,
+    /// The key has a deadline.
+    Xx,
+    /// The new deadline is later than the current one.
+    Gt,
+    /// The new deadline is earlier than the current one.
+    Lt,
+}
+
+impl ExpireCondition {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Nx => "NX",
+            Self::Xx => "XX",
+            Self::Gt => "GT",
+            Self::Lt => "LT",
+        }
+    }
+
+    fn allows(&self, current: Option<SystemTime>, new: SystemTime) -> bool {
+        match self {
+            Self::Nx => current.is_none(),
+            Self::Xx => current.is_some(),
+            Self::Gt => current.is_some_and(|current| new > current),
+            Self::Lt => current.is_none_or(|current| new < current),
+        }
+    }
+}
+
+/// Arguments of EXPIRE, PEXPIRE, EXPIREAT and PEXPIREAT.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ExpireArg {
+    pub key: BulkString,
+    pub time: ExpireTime,
+    pub conditions: Vec<ExpireCondition>,
+}
+
+impl CommandArgParser for ExpireArg {
+    /// EXPIRE key seconds [NX | XX | GT | LT]
+    fn parse_arg(iter: &mut std::slice::Iter<'_, Value>) -> Result<Self, ParseCommandError> {
+        Self::parse_with(iter, ExpireTime::Seconds)
+    }
+}
+
+impl ExpireArg {
+    /// PEXPIRE key milliseconds [NX | XX | GT | LT]
+    pub fn parse_pexpire(
+        iter: &mut std::slice::Iter<'_, Value>,
+    ) -> Result<Self, ParseCommandError> {
+        Self::parse_with(iter, ExpireTime::Millis)
+    }
+
+    /// EXPIREAT key unix-time-seconds [NX | XX | GT | LT]
+    pub fn parse_expireat(
+        iter: &mut std::slice::Iter<'_, Value>,
+    ) -> Result<Self, ParseCommandError> {
+        Self::parse_with(iter, ExpireTime::UnixSeconds)
+    }
+
+    /// PEXPIREAT key unix-time-milliseconds [NX | XX | GT | LT]
+    pub fn parse_pexpireat(
+        iter: &mut std::slice::Iter<'_, Value>,
+    ) -> Result<Self, ParseCommandError> {
+        Self::parse_with(iter, ExpireTime::UnixMillis)
+    }
+
+    fn parse_with(
+        iter: &mut std::slice::Iter<'_, Value>,
+        time: fn(i64) -> ExpireTime,
+    ) -> Result<Self, ParseCommandError> {
+        let args = consume_variadic_args_from_iter(iter, 2)?;
+        let key = args[0].clone();
+        let time = time(bulk_string_to_int64(&args[1])?);
+
+        let mut conditions = Vec::new();
+        for arg in &args[2..] {
+            let condition = match bulk_string_to_string(arg)?.to_lowercase().as_str() {
+                "nx" => ExpireCondition::Nx,
+                "xx" => ExpireCondition::Xx,
+                "gt" => ExpireCondition::Gt,
+                "lt" => ExpireCondition::Lt,
+                _ => {
+                    return Err(ParseCommandError::InvalidArgument(Value::BulkString(
+                        arg.clone(),
+                    )))
+                }
+            };
+            if !conditions.contains(&condition) {
+                conditions.push(condition);
+            }
+        }
+
+        // XX may go along with GT or LT, any other pair can never be met.
+        let has = |condition| conditions.contains(&condition);
+        if has(ExpireCondition::Nx) && conditions.len() > 1 {
+            return Err(ParseCommandError::IncompatibleOptions(
+                "NX and XX, GT or LT",
+            ));
+        }
+        if has(ExpireCondition::Gt) && has(ExpireCondition::Lt) {
+            return Err(ParseCommandError::IncompatibleOptions("GT and LT"));
+        }
+
+        Ok(Self {
+            key,
+            time,
+            conditions,
+        })
+    }
+}
+
+pub struct Expire;
+
+impl Expire {
+    /// Returns an instance of the handler shared by EXPIRE, PEXPIRE, EXPIREAT and PEXPIREAT.
+    ///
+    /// Keys given a deadline that already passed are removed, and freed on `lazyfree` if
+    /// given.
+    pub fn handler(
+        map: Arc<Store>,
+        clock: Arc<dyn Clock>,
+        lazyfree: Option<Arc<LazyFree>>,
+    ) -> ExpireHandler {
+        ExpireHandler {
+            map,
+            clock,
+            lazyfree,
+            events: None,
+        }
+    }
+
+    /// Returns the command taking the time of the arg as a Command in the form of Value.
+    pub fn command_value(arg: ExpireArg) -> Value {
+        let (ExpireTime::Seconds(time)
+        | ExpireTime::Millis(time)
+        | ExpireTime::UnixSeconds(time)
+        | ExpireTime::UnixMillis(time)) = arg.time;
+        let mut v = vec![
+            Value::BulkString(arg.time.command_name().to_uppercase().into()),
+            Value::BulkString(arg.key),
+            Value::BulkString(time.to_string().into()),
+        ];
+        v.extend(
+            arg.conditions
+                .iter()
+                .map(|condition| Value::BulkString(condition.name().into())),
+        );
+        Value::Array(v.into())
+    }
+}
+
+pub struct ExpireHandler {
+    map: Arc<Store>,
+    clock: Arc<dyn Clock>,
+    lazyfree: Option<Arc<LazyFree>>,
+    events: Option<Arc<KeyEvents>>,
+}
+
+impl ExpireHandler {
+    /// Tells the listeners about keys given a deadline or removed.
+    pub fn with_events(mut self, events: Arc<KeyEvents>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Sets the deadline of the key, returning 1 if it did, or 0 if the key doesn't exist
+    /// or a condition isn't met.
+    pub fn handle(&self, arg: ExpireArg) -> Result<Value, HandleCommandError> {
+        let key = arg.key.as_bytes().unwrap_or_default();
+        let now = self.clock.now();
+
+        let mut shard = self.map.write(key);
+        let current = match shard.get(key) {
+            Some(data) if !data.expired_at(now) => data.deadline,
+            _ => return Ok(Value::Integer(Integer::new(0))),
+        };
+        let deadline = arg
+            .time
+            .deadline(now)
+            .ok_or(ParseCommandError::InvalidExpireTime(
+                arg.time.command_name(),
+            ))?;
+        if !arg
+            .conditions
+            .iter()
+            .all(|condition| condition.allows(current, deadline))
+        {
+            return Ok(Value::Integer(Integer::new(0)));
+        }
+
+        let event = if deadline <= now {
+            let removed = shard.remove(key);
+            drop(shard);
+            if let (Some(lazyfree), Some(removed)) = (&self.lazyfree, removed) {
+                lazyfree.free(removed);
+            }
+            KeyEvent::Del
+        } else {
+            shard.set_deadline(key, Some(deadline));
+            drop(shard);
+            KeyEvent::Expire
+        };
+        if let Some(events) = &self.events {
+            events.notify(key, event);
+        }
+        Ok(Value::Integer(Integer::new(1)))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct PersistArg {
+    pub key: BulkString,
+}
+
+impl CommandArgParser for PersistArg {
+    /// PERSIST key
+    fn parse_arg(iter: &mut std::slice::Iter<'_, Value>) -> Result<Self, ParseCommandError> {
+        let args = consume_args_from_iter(iter, 1, 0)?;
+        let key = args.first().unwrap().clone();
+
+        Ok(Self { key })
+    }
+}
+
+pub struct Persist;
+
+impl Persist {
+    /// Returns an instance of PERSIST command handler.
+    pub fn handler(map: Arc<Store>, clock: Arc<dyn Clock>) -> PersistHandler {
+        PersistHandler {
+            map,
+            clock,
+            events: None,
+        }
+    }
+
+    /// Returns PERSIST as a Command in the form of Value.
+    pub fn command_value(arg: PersistArg) -> Value {
+        let v = vec![
+            Value::BulkString("PERSIST".into()),
+            Value::BulkString(arg.key),
+        ];
+        Value::Array(v.into())
+    }
+}
+
+pub struct PersistHandler {
+    map: Arc<Store>,
+    clock: Arc<dyn Clock>,
+    events: Option<Arc<KeyEvents>>,
+}
+
+impl PersistHandler {
+    /// Tells the listeners about keys whose deadline was removed.
+    pub fn with_events(mut self, events: Arc<KeyEvents>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Removes the deadline of the key, returning 1 if it had one, or 0 if it has none or
+    /// doesn't exist.
+    pub fn handle(&self, arg: PersistArg) -> Value {
+        let key = arg.key.as_bytes().unwrap_or_default();
+        let now = self.clock.now();
+
+        let mut shard = self.map.write(key);
+        let persisted = match shard.get(key) {
+            Some(data) if data.deadline.is_some() && !data.expired_at(now) => {
+                shard.set_deadline(key, None)
+            }
+            _ => false,
+        };
+        drop(shard);
+
+        if persisted {
+            if let Some(events) = &self.events {
+                events.notify(key, KeyEvent::Persist);
+            }
+        }
+        Value::Integer(Integer::new(persisted as i64))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::super::test_util;
+    use super::super::Command;
+    use super::*;
+
+    #[test]
+    fn parse_expire() {
+        let args = |parts: &[&str]| {
+            let value = test_util::command(parts.iter().copied());
+            value.array().unwrap().values().unwrap().to_vec()
+        };
+        for time in [
+            ExpireTime::Seconds(10),
+            ExpireTime::Millis(-5),
+            ExpireTime::UnixSeconds(1700000000),
+            ExpireTime::UnixMillis(1700000000000),
+        ] {
+            let arg = ExpireArg {
+                key: "key".into(),
+                time,
+                conditions: vec![ExpireCondition::Xx, ExpireCondition::Gt],
+            };
+            let parsed = Command::try_from(Expire::command_value(arg.clone())).unwrap();
+            assert_eq!(parsed.name(), time.command_name());
+            match parsed {
+                Command::Expire(parsed)
+                | Command::PExpire(parsed)
+                | Command::ExpireAt(parsed)
+                | Command::PExpireAt(parsed) => assert_eq!(parsed, arg),
+                other => panic!("Unexpected parse result {other:?}"),
+            }
+        }
+
+        assert!(matches!(
+            ExpireArg::parse_arg(&mut args(&["key", "10", "NX", "XX"]).iter()),
+            Err(ParseCommandError::IncompatibleOptions(_))
+        ));
+        assert!(matches!(
+            ExpireArg::parse_pexpire(&mut args(&["key", "10", "GT", "LT"]).iter()),
+            Err(ParseCommandError::IncompatibleOptions(_))
+        ));
+        assert!(matches!(
+            ExpireArg::parse_arg(&mut args(&["key", "10", "YY"]).iter()),
+            Err(ParseCommandError::InvalidArgument(_))
+        ));
+        assert!(matches!(
+            ExpireArg::parse_arg(&mut args(&["key", "soon"]).iter()),
+            Err(ParseCommandError::NotInteger)
+        ));
+    }
+
+    #[test]
+    fn parse_persist() {
+        let arg = PersistArg { key: "key".into() };
+        match Command::try_from(Persist::command_value(arg.clone())) {
+            Ok(Command::Persist(parsed)) => assert_eq!(parsed, arg),
+            other => panic!("Unexpected parse result {other:?}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod handler_test {
+    use super::super::super::clock::TestClock;
+    use super::super::super::handler::StoredData;
+    use super::super::super::key::Key;
+    use super::*;
+
+    fn expire(time: ExpireTime, conditions: &[ExpireCondition]) -> ExpireArg {
+        ExpireArg {
+            key: "key".into(),
+            time,
+            conditions: conditions.to_vec(),
+        }
+    }
+
+    #[test]
+    fn handle_expire() {
+        let clock = Arc::new(TestClock::new(UNIX_EPOCH + Duration::from_secs(1000)));
+        let map = Arc::new(Store::from_iter([(
+            Key::from("key"),
+            StoredData::new("value".into(), None),
+        )]));
+        let handler = Expire::handler(map.clone(), clock.clone(), None);
+        let deadline = || map.snapshot().get(b"key".as_slice()).unwrap().deadline;
+        let one = Value::Integer(Integer::new(1));
+        let zero = Value::Integer(Integer::new(0));
+
+        // XX and GT need a deadline to compare with.
+        let arg = expire(ExpireTime::Seconds(10), &[ExpireCondition::Xx]);
+        assert_eq!(handler.handle(arg).unwrap(), zero);
+        let arg = expire(ExpireTime::Seconds(10), &[ExpireCondition::Gt]);
+        assert_eq!(handler.handle(arg).unwrap(), zero);
+
+        let arg = expire(ExpireTime::Seconds(10), &[ExpireCondition::Nx]);
+        assert_eq!(handler.handle(arg).unwrap(), one);
+        assert_eq!(deadline(), Some(UNIX_EPOCH + Duration::from_secs(1010)));
+
+        let arg = expire(ExpireTime::Millis(20_000), &[ExpireCondition::Lt]);
+        assert_eq!(handler.handle(arg).unwrap(), zero);
+        let arg = expire(ExpireTime::UnixSeconds(1005), &[ExpireCondition::Lt]);
+        assert_eq!(handler.handle(arg).unwrap(), one);
+        let arg = expire(ExpireTime::UnixMillis(1_020_000), &[ExpireCondition::Gt]);
+        assert_eq!(handler.handle(arg).unwrap(), one);
+        assert_eq!(deadline(), Some(UNIX_EPOCH + Duration::from_secs(1020)));
+        assert_eq!(map.next_deadline(), deadline());
+
+        let arg = expire(ExpireTime::Seconds(i64::MAX), &[]);
+        assert!(matches!(
+            handler.handle(arg),
+            Err(HandleCommandError::Parse(
+                ParseCommandError::InvalidExpireTime("expire")
+            ))
+        ));
+
+        // A deadline in the past removes the key.
+        let arg = expire(ExpireTime::Seconds(-1), &[]);
+        assert_eq!(handler.handle(arg).unwrap(), one);
+        assert!(map.is_empty());
+        let arg = expire(ExpireTime::Seconds(10), &[]);
+        assert_eq!(handler.handle(arg).unwrap(), zero);
+    }
+
+    #[test]
+    fn handle_persist() {
+        let clock = Arc::new(TestClock::default());
+        let map = Arc::new(Store::from_iter([
+            (
+                Key::from("key"),
+                StoredData::new("value".into(), Some(clock.now() + Duration::from_secs(10))),
+            ),
+            (Key::from("forever"), StoredData::new("value".into(), None)),
+        ]));
+        let handler = Persist::handler(map.clone(), clock);
+        let persist = |key: &str| handler.handle(PersistArg { key: key.into() });
+
+        assert_eq!(persist("key"), Value::Integer(Integer::new(1)));
+        assert_eq!(persist("key"), Value::Integer(Integer::new(0)));
+        assert_eq!(persist("forever"), Value::Integer(Integer::new(0)));
+        assert_eq!(persist("missing"), Value::Integer(Integer::new(0)));
+        assert_eq!(map.next_deadline(), None);
+    }
+}
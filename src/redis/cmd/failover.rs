@@ -0,0 +1,415 @@
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use thiserror::Error;
+use tracing::{info, warn};
+
+use super::super::config::{HostPort, ServerConfig};
+use super::super::replica::Replication;
+use super::super::replication::{FailoverState, ReplicationState};
+use super::super::resp::{BulkString, SimpleString, Value};
+use super::super::util;
+use super::{bulk_string_to_string, CommandArgParser, ParseCommandError, Wait};
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum FailoverError {
+    #[error("FAILOVER is not valid when server is a replica.")]
+    Replica,
+
+    #[error("FAILOVER not allowed in cluster mode.")]
+    Cluster,
+
+    #[error("FAILOVER requires connected replicas.")]
+    NoReplicas,
+
+    #[error("FAILOVER target HOST and PORT is not a replica.")]
+    NotAReplica,
+
+    #[error("FAILOVER already in progress.")]
+    InProgress,
+
+    #[error("No failover in progress.")]
+    NotInProgress,
+
+    /// The target is being promoted, which can't be undone.
+    #[error("FAILOVER is promoting its target and can't be aborted.")]
+    Promoting,
+
+    #[error("FAILOVER timeout must be greater than 0")]
+    InvalidTimeout,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct FailoverArg {
+    /// Host and port of the replica to promote, any replica that catches up if not given.
+    pub to: Option<(String, u16)>,
+
+    /// Milliseconds to wait for the replica to catch up before giving up, refused by the
+    /// handler unless positive.
+    pub timeout: Option<i64>,
+
+    pub abort: bool,
+}
+
+impl CommandArgParser for FailoverArg {
+    /// FAILOVER [TO host port] [TIMEOUT milliseconds] [ABORT]
+    fn parse_arg(iter: &mut std::slice::Iter<'_, Value>) -> Result<Self, ParseCommandError> {
+        let mut arg = Self::default();
+        let invalid = |v: &Value| ParseCommandError::InvalidArgument(v.clone());
+        while let Some(v) = iter.next() {
+            let bs = v.bulk_string().ok_or_else(|| invalid(v))?;
+            match bulk_string_to_string(bs)?.to_lowercase().as_str() {
+                "to" if arg.to.is_none() => {
+                    let (host, port) = iter
+                        .next()
+                        .zip(iter.next())
+                        .ok_or(ParseCommandError::WrongNumArgs)?;
+                    let host = host.bulk_string().ok_or_else(|| invalid(host))?;
+                    let port_bs = port.bulk_string().ok_or_else(|| invalid(port))?;
+                    let port = bulk_string_to_string(port_bs)?
+                        .parse()
+                        .map_err(|_| invalid(port))?;
+                    arg.to = Some((bulk_string_to_string(host)?, port));
+                }
+                "timeout" if arg.timeout.is_none() => {
+                    let timeout = iter.next().ok_or(ParseCommandError::WrongNumArgs)?;
+                    let timeout_bs = timeout.bulk_string().ok_or_else(|| invalid(timeout))?;
+                    let timeout = bulk_string_to_string(timeout_bs)?
+                        .parse()
+                        .map_err(|_| invalid(timeout))?;
+                    arg.timeout = Some(timeout);
+                }
+                "abort" if !arg.abort => arg.abort = true,
+                _ => return Err(invalid(v)),
+            }
+        }
+
+        // Aborting takes no other option.
+        if arg.abort && (arg.to.is_some() || arg.timeout.is_some()) {
+            return Err(ParseCommandError::InvalidArgument(Value::BulkString(
+                "ABORT".into(),
+            )));
+        }
+        Ok(arg)
+    }
+}
+
+pub struct Failover;
+
+impl Failover {
+    /// Returns an instance of FAILOVER command handler.
+    pub fn handler(
+        replication: Option<Arc<ReplicationState>>,
+        config: Arc<ServerConfig>,
+    ) -> FailoverHandler {
+        FailoverHandler {
+            replication,
+            config,
+            cluster: false,
+        }
+    }
+
+    /// Returns FAILOVER as a Command in the form of Value.
+    pub fn command_value(arg: FailoverArg) -> Value {
+        let mut v = vec![Value::BulkString("FAILOVER".into())];
+        if let Some((host, port)) = arg.to {
+            v.push(Value::BulkString("TO".into()));
+            v.push(Value::BulkString(BulkString::from(host)));
+            v.push(Value::BulkString(BulkString::from(port.to_string())));
+        }
+        if let Some(timeout) = arg.timeout {
+            v.push(Value::BulkString("TIMEOUT".into()));
+            v.push(Value::BulkString(BulkString::from(timeout.to_string())));
+        }
+        if arg.abort {
+            v.push(Value::BulkString("ABORT".into()));
+        }
+        Value::Array(v.into())
+    }
+}
+
+#[derive(Debug)]
+pub struct FailoverHandler {
+    /// State of this master, `None` on a replica.
+    replication: Option<Arc<ReplicationState>>,
+    config: Arc<ServerConfig>,
+    cluster: bool,
+}
+
+impl FailoverHandler {
+    /// Refuses every failover in cluster mode, where CLUSTER FAILOVER is used instead.
+    pub fn with_cluster(mut self, cluster: bool) -> Self {
+        self.cluster = cluster;
+        self
+    }
+
+    /// Starts handing the master role over to the replica listening to the address given
+    /// with TO, or to the first replica that catches up, replying once started.
+    ///
+    /// Writes are paused meanwhile. Once the replica acknowledged every write, it is told to
+    /// serve as a master with `REPLICAOF NO ONE`, and `demote` makes this server follow it.
+    /// The failover is aborted, resuming writes on this master, if the replica doesn't catch
+    /// up before the timeout, can't be promoted, or FAILOVER ABORT is called first.
+    pub fn handle(
+        &self,
+        arg: FailoverArg,
+        demote: impl FnOnce(HostPort) + Send + 'static,
+    ) -> Result<Value, FailoverError> {
+        let replication = self.replication.clone().ok_or(FailoverError::Replica)?;
+        if self.cluster {
+            return Err(FailoverError::Cluster);
+        }
+        if arg.abort {
+            if replication.switch_failover(FailoverState::WaitingForSync, FailoverState::NoFailover)
+            {
+                return Ok(Value::SimpleString(SimpleString::from("OK")));
+            }
+            return match replication.failover_state() {
+                FailoverState::InProgress => Err(FailoverError::Promoting),
+                _ => Err(FailoverError::NotInProgress),
+            };
+        }
+        if replication.failover_state() != FailoverState::NoFailover {
+            return Err(FailoverError::InProgress);
+        }
+        if arg.timeout.is_some_and(|timeout| timeout <= 0) {
+            return Err(FailoverError::InvalidTimeout);
+        }
+        let target = match arg.to {
+            Some((host, port)) => {
+                // Replicas are known by the IP address they connected from.
+                let ip: IpAddr = host.parse().map_err(|_| FailoverError::NotAReplica)?;
+                let addr = SocketAddr::new(ip, port);
+                if !replication.stream().has_replica(addr) {
+                    return Err(FailoverError::NotAReplica);
+                }
+                Some(addr)
+            }
+            None => None,
+        };
+        if replication.stream().acked(0) == 0 {
+            return Err(FailoverError::NoReplicas);
+        }
+        if !replication.switch_failover(FailoverState::NoFailover, FailoverState::WaitingForSync) {
+            return Err(FailoverError::InProgress);
+        }
+
+        let timeout = arg
+            .timeout
+            .map(|timeout| Duration::from_millis(timeout as u64));
+        let config = self.config.clone();
+        util::spawn_named("failover", async move {
+            let caught_up = tokio::select! {
+                addr = Self::catch_up(&replication, target, timeout) => addr,
+                // FAILOVER ABORT was called.
+                _ = replication.failover_ended() => return,
+            };
+            let Some(addr) = caught_up else {
+                warn!("FAILOVER target didn't catch up in time, aborting the failover");
+                replication
+                    .switch_failover(FailoverState::WaitingForSync, FailoverState::NoFailover);
+                return;
+            };
+            if !replication
+                .switch_failover(FailoverState::WaitingForSync, FailoverState::InProgress)
+            {
+                return;
+            }
+
+            let target = HostPort::from(addr);
+            match Replication::promote(target.clone(), &config).await {
+                Ok(()) => {
+                    info!("Promoted {target}, following it as a replica");
+                    demote(target);
+                }
+                Err(e) => warn!("Unable to promote {target}, aborting the failover: {e}"),
+            }
+            replication.switch_failover(FailoverState::InProgress, FailoverState::NoFailover);
+        });
+        Ok(Value::SimpleString(SimpleString::from("OK")))
+    }
+
+    /// Waits until the replica listening to `target`, or any replica if `None`, acknowledged
+    /// every write propagated so far. Returns its address, or `None` once the timeout passed.
+    async fn catch_up(
+        replication: &ReplicationState,
+        target: Option<SocketAddr>,
+        timeout: Option<Duration>,
+    ) -> Option<SocketAddr> {
+        let deadline = timeout.map(|timeout| tokio::time::Instant::now() + timeout);
+        loop {
+            // Replicas answer GETACK with the offset before it.
+            let (offset, end) = {
+                let mut stream = replication.stream();
+                let offset = stream.offset();
+                stream.propagate(&Wait::getack());
+                (offset, stream.offset())
+            };
+            let timeout = deadline
+                .map(|deadline| deadline.saturating_duration_since(tokio::time::Instant::now()));
+            let addr = replication
+                .wait_for_replica(target, offset, timeout)
+                .await?;
+            // A write that ran before writes were paused may have been propagated meanwhile.
+            if replication.offset() == end {
+                return Some(addr);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::super::test_util;
+    use super::super::Command;
+    use super::*;
+
+    #[test]
+    fn command() {
+        let val = Failover::command_value(FailoverArg {
+            to: Some(("127.0.0.1".to_string(), 6380)),
+            timeout: Some(500),
+            abort: false,
+        });
+
+        assert_eq!(
+            val.array().unwrap().values().unwrap().to_vec(),
+            vec![
+                Value::BulkString("FAILOVER".into()),
+                Value::BulkString("TO".into()),
+                Value::BulkString("127.0.0.1".into()),
+                Value::BulkString("6380".into()),
+                Value::BulkString("TIMEOUT".into()),
+                Value::BulkString("500".into()),
+            ]
+        )
+    }
+
+    #[test]
+    fn parse_failover() {
+        let arg = FailoverArg {
+            to: Some(("localhost".to_string(), 6380)),
+            timeout: Some(500),
+            abort: false,
+        };
+        match Command::try_from(Failover::command_value(arg.clone())) {
+            Ok(Command::Failover(parsed)) => assert_eq!(parsed, arg),
+            res => panic!("Wrong parse result for failover: {res:?}"),
+        }
+
+        let parse = |args: &[&str]| {
+            let mut v = vec!["FAILOVER"];
+            v.extend(args);
+            Command::try_from(test_util::command(v))
+        };
+        assert!(parse(&["ABORT", "TIMEOUT", "10"]).is_err());
+        assert!(parse(&["TO", "localhost"]).is_err());
+        match parse(&["TIMEOUT", "-1"]) {
+            Ok(Command::Failover(parsed)) => assert_eq!(parsed.timeout, Some(-1)),
+            res => panic!("Wrong parse result for failover: {res:?}"),
+        }
+        assert!(parse(&["ABORT", "ABORT"]).is_err());
+    }
+}
+
+#[cfg(test)]
+mod handler_test {
+    use super::super::super::clients::ClientRegistry;
+    use super::super::super::reply::Reply;
+    use super::*;
+
+    #[tokio::test]
+    async fn handle_failover() {
+        let config = Arc::new(ServerConfig::default());
+        let demote = |_| panic!("Demoted without a failover");
+        assert_eq!(
+            Failover::handler(None, config.clone()).handle(FailoverArg::default(), demote),
+            Err(FailoverError::Replica)
+        );
+
+        let replication = Arc::new(ReplicationState::new());
+        let handler = Failover::handler(Some(replication.clone()), config);
+        assert_eq!(
+            handler.handle(FailoverArg::default(), demote),
+            Err(FailoverError::NoReplicas)
+        );
+        assert_eq!(
+            handler.handle(
+                FailoverArg {
+                    timeout: Some(0),
+                    ..Default::default()
+                },
+                demote
+            ),
+            Err(FailoverError::InvalidTimeout)
+        );
+        assert_eq!(
+            handler.handle(
+                FailoverArg {
+                    timeout: Some(-1),
+                    ..Default::default()
+                },
+                demote
+            ),
+            Err(FailoverError::InvalidTimeout)
+        );
+        let abort = FailoverArg {
+            abort: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            handler.handle(abort.clone(), demote),
+            Err(FailoverError::NotInProgress)
+        );
+
+        let registry = Arc::new(ClientRegistry::new());
+        let mut replica = registry.register(None, None);
+        let _rx = replica.take_receiver().unwrap();
+        let link = replica.state().lock().link().unwrap();
+        let addr: SocketAddr = "127.0.0.1:6380".parse().unwrap();
+        replication.attach(link, Some(addr), |_| Reply::Nothing);
+        assert_eq!(
+            handler.handle(
+                FailoverArg {
+                    to: Some(("127.0.0.1".to_string(), 6381)),
+                    ..Default::default()
+                },
+                demote
+            ),
+            Err(FailoverError::NotAReplica)
+        );
+
+        // The replica never acknowledges, so the failover waits until aborted.
+        let to = FailoverArg {
+            to: Some(("127.0.0.1".to_string(), 6380)),
+            ..Default::default()
+        };
+        assert_eq!(
+            handler.handle(to.clone(), demote),
+            Ok(Value::SimpleString(SimpleString::from("OK")))
+        );
+        assert_eq!(replication.failover_state(), FailoverState::WaitingForSync);
+        assert_eq!(handler.handle(to, demote), Err(FailoverError::InProgress));
+        assert_eq!(
+            handler.handle(abort.clone(), demote),
+            Ok(Value::SimpleString(SimpleString::from("OK")))
+        );
+        assert_eq!(replication.failover_state(), FailoverState::NoFailover);
+
+        // Neither does it before the timeout, which aborts the failover too.
+        assert_eq!(
+            handler.handle(
+                FailoverArg {
+                    timeout: Some(10),
+                    ..Default::default()
+                },
+                demote
+            ),
+            Ok(Value::SimpleString(SimpleString::from("OK")))
+        );
+        tokio::time::timeout(Duration::from_secs(1), replication.failover_ended())
+            .await
+            .expect("Failover not aborted after its timeout");
+    }
+}
@@ -0,0 +1,277 @@
+use std::sync::Arc;
+
+use parking_lot::RwLock;
+
+use super::super::resp::{BulkString, SimpleString, Value};
+use super::super::scripting::{self, Functions, ScriptCall, ScriptError};
+use super::{
+    bulk_string_to_string, value_to_bulk_string, CommandArgParser, EvalArg, ParseCommandError,
+};
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum FunctionSubcommand {
+    /// Loads a library, replacing the one of the same name only if `replace`.
+    Load {
+        code: BulkString,
+        replace: bool,
+    },
+    Delete(String),
+    Flush,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct FunctionArg {
+    pub subcommand: FunctionSubcommand,
+}
+
+impl CommandArgParser for FunctionArg {
+    /// FUNCTION LOAD [REPLACE] function-code
+    /// FUNCTION DELETE library-name
+    /// FUNCTION FLUSH [ASYNC|SYNC]
+    fn parse_arg(iter: &mut std::slice::Iter<'_, Value>) -> Result<Self, ParseCommandError> {
+        let args = iter
+            .map(value_to_bulk_string)
+            .collect::<Result<Vec<BulkString>, ParseCommandError>>()?;
+        let (subcommand, rest) = args.split_first().ok_or(ParseCommandError::WrongNumArgs)?;
+        let is = |bs: &BulkString, option: &str| {
+            bs.as_str().is_some_and(|s| s.eq_ignore_ascii_case(option))
+        };
+
+        let subcommand = match (
+            bulk_string_to_string(subcommand)?.to_lowercase().as_str(),
+            rest,
+        ) {
+            ("load", [code]) => FunctionSubcommand::Load {
+                code: code.clone(),
+                replace: false,
+            },
+            ("load", [replace, code]) if is(replace, "replace") => FunctionSubcommand::Load {
+                code: code.clone(),
+                replace: true,
+            },
+            ("delete", [library]) => FunctionSubcommand::Delete(bulk_string_to_string(library)?),
+            ("flush", []) => FunctionSubcommand::Flush,
+            ("flush", [mode]) if is(mode, "async") || is(mode, "sync") => FunctionSubcommand::Flush,
+            ("load" | "flush", [option, ..]) => {
+                return Err(ParseCommandError::InvalidArgument(Value::BulkString(
+                    option.clone(),
+                )))
+            }
+            ("delete", _) => return Err(ParseCommandError::WrongNumArgs),
+            _ => {
+                return Err(ParseCommandError::InvalidArgument(Value::BulkString(
+                    subcommand.clone(),
+                )))
+            }
+        };
+
+        Ok(Self { subcommand })
+    }
+}
+
+pub struct Function;
+
+impl Function {
+    /// Returns an instance of FUNCTION command handler.
+    pub fn handler(functions: Arc<RwLock<Functions>>) -> FunctionHandler {
+        FunctionHandler { functions }
+    }
+
+    /// Returns FUNCTION as a Command in the form of Value.
+    pub fn command_value(arg: FunctionArg) -> Value {
+        let mut v = vec![Value::BulkString("FUNCTION".into())];
+        match arg.subcommand {
+            FunctionSubcommand::Load { code, replace } => {
+                v.push(Value::BulkString("LOAD".into()));
+                if replace {
+                    v.push(Value::BulkString("REPLACE".into()));
+                }
+                v.push(Value::BulkString(code));
+            }
+            FunctionSubcommand::Delete(library) => {
+                v.push(Value::BulkString("DELETE".into()));
+                v.push(Value::BulkString(library.into()));
+            }
+            FunctionSubcommand::Flush => v.push(Value::BulkString("FLUSH".into())),
+        }
+        Value::Array(v.into())
+    }
+}
+
+#[derive(Debug)]
+pub struct FunctionHandler {
+    functions: Arc<RwLock<Functions>>,
+}
+
+impl FunctionHandler {
+    /// Manages the function libraries FCALL calls.
+    pub fn handle(&self, arg: FunctionArg) -> Result<Value, ScriptError> {
+        let mut functions = self.functions.write();
+        match arg.subcommand {
+            FunctionSubcommand::Load { code, replace } => {
+                let code = code.as_bytes().unwrap_or_default();
+                let library = functions.load(code, replace)?;
+                Ok(Value::BulkString(library.into()))
+            }
+            FunctionSubcommand::Delete(library) => {
+                functions.delete(&library)?;
+                Ok(Value::SimpleString(SimpleString::from("OK")))
+            }
+            FunctionSubcommand::Flush => {
+                functions.flush();
+                Ok(Value::SimpleString(SimpleString::from("OK")))
+            }
+        }
+    }
+}
+
+pub struct FCall;
+
+impl FCall {
+    /// Returns an instance of FCALL command handler.
+    pub fn handler(functions: Arc<RwLock<Functions>>) -> FCallHandler {
+        FCallHandler {
+            functions,
+            read_only: false,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct FCallHandler {
+    functions: Arc<RwLock<Functions>>,
+    read_only: bool,
+}
+
+impl FCallHandler {
+    /// Only calls functions flagged `no-writes`, as FCALL_RO does.
+    pub fn with_read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    /// Calls the function, which runs commands with `call`. Functions flagged `no-writes`
+    /// may not call write commands even through FCALL.
+    pub fn handle(&self, arg: EvalArg, call: &mut ScriptCall) -> Result<Value, ScriptError> {
+        let name = arg.script.as_str().ok_or(ScriptError::NoSuchFunction)?;
+        let function = self
+            .functions
+            .read()
+            .function(&name)
+            .ok_or(ScriptError::NoSuchFunction)?;
+        if self.read_only && !function.no_writes {
+            return Err(ScriptError::WriteFunction);
+        }
+
+        let read_only = self.read_only || function.no_writes;
+        scripting::fcall(&function.code, &name, &arg.keys, &arg.args, read_only, call)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::super::test_util;
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let cmd = test_util::command(["LOAD", "replace", "#!lua name=lib"]);
+        let mut iter = cmd.array().unwrap().values().unwrap().iter();
+
+        assert_eq!(
+            FunctionArg::parse_arg(&mut iter).expect("Parse function unexpected error"),
+            FunctionArg {
+                subcommand: FunctionSubcommand::Load {
+                    code: "#!lua name=lib".into(),
+                    replace: true,
+                },
+            }
+        );
+    }
+
+    #[test]
+    fn command() {
+        let val = Function::command_value(FunctionArg {
+            subcommand: FunctionSubcommand::Delete("lib".into()),
+        });
+
+        assert_eq!(
+            val.array().unwrap().values().unwrap().to_vec(),
+            vec![
+                Value::BulkString("FUNCTION".into()),
+                Value::BulkString("DELETE".into()),
+                Value::BulkString("lib".into()),
+            ]
+        )
+    }
+}
+
+#[cfg(test)]
+mod handler_test {
+    use super::*;
+
+    const LIBRARY: &str = "#!lua name=lib\n\
+        redis.register_function('set', function(keys, args) \
+            return redis.call('set', keys[1], args[1]) end)\n\
+        redis.register_function{function_name='get', \
+            callback=function(keys) return redis.call('get', keys[1]) end, \
+            flags={'no-writes'}}";
+
+    fn load() -> Arc<RwLock<Functions>> {
+        let functions = Arc::new(RwLock::new(Functions::default()));
+        let resp = Function::handler(functions.clone())
+            .handle(FunctionArg {
+                subcommand: FunctionSubcommand::Load {
+                    code: LIBRARY.into(),
+                    replace: false,
+                },
+            })
+            .expect("Handle function load unexpected error");
+        assert_eq!(resp, Value::BulkString("lib".into()));
+        functions
+    }
+
+    fn fcall(function: &str) -> EvalArg {
+        EvalArg {
+            script: function.into(),
+            keys: vec!["key".into()],
+            args: vec!["value".into()],
+        }
+    }
+
+    #[test]
+    fn handle_fcall_ro() {
+        let functions = load();
+        let mut calls = vec![];
+        let mut call = |argv: Vec<BulkString>, read_only: bool| {
+            calls.push((argv[0].clone(), read_only));
+            Value::SimpleString(SimpleString::from("OK"))
+        };
+
+        let err = FCall::handler(functions.clone())
+            .with_read_only(true)
+            .handle(fcall("set"), &mut call)
+            .expect_err("Handle fcall_ro no error");
+        assert_eq!(err, ScriptError::WriteFunction);
+
+        FCall::handler(functions.clone())
+            .with_read_only(true)
+            .handle(fcall("get"), &mut call)
+            .expect("Handle fcall_ro unexpected error");
+        // A no-writes function is read-only even when called with FCALL.
+        FCall::handler(functions.clone())
+            .handle(fcall("get"), &mut call)
+            .expect("Handle fcall unexpected error");
+        FCall::handler(functions)
+            .handle(fcall("set"), &mut call)
+            .expect("Handle fcall unexpected error");
+        assert_eq!(
+            calls,
+            vec![
+                ("get".into(), true),
+                ("get".into(), true),
+                ("set".into(), false),
+            ]
+        );
+    }
+}
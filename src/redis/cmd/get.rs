@@ -1,11 +1,12 @@
-use std::collections::hash_map::Entry;
-use std::collections::HashMap;
-use std::sync::{Arc, RwLock};
-
-use super::super::client::ClientError;
-use super::super::handler::StoredData;
-use super::super::resp::{Array, BulkString, SimpleString, Value};
-use super::super::session::{Request, Responder, Response};
+use std::sync::Arc;
+
+use super::super::clock::Clock;
+use super::super::events::{KeyEvent, KeyEvents};
+use super::super::eviction::LfuConfig;
+use super::super::handler::{HandleCommandError, StoredData};
+use super::super::lazyfree::LazyFree;
+use super::super::resp::{BulkString, Value};
+use super::super::store::Store;
 use super::{consume_args_from_iter, CommandArgParser, ParseCommandError};
 
 #[derive(Debug, Clone)]
@@ -16,7 +17,7 @@ pub struct GetArg {
 impl CommandArgParser for GetArg {
     fn parse_arg(iter: &mut std::slice::Iter<'_, Value>) -> Result<Self, ParseCommandError> {
         let args = consume_args_from_iter(iter, 1, 0)?;
-        let key = args.get(0).unwrap().clone();
+        let key = args.first().unwrap().clone();
 
         Ok(Self { key })
     }
@@ -31,8 +32,17 @@ impl Get {
     }
 
     /// Returns an instance of GET command handler.
-    pub fn handler(map: Arc<RwLock<HashMap<BulkString, StoredData>>>) -> GetHandler {
-        GetHandler { map }
+    ///
+    /// Expired keys are freed on `lazyfree` if given.
+    pub fn handler(
+        map: Arc<Store>,
+        clock: Arc<dyn Clock>,
+        lfu: LfuConfig,
+        lazyfree: Option<Arc<LazyFree>>,
+    ) -> GetHandler {
+        GetHandler {
+            lookup: Lookup::new(map, clock, lfu, lazyfree),
+        }
     }
 
     /// Returns GET as a Command in the form of Value.
@@ -45,46 +55,105 @@ impl Get {
 pub struct GetClient;
 
 pub struct GetHandler {
-    map: Arc<RwLock<HashMap<BulkString, StoredData>>>,
+    lookup: Lookup,
 }
 
 impl GetHandler {
+    /// Tells the listeners about keys found expired.
+    pub fn with_events(mut self, events: Arc<KeyEvents>) -> Self {
+        self.lookup = self.lookup.with_events(events);
+        self
+    }
+
     /// Get the value of key.
     /// If the key does not exist the special value nil is returned.
     ///
-    /// On getting a key, if the value stored in the key has expired, it will be removed.
-    /// TODO: Implement active expiry on-top of this passive one.
-    pub fn handle(&mut self, arg: GetArg) -> Value {
-        // Read lock to access data.
-        let read_map = self.map.read().expect("RwLock poisoned");
-        // Clone the data.
-        let data = match read_map.get(&arg.key) {
-            Some(data) => data.clone(),
-            None => return Value::BulkString(BulkString::null()),
-        };
+    /// Fails with WRONGTYPE if the key holds another type than a string.
+    pub fn handle(&mut self, arg: GetArg) -> Result<Value, HandleCommandError> {
+        let key = arg.key.as_bytes().unwrap_or_default();
+        match self.lookup.get(key) {
+            Some(data) => {
+                let value = data
+                    .value
+                    .as_string()
+                    .ok_or(HandleCommandError::WrongType)?;
+                Ok(Value::BulkString(value.clone()))
+            }
+            None => Ok(Value::BulkString(BulkString::null())),
+        }
+    }
+}
+
+/// Looks keys up the way reads do: finding a key counts as an access, and a key found
+/// expired is removed.
+pub struct Lookup {
+    map: Arc<Store>,
+    clock: Arc<dyn Clock>,
+    lfu: LfuConfig,
+    lazyfree: Option<Arc<LazyFree>>,
+    events: Option<Arc<KeyEvents>>,
+}
+
+impl Lookup {
+    /// Expired keys are freed on `lazyfree` if given.
+    pub fn new(
+        map: Arc<Store>,
+        clock: Arc<dyn Clock>,
+        lfu: LfuConfig,
+        lazyfree: Option<Arc<LazyFree>>,
+    ) -> Self {
+        Self {
+            map,
+            clock,
+            lfu,
+            lazyfree,
+            events: None,
+        }
+    }
+
+    /// Tells the listeners about keys found expired.
+    pub fn with_events(mut self, events: Arc<KeyEvents>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Returns the data held by the key, unless it doesn't exist or has expired.
+    ///
+    /// Keys that are never looked up are left to the active expire cycle.
+    pub fn get(&self, key: &[u8]) -> Option<StoredData> {
+        // Read lock the key's shard to access data.
+        let read_map = self.map.read(key);
+        // Clone the data, which shares the value's bytes rather than copying them.
+        let data = read_map.get(key).map(|data| {
+            data.access.touch(self.lfu);
+            data.clone()
+        })?;
 
         // Unlock, since we already have the cloned data.
         drop(read_map);
 
         // No deadline or deadline haven't reached yet.
-        if !data.has_expired() {
-            return Value::BulkString(data.value);
+        let now = self.clock.now();
+        if !data.expired_at(now) {
+            return Some(data);
         }
 
         // Deadline passed, we should clear the entry.
         // Write lock and test that entry is still expired. We need to test it again since
         // the entry could have been overwritten by the time we acquire write lock.
-        let mut write_map = self.map.write().expect("RwLock poisonsed");
-        match write_map.entry(arg.key.clone()) {
-            Entry::Occupied(e) => {
-                if e.get().has_expired() {
-                    e.remove();
-                }
+        let mut write_map = self.map.write(key);
+        if write_map.get(key).is_some_and(|data| data.expired_at(now)) {
+            let expired = write_map.remove(key);
+            drop(write_map);
+            if let Some(events) = &self.events {
+                events.notify(key, KeyEvent::Expired);
+            }
+            if let (Some(lazyfree), Some(expired)) = (&self.lazyfree, expired) {
+                lazyfree.free(expired);
             }
-            Entry::Vacant(_) => (),
-        };
+        }
 
-        Value::BulkString(BulkString::null())
+        None
     }
 }
 
@@ -108,16 +177,21 @@ mod test {
 
 #[cfg(test)]
 mod handler_test {
+    use super::super::super::clock;
+    use super::super::super::handler::{StoredData, StoredValue};
+    use super::super::super::key::Key;
     use super::*;
 
-    fn new_get_handler(map: Arc<RwLock<HashMap<BulkString, StoredData>>>) -> GetHandler {
-        Get::handler(map)
+    fn new_get_handler(map: Arc<Store>) -> GetHandler {
+        Get::handler(map, clock::system(), LfuConfig::default(), None)
     }
 
     fn simple_get(handler: &mut GetHandler, k: &str) -> Value {
         let key = BulkString::from(k);
 
-        handler.handle(GetArg { key })
+        handler
+            .handle(GetArg { key })
+            .expect("Handle get unexpected error")
     }
 
     #[test]
@@ -125,19 +199,27 @@ mod handler_test {
         let key = "My Key";
         let value = "My Value";
 
-        let mut map = HashMap::new();
-        map.insert(
-            BulkString::from(key),
-            StoredData {
-                value: BulkString::from(value),
-                deadline: None,
-            },
-        );
-
-        let map = Arc::new(RwLock::new(map));
+        let map = Arc::new(Store::from_iter([(
+            Key::from(key),
+            StoredData::new(BulkString::from(value).into(), None),
+        )]));
         let mut handler = new_get_handler(map.clone());
 
         let get_value = simple_get(&mut handler, key);
         assert_eq!(get_value, Value::BulkString(value.into()));
     }
+
+    #[test]
+    fn handle_get_wrong_type() {
+        let map = Arc::new(Store::from_iter([(
+            Key::from("list"),
+            StoredData::new(StoredValue::List(["a".into()].into()), None),
+        )]));
+        let mut handler = new_get_handler(map);
+
+        let err = handler
+            .handle(GetArg { key: "list".into() })
+            .expect_err("Handle get no error");
+        assert!(matches!(err, HandleCommandError::WrongType));
+    }
 }
@@ -0,0 +1,149 @@
+use std::sync::Arc;
+
+use super::super::clock::Clock;
+use super::super::handler::HandleCommandError;
+use super::super::resp::{BulkString, Value};
+use super::super::store::Store;
+use super::{bulk_string_to_int64, consume_args_from_iter, CommandArgParser, ParseCommandError};
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct GetRangeArg {
+    pub key: BulkString,
+    pub start: i64,
+    pub end: i64,
+}
+
+impl CommandArgParser for GetRangeArg {
+    /// GETRANGE key start end
+    fn parse_arg(iter: &mut std::slice::Iter<'_, Value>) -> Result<Self, ParseCommandError> {
+        let args = consume_args_from_iter(iter, 3, 0)?;
+        Ok(Self {
+            key: args[0].clone(),
+            start: bulk_string_to_int64(&args[1])?,
+            end: bulk_string_to_int64(&args[2])?,
+        })
+    }
+}
+
+pub struct GetRange;
+
+impl GetRange {
+    /// Returns an instance of GETRANGE command handler.
+    pub fn handler(map: Arc<Store>, clock: Arc<dyn Clock>) -> GetRangeHandler {
+        GetRangeHandler { map, clock }
+    }
+
+    /// Returns GETRANGE as a Command in the form of Value.
+    pub fn command_value(arg: GetRangeArg) -> Value {
+        let v = vec![
+            Value::BulkString("GETRANGE".into()),
+            Value::BulkString(arg.key),
+            Value::BulkString(arg.start.to_string().into()),
+            Value::BulkString(arg.end.to_string().into()),
+        ];
+        Value::Array(v.into())
+    }
+}
+
+#[derive(Debug)]
+pub struct GetRangeHandler {
+    map: Arc<Store>,
+    clock: Arc<dyn Clock>,
+}
+
+impl GetRangeHandler {
+    /// Returns the bytes of the string held by the key from `start` to `end`, both included.
+    /// Negative offsets count from the end, -1 being the last byte, and offsets past either
+    /// end are clamped to the string. A missing key reads as an empty string.
+    pub fn handle(&self, arg: GetRangeArg) -> Result<Value, HandleCommandError> {
+        let key = arg.key.as_bytes().unwrap_or_default();
+        let now = self.clock.now();
+        let value = match self.map.read(key).get(key) {
+            Some(data) if !data.expired_at(now) => data
+                .value
+                .as_string()
+                .ok_or(HandleCommandError::WrongType)?
+                .clone(),
+            _ => BulkString::from(""),
+        };
+
+        let bytes = value.as_bytes().unwrap_or_default();
+        let range = match byte_range(bytes.len(), arg.start, arg.end) {
+            Some((start, end)) => &bytes[start..=end],
+            None => &[],
+        };
+        Ok(Value::BulkString(BulkString::from(range.to_vec())))
+    }
+}
+
+/// Returns the first and last index of the range of a string of `len` bytes, or `None` if
+/// the range is empty.
+fn byte_range(len: usize, start: i64, end: i64) -> Option<(usize, usize)> {
+    let len = len as i64;
+    // Both offsets counting from the end with the start after the end is empty, even if
+    // clamping would make the start 0.
+    if start < 0 && end < 0 && start > end {
+        return None;
+    }
+    let resolve = |i: i64| if i < 0 { (len + i).max(0) } else { i };
+    let (start, end) = (resolve(start), resolve(end).min(len - 1));
+    (start <= end).then_some((start as usize, end as usize))
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::Command;
+    use super::*;
+
+    #[test]
+    fn parse_getrange() {
+        let arg = GetRangeArg {
+            key: "key".into(),
+            start: 0,
+            end: -1,
+        };
+        match Command::try_from(GetRange::command_value(arg.clone())) {
+            Ok(Command::GetRange(parsed)) => assert_eq!(parsed, arg),
+            other => panic!("Unexpected parse result {other:?}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod handler_test {
+    use super::super::super::clock;
+    use super::super::super::handler::StoredData;
+    use super::super::super::key::Key;
+    use super::*;
+
+    #[test]
+    fn handle_getrange() {
+        let map = Arc::new(Store::from_iter([(
+            Key::from("key"),
+            StoredData::new("This is a string".into(), None),
+        )]));
+        let handler = GetRange::handler(map, clock::system());
+        let getrange = |key: &str, start, end| {
+            handler
+                .handle(GetRangeArg {
+                    key: key.into(),
+                    start,
+                    end,
+                })
+                .expect("Handle getrange unexpected error")
+        };
+
+        assert_eq!(getrange("key", 0, 3), Value::BulkString("This".into()));
+        assert_eq!(getrange("key", -3, -1), Value::BulkString("ing".into()));
+        assert_eq!(
+            getrange("key", 0, -1),
+            Value::BulkString("This is a string".into())
+        );
+        assert_eq!(getrange("key", 10, 100), Value::BulkString("string".into()));
+        assert_eq!(getrange("key", -100, 1), Value::BulkString("Th".into()));
+        assert_eq!(getrange("key", 5, 2), Value::BulkString("".into()));
+        assert_eq!(getrange("key", -1, -5), Value::BulkString("".into()));
+        assert_eq!(getrange("key", 100, 200), Value::BulkString("".into()));
+        assert_eq!(getrange("missing", 0, -1), Value::BulkString("".into()));
+    }
+}
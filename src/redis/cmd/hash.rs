@@ -0,0 +1,1232 @@
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use super::super::clock::Clock;
+use super::super::events::{KeyEvent, KeyEvents};
+use super::super::handler::{parse_int, HandleCommandError, StoredData, StoredValue};
+use super::super::hash::Hash;
+use super::super::key::Key;
+use super::super::listpack::ListpackLimits;
+use super::super::reply::StreamedArray;
+use super::super::resp::{Array, BulkString, Integer, Value};
+use super::super::store::{Shard, Store};
+use super::{
+    bulk_string_to_int64, consume_args_from_iter, consume_variadic_args_from_iter,
+    CommandArgParser, ExpireTime, ParseCommandError,
+};
+
+/// Calls `f` with the hash held by the key, returning `None` if the key doesn't exist.
+fn read_hash<T>(
+    map: &Store,
+    now: SystemTime,
+    key: &[u8],
+    f: impl FnOnce(&Hash) -> T,
+) -> Result<Option<T>, HandleCommandError> {
+    match map.read(key).get(key) {
+        Some(data) if !data.expired_at(now) => {
+            let hash = data.value.as_hash().ok_or(HandleCommandError::WrongType)?;
+            Ok(Some(f(hash)))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Returns the hash held by the key, which a missing or expired key starts as empty.
+/// Fields that expired are removed first.
+fn hash_mut<'a>(
+    shard: &'a mut Shard,
+    key: &[u8],
+    now: SystemTime,
+) -> Result<&'a mut Hash, HandleCommandError> {
+    if shard.get(key).is_some_and(|data| data.expired_at(now)) {
+        shard.remove(key);
+    }
+    if shard.get(key).is_none() {
+        let hash = StoredValue::Hash(Hash::new());
+        shard.insert(Key::new(key), StoredData::new(hash, None));
+    }
+    let hash = shard
+        .value_mut(key)
+        .and_then(StoredValue::as_hash_mut)
+        .ok_or(HandleCommandError::WrongType)?;
+    hash.remove_expired(now);
+    Ok(hash)
+}
+
+/// Returns the hash held by the key with its expired fields removed, or `None` if the key
+/// doesn't exist.
+fn existing_hash_mut<'a>(
+    shard: &'a mut Shard,
+    key: &[u8],
+    now: SystemTime,
+) -> Result<Option<&'a mut Hash>, HandleCommandError> {
+    if shard.get(key).is_none_or(|data| data.expired_at(now)) {
+        return Ok(None);
+    }
+    let hash = shard
+        .value_mut(key)
+        .and_then(StoredValue::as_hash_mut)
+        .ok_or(HandleCommandError::WrongType)?;
+    hash.remove_expired(now);
+    Ok(Some(hash))
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct HSetArg {
+    pub key: BulkString,
+    pub pairs: Vec<(BulkString, BulkString)>,
+}
+
+impl CommandArgParser for HSetArg {
+    /// HSET key field value [field value ...]
+    fn parse_arg(iter: &mut std::slice::Iter<'_, Value>) -> Result<Self, ParseCommandError> {
+        let mut args = consume_variadic_args_from_iter(iter, 3)?;
+        let key = args.remove(0);
+        if args.len() % 2 != 0 {
+            return Err(ParseCommandError::WrongArity("hset"));
+        }
+        let pairs = args
+            .chunks_exact(2)
+            .map(|pair| (pair[0].clone(), pair[1].clone()))
+            .collect();
+
+        Ok(Self { key, pairs })
+    }
+}
+
+pub struct HSet;
+
+impl HSet {
+    /// Returns an instance of HSET command handler.
+    pub fn handler(map: Arc<Store>, clock: Arc<dyn Clock>) -> HSetHandler {
+        HSetHandler {
+            map,
+            clock,
+            events: None,
+            limits: ListpackLimits::DEFAULT,
+        }
+    }
+
+    /// Returns HSET as a Command in the form of Value.
+    pub fn command_value(arg: HSetArg) -> Value {
+        let mut v = vec![Value::BulkString("HSET".into()), Value::BulkString(arg.key)];
+        for (field, value) in arg.pairs {
+            v.push(Value::BulkString(field));
+            v.push(Value::BulkString(value));
+        }
+        Value::Array(v.into())
+    }
+}
+
+pub struct HSetHandler {
+    map: Arc<Store>,
+    clock: Arc<dyn Clock>,
+    events: Option<Arc<KeyEvents>>,
+    limits: ListpackLimits,
+}
+
+impl HSetHandler {
+    /// Tells the listeners about keys set.
+    pub fn with_events(mut self, events: Arc<KeyEvents>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Limits up to which the hash stays packed, see `hash-max-listpack-entries` and
+    /// `hash-max-listpack-value`.
+    pub fn with_listpack_limits(mut self, limits: ListpackLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Sets the fields to their values, returning how many of the fields are new. A field
+    /// given more than once ends up with the last of its values.
+    pub fn handle(&self, arg: HSetArg) -> Result<Value, HandleCommandError> {
+        let key = arg.key.as_bytes().unwrap_or_default();
+        let now = self.clock.now();
+
+        let mut shard = self.map.write(key);
+        let hash = hash_mut(&mut shard, key, now)?;
+        let mut added = 0;
+        for (field, value) in arg.pairs {
+            if hash.insert(field, value, self.limits).is_none() {
+                added += 1;
+            }
+        }
+        drop(shard);
+
+        if let Some(events) = &self.events {
+            events.notify(key, KeyEvent::HSet);
+        }
+        Ok(Value::Integer(Integer::new(added)))
+    }
+}
+
+/// Arguments of HGET and HEXISTS.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct HFieldArg {
+    pub key: BulkString,
+    pub field: BulkString,
+}
+
+impl CommandArgParser for HFieldArg {
+    /// HGET key field
+    fn parse_arg(iter: &mut std::slice::Iter<'_, Value>) -> Result<Self, ParseCommandError> {
+        let args = consume_args_from_iter(iter, 2, 0)?;
+
+        Ok(Self {
+            key: args[0].clone(),
+            field: args[1].clone(),
+        })
+    }
+}
+
+fn field_command_value(name: &str, arg: HFieldArg) -> Value {
+    let v = vec![
+        Value::BulkString(name.into()),
+        Value::BulkString(arg.key),
+        Value::BulkString(arg.field),
+    ];
+    Value::Array(v.into())
+}
+
+pub struct HGet;
+
+impl HGet {
+    /// Returns an instance of HGET command handler.
+    pub fn handler(map: Arc<Store>, clock: Arc<dyn Clock>) -> HGetHandler {
+        HGetHandler { map, clock }
+    }
+
+    /// Returns HGET as a Command in the form of Value.
+    pub fn command_value(arg: HFieldArg) -> Value {
+        field_command_value("HGET", arg)
+    }
+}
+
+#[derive(Debug)]
+pub struct HGetHandler {
+    map: Arc<Store>,
+    clock: Arc<dyn Clock>,
+}
+
+impl HGetHandler {
+    /// Returns the value of the field, nil if the field or the key doesn't exist.
+    pub fn handle(&self, arg: HFieldArg) -> Result<Value, HandleCommandError> {
+        let key = arg.key.as_bytes().unwrap_or_default();
+        let now = self.clock.now();
+        let value = read_hash(&self.map, now, key, |hash| hash.get_at(&arg.field, now))?;
+        Ok(Value::BulkString(
+            value.flatten().unwrap_or_else(BulkString::null),
+        ))
+    }
+}
+
+pub struct HExists;
+
+impl HExists {
+    /// Returns an instance of HEXISTS command handler.
+    pub fn handler(map: Arc<Store>, clock: Arc<dyn Clock>) -> HExistsHandler {
+        HExistsHandler { map, clock }
+    }
+
+    /// Returns HEXISTS as a Command in the form of Value.
+    pub fn command_value(arg: HFieldArg) -> Value {
+        field_command_value("HEXISTS", arg)
+    }
+}
+
+#[derive(Debug)]
+pub struct HExistsHandler {
+    map: Arc<Store>,
+    clock: Arc<dyn Clock>,
+}
+
+impl HExistsHandler {
+    /// Returns 1 if the hash held by the key has the field, 0 if not.
+    pub fn handle(&self, arg: HFieldArg) -> Result<Value, HandleCommandError> {
+        let key = arg.key.as_bytes().unwrap_or_default();
+        let now = self.clock.now();
+        let exists = read_hash(&self.map, now, key, |hash| {
+            hash.get_at(&arg.field, now).is_some()
+        })?;
+        Ok(Value::Integer(Integer::new((exists == Some(true)) as i64)))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct HDelArg {
+    pub key: BulkString,
+    pub fields: Vec<BulkString>,
+}
+
+impl CommandArgParser for HDelArg {
+    /// HDEL key field [field ...]
+    fn parse_arg(iter: &mut std::slice::Iter<'_, Value>) -> Result<Self, ParseCommandError> {
+        let mut args = consume_variadic_args_from_iter(iter, 2)?;
+        let key = args.remove(0);
+
+        Ok(Self { key, fields: args })
+    }
+}
+
+pub struct HDel;
+
+impl HDel {
+    /// Returns an instance of HDEL command handler.
+    pub fn handler(map: Arc<Store>, clock: Arc<dyn Clock>) -> HDelHandler {
+        HDelHandler {
+            map,
+            clock,
+            events: None,
+        }
+    }
+
+    /// Returns HDEL as a Command in the form of Value.
+    pub fn command_value(arg: HDelArg) -> Value {
+        let mut v = vec![Value::BulkString("HDEL".into()), Value::BulkString(arg.key)];
+        v.extend(arg.fields.into_iter().map(Value::BulkString));
+        Value::Array(v.into())
+    }
+}
+
+pub struct HDelHandler {
+    map: Arc<Store>,
+    clock: Arc<dyn Clock>,
+    events: Option<Arc<KeyEvents>>,
+}
+
+impl HDelHandler {
+    /// Tells the listeners about keys with fields removed, and hashes removed once empty.
+    pub fn with_events(mut self, events: Arc<KeyEvents>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Removes the fields from the hash, returning how many of them existed. The key is
+    /// removed once the hash is empty.
+    pub fn handle(&self, arg: HDelArg) -> Result<Value, HandleCommandError> {
+        let key = arg.key.as_bytes().unwrap_or_default();
+        let now = self.clock.now();
+
+        let mut shard = self.map.write(key);
+        let Some(hash) = existing_hash_mut(&mut shard, key, now)? else {
+            return Ok(Value::Integer(Integer::new(0)));
+        };
+        let removed = arg
+            .fields
+            .iter()
+            .filter(|field| hash.remove(field).is_some())
+            .count();
+        let emptied = hash.is_empty();
+        if emptied {
+            shard.remove(key);
+        }
+        drop(shard);
+
+        if let Some(events) = self.events.as_ref().filter(|_| removed > 0) {
+            events.notify(key, KeyEvent::HDel);
+            if emptied {
+                events.notify(key, KeyEvent::Del);
+            }
+        }
+        Ok(Value::Integer(Integer::new(removed as i64)))
+    }
+}
+
+/// Arguments of HGETALL and HLEN, which only take the key.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct HKeyArg {
+    pub key: BulkString,
+}
+
+impl CommandArgParser for HKeyArg {
+    /// HGETALL key
+    fn parse_arg(iter: &mut std::slice::Iter<'_, Value>) -> Result<Self, ParseCommandError> {
+        let args = consume_args_from_iter(iter, 1, 0)?;
+
+        Ok(Self {
+            key: args[0].clone(),
+        })
+    }
+}
+
+pub struct HGetAll;
+
+impl HGetAll {
+    /// Returns an instance of HGETALL command handler.
+    pub fn handler(map: Arc<Store>, clock: Arc<dyn Clock>) -> HGetAllHandler {
+        HGetAllHandler { map, clock }
+    }
+
+    /// Returns HGETALL as a Command in the form of Value.
+    pub fn command_value(arg: HKeyArg) -> Value {
+        let v = vec![
+            Value::BulkString("HGETALL".into()),
+            Value::BulkString(arg.key),
+        ];
+        Value::Array(v.into())
+    }
+}
+
+#[derive(Debug)]
+pub struct HGetAllHandler {
+    map: Arc<Store>,
+    clock: Arc<dyn Clock>,
+}
+
+impl HGetAllHandler {
+    /// Returns every field of the hash with its value as a map, which RESP2 clients get as
+    /// an array of each field followed by its value. A missing key is an empty hash.
+    ///
+    /// Fields and values are only turned into replies as they are written, so a large hash
+    /// never has to be in memory twice.
+    pub fn handle(&self, arg: HKeyArg) -> Result<StreamedArray, HandleCommandError> {
+        let key = arg.key.as_bytes().unwrap_or_default();
+        let now = self.clock.now();
+        let pairs = read_hash(&self.map, now, key, |hash| {
+            hash.iter_at(now).collect::<Vec<_>>()
+        })?
+        .unwrap_or_default();
+
+        let len = pairs.len();
+        let values = pairs
+            .into_iter()
+            .flat_map(|(field, value)| [field, value])
+            .map(Value::BulkString);
+        Ok(StreamedArray::map(len, values))
+    }
+}
+
+pub struct HLen;
+
+impl HLen {
+    /// Returns an instance of HLEN command handler.
+    pub fn handler(map: Arc<Store>, clock: Arc<dyn Clock>) -> HLenHandler {
+        HLenHandler { map, clock }
+    }
+
+    /// Returns HLEN as a Command in the form of Value.
+    pub fn command_value(arg: HKeyArg) -> Value {
+        let v = vec![Value::BulkString("HLEN".into()), Value::BulkString(arg.key)];
+        Value::Array(v.into())
+    }
+}
+
+#[derive(Debug)]
+pub struct HLenHandler {
+    map: Arc<Store>,
+    clock: Arc<dyn Clock>,
+}
+
+impl HLenHandler {
+    /// Returns the number of fields of the hash held by the key, 0 if it doesn't exist.
+    pub fn handle(&self, arg: HKeyArg) -> Result<Value, HandleCommandError> {
+        let key = arg.key.as_bytes().unwrap_or_default();
+        let now = self.clock.now();
+        let len = read_hash(&self.map, now, key, |hash| hash.len_at(now))?;
+        Ok(Value::Integer(Integer::new(len.unwrap_or_default() as i64)))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct HIncrByArg {
+    pub key: BulkString,
+    pub field: BulkString,
+    pub delta: i64,
+}
+
+impl CommandArgParser for HIncrByArg {
+    /// HINCRBY key field increment
+    fn parse_arg(iter: &mut std::slice::Iter<'_, Value>) -> Result<Self, ParseCommandError> {
+        let args = consume_args_from_iter(iter, 3, 0)?;
+
+        Ok(Self {
+            key: args[0].clone(),
+            field: args[1].clone(),
+            delta: bulk_string_to_int64(&args[2])?,
+        })
+    }
+}
+
+pub struct HIncrBy;
+
+impl HIncrBy {
+    /// Returns an instance of HINCRBY command handler.
+    pub fn handler(map: Arc<Store>, clock: Arc<dyn Clock>) -> HIncrByHandler {
+        HIncrByHandler {
+            map,
+            clock,
+            events: None,
+            limits: ListpackLimits::DEFAULT,
+        }
+    }
+
+    /// Returns HINCRBY as a Command in the form of Value.
+    pub fn command_value(arg: HIncrByArg) -> Value {
+        let v = vec![
+            Value::BulkString("HINCRBY".into()),
+            Value::BulkString(arg.key),
+            Value::BulkString(arg.field),
+            Value::BulkString(arg.delta.to_string().into()),
+        ];
+        Value::Array(v.into())
+    }
+}
+
+pub struct HIncrByHandler {
+    map: Arc<Store>,
+    clock: Arc<dyn Clock>,
+    events: Option<Arc<KeyEvents>>,
+    limits: ListpackLimits,
+}
+
+impl HIncrByHandler {
+    /// Tells the listeners about keys incremented.
+    pub fn with_events(mut self, events: Arc<KeyEvents>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Limits up to which the hash stays packed, see `hash-max-listpack-entries` and
+    /// `hash-max-listpack-value`.
+    pub fn with_listpack_limits(mut self, limits: ListpackLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Adds the delta to the integer held by the field and returns the result. A missing
+    /// field counts as 0.
+    pub fn handle(&self, arg: HIncrByArg) -> Result<Value, HandleCommandError> {
+        let key = arg.key.as_bytes().unwrap_or_default();
+        let now = self.clock.now();
+
+        let mut shard = self.map.write(key);
+        let hash = hash_mut(&mut shard, key, now)?;
+        let current = match hash.get(&arg.field) {
+            Some(value) => parse_int(value.as_bytes().unwrap_or_default())
+                .ok_or(HandleCommandError::HashNotInteger)?,
+            None => 0,
+        };
+        let n = current
+            .checked_add(arg.delta)
+            .ok_or(HandleCommandError::Overflow)?;
+        hash.insert(arg.field, n.to_string().into(), self.limits);
+        drop(shard);
+
+        if let Some(events) = &self.events {
+            events.notify(key, KeyEvent::HIncrBy);
+        }
+        Ok(Value::Integer(Integer::new(n)))
+    }
+}
+
+/// Parses `FIELDS numfields field [field ...]`, which must be the rest of the arguments.
+fn parse_fields(args: &[BulkString]) -> Result<Vec<BulkString>, ParseCommandError> {
+    let Some((keyword, rest)) = args.split_first() else {
+        return Err(ParseCommandError::MissingFields);
+    };
+    if !keyword
+        .as_str()
+        .is_some_and(|keyword| keyword.eq_ignore_ascii_case("fields"))
+    {
+        return Err(ParseCommandError::MissingFields);
+    }
+    let (numfields, fields) = rest
+        .split_first()
+        .ok_or(ParseCommandError::NumFieldsNotPositive)?;
+    let numfields = bulk_string_to_int64(numfields)
+        .ok()
+        .filter(|&n| n > 0)
+        .ok_or(ParseCommandError::NumFieldsNotPositive)?;
+    if numfields as usize != fields.len() {
+        return Err(ParseCommandError::NumFieldsMismatch);
+    }
+    Ok(fields.to_vec())
+}
+
+fn push_fields(v: &mut Vec<Value>, fields: Vec<BulkString>) {
+    v.push(Value::BulkString("FIELDS".into()));
+    v.push(Value::BulkString(fields.len().to_string().into()));
+    v.extend(fields.into_iter().map(Value::BulkString));
+}
+
+fn values_reply(values: Vec<Option<BulkString>>) -> Value {
+    let values = values
+        .into_iter()
+        .map(|value| Value::BulkString(value.unwrap_or_else(BulkString::null)))
+        .collect();
+    Value::Array(Array::new(values))
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct HGetDelArg {
+    pub key: BulkString,
+    pub fields: Vec<BulkString>,
+}
+
+impl CommandArgParser for HGetDelArg {
+    /// HGETDEL key FIELDS numfields field [field ...]
+    fn parse_arg(iter: &mut std::slice::Iter<'_, Value>) -> Result<Self, ParseCommandError> {
+        let args = consume_variadic_args_from_iter(iter, 4)?;
+
+        Ok(Self {
+            key: args[0].clone(),
+            fields: parse_fields(&args[1..])?,
+        })
+    }
+}
+
+pub struct HGetDel;
+
+impl HGetDel {
+    /// Returns an instance of HGETDEL command handler.
+    pub fn handler(map: Arc<Store>, clock: Arc<dyn Clock>) -> HGetDelHandler {
+        HGetDelHandler {
+            map,
+            clock,
+            events: None,
+        }
+    }
+
+    /// Returns HGETDEL as a Command in the form of Value.
+    pub fn command_value(arg: HGetDelArg) -> Value {
+        let mut v = vec![
+            Value::BulkString("HGETDEL".into()),
+            Value::BulkString(arg.key),
+        ];
+        push_fields(&mut v, arg.fields);
+        Value::Array(v.into())
+    }
+}
+
+pub struct HGetDelHandler {
+    map: Arc<Store>,
+    clock: Arc<dyn Clock>,
+    events: Option<Arc<KeyEvents>>,
+}
+
+impl HGetDelHandler {
+    /// Tells the listeners about keys with fields removed, and hashes removed once empty.
+    pub fn with_events(mut self, events: Arc<KeyEvents>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Removes the fields from the hash, replying with their values in the order given,
+    /// nil for fields that don't exist. The key is removed once the hash is empty.
+    pub fn handle(&self, arg: HGetDelArg) -> Result<Value, HandleCommandError> {
+        self.handle_counting(arg).map(|(resp, _)| resp)
+    }
+
+    /// Handles HGETDEL like [`Self::handle`], also returning how many fields were removed.
+    pub fn handle_counting(&self, arg: HGetDelArg) -> Result<(Value, u64), HandleCommandError> {
+        let key = arg.key.as_bytes().unwrap_or_default();
+        let now = self.clock.now();
+
+        let mut shard = self.map.write(key);
+        let Some(hash) = existing_hash_mut(&mut shard, key, now)? else {
+            return Ok((values_reply(vec![None; arg.fields.len()]), 0));
+        };
+        let values: Vec<_> = arg.fields.iter().map(|field| hash.remove(field)).collect();
+        let emptied = hash.is_empty();
+        if emptied {
+            shard.remove(key);
+        }
+        drop(shard);
+
+        let removed = values.iter().flatten().count() as u64;
+        if let Some(events) = self.events.as_ref().filter(|_| removed > 0) {
+            events.notify(key, KeyEvent::HDel);
+            if emptied {
+                events.notify(key, KeyEvent::Del);
+            }
+        }
+        Ok((values_reply(values), removed))
+    }
+}
+
+/// What HGETEX does to the deadlines of the fields it gets.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum HGetExExpiry {
+    /// EX, PX, EXAT or PXAT, named after the EXPIRE variant taking the same time.
+    Time(ExpireTime),
+    /// PERSIST: clear the deadlines.
+    Persist,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct HGetExArg {
+    pub key: BulkString,
+    pub expiry: Option<HGetExExpiry>,
+    pub fields: Vec<BulkString>,
+}
+
+impl CommandArgParser for HGetExArg {
+    /// HGETEX key [EX seconds | PX milliseconds | EXAT unix-time-seconds |
+    ///   PXAT unix-time-milliseconds | PERSIST] FIELDS numfields field [field ...]
+    fn parse_arg(iter: &mut std::slice::Iter<'_, Value>) -> Result<Self, ParseCommandError> {
+        let args = consume_variadic_args_from_iter(iter, 4)?;
+        let key = args[0].clone();
+
+        let option = args[1].as_str().unwrap_or_default().to_lowercase();
+        let time: Option<fn(i64) -> ExpireTime> = match option.as_str() {
+            "ex" => Some(ExpireTime::Seconds),
+            "px" => Some(ExpireTime::Millis),
+            "exat" => Some(ExpireTime::UnixSeconds),
+            "pxat" => Some(ExpireTime::UnixMillis),
+            _ => None,
+        };
+        let (expiry, rest) = match (option.as_str(), time) {
+            ("persist", _) => (Some(HGetExExpiry::Persist), &args[2..]),
+            (_, Some(time)) => {
+                let value = bulk_string_to_int64(&args[2])?;
+                if value < 0 {
+                    return Err(ParseCommandError::InvalidExpireTime("hgetex"));
+                }
+                (Some(HGetExExpiry::Time(time(value))), &args[3..])
+            }
+            _ => (None, &args[1..]),
+        };
+
+        Ok(Self {
+            key,
+            expiry,
+            fields: parse_fields(rest)?,
+        })
+    }
+}
+
+pub struct HGetEx;
+
+impl HGetEx {
+    /// Returns an instance of HGETEX command handler.
+    pub fn handler(map: Arc<Store>, clock: Arc<dyn Clock>) -> HGetExHandler {
+        HGetExHandler {
+            map,
+            clock,
+            events: None,
+        }
+    }
+
+    /// Returns HGETEX as a Command in the form of Value.
+    pub fn command_value(arg: HGetExArg) -> Value {
+        let mut v = vec![
+            Value::BulkString("HGETEX".into()),
+            Value::BulkString(arg.key),
+        ];
+        match arg.expiry {
+            Some(HGetExExpiry::Time(time)) => {
+                let (option, value) = match time {
+                    ExpireTime::Seconds(value) => ("EX", value),
+                    ExpireTime::Millis(value) => ("PX", value),
+                    ExpireTime::UnixSeconds(value) => ("EXAT", value),
+                    ExpireTime::UnixMillis(value) => ("PXAT", value),
+                };
+                v.push(Value::BulkString(option.into()));
+                v.push(Value::BulkString(value.to_string().into()));
+            }
+            Some(HGetExExpiry::Persist) => v.push(Value::BulkString("PERSIST".into())),
+            None => (),
+        }
+        push_fields(&mut v, arg.fields);
+        Value::Array(v.into())
+    }
+}
+
+pub struct HGetExHandler {
+    map: Arc<Store>,
+    clock: Arc<dyn Clock>,
+    events: Option<Arc<KeyEvents>>,
+}
+
+impl HGetExHandler {
+    /// Tells the listeners about fields given or cleared of deadlines, fields removed by a
+    /// deadline that already passed, and hashes removed once empty.
+    pub fn with_events(mut self, events: Arc<KeyEvents>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Replies with the values of the fields in the order given, nil for fields that don't
+    /// exist, and sets or clears the deadlines of those that do. A deadline that already
+    /// passed removes the field, and the key once the hash is empty.
+    pub fn handle(&self, arg: HGetExArg) -> Result<Value, HandleCommandError> {
+        self.handle_counting(arg).map(|(resp, _)| resp)
+    }
+
+    /// Handles HGETEX like [`Self::handle`], also returning how many fields had their
+    /// deadline changed or were removed.
+    pub fn handle_counting(&self, arg: HGetExArg) -> Result<(Value, u64), HandleCommandError> {
+        let key = arg.key.as_bytes().unwrap_or_default();
+        let now = self.clock.now();
+        let deadline = match arg.expiry {
+            Some(HGetExExpiry::Time(time)) => Some(
+                time.deadline(now)
+                    .ok_or(ParseCommandError::InvalidExpireTime("hgetex"))?,
+            ),
+            _ => None,
+        };
+
+        // Without an option nothing changes, so a read lock is enough.
+        let Some(expiry) = arg.expiry else {
+            let values = read_hash(&self.map, now, key, |hash| {
+                arg.fields
+                    .iter()
+                    .map(|field| hash.get_at(field, now))
+                    .collect()
+            })?;
+            let values = values.unwrap_or_else(|| vec![None; arg.fields.len()]);
+            return Ok((values_reply(values), 0));
+        };
+
+        let mut shard = self.map.write(key);
+        let Some(hash) = existing_hash_mut(&mut shard, key, now)? else {
+            return Ok((values_reply(vec![None; arg.fields.len()]), 0));
+        };
+        let (mut changed, mut removed) = (0, 0);
+        let mut values = Vec::with_capacity(arg.fields.len());
+        for field in &arg.fields {
+            let value = hash.get(field);
+            if value.is_some() {
+                match (expiry, deadline) {
+                    (HGetExExpiry::Persist, _) if hash.deadline(field).is_some() => {
+                        hash.set_deadline(field, None);
+                        changed += 1;
+                    }
+                    (HGetExExpiry::Persist, _) => (),
+                    (HGetExExpiry::Time(_), Some(deadline)) if deadline <= now => {
+                        hash.remove(field);
+                        removed += 1;
+                    }
+                    (HGetExExpiry::Time(_), deadline) => {
+                        hash.set_deadline(field, deadline);
+                        changed += 1;
+                    }
+                }
+            }
+            values.push(value);
+        }
+        let emptied = hash.is_empty();
+        if emptied {
+            shard.remove(key);
+        }
+        drop(shard);
+
+        if let Some(events) = &self.events {
+            if changed > 0 {
+                let event = match expiry {
+                    HGetExExpiry::Persist => KeyEvent::HPersist,
+                    HGetExExpiry::Time(_) => KeyEvent::HExpire,
+                };
+                events.notify(key, event);
+            }
+            if removed > 0 {
+                events.notify(key, KeyEvent::HDel);
+            }
+            if emptied {
+                events.notify(key, KeyEvent::Del);
+            }
+        }
+        Ok((values_reply(values), changed + removed))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::super::test_util::command;
+    use super::super::Command;
+    use super::*;
+
+    #[test]
+    fn parse_hash_commands() {
+        let arg = HSetArg {
+            key: "hash".into(),
+            pairs: vec![("a".into(), "1".into()), ("b".into(), "2".into())],
+        };
+        match Command::try_from(HSet::command_value(arg.clone())) {
+            Ok(Command::HSet(parsed)) => assert_eq!(parsed, arg),
+            other => panic!("Unexpected parse result {other:?}"),
+        }
+
+        let arg = HFieldArg {
+            key: "hash".into(),
+            field: "a".into(),
+        };
+        match Command::try_from(HGet::command_value(arg.clone())) {
+            Ok(Command::HGet(parsed)) => assert_eq!(parsed, arg),
+            other => panic!("Unexpected parse result {other:?}"),
+        }
+        match Command::try_from(HExists::command_value(arg.clone())) {
+            Ok(Command::HExists(parsed)) => assert_eq!(parsed, arg),
+            other => panic!("Unexpected parse result {other:?}"),
+        }
+
+        let arg = HDelArg {
+            key: "hash".into(),
+            fields: vec!["a".into(), "b".into()],
+        };
+        match Command::try_from(HDel::command_value(arg.clone())) {
+            Ok(Command::HDel(parsed)) => assert_eq!(parsed, arg),
+            other => panic!("Unexpected parse result {other:?}"),
+        }
+
+        let arg = HKeyArg { key: "hash".into() };
+        match Command::try_from(HGetAll::command_value(arg.clone())) {
+            Ok(Command::HGetAll(parsed)) => assert_eq!(parsed, arg),
+            other => panic!("Unexpected parse result {other:?}"),
+        }
+        match Command::try_from(HLen::command_value(arg.clone())) {
+            Ok(Command::HLen(parsed)) => assert_eq!(parsed, arg),
+            other => panic!("Unexpected parse result {other:?}"),
+        }
+
+        let arg = HIncrByArg {
+            key: "hash".into(),
+            field: "a".into(),
+            delta: -3,
+        };
+        match Command::try_from(HIncrBy::command_value(arg.clone())) {
+            Ok(Command::HIncrBy(parsed)) => assert_eq!(parsed, arg),
+            other => panic!("Unexpected parse result {other:?}"),
+        }
+
+        let arg = HGetDelArg {
+            key: "hash".into(),
+            fields: vec!["a".into(), "b".into()],
+        };
+        match Command::try_from(HGetDel::command_value(arg.clone())) {
+            Ok(Command::HGetDel(parsed)) => assert_eq!(parsed, arg),
+            other => panic!("Unexpected parse result {other:?}"),
+        }
+
+        for expiry in [
+            None,
+            Some(HGetExExpiry::Persist),
+            Some(HGetExExpiry::Time(ExpireTime::Seconds(10))),
+            Some(HGetExExpiry::Time(ExpireTime::UnixMillis(
+                1_700_000_000_000,
+            ))),
+        ] {
+            let arg = HGetExArg {
+                key: "hash".into(),
+                expiry,
+                fields: vec!["a".into()],
+            };
+            match Command::try_from(HGetEx::command_value(arg.clone())) {
+                Ok(Command::HGetEx(parsed)) => assert_eq!(parsed, arg),
+                other => panic!("Unexpected parse result {other:?}"),
+            }
+        }
+
+        assert!(matches!(
+            Command::try_from(command(["HSET", "hash", "a", "1", "b"])),
+            Err(ParseCommandError::WrongArity("hset"))
+        ));
+        assert!(matches!(
+            Command::try_from(command(["HGETDEL", "hash", "FIELD", "1", "a"])),
+            Err(ParseCommandError::MissingFields)
+        ));
+        assert!(matches!(
+            Command::try_from(command(["HGETDEL", "hash", "FIELDS", "0", "a"])),
+            Err(ParseCommandError::NumFieldsNotPositive)
+        ));
+        assert!(matches!(
+            Command::try_from(command(["HGETEX", "hash", "FIELDS", "2", "a"])),
+            Err(ParseCommandError::NumFieldsMismatch)
+        ));
+        assert!(matches!(
+            Command::try_from(command(["HGETEX", "hash", "EX", "-1", "FIELDS", "1", "a"])),
+            Err(ParseCommandError::InvalidExpireTime("hgetex"))
+        ));
+        assert!(matches!(
+            Command::try_from(command(["HINCRBY", "hash", "a", "x"])),
+            Err(ParseCommandError::NotInteger)
+        ));
+    }
+}
+
+#[cfg(test)]
+mod handler_test {
+    use std::time::Duration;
+
+    use super::super::super::clock::{self, TestClock};
+    use super::super::super::reply::Reply;
+    use super::super::super::resp::Map;
+    use super::*;
+
+    fn hset(map: &Arc<Store>, pairs: &[(&str, &str)]) -> Value {
+        let arg = HSetArg {
+            key: "hash".into(),
+            pairs: pairs
+                .iter()
+                .map(|&(field, value)| (field.into(), value.into()))
+                .collect(),
+        };
+        HSet::handler(map.clone(), clock::system())
+            .handle(arg)
+            .unwrap()
+    }
+
+    fn field(field: &str) -> HFieldArg {
+        HFieldArg {
+            key: "hash".into(),
+            field: field.into(),
+        }
+    }
+
+    #[test]
+    fn handle_hset_and_hget() {
+        let map = Arc::new(Store::default());
+        assert_eq!(
+            hset(&map, &[("a", "1"), ("b", "2"), ("a", "3")]),
+            Value::Integer(Integer::new(2))
+        );
+        assert_eq!(hset(&map, &[("b", "4")]), Value::Integer(Integer::new(0)));
+
+        let hget = HGet::handler(map.clone(), clock::system());
+        assert_eq!(
+            hget.handle(field("a")).unwrap(),
+            Value::BulkString("3".into())
+        );
+        assert_eq!(
+            hget.handle(field("c")).unwrap(),
+            Value::BulkString(BulkString::null())
+        );
+
+        let hexists = HExists::handler(map.clone(), clock::system());
+        assert_eq!(
+            hexists.handle(field("b")).unwrap(),
+            Value::Integer(Integer::new(1))
+        );
+        assert_eq!(
+            hexists.handle(field("c")).unwrap(),
+            Value::Integer(Integer::new(0))
+        );
+
+        let hlen = HLen::handler(map.clone(), clock::system());
+        let len = hlen.handle(HKeyArg { key: "hash".into() }).unwrap();
+        assert_eq!(len, Value::Integer(Integer::new(2)));
+    }
+
+    #[test]
+    fn handle_hgetall_by_protocol() {
+        let map = Arc::new(Store::default());
+        hset(&map, &[("a", "1")]);
+        let handler = HGetAll::handler(map.clone(), clock::system());
+        let hgetall = |arg, resp| {
+            Reply::from(handler.handle(arg).unwrap())
+                .into_protocol(resp)
+                .into_value()
+        };
+        let arg = HKeyArg { key: "hash".into() };
+
+        assert_eq!(
+            hgetall(arg.clone(), 2),
+            Value::Array(Array::new(vec![
+                Value::BulkString("a".into()),
+                Value::BulkString("1".into()),
+            ]))
+        );
+        assert_eq!(
+            hgetall(arg, 3),
+            Value::Map(Map::new(vec![(
+                Value::BulkString("a".into()),
+                Value::BulkString("1".into()),
+            )]))
+        );
+
+        let missing = HKeyArg {
+            key: "missing".into(),
+        };
+        assert_eq!(hgetall(missing, 2), Value::Array(Array::new(vec![])));
+    }
+
+    #[test]
+    fn handle_hdel_removes_empty_hashes() {
+        let map = Arc::new(Store::default());
+        hset(&map, &[("a", "1"), ("b", "2")]);
+        let hdel = HDel::handler(map.clone(), clock::system());
+        let arg = |fields: &[&str]| HDelArg {
+            key: "hash".into(),
+            fields: fields.iter().map(|&field| field.into()).collect(),
+        };
+
+        assert_eq!(
+            hdel.handle(arg(&["a", "a", "c"])).unwrap(),
+            Value::Integer(Integer::new(1))
+        );
+        assert_eq!(
+            hdel.handle(arg(&["b"])).unwrap(),
+            Value::Integer(Integer::new(1))
+        );
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn handle_hincrby() {
+        let map = Arc::new(Store::default());
+        hset(&map, &[("text", "abc")]);
+        let hincrby = HIncrBy::handler(map.clone(), clock::system());
+        let arg = |field: &str, delta| HIncrByArg {
+            key: "hash".into(),
+            field: field.into(),
+            delta,
+        };
+
+        assert_eq!(
+            hincrby.handle(arg("n", 5)).unwrap(),
+            Value::Integer(Integer::new(5))
+        );
+        assert_eq!(
+            hincrby.handle(arg("n", -7)).unwrap(),
+            Value::Integer(Integer::new(-2))
+        );
+        assert!(matches!(
+            hincrby.handle(arg("text", 1)),
+            Err(HandleCommandError::HashNotInteger)
+        ));
+        assert!(matches!(
+            hincrby.handle(arg("n", i64::MIN)),
+            Err(HandleCommandError::Overflow)
+        ));
+    }
+
+    fn values(values: &[Option<&str>]) -> Value {
+        let values = values
+            .iter()
+            .map(|value| Value::BulkString(value.map_or_else(BulkString::null, Into::into)))
+            .collect();
+        Value::Array(Array::new(values))
+    }
+
+    #[test]
+    fn handle_hgetdel_removes_empty_hashes() {
+        let map = Arc::new(Store::default());
+        hset(&map, &[("a", "1"), ("b", "2")]);
+        let hgetdel = HGetDel::handler(map.clone(), clock::system());
+        let arg = |fields: &[&str]| HGetDelArg {
+            key: "hash".into(),
+            fields: fields.iter().map(|&field| field.into()).collect(),
+        };
+
+        assert_eq!(
+            hgetdel.handle_counting(arg(&["a", "c", "a"])).unwrap(),
+            (values(&[Some("1"), None, None]), 1)
+        );
+        assert_eq!(hgetdel.handle(arg(&["b"])).unwrap(), values(&[Some("2")]));
+        assert!(map.is_empty());
+        assert_eq!(hgetdel.handle(arg(&["b"])).unwrap(), values(&[None]));
+    }
+
+    #[test]
+    fn handle_hgetex_expires_fields() {
+        let map = Arc::new(Store::default());
+        let clock = Arc::new(TestClock::default());
+        hset(&map, &[("a", "1"), ("b", "2")]);
+        let hgetex = HGetEx::handler(map.clone(), clock.clone());
+        let arg = |expiry, fields: &[&str]| HGetExArg {
+            key: "hash".into(),
+            expiry,
+            fields: fields.iter().map(|&field| field.into()).collect(),
+        };
+        let px = |millis| Some(HGetExExpiry::Time(ExpireTime::Millis(millis)));
+        let hget = HGet::handler(map.clone(), clock.clone());
+
+        assert_eq!(
+            hgetex.handle_counting(arg(px(100), &["a", "c"])).unwrap(),
+            (values(&[Some("1"), None]), 1)
+        );
+        assert_eq!(
+            hgetex.handle(arg(None, &["a", "b"])).unwrap(),
+            values(&[Some("1"), Some("2")])
+        );
+        clock.advance(Duration::from_millis(200));
+        assert_eq!(
+            hgetex.handle(arg(None, &["a", "b"])).unwrap(),
+            values(&[None, Some("2")])
+        );
+        assert_eq!(
+            hget.handle(field("a")).unwrap(),
+            Value::BulkString(BulkString::null())
+        );
+        let hlen = HLen::handler(map.clone(), clock.clone());
+        let len = hlen.handle(HKeyArg { key: "hash".into() }).unwrap();
+        assert_eq!(len, Value::Integer(Integer::new(1)));
+
+        // PERSIST keeps the field past its deadline.
+        hgetex.handle(arg(px(100), &["b"])).unwrap();
+        assert_eq!(
+            hgetex
+                .handle_counting(arg(Some(HGetExExpiry::Persist), &["b"]))
+                .unwrap(),
+            (values(&[Some("2")]), 1)
+        );
+        clock.advance(Duration::from_millis(200));
+        assert_eq!(
+            hget.handle(field("b")).unwrap(),
+            Value::BulkString("2".into())
+        );
+
+        // A deadline that already passed removes the field, and the key with it.
+        assert_eq!(
+            hgetex.handle(arg(px(0), &["b"])).unwrap(),
+            values(&[Some("2")])
+        );
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn hash_with_every_field_expired_is_gone() {
+        let map = Arc::new(Store::default());
+        let clock = Arc::new(TestClock::default());
+        hset(&map, &[("a", "1")]);
+        let arg = HGetExArg {
+            key: "hash".into(),
+            expiry: Some(HGetExExpiry::Time(ExpireTime::Millis(100))),
+            fields: vec!["a".into()],
+        };
+        HGetEx::handler(map.clone(), clock.clone())
+            .handle(arg)
+            .unwrap();
+        clock.advance(Duration::from_millis(200));
+
+        assert!(map
+            .read(b"hash")
+            .get(b"hash".as_slice())
+            .is_some_and(|data| data.expired_at(clock.now())));
+        // Writing to it starts a new hash.
+        let hset = HSet::handler(map.clone(), clock.clone());
+        let arg = HSetArg {
+            key: "hash".into(),
+            pairs: vec![("b".into(), "2".into())],
+        };
+        assert_eq!(hset.handle(arg).unwrap(), Value::Integer(Integer::new(1)));
+        let hlen = HLen::handler(map.clone(), clock.clone());
+        let len = hlen.handle(HKeyArg { key: "hash".into() }).unwrap();
+        assert_eq!(len, Value::Integer(Integer::new(1)));
+    }
+
+    #[test]
+    fn handle_wrong_type() {
+        let map = Arc::new(Store::default());
+        map.write(b"hash")
+            .insert("hash".into(), StoredData::new("value".into(), None));
+
+        assert!(matches!(
+            HSet::handler(map.clone(), clock::system()).handle(HSetArg {
+                key: "hash".into(),
+                pairs: vec![("a".into(), "1".into())],
+            }),
+            Err(HandleCommandError::WrongType)
+        ));
+        assert!(matches!(
+            HGet::handler(map.clone(), clock::system()).handle(field("a")),
+            Err(HandleCommandError::WrongType)
+        ));
+        assert!(matches!(
+            HGetAll::handler(map, clock::system()).handle(HKeyArg { key: "hash".into() }),
+            Err(HandleCommandError::WrongType)
+        ));
+    }
+}
@@ -0,0 +1,304 @@
+use thiserror::Error;
+
+use super::super::acl::AclError;
+use super::super::clients::ClientState;
+use super::super::resp::{Array, BulkString, Integer, Map, Value};
+use super::{
+    bulk_string_to_int64, bulk_string_to_string, value_to_bulk_string, AuthArg, AuthHandler,
+    Client, ClientCommandError, CommandArgParser, ParseCommandError,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum HelloError {
+    #[error("unsupported protocol version")]
+    NoProto,
+
+    #[error(transparent)]
+    Acl(#[from] AclError),
+
+    #[error(transparent)]
+    Client(#[from] ClientCommandError),
+}
+
+impl HelloError {
+    /// Returns the error code prefixed to the RESP error reply.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::NoProto => "NOPROTO",
+            Self::Acl(e) => e.code(),
+            Self::Client(_) => "ERR",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct HelloArg {
+    /// Protocol version to switch to, the current one is kept if `None`.
+    pub protover: Option<i64>,
+    pub auth: Option<AuthArg>,
+    pub setname: Option<BulkString>,
+}
+
+impl CommandArgParser for HelloArg {
+    /// HELLO [protover [AUTH username password] [SETNAME clientname]]
+    fn parse_arg(iter: &mut std::slice::Iter<'_, Value>) -> Result<Self, ParseCommandError> {
+        let mut arg = Self::default();
+        let Some(protover) = iter.next() else {
+            return Ok(arg);
+        };
+        arg.protover = Some(bulk_string_to_int64(&value_to_bulk_string(protover)?)?);
+
+        while let Some(option) = iter.next() {
+            let option = value_to_bulk_string(option)?;
+            let mut next = || {
+                iter.next()
+                    .ok_or(ParseCommandError::WrongNumArgs)
+                    .and_then(value_to_bulk_string)
+            };
+            match bulk_string_to_string(&option)?.to_lowercase().as_str() {
+                "auth" => {
+                    let username = bulk_string_to_string(&next()?)?;
+                    let password = bulk_string_to_string(&next()?)?;
+                    arg.auth = Some(AuthArg {
+                        username: Some(username),
+                        password,
+                    });
+                }
+                "setname" => arg.setname = Some(next()?),
+                _ => {
+                    return Err(ParseCommandError::InvalidArgument(Value::BulkString(
+                        option,
+                    )))
+                }
+            }
+        }
+
+        Ok(arg)
+    }
+}
+
+pub struct Hello;
+
+impl Hello {
+    /// Returns an instance of HELLO command handler.
+    pub fn handler(auth: AuthHandler) -> HelloHandler {
+        HelloHandler {
+            auth,
+            role: "master",
+            mode: "standalone",
+        }
+    }
+
+    /// Returns HELLO as a Command in the form of Value.
+    pub fn command_value(arg: HelloArg) -> Value {
+        let mut v = vec![Value::BulkString("HELLO".into())];
+        if let Some(protover) = arg.protover {
+            v.push(Value::BulkString(protover.to_string().into()));
+        }
+        if let Some(auth) = arg.auth {
+            v.push(Value::BulkString("AUTH".into()));
+            v.push(Value::BulkString(auth.username.unwrap_or_default().into()));
+            v.push(Value::BulkString(auth.password.into()));
+        }
+        if let Some(name) = arg.setname {
+            v.push(Value::BulkString("SETNAME".into()));
+            v.push(Value::BulkString(name));
+        }
+
+        Value::Array(v.into())
+    }
+}
+
+#[derive(Debug)]
+pub struct HelloHandler {
+    auth: AuthHandler,
+    role: &'static str,
+    mode: &'static str,
+}
+
+impl HelloHandler {
+    /// Sets the role reported to the client, `master` by default.
+    pub fn with_replica(mut self, is_replica: bool) -> Self {
+        self.role = if is_replica { "replica" } else { "master" };
+        self
+    }
+
+    /// Sets the mode reported to the client, `standalone` by default.
+    pub fn with_cluster(mut self, cluster: bool) -> Self {
+        self.mode = if cluster { "cluster" } else { "standalone" };
+        self
+    }
+
+    /// Switches the connection to the protocol version after authenticating and naming it
+    /// if asked to, then replies with information about the server as a map, which RESP2
+    /// connections get as a flat array of fields and values.
+    pub fn handle(&self, arg: HelloArg, client: &mut ClientState) -> Result<Value, HelloError> {
+        let resp = match arg.protover {
+            None => client.resp,
+            Some(protover @ (2 | 3)) => protover as u8,
+            Some(_) => return Err(HelloError::NoProto),
+        };
+        let name = arg
+            .setname
+            .as_ref()
+            .map(Client::validate_name)
+            .transpose()?;
+        if let Some(auth) = arg.auth {
+            self.auth.handle(auth, client)?;
+        }
+        if let Some(name) = name {
+            client.set_name(name);
+        }
+        client.resp = resp;
+
+        let fields = vec![
+            ("server", Value::BulkString("redis".into())),
+            (
+                "version",
+                Value::BulkString(env!("CARGO_PKG_VERSION").into()),
+            ),
+            ("proto", Value::Integer(Integer::new(resp as i64))),
+            ("id", Value::Integer(Integer::new(client.id() as i64))),
+            ("mode", Value::BulkString(self.mode.into())),
+            ("role", Value::BulkString(self.role.into())),
+            ("modules", Value::Array(Array::new(vec![]))),
+        ];
+        let fields = fields
+            .into_iter()
+            .map(|(field, value)| (Value::BulkString(BulkString::from(field)), value));
+        Ok(Value::Map(Map::new(fields.collect())))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::super::test_util;
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let cmd = test_util::command(["3", "auth", "alice", "secret", "SETNAME", "conn"]);
+        let mut iter = cmd.array().unwrap().values().unwrap().iter();
+
+        assert_eq!(
+            HelloArg::parse_arg(&mut iter).expect("Parse hello unexpected error"),
+            HelloArg {
+                protover: Some(3),
+                auth: Some(AuthArg {
+                    username: Some("alice".into()),
+                    password: "secret".into(),
+                }),
+                setname: Some("conn".into()),
+            }
+        );
+
+        let cmd = test_util::command(["3", "AUTH", "alice"]);
+        let mut iter = cmd.array().unwrap().values().unwrap().iter();
+        let err = HelloArg::parse_arg(&mut iter).expect_err("Parse hello no error");
+        assert!(matches!(err, ParseCommandError::WrongNumArgs));
+    }
+}
+
+#[cfg(test)]
+mod handler_test {
+    use std::sync::Arc;
+
+    use parking_lot::RwLock;
+
+    use super::super::super::acl::AccessControl;
+    use super::super::super::clock;
+    use super::super::super::stats::CommandStats;
+    use super::super::super::throttle::AuthThrottle;
+    use super::super::Auth;
+    use super::*;
+
+    fn hello_handler() -> HelloHandler {
+        let mut acl = AccessControl::new();
+        acl.set_user("alice", &["on".into(), ">secret".into()])
+            .expect("Set user unexpected error");
+        Hello::handler(Auth::handler(
+            Arc::new(RwLock::new(acl)),
+            Arc::new(AuthThrottle::default()),
+            clock::system(),
+            Arc::new(CommandStats::default()),
+        ))
+    }
+
+    #[test]
+    fn handle_hello() {
+        let handler = hello_handler();
+        let mut client = ClientState::new(7, None);
+
+        let err = handler
+            .handle(
+                HelloArg {
+                    protover: Some(4),
+                    ..Default::default()
+                },
+                &mut client,
+            )
+            .expect_err("Handle hello no error");
+        assert_eq!(err, HelloError::NoProto);
+
+        let resp = handler
+            .handle(HelloArg::default(), &mut client)
+            .expect("Handle hello unexpected error");
+        assert_eq!(resp.map().unwrap().pairs().len(), 7);
+        assert_eq!(
+            resp.into_protocol(client.resp)
+                .array()
+                .unwrap()
+                .values()
+                .unwrap()
+                .len(),
+            14
+        );
+        assert_eq!(client.resp, 2);
+
+        let resp = handler
+            .handle(
+                HelloArg {
+                    protover: Some(3),
+                    auth: Some(AuthArg {
+                        username: Some("alice".into()),
+                        password: "secret".into(),
+                    }),
+                    setname: Some("conn".into()),
+                },
+                &mut client,
+            )
+            .expect("Handle hello unexpected error");
+        let Value::Map(map) = resp else {
+            panic!("HELLO 3 replied {resp:?}");
+        };
+        assert!(map.pairs().contains(&(
+            Value::BulkString("proto".into()),
+            Value::Integer(Integer::new(3))
+        )));
+        assert_eq!(client.resp, 3);
+        assert_eq!(client.user(), Some("alice"));
+        assert_eq!(client.name(), Some("conn"));
+    }
+
+    #[test]
+    fn failed_auth_keeps_protocol() {
+        let handler = hello_handler();
+        let mut client = ClientState::new(7, None);
+
+        let err = handler
+            .handle(
+                HelloArg {
+                    protover: Some(3),
+                    auth: Some(AuthArg {
+                        username: Some("alice".into()),
+                        password: "wrong".into(),
+                    }),
+                    setname: None,
+                },
+                &mut client,
+            )
+            .expect_err("Handle hello no error");
+        assert_eq!(err, HelloError::Acl(AclError::WrongPass));
+        assert_eq!(client.resp, 2);
+    }
+}
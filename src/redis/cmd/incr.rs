@@ -0,0 +1,221 @@
+use std::sync::Arc;
+
+use super::super::clock::Clock;
+use super::super::handler::{parse_int, HandleCommandError, StoredData, StoredValue};
+use super::super::key::Key;
+use super::super::resp::{BulkString, Integer, Value};
+use super::super::store::Store;
+use super::{bulk_string_to_int64, consume_args_from_iter, CommandArgParser, ParseCommandError};
+
+/// Arguments of INCR, DECR, INCRBY and DECRBY, which all add a delta to the key.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct IncrArg {
+    pub key: BulkString,
+    pub delta: i64,
+}
+
+impl CommandArgParser for IncrArg {
+    /// INCR key
+    fn parse_arg(iter: &mut std::slice::Iter<'_, Value>) -> Result<Self, ParseCommandError> {
+        let args = consume_args_from_iter(iter, 1, 0)?;
+        Ok(Self {
+            key: args[0].clone(),
+            delta: 1,
+        })
+    }
+}
+
+impl IncrArg {
+    /// DECR key
+    pub fn parse_decr(iter: &mut std::slice::Iter<'_, Value>) -> Result<Self, ParseCommandError> {
+        let args = consume_args_from_iter(iter, 1, 0)?;
+        Ok(Self {
+            key: args[0].clone(),
+            delta: -1,
+        })
+    }
+
+    /// INCRBY key increment
+    pub fn parse_incrby(iter: &mut std::slice::Iter<'_, Value>) -> Result<Self, ParseCommandError> {
+        let args = consume_args_from_iter(iter, 2, 0)?;
+        Ok(Self {
+            key: args[0].clone(),
+            delta: bulk_string_to_int64(&args[1])?,
+        })
+    }
+
+    /// DECRBY key decrement
+    pub fn parse_decrby(iter: &mut std::slice::Iter<'_, Value>) -> Result<Self, ParseCommandError> {
+        let args = consume_args_from_iter(iter, 2, 0)?;
+        // The decrement is negated, which only overflows for the smallest i64.
+        let delta = bulk_string_to_int64(&args[1])?
+            .checked_neg()
+            .ok_or(ParseCommandError::Overflow)?;
+        Ok(Self {
+            key: args[0].clone(),
+            delta,
+        })
+    }
+}
+
+pub struct Incr;
+
+impl Incr {
+    /// Returns an instance of the handler shared by INCR, DECR, INCRBY and DECRBY.
+    pub fn handler(map: Arc<Store>, clock: Arc<dyn Clock>) -> IncrHandler {
+        IncrHandler { map, clock }
+    }
+
+    /// Returns INCRBY as a Command in the form of Value, which every one of them can be
+    /// written as.
+    pub fn command_value(arg: IncrArg) -> Value {
+        let v = vec![
+            Value::BulkString("INCRBY".into()),
+            Value::BulkString(arg.key),
+            Value::BulkString(arg.delta.to_string().into()),
+        ];
+        Value::Array(v.into())
+    }
+}
+
+#[derive(Debug)]
+pub struct IncrHandler {
+    map: Arc<Store>,
+    clock: Arc<dyn Clock>,
+}
+
+impl IncrHandler {
+    /// Adds the delta to the integer held by the key and returns the result. A missing or
+    /// expired key counts as 0, while an existing key keeps its time to live.
+    ///
+    /// The shard stays write locked from reading the value to writing the result, so
+    /// concurrent increments don't overwrite each other.
+    pub fn handle(&self, arg: IncrArg) -> Result<Value, HandleCommandError> {
+        let key = arg.key.as_bytes().unwrap_or_default();
+        let now = self.clock.now();
+        let mut map = self.map.write(key);
+        let (current, mut data) = match map.get(key).filter(|data| !data.expired_at(now)) {
+            Some(data) => {
+                let value = data
+                    .value
+                    .as_string()
+                    .ok_or(HandleCommandError::WrongType)?;
+                let current = parse_int(value.as_bytes().unwrap_or_default())
+                    .ok_or(HandleCommandError::NotInteger)?;
+                (current, data.clone())
+            }
+            None => (0, StoredData::new(StoredValue::String("0".into()), None)),
+        };
+
+        let n = current
+            .checked_add(arg.delta)
+            .ok_or(HandleCommandError::Overflow)?;
+        data.value = StoredValue::String(n.to_string().into());
+        data.raw = false;
+        map.insert(Key::new(key), data);
+
+        Ok(Value::Integer(Integer::new(n)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::super::test_util::command;
+    use super::super::Command;
+    use super::*;
+
+    #[test]
+    fn parse_incr_family() {
+        let parsed = |args: &[&str]| match Command::try_from(command(args.iter().copied())) {
+            Ok(
+                Command::Incr(arg)
+                | Command::Decr(arg)
+                | Command::IncrBy(arg)
+                | Command::DecrBy(arg),
+            ) => arg.delta,
+            other => panic!("Unexpected parse result {other:?}"),
+        };
+        assert_eq!(parsed(&["INCR", "key"]), 1);
+        assert_eq!(parsed(&["DECR", "key"]), -1);
+        assert_eq!(parsed(&["INCRBY", "key", "-5"]), -5);
+        assert_eq!(parsed(&["DECRBY", "key", "5"]), -5);
+
+        let arg = IncrArg {
+            key: "key".into(),
+            delta: 7,
+        };
+        match Command::try_from(Incr::command_value(arg.clone())) {
+            Ok(Command::IncrBy(parsed)) => assert_eq!(parsed, arg),
+            other => panic!("Unexpected parse result {other:?}"),
+        }
+
+        assert!(matches!(
+            Command::try_from(command(["INCRBY", "key", "1.5"])),
+            Err(ParseCommandError::NotInteger)
+        ));
+        let min = i64::MIN.to_string();
+        assert!(matches!(
+            Command::try_from(command(["DECRBY", "key", &min])),
+            Err(ParseCommandError::Overflow)
+        ));
+    }
+}
+
+#[cfg(test)]
+mod handler_test {
+    use std::time::Duration;
+
+    use super::super::super::clock::TestClock;
+    use super::*;
+
+    #[test]
+    fn handle_incr() {
+        let clock = Arc::new(TestClock::default());
+        let deadline = clock.now() + Duration::from_secs(10);
+        let map = Arc::new(Store::from_iter([
+            (Key::from("n"), StoredData::new("10".into(), Some(deadline))),
+            (Key::from("text"), StoredData::new("ten".into(), None)),
+            (Key::from("padded"), StoredData::new("010".into(), None)),
+            (
+                Key::from("list"),
+                StoredData::new(StoredValue::List(["1".into()].into()), None),
+            ),
+        ]));
+        let handler = Incr::handler(map.clone(), clock.clone());
+        let data = |key: &str| map.snapshot().get(key.as_bytes()).cloned().unwrap();
+        let incr = |key: &str, delta| {
+            handler.handle(IncrArg {
+                key: key.into(),
+                delta,
+            })
+        };
+
+        assert_eq!(incr("n", 5).unwrap(), Value::Integer(Integer::new(15)));
+        assert_eq!(data("n").deadline, Some(deadline));
+        assert_eq!(
+            incr("missing", -1).unwrap(),
+            Value::Integer(Integer::new(-1))
+        );
+        assert_eq!(data("missing").value, StoredValue::String("-1".into()));
+
+        // An expired key starts over from 0, without its deadline.
+        clock.advance(Duration::from_secs(20));
+        assert_eq!(incr("n", 1).unwrap(), Value::Integer(Integer::new(1)));
+        assert_eq!(data("n").deadline, None);
+
+        assert!(matches!(
+            incr("text", 1),
+            Err(HandleCommandError::NotInteger)
+        ));
+        assert!(matches!(
+            incr("padded", 1),
+            Err(HandleCommandError::NotInteger)
+        ));
+        assert!(matches!(
+            incr("list", 1),
+            Err(HandleCommandError::WrongType)
+        ));
+        incr("n", i64::MAX - 1).unwrap();
+        assert!(matches!(incr("n", 1), Err(HandleCommandError::Overflow)));
+    }
+}
@@ -1,21 +1,41 @@
-use std::fmt::Display;
+use std::sync::Arc;
 
-use super::super::client::ClientError;
-use super::super::resp::{Array, BulkString, SimpleString, Value};
-use super::super::session::{Request, Responder, Response};
+use super::super::allocator;
+use super::super::config::ServerConfig;
+use super::super::lazyfree::LazyFree;
+use super::super::memory::MemoryTracker;
+use super::super::persistence::PersistenceState;
+use super::super::replication::FailoverState;
+use super::super::resp::{BulkString, Value};
+use super::super::stats::CommandStats;
 use super::{consume_args_from_iter, CommandArgParser, ParseCommandError};
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum InfoSection {
     Default,
+    /// Every section, asked for with `all` or `everything`.
+    All,
     Replication,
+    Persistence,
+    Memory,
+    Stats,
+    Commandstats,
+    Latencystats,
+    Cluster,
 }
 
 impl InfoSection {
     fn to_bulk_strings(&self) -> Vec<BulkString> {
         match self {
             Self::Default => vec![BulkString::from("default")],
+            Self::All => vec![BulkString::from("all")],
             Self::Replication => vec![BulkString::from("replication")],
+            Self::Persistence => vec![BulkString::from("persistence")],
+            Self::Memory => vec![BulkString::from("memory")],
+            Self::Stats => vec![BulkString::from("stats")],
+            Self::Commandstats => vec![BulkString::from("commandstats")],
+            Self::Latencystats => vec![BulkString::from("latencystats")],
+            Self::Cluster => vec![BulkString::from("cluster")],
         }
     }
 }
@@ -27,7 +47,7 @@ pub struct InfoArg {
 impl CommandArgParser for InfoArg {
     fn parse_arg(iter: &mut std::slice::Iter<'_, Value>) -> Result<Self, ParseCommandError> {
         let args = consume_args_from_iter(iter, 0, 1)?;
-        let section = Self::parse_info_section(args.get(0))?;
+        let section = Self::parse_info_section(args.first())?;
 
         Ok(Self { section })
     }
@@ -47,7 +67,15 @@ impl InfoArg {
 
         match section_str.to_lowercase().as_str() {
             "replication" => Ok(InfoSection::Replication),
+            "persistence" => Ok(InfoSection::Persistence),
+            "memory" => Ok(InfoSection::Memory),
+            "stats" => Ok(InfoSection::Stats),
+            "commandstats" => Ok(InfoSection::Commandstats),
+            "latencystats" => Ok(InfoSection::Latencystats),
+            "cluster" => Ok(InfoSection::Cluster),
             "default" => Ok(InfoSection::Default),
+            // There are no modules, so everything is the same as all.
+            "all" | "everything" => Ok(InfoSection::All),
             "" => Ok(InfoSection::Default),
             _ => Err(ParseCommandError::InvalidArgument(Value::BulkString(
                 section_str.into(),
@@ -68,8 +96,21 @@ impl Info {
     pub fn handler(
         is_replica: bool,
         master_repl_id_and_offset: Option<(String, u64)>,
+        persistence: Arc<PersistenceState>,
+        memory: Arc<MemoryTracker>,
+        lazyfree: Arc<LazyFree>,
+        stats: Arc<CommandStats>,
+        config: Arc<ServerConfig>,
     ) -> InfoHandler {
-        InfoHandler::new(is_replica, master_repl_id_and_offset)
+        InfoHandler::new(
+            is_replica,
+            master_repl_id_and_offset,
+            persistence,
+            memory,
+            lazyfree,
+            stats,
+            config,
+        )
     }
 
     /// Returns INFO as a Command in the form of Value.
@@ -94,36 +135,143 @@ pub struct InfoClient;
 pub struct InfoHandler {
     is_replica: bool,
     master_repl_id_and_offset: Option<(String, u64)>,
+    #[cfg_attr(not(feature = "persistence"), allow(dead_code))]
+    persistence: Arc<PersistenceState>,
+    memory: Arc<MemoryTracker>,
+    lazyfree: Arc<LazyFree>,
+    stats: Arc<CommandStats>,
+    config: Arc<ServerConfig>,
+    failover_state: FailoverState,
 }
 
 impl InfoHandler {
-    fn new(is_replica: bool, master_repl_id_and_offset: Option<(String, u64)>) -> Self {
+    fn new(
+        is_replica: bool,
+        master_repl_id_and_offset: Option<(String, u64)>,
+        persistence: Arc<PersistenceState>,
+        memory: Arc<MemoryTracker>,
+        lazyfree: Arc<LazyFree>,
+        stats: Arc<CommandStats>,
+        config: Arc<ServerConfig>,
+    ) -> Self {
         Self {
             is_replica,
             master_repl_id_and_offset,
+            persistence,
+            memory,
+            lazyfree,
+            stats,
+            config,
+            failover_state: FailoverState::NoFailover,
         }
     }
 
+    /// Reports the progress of the FAILOVER this master is running, if any.
+    pub fn with_failover_state(mut self, failover_state: FailoverState) -> Self {
+        self.failover_state = failover_state;
+        self
+    }
+
     /// Returns information and statistics about the server in a format that is simple to parse by computers and easy to read by humans.
     pub fn handle(&self, arg: InfoArg) -> Value {
-        match arg.section.to_owned() {
-            InfoSection::Replication => self.handle_replication(),
-            InfoSection::Default => todo!(),
+        let sections = match arg.section {
+            // The sections Redis replies with when none is asked for.
+            InfoSection::Default => vec![
+                InfoSection::Memory,
+                InfoSection::Persistence,
+                InfoSection::Stats,
+                InfoSection::Replication,
+                InfoSection::Cluster,
+            ],
+            InfoSection::All => vec![
+                InfoSection::Memory,
+                InfoSection::Persistence,
+                InfoSection::Stats,
+                InfoSection::Replication,
+                InfoSection::Commandstats,
+                InfoSection::Latencystats,
+                InfoSection::Cluster,
+            ],
+            section => vec![section],
+        };
+
+        // Each section is under a header as Redis does.
+        let sections: Vec<String> = sections
+            .into_iter()
+            .map(|section| {
+                let (name, info) = self.section_info(section);
+                let mut lines = vec![format!("# {name}")];
+                lines.extend(info);
+                lines.join("\n")
+            })
+            .collect();
+        Value::BulkString(BulkString::from(sections.join("\n\n").as_ref()))
+    }
+
+    /// Returns the header and the lines of a single section.
+    fn section_info(&self, section: InfoSection) -> (&'static str, Vec<String>) {
+        match section {
+            InfoSection::Replication => ("Replication", self.replication_info()),
+            InfoSection::Persistence => ("Persistence", self.persistence_info()),
+            InfoSection::Memory => ("Memory", self.memory_info()),
+            InfoSection::Stats => ("Stats", self.stats.stats_info()),
+            InfoSection::Commandstats => ("Commandstats", self.stats.commandstats_info()),
+            InfoSection::Latencystats => ("Latencystats", self.stats.latencystats_info()),
+            InfoSection::Cluster => ("Cluster", self.cluster_info()),
+            InfoSection::Default | InfoSection::All => {
+                unreachable!("sections are expanded before rendering")
+            }
         }
     }
 
-    fn handle_replication(&self) -> Value {
+    fn replication_info(&self) -> Vec<String> {
         if self.is_replica {
-            Value::BulkString(BulkString::from("role:slave"))
+            let config = self.config.read();
+            let mut info = vec!["role:slave".to_string()];
+            if let Some(master) = &config.replica_of {
+                info.push(format!("master_host:{}", master.host));
+                info.push(format!("master_port:{}", master.port));
+            }
+            info.push(format!("slave_priority:{}", config.replica_priority));
+            info
         } else {
-            let mut info = vec!["role:master".to_string()];
+            let mut info = vec![
+                "role:master".to_string(),
+                format!("master_failover_state:{}", self.failover_state.as_str()),
+            ];
             if self.master_repl_id_and_offset.is_some() {
                 let m = self.master_repl_id_and_offset.clone().unwrap();
                 info.push(format!("master_replid:{}", m.0,));
                 info.push(format!("master_repl_offset:{}", m.1,));
             }
-
-            Value::BulkString(BulkString::from(info.join("\n").as_ref()))
+            info
         }
     }
+
+    #[cfg(feature = "persistence")]
+    fn persistence_info(&self) -> Vec<String> {
+        self.persistence.info()
+    }
+
+    /// Without persistence compiled in the section is empty, like an unknown one.
+    #[cfg(not(feature = "persistence"))]
+    fn persistence_info(&self) -> Vec<String> {
+        vec![]
+    }
+
+    fn memory_info(&self) -> Vec<String> {
+        let (maxmemory, policy) = {
+            let config = self.config.read();
+            (config.maxmemory, config.maxmemory_policy)
+        };
+        let mut info = self.memory.info(maxmemory, policy);
+        info.extend(allocator::info(self.memory.used()));
+        info.extend(self.lazyfree.info());
+        info
+    }
+
+    fn cluster_info(&self) -> Vec<String> {
+        let enabled = self.config.read().cluster_enabled;
+        vec![format!("cluster_enabled:{}", enabled as u8)]
+    }
 }
@@ -0,0 +1,140 @@
+use super::super::clock::Clock;
+use super::super::reply::StreamedArray;
+use super::super::resp::{BulkString, Value};
+use super::super::store::Store;
+use super::super::util;
+use super::{consume_args_from_iter, CommandArgParser, ParseCommandError};
+use std::sync::Arc;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeysArg {
+    pub pattern: BulkString,
+}
+
+impl CommandArgParser for KeysArg {
+    /// KEYS pattern
+    fn parse_arg(iter: &mut std::slice::Iter<'_, Value>) -> Result<Self, ParseCommandError> {
+        let args = consume_args_from_iter(iter, 1, 0)?;
+        let pattern = args.first().unwrap().clone();
+
+        Ok(Self { pattern })
+    }
+}
+
+pub struct Keys;
+
+impl Keys {
+    /// Returns an instance of KEYS command handler.
+    pub fn handler(map: Arc<Store>, clock: Arc<dyn Clock>) -> KeysHandler {
+        KeysHandler { map, clock }
+    }
+
+    /// Returns KEYS as a Command in the form of Value.
+    pub fn command_value(arg: KeysArg) -> Value {
+        let v = vec![
+            Value::BulkString("KEYS".into()),
+            Value::BulkString(arg.pattern),
+        ];
+        Value::Array(v.into())
+    }
+}
+
+#[derive(Debug)]
+pub struct KeysHandler {
+    map: Arc<Store>,
+    clock: Arc<dyn Clock>,
+}
+
+impl KeysHandler {
+    /// Returns every key matching the glob-style pattern, in no particular order.
+    ///
+    /// The keys come from a snapshot of the store, so writers aren't held up while the
+    /// reply is written. They are counted first and only turned into replies as they are
+    /// written, so the reply never has to be in memory all at once.
+    pub fn handle(&self, arg: KeysArg) -> StreamedArray {
+        let pattern = arg.pattern.as_bytes().unwrap_or_default().to_vec();
+        let snapshot = self.map.snapshot();
+        // Both passes must agree on which keys have expired.
+        let now = self.clock.now();
+        let matches = move |key: &[u8], expired: bool| !expired && util::glob_match(&pattern, key);
+
+        let len = snapshot
+            .iter()
+            .filter(|(key, data)| matches(key, data.expired_at(now)))
+            .count();
+        let keys = snapshot
+            .into_entries()
+            .filter(move |(key, data)| matches(key, data.expired_at(now)))
+            .map(|(key, _)| Value::BulkString(key.as_bytes().to_vec().into()));
+        StreamedArray::new(len, keys)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn command() {
+        let val = Keys::command_value(KeysArg {
+            pattern: "user:*".into(),
+        });
+
+        assert_eq!(
+            val.array().unwrap().values().unwrap().to_vec(),
+            vec![
+                Value::BulkString("KEYS".into()),
+                Value::BulkString("user:*".into())
+            ]
+        )
+    }
+}
+
+#[cfg(test)]
+mod handler_test {
+    use std::time::Duration;
+
+    use super::super::super::clock::{Clock, TestClock};
+    use super::super::super::handler::StoredData;
+    use super::super::super::key::Key;
+    use super::super::super::reply::Reply;
+    use super::*;
+
+    #[test]
+    fn handle_keys() {
+        let clock = Arc::new(TestClock::default());
+        let soon = clock.now() + Duration::from_secs(1);
+        let map = Arc::new(Store::from_iter([
+            (Key::from("user:1"), StoredData::new("a".into(), None)),
+            (Key::from("user:2"), StoredData::new("b".into(), None)),
+            (Key::from("user:3"), StoredData::new("c".into(), Some(soon))),
+            (Key::from("session:1"), StoredData::new("d".into(), None)),
+        ]));
+        let handler = Keys::handler(map.clone(), clock.clone());
+        clock.advance(Duration::from_secs(2));
+
+        let keys = handler.handle(KeysArg {
+            pattern: "user:*".into(),
+        });
+        assert_eq!(keys.len(), 2);
+        // Keys written after the call aren't part of the reply.
+        map.write(b"user:4")
+            .insert("user:4".into(), StoredData::new("e".into(), None));
+
+        let mut keys = Reply::from(keys)
+            .into_value()
+            .array()
+            .unwrap()
+            .values()
+            .unwrap()
+            .to_vec();
+        keys.sort_by_key(|key| key.bulk_string().unwrap().as_bytes().unwrap().to_vec());
+        assert_eq!(
+            keys,
+            vec![
+                Value::BulkString("user:1".into()),
+                Value::BulkString("user:2".into())
+            ]
+        );
+    }
+}
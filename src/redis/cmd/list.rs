@@ -0,0 +1,584 @@
+use std::sync::Arc;
+
+use super::super::clock::Clock;
+use super::super::events::{KeyEvent, KeyEvents};
+use super::super::handler::{HandleCommandError, StoredData, StoredValue};
+use super::super::key::Key;
+use super::super::list::{List, DEFAULT_NODE_SIZE};
+use super::super::listpack::ListpackSize;
+use super::super::reply::StreamedArray;
+use super::super::resp::{Array, BulkString, Integer, Value};
+use super::super::store::Store;
+use super::{
+    bulk_string_to_int64, consume_args_from_iter, consume_variadic_args_from_iter,
+    CommandArgParser, ParseCommandError,
+};
+
+/// The end of a list that elements are pushed to or popped from.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ListEnd {
+    Left,
+    Right,
+}
+
+/// Arguments of LPUSH and RPUSH.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct PushArg {
+    pub key: BulkString,
+    pub elements: Vec<BulkString>,
+    pub end: ListEnd,
+}
+
+impl CommandArgParser for PushArg {
+    /// LPUSH key element [element ...]
+    fn parse_arg(iter: &mut std::slice::Iter<'_, Value>) -> Result<Self, ParseCommandError> {
+        Self::parse_with(iter, ListEnd::Left)
+    }
+}
+
+impl PushArg {
+    /// RPUSH key element [element ...]
+    pub fn parse_rpush(iter: &mut std::slice::Iter<'_, Value>) -> Result<Self, ParseCommandError> {
+        Self::parse_with(iter, ListEnd::Right)
+    }
+
+    fn parse_with(
+        iter: &mut std::slice::Iter<'_, Value>,
+        end: ListEnd,
+    ) -> Result<Self, ParseCommandError> {
+        let mut args = consume_variadic_args_from_iter(iter, 2)?;
+        let key = args.remove(0);
+
+        Ok(Self {
+            key,
+            elements: args,
+            end,
+        })
+    }
+}
+
+pub struct Push;
+
+impl Push {
+    /// Returns an instance of the handler shared by LPUSH and RPUSH.
+    pub fn handler(map: Arc<Store>, clock: Arc<dyn Clock>) -> PushHandler {
+        PushHandler {
+            map,
+            clock,
+            events: None,
+            size: DEFAULT_NODE_SIZE,
+        }
+    }
+
+    /// Returns LPUSH or RPUSH, whichever pushes to the end of the arg, as a Command in the
+    /// form of Value.
+    pub fn command_value(arg: PushArg) -> Value {
+        let name = match arg.end {
+            ListEnd::Left => "LPUSH",
+            ListEnd::Right => "RPUSH",
+        };
+        let mut v = vec![Value::BulkString(name.into()), Value::BulkString(arg.key)];
+        v.extend(arg.elements.into_iter().map(Value::BulkString));
+        Value::Array(v.into())
+    }
+}
+
+pub struct PushHandler {
+    map: Arc<Store>,
+    clock: Arc<dyn Clock>,
+    events: Option<Arc<KeyEvents>>,
+    size: ListpackSize,
+}
+
+impl PushHandler {
+    /// Tells the listeners about keys pushed to.
+    pub fn with_events(mut self, events: Arc<KeyEvents>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Limits the listpack nodes of the list, see `list-max-listpack-size`.
+    pub fn with_listpack_size(mut self, size: ListpackSize) -> Self {
+        self.size = size;
+        self
+    }
+
+    /// Pushes the elements one after the other, so LPUSH leaves them in reverse order, and
+    /// returns the length of the list. A missing or expired key starts as an empty list.
+    pub fn handle(&self, arg: PushArg) -> Result<Value, HandleCommandError> {
+        let key = arg.key.as_bytes().unwrap_or_default();
+        let now = self.clock.now();
+
+        let mut shard = self.map.write(key);
+        if shard.get(key).is_some_and(|data| data.expired_at(now)) {
+            shard.remove(key);
+        }
+        if shard.get(key).is_none() {
+            let list = StoredValue::List(List::new());
+            shard.insert(Key::new(key), StoredData::new(list, None));
+        }
+        let list = shard
+            .value_mut(key)
+            .and_then(StoredValue::as_list_mut)
+            .ok_or(HandleCommandError::WrongType)?;
+        for element in &arg.elements {
+            let element = element.as_bytes().unwrap_or_default();
+            match arg.end {
+                ListEnd::Left => list.push_front(element, self.size),
+                ListEnd::Right => list.push_back(element, self.size),
+            }
+        }
+        let len = list.len();
+        drop(shard);
+
+        if let Some(events) = &self.events {
+            let event = match arg.end {
+                ListEnd::Left => KeyEvent::LPush,
+                ListEnd::Right => KeyEvent::RPush,
+            };
+            events.notify(key, event);
+        }
+        Ok(Value::Integer(Integer::new(len as i64)))
+    }
+}
+
+/// Arguments of LPOP and RPOP.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct PopArg {
+    pub key: BulkString,
+    /// How many elements to pop, replying with an array rather than a single element if
+    /// given.
+    pub count: Option<usize>,
+    pub end: ListEnd,
+}
+
+impl CommandArgParser for PopArg {
+    /// LPOP key [count]
+    fn parse_arg(iter: &mut std::slice::Iter<'_, Value>) -> Result<Self, ParseCommandError> {
+        Self::parse_with(iter, ListEnd::Left)
+    }
+}
+
+impl PopArg {
+    /// RPOP key [count]
+    pub fn parse_rpop(iter: &mut std::slice::Iter<'_, Value>) -> Result<Self, ParseCommandError> {
+        Self::parse_with(iter, ListEnd::Right)
+    }
+
+    fn parse_with(
+        iter: &mut std::slice::Iter<'_, Value>,
+        end: ListEnd,
+    ) -> Result<Self, ParseCommandError> {
+        let args = consume_args_from_iter(iter, 1, 1)?;
+        let count = match args.get(1) {
+            Some(count) => Some(
+                usize::try_from(bulk_string_to_int64(count)?)
+                    .map_err(|_| ParseCommandError::NotPositive)?,
+            ),
+            None => None,
+        };
+
+        Ok(Self {
+            key: args[0].clone(),
+            count,
+            end,
+        })
+    }
+}
+
+pub struct Pop;
+
+impl Pop {
+    /// Returns an instance of the handler shared by LPOP and RPOP.
+    pub fn handler(map: Arc<Store>, clock: Arc<dyn Clock>) -> PopHandler {
+        PopHandler {
+            map,
+            clock,
+            events: None,
+        }
+    }
+
+    /// Returns LPOP or RPOP, whichever pops from the end of the arg, as a Command in the
+    /// form of Value.
+    pub fn command_value(arg: PopArg) -> Value {
+        let name = match arg.end {
+            ListEnd::Left => "LPOP",
+            ListEnd::Right => "RPOP",
+        };
+        let mut v = vec![Value::BulkString(name.into()), Value::BulkString(arg.key)];
+        if let Some(count) = arg.count {
+            v.push(Value::BulkString(count.to_string().into()));
+        }
+        Value::Array(v.into())
+    }
+}
+
+pub struct PopHandler {
+    map: Arc<Store>,
+    clock: Arc<dyn Clock>,
+    events: Option<Arc<KeyEvents>>,
+}
+
+impl PopHandler {
+    /// Tells the listeners about keys popped from, and lists removed once empty.
+    pub fn with_events(mut self, events: Arc<KeyEvents>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Pops the first element, or up to `count` elements, from the end of the list. Replies
+    /// nil if the key doesn't exist.
+    pub fn handle(&self, arg: PopArg) -> Result<Value, HandleCommandError> {
+        let key = arg.key.as_bytes().unwrap_or_default();
+        let popped = self.pop(key, arg.count.unwrap_or(1), arg.end)?;
+        Ok(match (popped, arg.count) {
+            (None, Some(_)) => Value::Array(Array::null()),
+            (None, None) => Value::BulkString(BulkString::null()),
+            (Some(popped), Some(_)) => Value::Array(Array::new(
+                popped.into_iter().map(Value::BulkString).collect(),
+            )),
+            (Some(popped), None) => {
+                Value::BulkString(popped.into_iter().next().unwrap_or_else(BulkString::null))
+            }
+        })
+    }
+
+    /// Pops up to `count` elements from the end of the list held by the key, removing the
+    /// key once the list is empty. Returns `None` if the key doesn't exist.
+    pub fn pop(
+        &self,
+        key: &[u8],
+        count: usize,
+        end: ListEnd,
+    ) -> Result<Option<Vec<BulkString>>, HandleCommandError> {
+        let now = self.clock.now();
+
+        let mut shard = self.map.write(key);
+        if shard.get(key).is_none_or(|data| data.expired_at(now)) {
+            return Ok(None);
+        }
+        let list = shard
+            .value_mut(key)
+            .and_then(StoredValue::as_list_mut)
+            .ok_or(HandleCommandError::WrongType)?;
+        let count = count.min(list.len());
+        let popped: Vec<_> = match end {
+            ListEnd::Left => (0..count).filter_map(|_| list.pop_front()).collect(),
+            ListEnd::Right => (0..count).filter_map(|_| list.pop_back()).collect(),
+        };
+        let emptied = list.is_empty();
+        if emptied {
+            shard.remove(key);
+        }
+        drop(shard);
+
+        if let Some(events) = self.events.as_ref().filter(|_| !popped.is_empty()) {
+            let event = match end {
+                ListEnd::Left => KeyEvent::LPop,
+                ListEnd::Right => KeyEvent::RPop,
+            };
+            events.notify(key, event);
+            if emptied {
+                events.notify(key, KeyEvent::Del);
+            }
+        }
+        Ok(Some(popped))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct LLenArg {
+    pub key: BulkString,
+}
+
+impl CommandArgParser for LLenArg {
+    /// LLEN key
+    fn parse_arg(iter: &mut std::slice::Iter<'_, Value>) -> Result<Self, ParseCommandError> {
+        let args = consume_args_from_iter(iter, 1, 0)?;
+        let key = args.first().unwrap().clone();
+
+        Ok(Self { key })
+    }
+}
+
+pub struct LLen;
+
+impl LLen {
+    /// Returns an instance of LLEN command handler.
+    pub fn handler(map: Arc<Store>, clock: Arc<dyn Clock>) -> LLenHandler {
+        LLenHandler { map, clock }
+    }
+
+    /// Returns LLEN as a Command in the form of Value.
+    pub fn command_value(arg: LLenArg) -> Value {
+        let v = vec![Value::BulkString("LLEN".into()), Value::BulkString(arg.key)];
+        Value::Array(v.into())
+    }
+}
+
+#[derive(Debug)]
+pub struct LLenHandler {
+    map: Arc<Store>,
+    clock: Arc<dyn Clock>,
+}
+
+impl LLenHandler {
+    /// Returns the length of the list held by the key, 0 if it doesn't exist.
+    pub fn handle(&self, arg: LLenArg) -> Result<Value, HandleCommandError> {
+        let key = arg.key.as_bytes().unwrap_or_default();
+        let now = self.clock.now();
+        let len = match self.map.read(key).get(key) {
+            Some(data) if !data.expired_at(now) => data
+                .value
+                .as_list()
+                .ok_or(HandleCommandError::WrongType)?
+                .len(),
+            _ => 0,
+        };
+        Ok(Value::Integer(Integer::new(len as i64)))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct LRangeArg {
+    pub key: BulkString,
+    pub start: i64,
+    pub stop: i64,
+}
+
+impl CommandArgParser for LRangeArg {
+    /// LRANGE key start stop
+    fn parse_arg(iter: &mut std::slice::Iter<'_, Value>) -> Result<Self, ParseCommandError> {
+        let args = consume_args_from_iter(iter, 3, 0)?;
+
+        Ok(Self {
+            key: args[0].clone(),
+            start: bulk_string_to_int64(&args[1])?,
+            stop: bulk_string_to_int64(&args[2])?,
+        })
+    }
+}
+
+pub struct LRange;
+
+impl LRange {
+    /// Returns an instance of LRANGE command handler.
+    pub fn handler(map: Arc<Store>, clock: Arc<dyn Clock>) -> LRangeHandler {
+        LRangeHandler { map, clock }
+    }
+
+    /// Returns LRANGE as a Command in the form of Value.
+    pub fn command_value(arg: LRangeArg) -> Value {
+        let v = vec![
+            Value::BulkString("LRANGE".into()),
+            Value::BulkString(arg.key),
+            Value::BulkString(arg.start.to_string().into()),
+            Value::BulkString(arg.stop.to_string().into()),
+        ];
+        Value::Array(v.into())
+    }
+}
+
+#[derive(Debug)]
+pub struct LRangeHandler {
+    map: Arc<Store>,
+    clock: Arc<dyn Clock>,
+}
+
+impl LRangeHandler {
+    /// Returns the elements from `start` to `stop`, both inclusive. Negative indexes count
+    /// from the end of the list, -1 being the last element.
+    ///
+    /// The elements are copied out packed like the list, and only turned into replies as
+    /// they are written, so a long range never has to be in memory all at once.
+    pub fn handle(&self, arg: LRangeArg) -> Result<StreamedArray, HandleCommandError> {
+        let key = arg.key.as_bytes().unwrap_or_default();
+        let now = self.clock.now();
+        let map = self.map.read(key);
+        let list = match map.get(key) {
+            Some(data) if !data.expired_at(now) => {
+                data.value.as_list().ok_or(HandleCommandError::WrongType)?
+            }
+            _ => return Ok(StreamedArray::new(0, std::iter::empty())),
+        };
+
+        let elements = match list_range(list.len(), arg.start, arg.stop) {
+            Some((start, stop)) => list.slice(start..=stop),
+            None => List::new(),
+        };
+        let len = elements.len();
+        let elements = elements
+            .into_iter()
+            .map(|element| Value::BulkString(element.into()));
+        Ok(StreamedArray::new(len, elements))
+    }
+}
+
+/// Returns the first and last index of the range of a list of `len` elements, or `None` if
+/// the range is empty. Unlike for strings, a stop before the start of the list is empty.
+pub(super) fn list_range(len: usize, start: i64, stop: i64) -> Option<(usize, usize)> {
+    let len = len as i64;
+    let resolve = |i: i64| if i < 0 { len + i } else { i };
+    let (start, stop) = (resolve(start).max(0), resolve(stop).min(len - 1));
+    (start <= stop).then_some((start as usize, stop as usize))
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::super::test_util::command;
+    use super::super::Command;
+    use super::*;
+
+    #[test]
+    fn parse_list_commands() {
+        for end in [ListEnd::Left, ListEnd::Right] {
+            let arg = PushArg {
+                key: "key".into(),
+                elements: vec!["a".into(), "b".into()],
+                end,
+            };
+            match Command::try_from(Push::command_value(arg.clone())) {
+                Ok(Command::LPush(parsed) | Command::RPush(parsed)) => assert_eq!(parsed, arg),
+                other => panic!("Unexpected parse result {other:?}"),
+            }
+
+            let arg = PopArg {
+                key: "key".into(),
+                count: Some(2),
+                end,
+            };
+            match Command::try_from(Pop::command_value(arg.clone())) {
+                Ok(Command::LPop(parsed) | Command::RPop(parsed)) => assert_eq!(parsed, arg),
+                other => panic!("Unexpected parse result {other:?}"),
+            }
+        }
+
+        let arg = LRangeArg {
+            key: "key".into(),
+            start: 0,
+            stop: -1,
+        };
+        match Command::try_from(LRange::command_value(arg.clone())) {
+            Ok(Command::LRange(parsed)) => assert_eq!(parsed, arg),
+            other => panic!("Unexpected parse result {other:?}"),
+        }
+
+        assert!(matches!(
+            Command::try_from(command(["LPOP", "key", "-1"])),
+            Err(ParseCommandError::NotPositive)
+        ));
+    }
+
+    #[test]
+    fn list_ranges() {
+        assert_eq!(list_range(5, 0, -1), Some((0, 4)));
+        assert_eq!(list_range(5, -3, 10), Some((2, 4)));
+        assert_eq!(list_range(5, -10, 1), Some((0, 1)));
+        assert_eq!(list_range(5, 0, -10), None);
+        assert_eq!(list_range(5, 3, 1), None);
+        assert_eq!(list_range(5, 5, 10), None);
+        assert_eq!(list_range(0, 0, -1), None);
+    }
+}
+
+#[cfg(test)]
+mod handler_test {
+    use super::super::super::clock;
+    use super::super::super::reply::Reply;
+    use super::super::super::test_util::bulk;
+    use super::*;
+
+    fn push(map: &Arc<Store>, end: ListEnd, elements: &[&str]) -> Value {
+        let arg = PushArg {
+            key: "list".into(),
+            elements: elements.iter().map(|&e| e.into()).collect(),
+            end,
+        };
+        Push::handler(map.clone(), clock::system())
+            .handle(arg)
+            .unwrap()
+    }
+
+    fn lrange(map: &Arc<Store>, start: i64, stop: i64) -> Vec<Value> {
+        let arg = LRangeArg {
+            key: "list".into(),
+            start,
+            stop,
+        };
+        let resp = LRange::handler(map.clone(), clock::system())
+            .handle(arg)
+            .unwrap();
+        let resp = Reply::from(resp).into_value();
+        resp.array().unwrap().values().unwrap().to_vec()
+    }
+
+    #[test]
+    fn handle_push_and_range() {
+        let map = Arc::new(Store::default());
+
+        assert_eq!(
+            push(&map, ListEnd::Right, &["b", "c"]),
+            Value::Integer(Integer::new(2))
+        );
+        assert_eq!(
+            push(&map, ListEnd::Left, &["a", "z"]),
+            Value::Integer(Integer::new(4))
+        );
+
+        assert_eq!(lrange(&map, 0, -1), ["z", "a", "b", "c"].map(bulk));
+        assert_eq!(lrange(&map, -2, 100), ["b", "c"].map(bulk));
+        assert!(lrange(&map, 2, 1).is_empty());
+
+        let llen = LLen::handler(map.clone(), clock::system());
+        let len = llen.handle(LLenArg { key: "list".into() }).unwrap();
+        assert_eq!(len, Value::Integer(Integer::new(4)));
+
+        map.write(b"string")
+            .insert("string".into(), StoredData::new("value".into(), None));
+        let arg = PushArg {
+            key: "string".into(),
+            elements: vec!["a".into()],
+            end: ListEnd::Left,
+        };
+        assert!(matches!(
+            Push::handler(map.clone(), clock::system()).handle(arg),
+            Err(HandleCommandError::WrongType)
+        ));
+        assert!(matches!(
+            llen.handle(LLenArg {
+                key: "string".into()
+            }),
+            Err(HandleCommandError::WrongType)
+        ));
+    }
+
+    #[test]
+    fn handle_pop_removes_empty_lists() {
+        let map = Arc::new(Store::default());
+        push(&map, ListEnd::Right, &["a", "b", "c", "d"]);
+        let handler = Pop::handler(map.clone(), clock::system());
+        let pop = |count, end| {
+            handler
+                .handle(PopArg {
+                    key: "list".into(),
+                    count,
+                    end,
+                })
+                .unwrap()
+        };
+
+        assert_eq!(pop(None, ListEnd::Left), bulk("a"));
+        assert_eq!(pop(None, ListEnd::Right), bulk("d"));
+        assert_eq!(
+            pop(Some(5), ListEnd::Right),
+            Value::Array(Array::new(vec![bulk("c"), bulk("b")]))
+        );
+        assert!(map.is_empty());
+
+        assert_eq!(
+            pop(None, ListEnd::Left),
+            Value::BulkString(BulkString::null())
+        );
+        assert_eq!(pop(Some(1), ListEnd::Left), Value::Array(Array::null()));
+    }
+}
@@ -0,0 +1,319 @@
+use std::sync::Arc;
+
+use thiserror::Error;
+
+use super::super::allocator;
+use super::super::clock::Clock;
+use super::super::config::ServerConfig;
+use super::super::memory::{entry_usage, human_bytes, MemoryTracker};
+use super::super::resp::{Array, BulkString, Integer, Value};
+use super::super::store::Store;
+use super::{bulk_string_to_string, consume_args_from_iter, CommandArgParser, ParseCommandError};
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum MemoryError {
+    #[error("Error purging dirty pages")]
+    Purge,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum MemorySubcommand {
+    Usage {
+        key: BulkString,
+        samples: Option<u64>,
+    },
+    Stats,
+    Doctor,
+    /// Releases memory the allocator keeps for later back to the OS.
+    Purge,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct MemoryArg {
+    pub subcommand: MemorySubcommand,
+}
+
+impl CommandArgParser for MemoryArg {
+    /// MEMORY USAGE key [SAMPLES count]
+    /// MEMORY STATS | DOCTOR | PURGE
+    fn parse_arg(iter: &mut std::slice::Iter<'_, Value>) -> Result<Self, ParseCommandError> {
+        let args = consume_args_from_iter(iter, 1, 3)?;
+        let subcommand = args.first().unwrap();
+
+        let subcommand = match (
+            bulk_string_to_string(subcommand)?.to_lowercase().as_str(),
+            &args[1..],
+        ) {
+            ("usage", [key]) => MemorySubcommand::Usage {
+                key: key.clone(),
+                samples: None,
+            },
+            ("usage", [key, option, count]) => {
+                if !bulk_string_to_string(option)?.eq_ignore_ascii_case("samples") {
+                    return Err(ParseCommandError::InvalidArgument(Value::BulkString(
+                        option.clone(),
+                    )));
+                }
+                let samples = count
+                    .as_str()
+                    .and_then(|count| count.parse().ok())
+                    .ok_or(ParseCommandError::NotInteger)?;
+                MemorySubcommand::Usage {
+                    key: key.clone(),
+                    samples: Some(samples),
+                }
+            }
+            ("stats", []) => MemorySubcommand::Stats,
+            ("doctor", []) => MemorySubcommand::Doctor,
+            ("purge", []) => MemorySubcommand::Purge,
+            ("usage" | "stats" | "doctor" | "purge", _) => {
+                return Err(ParseCommandError::WrongNumArgs)
+            }
+            _ => {
+                return Err(ParseCommandError::InvalidArgument(Value::BulkString(
+                    subcommand.clone(),
+                )))
+            }
+        };
+
+        Ok(Self { subcommand })
+    }
+}
+
+pub struct Memory;
+
+impl Memory {
+    /// Returns an instance of MEMORY command handler.
+    pub fn handler(
+        map: Arc<Store>,
+        clock: Arc<dyn Clock>,
+        memory: Arc<MemoryTracker>,
+        config: Arc<ServerConfig>,
+    ) -> MemoryHandler {
+        MemoryHandler {
+            map,
+            clock,
+            memory,
+            config,
+        }
+    }
+
+    /// Returns MEMORY as a Command in the form of Value.
+    pub fn command_value(arg: MemoryArg) -> Value {
+        let mut v = vec![Value::BulkString("MEMORY".into())];
+        match arg.subcommand {
+            MemorySubcommand::Usage { key, samples } => {
+                v.push(Value::BulkString("USAGE".into()));
+                v.push(Value::BulkString(key));
+                if let Some(samples) = samples {
+                    v.push(Value::BulkString("SAMPLES".into()));
+                    v.push(Value::BulkString(samples.to_string().into()));
+                }
+            }
+            MemorySubcommand::Stats => v.push(Value::BulkString("STATS".into())),
+            MemorySubcommand::Doctor => v.push(Value::BulkString("DOCTOR".into())),
+            MemorySubcommand::Purge => v.push(Value::BulkString("PURGE".into())),
+        }
+        Value::Array(v.into())
+    }
+}
+
+#[derive(Debug)]
+pub struct MemoryHandler {
+    map: Arc<Store>,
+    clock: Arc<dyn Clock>,
+    memory: Arc<MemoryTracker>,
+    config: Arc<ServerConfig>,
+}
+
+impl MemoryHandler {
+    /// USAGE returns the bytes used by the key and its value, or nil if it doesn't exist.
+    /// SAMPLES only matters for collections, which are estimated from that many elements.
+    /// STATS returns a flat array of memory statistics.
+    /// DOCTOR returns a human readable report of memory issues.
+    /// PURGE returns OK once the allocator released what it could.
+    pub fn handle(&self, arg: MemoryArg) -> Result<Value, MemoryError> {
+        let value = match arg.subcommand {
+            MemorySubcommand::Usage { key, samples: _ } => {
+                let key = key.as_bytes().unwrap_or_default();
+                let map = self.map.read(key);
+                match map
+                    .get_key_value(key)
+                    .filter(|(_, data)| !data.expired_at(self.clock.now()))
+                {
+                    Some((key, data)) => {
+                        Value::Integer(Integer::new(entry_usage(key, data) as i64))
+                    }
+                    None => Value::BulkString(BulkString::null()),
+                }
+            }
+            MemorySubcommand::Stats => {
+                let keys = self.map.len() as u64;
+                let used = self.memory.used();
+                let stats = [
+                    ("peak.allocated", self.memory.peak()),
+                    ("total.allocated", used),
+                    ("keys.count", keys),
+                    ("keys.bytes-per-key", used.checked_div(keys).unwrap_or(0)),
+                    ("dataset.bytes", used),
+                ];
+
+                Value::Array(Array::new(
+                    stats
+                        .into_iter()
+                        .flat_map(|(name, n)| {
+                            [
+                                Value::BulkString(name.into()),
+                                Value::Integer(Integer::new(n as i64)),
+                            ]
+                        })
+                        .collect(),
+                ))
+            }
+            MemorySubcommand::Doctor => Value::BulkString(self.doctor().into()),
+            MemorySubcommand::Purge => {
+                if !allocator::purge() {
+                    return Err(MemoryError::Purge);
+                }
+                Value::SimpleString("OK".into())
+            }
+        };
+        Ok(value)
+    }
+
+    fn doctor(&self) -> String {
+        let used = self.memory.used();
+        let peak = self.memory.peak();
+        let maxmemory = self.config.read().maxmemory;
+
+        if used < 1024 * 1024 && peak < 1024 * 1024 {
+            return "Hi Sam, this instance is empty or is using very little memory, my issues \
+                detector can't be used in these conditions."
+                .to_string();
+        }
+
+        let mut issues = vec![];
+        if maxmemory != 0 && used > maxmemory / 10 * 9 {
+            issues.push(format!(
+                " * High memory usage: {} used out of maxmemory {}. Keys will be evicted or \
+                writes rejected depending on maxmemory-policy.",
+                human_bytes(used),
+                human_bytes(maxmemory)
+            ));
+        }
+        if peak > used / 2 * 3 {
+            issues.push(format!(
+                " * Peak memory: in the past this instance used more than 150% the memory \
+                that is currently using ({} peak, {} now).",
+                human_bytes(peak),
+                human_bytes(used)
+            ));
+        }
+
+        if issues.is_empty() {
+            "Hi Sam, I can't find any memory issue in your instance.".to_string()
+        } else {
+            format!(
+                "Sam, I detected a few issues in this Redis instance memory implants:\n\n{}",
+                issues.join("\n")
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn command() {
+        let val = Memory::command_value(MemoryArg {
+            subcommand: MemorySubcommand::Usage {
+                key: "key".into(),
+                samples: Some(5),
+            },
+        });
+
+        assert_eq!(
+            val.array().unwrap().values().unwrap().to_vec(),
+            vec![
+                Value::BulkString("MEMORY".into()),
+                Value::BulkString("USAGE".into()),
+                Value::BulkString("key".into()),
+                Value::BulkString("SAMPLES".into()),
+                Value::BulkString("5".into()),
+            ]
+        )
+    }
+
+    #[test]
+    fn parse_usage_samples() {
+        let parse = |count: &str| {
+            let values = [
+                Value::BulkString("USAGE".into()),
+                Value::BulkString("key".into()),
+                Value::BulkString("SAMPLES".into()),
+                Value::BulkString(count.into()),
+            ];
+            MemoryArg::parse_arg(&mut values.iter()).map(|arg| arg.subcommand)
+        };
+
+        assert_eq!(
+            parse("5").unwrap(),
+            MemorySubcommand::Usage {
+                key: "key".into(),
+                samples: Some(5),
+            }
+        );
+        for count in ["-1", "five", "1.5"] {
+            let err = parse(count).unwrap_err();
+            assert!(
+                matches!(err, ParseCommandError::NotInteger),
+                "{count} accepted"
+            );
+            assert_eq!(err.to_string(), "value is not an integer or out of range");
+        }
+    }
+}
+
+#[cfg(test)]
+mod handler_test {
+    use super::super::super::clock;
+    use super::super::super::handler::StoredData;
+    use super::super::super::key::Key;
+    use super::*;
+
+    #[test]
+    fn handle_usage() {
+        let key = Key::from("key");
+        let data = StoredData::new("value".into(), None);
+        let expected = entry_usage(&key, &data);
+        let handler = Memory::handler(
+            Arc::new(Store::from_iter([(key, data)])),
+            clock::system(),
+            Arc::new(MemoryTracker::default()),
+            Arc::new(ServerConfig::default()),
+        );
+
+        let resp = handler.handle(MemoryArg {
+            subcommand: MemorySubcommand::Usage {
+                key: "key".into(),
+                samples: None,
+            },
+        });
+        assert_eq!(resp, Ok(Value::Integer(Integer::new(expected as i64))));
+
+        let resp = handler.handle(MemoryArg {
+            subcommand: MemorySubcommand::Usage {
+                key: "missing".into(),
+                samples: None,
+            },
+        });
+        assert_eq!(resp, Ok(Value::BulkString(BulkString::null())));
+
+        let resp = handler.handle(MemoryArg {
+            subcommand: MemorySubcommand::Purge,
+        });
+        assert_eq!(resp, Ok(Value::SimpleString("OK".into())));
+    }
+}
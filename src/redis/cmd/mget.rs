@@ -0,0 +1,124 @@
+use std::sync::Arc;
+
+use super::super::clock::Clock;
+use super::super::events::KeyEvents;
+use super::super::eviction::LfuConfig;
+use super::super::lazyfree::LazyFree;
+use super::super::resp::{Array, BulkString, Value};
+use super::super::store::Store;
+use super::{consume_variadic_args_from_iter, CommandArgParser, Lookup, ParseCommandError};
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct MGetArg {
+    pub keys: Vec<BulkString>,
+}
+
+impl CommandArgParser for MGetArg {
+    /// MGET key [key ...]
+    fn parse_arg(iter: &mut std::slice::Iter<'_, Value>) -> Result<Self, ParseCommandError> {
+        let keys = consume_variadic_args_from_iter(iter, 1)?;
+
+        Ok(Self { keys })
+    }
+}
+
+pub struct MGet;
+
+impl MGet {
+    /// Returns an instance of MGET command handler.
+    ///
+    /// Expired keys are freed on `lazyfree` if given.
+    pub fn handler(
+        map: Arc<Store>,
+        clock: Arc<dyn Clock>,
+        lfu: LfuConfig,
+        lazyfree: Option<Arc<LazyFree>>,
+    ) -> MGetHandler {
+        MGetHandler {
+            lookup: Lookup::new(map, clock, lfu, lazyfree),
+        }
+    }
+
+    /// Returns MGET as a Command in the form of Value.
+    pub fn command_value(arg: MGetArg) -> Value {
+        let mut v = vec![Value::BulkString("MGET".into())];
+        v.extend(arg.keys.into_iter().map(Value::BulkString));
+        Value::Array(v.into())
+    }
+}
+
+pub struct MGetHandler {
+    lookup: Lookup,
+}
+
+impl MGetHandler {
+    /// Tells the listeners about keys found expired.
+    pub fn with_events(mut self, events: Arc<KeyEvents>) -> Self {
+        self.lookup = self.lookup.with_events(events);
+        self
+    }
+
+    /// Returns the value of each key, nil for keys that don't exist or don't hold a string.
+    pub fn handle(&self, arg: MGetArg) -> Value {
+        let values = arg
+            .keys
+            .iter()
+            .map(|key| {
+                let data = self.lookup.get(key.as_bytes().unwrap_or_default());
+                let value = data.as_ref().and_then(|data| data.value.as_string());
+                Value::BulkString(value.cloned().unwrap_or_else(BulkString::null))
+            })
+            .collect();
+        Value::Array(Array::new(values))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::Command;
+    use super::*;
+
+    #[test]
+    fn parse_mget() {
+        let arg = MGetArg {
+            keys: vec!["a".into(), "b".into()],
+        };
+        match Command::try_from(MGet::command_value(arg.clone())) {
+            Ok(Command::MGet(parsed)) => assert_eq!(parsed, arg),
+            other => panic!("Unexpected parse result {other:?}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod handler_test {
+    use super::super::super::clock;
+    use super::super::super::handler::{StoredData, StoredValue};
+    use super::super::super::key::Key;
+    use super::*;
+
+    #[test]
+    fn handle_mget() {
+        let map = Arc::new(Store::from_iter([
+            (Key::from("a"), StoredData::new("1".into(), None)),
+            (
+                Key::from("list"),
+                StoredData::new(StoredValue::List(["a".into()].into()), None),
+            ),
+        ]));
+        let handler = MGet::handler(map, clock::system(), LfuConfig::default(), None);
+
+        let arg = MGetArg {
+            keys: vec!["a".into(), "missing".into(), "list".into(), "a".into()],
+        };
+        assert_eq!(
+            handler.handle(arg),
+            Value::Array(Array::new(vec![
+                Value::BulkString("1".into()),
+                Value::BulkString(BulkString::null()),
+                Value::BulkString(BulkString::null()),
+                Value::BulkString("1".into()),
+            ]))
+        );
+    }
+}
@@ -0,0 +1,255 @@
+use std::sync::Arc;
+
+use super::super::clock::Clock;
+use super::super::events::{KeyEvent, KeyEvents};
+use super::super::handler::StoredData;
+use super::super::key::Key;
+use super::super::lazyfree::LazyFree;
+use super::super::resp::{BulkString, Integer, SimpleString, Value};
+use super::super::store::Store;
+use super::{consume_variadic_args_from_iter, CommandArgParser, ParseCommandError};
+
+/// Arguments of MSET and MSETNX.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct MSetArg {
+    pub pairs: Vec<(BulkString, BulkString)>,
+}
+
+impl CommandArgParser for MSetArg {
+    /// MSET key value [key value ...]
+    fn parse_arg(iter: &mut std::slice::Iter<'_, Value>) -> Result<Self, ParseCommandError> {
+        Self::parse_pairs(iter, "mset")
+    }
+}
+
+impl MSetArg {
+    /// MSETNX key value [key value ...]
+    pub fn parse_msetnx(iter: &mut std::slice::Iter<'_, Value>) -> Result<Self, ParseCommandError> {
+        Self::parse_pairs(iter, "msetnx")
+    }
+
+    fn parse_pairs(
+        iter: &mut std::slice::Iter<'_, Value>,
+        name: &'static str,
+    ) -> Result<Self, ParseCommandError> {
+        let args = consume_variadic_args_from_iter(iter, 2)?;
+        if args.len() % 2 != 0 {
+            return Err(ParseCommandError::WrongArity(name));
+        }
+        let pairs = args
+            .chunks_exact(2)
+            .map(|pair| (pair[0].clone(), pair[1].clone()))
+            .collect();
+
+        Ok(Self { pairs })
+    }
+
+    /// Returns the keys, in the order they were given.
+    pub fn keys(&self) -> impl Iterator<Item = &BulkString> {
+        self.pairs.iter().map(|(key, _)| key)
+    }
+}
+
+pub struct MSet;
+
+impl MSet {
+    /// Returns an instance of the handler shared by MSET and MSETNX.
+    ///
+    /// Overwritten values are freed on `lazyfree` if given.
+    pub fn handler(
+        map: Arc<Store>,
+        clock: Arc<dyn Clock>,
+        lazyfree: Option<Arc<LazyFree>>,
+    ) -> MSetHandler {
+        MSetHandler {
+            map,
+            clock,
+            lazyfree,
+            events: None,
+        }
+    }
+
+    /// Returns MSET as a Command in the form of Value.
+    pub fn command_value(arg: MSetArg) -> Value {
+        Self::pairs_value("MSET", arg)
+    }
+
+    /// Returns MSETNX as a Command in the form of Value.
+    pub fn msetnx_command_value(arg: MSetArg) -> Value {
+        Self::pairs_value("MSETNX", arg)
+    }
+
+    fn pairs_value(name: &str, arg: MSetArg) -> Value {
+        let mut v = vec![Value::BulkString(name.into())];
+        for (key, value) in arg.pairs {
+            v.push(Value::BulkString(key));
+            v.push(Value::BulkString(value));
+        }
+        Value::Array(v.into())
+    }
+}
+
+pub struct MSetHandler {
+    map: Arc<Store>,
+    clock: Arc<dyn Clock>,
+    lazyfree: Option<Arc<LazyFree>>,
+    events: Option<Arc<KeyEvents>>,
+}
+
+impl MSetHandler {
+    /// Tells the listeners about keys set.
+    pub fn with_events(mut self, events: Arc<KeyEvents>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Sets every key to its value, discarding any time to live, and replies OK. A key
+    /// given more than once ends up with the last of its values.
+    pub fn handle(&self, arg: MSetArg) -> Value {
+        self.set(arg, false);
+        Value::SimpleString(SimpleString::from("OK"))
+    }
+
+    /// Like `handle`, but sets none of the keys if any of them exists, replying 1 if the
+    /// keys were set and 0 if not.
+    pub fn handle_nx(&self, arg: MSetArg) -> Value {
+        let set = self.set(arg, true);
+        Value::Integer(Integer::new(set as i64))
+    }
+
+    /// Sets the keys while holding the lock of every shard they are in, so other commands
+    /// see all of them set or none.
+    fn set(&self, arg: MSetArg, nx: bool) -> bool {
+        let now = self.clock.now();
+        let mut shards = self
+            .map
+            .write_many(arg.keys().map(|key| key.as_bytes().unwrap_or_default()));
+        if nx {
+            let exists = arg.keys().any(|key| {
+                let key = key.as_bytes().unwrap_or_default();
+                shards
+                    .shard(key)
+                    .get(key)
+                    .is_some_and(|data| !data.expired_at(now))
+            });
+            if exists {
+                return false;
+            }
+        }
+
+        let mut replaced = Vec::new();
+        for (key, value) in &arg.pairs {
+            let key = key.as_bytes().unwrap_or_default();
+            let data = StoredData::new(value.clone().into(), None);
+            replaced.extend(shards.shard(key).insert(Key::new(key), data));
+        }
+        drop(shards);
+
+        if let Some(lazyfree) = &self.lazyfree {
+            replaced.into_iter().for_each(|old| lazyfree.free(old));
+        }
+        if let Some(events) = &self.events {
+            for key in arg.keys() {
+                events.notify(key.as_bytes().unwrap_or_default(), KeyEvent::Set);
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::Command;
+    use super::*;
+
+    #[test]
+    fn parse_mset() {
+        let arg = MSetArg {
+            pairs: vec![("a".into(), "1".into()), ("b".into(), "2".into())],
+        };
+        match Command::try_from(MSet::command_value(arg.clone())) {
+            Ok(Command::MSet(parsed)) => assert_eq!(parsed, arg),
+            other => panic!("Unexpected parse result {other:?}"),
+        }
+        match Command::try_from(MSet::msetnx_command_value(arg.clone())) {
+            Ok(Command::MSetNx(parsed)) => assert_eq!(parsed, arg),
+            other => panic!("Unexpected parse result {other:?}"),
+        }
+
+        let odd = Value::Array(
+            vec![
+                Value::BulkString("MSET".into()),
+                Value::BulkString("a".into()),
+                Value::BulkString("1".into()),
+                Value::BulkString("b".into()),
+            ]
+            .into(),
+        );
+        assert!(matches!(
+            Command::try_from(odd),
+            Err(ParseCommandError::WrongArity("mset"))
+        ));
+    }
+}
+
+#[cfg(test)]
+mod handler_test {
+    use std::time::Duration;
+
+    use super::super::super::clock::{self, TestClock};
+    use super::*;
+
+    fn pairs(pairs: &[(&str, &str)]) -> MSetArg {
+        MSetArg {
+            pairs: pairs
+                .iter()
+                .map(|(key, value)| ((*key).into(), (*value).into()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn handle_mset() {
+        let map = Arc::new(Store::from_iter([(
+            Key::from("a"),
+            StoredData::new(
+                "old".into(),
+                Some(clock::system().now() + Duration::from_secs(10)),
+            ),
+        )]));
+        let handler = MSet::handler(map.clone(), clock::system(), None);
+
+        let resp = handler.handle(pairs(&[("a", "1"), ("b", "2"), ("b", "3")]));
+        assert_eq!(resp, Value::SimpleString(SimpleString::from("OK")));
+
+        let snapshot = map.snapshot();
+        let a = snapshot.get(b"a".as_slice()).unwrap();
+        assert_eq!(a, &StoredData::new("1".into(), None));
+        assert_eq!(snapshot.get(b"b".as_slice()).unwrap().value, "3".into());
+    }
+
+    #[test]
+    fn handle_msetnx() {
+        let clock = Arc::new(TestClock::default());
+        let map = Arc::new(Store::from_iter([(
+            Key::from("expired"),
+            StoredData::new("old".into(), Some(clock.now())),
+        )]));
+        clock.advance(Duration::from_millis(1));
+        let handler = MSet::handler(map.clone(), clock, None);
+
+        let resp = handler.handle_nx(pairs(&[("a", "1"), ("expired", "2")]));
+        assert_eq!(resp, Value::Integer(Integer::new(1)));
+
+        // Nothing is set once one of the keys exists.
+        let resp = handler.handle_nx(pairs(&[("b", "1"), ("a", "2")]));
+        assert_eq!(resp, Value::Integer(Integer::new(0)));
+        let snapshot = map.snapshot();
+        assert!(snapshot.get(b"b".as_slice()).is_none());
+        assert_eq!(snapshot.get(b"a".as_slice()).unwrap().value, "1".into());
+        assert_eq!(
+            snapshot.get(b"expired".as_slice()).unwrap().value,
+            "2".into()
+        );
+    }
+}
@@ -0,0 +1,240 @@
+use std::sync::Arc;
+
+use thiserror::Error;
+
+use super::super::clock::Clock;
+use super::super::config::ServerConfig;
+use super::super::resp::{BulkString, Integer, Value};
+use super::super::store::Store;
+use super::{bulk_string_to_string, consume_args_from_iter, CommandArgParser, ParseCommandError};
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ObjectError {
+    #[error("An LFU maxmemory policy is not selected, access frequency not tracked.")]
+    LfuNotSelected,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ObjectSubcommand {
+    Encoding(BulkString),
+    Freq(BulkString),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ObjectArg {
+    pub subcommand: ObjectSubcommand,
+}
+
+impl CommandArgParser for ObjectArg {
+    /// OBJECT ENCODING key
+    /// OBJECT FREQ key
+    fn parse_arg(iter: &mut std::slice::Iter<'_, Value>) -> Result<Self, ParseCommandError> {
+        let args = consume_args_from_iter(iter, 2, 0)?;
+        let subcommand = args.first().unwrap();
+
+        let subcommand = match bulk_string_to_string(subcommand)?.to_lowercase().as_str() {
+            "encoding" => ObjectSubcommand::Encoding(args.get(1).unwrap().clone()),
+            "freq" => ObjectSubcommand::Freq(args.get(1).unwrap().clone()),
+            _ => {
+                return Err(ParseCommandError::InvalidArgument(Value::BulkString(
+                    subcommand.clone(),
+                )))
+            }
+        };
+
+        Ok(Self { subcommand })
+    }
+}
+
+pub struct Object;
+
+impl Object {
+    /// Returns an instance of OBJECT command handler.
+    pub fn handler(
+        map: Arc<Store>,
+        clock: Arc<dyn Clock>,
+        config: Arc<ServerConfig>,
+    ) -> ObjectHandler {
+        ObjectHandler { map, clock, config }
+    }
+
+    /// Returns OBJECT as a Command in the form of Value.
+    pub fn command_value(arg: ObjectArg) -> Value {
+        let v = match arg.subcommand {
+            ObjectSubcommand::Encoding(key) => vec![
+                Value::BulkString("OBJECT".into()),
+                Value::BulkString("ENCODING".into()),
+                Value::BulkString(key),
+            ],
+            ObjectSubcommand::Freq(key) => vec![
+                Value::BulkString("OBJECT".into()),
+                Value::BulkString("FREQ".into()),
+                Value::BulkString(key),
+            ],
+        };
+        Value::Array(v.into())
+    }
+}
+
+#[derive(Debug)]
+pub struct ObjectHandler {
+    map: Arc<Store>,
+    clock: Arc<dyn Clock>,
+    config: Arc<ServerConfig>,
+}
+
+impl ObjectHandler {
+    /// ENCODING returns how the value of the key is stored, e.g. `embstr` or `listpack`.
+    ///
+    /// FREQ returns the access frequency counter of the key, which is only tracked with
+    /// an LFU eviction policy.
+    ///
+    /// Both return nil if the key doesn't exist.
+    pub fn handle(&self, arg: ObjectArg) -> Result<Value, ObjectError> {
+        match arg.subcommand {
+            ObjectSubcommand::Encoding(key) => {
+                let key = key.as_bytes().unwrap_or_default();
+                let map = self.map.read(key);
+                Ok(
+                    match map
+                        .get(key)
+                        .filter(|data| !data.expired_at(self.clock.now()))
+                    {
+                        Some(data) => Value::BulkString(data.encoding().into()),
+                        None => Value::BulkString(BulkString::null()),
+                    },
+                )
+            }
+            ObjectSubcommand::Freq(key) => {
+                let (policy, decay_time) = {
+                    let config = self.config.read();
+                    (config.maxmemory_policy, config.lfu_decay_time)
+                };
+                if !policy.is_lfu() {
+                    return Err(ObjectError::LfuNotSelected);
+                }
+
+                let key = key.as_bytes().unwrap_or_default();
+                let map = self.map.read(key);
+                Ok(
+                    match map
+                        .get(key)
+                        .filter(|data| !data.expired_at(self.clock.now()))
+                    {
+                        Some(data) => {
+                            Value::Integer(Integer::new(data.access.frequency(decay_time) as i64))
+                        }
+                        None => Value::BulkString(BulkString::null()),
+                    },
+                )
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn command() {
+        let val = Object::command_value(ObjectArg {
+            subcommand: ObjectSubcommand::Freq("key".into()),
+        });
+
+        assert_eq!(
+            val.array().unwrap().values().unwrap().to_vec(),
+            vec![
+                Value::BulkString("OBJECT".into()),
+                Value::BulkString("FREQ".into()),
+                Value::BulkString("key".into()),
+            ]
+        )
+    }
+}
+
+#[cfg(test)]
+mod handler_test {
+    use super::super::super::clock;
+    use super::super::super::handler::StoredData;
+    use super::super::super::key::Key;
+    use super::super::append::{Append, AppendArg};
+    use super::super::setrange::{SetRange, SetRangeArg};
+    use super::*;
+
+    fn freq_arg(key: &str) -> ObjectArg {
+        ObjectArg {
+            subcommand: ObjectSubcommand::Freq(key.into()),
+        }
+    }
+
+    #[test]
+    fn handle_freq() {
+        let map = Store::from_iter([(Key::from("key"), StoredData::new("value".into(), None))]);
+        let config = Arc::new(ServerConfig::default());
+        let handler = Object::handler(Arc::new(map), clock::system(), config.clone());
+
+        let err = handler
+            .handle(freq_arg("key"))
+            .expect_err("Handle object freq no error");
+        assert_eq!(err, ObjectError::LfuNotSelected);
+
+        config
+            .set(&[("maxmemory-policy".to_string(), "allkeys-lfu".to_string())])
+            .expect("Set config unexpected error");
+        let resp = handler
+            .handle(freq_arg("key"))
+            .expect("Handle object freq unexpected error");
+        assert_eq!(resp, Value::Integer(Integer::new(5)));
+
+        let resp = handler
+            .handle(freq_arg("missing"))
+            .expect("Handle object freq unexpected error");
+        assert_eq!(resp, Value::BulkString(BulkString::null()));
+    }
+
+    #[test]
+    fn handle_encoding() {
+        let map = Store::from_iter([
+            (Key::from("int"), StoredData::new("123".into(), None)),
+            (Key::from("str"), StoredData::new("value".into(), None)),
+        ]);
+        let handler = Object::handler(
+            Arc::new(map),
+            clock::system(),
+            Arc::new(ServerConfig::default()),
+        );
+        let encoding = |key: &str| {
+            handler
+                .handle(ObjectArg {
+                    subcommand: ObjectSubcommand::Encoding(key.into()),
+                })
+                .expect("Handle object encoding unexpected error")
+        };
+
+        assert_eq!(encoding("int"), Value::BulkString("int".into()));
+        assert_eq!(encoding("str"), Value::BulkString("embstr".into()));
+        assert_eq!(encoding("missing"), Value::BulkString(BulkString::null()));
+
+        // Strings modified in place are raw, however short.
+        for key in ["int", "str"] {
+            let arg = AppendArg {
+                key: key.into(),
+                value: "1".into(),
+            };
+            Append::handler(handler.map.clone(), clock::system())
+                .handle(arg)
+                .expect("Handle append unexpected error");
+            assert_eq!(encoding(key), Value::BulkString("raw".into()));
+        }
+        let arg = SetRangeArg {
+            key: "new".into(),
+            offset: 0,
+            value: "value".into(),
+        };
+        SetRange::handler(handler.map.clone(), clock::system())
+            .handle(arg)
+            .expect("Handle setrange unexpected error");
+        assert_eq!(encoding("new"), Value::BulkString("raw".into()));
+    }
+}
@@ -12,7 +12,7 @@ impl CommandArgParser for PingArg {
     /// PING [msg]
     fn parse_arg(iter: &mut std::slice::Iter<'_, Value>) -> Result<Self, ParseCommandError> {
         let args = consume_args_from_iter(iter, 0, 1)?;
-        let msg = args.get(0).map(|bs| bs.clone());
+        let msg = args.first().cloned();
 
         Ok(PingArg { msg })
     }
@@ -37,8 +37,8 @@ impl Ping {
     /// Returns PING as a Command in the form of Value.
     pub fn command_value(arg: PingArg) -> Value {
         let mut parts = vec![Value::BulkString("PING".into())];
-        if arg.msg.is_some() {
-            parts.push(Value::BulkString(arg.msg.unwrap()));
+        if let Some(msg) = arg.msg {
+            parts.push(Value::BulkString(msg));
         }
         Value::Array(Array::new(parts))
     }
@@ -115,23 +115,32 @@ mod test {
 
 #[cfg(test)]
 mod client_test {
-    use super::super::super::session::MockResponder;
+    use super::super::super::test_util::{self, ScriptedResponder};
     use super::*;
 
     fn new_ping_responder(
         expected_msg: Option<BulkString>,
         returned_value: Value,
-    ) -> MockResponder {
+    ) -> ScriptedResponder {
         let mut values = vec![Value::BulkString("PING".into())];
-        if expected_msg.is_some() {
-            values.push(Value::BulkString(expected_msg.unwrap()))
+        if let Some(msg) = expected_msg {
+            values.push(Value::BulkString(msg))
         }
-        let expected_req = Request::new(Value::Array(Array::new(values)));
 
-        MockResponder {
-            expected_req,
-            returned_resp: returned_value.into(),
-        }
+        ScriptedResponder::new().expect(Value::Array(Array::new(values)), returned_value)
+    }
+
+    #[tokio::test]
+    async fn ping_sends_command() {
+        let mut responder = ScriptedResponder::new()
+            .expect(test_util::request(["PING"]), test_util::simple("PONG"));
+        let mut client = Ping::client(&mut responder);
+
+        client
+            .ping(PingArg { msg: None })
+            .await
+            .expect("Unexpected ping error");
+        assert!(responder.is_done());
     }
 
     #[tokio::test]
@@ -0,0 +1,230 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use thiserror::Error;
+
+use super::super::clients::ClientState;
+use super::super::clock::Clock;
+use super::super::rdb;
+use super::super::replication::{ReplicationState, StreamGuard};
+use super::super::reply::Reply;
+use super::super::resp::{BulkString, SimpleString, Value};
+use super::super::store::Store;
+use super::{bulk_string_to_string, consume_args_from_iter, CommandArgParser, ParseCommandError};
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum PsyncError {
+    #[error("PSYNC is not supported by replicas")]
+    Replica,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct PsyncArg {
+    /// Replication ID the replica last synced with, `?` if it never synced.
+    pub repl_id: String,
+
+    /// Offset of the first byte of the stream the replica wants to continue from, one past
+    /// the last it has, or -1 to ask for a full resync.
+    pub offset: i64,
+}
+
+impl CommandArgParser for PsyncArg {
+    /// PSYNC replicationid offset
+    fn parse_arg(iter: &mut std::slice::Iter<'_, Value>) -> Result<Self, ParseCommandError> {
+        let args = consume_args_from_iter(iter, 2, 0)?;
+        let repl_id = bulk_string_to_string(&args[0])?;
+        let offset = bulk_string_to_string(&args[1])?
+            .parse::<i64>()
+            .map_err(|_| ParseCommandError::InvalidArgument(Value::BulkString(args[1].clone())))?;
+
+        Ok(Self { repl_id, offset })
+    }
+}
+
+pub struct Psync;
+
+impl Psync {
+    /// Returns an instance of PSYNC command handler.
+    pub fn handler(
+        replication: Option<Arc<ReplicationState>>,
+        map: Arc<Store>,
+        clock: Arc<dyn Clock>,
+    ) -> PsyncHandler {
+        PsyncHandler {
+            replication,
+            map,
+            clock,
+        }
+    }
+
+    /// Returns PSYNC as a Command in the form of Value.
+    pub fn command_value(arg: PsyncArg) -> Value {
+        let v = vec![
+            Value::BulkString("PSYNC".into()),
+            Value::BulkString(BulkString::from(arg.repl_id)),
+            Value::BulkString(BulkString::from(arg.offset.to_string())),
+        ];
+        Value::Array(v.into())
+    }
+}
+
+#[derive(Debug)]
+pub struct PsyncHandler {
+    /// State of this master, `None` on a replica.
+    replication: Option<Arc<ReplicationState>>,
+    map: Arc<Store>,
+    clock: Arc<dyn Clock>,
+}
+
+impl PsyncHandler {
+    /// Replies `+CONTINUE <replid>` followed by the part of the replication stream the
+    /// replica missed, if it asks to continue this master's history from an offset the
+    /// backlog still reaches back to. Otherwise replies `+FULLRESYNC <replid> <offset>`
+    /// followed by the RDB file of the dataset. Either way the client is marked as a replica.
+    ///
+    /// The replica is attached to the replication stream along with the reply, so the writes
+    /// propagated after it follow. Then nothing is left to reply.
+    pub fn handle(&self, arg: PsyncArg, client: &mut ClientState) -> Result<Reply, PsyncError> {
+        let replication = self.replication.as_ref().ok_or(PsyncError::Replica)?;
+        let repl_id = replication.repl_id();
+        let sync = |stream: &StreamGuard| {
+            // The replica has the stream up to the byte before the one it asks for.
+            let backlog = (arg.repl_id == repl_id)
+                .then(|| u64::try_from(arg.offset - 1).ok())
+                .flatten()
+                .and_then(|offset| stream.backlog(offset));
+            if let Some(backlog) = backlog {
+                let header = format!("CONTINUE {repl_id}");
+                return Reply::Backlog(Value::SimpleString(SimpleString::from(header)), backlog);
+            }
+            let offset = stream.offset();
+            let rdb = rdb::encode(
+                self.map.snapshot().into_entries(),
+                Some((repl_id, offset)),
+                self.clock.now(),
+            );
+            let header = format!("FULLRESYNC {repl_id} {offset}");
+            Reply::Payload(Value::SimpleString(SimpleString::from(header)), rdb)
+        };
+        client.flags.replica = true;
+        // Replicas are told apart by the address they listen to, for FAILOVER TO.
+        let addr = client
+            .addr()
+            .zip(client.listening_port)
+            .map(|(addr, port)| SocketAddr::new(addr.ip(), port));
+
+        match client.link() {
+            Some(link) => {
+                replication.attach(link, addr, sync);
+                Ok(Reply::Nothing)
+            }
+            // Not a connection, so there is nothing to stream to.
+            None => Ok(sync(&replication.stream())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::Command;
+    use super::*;
+
+    #[test]
+    fn parse_psync() {
+        let arg = PsyncArg {
+            repl_id: "?".to_string(),
+            offset: -1,
+        };
+        match Command::try_from(Psync::command_value(arg.clone())) {
+            Ok(Command::Psync(parsed)) => assert_eq!(parsed, arg),
+            other => panic!("Unexpected parse result {other:?}"),
+        }
+
+        let value = Value::Array(
+            vec![
+                Value::BulkString("PSYNC".into()),
+                Value::BulkString("?".into()),
+                Value::BulkString("one".into()),
+            ]
+            .into(),
+        );
+        assert!(matches!(
+            Command::try_from(value),
+            Err(ParseCommandError::InvalidArgument(_))
+        ));
+    }
+}
+
+#[cfg(test)]
+mod handler_test {
+    use super::super::super::clients::ClientRegistry;
+    use super::super::super::clock;
+    use super::super::super::handler::StoredData;
+    use super::super::super::key::Key;
+    use super::*;
+
+    #[test]
+    fn handle_full_resync() {
+        let map = Store::from_iter([(Key::from("key"), StoredData::new("value".into(), None))]);
+        let replication = Arc::new(ReplicationState::new());
+        let handler = Psync::handler(Some(replication.clone()), Arc::new(map), clock::system());
+        let arg = PsyncArg {
+            repl_id: "?".to_string(),
+            offset: -1,
+        };
+
+        let mut client = ClientState::new(1, None);
+        let Ok(Reply::Payload(header, payload)) = handler.handle(arg.clone(), &mut client) else {
+            panic!("Handle psync returned no payload");
+        };
+        assert_eq!(
+            header,
+            Value::SimpleString(format!("FULLRESYNC {} 0", replication.repl_id()).into())
+        );
+        let dataset = rdb::parse(&payload, clock::system().now()).expect("Parse unexpected error");
+        assert_eq!(dataset.entries.len(), 1);
+        assert_eq!(dataset.repl_id.as_deref(), Some(replication.repl_id()));
+        assert!(client.flags.replica);
+
+        // A connected replica gets the reply in its queue, ahead of the writes that follow.
+        let registry = Arc::new(ClientRegistry::new());
+        let mut replica = registry.register(None, None);
+        let mut rx = replica.take_receiver().unwrap();
+        let reply = handler
+            .handle(arg.clone(), &mut replica.state().lock())
+            .expect("Handle psync unexpected error");
+        assert!(matches!(reply, Reply::Nothing));
+        assert!(matches!(rx.try_recv(), Ok(Reply::Payload(..))));
+
+        // A replica that has this master's history up to its current offset continues.
+        let arg = PsyncArg {
+            repl_id: replication.repl_id().to_string(),
+            offset: 1,
+        };
+        let reply = handler
+            .handle(arg.clone(), &mut ClientState::new(2, None))
+            .expect("Handle psync unexpected error");
+        assert!(matches!(
+            reply,
+            Reply::Backlog(Value::SimpleString(header), backlog)
+                if header.as_str() == format!("CONTINUE {}", replication.repl_id())
+                    && backlog.is_empty()
+        ));
+
+        // One past it, or with another history, gets a full resync.
+        for (repl_id, offset) in [(replication.repl_id(), 2), ("other", 1)] {
+            let arg = PsyncArg {
+                repl_id: repl_id.to_string(),
+                offset,
+            };
+            let reply = handler.handle(arg, &mut ClientState::new(2, None));
+            assert!(matches!(reply, Ok(Reply::Payload(..))));
+        }
+
+        let handler = Psync::handler(None, Arc::new(Store::default()), clock::system());
+        let err = handler
+            .handle(arg, &mut ClientState::new(2, None))
+            .expect_err("Handle psync no error");
+        assert_eq!(err, PsyncError::Replica);
+    }
+}
@@ -0,0 +1,112 @@
+use std::sync::Arc;
+
+use super::super::clock::Clock;
+use super::super::resp::{BulkString, Integer, Value};
+use super::super::store::Store;
+use super::{consume_args_from_iter, CommandArgParser, ParseCommandError};
+
+#[derive(Debug, Clone)]
+pub struct PttlArg {
+    pub key: BulkString,
+}
+
+impl CommandArgParser for PttlArg {
+    /// PTTL key
+    fn parse_arg(iter: &mut std::slice::Iter<'_, Value>) -> Result<Self, ParseCommandError> {
+        let args = consume_args_from_iter(iter, 1, 0)?;
+        let key = args.first().unwrap().clone();
+
+        Ok(Self { key })
+    }
+}
+
+pub struct Pttl;
+
+impl Pttl {
+    /// Returns an instance of PTTL command handler.
+    pub fn handler(map: Arc<Store>, clock: Arc<dyn Clock>) -> PttlHandler {
+        PttlHandler { map, clock }
+    }
+
+    /// Returns PTTL as a Command in the form of Value.
+    pub fn command_value(arg: PttlArg) -> Value {
+        let v = vec![Value::BulkString("PTTL".into()), Value::BulkString(arg.key)];
+        Value::Array(v.into())
+    }
+}
+
+#[derive(Debug)]
+pub struct PttlHandler {
+    map: Arc<Store>,
+    clock: Arc<dyn Clock>,
+}
+
+impl PttlHandler {
+    /// Returns the milliseconds the key has left to live, -1 if it has no expiry and -2 if it
+    /// doesn't exist. Expired keys are left to GET or the active expire cycle to remove.
+    pub fn handle(&self, arg: PttlArg) -> Value {
+        let key = arg.key.as_bytes().unwrap_or_default();
+        let now = self.clock.now();
+        let ttl = match self.map.read(key).get(key) {
+            Some(data) if data.expired_at(now) => -2,
+            Some(data) => match data.deadline {
+                Some(deadline) => {
+                    deadline.duration_since(now).unwrap_or_default().as_millis() as i64
+                }
+                None => -1,
+            },
+            None => -2,
+        };
+        Value::Integer(Integer::new(ttl))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn command() {
+        let val = Pttl::command_value(PttlArg { key: "key".into() });
+
+        assert_eq!(
+            val.array().unwrap().values().unwrap().to_vec(),
+            vec![
+                Value::BulkString("PTTL".into()),
+                Value::BulkString("key".into()),
+            ]
+        )
+    }
+}
+
+#[cfg(test)]
+mod handler_test {
+    use std::time::Duration;
+
+    use super::super::super::clock::TestClock;
+    use super::super::super::handler::StoredData;
+    use super::super::super::key::Key;
+    use super::*;
+
+    #[test]
+    fn handle_pttl() {
+        let clock = Arc::new(TestClock::default());
+        let deadline = clock.now() + Duration::from_millis(1500);
+        let map = Arc::new(Store::from_iter([
+            (Key::from("forever"), StoredData::new("a".into(), None)),
+            (
+                Key::from("soon"),
+                StoredData::new("b".into(), Some(deadline)),
+            ),
+        ]));
+        let handler = Pttl::handler(map, clock.clone());
+        let pttl = |key: &str| handler.handle(PttlArg { key: key.into() });
+
+        assert_eq!(pttl("forever"), Value::Integer(Integer::new(-1)));
+        assert_eq!(pttl("missing"), Value::Integer(Integer::new(-2)));
+        clock.advance(Duration::from_millis(500));
+        assert_eq!(pttl("soon"), Value::Integer(Integer::new(1000)));
+        clock.advance(Duration::from_secs(2));
+        assert_eq!(pttl("soon"), Value::Integer(Integer::new(-2)));
+    }
+}
@@ -1,7 +1,6 @@
-use std::fmt::Display;
-
 use super::super::client::ClientError;
-use super::super::resp::{Array, BulkString, SimpleString, Value};
+use super::super::clients::ClientState;
+use super::super::resp::{BulkString, SimpleString, Value};
 use super::super::session::{Request, Responder, Response};
 use super::{consume_args_from_iter, CommandArgParser, ParseCommandError};
 
@@ -9,6 +8,15 @@ use super::{consume_args_from_iter, CommandArgParser, ParseCommandError};
 pub enum ReplConfArgConfig {
     ListeningPort(u16),
     Capabilities(String),
+
+    /// Replica priority of the replica, see replica-priority.
+    Priority(u32),
+
+    /// Offset of the replication stream a replica has processed.
+    Ack(u64),
+
+    /// Asks a replica to acknowledge its offset, always sent as `GETACK *`.
+    GetAck,
 }
 
 impl ReplConfArgConfig {
@@ -19,6 +27,15 @@ impl ReplConfArgConfig {
                 BulkString::from(port.to_string()),
             ],
             Self::Capabilities(s) => vec![BulkString::from("capa"), BulkString::from(s.clone())],
+            Self::Priority(priority) => vec![
+                BulkString::from("priority"),
+                BulkString::from(priority.to_string()),
+            ],
+            Self::Ack(offset) => vec![
+                BulkString::from("ACK"),
+                BulkString::from(offset.to_string()),
+            ],
+            Self::GetAck => vec![BulkString::from("GETACK"), BulkString::from("*")],
         }
     }
 }
@@ -31,7 +48,7 @@ pub struct ReplConfArg {
 impl CommandArgParser for ReplConfArg {
     fn parse_arg(iter: &mut std::slice::Iter<'_, Value>) -> Result<Self, ParseCommandError> {
         let args = consume_args_from_iter(iter, 2, 0)?;
-        let first = args.get(0).unwrap();
+        let first = args.first().unwrap();
         let second = args.get(1).unwrap();
 
         let key = first
@@ -59,6 +76,25 @@ impl CommandArgParser for ReplConfArg {
             "capa" => Ok(Self {
                 config: ReplConfArgConfig::Capabilities(value),
             }),
+            "priority" => {
+                let priority = value.parse::<u32>().map_err(|_| {
+                    ParseCommandError::InvalidArgument(Value::BulkString(second.clone()))
+                })?;
+                Ok(Self {
+                    config: ReplConfArgConfig::Priority(priority),
+                })
+            }
+            "ack" => {
+                let offset = value.parse::<u64>().map_err(|_| {
+                    ParseCommandError::InvalidArgument(Value::BulkString(second.clone()))
+                })?;
+                Ok(Self {
+                    config: ReplConfArgConfig::Ack(offset),
+                })
+            }
+            "getack" => Ok(Self {
+                config: ReplConfArgConfig::GetAck,
+            }),
             _ => Err(ParseCommandError::InvalidArgument(Value::BulkString(
                 first.clone(),
             ))),
@@ -121,3 +157,62 @@ where
 }
 
 pub struct ReplConfHandler;
+
+impl ReplConfHandler {
+    /// Records what the replica tells about itself during the handshake. Acknowledged
+    /// offsets aren't replied to, so they aren't handled here.
+    pub fn handle(&self, arg: ReplConfArg, client: &mut ClientState) -> Value {
+        match arg.config {
+            ReplConfArgConfig::ListeningPort(port) => client.listening_port = Some(port),
+            ReplConfArgConfig::Priority(priority) => client.replica_priority = Some(priority),
+            _ => (),
+        }
+        Value::SimpleString(SimpleString::from("OK"))
+    }
+}
+
+#[cfg(all(test, feature = "replication"))]
+mod test {
+    use super::super::Command;
+    use super::*;
+
+    #[test]
+    fn parse_priority() {
+        let arg = ReplConfArg {
+            config: ReplConfArgConfig::Priority(0),
+        };
+        match Command::try_from(ReplConf::command_value(arg.clone())) {
+            Ok(Command::ReplConf(parsed)) => assert_eq!(parsed, arg),
+            other => panic!("Unexpected parse result {other:?}"),
+        }
+
+        let mut client = ClientState::new(1, None);
+        let reply = ReplConf::handler().handle(arg, &mut client);
+        assert_eq!(reply, Value::SimpleString(SimpleString::from("OK")));
+        assert_eq!(client.replica_priority, Some(0));
+    }
+
+    #[test]
+    fn parse_getack_and_ack() {
+        for config in [ReplConfArgConfig::GetAck, ReplConfArgConfig::Ack(37)] {
+            let arg = ReplConfArg { config };
+            match Command::try_from(ReplConf::command_value(arg.clone())) {
+                Ok(Command::ReplConf(parsed)) => assert_eq!(parsed, arg),
+                other => panic!("Unexpected parse result {other:?}"),
+            }
+        }
+
+        let value = Value::Array(
+            vec![
+                Value::BulkString("REPLCONF".into()),
+                Value::BulkString("ACK".into()),
+                Value::BulkString("-1".into()),
+            ]
+            .into(),
+        );
+        assert!(matches!(
+            Command::try_from(value),
+            Err(ParseCommandError::InvalidArgument(_))
+        ));
+    }
+}
@@ -0,0 +1,176 @@
+use thiserror::Error;
+
+use super::super::config::HostPort;
+use super::super::resp::{BulkString, SimpleString, Value};
+use super::{bulk_string_to_string, consume_args_from_iter, CommandArgParser, ParseCommandError};
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ReplicaOfError {
+    #[error("REPLICAOF not allowed in cluster mode.")]
+    Cluster,
+
+    #[error("REPLICAOF not allowed while failing over.")]
+    Failover,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ReplicaOfArg {
+    /// Master to follow, `None` for `NO ONE` to serve as a master.
+    pub master: Option<HostPort>,
+}
+
+impl CommandArgParser for ReplicaOfArg {
+    /// REPLICAOF host port
+    /// REPLICAOF NO ONE
+    fn parse_arg(iter: &mut std::slice::Iter<'_, Value>) -> Result<Self, ParseCommandError> {
+        let args = consume_args_from_iter(iter, 2, 0)?;
+        let host = bulk_string_to_string(&args[0])?;
+        let port = bulk_string_to_string(&args[1])?;
+        if host.eq_ignore_ascii_case("no") && port.eq_ignore_ascii_case("one") {
+            return Ok(Self { master: None });
+        }
+
+        let port = port
+            .parse()
+            .map_err(|_| ParseCommandError::InvalidArgument(Value::BulkString(args[1].clone())))?;
+        Ok(Self {
+            master: Some(HostPort::new(host, port)),
+        })
+    }
+}
+
+pub struct ReplicaOf;
+
+impl ReplicaOf {
+    /// Returns an instance of REPLICAOF command handler.
+    pub fn handler(master: Option<HostPort>) -> ReplicaOfHandler {
+        ReplicaOfHandler {
+            master,
+            failing_over: false,
+            cluster: false,
+        }
+    }
+
+    /// Returns REPLICAOF as a Command in the form of Value.
+    pub fn command_value(arg: ReplicaOfArg) -> Value {
+        let (host, port) = match arg.master {
+            Some(master) => (master.host, master.port.to_string()),
+            None => ("NO".to_string(), "ONE".to_string()),
+        };
+        let v = vec![
+            Value::BulkString("REPLICAOF".into()),
+            Value::BulkString(BulkString::from(host)),
+            Value::BulkString(BulkString::from(port)),
+        ];
+        Value::Array(v.into())
+    }
+}
+
+#[derive(Debug)]
+pub struct ReplicaOfHandler {
+    /// Master this server follows, `None` on a master.
+    master: Option<HostPort>,
+    failing_over: bool,
+    cluster: bool,
+}
+
+impl ReplicaOfHandler {
+    /// Refuses to switch roles while a FAILOVER is in progress.
+    pub fn with_failing_over(mut self, failing_over: bool) -> Self {
+        self.failing_over = failing_over;
+        self
+    }
+
+    /// Refuses to switch roles in cluster mode, where CLUSTER REPLICATE is used instead.
+    pub fn with_cluster(mut self, cluster: bool) -> Self {
+        self.cluster = cluster;
+        self
+    }
+
+    /// Makes this server follow the master with `follow`, or serve as a master for
+    /// `NO ONE`. Following the master it already follows changes nothing.
+    pub fn handle(
+        &self,
+        arg: ReplicaOfArg,
+        follow: impl FnOnce(Option<HostPort>),
+    ) -> Result<Value, ReplicaOfError> {
+        if self.cluster {
+            return Err(ReplicaOfError::Cluster);
+        }
+        if self.failing_over {
+            return Err(ReplicaOfError::Failover);
+        }
+        if arg.master.is_some() && arg.master == self.master {
+            return Ok(Value::SimpleString(SimpleString::from(
+                "OK Already connected to specified master",
+            )));
+        }
+
+        follow(arg.master);
+        Ok(Value::SimpleString(SimpleString::from("OK")))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::super::test_util;
+    use super::super::Command;
+    use super::*;
+
+    #[test]
+    fn parse_replicaof() {
+        let arg = ReplicaOfArg {
+            master: Some(HostPort::new("127.0.0.1", 6380)),
+        };
+        match Command::try_from(ReplicaOf::command_value(arg.clone())) {
+            Ok(Command::ReplicaOf(parsed)) => assert_eq!(parsed, arg),
+            res => panic!("Wrong parse result for replicaof: {res:?}"),
+        }
+
+        match Command::try_from(test_util::command(["REPLICAOF", "no", "one"])) {
+            Ok(Command::ReplicaOf(parsed)) => assert_eq!(parsed.master, None),
+            res => panic!("Wrong parse result for replicaof: {res:?}"),
+        }
+        assert!(Command::try_from(test_util::command(["REPLICAOF", "host", "port"])).is_err());
+    }
+}
+
+#[cfg(test)]
+mod handler_test {
+    use super::*;
+
+    #[test]
+    fn handle_replicaof() {
+        let master = HostPort::new("127.0.0.1", 6380);
+        let arg = ReplicaOfArg {
+            master: Some(master.clone()),
+        };
+        let mut followed = vec![];
+
+        let resp = ReplicaOf::handler(None)
+            .handle(arg.clone(), |master| followed.push(master))
+            .expect("Handle replicaof unexpected error");
+        assert_eq!(resp, Value::SimpleString(SimpleString::from("OK")));
+        let resp = ReplicaOf::handler(Some(master.clone()))
+            .handle(arg.clone(), |master| followed.push(master))
+            .expect("Handle replicaof unexpected error");
+        assert_eq!(
+            resp,
+            Value::SimpleString(SimpleString::from(
+                "OK Already connected to specified master"
+            ))
+        );
+        ReplicaOf::handler(Some(master.clone()))
+            .handle(ReplicaOfArg { master: None }, |master| {
+                followed.push(master)
+            })
+            .expect("Handle replicaof unexpected error");
+        assert_eq!(followed, vec![Some(master), None]);
+
+        let err = ReplicaOf::handler(None)
+            .with_failing_over(true)
+            .handle(arg, |_| panic!("Followed while failing over"))
+            .expect_err("Handle replicaof no error");
+        assert_eq!(err, ReplicaOfError::Failover);
+    }
+}
@@ -0,0 +1,180 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use thiserror::Error;
+use tracing::info;
+
+use super::super::clock::Clock;
+use super::super::config::ServerConfig;
+use super::super::persistence::PersistenceState;
+use super::super::rdb;
+use super::super::replication::Role;
+use super::super::resp::{SimpleString, Value};
+use super::super::store::{Snapshot, Store};
+use super::{consume_args_from_iter, CommandArgParser, ParseCommandError};
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum SaveError {
+    #[error("Background save already in progress")]
+    InProgress,
+
+    #[error("Error saving DB on disk: {0}")]
+    Failed(String),
+
+    /// The RDB file had data this server left out when loading it, e.g. keys of other
+    /// databases, which saving over it would lose.
+    #[error("Refusing to overwrite an RDB file with data that wasn't loaded")]
+    PartialLoad,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct SaveArg;
+
+impl CommandArgParser for SaveArg {
+    /// SAVE
+    fn parse_arg(iter: &mut std::slice::Iter<'_, Value>) -> Result<Self, ParseCommandError> {
+        consume_args_from_iter(iter, 0, 0)?;
+        Ok(Self)
+    }
+}
+
+pub struct Save;
+
+impl Save {
+    /// Returns an instance of SAVE command handler.
+    pub fn handler(
+        map: Arc<Store>,
+        clock: Arc<dyn Clock>,
+        config: Arc<ServerConfig>,
+        persistence: Arc<PersistenceState>,
+        role: Option<Role>,
+    ) -> SaveHandler {
+        SaveHandler {
+            map,
+            clock,
+            config,
+            persistence,
+            role,
+        }
+    }
+
+    /// Returns SAVE as a Command in the form of Value.
+    pub fn command_value(_arg: SaveArg) -> Value {
+        Value::Array(vec![Value::BulkString("SAVE".into())].into())
+    }
+}
+
+#[derive(Debug)]
+pub struct SaveHandler {
+    map: Arc<Store>,
+    clock: Arc<dyn Clock>,
+    config: Arc<ServerConfig>,
+    persistence: Arc<PersistenceState>,
+
+    /// Side of replication the server is on, `None` if it doesn't replicate.
+    role: Option<Role>,
+}
+
+impl SaveHandler {
+    /// Writes the dataset to the RDB file `dbfilename` in `dir`, replying once it is on disk.
+    /// The file records the replication ID and offset of the dataset, for a restart to
+    /// continue replicating from. Refused while a background save runs, since both would
+    /// write the same file, and if the file loaded had data left out.
+    pub fn handle(&self, _arg: SaveArg) -> Result<Value, SaveError> {
+        if self.persistence.bgsave_in_progress() {
+            return Err(SaveError::InProgress);
+        }
+        if self.persistence.partial_load() {
+            return Err(SaveError::PartialLoad);
+        }
+        let dirty = self.persistence.dirty();
+        let path = rdb_path(&self.config);
+        let (snapshot, repl) = snapshot(&self.map, self.role.as_ref());
+        let repl = repl
+            .as_ref()
+            .map(|(repl_id, offset)| (repl_id.as_str(), *offset));
+        let saved = rdb::save(snapshot.into_entries(), repl, &path, self.clock.now());
+        self.persistence.set_last_bgsave_ok(saved.is_ok());
+        saved.map_err(|e| SaveError::Failed(e.to_string()))?;
+        self.persistence.mark_saved(dirty);
+        info!("DB saved on disk to {}", path.display());
+
+        Ok(Value::SimpleString(SimpleString::from("OK")))
+    }
+}
+
+/// Takes a snapshot of the dataset, along with the replication ID and offset it reflects
+/// if the server replicates.
+pub(super) fn snapshot(map: &Store, role: Option<&Role>) -> (Snapshot, Option<(String, u64)>) {
+    match role {
+        Some(role) => role.snapshot(map),
+        None => (map.snapshot(), None),
+    }
+}
+
+/// Returns where the RDB file is saved, `dbfilename` in `dir`.
+pub(super) fn rdb_path(config: &ServerConfig) -> PathBuf {
+    let config = config.read();
+    config.dir.join(&config.dbfilename)
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::Command;
+    use super::*;
+
+    #[test]
+    fn parse_save() {
+        match Command::try_from(Save::command_value(SaveArg)) {
+            Ok(Command::Save(parsed)) => assert_eq!(parsed, SaveArg),
+            other => panic!("Unexpected parse result {other:?}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod handler_test {
+    use super::super::super::clock;
+    use super::super::super::config::ConfigValues;
+    use super::super::super::handler::StoredData;
+    use super::super::super::key::Key;
+    use super::super::super::replication::ReplicationState;
+    use super::*;
+
+    #[test]
+    fn handle_save() {
+        let dir = std::env::temp_dir().join(format!("save-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let config = Arc::new(ServerConfig::new(ConfigValues {
+            dir: dir.clone(),
+            ..Default::default()
+        }));
+        let map = Store::from_iter([(Key::from("key"), StoredData::new("value".into(), None))]);
+        let persistence = Arc::new(PersistenceState::new());
+        persistence.incr_dirty(1);
+        let replication = Arc::new(ReplicationState::new());
+        let handler = Save::handler(
+            Arc::new(map),
+            clock::system(),
+            config.clone(),
+            persistence.clone(),
+            Some(Role::Master(replication.clone())),
+        );
+
+        let resp = handler
+            .handle(SaveArg)
+            .expect("Handle save unexpected error");
+        assert_eq!(resp, Value::SimpleString(SimpleString::from("OK")));
+        assert_eq!(persistence.dirty(), 0);
+        let saved = std::fs::read(rdb_path(&config)).expect("Read unexpected error");
+        let dataset = rdb::parse(&saved, clock::system().now()).expect("Parse unexpected error");
+        assert_eq!(dataset.entries.len(), 1);
+        assert_eq!(dataset.repl_id.as_deref(), Some(replication.repl_id()));
+        assert_eq!(dataset.repl_offset, Some(0));
+
+        persistence.set_bgsave_in_progress(true);
+        let err = handler.handle(SaveArg).expect_err("Handle save no error");
+        assert_eq!(err, SaveError::InProgress);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,48 +1,119 @@
-use std::collections::hash_map::Entry;
-use std::collections::HashMap;
-use std::sync::{Arc, RwLock};
-use std::time::{Duration, SystemTime};
+use std::sync::Arc;
+use std::time::Duration;
 
-use super::super::client::ClientError;
-use super::super::handler::StoredData;
+use super::super::clock::Clock;
+use super::super::events::{KeyEvent, KeyEvents};
+use super::super::handler::{HandleCommandError, StoredData};
+use super::super::key::Key;
+use super::super::lazyfree::LazyFree;
 use super::super::resp::{Array, BulkString, SimpleString, Value};
-use super::super::session::{Request, Responder, Response};
+use super::super::store::Store;
 use super::{
-    bulk_string_to_string, bulk_string_to_uint64, consume_args_from_iter, CommandArgParser,
-    ParseCommandError,
+    bulk_string_to_int64, bulk_string_to_string, consume_variadic_args_from_iter, CommandArgParser,
+    ExpireTime, ParseCommandError,
 };
 
+/// How SET treats the deadline of the key.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SetExpiry {
+    /// EX, PX, EXAT or PXAT, named after the EXPIRE variant taking the same time.
+    Time(ExpireTime),
+    /// KEEPTTL: keep the deadline the key already has.
+    KeepTtl,
+}
+
+impl From<Duration> for SetExpiry {
+    /// PX, rounded down to milliseconds.
+    fn from(expiry: Duration) -> Self {
+        Self::Time(ExpireTime::Millis(expiry.as_millis() as i64))
+    }
+}
+
+/// Only set the key if it doesn't (NX) or does (XX) exist already.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SetCondition {
+    Nx,
+    Xx,
+}
+
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct SetArg {
     pub key: BulkString,
     pub value: BulkString,
-    pub expiry: Option<Duration>,
+    pub expiry: Option<SetExpiry>,
+    pub condition: Option<SetCondition>,
+    /// Reply with the value the key held before, rather than OK.
+    pub get: bool,
 }
 
 impl CommandArgParser for SetArg {
-    /// SET key value [px milliseconds]
+    /// SET key value [NX | XX] [GET] [EX seconds | PX milliseconds |
+    ///   EXAT unix-time-seconds | PXAT unix-time-milliseconds | KEEPTTL]
     fn parse_arg(iter: &mut std::slice::Iter<'_, Value>) -> Result<Self, ParseCommandError> {
-        let args = consume_args_from_iter(iter, 2, 2)?;
-        let key = args.get(0).unwrap().clone();
-        let value = args.get(1).unwrap().clone();
-
-        let expiry = match args.get(2) {
-            Some(arg) => {
-                if bulk_string_to_string(arg)?.eq_ignore_ascii_case("px") {
-                    // Has expiry defined as `px milliseconds`
-                    Some(Duration::from_millis(bulk_string_to_uint64(
-                        args.get(3).ok_or(ParseCommandError::WrongNumArgs)?,
-                    )?))
-                } else {
-                    return Err(ParseCommandError::InvalidArgument(Value::BulkString(
-                        arg.clone(),
-                    )));
+        let args = consume_variadic_args_from_iter(iter, 2)?;
+        let mut arg = Self {
+            key: args[0].clone(),
+            value: args[1].clone(),
+            expiry: None,
+            condition: None,
+            get: false,
+        };
+
+        let syntax_error = |option: &BulkString| {
+            ParseCommandError::InvalidArgument(Value::BulkString(option.clone()))
+        };
+        let mut options = args[2..].iter();
+        while let Some(option) = options.next() {
+            let name = bulk_string_to_string(option)?.to_lowercase();
+            let time: Option<fn(i64) -> ExpireTime> = match name.as_str() {
+                "ex" => Some(ExpireTime::Seconds),
+                "px" => Some(ExpireTime::Millis),
+                "exat" => Some(ExpireTime::UnixSeconds),
+                "pxat" => Some(ExpireTime::UnixMillis),
+                _ => None,
+            };
+
+            // An option can be repeated, but not given along with one it rules out.
+            match (name.as_str(), time) {
+                ("nx" | "xx", _) => {
+                    let condition = if name == "nx" {
+                        SetCondition::Nx
+                    } else {
+                        SetCondition::Xx
+                    };
+                    if arg.condition.is_some_and(|c| c != condition) {
+                        return Err(syntax_error(option));
+                    }
+                    arg.condition = Some(condition);
+                }
+                ("get", _) => arg.get = true,
+                ("keepttl", _) => {
+                    if arg.expiry.is_some_and(|e| e != SetExpiry::KeepTtl) {
+                        return Err(syntax_error(option));
+                    }
+                    arg.expiry = Some(SetExpiry::KeepTtl);
+                }
+                (_, Some(time)) => {
+                    let value = options.next().ok_or_else(|| syntax_error(option))?;
+                    let value = bulk_string_to_int64(value)?;
+                    if value <= 0 {
+                        return Err(ParseCommandError::InvalidExpireTime("set"));
+                    }
+                    let time = time(value);
+                    let same_option = |expiry| match expiry {
+                        SetExpiry::Time(other) => other.command_name() == time.command_name(),
+                        SetExpiry::KeepTtl => false,
+                    };
+                    if arg.expiry.is_some_and(|expiry| !same_option(expiry)) {
+                        return Err(syntax_error(option));
+                    }
+                    arg.expiry = Some(SetExpiry::Time(time));
                 }
+                _ => return Err(syntax_error(option)),
             }
-            None => None,
-        };
+        }
 
-        Ok(Self { key, value, expiry })
+        Ok(arg)
     }
 }
 
@@ -55,8 +126,14 @@ impl Set {
     }
 
     /// Returns an instance of SET command handler.
-    pub fn handler(map: Arc<RwLock<HashMap<BulkString, StoredData>>>) -> SetHandler {
-        SetHandler::new(map)
+    ///
+    /// Overwritten values are freed on `lazyfree` if given.
+    pub fn handler(
+        map: Arc<Store>,
+        clock: Arc<dyn Clock>,
+        lazyfree: Option<Arc<LazyFree>>,
+    ) -> SetHandler {
+        SetHandler::new(map, clock, lazyfree)
     }
 
     /// Returns SET as a Command in the form of Value.
@@ -66,10 +143,27 @@ impl Set {
             Value::BulkString(arg.key),
             Value::BulkString(arg.value),
         ];
-        if arg.expiry.is_some() {
-            let expiry = arg.expiry.unwrap().as_millis().to_string();
-            parts.push(Value::BulkString("px".into()));
-            parts.push(Value::BulkString(expiry.into()));
+        match arg.condition {
+            Some(SetCondition::Nx) => parts.push(Value::BulkString("nx".into())),
+            Some(SetCondition::Xx) => parts.push(Value::BulkString("xx".into())),
+            None => {}
+        }
+        if arg.get {
+            parts.push(Value::BulkString("get".into()));
+        }
+        match arg.expiry {
+            Some(SetExpiry::Time(time)) => {
+                let (option, time) = match time {
+                    ExpireTime::Seconds(time) => ("ex", time),
+                    ExpireTime::Millis(time) => ("px", time),
+                    ExpireTime::UnixSeconds(time) => ("exat", time),
+                    ExpireTime::UnixMillis(time) => ("pxat", time),
+                };
+                parts.push(Value::BulkString(option.into()));
+                parts.push(Value::BulkString(time.to_string().into()));
+            }
+            Some(SetExpiry::KeepTtl) => parts.push(Value::BulkString("keepttl".into())),
+            None => {}
         }
         Value::Array(Array::new(parts))
     }
@@ -79,43 +173,101 @@ pub struct SetClient;
 
 #[derive(Debug)]
 pub struct SetHandler {
-    map: Arc<RwLock<HashMap<BulkString, StoredData>>>,
+    map: Arc<Store>,
+    clock: Arc<dyn Clock>,
+    lazyfree: Option<Arc<LazyFree>>,
+    events: Option<Arc<KeyEvents>>,
+    written: bool,
 }
 
 impl SetHandler {
-    pub fn new(map: Arc<RwLock<HashMap<BulkString, StoredData>>>) -> Self {
-        Self { map }
+    pub fn new(map: Arc<Store>, clock: Arc<dyn Clock>, lazyfree: Option<Arc<LazyFree>>) -> Self {
+        Self {
+            map,
+            clock,
+            lazyfree,
+            events: None,
+            written: false,
+        }
+    }
+
+    /// Tells the listeners about keys set.
+    pub fn with_events(mut self, events: Arc<KeyEvents>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Returns whether the last SET handled wrote the key, which NX and XX may keep it from.
+    pub fn written(&self) -> bool {
+        self.written
     }
 
     /// Set key to hold the value.
-    /// If key already holds a value, it is overwritten.
-    /// Any previous time to live associated with the key is discarded on successful SET operation.
-    pub fn handle(&mut self, arg: SetArg) -> Value {
-        // Calculate deadline from expiry
+    /// If key already holds a value, it is overwritten, whatever its type.
+    /// Any previous time to live associated with the key is discarded on successful SET
+    /// operation, unless KEEPTTL is given.
+    ///
+    /// Replies with OK, or nil if NX or XX kept the key from being set. With GET, replies
+    /// with the old value instead, failing with WRONGTYPE if it isn't a string.
+    pub fn handle(&mut self, arg: SetArg) -> Result<Value, HandleCommandError> {
+        self.written = false;
+        let now = self.clock.now();
         let deadline = match arg.expiry {
-            Some(expiry) => SystemTime::now().checked_add(expiry),
-            None => None,
+            Some(SetExpiry::Time(time)) => Some(
+                time.deadline(now)
+                    .ok_or(ParseCommandError::InvalidExpireTime("set"))?,
+            ),
+            _ => None,
         };
-        let data = StoredData {
-            value: arg.value.clone(),
-            deadline,
+
+        // Write lock, so that the old value can't change before it is replaced.
+        let key = arg.key.as_bytes().unwrap_or_default();
+        let mut write_map = self.map.write(key);
+        let current = write_map.get(key).filter(|data| !data.expired_at(now));
+        let old_value = match current {
+            Some(data) if arg.get => Some(
+                data.value
+                    .as_string()
+                    .ok_or(HandleCommandError::WrongType)?
+                    .clone(),
+            ),
+            _ => None,
+        };
+        let allowed = match arg.condition {
+            Some(SetCondition::Nx) => current.is_none(),
+            Some(SetCondition::Xx) => current.is_some(),
+            None => true,
         };
 
-        // Write lock and insert data
-        let mut map = self.map.write().expect("RwLock poisoned");
-        match map.entry(arg.key.clone()) {
-            Entry::Occupied(mut e) => *e.get_mut() = data,
-            Entry::Vacant(e) => {
-                e.insert(data);
+        if allowed {
+            let deadline = match arg.expiry {
+                Some(SetExpiry::KeepTtl) => current.and_then(|data| data.deadline),
+                _ => deadline,
+            };
+            let data = StoredData::new(arg.value.into(), deadline);
+            let old = write_map.insert(Key::new(key), data);
+            drop(write_map);
+            if let (Some(lazyfree), Some(old)) = (&self.lazyfree, old) {
+                lazyfree.free(old);
             }
-        };
+            if let Some(events) = &self.events {
+                events.notify(key, KeyEvent::Set);
+            }
+            self.written = true;
+        }
 
-        Value::SimpleString(SimpleString::new("OK".into()))
+        Ok(match (arg.get, allowed) {
+            (true, _) => Value::BulkString(old_value.unwrap_or_else(BulkString::null)),
+            (false, true) => Value::SimpleString(SimpleString::new("OK".into())),
+            (false, false) => Value::BulkString(BulkString::null()),
+        })
     }
 }
 
 #[cfg(test)]
 mod test {
+    use super::super::super::test_util;
+    use super::super::Command;
     use super::*;
 
     #[test]
@@ -123,7 +275,9 @@ mod test {
         let val = Set::command_value(SetArg {
             key: "key".into(),
             value: "value".into(),
-            expiry: Some(Duration::from_millis(200)),
+            expiry: Some(Duration::from_millis(200).into()),
+            condition: None,
+            get: false,
         });
 
         assert_eq!(
@@ -137,46 +291,202 @@ mod test {
             ]
         )
     }
+
+    #[test]
+    fn parse_options() {
+        let parse = |args: &[&str]| {
+            let value = test_util::command(args.iter().copied());
+            SetArg::parse_arg(&mut value.array().unwrap().values().unwrap().iter())
+        };
+        let arg = SetArg {
+            key: "key".into(),
+            value: "value".into(),
+            expiry: Some(SetExpiry::Time(ExpireTime::UnixSeconds(1700000000))),
+            condition: Some(SetCondition::Xx),
+            get: true,
+        };
+        assert_eq!(
+            parse(&["key", "value", "EXAT", "1700000000", "GET", "xx"]).unwrap(),
+            arg
+        );
+        match Command::try_from(Set::command_value(arg.clone())) {
+            Ok(Command::Set(parsed)) => assert_eq!(parsed, arg),
+            other => panic!("Unexpected parse result {other:?}"),
+        }
+
+        let arg = parse(&["key", "value", "KEEPTTL", "NX"]).unwrap();
+        assert_eq!(arg.expiry, Some(SetExpiry::KeepTtl));
+        assert_eq!(arg.condition, Some(SetCondition::Nx));
+
+        for parts in [
+            &["key", "value", "NX", "XX"][..],
+            &["key", "value", "EX", "10", "PX", "10"],
+            &["key", "value", "PX", "10", "KEEPTTL"],
+            &["key", "value", "EX"],
+            &["key", "value", "LATER"],
+        ] {
+            assert!(matches!(
+                parse(parts),
+                Err(ParseCommandError::InvalidArgument(_))
+            ));
+        }
+        assert!(matches!(
+            parse(&["key", "value", "EX", "0"]),
+            Err(ParseCommandError::InvalidExpireTime("set"))
+        ));
+        assert!(matches!(
+            parse(&["key", "value", "PX", "soon"]),
+            Err(ParseCommandError::NotInteger)
+        ));
+    }
 }
 
 #[cfg(test)]
 mod handler_test {
+    use std::time::UNIX_EPOCH;
+
+    use super::super::super::clock::{self, TestClock};
+    use super::super::super::handler::StoredValue;
     use super::*;
 
-    fn new_set_handler(map: Arc<RwLock<HashMap<BulkString, StoredData>>>) -> SetHandler {
-        Set::handler(map)
+    fn new_set_handler(map: Arc<Store>) -> SetHandler {
+        Set::handler(map, clock::system(), None)
+    }
+
+    fn set_arg(key: &str, value: &str) -> SetArg {
+        SetArg {
+            key: key.into(),
+            value: value.into(),
+            expiry: None,
+            condition: None,
+            get: false,
+        }
     }
 
     fn simple_set(handler: &mut SetHandler, key: &str, value: &str, expiry: Option<Duration>) {
-        let key = BulkString::from(key);
-        let value = BulkString::from(value);
+        let arg = SetArg {
+            expiry: expiry.map(Into::into),
+            ..set_arg(key, value)
+        };
 
-        let resp = handler.handle(SetArg {
-            key,
-            value,
-            expiry: expiry.clone(),
-        });
+        let resp = handler.handle(arg).unwrap();
         assert_eq!(resp, Value::SimpleString(SimpleString::from("OK")));
     }
 
     #[test]
     fn handle_set() {
-        let map = Arc::new(RwLock::new(HashMap::new()));
+        let map = Arc::new(Store::default());
         let mut handler = new_set_handler(map.clone());
 
         let key = "My Key";
         let value = "My Value";
 
         simple_set(&mut handler, key, value, None);
-        let read_map = map.read().expect("RwLock poisoned");
-        let data = read_map.get(&BulkString::from(key)).unwrap();
+        let read_map = map.read(key.as_bytes());
+        let data = read_map.get(key.as_bytes()).unwrap();
 
+        assert_eq!(data, &StoredData::new(BulkString::from(value).into(), None))
+    }
+
+    #[test]
+    fn handle_set_conditions_and_get() {
+        let map = Arc::new(Store::default());
+        let mut handler = new_set_handler(map.clone());
+        let ok = Value::SimpleString(SimpleString::from("OK"));
+        let nil = Value::BulkString(BulkString::null());
+        let value = |key: &str| map.snapshot().get(key.as_bytes()).map(|d| d.value.clone());
+
+        let xx = SetArg {
+            condition: Some(SetCondition::Xx),
+            ..set_arg("key", "a")
+        };
+        assert_eq!(handler.handle(xx.clone()).unwrap(), nil);
+        assert!(!handler.written());
+        assert_eq!(value("key"), None);
+
+        let nx = SetArg {
+            condition: Some(SetCondition::Nx),
+            ..set_arg("key", "b")
+        };
+        assert_eq!(handler.handle(nx.clone()).unwrap(), ok);
+        assert!(handler.written());
+        assert_eq!(handler.handle(nx).unwrap(), nil);
+        assert_eq!(handler.handle(xx).unwrap(), ok);
+        assert_eq!(value("key"), Some("a".into()));
+
+        let get = SetArg {
+            get: true,
+            ..set_arg("key", "c")
+        };
         assert_eq!(
-            data,
-            &StoredData {
-                value: BulkString::from(value),
-                deadline: None
-            }
-        )
+            handler.handle(get.clone()).unwrap(),
+            Value::BulkString("a".into())
+        );
+        let get_nx = SetArg {
+            condition: Some(SetCondition::Nx),
+            ..get.clone()
+        };
+        assert_eq!(
+            handler.handle(get_nx).unwrap(),
+            Value::BulkString("c".into())
+        );
+        assert!(!handler.written());
+
+        map.write(b"list").insert(
+            "list".into(),
+            StoredData::new(StoredValue::List(["a".into()].into()), None),
+        );
+        let get_list = SetArg {
+            key: "list".into(),
+            ..get
+        };
+        assert!(matches!(
+            handler.handle(get_list),
+            Err(HandleCommandError::WrongType)
+        ));
+        assert_eq!(value("list").unwrap().type_name(), "list");
+    }
+
+    #[test]
+    fn handle_set_expiry() {
+        let clock = Arc::new(TestClock::new(UNIX_EPOCH + Duration::from_secs(1000)));
+        let map = Arc::new(Store::default());
+        let mut handler = Set::handler(map.clone(), clock.clone(), None);
+        let deadline = || map.snapshot().get(b"key".as_slice()).unwrap().deadline;
+
+        let ex = SetArg {
+            expiry: Some(SetExpiry::Time(ExpireTime::Seconds(10))),
+            ..set_arg("key", "a")
+        };
+        handler.handle(ex).unwrap();
+        assert_eq!(deadline(), Some(UNIX_EPOCH + Duration::from_secs(1010)));
+
+        let keepttl = SetArg {
+            expiry: Some(SetExpiry::KeepTtl),
+            ..set_arg("key", "b")
+        };
+        handler.handle(keepttl).unwrap();
+        assert_eq!(deadline(), Some(UNIX_EPOCH + Duration::from_secs(1010)));
+
+        let pxat = SetArg {
+            expiry: Some(SetExpiry::Time(ExpireTime::UnixMillis(2_000_000))),
+            ..set_arg("key", "c")
+        };
+        handler.handle(pxat).unwrap();
+        assert_eq!(deadline(), Some(UNIX_EPOCH + Duration::from_secs(2000)));
+
+        handler.handle(set_arg("key", "d")).unwrap();
+        assert_eq!(deadline(), None);
+
+        let overflow = SetArg {
+            expiry: Some(SetExpiry::Time(ExpireTime::Seconds(i64::MAX))),
+            ..set_arg("key", "e")
+        };
+        assert!(matches!(
+            handler.handle(overflow),
+            Err(HandleCommandError::Parse(
+                ParseCommandError::InvalidExpireTime("set")
+            ))
+        ));
     }
 }
@@ -0,0 +1,188 @@
+use std::sync::Arc;
+
+use super::super::clock::Clock;
+use super::super::handler::{HandleCommandError, StoredData, StoredValue, MAX_STRING_LEN};
+use super::super::key::Key;
+use super::super::resp::{BulkString, Integer, Value};
+use super::super::store::Store;
+use super::{bulk_string_to_int64, consume_args_from_iter, CommandArgParser, ParseCommandError};
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct SetRangeArg {
+    pub key: BulkString,
+    pub offset: i64,
+    pub value: BulkString,
+}
+
+impl CommandArgParser for SetRangeArg {
+    /// SETRANGE key offset value
+    fn parse_arg(iter: &mut std::slice::Iter<'_, Value>) -> Result<Self, ParseCommandError> {
+        let args = consume_args_from_iter(iter, 3, 0)?;
+        Ok(Self {
+            key: args[0].clone(),
+            offset: bulk_string_to_int64(&args[1])?,
+            value: args[2].clone(),
+        })
+    }
+}
+
+pub struct SetRange;
+
+impl SetRange {
+    /// Returns an instance of SETRANGE command handler.
+    pub fn handler(map: Arc<Store>, clock: Arc<dyn Clock>) -> SetRangeHandler {
+        SetRangeHandler { map, clock }
+    }
+
+    /// Returns SETRANGE as a Command in the form of Value.
+    pub fn command_value(arg: SetRangeArg) -> Value {
+        let v = vec![
+            Value::BulkString("SETRANGE".into()),
+            Value::BulkString(arg.key),
+            Value::BulkString(arg.offset.to_string().into()),
+            Value::BulkString(arg.value),
+        ];
+        Value::Array(v.into())
+    }
+}
+
+#[derive(Debug)]
+pub struct SetRangeHandler {
+    map: Arc<Store>,
+    clock: Arc<dyn Clock>,
+}
+
+impl SetRangeHandler {
+    /// Overwrites the string held by the key with the value from `offset` on, padding it
+    /// with zero bytes if it is shorter than the offset, and returns the length of the
+    /// result. A missing key counts as an empty string, and an existing key keeps its time
+    /// to live.
+    ///
+    /// An empty value changes nothing, so it doesn't create the key either.
+    pub fn handle(&self, arg: SetRangeArg) -> Result<Value, HandleCommandError> {
+        let offset =
+            usize::try_from(arg.offset).map_err(|_| HandleCommandError::OffsetOutOfRange)?;
+        let key = arg.key.as_bytes().unwrap_or_default();
+        let value = arg.value.as_bytes().unwrap_or_default();
+        let now = self.clock.now();
+        let mut map = self.map.write(key);
+        let existing = match map.get(key).filter(|data| !data.expired_at(now)) {
+            Some(data) if data.value.as_string().is_none() => {
+                return Err(HandleCommandError::WrongType)
+            }
+            existing => existing,
+        };
+        if value.is_empty() {
+            let len = existing.map_or(0, |data| data.value.string_bytes().len());
+            return Ok(Value::Integer(Integer::new(len as i64)));
+        }
+        let end = offset
+            .checked_add(value.len())
+            .filter(|&end| end <= MAX_STRING_LEN)
+            .ok_or(HandleCommandError::StringTooLong)?;
+
+        let mut data = existing
+            .cloned()
+            .unwrap_or_else(|| StoredData::new(StoredValue::String("".into()), None));
+        let mut bytes = data.value.string_bytes().to_vec();
+        if bytes.len() < end {
+            bytes.resize(end, 0);
+        }
+        bytes[offset..end].copy_from_slice(value);
+        let len = bytes.len();
+        data.value = StoredValue::String(bytes.into());
+        data.raw = true;
+        map.insert(Key::new(key), data);
+
+        Ok(Value::Integer(Integer::new(len as i64)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::Command;
+    use super::*;
+
+    #[test]
+    fn parse_setrange() {
+        let arg = SetRangeArg {
+            key: "key".into(),
+            offset: 6,
+            value: "Redis".into(),
+        };
+        match Command::try_from(SetRange::command_value(arg.clone())) {
+            Ok(Command::SetRange(parsed)) => assert_eq!(parsed, arg),
+            other => panic!("Unexpected parse result {other:?}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod handler_test {
+    use super::super::super::clock;
+    use super::*;
+
+    #[test]
+    fn handle_setrange() {
+        let map = Arc::new(Store::from_iter([
+            (
+                Key::from("key"),
+                StoredData::new("Hello World".into(), None),
+            ),
+            (
+                Key::from("list"),
+                StoredData::new(StoredValue::List(["a".into()].into()), None),
+            ),
+        ]));
+        let handler = SetRange::handler(map.clone(), clock::system());
+        let setrange = |key: &str, offset, value: &str| {
+            handler.handle(SetRangeArg {
+                key: key.into(),
+                offset,
+                value: value.into(),
+            })
+        };
+        let value = |key: &str| {
+            map.snapshot()
+                .get(key.as_bytes())
+                .map(|data| data.value.clone())
+        };
+
+        assert_eq!(
+            setrange("key", 6, "Redis").unwrap(),
+            Value::Integer(Integer::new(11))
+        );
+        assert_eq!(
+            value("key"),
+            Some(StoredValue::String("Hello Redis".into()))
+        );
+
+        // Padded with zero bytes up to the offset.
+        assert_eq!(
+            setrange("new", 3, "ab").unwrap(),
+            Value::Integer(Integer::new(5))
+        );
+        assert_eq!(
+            value("new"),
+            Some(StoredValue::String(b"\0\0\0ab".to_vec().into()))
+        );
+
+        assert_eq!(
+            setrange("missing", 5, "").unwrap(),
+            Value::Integer(Integer::new(0))
+        );
+        assert_eq!(value("missing"), None);
+        assert!(matches!(
+            setrange("key", -1, "x"),
+            Err(HandleCommandError::OffsetOutOfRange)
+        ));
+        assert!(matches!(
+            setrange("key", MAX_STRING_LEN as i64, "x"),
+            Err(HandleCommandError::StringTooLong)
+        ));
+        assert!(matches!(
+            setrange("list", 0, "x"),
+            Err(HandleCommandError::WrongType)
+        ));
+    }
+}
@@ -0,0 +1,672 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use super::super::clock::Clock;
+use super::super::events::{KeyEvent, KeyEvents};
+use super::super::handler::{HandleCommandError, StoredData, StoredValue};
+use super::super::key::Key;
+use super::super::reply::StreamedArray;
+use super::super::resp::{BulkString, Integer, Set, Value};
+use super::super::store::Store;
+use super::{
+    consume_args_from_iter, consume_variadic_args_from_iter, CommandArgParser, ParseCommandError,
+};
+
+/// Members of a set.
+type Members = HashSet<BulkString>;
+
+/// Calls `f` with the set held by the key, returning `None` if the key doesn't exist.
+fn read_set<T>(
+    map: &Store,
+    now: SystemTime,
+    key: &[u8],
+    f: impl FnOnce(&Members) -> T,
+) -> Result<Option<T>, HandleCommandError> {
+    match map.read(key).get(key) {
+        Some(data) if !data.expired_at(now) => {
+            let set = data.value.as_set().ok_or(HandleCommandError::WrongType)?;
+            Ok(Some(f(set)))
+        }
+        _ => Ok(None),
+    }
+}
+
+fn members_value(members: impl IntoIterator<Item = BulkString>) -> Value {
+    Value::Set(Set::new(
+        members.into_iter().map(Value::BulkString).collect(),
+    ))
+}
+
+/// Arguments of SADD and SREM.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct SMembersArg {
+    pub key: BulkString,
+    pub members: Vec<BulkString>,
+}
+
+impl CommandArgParser for SMembersArg {
+    /// SADD key member [member ...]
+    fn parse_arg(iter: &mut std::slice::Iter<'_, Value>) -> Result<Self, ParseCommandError> {
+        let mut args = consume_variadic_args_from_iter(iter, 2)?;
+        let key = args.remove(0);
+
+        Ok(Self { key, members: args })
+    }
+}
+
+fn members_command_value(name: &str, arg: SMembersArg) -> Value {
+    let mut v = vec![Value::BulkString(name.into()), Value::BulkString(arg.key)];
+    v.extend(arg.members.into_iter().map(Value::BulkString));
+    Value::Array(v.into())
+}
+
+pub struct SAdd;
+
+impl SAdd {
+    /// Returns an instance of SADD command handler.
+    pub fn handler(map: Arc<Store>, clock: Arc<dyn Clock>) -> SAddHandler {
+        SAddHandler {
+            map,
+            clock,
+            events: None,
+        }
+    }
+
+    /// Returns SADD as a Command in the form of Value.
+    pub fn command_value(arg: SMembersArg) -> Value {
+        members_command_value("SADD", arg)
+    }
+}
+
+pub struct SAddHandler {
+    map: Arc<Store>,
+    clock: Arc<dyn Clock>,
+    events: Option<Arc<KeyEvents>>,
+}
+
+impl SAddHandler {
+    /// Tells the listeners about keys added to.
+    pub fn with_events(mut self, events: Arc<KeyEvents>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Adds the members to the set, returning how many of them are new. A missing or
+    /// expired key starts as an empty set.
+    pub fn handle(&self, arg: SMembersArg) -> Result<Value, HandleCommandError> {
+        let key = arg.key.as_bytes().unwrap_or_default();
+        let now = self.clock.now();
+
+        let mut shard = self.map.write(key);
+        if shard.get(key).is_some_and(|data| data.expired_at(now)) {
+            shard.remove(key);
+        }
+        if shard.get(key).is_none() {
+            let set = StoredValue::Set(HashSet::new());
+            shard.insert(Key::new(key), StoredData::new(set, None));
+        }
+        let set = shard
+            .value_mut(key)
+            .and_then(StoredValue::as_set_mut)
+            .ok_or(HandleCommandError::WrongType)?;
+        let added = arg
+            .members
+            .into_iter()
+            .filter(|member| set.insert(member.clone()))
+            .count();
+        drop(shard);
+
+        if let Some(events) = self.events.as_ref().filter(|_| added > 0) {
+            events.notify(key, KeyEvent::SAdd);
+        }
+        Ok(Value::Integer(Integer::new(added as i64)))
+    }
+}
+
+pub struct SRem;
+
+impl SRem {
+    /// Returns an instance of SREM command handler.
+    pub fn handler(map: Arc<Store>, clock: Arc<dyn Clock>) -> SRemHandler {
+        SRemHandler {
+            map,
+            clock,
+            events: None,
+        }
+    }
+
+    /// Returns SREM as a Command in the form of Value.
+    pub fn command_value(arg: SMembersArg) -> Value {
+        members_command_value("SREM", arg)
+    }
+}
+
+pub struct SRemHandler {
+    map: Arc<Store>,
+    clock: Arc<dyn Clock>,
+    events: Option<Arc<KeyEvents>>,
+}
+
+impl SRemHandler {
+    /// Tells the listeners about keys with members removed, and sets removed once empty.
+    pub fn with_events(mut self, events: Arc<KeyEvents>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Removes the members from the set, returning how many of them existed. The key is
+    /// removed once the set is empty.
+    pub fn handle(&self, arg: SMembersArg) -> Result<Value, HandleCommandError> {
+        let key = arg.key.as_bytes().unwrap_or_default();
+        let now = self.clock.now();
+
+        let mut shard = self.map.write(key);
+        if shard.get(key).is_none_or(|data| data.expired_at(now)) {
+            return Ok(Value::Integer(Integer::new(0)));
+        }
+        let set = shard
+            .value_mut(key)
+            .and_then(StoredValue::as_set_mut)
+            .ok_or(HandleCommandError::WrongType)?;
+        let removed = arg
+            .members
+            .iter()
+            .filter(|member| set.remove(*member))
+            .count();
+        let emptied = set.is_empty();
+        if emptied {
+            shard.remove(key);
+        }
+        drop(shard);
+
+        if let Some(events) = self.events.as_ref().filter(|_| removed > 0) {
+            events.notify(key, KeyEvent::SRem);
+            if emptied {
+                events.notify(key, KeyEvent::Del);
+            }
+        }
+        Ok(Value::Integer(Integer::new(removed as i64)))
+    }
+}
+
+/// Arguments of SMEMBERS and SCARD, which only take the key.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct SKeyArg {
+    pub key: BulkString,
+}
+
+impl CommandArgParser for SKeyArg {
+    /// SMEMBERS key
+    fn parse_arg(iter: &mut std::slice::Iter<'_, Value>) -> Result<Self, ParseCommandError> {
+        let args = consume_args_from_iter(iter, 1, 0)?;
+
+        Ok(Self {
+            key: args[0].clone(),
+        })
+    }
+}
+
+pub struct SMembers;
+
+impl SMembers {
+    /// Returns an instance of SMEMBERS command handler.
+    pub fn handler(map: Arc<Store>, clock: Arc<dyn Clock>) -> SMembersHandler {
+        SMembersHandler { map, clock }
+    }
+
+    /// Returns SMEMBERS as a Command in the form of Value.
+    pub fn command_value(arg: SKeyArg) -> Value {
+        let v = vec![
+            Value::BulkString("SMEMBERS".into()),
+            Value::BulkString(arg.key),
+        ];
+        Value::Array(v.into())
+    }
+}
+
+#[derive(Debug)]
+pub struct SMembersHandler {
+    map: Arc<Store>,
+    clock: Arc<dyn Clock>,
+}
+
+impl SMembersHandler {
+    /// Returns every member of the set, in no particular order. A missing key is an empty
+    /// set.
+    ///
+    /// Members are only turned into replies as they are written, so a large set never has
+    /// to be in memory twice.
+    pub fn handle(&self, arg: SKeyArg) -> Result<StreamedArray, HandleCommandError> {
+        let key = arg.key.as_bytes().unwrap_or_default();
+        let members = read_set(&self.map, self.clock.now(), key, |set| {
+            set.iter().cloned().collect::<Vec<_>>()
+        })?
+        .unwrap_or_default();
+        let len = members.len();
+        Ok(StreamedArray::set(
+            len,
+            members.into_iter().map(Value::BulkString),
+        ))
+    }
+}
+
+pub struct SCard;
+
+impl SCard {
+    /// Returns an instance of SCARD command handler.
+    pub fn handler(map: Arc<Store>, clock: Arc<dyn Clock>) -> SCardHandler {
+        SCardHandler { map, clock }
+    }
+
+    /// Returns SCARD as a Command in the form of Value.
+    pub fn command_value(arg: SKeyArg) -> Value {
+        let v = vec![
+            Value::BulkString("SCARD".into()),
+            Value::BulkString(arg.key),
+        ];
+        Value::Array(v.into())
+    }
+}
+
+#[derive(Debug)]
+pub struct SCardHandler {
+    map: Arc<Store>,
+    clock: Arc<dyn Clock>,
+}
+
+impl SCardHandler {
+    /// Returns the number of members of the set held by the key, 0 if it doesn't exist.
+    pub fn handle(&self, arg: SKeyArg) -> Result<Value, HandleCommandError> {
+        let key = arg.key.as_bytes().unwrap_or_default();
+        let len = read_set(&self.map, self.clock.now(), key, HashSet::len)?;
+        Ok(Value::Integer(Integer::new(len.unwrap_or_default() as i64)))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct SIsMemberArg {
+    pub key: BulkString,
+    pub member: BulkString,
+}
+
+impl CommandArgParser for SIsMemberArg {
+    /// SISMEMBER key member
+    fn parse_arg(iter: &mut std::slice::Iter<'_, Value>) -> Result<Self, ParseCommandError> {
+        let args = consume_args_from_iter(iter, 2, 0)?;
+
+        Ok(Self {
+            key: args[0].clone(),
+            member: args[1].clone(),
+        })
+    }
+}
+
+pub struct SIsMember;
+
+impl SIsMember {
+    /// Returns an instance of SISMEMBER command handler.
+    pub fn handler(map: Arc<Store>, clock: Arc<dyn Clock>) -> SIsMemberHandler {
+        SIsMemberHandler { map, clock }
+    }
+
+    /// Returns SISMEMBER as a Command in the form of Value.
+    pub fn command_value(arg: SIsMemberArg) -> Value {
+        let v = vec![
+            Value::BulkString("SISMEMBER".into()),
+            Value::BulkString(arg.key),
+            Value::BulkString(arg.member),
+        ];
+        Value::Array(v.into())
+    }
+}
+
+#[derive(Debug)]
+pub struct SIsMemberHandler {
+    map: Arc<Store>,
+    clock: Arc<dyn Clock>,
+}
+
+impl SIsMemberHandler {
+    /// Returns 1 if the set held by the key has the member, 0 if not.
+    pub fn handle(&self, arg: SIsMemberArg) -> Result<Value, HandleCommandError> {
+        let key = arg.key.as_bytes().unwrap_or_default();
+        let member = read_set(&self.map, self.clock.now(), key, |set| {
+            set.contains(&arg.member)
+        })?;
+        Ok(Value::Integer(Integer::new((member == Some(true)) as i64)))
+    }
+}
+
+/// The operation SINTER, SUNION or SDIFF combines the sets with.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SetOp {
+    Inter,
+    Union,
+    /// The members of the first set that aren't in any of the others.
+    Diff,
+}
+
+impl SetOp {
+    pub fn command_name(&self) -> &'static str {
+        match self {
+            Self::Inter => "SINTER",
+            Self::Union => "SUNION",
+            Self::Diff => "SDIFF",
+        }
+    }
+}
+
+/// Arguments of SINTER, SUNION and SDIFF.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct SetOpArg {
+    pub keys: Vec<BulkString>,
+    pub op: SetOp,
+}
+
+impl CommandArgParser for SetOpArg {
+    /// SINTER key [key ...]
+    fn parse_arg(iter: &mut std::slice::Iter<'_, Value>) -> Result<Self, ParseCommandError> {
+        Self::parse_with(iter, SetOp::Inter)
+    }
+}
+
+impl SetOpArg {
+    /// SUNION key [key ...]
+    pub fn parse_sunion(iter: &mut std::slice::Iter<'_, Value>) -> Result<Self, ParseCommandError> {
+        Self::parse_with(iter, SetOp::Union)
+    }
+
+    /// SDIFF key [key ...]
+    pub fn parse_sdiff(iter: &mut std::slice::Iter<'_, Value>) -> Result<Self, ParseCommandError> {
+        Self::parse_with(iter, SetOp::Diff)
+    }
+
+    fn parse_with(
+        iter: &mut std::slice::Iter<'_, Value>,
+        op: SetOp,
+    ) -> Result<Self, ParseCommandError> {
+        let keys = consume_variadic_args_from_iter(iter, 1)?;
+
+        Ok(Self { keys, op })
+    }
+}
+
+pub struct SetOps;
+
+impl SetOps {
+    /// Returns an instance of the handler shared by SINTER, SUNION and SDIFF.
+    pub fn handler(map: Arc<Store>, clock: Arc<dyn Clock>) -> SetOpsHandler {
+        SetOpsHandler { map, clock }
+    }
+
+    /// Returns SINTER, SUNION or SDIFF, whichever is the operation of the arg, as a Command
+    /// in the form of Value.
+    pub fn command_value(arg: SetOpArg) -> Value {
+        let mut v = vec![Value::BulkString(arg.op.command_name().into())];
+        v.extend(arg.keys.into_iter().map(Value::BulkString));
+        Value::Array(v.into())
+    }
+}
+
+#[derive(Debug)]
+pub struct SetOpsHandler {
+    map: Arc<Store>,
+    clock: Arc<dyn Clock>,
+}
+
+impl SetOpsHandler {
+    /// Combines the sets held by the keys, replying with the members of the result in no
+    /// particular order. Missing keys are empty sets.
+    ///
+    /// Every shard holding one of the keys stays read locked while combining, so the sets
+    /// are seen as they were at one point in time.
+    pub fn handle(&self, arg: SetOpArg) -> Result<Value, HandleCommandError> {
+        let now = self.clock.now();
+        let keys: Vec<&[u8]> = arg
+            .keys
+            .iter()
+            .map(|key| key.as_bytes().unwrap_or_default())
+            .collect();
+        let shards = self.map.read_many(keys.iter().copied());
+        let sets = keys
+            .iter()
+            .map(|&key| match shards.shard(key).get(key) {
+                Some(data) if !data.expired_at(now) => data
+                    .value
+                    .as_set()
+                    .map(Some)
+                    .ok_or(HandleCommandError::WrongType),
+                _ => Ok(None),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let empty = HashSet::new();
+        let mut sets = sets.into_iter().map(|set| set.unwrap_or(&empty));
+        let first = sets.next().unwrap_or(&empty);
+        let members: Vec<BulkString> = match arg.op {
+            SetOp::Inter => {
+                let others: Vec<_> = sets.collect();
+                first
+                    .iter()
+                    .filter(|member| others.iter().all(|set| set.contains(*member)))
+                    .cloned()
+                    .collect()
+            }
+            SetOp::Union => {
+                let mut union: HashSet<&BulkString> = first.iter().collect();
+                for set in sets {
+                    union.extend(set);
+                }
+                union.into_iter().cloned().collect()
+            }
+            SetOp::Diff => {
+                let others: Vec<_> = sets.collect();
+                first
+                    .iter()
+                    .filter(|member| !others.iter().any(|set| set.contains(*member)))
+                    .cloned()
+                    .collect()
+            }
+        };
+        drop(shards);
+
+        Ok(members_value(members))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::Command;
+    use super::*;
+
+    #[test]
+    fn parse_set_commands() {
+        let arg = SMembersArg {
+            key: "set".into(),
+            members: vec!["a".into(), "b".into()],
+        };
+        match Command::try_from(SAdd::command_value(arg.clone())) {
+            Ok(Command::SAdd(parsed)) => assert_eq!(parsed, arg),
+            other => panic!("Unexpected parse result {other:?}"),
+        }
+        match Command::try_from(SRem::command_value(arg.clone())) {
+            Ok(Command::SRem(parsed)) => assert_eq!(parsed, arg),
+            other => panic!("Unexpected parse result {other:?}"),
+        }
+
+        let arg = SKeyArg { key: "set".into() };
+        match Command::try_from(SMembers::command_value(arg.clone())) {
+            Ok(Command::SMembers(parsed)) => assert_eq!(parsed, arg),
+            other => panic!("Unexpected parse result {other:?}"),
+        }
+        match Command::try_from(SCard::command_value(arg.clone())) {
+            Ok(Command::SCard(parsed)) => assert_eq!(parsed, arg),
+            other => panic!("Unexpected parse result {other:?}"),
+        }
+
+        let arg = SIsMemberArg {
+            key: "set".into(),
+            member: "a".into(),
+        };
+        match Command::try_from(SIsMember::command_value(arg.clone())) {
+            Ok(Command::SIsMember(parsed)) => assert_eq!(parsed, arg),
+            other => panic!("Unexpected parse result {other:?}"),
+        }
+
+        for op in [SetOp::Inter, SetOp::Union, SetOp::Diff] {
+            let arg = SetOpArg {
+                keys: vec!["a".into(), "b".into()],
+                op,
+            };
+            match Command::try_from(SetOps::command_value(arg.clone())) {
+                Ok(Command::SInter(parsed) | Command::SUnion(parsed) | Command::SDiff(parsed)) => {
+                    assert_eq!(parsed, arg)
+                }
+                other => panic!("Unexpected parse result {other:?}"),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod handler_test {
+    use std::time::Duration;
+
+    use super::super::super::clock::{self, TestClock};
+    use super::super::super::reply::Reply;
+    use super::super::super::test_util::bulk;
+    use super::*;
+
+    fn sadd(map: &Arc<Store>, key: &str, members: &[&str]) -> Value {
+        let arg = SMembersArg {
+            key: key.into(),
+            members: members.iter().map(|&member| member.into()).collect(),
+        };
+        SAdd::handler(map.clone(), clock::system())
+            .handle(arg)
+            .unwrap()
+    }
+
+    /// Returns the members of the reply, sorted.
+    fn sorted(resp: Value) -> Vec<Value> {
+        let mut members = resp.set().unwrap().values().to_vec();
+        members.sort_by(|a, b| {
+            let (a, b) = (a.bulk_string().unwrap(), b.bulk_string().unwrap());
+            a.as_bytes().cmp(&b.as_bytes())
+        });
+        members
+    }
+
+    #[test]
+    fn handle_sadd_and_srem() {
+        let map = Arc::new(Store::default());
+        assert_eq!(
+            sadd(&map, "set", &["a", "b", "a"]),
+            Value::Integer(Integer::new(2))
+        );
+        assert_eq!(
+            sadd(&map, "set", &["b", "c"]),
+            Value::Integer(Integer::new(1))
+        );
+
+        let smembers = SMembers::handler(map.clone(), clock::system());
+        let members = smembers.handle(SKeyArg { key: "set".into() }).unwrap();
+        assert_eq!(
+            sorted(Reply::from(members).into_value()),
+            ["a", "b", "c"].map(bulk)
+        );
+        let sismember = SIsMember::handler(map.clone(), clock::system());
+        let arg = |member: &str| SIsMemberArg {
+            key: "set".into(),
+            member: member.into(),
+        };
+        assert_eq!(
+            sismember.handle(arg("a")).unwrap(),
+            Value::Integer(Integer::new(1))
+        );
+        assert_eq!(
+            sismember.handle(arg("z")).unwrap(),
+            Value::Integer(Integer::new(0))
+        );
+
+        let srem = SRem::handler(map.clone(), clock::system());
+        let arg = |members: &[&str]| SMembersArg {
+            key: "set".into(),
+            members: members.iter().map(|&member| member.into()).collect(),
+        };
+        assert_eq!(
+            srem.handle(arg(&["a", "z"])).unwrap(),
+            Value::Integer(Integer::new(1))
+        );
+        let scard = SCard::handler(map.clone(), clock::system());
+        let card = scard.handle(SKeyArg { key: "set".into() }).unwrap();
+        assert_eq!(card, Value::Integer(Integer::new(2)));
+        assert_eq!(
+            srem.handle(arg(&["b", "c"])).unwrap(),
+            Value::Integer(Integer::new(2))
+        );
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn handle_set_ops() {
+        let clock = Arc::new(TestClock::default());
+        let map = Arc::new(Store::default());
+        sadd(&map, "a", &["1", "2", "3"]);
+        sadd(&map, "b", &["2", "3", "4"]);
+        sadd(&map, "c", &["3", "5"]);
+        map.write(b"expired").insert(
+            "expired".into(),
+            StoredData::new(
+                StoredValue::Set([BulkString::from("3")].into_iter().collect()),
+                Some(clock.now()),
+            ),
+        );
+        clock.advance(Duration::from_millis(1));
+        let handler = SetOps::handler(map.clone(), clock);
+        let op = |op, keys: &[&str]| {
+            let arg = SetOpArg {
+                keys: keys.iter().map(|&key| key.into()).collect(),
+                op,
+            };
+            sorted(handler.handle(arg).unwrap())
+        };
+
+        assert_eq!(op(SetOp::Inter, &["a", "b", "c"]), [bulk("3")]);
+        assert!(op(SetOp::Inter, &["a", "missing"]).is_empty());
+        assert!(op(SetOp::Inter, &["a", "expired"]).is_empty());
+        assert_eq!(
+            op(SetOp::Union, &["a", "c", "missing"]),
+            ["1", "2", "3", "5"].map(bulk)
+        );
+        assert_eq!(op(SetOp::Diff, &["a", "b"]), [bulk("1")]);
+        assert!(op(SetOp::Diff, &["a", "a"]).is_empty());
+        assert!(op(SetOp::Diff, &["missing", "a"]).is_empty());
+    }
+
+    #[test]
+    fn handle_wrong_type() {
+        let map = Arc::new(Store::default());
+        sadd(&map, "set", &["a"]);
+        map.write(b"string")
+            .insert("string".into(), StoredData::new("value".into(), None));
+
+        let arg = SMembersArg {
+            key: "string".into(),
+            members: vec!["a".into()],
+        };
+        assert!(matches!(
+            SAdd::handler(map.clone(), clock::system()).handle(arg),
+            Err(HandleCommandError::WrongType)
+        ));
+        let arg = SetOpArg {
+            keys: vec!["set".into(), "string".into()],
+            op: SetOp::Union,
+        };
+        assert!(matches!(
+            SetOps::handler(map, clock::system()).handle(arg),
+            Err(HandleCommandError::WrongType)
+        ));
+    }
+}
@@ -0,0 +1,539 @@
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use super::super::clock::Clock;
+use super::super::handler::HandleCommandError;
+use super::super::resp::{Array, BulkString, Map, Value};
+use super::super::store::Store;
+use super::super::stream::{Stream, StreamFields, StreamId};
+use super::{
+    bulk_string_to_int64, consume_variadic_args_from_iter, CommandArgParser, ParseCommandError,
+};
+
+/// Calls `f` with the stream held by the key, returning `None` if the key doesn't exist.
+fn read_stream<T>(
+    map: &Store,
+    now: SystemTime,
+    key: &[u8],
+    f: impl FnOnce(&Stream) -> T,
+) -> Result<Option<T>, HandleCommandError> {
+    match map.read(key).get(key) {
+        Some(data) if !data.expired_at(now) => {
+            let stream = data
+                .value
+                .as_stream()
+                .ok_or(HandleCommandError::WrongType)?;
+            Ok(Some(f(stream)))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Parses an ID given as `ms-seq`, or as `ms` alone, the sequence number then being
+/// `missing_seq`.
+fn parse_id(bs: &BulkString, missing_seq: u64) -> Result<StreamId, ParseCommandError> {
+    let s = bs.as_str().ok_or(ParseCommandError::InvalidStreamId)?;
+    let number = |s: &str| s.parse().map_err(|_| ParseCommandError::InvalidStreamId);
+    match s.split_once('-') {
+        Some((ms, seq)) => Ok(StreamId {
+            ms: number(ms)?,
+            seq: number(seq)?,
+        }),
+        None => Ok(StreamId {
+            ms: number(&s)?,
+            seq: missing_seq,
+        }),
+    }
+}
+
+/// Parses the argument of COUNT, where a negative count is taken as 0.
+fn parse_count(bs: Option<&BulkString>) -> Result<usize, ParseCommandError> {
+    let count = bulk_string_to_int64(bs.ok_or(ParseCommandError::WrongNumArgs)?)?;
+    Ok(count.max(0) as usize)
+}
+
+/// Returns the entries as an array of each ID followed by an array of its fields and
+/// values.
+fn entries_value<'a>(entries: impl Iterator<Item = (&'a StreamId, &'a StreamFields)>) -> Value {
+    let entries = entries
+        .map(|(id, fields)| {
+            let fields = fields
+                .iter()
+                .flat_map(|(field, value)| [field, value])
+                .cloned()
+                .map(Value::BulkString)
+                .collect();
+            let entry = vec![
+                Value::BulkString(id.to_string().into()),
+                Value::Array(Array::new(fields)),
+            ];
+            Value::Array(Array::new(entry))
+        })
+        .collect();
+    Value::Array(Array::new(entries))
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct XRangeArg {
+    pub key: BulkString,
+    pub start: StreamId,
+    pub end: StreamId,
+    pub count: Option<usize>,
+}
+
+impl CommandArgParser for XRangeArg {
+    /// XRANGE key start end [COUNT count]
+    fn parse_arg(iter: &mut std::slice::Iter<'_, Value>) -> Result<Self, ParseCommandError> {
+        let args = consume_variadic_args_from_iter(iter, 3)?;
+
+        // A partial ID covers every sequence number of the millisecond.
+        let start = match args[1].as_str().as_deref() {
+            Some("-") => StreamId::MIN,
+            _ => parse_id(&args[1], 0)?,
+        };
+        let end = match args[2].as_str().as_deref() {
+            Some("+") => StreamId::MAX,
+            _ => parse_id(&args[2], u64::MAX)?,
+        };
+
+        let count = match &args[3..] {
+            [] => None,
+            [option, count]
+                if option
+                    .as_str()
+                    .is_some_and(|s| s.eq_ignore_ascii_case("count")) =>
+            {
+                Some(parse_count(Some(count))?)
+            }
+            [option, ..] => {
+                return Err(ParseCommandError::InvalidArgument(Value::BulkString(
+                    option.clone(),
+                )))
+            }
+        };
+
+        Ok(Self {
+            key: args[0].clone(),
+            start,
+            end,
+            count,
+        })
+    }
+}
+
+pub struct XRange;
+
+impl XRange {
+    /// Returns an instance of XRANGE command handler.
+    pub fn handler(map: Arc<Store>, clock: Arc<dyn Clock>) -> XRangeHandler {
+        XRangeHandler { map, clock }
+    }
+
+    /// Returns XRANGE as a Command in the form of Value.
+    pub fn command_value(arg: XRangeArg) -> Value {
+        let mut v = vec![
+            Value::BulkString("XRANGE".into()),
+            Value::BulkString(arg.key),
+            Value::BulkString(arg.start.to_string().into()),
+            Value::BulkString(arg.end.to_string().into()),
+        ];
+        if let Some(count) = arg.count {
+            v.push(Value::BulkString("COUNT".into()));
+            v.push(Value::BulkString(count.to_string().into()));
+        }
+        Value::Array(v.into())
+    }
+}
+
+#[derive(Debug)]
+pub struct XRangeHandler {
+    map: Arc<Store>,
+    clock: Arc<dyn Clock>,
+}
+
+impl XRangeHandler {
+    /// Returns the entries with IDs from `start` to `end`, both inclusive, up to `count` of
+    /// them. A missing key is an empty stream.
+    pub fn handle(&self, arg: XRangeArg) -> Result<Value, HandleCommandError> {
+        let key = arg.key.as_bytes().unwrap_or_default();
+        let count = arg.count.unwrap_or(usize::MAX);
+        let entries = read_stream(&self.map, self.clock.now(), key, |stream| {
+            entries_value(stream.range(arg.start, arg.end).take(count))
+        })?;
+        Ok(entries.unwrap_or_else(|| Value::Array(Array::new(vec![]))))
+    }
+}
+
+/// Where XREAD reads a stream from.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum XReadFrom {
+    /// Entries after the ID.
+    Id(StreamId),
+    /// Entries after the last one at the time of reading, given as `$`.
+    Last,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct XReadArg {
+    /// Maximum number of entries to read from each stream, 0 for no limit.
+    pub count: usize,
+    pub streams: Vec<(BulkString, XReadFrom)>,
+}
+
+impl XReadArg {
+    /// Returns the keys of the streams.
+    pub fn keys(&self) -> impl Iterator<Item = &BulkString> {
+        self.streams.iter().map(|(key, _)| key)
+    }
+}
+
+impl CommandArgParser for XReadArg {
+    /// XREAD [COUNT count] STREAMS key [key ...] id [id ...]
+    fn parse_arg(iter: &mut std::slice::Iter<'_, Value>) -> Result<Self, ParseCommandError> {
+        let args = consume_variadic_args_from_iter(iter, 3)?;
+
+        let mut count = 0;
+        let mut options = args.iter();
+        let streams = loop {
+            let Some(option) = options.next() else {
+                return Err(ParseCommandError::InvalidArgument(Value::BulkString(
+                    args[args.len() - 1].clone(),
+                )));
+            };
+            match option.as_str().unwrap_or_default().to_lowercase().as_str() {
+                "count" => count = parse_count(options.next())?,
+                "streams" => break options.as_slice(),
+                _ => {
+                    return Err(ParseCommandError::InvalidArgument(Value::BulkString(
+                        option.clone(),
+                    )))
+                }
+            }
+        };
+
+        if streams.is_empty() || streams.len() % 2 != 0 {
+            return Err(ParseCommandError::UnbalancedStreams("xread"));
+        }
+        let (keys, ids) = streams.split_at(streams.len() / 2);
+        let streams = keys
+            .iter()
+            .zip(ids)
+            .map(|(key, id)| {
+                let from = match id.as_str().as_deref() {
+                    Some("$") => XReadFrom::Last,
+                    _ => XReadFrom::Id(parse_id(id, 0)?),
+                };
+                Ok((key.clone(), from))
+            })
+            .collect::<Result<_, ParseCommandError>>()?;
+
+        Ok(Self { count, streams })
+    }
+}
+
+pub struct XRead;
+
+impl XRead {
+    /// Returns an instance of XREAD command handler.
+    pub fn handler(map: Arc<Store>, clock: Arc<dyn Clock>) -> XReadHandler {
+        XReadHandler { map, clock }
+    }
+
+    /// Returns XREAD as a Command in the form of Value.
+    pub fn command_value(arg: XReadArg) -> Value {
+        let mut v = vec![Value::BulkString("XREAD".into())];
+        if arg.count > 0 {
+            v.push(Value::BulkString("COUNT".into()));
+            v.push(Value::BulkString(arg.count.to_string().into()));
+        }
+        v.push(Value::BulkString("STREAMS".into()));
+        let ids: Vec<BulkString> = arg
+            .streams
+            .iter()
+            .map(|(_, from)| match from {
+                XReadFrom::Id(id) => id.to_string().into(),
+                XReadFrom::Last => "$".into(),
+            })
+            .collect();
+        v.extend(
+            arg.streams
+                .into_iter()
+                .map(|(key, _)| Value::BulkString(key)),
+        );
+        v.extend(ids.into_iter().map(Value::BulkString));
+        Value::Array(v.into())
+    }
+}
+
+#[derive(Debug)]
+pub struct XReadHandler {
+    map: Arc<Store>,
+    clock: Arc<dyn Clock>,
+}
+
+impl XReadHandler {
+    /// Returns the entries of each stream with IDs greater than the one given for it, as a
+    /// map of the key to its entries to clients speaking `resp` 3 and as an array of key
+    /// and entries pairs to older ones. Streams without such entries are left out, and the
+    /// reply is null if every stream is.
+    ///
+    /// Every shard holding one of the keys stays read locked while reading, so the streams
+    /// are seen as they were at one point in time.
+    pub fn handle(&self, arg: XReadArg, resp: u8) -> Result<Value, HandleCommandError> {
+        let now = self.clock.now();
+        let count = if arg.count == 0 {
+            usize::MAX
+        } else {
+            arg.count
+        };
+        let shards = self
+            .map
+            .read_many(arg.keys().map(|key| key.as_bytes().unwrap_or_default()));
+
+        let mut pairs = Vec::new();
+        for (key, from) in &arg.streams {
+            let bytes = key.as_bytes().unwrap_or_default();
+            let stream = match shards.shard(bytes).get(bytes) {
+                Some(data) if !data.expired_at(now) => data
+                    .value
+                    .as_stream()
+                    .ok_or(HandleCommandError::WrongType)?,
+                _ => continue,
+            };
+            let id = match from {
+                XReadFrom::Id(id) => *id,
+                XReadFrom::Last => stream.last_id(),
+            };
+            let mut entries = stream.after(id).take(count).peekable();
+            if entries.peek().is_some() {
+                pairs.push((Value::BulkString(key.clone()), entries_value(entries)));
+            }
+        }
+        drop(shards);
+
+        if pairs.is_empty() {
+            return Ok(Value::Array(Array::null()));
+        }
+        if resp >= 3 {
+            return Ok(Value::Map(Map::new(pairs)));
+        }
+        let pairs = pairs
+            .into_iter()
+            .map(|(key, entries)| Value::Array(Array::new(vec![key, entries])))
+            .collect();
+        Ok(Value::Array(Array::new(pairs)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::super::test_util::command;
+    use super::super::Command;
+    use super::*;
+
+    fn id(ms: u64, seq: u64) -> StreamId {
+        StreamId { ms, seq }
+    }
+
+    #[test]
+    fn parse_xrange() {
+        let arg = XRangeArg {
+            key: "stream".into(),
+            start: id(1, 2),
+            end: id(3, 4),
+            count: Some(10),
+        };
+        match Command::try_from(XRange::command_value(arg.clone())) {
+            Ok(Command::XRange(parsed)) => assert_eq!(parsed, arg),
+            other => panic!("Unexpected parse result {other:?}"),
+        }
+
+        let arg = XRangeArg {
+            key: "stream".into(),
+            start: StreamId::MIN,
+            end: StreamId::MAX,
+            count: None,
+        };
+        match Command::try_from(command(["XRANGE", "stream", "-", "+"])) {
+            Ok(Command::XRange(parsed)) => assert_eq!(parsed, arg),
+            other => panic!("Unexpected parse result {other:?}"),
+        }
+        let arg = XRangeArg {
+            key: "stream".into(),
+            start: id(5, 0),
+            end: id(5, u64::MAX),
+            count: Some(0),
+        };
+        match Command::try_from(command(["XRANGE", "stream", "5", "5", "count", "-1"])) {
+            Ok(Command::XRange(parsed)) => assert_eq!(parsed, arg),
+            other => panic!("Unexpected parse result {other:?}"),
+        }
+
+        assert!(matches!(
+            Command::try_from(command(["XRANGE", "stream", "1-x", "+"])),
+            Err(ParseCommandError::InvalidStreamId)
+        ));
+        assert!(matches!(
+            Command::try_from(command(["XRANGE", "stream", "-", "+", "COUNT"])),
+            Err(ParseCommandError::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn parse_xread() {
+        let arg = XReadArg {
+            count: 2,
+            streams: vec![
+                ("a".into(), XReadFrom::Id(id(1, 0))),
+                ("b".into(), XReadFrom::Last),
+            ],
+        };
+        match Command::try_from(XRead::command_value(arg.clone())) {
+            Ok(Command::XRead(parsed)) => {
+                assert_eq!(parsed, arg);
+                assert_eq!(Command::XRead(parsed).keys(), vec![b"a", b"b"]);
+            }
+            other => panic!("Unexpected parse result {other:?}"),
+        }
+
+        let arg = XReadArg {
+            count: 0,
+            streams: vec![("a".into(), XReadFrom::Id(id(7, 0)))],
+        };
+        match Command::try_from(command(["XREAD", "streams", "a", "7"])) {
+            Ok(Command::XRead(parsed)) => assert_eq!(parsed, arg),
+            other => panic!("Unexpected parse result {other:?}"),
+        }
+
+        assert!(matches!(
+            Command::try_from(command(["XREAD", "STREAMS", "a", "b", "0"])),
+            Err(ParseCommandError::UnbalancedStreams("xread"))
+        ));
+        assert!(matches!(
+            Command::try_from(command(["XREAD", "COUNT", "1", "a"])),
+            Err(ParseCommandError::InvalidArgument(_))
+        ));
+        assert!(matches!(
+            Command::try_from(command(["XREAD", "BLOCK", "0", "STREAMS", "a", "0"])),
+            Err(ParseCommandError::InvalidArgument(_))
+        ));
+    }
+}
+
+#[cfg(test)]
+mod handler_test {
+    use super::super::super::clock;
+    use super::super::super::handler::{StoredData, StoredValue};
+    use super::*;
+
+    fn id(ms: u64, seq: u64) -> StreamId {
+        StreamId { ms, seq }
+    }
+
+    /// Returns a store with a stream at `stream` holding an entry at each of the IDs.
+    fn store(ids: &[StreamId]) -> Arc<Store> {
+        let map = Arc::new(Store::default());
+        let mut stream = Stream::new();
+        for (i, &id) in ids.iter().enumerate() {
+            stream.insert(id, vec![("n".into(), i.to_string().into())]);
+        }
+        map.write(b"stream").insert(
+            "stream".into(),
+            StoredData::new(StoredValue::Stream(stream), None),
+        );
+        map
+    }
+
+    fn entry(id: &str, n: &str) -> Value {
+        let fields = vec![Value::BulkString("n".into()), Value::BulkString(n.into())];
+        let entry = vec![
+            Value::BulkString(id.into()),
+            Value::Array(Array::new(fields)),
+        ];
+        Value::Array(Array::new(entry))
+    }
+
+    fn entries(entries: Vec<Value>) -> Value {
+        Value::Array(Array::new(entries))
+    }
+
+    #[test]
+    fn handle_xrange() {
+        let map = store(&[id(1, 0), id(1, 1), id(2, 0)]);
+        let handler = XRange::handler(map, clock::system());
+        let xrange = |key: &str, start, end, count| {
+            let arg = XRangeArg {
+                key: key.into(),
+                start,
+                end,
+                count,
+            };
+            handler.handle(arg).unwrap()
+        };
+
+        assert_eq!(
+            xrange("stream", StreamId::MIN, StreamId::MAX, None),
+            entries(vec![
+                entry("1-0", "0"),
+                entry("1-1", "1"),
+                entry("2-0", "2")
+            ])
+        );
+        assert_eq!(
+            xrange("stream", id(1, 0), id(1, u64::MAX), None),
+            entries(vec![entry("1-0", "0"), entry("1-1", "1")])
+        );
+        assert_eq!(
+            xrange("stream", id(1, 1), StreamId::MAX, Some(1)),
+            entries(vec![entry("1-1", "1")])
+        );
+        assert_eq!(xrange("stream", id(2, 0), id(1, 0), None), entries(vec![]));
+        assert_eq!(
+            xrange("missing", StreamId::MIN, StreamId::MAX, None),
+            entries(vec![])
+        );
+    }
+
+    #[test]
+    fn handle_xread() {
+        let map = store(&[id(1, 0), id(1, 1), id(2, 0)]);
+        let handler = XRead::handler(map.clone(), clock::system());
+        let arg = |count, from| XReadArg {
+            count,
+            streams: vec![
+                ("missing".into(), XReadFrom::Id(StreamId::MIN)),
+                ("stream".into(), from),
+            ],
+        };
+
+        assert_eq!(
+            handler.handle(arg(0, XReadFrom::Id(id(1, 0))), 2).unwrap(),
+            Value::Array(Array::new(vec![Value::Array(Array::new(vec![
+                Value::BulkString("stream".into()),
+                entries(vec![entry("1-1", "1"), entry("2-0", "2")]),
+            ]))]))
+        );
+        assert_eq!(
+            handler.handle(arg(1, XReadFrom::Id(id(1, 0))), 3).unwrap(),
+            Value::Map(Map::new(vec![(
+                Value::BulkString("stream".into()),
+                entries(vec![entry("1-1", "1")]),
+            )]))
+        );
+        assert_eq!(
+            handler.handle(arg(0, XReadFrom::Last), 2).unwrap(),
+            Value::Array(Array::null())
+        );
+
+        map.write(b"string")
+            .insert("string".into(), StoredData::new("value".into(), None));
+        let arg = XReadArg {
+            count: 0,
+            streams: vec![("string".into(), XReadFrom::Last)],
+        };
+        assert!(matches!(
+            handler.handle(arg, 2),
+            Err(HandleCommandError::WrongType)
+        ));
+    }
+}
@@ -0,0 +1,104 @@
+use std::sync::Arc;
+
+use super::super::clock::Clock;
+use super::super::handler::HandleCommandError;
+use super::super::resp::{BulkString, Integer, Value};
+use super::super::store::Store;
+use super::{consume_args_from_iter, CommandArgParser, ParseCommandError};
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct StrlenArg {
+    pub key: BulkString,
+}
+
+impl CommandArgParser for StrlenArg {
+    /// STRLEN key
+    fn parse_arg(iter: &mut std::slice::Iter<'_, Value>) -> Result<Self, ParseCommandError> {
+        let args = consume_args_from_iter(iter, 1, 0)?;
+        let key = args.first().unwrap().clone();
+
+        Ok(Self { key })
+    }
+}
+
+pub struct Strlen;
+
+impl Strlen {
+    /// Returns an instance of STRLEN command handler.
+    pub fn handler(map: Arc<Store>, clock: Arc<dyn Clock>) -> StrlenHandler {
+        StrlenHandler { map, clock }
+    }
+
+    /// Returns STRLEN as a Command in the form of Value.
+    pub fn command_value(arg: StrlenArg) -> Value {
+        let v = vec![
+            Value::BulkString("STRLEN".into()),
+            Value::BulkString(arg.key),
+        ];
+        Value::Array(v.into())
+    }
+}
+
+#[derive(Debug)]
+pub struct StrlenHandler {
+    map: Arc<Store>,
+    clock: Arc<dyn Clock>,
+}
+
+impl StrlenHandler {
+    /// Returns the length in bytes of the string held by the key, 0 if it doesn't exist.
+    pub fn handle(&self, arg: StrlenArg) -> Result<Value, HandleCommandError> {
+        let key = arg.key.as_bytes().unwrap_or_default();
+        let now = self.clock.now();
+        let len = match self.map.read(key).get(key) {
+            Some(data) if !data.expired_at(now) => data
+                .value
+                .as_string()
+                .ok_or(HandleCommandError::WrongType)?
+                .as_bytes()
+                .map_or(0, <[u8]>::len),
+            _ => 0,
+        };
+        Ok(Value::Integer(Integer::new(len as i64)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::Command;
+    use super::*;
+
+    #[test]
+    fn parse_strlen() {
+        let arg = StrlenArg { key: "key".into() };
+        match Command::try_from(Strlen::command_value(arg.clone())) {
+            Ok(Command::Strlen(parsed)) => assert_eq!(parsed, arg),
+            other => panic!("Unexpected parse result {other:?}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod handler_test {
+    use super::super::super::clock;
+    use super::super::super::handler::{StoredData, StoredValue};
+    use super::super::super::key::Key;
+    use super::*;
+
+    #[test]
+    fn handle_strlen() {
+        let map = Arc::new(Store::from_iter([
+            (Key::from("key"), StoredData::new("value".into(), None)),
+            (
+                Key::from("list"),
+                StoredData::new(StoredValue::List(["a".into()].into()), None),
+            ),
+        ]));
+        let handler = Strlen::handler(map, clock::system());
+        let strlen = |key: &str| handler.handle(StrlenArg { key: key.into() });
+
+        assert_eq!(strlen("key").unwrap(), Value::Integer(Integer::new(5)));
+        assert_eq!(strlen("missing").unwrap(), Value::Integer(Integer::new(0)));
+        assert!(matches!(strlen("list"), Err(HandleCommandError::WrongType)));
+    }
+}
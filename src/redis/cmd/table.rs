@@ -0,0 +1,1203 @@
+use super::super::resp::BulkString;
+
+/// Static description of a command, as reported by COMMAND.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CommandSpec {
+    /// Lowercase name of the command.
+    pub name: &'static str,
+
+    /// Number of arguments including the command name. Negative means at least that many.
+    pub arity: i64,
+
+    /// Flags such as `write`, `readonly`, `denyoom` or `fast`.
+    pub flags: &'static [&'static str],
+
+    /// Position of the first key argument, 0 if the command has no keys.
+    pub first_key: i64,
+
+    /// Position of the last key argument, negative to count from the end.
+    pub last_key: i64,
+
+    /// Step between key arguments.
+    pub key_step: i64,
+
+    /// Keys whose positions depend on the arguments, like those of EVAL.
+    pub key_specs: &'static [KeySpec],
+
+    /// ACL categories, without the `@` prefix.
+    pub categories: &'static [&'static str],
+
+    /// Short description returned by COMMAND DOCS.
+    pub summary: &'static str,
+
+    /// Group the command belongs to, e.g. `string` or `server`.
+    pub group: &'static str,
+}
+
+impl CommandSpec {
+    /// Returns true if the command has the flag.
+    pub fn has_flag(&self, flag: &str) -> bool {
+        self.flags.contains(&flag)
+    }
+
+    /// Returns true if the number of arguments, including the command name, fits the arity.
+    pub fn arity_matches(&self, argc: usize) -> bool {
+        let argc = argc as i64;
+        if self.arity >= 0 {
+            argc == self.arity
+        } else {
+            argc >= -self.arity
+        }
+    }
+
+    /// Returns the positions of the key arguments of the command, its name first, those
+    /// found by the key specs included.
+    pub fn key_positions_in(&self, args: &[BulkString]) -> Vec<usize> {
+        let mut positions = self.key_positions(args.len());
+        for spec in self.key_specs {
+            positions.extend(spec.key_positions(args));
+        }
+        positions
+    }
+
+    /// Returns the positions of the key arguments in a command with `argc` arguments
+    /// including the command name.
+    pub fn key_positions(&self, argc: usize) -> Vec<usize> {
+        if self.first_key <= 0 || self.key_step <= 0 {
+            return vec![];
+        }
+        let last = if self.last_key < 0 {
+            argc as i64 + self.last_key
+        } else {
+            self.last_key.min(argc as i64 - 1)
+        };
+
+        (self.first_key..=last)
+            .step_by(self.key_step as usize)
+            .map(|pos| pos as usize)
+            .collect()
+    }
+}
+
+/// Keys whose number is given by an argument, like Redis' key specs with an `index`
+/// begin search and `keynum` find keys: the argument at `begin_search` tells how many
+/// keys there are, and they follow it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeySpec {
+    /// Flags of the access to the keys, e.g. `RO` or `RW`.
+    pub flags: &'static [&'static str],
+
+    /// Position of the argument the search for keys begins at.
+    pub begin_search: usize,
+
+    /// Position of the number of keys, relative to `begin_search`.
+    pub keynum_index: usize,
+
+    /// Position of the first key, relative to `begin_search`.
+    pub first_key: usize,
+
+    /// Step between keys.
+    pub key_step: usize,
+}
+
+impl KeySpec {
+    /// Returns the positions of the keys in the arguments of the command, its name first.
+    /// A number of keys that isn't a positive integer, or more keys than arguments, is none.
+    pub fn key_positions(&self, args: &[BulkString]) -> Vec<usize> {
+        let numkeys = args
+            .get(self.begin_search + self.keynum_index)
+            .and_then(|arg| std::str::from_utf8(arg.as_bytes()?).ok())
+            .and_then(|n| n.parse::<usize>().ok())
+            .unwrap_or(0);
+        let first = self.begin_search + self.first_key;
+        let positions: Vec<usize> = (0..numkeys).map(|i| first + i * self.key_step).collect();
+        match positions.last() {
+            Some(&last) if last < args.len() => positions,
+            _ => vec![],
+        }
+    }
+}
+
+/// `EVAL script numkeys key... arg...` and alike, which may write the keys.
+#[cfg(feature = "scripting")]
+const KEYNUM_RW: KeySpec = KeySpec {
+    flags: &["RW", "ACCESS", "UPDATE"],
+    begin_search: 2,
+    keynum_index: 0,
+    first_key: 1,
+    key_step: 1,
+};
+
+/// `EVAL_RO script numkeys key... arg...` and alike, which only read the keys.
+#[cfg(feature = "scripting")]
+const KEYNUM_RO: KeySpec = KeySpec {
+    flags: &["RO", "ACCESS"],
+    ..KEYNUM_RW
+};
+
+const ADMIN_FLAGS: &[&str] = &["admin", "noscript", "loading", "stale"];
+
+/// Every command supported by the server, in alphabetical order.
+pub const COMMAND_TABLE: &[CommandSpec] = &[
+    CommandSpec {
+        name: "acl",
+        arity: -2,
+        flags: ADMIN_FLAGS,
+        first_key: 0,
+        last_key: 0,
+        key_step: 0,
+        key_specs: &[],
+        categories: &["admin", "slow", "dangerous"],
+        summary: "A container for Access List Control commands.",
+        group: "server",
+    },
+    CommandSpec {
+        name: "append",
+        arity: 3,
+        flags: &["write", "denyoom", "fast"],
+        first_key: 1,
+        last_key: 1,
+        key_step: 1,
+        key_specs: &[],
+        categories: &["write", "string", "fast"],
+        summary: "Appends a string to the value of a key. Creates the key if it doesn't exist.",
+        group: "string",
+    },
+    CommandSpec {
+        name: "asking",
+        arity: 1,
+        flags: &["fast"],
+        first_key: 0,
+        last_key: 0,
+        key_step: 0,
+        key_specs: &[],
+        categories: &["fast", "connection"],
+        summary: "Signals that a cluster client is following an -ASK redirect.",
+        group: "cluster",
+    },
+    CommandSpec {
+        name: "auth",
+        arity: -2,
+        flags: &["noscript", "loading", "stale", "fast", "no_auth"],
+        first_key: 0,
+        last_key: 0,
+        key_step: 0,
+        key_specs: &[],
+        categories: &["fast", "connection"],
+        summary: "Authenticates the connection.",
+        group: "connection",
+    },
+    #[cfg(feature = "persistence")]
+    CommandSpec {
+        name: "bgsave",
+        arity: 1,
+        flags: &["admin", "noscript", "no_async_loading"],
+        first_key: 0,
+        last_key: 0,
+        key_step: 0,
+        key_specs: &[],
+        categories: &["admin", "slow", "dangerous"],
+        summary: "Asynchronously saves the database(s) to disk.",
+        group: "server",
+    },
+    CommandSpec {
+        name: "blpop",
+        arity: -3,
+        flags: &["write", "blocking"],
+        first_key: 1,
+        last_key: -2,
+        key_step: 1,
+        key_specs: &[],
+        categories: &["write", "list", "slow", "blocking"],
+        summary: "Removes and returns the first element in a list. Blocks until an element is available otherwise. Deletes the list if the last element was popped.",
+        group: "list",
+    },
+    CommandSpec {
+        name: "brpop",
+        arity: -3,
+        flags: &["write", "blocking"],
+        first_key: 1,
+        last_key: -2,
+        key_step: 1,
+        key_specs: &[],
+        categories: &["write", "list", "slow", "blocking"],
+        summary: "Removes and returns the last element in a list. Blocks until an element is available otherwise. Deletes the list if the last element was popped.",
+        group: "list",
+    },
+    CommandSpec {
+        name: "client",
+        arity: -2,
+        flags: &["noscript", "loading", "stale"],
+        first_key: 0,
+        last_key: 0,
+        key_step: 0,
+        key_specs: &[],
+        categories: &["slow", "connection"],
+        summary: "A container for client connection commands.",
+        group: "connection",
+    },
+    CommandSpec {
+        name: "cluster",
+        arity: -2,
+        flags: &[],
+        first_key: 0,
+        last_key: 0,
+        key_step: 0,
+        key_specs: &[],
+        categories: &["slow"],
+        summary: "A container for Redis Cluster commands.",
+        group: "cluster",
+    },
+    CommandSpec {
+        name: "command",
+        arity: -1,
+        flags: &["loading", "stale"],
+        first_key: 0,
+        last_key: 0,
+        key_step: 0,
+        key_specs: &[],
+        categories: &["slow", "connection"],
+        summary: "Returns detailed information about all commands.",
+        group: "server",
+    },
+    CommandSpec {
+        name: "config",
+        arity: -2,
+        flags: ADMIN_FLAGS,
+        first_key: 0,
+        last_key: 0,
+        key_step: 0,
+        key_specs: &[],
+        categories: &["admin", "slow", "dangerous"],
+        summary: "A container for server configuration commands.",
+        group: "server",
+    },
+    CommandSpec {
+        name: "debug",
+        arity: -2,
+        flags: ADMIN_FLAGS,
+        first_key: 0,
+        last_key: 0,
+        key_step: 0,
+        key_specs: &[],
+        categories: &["admin", "slow", "dangerous"],
+        summary: "A container for debugging commands.",
+        group: "server",
+    },
+    CommandSpec {
+        name: "decr",
+        arity: 2,
+        flags: &["write", "denyoom", "fast"],
+        first_key: 1,
+        last_key: 1,
+        key_step: 1,
+        key_specs: &[],
+        categories: &["write", "string", "fast"],
+        summary: "Decrements the integer value of a key by one. Uses 0 as initial value if the key doesn't exist.",
+        group: "string",
+    },
+    CommandSpec {
+        name: "decrby",
+        arity: 3,
+        flags: &["write", "denyoom", "fast"],
+        first_key: 1,
+        last_key: 1,
+        key_step: 1,
+        key_specs: &[],
+        categories: &["write", "string", "fast"],
+        summary: "Decrements a number from the integer value of a key. Uses 0 as initial value if the key doesn't exist.",
+        group: "string",
+    },
+    CommandSpec {
+        name: "del",
+        arity: -2,
+        flags: &["write"],
+        first_key: 1,
+        last_key: -1,
+        key_step: 1,
+        key_specs: &[],
+        categories: &["keyspace", "write", "slow"],
+        summary: "Deletes one or more keys.",
+        group: "generic",
+    },
+    CommandSpec {
+        name: "echo",
+        arity: 2,
+        flags: &["fast"],
+        first_key: 0,
+        last_key: 0,
+        key_step: 0,
+        key_specs: &[],
+        categories: &["fast", "connection"],
+        summary: "Returns the given string.",
+        group: "connection",
+    },
+    #[cfg(feature = "scripting")]
+    CommandSpec {
+        name: "eval",
+        arity: -3,
+        flags: &["noscript", "stale", "may_replicate", "no_mandatory_keys", "movablekeys"],
+        first_key: 0,
+        last_key: 0,
+        key_step: 0,
+        key_specs: &[KEYNUM_RW],
+        categories: &["slow", "scripting"],
+        summary: "Executes a server-side Lua script.",
+        group: "scripting",
+    },
+    #[cfg(feature = "scripting")]
+    CommandSpec {
+        name: "eval_ro",
+        arity: -3,
+        flags: &["noscript", "stale", "readonly", "no_mandatory_keys", "movablekeys"],
+        first_key: 0,
+        last_key: 0,
+        key_step: 0,
+        key_specs: &[KEYNUM_RO],
+        categories: &["slow", "scripting"],
+        summary: "Executes a read-only server-side Lua script.",
+        group: "scripting",
+    },
+    CommandSpec {
+        name: "exists",
+        arity: -2,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: -1,
+        key_step: 1,
+        key_specs: &[],
+        categories: &["keyspace", "read", "fast"],
+        summary: "Determines whether one or more keys exist.",
+        group: "generic",
+    },
+    CommandSpec {
+        name: "expire",
+        arity: -3,
+        flags: &["write", "fast"],
+        first_key: 1,
+        last_key: 1,
+        key_step: 1,
+        key_specs: &[],
+        categories: &["keyspace", "write", "fast"],
+        summary: "Sets the expiration time of a key in seconds.",
+        group: "generic",
+    },
+    CommandSpec {
+        name: "expireat",
+        arity: -3,
+        flags: &["write", "fast"],
+        first_key: 1,
+        last_key: 1,
+        key_step: 1,
+        key_specs: &[],
+        categories: &["keyspace", "write", "fast"],
+        summary: "Sets the expiration time of a key to a Unix timestamp.",
+        group: "generic",
+    },
+    #[cfg(feature = "replication")]
+    CommandSpec {
+        name: "failover",
+        arity: -1,
+        flags: ADMIN_FLAGS,
+        first_key: 0,
+        last_key: 0,
+        key_step: 0,
+        key_specs: &[],
+        categories: &["admin", "slow", "dangerous"],
+        summary: "Starts a coordinated failover from a server to one of its replicas.",
+        group: "server",
+    },
+    #[cfg(feature = "scripting")]
+    CommandSpec {
+        name: "fcall",
+        arity: -3,
+        flags: &["noscript", "stale", "may_replicate", "no_mandatory_keys", "movablekeys"],
+        first_key: 0,
+        last_key: 0,
+        key_step: 0,
+        key_specs: &[KEYNUM_RW],
+        categories: &["slow", "scripting"],
+        summary: "Invokes a function.",
+        group: "scripting",
+    },
+    #[cfg(feature = "scripting")]
+    CommandSpec {
+        name: "fcall_ro",
+        arity: -3,
+        flags: &["noscript", "stale", "readonly", "no_mandatory_keys", "movablekeys"],
+        first_key: 0,
+        last_key: 0,
+        key_step: 0,
+        key_specs: &[KEYNUM_RO],
+        categories: &["slow", "scripting"],
+        summary: "Invokes a read-only function.",
+        group: "scripting",
+    },
+    #[cfg(feature = "scripting")]
+    CommandSpec {
+        name: "function",
+        arity: -2,
+        flags: &["write", "denyoom", "noscript"],
+        first_key: 0,
+        last_key: 0,
+        key_step: 0,
+        key_specs: &[],
+        categories: &["write", "slow", "scripting"],
+        summary: "A container for function commands.",
+        group: "scripting",
+    },
+    CommandSpec {
+        name: "get",
+        arity: 2,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: 1,
+        key_step: 1,
+        key_specs: &[],
+        categories: &["read", "string", "fast"],
+        summary: "Returns the string value of a key.",
+        group: "string",
+    },
+    CommandSpec {
+        name: "getrange",
+        arity: 4,
+        flags: &["readonly"],
+        first_key: 1,
+        last_key: 1,
+        key_step: 1,
+        key_specs: &[],
+        categories: &["read", "string", "slow"],
+        summary: "Returns a substring of the string stored at a key.",
+        group: "string",
+    },
+    CommandSpec {
+        name: "hdel",
+        arity: -3,
+        flags: &["write", "fast"],
+        first_key: 1,
+        last_key: 1,
+        key_step: 1,
+        key_specs: &[],
+        categories: &["write", "hash", "fast"],
+        summary: "Deletes one or more fields and their values from a hash. Deletes the hash if no fields remain.",
+        group: "hash",
+    },
+    CommandSpec {
+        name: "hello",
+        arity: -1,
+        flags: &["noscript", "loading", "stale", "fast", "no_auth"],
+        first_key: 0,
+        last_key: 0,
+        key_step: 0,
+        key_specs: &[],
+        categories: &["fast", "connection"],
+        summary: "Handshakes with the Redis server.",
+        group: "connection",
+    },
+    CommandSpec {
+        name: "hexists",
+        arity: 3,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: 1,
+        key_step: 1,
+        key_specs: &[],
+        categories: &["read", "hash", "fast"],
+        summary: "Determines whether a field exists in a hash.",
+        group: "hash",
+    },
+    CommandSpec {
+        name: "hget",
+        arity: 3,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: 1,
+        key_step: 1,
+        key_specs: &[],
+        categories: &["read", "hash", "fast"],
+        summary: "Returns the value of a field in a hash.",
+        group: "hash",
+    },
+    CommandSpec {
+        name: "hgetall",
+        arity: 2,
+        flags: &["readonly"],
+        first_key: 1,
+        last_key: 1,
+        key_step: 1,
+        key_specs: &[],
+        categories: &["read", "hash", "slow"],
+        summary: "Returns all fields and values in a hash.",
+        group: "hash",
+    },
+    CommandSpec {
+        name: "hgetdel",
+        arity: -5,
+        flags: &["write", "fast"],
+        first_key: 1,
+        last_key: 1,
+        key_step: 1,
+        key_specs: &[],
+        categories: &["write", "hash", "fast"],
+        summary: "Returns the value of a field and deletes it from the hash.",
+        group: "hash",
+    },
+    CommandSpec {
+        name: "hgetex",
+        arity: -5,
+        flags: &["write", "fast"],
+        first_key: 1,
+        last_key: 1,
+        key_step: 1,
+        key_specs: &[],
+        categories: &["write", "hash", "fast"],
+        summary: "Get the value of one or more fields of a given hash key, and optionally set their expiration.",
+        group: "hash",
+    },
+    CommandSpec {
+        name: "hincrby",
+        arity: 4,
+        flags: &["write", "denyoom", "fast"],
+        first_key: 1,
+        last_key: 1,
+        key_step: 1,
+        key_specs: &[],
+        categories: &["write", "hash", "fast"],
+        summary: "Increments the integer value of a field in a hash by a number. Uses 0 as initial value if the field doesn't exist.",
+        group: "hash",
+    },
+    CommandSpec {
+        name: "hlen",
+        arity: 2,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: 1,
+        key_step: 1,
+        key_specs: &[],
+        categories: &["read", "hash", "fast"],
+        summary: "Returns the number of fields in a hash.",
+        group: "hash",
+    },
+    CommandSpec {
+        name: "hset",
+        arity: -4,
+        flags: &["write", "denyoom", "fast"],
+        first_key: 1,
+        last_key: 1,
+        key_step: 1,
+        key_specs: &[],
+        categories: &["write", "hash", "fast"],
+        summary: "Creates or modifies the value of a field in a hash.",
+        group: "hash",
+    },
+    CommandSpec {
+        name: "incr",
+        arity: 2,
+        flags: &["write", "denyoom", "fast"],
+        first_key: 1,
+        last_key: 1,
+        key_step: 1,
+        key_specs: &[],
+        categories: &["write", "string", "fast"],
+        summary: "Increments the integer value of a key by one. Uses 0 as initial value if the key doesn't exist.",
+        group: "string",
+    },
+    CommandSpec {
+        name: "incrby",
+        arity: 3,
+        flags: &["write", "denyoom", "fast"],
+        first_key: 1,
+        last_key: 1,
+        key_step: 1,
+        key_specs: &[],
+        categories: &["write", "string", "fast"],
+        summary: "Increments the integer value of a key by a number. Uses 0 as initial value if the key doesn't exist.",
+        group: "string",
+    },
+    CommandSpec {
+        name: "info",
+        arity: -1,
+        flags: &["loading", "stale"],
+        first_key: 0,
+        last_key: 0,
+        key_step: 0,
+        key_specs: &[],
+        categories: &["slow", "dangerous"],
+        summary: "Returns information and statistics about the server.",
+        group: "server",
+    },
+    CommandSpec {
+        name: "keys",
+        arity: 2,
+        flags: &["readonly"],
+        first_key: 0,
+        last_key: 0,
+        key_step: 0,
+        key_specs: &[],
+        categories: &["keyspace", "read", "slow", "dangerous"],
+        summary: "Returns all key names that match a pattern.",
+        group: "generic",
+    },
+    CommandSpec {
+        name: "llen",
+        arity: 2,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: 1,
+        key_step: 1,
+        key_specs: &[],
+        categories: &["read", "list", "fast"],
+        summary: "Returns the length of a list.",
+        group: "list",
+    },
+    CommandSpec {
+        name: "lpop",
+        arity: -2,
+        flags: &["write", "fast"],
+        first_key: 1,
+        last_key: 1,
+        key_step: 1,
+        key_specs: &[],
+        categories: &["write", "list", "fast"],
+        summary: "Returns the first elements in a list after removing it. Deletes the list if the last element was popped.",
+        group: "list",
+    },
+    CommandSpec {
+        name: "lpush",
+        arity: -3,
+        flags: &["write", "denyoom", "fast"],
+        first_key: 1,
+        last_key: 1,
+        key_step: 1,
+        key_specs: &[],
+        categories: &["write", "list", "fast"],
+        summary: "Prepends one or more elements to a list. Creates the key if it doesn't exist.",
+        group: "list",
+    },
+    CommandSpec {
+        name: "lrange",
+        arity: 4,
+        flags: &["readonly"],
+        first_key: 1,
+        last_key: 1,
+        key_step: 1,
+        key_specs: &[],
+        categories: &["read", "list", "slow"],
+        summary: "Returns a range of elements from a list.",
+        group: "list",
+    },
+    CommandSpec {
+        name: "memory",
+        arity: -2,
+        flags: &["readonly"],
+        first_key: 2,
+        last_key: 2,
+        key_step: 1,
+        key_specs: &[],
+        categories: &["read", "slow"],
+        summary: "A container for memory diagnostics commands.",
+        group: "server",
+    },
+    CommandSpec {
+        name: "mget",
+        arity: -2,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: -1,
+        key_step: 1,
+        key_specs: &[],
+        categories: &["read", "string", "fast"],
+        summary: "Atomically returns the string values of one or more keys.",
+        group: "string",
+    },
+    CommandSpec {
+        name: "mset",
+        arity: -3,
+        flags: &["write", "denyoom"],
+        first_key: 1,
+        last_key: -1,
+        key_step: 2,
+        key_specs: &[],
+        categories: &["write", "string", "slow"],
+        summary: "Atomically creates or modifies the string values of one or more keys.",
+        group: "string",
+    },
+    CommandSpec {
+        name: "msetnx",
+        arity: -3,
+        flags: &["write", "denyoom"],
+        first_key: 1,
+        last_key: -1,
+        key_step: 2,
+        key_specs: &[],
+        categories: &["write", "string", "slow"],
+        summary: "Atomically modifies the string values of one or more keys only when all keys don't exist.",
+        group: "string",
+    },
+    CommandSpec {
+        name: "object",
+        arity: -2,
+        flags: &["readonly"],
+        first_key: 2,
+        last_key: 2,
+        key_step: 1,
+        key_specs: &[],
+        categories: &["keyspace", "read", "slow"],
+        summary: "A container for object introspection commands.",
+        group: "generic",
+    },
+    CommandSpec {
+        name: "persist",
+        arity: 2,
+        flags: &["write", "fast"],
+        first_key: 1,
+        last_key: 1,
+        key_step: 1,
+        key_specs: &[],
+        categories: &["keyspace", "write", "fast"],
+        summary: "Removes the expiration time of a key.",
+        group: "generic",
+    },
+    CommandSpec {
+        name: "pexpire",
+        arity: -3,
+        flags: &["write", "fast"],
+        first_key: 1,
+        last_key: 1,
+        key_step: 1,
+        key_specs: &[],
+        categories: &["keyspace", "write", "fast"],
+        summary: "Sets the expiration time of a key in milliseconds.",
+        group: "generic",
+    },
+    CommandSpec {
+        name: "pexpireat",
+        arity: -3,
+        flags: &["write", "fast"],
+        first_key: 1,
+        last_key: 1,
+        key_step: 1,
+        key_specs: &[],
+        categories: &["keyspace", "write", "fast"],
+        summary: "Sets the expiration time of a key to a Unix milliseconds timestamp.",
+        group: "generic",
+    },
+    CommandSpec {
+        name: "ping",
+        arity: -1,
+        flags: &["fast"],
+        first_key: 0,
+        last_key: 0,
+        key_step: 0,
+        key_specs: &[],
+        categories: &["fast", "connection"],
+        summary: "Returns the server's liveliness response.",
+        group: "connection",
+    },
+    #[cfg(feature = "replication")]
+    CommandSpec {
+        name: "psync",
+        arity: -3,
+        flags: &["admin", "noscript", "no_async_loading", "no_multi"],
+        first_key: 0,
+        last_key: 0,
+        key_step: 0,
+        key_specs: &[],
+        categories: &["admin", "slow", "dangerous"],
+        summary: "An internal command used in replication.",
+        group: "server",
+    },
+    CommandSpec {
+        name: "pttl",
+        arity: 2,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: 1,
+        key_step: 1,
+        key_specs: &[],
+        categories: &["keyspace", "read", "fast"],
+        summary: "Returns the expiration time in milliseconds of a key.",
+        group: "generic",
+    },
+    #[cfg(feature = "replication")]
+    CommandSpec {
+        name: "replconf",
+        arity: -1,
+        flags: ADMIN_FLAGS,
+        first_key: 0,
+        last_key: 0,
+        key_step: 0,
+        key_specs: &[],
+        categories: &["admin", "slow", "dangerous"],
+        summary: "An internal command for configuring the replication stream.",
+        group: "server",
+    },
+    #[cfg(feature = "replication")]
+    CommandSpec {
+        name: "replicaof",
+        arity: 3,
+        flags: &["admin", "noscript", "stale"],
+        first_key: 0,
+        last_key: 0,
+        key_step: 0,
+        key_specs: &[],
+        categories: &["admin", "slow", "dangerous"],
+        summary: "Configures a server as replica of another, or promotes it to a master.",
+        group: "server",
+    },
+    CommandSpec {
+        name: "rpop",
+        arity: -2,
+        flags: &["write", "fast"],
+        first_key: 1,
+        last_key: 1,
+        key_step: 1,
+        key_specs: &[],
+        categories: &["write", "list", "fast"],
+        summary: "Returns and removes the last elements of the list. Deletes the list if the last element was popped.",
+        group: "list",
+    },
+    CommandSpec {
+        name: "rpush",
+        arity: -3,
+        flags: &["write", "denyoom", "fast"],
+        first_key: 1,
+        last_key: 1,
+        key_step: 1,
+        key_specs: &[],
+        categories: &["write", "list", "fast"],
+        summary: "Appends one or more elements to a list. Creates the key if it doesn't exist.",
+        group: "list",
+    },
+    CommandSpec {
+        name: "sadd",
+        arity: -3,
+        flags: &["write", "denyoom", "fast"],
+        first_key: 1,
+        last_key: 1,
+        key_step: 1,
+        key_specs: &[],
+        categories: &["write", "set", "fast"],
+        summary: "Adds one or more members to a set. Creates the key if it doesn't exist.",
+        group: "set",
+    },
+    #[cfg(feature = "persistence")]
+    CommandSpec {
+        name: "save",
+        arity: 1,
+        flags: &["admin", "noscript", "no_async_loading", "no_multi"],
+        first_key: 0,
+        last_key: 0,
+        key_step: 0,
+        key_specs: &[],
+        categories: &["admin", "slow", "dangerous"],
+        summary: "Synchronously saves the database(s) to disk.",
+        group: "server",
+    },
+    CommandSpec {
+        name: "scard",
+        arity: 2,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: 1,
+        key_step: 1,
+        key_specs: &[],
+        categories: &["read", "set", "fast"],
+        summary: "Returns the number of members in a set.",
+        group: "set",
+    },
+    CommandSpec {
+        name: "sdiff",
+        arity: -2,
+        flags: &["readonly"],
+        first_key: 1,
+        last_key: -1,
+        key_step: 1,
+        key_specs: &[],
+        categories: &["read", "set", "slow"],
+        summary: "Returns the difference of multiple sets.",
+        group: "set",
+    },
+    CommandSpec {
+        name: "set",
+        arity: -3,
+        flags: &["write", "denyoom"],
+        first_key: 1,
+        last_key: 1,
+        key_step: 1,
+        key_specs: &[],
+        categories: &["write", "string", "slow"],
+        summary: "Sets the string value of a key, ignoring its type.",
+        group: "string",
+    },
+    CommandSpec {
+        name: "setrange",
+        arity: 4,
+        flags: &["write", "denyoom"],
+        first_key: 1,
+        last_key: 1,
+        key_step: 1,
+        key_specs: &[],
+        categories: &["write", "string", "slow"],
+        summary: "Overwrites a part of a string value with another by an offset. Creates the key if it doesn't exist.",
+        group: "string",
+    },
+    CommandSpec {
+        name: "sinter",
+        arity: -2,
+        flags: &["readonly"],
+        first_key: 1,
+        last_key: -1,
+        key_step: 1,
+        key_specs: &[],
+        categories: &["read", "set", "slow"],
+        summary: "Returns the intersect of multiple sets.",
+        group: "set",
+    },
+    CommandSpec {
+        name: "sismember",
+        arity: 3,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: 1,
+        key_step: 1,
+        key_specs: &[],
+        categories: &["read", "set", "fast"],
+        summary: "Determines whether a member belongs to a set.",
+        group: "set",
+    },
+    CommandSpec {
+        name: "smembers",
+        arity: 2,
+        flags: &["readonly"],
+        first_key: 1,
+        last_key: 1,
+        key_step: 1,
+        key_specs: &[],
+        categories: &["read", "set", "slow"],
+        summary: "Returns all members of a set.",
+        group: "set",
+    },
+    CommandSpec {
+        name: "srem",
+        arity: -3,
+        flags: &["write", "fast"],
+        first_key: 1,
+        last_key: 1,
+        key_step: 1,
+        key_specs: &[],
+        categories: &["write", "set", "fast"],
+        summary: "Removes one or more members from a set. Deletes the set if the last member was removed.",
+        group: "set",
+    },
+    CommandSpec {
+        name: "strlen",
+        arity: 2,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: 1,
+        key_step: 1,
+        key_specs: &[],
+        categories: &["read", "string", "fast"],
+        summary: "Returns the length of a string value.",
+        group: "string",
+    },
+    CommandSpec {
+        name: "sunion",
+        arity: -2,
+        flags: &["readonly"],
+        first_key: 1,
+        last_key: -1,
+        key_step: 1,
+        key_specs: &[],
+        categories: &["read", "set", "slow"],
+        summary: "Returns the union of multiple sets.",
+        group: "set",
+    },
+    CommandSpec {
+        name: "type",
+        arity: 2,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: 1,
+        key_step: 1,
+        key_specs: &[],
+        categories: &["keyspace", "read", "fast"],
+        summary: "Determines the type of value stored at a key.",
+        group: "generic",
+    },
+    CommandSpec {
+        name: "unlink",
+        arity: -2,
+        flags: &["write", "fast"],
+        first_key: 1,
+        last_key: -1,
+        key_step: 1,
+        key_specs: &[],
+        categories: &["keyspace", "write", "fast"],
+        summary: "Asynchronously deletes one or more keys.",
+        group: "generic",
+    },
+    #[cfg(feature = "replication")]
+    CommandSpec {
+        name: "wait",
+        arity: 3,
+        flags: &["noscript"],
+        first_key: 0,
+        last_key: 0,
+        key_step: 0,
+        key_specs: &[],
+        categories: &["slow", "connection"],
+        summary: "Blocks until the writes propagated so far are acknowledged by replicas.",
+        group: "generic",
+    },
+    CommandSpec {
+        name: "xrange",
+        arity: -4,
+        flags: &["readonly"],
+        first_key: 1,
+        last_key: 1,
+        key_step: 1,
+        key_specs: &[],
+        categories: &["read", "stream", "slow"],
+        summary: "Returns the messages from a stream within a range of IDs.",
+        group: "stream",
+    },
+    CommandSpec {
+        name: "xread",
+        arity: -4,
+        flags: &["readonly", "movablekeys"],
+        first_key: 0,
+        last_key: 0,
+        key_step: 0,
+        key_specs: &[],
+        categories: &["read", "stream", "slow"],
+        summary: "Returns messages from multiple streams with IDs greater than the ones requested.",
+        group: "stream",
+    },
+    CommandSpec {
+        name: "zadd",
+        arity: -4,
+        flags: &["write", "denyoom", "fast"],
+        first_key: 1,
+        last_key: 1,
+        key_step: 1,
+        key_specs: &[],
+        categories: &["write", "sortedset", "fast"],
+        summary: "Adds one or more members to a sorted set, or updates their scores. Creates the key if it doesn't exist.",
+        group: "sorted_set",
+    },
+    CommandSpec {
+        name: "zcard",
+        arity: 2,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: 1,
+        key_step: 1,
+        key_specs: &[],
+        categories: &["read", "sortedset", "fast"],
+        summary: "Returns the number of members in a sorted set.",
+        group: "sorted_set",
+    },
+    CommandSpec {
+        name: "zrange",
+        arity: -4,
+        flags: &["readonly"],
+        first_key: 1,
+        last_key: 1,
+        key_step: 1,
+        key_specs: &[],
+        categories: &["read", "sortedset", "slow"],
+        summary: "Returns members in a sorted set within a range of indexes or scores.",
+        group: "sorted_set",
+    },
+    CommandSpec {
+        name: "zrangebyscore",
+        arity: -4,
+        flags: &["readonly"],
+        first_key: 1,
+        last_key: 1,
+        key_step: 1,
+        key_specs: &[],
+        categories: &["read", "sortedset", "slow"],
+        summary: "Returns members in a sorted set within a range of scores.",
+        group: "sorted_set",
+    },
+    CommandSpec {
+        name: "zrank",
+        arity: 3,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: 1,
+        key_step: 1,
+        key_specs: &[],
+        categories: &["read", "sortedset", "fast"],
+        summary: "Returns the index of a member in a sorted set ordered by ascending scores.",
+        group: "sorted_set",
+    },
+    CommandSpec {
+        name: "zrem",
+        arity: -3,
+        flags: &["write", "fast"],
+        first_key: 1,
+        last_key: 1,
+        key_step: 1,
+        key_specs: &[],
+        categories: &["write", "sortedset", "fast"],
+        summary: "Removes one or more members from a sorted set. Deletes the sorted set if all members were removed.",
+        group: "sorted_set",
+    },
+    CommandSpec {
+        name: "zscore",
+        arity: 3,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: 1,
+        key_step: 1,
+        key_specs: &[],
+        categories: &["read", "sortedset", "fast"],
+        summary: "Returns the score of a member in a sorted set.",
+        group: "sorted_set",
+    },
+];
+
+/// Returns the spec of the command, matching the name case insensitively.
+pub fn lookup(name: &str) -> Option<&'static CommandSpec> {
+    COMMAND_TABLE
+        .iter()
+        .find(|spec| spec.name.eq_ignore_ascii_case(name))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn table_is_sorted() {
+        assert!(COMMAND_TABLE.windows(2).all(|w| w[0].name < w[1].name));
+    }
+
+    #[test]
+    fn only_compiled_in_commands_are_registered() {
+        assert_eq!(lookup("replconf").is_some(), cfg!(feature = "replication"));
+        assert_eq!(lookup("failover").is_some(), cfg!(feature = "replication"));
+        assert_eq!(lookup("replicaof").is_some(), cfg!(feature = "replication"));
+        assert_eq!(lookup("psync").is_some(), cfg!(feature = "replication"));
+        assert_eq!(lookup("wait").is_some(), cfg!(feature = "replication"));
+        assert_eq!(lookup("save").is_some(), cfg!(feature = "persistence"));
+        assert_eq!(lookup("bgsave").is_some(), cfg!(feature = "persistence"));
+        assert_eq!(lookup("eval_ro").is_some(), cfg!(feature = "scripting"));
+        assert_eq!(lookup("fcall_ro").is_some(), cfg!(feature = "scripting"));
+    }
+
+    #[test]
+    fn arity_and_keys() {
+        let set = lookup("SET").unwrap();
+        assert!(!set.arity_matches(2));
+        assert!(set.arity_matches(5));
+        assert_eq!(set.key_positions(5), vec![1]);
+
+        let ping = lookup("ping").unwrap();
+        assert_eq!(ping.key_positions(2), Vec::<usize>::new());
+    }
+}
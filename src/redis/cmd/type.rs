@@ -0,0 +1,105 @@
+use std::sync::Arc;
+
+use super::super::clock::Clock;
+use super::super::resp::{BulkString, SimpleString, Value};
+use super::super::store::Store;
+use super::{consume_args_from_iter, CommandArgParser, ParseCommandError};
+
+#[derive(Debug, Clone)]
+pub struct TypeArg {
+    pub key: BulkString,
+}
+
+impl CommandArgParser for TypeArg {
+    /// TYPE key
+    fn parse_arg(iter: &mut std::slice::Iter<'_, Value>) -> Result<Self, ParseCommandError> {
+        let args = consume_args_from_iter(iter, 1, 0)?;
+        let key = args.first().unwrap().clone();
+
+        Ok(Self { key })
+    }
+}
+
+pub struct Type;
+
+impl Type {
+    /// Returns an instance of TYPE command handler.
+    pub fn handler(map: Arc<Store>, clock: Arc<dyn Clock>) -> TypeHandler {
+        TypeHandler { map, clock }
+    }
+
+    /// Returns TYPE as a Command in the form of Value.
+    pub fn command_value(arg: TypeArg) -> Value {
+        let v = vec![Value::BulkString("TYPE".into()), Value::BulkString(arg.key)];
+        Value::Array(v.into())
+    }
+}
+
+#[derive(Debug)]
+pub struct TypeHandler {
+    map: Arc<Store>,
+    clock: Arc<dyn Clock>,
+}
+
+impl TypeHandler {
+    /// Returns the type of the value held by the key, e.g. `string`, or `none` if the key
+    /// doesn't exist or has expired.
+    pub fn handle(&self, arg: TypeArg) -> Value {
+        let key = arg.key.as_bytes().unwrap_or_default();
+        let now = self.clock.now();
+        let name = match self.map.read(key).get(key) {
+            Some(data) if !data.expired_at(now) => data.value.type_name(),
+            _ => "none",
+        };
+        Value::SimpleString(SimpleString::from(name))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn command() {
+        let val = Type::command_value(TypeArg { key: "key".into() });
+
+        assert_eq!(
+            val.array().unwrap().values().unwrap().to_vec(),
+            vec![
+                Value::BulkString("TYPE".into()),
+                Value::BulkString("key".into()),
+            ]
+        )
+    }
+}
+
+#[cfg(test)]
+mod handler_test {
+    use std::time::Duration;
+
+    use super::super::super::clock::TestClock;
+    use super::super::super::handler::StoredData;
+    use super::super::super::key::Key;
+    use super::*;
+
+    #[test]
+    fn handle_type() {
+        let clock = Arc::new(TestClock::default());
+        let deadline = clock.now() + Duration::from_millis(100);
+        let map = Arc::new(Store::from_iter([
+            (Key::from("str"), StoredData::new("a".into(), None)),
+            (
+                Key::from("soon"),
+                StoredData::new("b".into(), Some(deadline)),
+            ),
+        ]));
+        let handler = Type::handler(map, clock.clone());
+        let type_of = |key: &str| handler.handle(TypeArg { key: key.into() });
+
+        assert_eq!(type_of("str"), Value::SimpleString("string".into()));
+        assert_eq!(type_of("soon"), Value::SimpleString("string".into()));
+        assert_eq!(type_of("missing"), Value::SimpleString("none".into()));
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(type_of("soon"), Value::SimpleString("none".into()));
+    }
+}
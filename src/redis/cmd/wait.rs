@@ -0,0 +1,191 @@
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use thiserror::Error;
+
+use super::super::replication::ReplicationState;
+use super::super::reply::{Deferred, Reply};
+use super::super::resp::{BulkString, Integer, Value};
+use super::{
+    bulk_string_to_string, consume_args_from_iter, CommandArgParser, ParseCommandError, ReplConf,
+    ReplConfArg, ReplConfArgConfig,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum WaitError {
+    #[error("WAIT cannot be used with replica instances.")]
+    Replica,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct WaitArg {
+    pub numreplicas: usize,
+
+    /// Milliseconds to wait for, 0 to wait forever.
+    pub timeout: u64,
+}
+
+impl CommandArgParser for WaitArg {
+    /// WAIT numreplicas timeout
+    fn parse_arg(iter: &mut std::slice::Iter<'_, Value>) -> Result<Self, ParseCommandError> {
+        fn parse<T: FromStr>(bs: &BulkString) -> Result<T, ParseCommandError> {
+            bulk_string_to_string(bs)?
+                .parse()
+                .map_err(|_| ParseCommandError::InvalidArgument(Value::BulkString(bs.clone())))
+        }
+
+        let args = consume_args_from_iter(iter, 2, 0)?;
+        Ok(Self {
+            numreplicas: parse(&args[0])?,
+            timeout: parse(&args[1])?,
+        })
+    }
+}
+
+pub struct Wait;
+
+impl Wait {
+    /// Returns an instance of WAIT command handler.
+    pub fn handler(replication: Option<Arc<ReplicationState>>) -> WaitHandler {
+        WaitHandler { replication }
+    }
+
+    /// Returns WAIT as a Command in the form of Value.
+    pub fn command_value(arg: WaitArg) -> Value {
+        let v = vec![
+            Value::BulkString("WAIT".into()),
+            Value::BulkString(BulkString::from(arg.numreplicas.to_string())),
+            Value::BulkString(BulkString::from(arg.timeout.to_string())),
+        ];
+        Value::Array(v.into())
+    }
+
+    /// Returns `REPLCONF GETACK *`, which asks replicas to acknowledge their offset.
+    pub fn getack() -> Value {
+        ReplConf::command_value(ReplConfArg {
+            config: ReplConfArgConfig::GetAck,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct WaitHandler {
+    /// State of this master, `None` on a replica.
+    replication: Option<Arc<ReplicationState>>,
+}
+
+impl WaitHandler {
+    /// Replies how many replicas acknowledged every write propagated so far, once at least
+    /// `numreplicas` did or the timeout passed.
+    ///
+    /// Replicas that haven't acknowledged the current offset yet are asked to with
+    /// `REPLCONF GETACK *`.
+    pub fn handle(&self, arg: WaitArg) -> Result<Reply, WaitError> {
+        let replication = self.replication.clone().ok_or(WaitError::Replica)?;
+        let offset = {
+            let mut stream = replication.stream();
+            let offset = stream.offset();
+            if stream.acked(offset) < arg.numreplicas {
+                stream.propagate(&Wait::getack());
+            }
+            offset
+        };
+
+        let timeout = (arg.timeout > 0).then(|| Duration::from_millis(arg.timeout));
+        Ok(Reply::Deferred(Deferred::new(async move {
+            let acked = replication.wait(arg.numreplicas, offset, timeout).await;
+            Value::Integer(Integer::new(acked as i64))
+        })))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::Command;
+    use super::*;
+
+    #[test]
+    fn parse_wait() {
+        let arg = WaitArg {
+            numreplicas: 2,
+            timeout: 500,
+        };
+        match Command::try_from(Wait::command_value(arg.clone())) {
+            Ok(Command::Wait(parsed)) => assert_eq!(parsed, arg),
+            other => panic!("Unexpected parse result {other:?}"),
+        }
+
+        let value = Value::Array(
+            vec![
+                Value::BulkString("WAIT".into()),
+                Value::BulkString("1".into()),
+                Value::BulkString("-1".into()),
+            ]
+            .into(),
+        );
+        assert!(matches!(
+            Command::try_from(value),
+            Err(ParseCommandError::InvalidArgument(_))
+        ));
+    }
+}
+
+#[cfg(test)]
+mod handler_test {
+    use super::super::super::clients::ClientRegistry;
+    use super::*;
+
+    #[tokio::test]
+    async fn handle_wait() {
+        let registry = Arc::new(ClientRegistry::new());
+        let mut replica = registry.register(None, None);
+        let mut rx = replica.take_receiver().unwrap();
+        let link = replica.state().lock().link().unwrap();
+        let replication = Arc::new(ReplicationState::new());
+        replication.attach(link, None, |_| Reply::Nothing);
+        let handler = Wait::handler(Some(replication.clone()));
+        let ping = Value::Array(vec![Value::BulkString("PING".into())].into());
+        replication.stream().propagate(&ping);
+
+        // The replica is asked to acknowledge the offset, which doesn't count the request.
+        let arg = WaitArg {
+            numreplicas: 1,
+            timeout: 0,
+        };
+        let Ok(Reply::Deferred(deferred)) = handler.handle(arg) else {
+            panic!("Handle wait returned no deferred reply");
+        };
+        assert!(matches!(rx.recv().await, Some(Reply::Nothing)));
+        assert!(matches!(rx.recv().await, Some(Reply::Value(value)) if value == ping));
+        assert!(matches!(rx.recv().await, Some(Reply::Value(value)) if value == Wait::getack()));
+        replication.ack(replica.id(), replication.offset());
+        assert_eq!(deferred.value().await, Value::Integer(Integer::new(1)));
+
+        // The replica already acknowledged the offset.
+        let arg = WaitArg {
+            numreplicas: 1,
+            timeout: 10,
+        };
+        let Ok(Reply::Deferred(deferred)) = handler.handle(arg) else {
+            panic!("Handle wait returned no deferred reply");
+        };
+        assert_eq!(deferred.value().await, Value::Integer(Integer::new(1)));
+        assert!(rx.try_recv().is_err());
+
+        // Too few replicas, so it times out with the ones that acknowledged.
+        let arg = WaitArg {
+            numreplicas: 2,
+            timeout: 10,
+        };
+        let Ok(Reply::Deferred(deferred)) = handler.handle(arg.clone()) else {
+            panic!("Handle wait returned no deferred reply");
+        };
+        assert_eq!(deferred.value().await, Value::Integer(Integer::new(1)));
+
+        let err = Wait::handler(None)
+            .handle(arg)
+            .expect_err("Handle wait no error");
+        assert_eq!(err, WaitError::Replica);
+    }
+}
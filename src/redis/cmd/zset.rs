@@ -0,0 +1,194 @@
+//! Commands on sorted sets, the members of which are ordered by their score.
+
+mod zadd;
+pub use zadd::*;
+mod zcard;
+pub use zcard::*;
+mod zrange;
+pub use zrange::*;
+mod zrem;
+pub use zrem::*;
+mod zscore;
+pub use zscore::*;
+
+use std::time::SystemTime;
+
+use super::super::handler::HandleCommandError;
+use super::super::resp::{BulkString, Value};
+use super::super::store::Store;
+use super::super::zset::SortedSet;
+use super::ParseCommandError;
+
+/// Calls `f` with the sorted set held by the key, returning `None` if the key doesn't exist.
+fn read_zset<T>(
+    map: &Store,
+    now: SystemTime,
+    key: &[u8],
+    f: impl FnOnce(&SortedSet) -> T,
+) -> Result<Option<T>, HandleCommandError> {
+    match map.read(key).get(key) {
+        Some(data) if !data.expired_at(now) => {
+            let zset = data.value.as_zset().ok_or(HandleCommandError::WrongType)?;
+            Ok(Some(f(zset)))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Parses a score, which may be `inf` or `-inf` but never NaN. Only those words parse to
+/// an infinity, a number too large for a double like `1e400` isn't a valid score.
+fn parse_score(s: &str) -> Option<f64> {
+    let score = s.parse::<f64>().ok().filter(|score| !score.is_nan())?;
+    if score.is_infinite() && !["inf", "+inf", "-inf"].contains(&s.to_lowercase().as_str()) {
+        return None;
+    }
+    Some(score)
+}
+
+/// Formats the score as Redis does: integers without a fraction, infinities as `inf` and
+/// `-inf`, and anything else in the shortest form that parses back to the same score. That
+/// form is written like `%.17g` would, with an exponent, e.g. `1e+308` or `1.5e-07`, when
+/// it is below -4 or from 17 up.
+fn format_score(score: f64) -> String {
+    // Integers up to half the range of `long long` are printed as such.
+    const MAX_INTEGER: f64 = (i64::MAX / 2) as f64;
+    if score.is_infinite() {
+        return if score > 0.0 { "inf" } else { "-inf" }.to_string();
+    }
+    if score.fract() == 0.0 && score.abs() <= MAX_INTEGER {
+        return (score as i64).to_string();
+    }
+
+    let scientific = format!("{score:e}");
+    let (mantissa, exponent) = scientific.split_once('e').expect("Formatted with exponent");
+    let exponent: i32 = exponent.parse().expect("Formatted exponent");
+    if (-4..17).contains(&exponent) {
+        score.to_string()
+    } else {
+        let sign = if exponent < 0 { '-' } else { '+' };
+        format!("{mantissa}e{sign}{:02}", exponent.abs())
+    }
+}
+
+fn score_value(score: f64) -> Value {
+    Value::BulkString(format_score(score).into())
+}
+
+/// A bound of a score range, `(` before the score making it exclusive.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct ScoreBound {
+    pub score: f64,
+    pub exclusive: bool,
+}
+
+impl ScoreBound {
+    pub fn inclusive(score: f64) -> Self {
+        Self {
+            score,
+            exclusive: false,
+        }
+    }
+
+    pub fn exclusive(score: f64) -> Self {
+        Self {
+            score,
+            exclusive: true,
+        }
+    }
+
+    fn parse(bs: &BulkString) -> Result<Self, ParseCommandError> {
+        let s = bs.as_str().ok_or(ParseCommandError::MinMaxNotFloat)?;
+        let (s, exclusive) = match s.strip_prefix('(') {
+            Some(s) => (s, true),
+            None => (s.as_str(), false),
+        };
+        let score = parse_score(s).ok_or(ParseCommandError::MinMaxNotFloat)?;
+        // Adding 0 turns -0 into 0, like the scores of members.
+        Ok(Self {
+            score: score + 0.0,
+            exclusive,
+        })
+    }
+
+    /// Returns whether the score is within this bound taken as the minimum.
+    fn fits_min(&self, score: f64) -> bool {
+        if self.exclusive {
+            self.score < score
+        } else {
+            self.score <= score
+        }
+    }
+
+    /// Returns whether the score is within this bound taken as the maximum.
+    fn fits_max(&self, score: f64) -> bool {
+        if self.exclusive {
+            score < self.score
+        } else {
+            score <= self.score
+        }
+    }
+
+    fn to_bulk_string(self) -> BulkString {
+        let score = format_score(self.score);
+        if self.exclusive {
+            format!("({score}").into()
+        } else {
+            score.into()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_and_format_scores() {
+        for (s, score) in [
+            ("1", 1.0),
+            ("1.5", 1.5),
+            ("-2.25", -2.25),
+            ("inf", f64::INFINITY),
+            ("-inf", f64::NEG_INFINITY),
+        ] {
+            assert_eq!(parse_score(s), Some(score));
+            assert_eq!(format_score(score), s);
+        }
+        for (score, s) in [
+            (1e308, "1e+308"),
+            (-1.5e20, "-1.5e+20"),
+            (1e17, "100000000000000000"),
+            (123456.789, "123456.789"),
+            (0.0001, "0.0001"),
+            (1.5e-7, "1.5e-07"),
+            (-2.5e-300, "-2.5e-300"),
+            (f64::MIN_POSITIVE, "2.2250738585072014e-308"),
+        ] {
+            assert_eq!(format_score(score), s);
+            assert_eq!(parse_score(s), Some(score));
+        }
+        assert_eq!(parse_score("+inf"), Some(f64::INFINITY));
+        assert_eq!(parse_score("nan"), None);
+        assert_eq!(parse_score("1e400"), None);
+        assert_eq!(parse_score("-1e400"), None);
+        assert_eq!(parse_score("infinity"), None);
+        assert_eq!(parse_score("one"), None);
+
+        assert_eq!(
+            ScoreBound::parse(&"(1.5".into()).unwrap(),
+            ScoreBound::exclusive(1.5)
+        );
+        assert_eq!(
+            ScoreBound::parse(&"-inf".into()).unwrap(),
+            ScoreBound::inclusive(f64::NEG_INFINITY)
+        );
+        assert!(matches!(
+            ScoreBound::parse(&"(".into()),
+            Err(ParseCommandError::MinMaxNotFloat)
+        ));
+        assert!(ScoreBound::exclusive(1.0).fits_min(1.5));
+        assert!(!ScoreBound::exclusive(1.0).fits_min(1.0));
+        assert!(ScoreBound::inclusive(1.0).fits_max(1.0));
+        assert!(!ScoreBound::inclusive(1.0).fits_max(1.5));
+    }
+}
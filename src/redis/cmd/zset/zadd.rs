@@ -0,0 +1,396 @@
+use std::sync::Arc;
+
+use super::super::super::clock::Clock;
+use super::super::super::events::{KeyEvent, KeyEvents};
+use super::super::super::handler::{HandleCommandError, StoredData, StoredValue};
+use super::super::super::key::Key;
+use super::super::super::listpack::ListpackLimits;
+use super::super::super::resp::{BulkString, Integer, Value};
+use super::super::super::store::Store;
+use super::super::super::zset::SortedSet;
+use super::super::{consume_variadic_args_from_iter, CommandArgParser, ParseCommandError};
+use super::{format_score, parse_score, score_value};
+
+/// Condition of ZADD on adding or updating a member.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ZAddCondition {
+    /// Only add new members.
+    Nx,
+    /// Only update existing members.
+    Xx,
+    /// Only update a member to a greater score.
+    Gt,
+    /// Only update a member to a lower score.
+    Lt,
+}
+
+impl ZAddCondition {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Nx => "NX",
+            Self::Xx => "XX",
+            Self::Gt => "GT",
+            Self::Lt => "LT",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct ZAddArg {
+    pub key: BulkString,
+    pub conditions: Vec<ZAddCondition>,
+    /// Whether the reply counts the members updated along with those added.
+    pub changed: bool,
+    /// Whether the score is added to that of the member, replying with the new score.
+    pub incr: bool,
+    pub members: Vec<(f64, BulkString)>,
+}
+
+impl CommandArgParser for ZAddArg {
+    /// ZADD key [NX | XX] [GT | LT] [CH] [INCR] score member [score member ...]
+    fn parse_arg(iter: &mut std::slice::Iter<'_, Value>) -> Result<Self, ParseCommandError> {
+        let args = consume_variadic_args_from_iter(iter, 3)?;
+        let key = args[0].clone();
+
+        let mut conditions = Vec::new();
+        let mut changed = false;
+        let mut incr = false;
+        let mut rest = &args[1..];
+        while let Some(arg) = rest.first() {
+            let condition = match arg.as_str().unwrap_or_default().to_lowercase().as_str() {
+                "nx" => ZAddCondition::Nx,
+                "xx" => ZAddCondition::Xx,
+                "gt" => ZAddCondition::Gt,
+                "lt" => ZAddCondition::Lt,
+                "ch" => {
+                    changed = true;
+                    rest = &rest[1..];
+                    continue;
+                }
+                "incr" => {
+                    incr = true;
+                    rest = &rest[1..];
+                    continue;
+                }
+                _ => break,
+            };
+            if !conditions.contains(&condition) {
+                conditions.push(condition);
+            }
+            rest = &rest[1..];
+        }
+
+        if rest.is_empty() || rest.len() % 2 != 0 {
+            let last = args[args.len() - 1].clone();
+            return Err(ParseCommandError::InvalidArgument(Value::BulkString(last)));
+        }
+        let has = |condition| conditions.contains(&condition);
+        if has(ZAddCondition::Nx) && has(ZAddCondition::Xx) {
+            return Err(ParseCommandError::IncompatibleOptions("XX and NX"));
+        }
+        let ordered = has(ZAddCondition::Gt) as u8 + has(ZAddCondition::Lt) as u8;
+        if ordered > 1 || (ordered > 0 && has(ZAddCondition::Nx)) {
+            return Err(ParseCommandError::IncompatibleOptions("GT, LT, and/or NX"));
+        }
+        if incr && rest.len() > 2 {
+            return Err(ParseCommandError::IncrMultiplePairs);
+        }
+
+        let members = rest
+            .chunks(2)
+            .map(|pair| {
+                let score = pair[0].as_str().as_deref().and_then(parse_score);
+                Ok((score.ok_or(ParseCommandError::NotFloat)?, pair[1].clone()))
+            })
+            .collect::<Result<_, ParseCommandError>>()?;
+
+        Ok(Self {
+            key,
+            conditions,
+            changed,
+            incr,
+            members,
+        })
+    }
+}
+
+pub struct ZAdd;
+
+impl ZAdd {
+    /// Returns an instance of ZADD command handler.
+    pub fn handler(map: Arc<Store>, clock: Arc<dyn Clock>) -> ZAddHandler {
+        ZAddHandler {
+            map,
+            clock,
+            events: None,
+            limits: ListpackLimits::DEFAULT,
+        }
+    }
+
+    /// Returns ZADD as a Command in the form of Value.
+    pub fn command_value(arg: ZAddArg) -> Value {
+        let mut v = vec![Value::BulkString("ZADD".into()), Value::BulkString(arg.key)];
+        let options = arg
+            .conditions
+            .iter()
+            .map(ZAddCondition::name)
+            .chain(arg.changed.then_some("CH"))
+            .chain(arg.incr.then_some("INCR"));
+        v.extend(options.map(|option| Value::BulkString(option.into())));
+        for (score, member) in arg.members {
+            v.push(Value::BulkString(format_score(score).into()));
+            v.push(Value::BulkString(member));
+        }
+        Value::Array(v.into())
+    }
+}
+
+pub struct ZAddHandler {
+    map: Arc<Store>,
+    clock: Arc<dyn Clock>,
+    events: Option<Arc<KeyEvents>>,
+    limits: ListpackLimits,
+}
+
+impl ZAddHandler {
+    /// Tells the listeners about keys with members added or updated.
+    pub fn with_events(mut self, events: Arc<KeyEvents>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Limits up to which the sorted set stays packed, see `zset-max-listpack-entries` and
+    /// `zset-max-listpack-value`.
+    pub fn with_listpack_limits(mut self, limits: ListpackLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Adds the members to the sorted set, or updates their scores, as far as the
+    /// conditions allow. A missing or expired key starts as an empty sorted set.
+    ///
+    /// Replies with how many members were added, counting those updated too with CH. With
+    /// INCR, replies with the new score of the member instead, or nil if not allowed.
+    pub fn handle(&self, arg: ZAddArg) -> Result<Value, HandleCommandError> {
+        self.handle_counting(arg).map(|(resp, _)| resp)
+    }
+
+    /// Handles ZADD like [`Self::handle`], also returning how many members were added or
+    /// updated whatever the reply counts.
+    pub fn handle_counting(&self, arg: ZAddArg) -> Result<(Value, u64), HandleCommandError> {
+        let key = arg.key.as_bytes().unwrap_or_default();
+        let now = self.clock.now();
+        let has = |condition| arg.conditions.contains(&condition);
+        let unchanged = if arg.incr {
+            Value::BulkString(BulkString::null())
+        } else {
+            Value::Integer(Integer::new(0))
+        };
+
+        let mut shard = self.map.write(key);
+        if shard.get(key).is_some_and(|data| data.expired_at(now)) {
+            shard.remove(key);
+        }
+        if shard.get(key).is_none() {
+            if has(ZAddCondition::Xx) {
+                return Ok((unchanged, 0));
+            }
+            let zset = StoredValue::SortedSet(SortedSet::new());
+            shard.insert(Key::new(key), StoredData::new(zset, None));
+        }
+        let zset = shard
+            .value_mut(key)
+            .and_then(StoredValue::as_zset_mut)
+            .ok_or(HandleCommandError::WrongType)?;
+
+        let (mut added, mut updated) = (0, 0);
+        let mut last_score = None;
+        for (score, member) in arg.members {
+            let old = zset.score(&member);
+            let score = match old {
+                Some(old) if arg.incr => old + score,
+                _ => score,
+            };
+            if score.is_nan() {
+                return Err(HandleCommandError::ScoreNaN);
+            }
+            let allowed = match old {
+                None => !has(ZAddCondition::Xx),
+                Some(old) => {
+                    !(has(ZAddCondition::Nx)
+                        || (has(ZAddCondition::Gt) && score <= old)
+                        || (has(ZAddCondition::Lt) && score >= old))
+                }
+            };
+            if !allowed {
+                continue;
+            }
+
+            match old {
+                None => added += 1,
+                Some(old) if old != score => updated += 1,
+                Some(_) => (),
+            }
+            zset.insert(member, score, self.limits);
+            last_score = Some(score);
+        }
+        drop(shard);
+
+        if let Some(events) = self.events.as_ref().filter(|_| added + updated > 0) {
+            let event = if arg.incr {
+                KeyEvent::ZIncrBy
+            } else {
+                KeyEvent::ZAdd
+            };
+            events.notify(key, event);
+        }
+        let changes = (added + updated) as u64;
+        if arg.incr {
+            return Ok((last_score.map(score_value).unwrap_or(unchanged), changes));
+        }
+        let count = if arg.changed { added + updated } else { added };
+        Ok((Value::Integer(Integer::new(count)), changes))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::super::super::test_util::command;
+    use super::super::super::Command;
+    use super::*;
+
+    #[test]
+    fn parse_zadd() {
+        let arg = ZAddArg {
+            key: "zset".into(),
+            conditions: vec![ZAddCondition::Xx, ZAddCondition::Gt],
+            changed: true,
+            incr: false,
+            members: vec![(1.5, "a".into()), (f64::NEG_INFINITY, "b".into())],
+        };
+        match Command::try_from(ZAdd::command_value(arg.clone())) {
+            Ok(Command::ZAdd(parsed)) => assert_eq!(parsed, arg),
+            other => panic!("Unexpected parse result {other:?}"),
+        }
+
+        assert!(matches!(
+            Command::try_from(command(["ZADD", "zset", "NX", "1"])),
+            Err(ParseCommandError::InvalidArgument(_))
+        ));
+        assert!(matches!(
+            Command::try_from(command(["ZADD", "zset", "NX", "XX", "1", "a"])),
+            Err(ParseCommandError::IncompatibleOptions("XX and NX"))
+        ));
+        assert!(matches!(
+            Command::try_from(command(["ZADD", "zset", "GT", "NX", "1", "a"])),
+            Err(ParseCommandError::IncompatibleOptions(_))
+        ));
+        assert!(matches!(
+            Command::try_from(command(["ZADD", "zset", "INCR", "1", "a", "2", "b"])),
+            Err(ParseCommandError::IncrMultiplePairs)
+        ));
+        assert!(matches!(
+            Command::try_from(command(["ZADD", "zset", "nan", "a"])),
+            Err(ParseCommandError::NotFloat)
+        ));
+        assert!(matches!(
+            Command::try_from(command(["ZADD", "zset", "1e400", "a"])),
+            Err(ParseCommandError::NotFloat)
+        ));
+    }
+}
+
+#[cfg(test)]
+mod handler_test {
+    use super::super::super::super::clock;
+    use super::*;
+
+    fn zadd(
+        map: &Arc<Store>,
+        conditions: &[ZAddCondition],
+        incr: bool,
+        members: &[(f64, &str)],
+    ) -> Result<Value, HandleCommandError> {
+        let arg = ZAddArg {
+            key: "zset".into(),
+            conditions: conditions.to_vec(),
+            changed: true,
+            incr,
+            members: members
+                .iter()
+                .map(|&(score, member)| (score, member.into()))
+                .collect(),
+        };
+        ZAdd::handler(map.clone(), clock::system()).handle(arg)
+    }
+
+    fn score(map: &Store, member: &str) -> Option<f64> {
+        map.read(b"zset")
+            .get(b"zset".as_slice())
+            .and_then(|data| data.value.as_zset()?.score(&member.into()))
+    }
+
+    #[test]
+    fn handle_zadd_conditions() {
+        let map = Arc::new(Store::default());
+        let changed = |n| Value::Integer(Integer::new(n));
+
+        // XX never creates the key.
+        assert_eq!(
+            zadd(&map, &[ZAddCondition::Xx], false, &[(1.0, "a")]).unwrap(),
+            changed(0)
+        );
+        assert!(map.is_empty());
+
+        assert_eq!(
+            zadd(&map, &[], false, &[(1.0, "a"), (2.0, "b")]).unwrap(),
+            changed(2)
+        );
+        assert_eq!(
+            zadd(&map, &[ZAddCondition::Nx], false, &[(5.0, "a"), (3.0, "c")]).unwrap(),
+            changed(1)
+        );
+        assert_eq!(score(&map, "a"), Some(1.0));
+        assert_eq!(
+            zadd(&map, &[ZAddCondition::Gt], false, &[(0.0, "a"), (4.0, "b")]).unwrap(),
+            changed(1)
+        );
+        assert_eq!(
+            zadd(&map, &[ZAddCondition::Lt], false, &[(0.0, "a"), (5.0, "b")]).unwrap(),
+            changed(1)
+        );
+        assert_eq!((score(&map, "a"), score(&map, "b")), (Some(0.0), Some(4.0)));
+    }
+
+    #[test]
+    fn handle_zadd_incr() {
+        let map = Arc::new(Store::default());
+        assert_eq!(
+            zadd(&map, &[], true, &[(1.5, "a")]).unwrap(),
+            Value::BulkString("1.5".into())
+        );
+        assert_eq!(
+            zadd(&map, &[], true, &[(f64::INFINITY, "a")]).unwrap(),
+            Value::BulkString("inf".into())
+        );
+        assert!(matches!(
+            zadd(&map, &[], true, &[(f64::NEG_INFINITY, "a")]),
+            Err(HandleCommandError::ScoreNaN)
+        ));
+        assert_eq!(
+            zadd(&map, &[ZAddCondition::Nx], true, &[(1.0, "a")]).unwrap(),
+            Value::BulkString(BulkString::null())
+        );
+    }
+
+    #[test]
+    fn handle_zadd_wrong_type() {
+        let map = Arc::new(Store::default());
+        map.write(b"zset")
+            .insert("zset".into(), StoredData::new("value".into(), None));
+        assert!(matches!(
+            zadd(&map, &[], false, &[(1.0, "a")]),
+            Err(HandleCommandError::WrongType)
+        ));
+    }
+}
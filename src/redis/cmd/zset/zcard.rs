@@ -0,0 +1,111 @@
+use std::sync::Arc;
+
+use super::super::super::clock::Clock;
+use super::super::super::handler::HandleCommandError;
+use super::super::super::resp::{BulkString, Integer, Value};
+use super::super::super::store::Store;
+use super::super::super::zset::SortedSet;
+use super::super::{consume_args_from_iter, CommandArgParser, ParseCommandError};
+use super::read_zset;
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ZCardArg {
+    pub key: BulkString,
+}
+
+impl CommandArgParser for ZCardArg {
+    /// ZCARD key
+    fn parse_arg(iter: &mut std::slice::Iter<'_, Value>) -> Result<Self, ParseCommandError> {
+        let args = consume_args_from_iter(iter, 1, 0)?;
+
+        Ok(Self {
+            key: args[0].clone(),
+        })
+    }
+}
+
+pub struct ZCard;
+
+impl ZCard {
+    /// Returns an instance of ZCARD command handler.
+    pub fn handler(map: Arc<Store>, clock: Arc<dyn Clock>) -> ZCardHandler {
+        ZCardHandler { map, clock }
+    }
+
+    /// Returns ZCARD as a Command in the form of Value.
+    pub fn command_value(arg: ZCardArg) -> Value {
+        let v = vec![
+            Value::BulkString("ZCARD".into()),
+            Value::BulkString(arg.key),
+        ];
+        Value::Array(v.into())
+    }
+}
+
+#[derive(Debug)]
+pub struct ZCardHandler {
+    map: Arc<Store>,
+    clock: Arc<dyn Clock>,
+}
+
+impl ZCardHandler {
+    /// Returns the number of members of the sorted set held by the key, 0 if it doesn't
+    /// exist.
+    pub fn handle(&self, arg: ZCardArg) -> Result<Value, HandleCommandError> {
+        let key = arg.key.as_bytes().unwrap_or_default();
+        let len = read_zset(&self.map, self.clock.now(), key, SortedSet::len)?;
+        Ok(Value::Integer(Integer::new(len.unwrap_or_default() as i64)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::super::Command;
+    use super::*;
+
+    #[test]
+    fn parse_zcard() {
+        let arg = ZCardArg { key: "zset".into() };
+        match Command::try_from(ZCard::command_value(arg.clone())) {
+            Ok(Command::ZCard(parsed)) => assert_eq!(parsed, arg),
+            other => panic!("Unexpected parse result {other:?}"),
+        }
+
+        let v = Value::Array(vec![Value::BulkString("ZCARD".into())].into());
+        assert!(Command::try_from(v).is_err());
+    }
+}
+
+#[cfg(test)]
+mod handler_test {
+    use std::time::Duration;
+
+    use super::super::super::super::clock::TestClock;
+    use super::super::super::super::handler::{StoredData, StoredValue};
+    use super::*;
+
+    #[test]
+    fn handle_zcard() {
+        let map = Arc::new(Store::default());
+        let clock = Arc::new(TestClock::default());
+        let zcard = ZCard::handler(map.clone(), clock.clone());
+        let card = || zcard.handle(ZCardArg { key: "zset".into() });
+
+        assert_eq!(card().unwrap(), Value::Integer(Integer::new(0)));
+
+        let zset = SortedSet::from_iter([("a".into(), 1.0), ("b".into(), 2.0)]);
+        let expiry = clock.now() + Duration::from_millis(100);
+        map.write(b"zset").insert(
+            "zset".into(),
+            StoredData::new(StoredValue::SortedSet(zset), Some(expiry)),
+        );
+        assert_eq!(card().unwrap(), Value::Integer(Integer::new(2)));
+
+        clock.advance(Duration::from_millis(200));
+        assert_eq!(card().unwrap(), Value::Integer(Integer::new(0)));
+
+        map.write(b"zset")
+            .insert("zset".into(), StoredData::new("value".into(), None));
+        assert!(matches!(card(), Err(HandleCommandError::WrongType)));
+    }
+}
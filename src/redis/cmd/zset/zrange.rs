@@ -0,0 +1,386 @@
+use std::sync::Arc;
+
+use super::super::super::clock::Clock;
+use super::super::super::handler::HandleCommandError;
+use super::super::super::resp::{Array, BulkString, Value};
+use super::super::super::store::Store;
+use super::super::super::zset::SortedSet;
+use super::super::list::list_range;
+use super::super::{
+    bulk_string_to_int64, consume_variadic_args_from_iter, CommandArgParser, ParseCommandError,
+};
+use super::{read_zset, score_value, ScoreBound};
+
+/// What ZRANGE selects the members by.
+#[derive(Debug, PartialEq, Clone)]
+pub enum ZRangeBy {
+    /// Members from the `start` to the `stop` rank, both inclusive. Negative ranks count
+    /// from the end, -1 being the last member.
+    Index { start: i64, stop: i64 },
+    /// Members with scores from `min` to `max`.
+    Score { min: ScoreBound, max: ScoreBound },
+}
+
+/// Arguments of ZRANGE and ZRANGEBYSCORE.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ZRangeArg {
+    pub key: BulkString,
+    pub by: ZRangeBy,
+    /// Whether the members are ordered from the highest score.
+    pub rev: bool,
+    /// Offset and count of the members in range to reply with, a negative count meaning all
+    /// of them after the offset.
+    pub limit: Option<(i64, i64)>,
+    pub with_scores: bool,
+}
+
+impl CommandArgParser for ZRangeArg {
+    /// ZRANGE key start stop [BYSCORE] [REV] [LIMIT offset count] [WITHSCORES]
+    fn parse_arg(iter: &mut std::slice::Iter<'_, Value>) -> Result<Self, ParseCommandError> {
+        Self::parse_with(iter, false)
+    }
+}
+
+impl ZRangeArg {
+    /// ZRANGEBYSCORE key min max [WITHSCORES] [LIMIT offset count]
+    pub fn parse_zrangebyscore(
+        iter: &mut std::slice::Iter<'_, Value>,
+    ) -> Result<Self, ParseCommandError> {
+        Self::parse_with(iter, true)
+    }
+
+    fn parse_with(
+        iter: &mut std::slice::Iter<'_, Value>,
+        by_score: bool,
+    ) -> Result<Self, ParseCommandError> {
+        let args = consume_variadic_args_from_iter(iter, 3)?;
+        let invalid =
+            |arg: &BulkString| ParseCommandError::InvalidArgument(Value::BulkString(arg.clone()));
+
+        let mut scored = by_score;
+        let mut rev = false;
+        let mut limit = None;
+        let mut with_scores = false;
+        let mut options = args[3..].iter();
+        while let Some(arg) = options.next() {
+            match arg.as_str().unwrap_or_default().to_lowercase().as_str() {
+                "byscore" if !by_score => scored = true,
+                "rev" if !by_score => rev = true,
+                "withscores" => with_scores = true,
+                "limit" => {
+                    let mut limit_arg = || {
+                        let arg = options.next().ok_or_else(|| invalid(arg))?;
+                        bulk_string_to_int64(arg)
+                    };
+                    limit = Some((limit_arg()?, limit_arg()?));
+                }
+                _ => return Err(invalid(arg)),
+            }
+        }
+
+        let by = if scored {
+            // The range is given from the highest score in reverse.
+            let (min, max) = if rev {
+                (&args[2], &args[1])
+            } else {
+                (&args[1], &args[2])
+            };
+            ZRangeBy::Score {
+                min: ScoreBound::parse(min)?,
+                max: ScoreBound::parse(max)?,
+            }
+        } else {
+            if limit.is_some() {
+                return Err(invalid(&args[0]));
+            }
+            ZRangeBy::Index {
+                start: bulk_string_to_int64(&args[1])?,
+                stop: bulk_string_to_int64(&args[2])?,
+            }
+        };
+
+        Ok(Self {
+            key: args[0].clone(),
+            by,
+            rev,
+            limit,
+            with_scores,
+        })
+    }
+}
+
+pub struct ZRange;
+
+impl ZRange {
+    /// Returns an instance of the handler shared by ZRANGE and ZRANGEBYSCORE.
+    pub fn handler(map: Arc<Store>, clock: Arc<dyn Clock>) -> ZRangeHandler {
+        ZRangeHandler { map, clock }
+    }
+
+    /// Returns ZRANGE as a Command in the form of Value, which covers ZRANGEBYSCORE too.
+    pub fn command_value(arg: ZRangeArg) -> Value {
+        let (start, stop) = match arg.by {
+            ZRangeBy::Index { start, stop } => (start.to_string().into(), stop.to_string().into()),
+            ZRangeBy::Score { min, max } if arg.rev => (max.to_bulk_string(), min.to_bulk_string()),
+            ZRangeBy::Score { min, max } => (min.to_bulk_string(), max.to_bulk_string()),
+        };
+        let mut v: Vec<BulkString> = vec!["ZRANGE".into(), arg.key, start, stop];
+        if matches!(arg.by, ZRangeBy::Score { .. }) {
+            v.push("BYSCORE".into());
+        }
+        if arg.rev {
+            v.push("REV".into());
+        }
+        if let Some((offset, count)) = arg.limit {
+            v.push("LIMIT".into());
+            v.push(offset.to_string().into());
+            v.push(count.to_string().into());
+        }
+        if arg.with_scores {
+            v.push("WITHSCORES".into());
+        }
+        Value::Array(
+            v.into_iter()
+                .map(Value::BulkString)
+                .collect::<Vec<_>>()
+                .into(),
+        )
+    }
+}
+
+#[derive(Debug)]
+pub struct ZRangeHandler {
+    map: Arc<Store>,
+    clock: Arc<dyn Clock>,
+}
+
+impl ZRangeHandler {
+    /// Returns the members in range, each followed by its score with WITHSCORES. A missing
+    /// key is an empty sorted set.
+    pub fn handle(&self, arg: ZRangeArg) -> Result<Value, HandleCommandError> {
+        let key = arg.key.as_bytes().unwrap_or_default();
+        let members = read_zset(&self.map, self.clock.now(), key, |zset| range(zset, &arg))?;
+
+        let mut v = Vec::new();
+        for (member, score) in members.unwrap_or_default() {
+            v.push(Value::BulkString(member));
+            if arg.with_scores {
+                v.push(score_value(score));
+            }
+        }
+        Ok(Value::Array(Array::new(v)))
+    }
+}
+
+/// Returns the members of the sorted set in the range of the arg, with their scores.
+fn range(zset: &SortedSet, arg: &ZRangeArg) -> Vec<(BulkString, f64)> {
+    let members: Box<dyn Iterator<Item = (BulkString, f64)>> = if arg.rev {
+        Box::new(zset.iter().rev())
+    } else {
+        Box::new(zset.iter())
+    };
+    let members: Box<dyn Iterator<Item = (BulkString, f64)>> = match arg.by {
+        ZRangeBy::Index { start, stop } => match list_range(zset.len(), start, stop) {
+            Some((start, stop)) => Box::new(members.skip(start).take(stop - start + 1)),
+            None => return vec![],
+        },
+        // The members are walked from the highest score in reverse.
+        ZRangeBy::Score { min, max } if arg.rev => Box::new(
+            members
+                .skip_while(move |&(_, score)| !max.fits_max(score))
+                .take_while(move |&(_, score)| min.fits_min(score)),
+        ),
+        ZRangeBy::Score { min, max } => Box::new(
+            members
+                .skip_while(move |&(_, score)| !min.fits_min(score))
+                .take_while(move |&(_, score)| max.fits_max(score)),
+        ),
+    };
+
+    let (offset, count) = arg.limit.unwrap_or((0, -1));
+    if offset < 0 {
+        return vec![];
+    }
+    let count = usize::try_from(count).unwrap_or(usize::MAX);
+    members.skip(offset as usize).take(count).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::super::super::test_util::command;
+    use super::super::super::Command;
+    use super::*;
+
+    #[test]
+    fn parse_zrange() {
+        for arg in [
+            ZRangeArg {
+                key: "zset".into(),
+                by: ZRangeBy::Index { start: 0, stop: -1 },
+                rev: true,
+                limit: None,
+                with_scores: true,
+            },
+            ZRangeArg {
+                key: "zset".into(),
+                by: ZRangeBy::Score {
+                    min: ScoreBound::exclusive(1.5),
+                    max: ScoreBound::inclusive(f64::INFINITY),
+                },
+                rev: true,
+                limit: Some((1, -1)),
+                with_scores: false,
+            },
+        ] {
+            match Command::try_from(ZRange::command_value(arg.clone())) {
+                Ok(Command::ZRange(parsed)) => assert_eq!(parsed, arg),
+                other => panic!("Unexpected parse result {other:?}"),
+            }
+        }
+
+        let parsed = Command::try_from(command([
+            "ZRANGEBYSCORE",
+            "zset",
+            "-inf",
+            "(2",
+            "WITHSCORES",
+            "LIMIT",
+            "0",
+            "1",
+        ]));
+        let arg = ZRangeArg {
+            key: "zset".into(),
+            by: ZRangeBy::Score {
+                min: ScoreBound::inclusive(f64::NEG_INFINITY),
+                max: ScoreBound::exclusive(2.0),
+            },
+            rev: false,
+            limit: Some((0, 1)),
+            with_scores: true,
+        };
+        match parsed {
+            Ok(Command::ZRangeByScore(parsed)) => assert_eq!(parsed, arg),
+            other => panic!("Unexpected parse result {other:?}"),
+        }
+
+        assert!(matches!(
+            Command::try_from(command(["ZRANGEBYSCORE", "zset", "0", "1", "REV"])),
+            Err(ParseCommandError::InvalidArgument(_))
+        ));
+        assert!(matches!(
+            Command::try_from(command(["ZRANGE", "zset", "0", "1", "LIMIT", "0", "1"])),
+            Err(ParseCommandError::InvalidArgument(_))
+        ));
+        assert!(matches!(
+            Command::try_from(command(["ZRANGEBYSCORE", "zset", "low", "1"])),
+            Err(ParseCommandError::MinMaxNotFloat)
+        ));
+    }
+}
+
+#[cfg(test)]
+mod handler_test {
+    use super::super::super::super::clock;
+    use super::super::super::super::handler::{StoredData, StoredValue};
+    use super::*;
+
+    fn values(values: &[&str]) -> Value {
+        let v = values
+            .iter()
+            .map(|&v| Value::BulkString(v.into()))
+            .collect();
+        Value::Array(Array::new(v))
+    }
+
+    fn zrange(by: ZRangeBy, rev: bool, limit: Option<(i64, i64)>) -> Value {
+        let map = Arc::new(Store::default());
+        let zset = SortedSet::from_iter([
+            ("a".into(), 1.0),
+            ("b".into(), 2.0),
+            ("c".into(), 2.0),
+            ("d".into(), f64::INFINITY),
+        ]);
+        map.write(b"zset").insert(
+            "zset".into(),
+            StoredData::new(StoredValue::SortedSet(zset), None),
+        );
+        let arg = ZRangeArg {
+            key: "zset".into(),
+            by,
+            rev,
+            limit,
+            with_scores: false,
+        };
+        ZRange::handler(map, clock::system()).handle(arg).unwrap()
+    }
+
+    #[test]
+    fn handle_zrange_by_index() {
+        let by = |start, stop| ZRangeBy::Index { start, stop };
+        assert_eq!(
+            zrange(by(0, -1), false, None),
+            values(&["a", "b", "c", "d"])
+        );
+        assert_eq!(zrange(by(1, 2), false, None), values(&["b", "c"]));
+        assert_eq!(zrange(by(0, 1), true, None), values(&["d", "c"]));
+        assert_eq!(zrange(by(-1, 10), false, None), values(&["d"]));
+        assert_eq!(zrange(by(3, 1), false, None), values(&[]));
+    }
+
+    #[test]
+    fn handle_zrange_by_score() {
+        let by = |min, max| ZRangeBy::Score { min, max };
+        let (inclusive, exclusive) = (ScoreBound::inclusive, ScoreBound::exclusive);
+        assert_eq!(
+            zrange(by(inclusive(2.0), inclusive(f64::INFINITY)), false, None),
+            values(&["b", "c", "d"])
+        );
+        assert_eq!(
+            zrange(by(exclusive(1.0), exclusive(f64::INFINITY)), false, None),
+            values(&["b", "c"])
+        );
+        assert_eq!(
+            zrange(by(inclusive(1.0), inclusive(2.0)), true, None),
+            values(&["c", "b", "a"])
+        );
+        assert_eq!(
+            zrange(
+                by(inclusive(f64::NEG_INFINITY), inclusive(f64::INFINITY)),
+                false,
+                Some((1, 2))
+            ),
+            values(&["b", "c"])
+        );
+        assert_eq!(
+            zrange(by(inclusive(3.0), inclusive(2.0)), false, None),
+            values(&[])
+        );
+    }
+
+    #[test]
+    fn handle_zrange_with_scores() {
+        let map = Arc::new(Store::default());
+        let zset = SortedSet::from_iter([("a".into(), 1.5), ("b".into(), f64::NEG_INFINITY)]);
+        map.write(b"zset").insert(
+            "zset".into(),
+            StoredData::new(StoredValue::SortedSet(zset), None),
+        );
+        let arg = ZRangeArg {
+            key: "zset".into(),
+            by: ZRangeBy::Index { start: 0, stop: -1 },
+            rev: false,
+            limit: None,
+            with_scores: true,
+        };
+        let handler = ZRange::handler(map, clock::system());
+        assert_eq!(
+            handler.handle(arg.clone()).unwrap(),
+            values(&["b", "-inf", "a", "1.5"])
+        );
+        let missing = ZRangeArg {
+            key: "missing".into(),
+            ..arg
+        };
+        assert_eq!(handler.handle(missing).unwrap(), values(&[]));
+    }
+}
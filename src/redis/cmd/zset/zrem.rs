@@ -0,0 +1,154 @@
+use std::sync::Arc;
+
+use super::super::super::clock::Clock;
+use super::super::super::events::{KeyEvent, KeyEvents};
+use super::super::super::handler::{HandleCommandError, StoredValue};
+use super::super::super::resp::{BulkString, Integer, Value};
+use super::super::super::store::Store;
+use super::super::{consume_variadic_args_from_iter, CommandArgParser, ParseCommandError};
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ZRemArg {
+    pub key: BulkString,
+    pub members: Vec<BulkString>,
+}
+
+impl CommandArgParser for ZRemArg {
+    /// ZREM key member [member ...]
+    fn parse_arg(iter: &mut std::slice::Iter<'_, Value>) -> Result<Self, ParseCommandError> {
+        let mut args = consume_variadic_args_from_iter(iter, 2)?;
+        let key = args.remove(0);
+
+        Ok(Self { key, members: args })
+    }
+}
+
+pub struct ZRem;
+
+impl ZRem {
+    /// Returns an instance of ZREM command handler.
+    pub fn handler(map: Arc<Store>, clock: Arc<dyn Clock>) -> ZRemHandler {
+        ZRemHandler {
+            map,
+            clock,
+            events: None,
+        }
+    }
+
+    /// Returns ZREM as a Command in the form of Value.
+    pub fn command_value(arg: ZRemArg) -> Value {
+        let mut v = vec![Value::BulkString("ZREM".into()), Value::BulkString(arg.key)];
+        v.extend(arg.members.into_iter().map(Value::BulkString));
+        Value::Array(v.into())
+    }
+}
+
+pub struct ZRemHandler {
+    map: Arc<Store>,
+    clock: Arc<dyn Clock>,
+    events: Option<Arc<KeyEvents>>,
+}
+
+impl ZRemHandler {
+    /// Tells the listeners about keys with members removed, and sorted sets removed once
+    /// empty.
+    pub fn with_events(mut self, events: Arc<KeyEvents>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Removes the members from the sorted set, returning how many of them existed. The key
+    /// is removed once the sorted set is empty.
+    pub fn handle(&self, arg: ZRemArg) -> Result<Value, HandleCommandError> {
+        let key = arg.key.as_bytes().unwrap_or_default();
+        let now = self.clock.now();
+
+        let mut shard = self.map.write(key);
+        if shard.get(key).is_none_or(|data| data.expired_at(now)) {
+            return Ok(Value::Integer(Integer::new(0)));
+        }
+        let zset = shard
+            .value_mut(key)
+            .and_then(StoredValue::as_zset_mut)
+            .ok_or(HandleCommandError::WrongType)?;
+        let removed = arg
+            .members
+            .iter()
+            .filter(|member| zset.remove(member).is_some())
+            .count();
+        let emptied = zset.is_empty();
+        if emptied {
+            shard.remove(key);
+        }
+        drop(shard);
+
+        if let Some(events) = self.events.as_ref().filter(|_| removed > 0) {
+            events.notify(key, KeyEvent::ZRem);
+            if emptied {
+                events.notify(key, KeyEvent::Del);
+            }
+        }
+        Ok(Value::Integer(Integer::new(removed as i64)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::super::Command;
+    use super::*;
+
+    #[test]
+    fn parse_zrem() {
+        let arg = ZRemArg {
+            key: "zset".into(),
+            members: vec!["a".into(), "b".into()],
+        };
+        match Command::try_from(ZRem::command_value(arg.clone())) {
+            Ok(Command::ZRem(parsed)) => assert_eq!(parsed, arg),
+            other => panic!("Unexpected parse result {other:?}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod handler_test {
+    use super::super::super::super::clock;
+    use super::super::super::super::handler::StoredData;
+    use super::super::super::super::zset::SortedSet;
+    use super::*;
+
+    #[test]
+    fn handle_zrem() {
+        let map = Arc::new(Store::default());
+        let zset = SortedSet::from_iter([("a".into(), 1.0), ("b".into(), 2.0)]);
+        map.write(b"zset").insert(
+            "zset".into(),
+            StoredData::new(StoredValue::SortedSet(zset), None),
+        );
+        let zrem = ZRem::handler(map.clone(), clock::system());
+        let arg = |members: &[&str]| ZRemArg {
+            key: "zset".into(),
+            members: members.iter().map(|&member| member.into()).collect(),
+        };
+        let len = || {
+            map.read(b"zset")
+                .get(b"zset".as_slice())
+                .and_then(|data| Some(data.value.as_zset()?.len()))
+        };
+
+        assert_eq!(
+            zrem.handle(arg(&["a", "z"])).unwrap(),
+            Value::Integer(Integer::new(1))
+        );
+        assert_eq!(len(), Some(1));
+        assert_eq!(
+            zrem.handle(arg(&["b"])).unwrap(),
+            Value::Integer(Integer::new(1))
+        );
+        assert!(map.is_empty());
+        assert_eq!(
+            zrem.handle(arg(&["b"])).unwrap(),
+            Value::Integer(Integer::new(0))
+        );
+    }
+}
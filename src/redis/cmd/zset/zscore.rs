@@ -0,0 +1,177 @@
+use std::sync::Arc;
+
+use super::super::super::clock::Clock;
+use super::super::super::handler::HandleCommandError;
+use super::super::super::resp::{BulkString, Integer, Value};
+use super::super::super::store::Store;
+use super::super::{consume_args_from_iter, CommandArgParser, ParseCommandError};
+use super::{read_zset, score_value};
+
+/// Arguments of ZSCORE and ZRANK.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ZMemberArg {
+    pub key: BulkString,
+    pub member: BulkString,
+}
+
+impl CommandArgParser for ZMemberArg {
+    /// ZSCORE key member
+    fn parse_arg(iter: &mut std::slice::Iter<'_, Value>) -> Result<Self, ParseCommandError> {
+        let args = consume_args_from_iter(iter, 2, 0)?;
+
+        Ok(Self {
+            key: args[0].clone(),
+            member: args[1].clone(),
+        })
+    }
+}
+
+fn member_command_value(name: &str, arg: ZMemberArg) -> Value {
+    let v = vec![
+        Value::BulkString(name.into()),
+        Value::BulkString(arg.key),
+        Value::BulkString(arg.member),
+    ];
+    Value::Array(v.into())
+}
+
+pub struct ZScore;
+
+impl ZScore {
+    /// Returns an instance of ZSCORE command handler.
+    pub fn handler(map: Arc<Store>, clock: Arc<dyn Clock>) -> ZScoreHandler {
+        ZScoreHandler { map, clock }
+    }
+
+    /// Returns ZSCORE as a Command in the form of Value.
+    pub fn command_value(arg: ZMemberArg) -> Value {
+        member_command_value("ZSCORE", arg)
+    }
+}
+
+#[derive(Debug)]
+pub struct ZScoreHandler {
+    map: Arc<Store>,
+    clock: Arc<dyn Clock>,
+}
+
+impl ZScoreHandler {
+    /// Returns the score of the member, or nil if the key or the member doesn't exist.
+    pub fn handle(&self, arg: ZMemberArg) -> Result<Value, HandleCommandError> {
+        let key = arg.key.as_bytes().unwrap_or_default();
+        let score = read_zset(&self.map, self.clock.now(), key, |zset| {
+            zset.score(&arg.member)
+        })?;
+        Ok(match score.flatten() {
+            Some(score) => score_value(score),
+            None => Value::BulkString(BulkString::null()),
+        })
+    }
+}
+
+pub struct ZRank;
+
+impl ZRank {
+    /// Returns an instance of ZRANK command handler.
+    pub fn handler(map: Arc<Store>, clock: Arc<dyn Clock>) -> ZRankHandler {
+        ZRankHandler { map, clock }
+    }
+
+    /// Returns ZRANK as a Command in the form of Value.
+    pub fn command_value(arg: ZMemberArg) -> Value {
+        member_command_value("ZRANK", arg)
+    }
+}
+
+#[derive(Debug)]
+pub struct ZRankHandler {
+    map: Arc<Store>,
+    clock: Arc<dyn Clock>,
+}
+
+impl ZRankHandler {
+    /// Returns the 0-based rank of the member from the lowest score, or nil if the key or
+    /// the member doesn't exist.
+    pub fn handle(&self, arg: ZMemberArg) -> Result<Value, HandleCommandError> {
+        let key = arg.key.as_bytes().unwrap_or_default();
+        let rank = read_zset(&self.map, self.clock.now(), key, |zset| {
+            zset.rank(&arg.member)
+        })?;
+        Ok(match rank.flatten() {
+            Some(rank) => Value::Integer(Integer::new(rank as i64)),
+            None => Value::BulkString(BulkString::null()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::super::Command;
+    use super::*;
+
+    #[test]
+    fn parse_zscore_and_zrank() {
+        let arg = ZMemberArg {
+            key: "zset".into(),
+            member: "a".into(),
+        };
+        match Command::try_from(ZScore::command_value(arg.clone())) {
+            Ok(Command::ZScore(parsed)) => assert_eq!(parsed, arg),
+            other => panic!("Unexpected parse result {other:?}"),
+        }
+        match Command::try_from(ZRank::command_value(arg.clone())) {
+            Ok(Command::ZRank(parsed)) => assert_eq!(parsed, arg),
+            other => panic!("Unexpected parse result {other:?}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod handler_test {
+    use super::super::super::super::clock;
+    use super::super::super::super::handler::{StoredData, StoredValue};
+    use super::super::super::super::zset::SortedSet;
+    use super::*;
+
+    #[test]
+    fn handle_zscore_and_zrank() {
+        let map = Arc::new(Store::default());
+        let zset = SortedSet::from_iter([("a".into(), 2.5), ("b".into(), 1.0)]);
+        map.write(b"zset").insert(
+            "zset".into(),
+            StoredData::new(StoredValue::SortedSet(zset), None),
+        );
+        let arg = |member: &str| ZMemberArg {
+            key: "zset".into(),
+            member: member.into(),
+        };
+
+        let zscore = ZScore::handler(map.clone(), clock::system());
+        assert_eq!(
+            zscore.handle(arg("a")).unwrap(),
+            Value::BulkString("2.5".into())
+        );
+        assert_eq!(
+            zscore.handle(arg("c")).unwrap(),
+            Value::BulkString(BulkString::null())
+        );
+
+        let zrank = ZRank::handler(map.clone(), clock::system());
+        assert_eq!(
+            zrank.handle(arg("a")).unwrap(),
+            Value::Integer(Integer::new(1))
+        );
+        assert_eq!(
+            zrank.handle(arg("c")).unwrap(),
+            Value::BulkString(BulkString::null())
+        );
+        let missing = ZMemberArg {
+            key: "missing".into(),
+            member: "a".into(),
+        };
+        assert_eq!(
+            zrank.handle(missing).unwrap(),
+            Value::BulkString(BulkString::null())
+        );
+    }
+}
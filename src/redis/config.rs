@@ -0,0 +1,1180 @@
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use parking_lot::{RwLock, RwLockReadGuard};
+use thiserror::Error;
+use tracing::level_filters::LevelFilter;
+
+use super::eviction::{EvictionPolicy, LfuConfig};
+use super::listpack::{ListpackLimits, ListpackSize};
+use super::util;
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ConfigError {
+    #[error("Unknown option or number of arguments for CONFIG SET - '{0}'")]
+    UnknownParameter(String),
+
+    #[error("CONFIG SET failed (possibly related to argument '{0}') - can't set immutable config")]
+    Immutable(String),
+
+    #[error("CONFIG SET failed (possibly related to argument '{0}') - duplicate parameter")]
+    Duplicate(String),
+
+    #[error("CONFIG SET failed (possibly related to argument '{name}') - {reason}")]
+    InvalidValue { name: String, reason: String },
+}
+
+/// Whether TLS clients must present a certificate signed by the configured CA.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TlsAuthClients {
+    No,
+    Yes,
+    Optional,
+}
+
+impl FromStr for TlsAuthClients {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "no" => Ok(Self::No),
+            "yes" => Ok(Self::Yes),
+            "optional" => Ok(Self::Optional),
+            _ => Err("argument must be one of yes, no or optional".to_string()),
+        }
+    }
+}
+
+impl std::fmt::Display for TlsAuthClients {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::No => write!(f, "no"),
+            Self::Yes => write!(f, "yes"),
+            Self::Optional => write!(f, "optional"),
+        }
+    }
+}
+
+/// How much the server logs, named as in Redis.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogLevel {
+    Debug,
+    Verbose,
+    #[default]
+    Notice,
+    Warning,
+    Nothing,
+}
+
+impl LogLevel {
+    /// Returns the most verbose tracing level logged, matching the levels to the characters
+    /// of `RedisLogFormat`.
+    pub fn level_filter(&self) -> LevelFilter {
+        match self {
+            Self::Debug => LevelFilter::TRACE,
+            Self::Verbose => LevelFilter::DEBUG,
+            Self::Notice => LevelFilter::INFO,
+            Self::Warning => LevelFilter::WARN,
+            Self::Nothing => LevelFilter::OFF,
+        }
+    }
+}
+
+impl FromStr for LogLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "debug" => Ok(Self::Debug),
+            "verbose" => Ok(Self::Verbose),
+            "notice" => Ok(Self::Notice),
+            "warning" => Ok(Self::Warning),
+            "nothing" => Ok(Self::Nothing),
+            _ => Err(
+                "argument must be one of debug, verbose, notice, warning or nothing".to_string(),
+            ),
+        }
+    }
+}
+
+impl std::fmt::Display for LogLevel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Debug => write!(f, "debug"),
+            Self::Verbose => write!(f, "verbose"),
+            Self::Notice => write!(f, "notice"),
+            Self::Warning => write!(f, "warning"),
+            Self::Nothing => write!(f, "nothing"),
+        }
+    }
+}
+
+/// How a replica loads the RDB file of a full resync, named as in Redis.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReplDisklessLoad {
+    /// Writes the file to `dbfilename` first, then loads it from there.
+    #[default]
+    Disabled,
+
+    /// Loads the file straight from the link if there are no keys yet, else like `Disabled`.
+    OnEmptyDb,
+
+    /// Always loads the file straight from the link.
+    Swapdb,
+}
+
+impl FromStr for ReplDisklessLoad {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "disabled" => Ok(Self::Disabled),
+            "on-empty-db" => Ok(Self::OnEmptyDb),
+            "swapdb" => Ok(Self::Swapdb),
+            _ => Err("argument must be one of disabled, on-empty-db or swapdb".to_string()),
+        }
+    }
+}
+
+impl std::fmt::Display for ReplDisklessLoad {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Disabled => write!(f, "disabled"),
+            Self::OnEmptyDb => write!(f, "on-empty-db"),
+            Self::Swapdb => write!(f, "swapdb"),
+        }
+    }
+}
+
+/// Applies the log level set with CONFIG SET loglevel, e.g. by reloading the filter of the
+/// tracing subscriber.
+pub trait LogLevelControl: Send + Sync + std::fmt::Debug {
+    fn set_level(&self, level: LogLevel);
+}
+
+/// Applies the TLS files and client authentication set with CONFIG SET, e.g. by rebuilding
+/// the config of the TLS listeners so certificates are rotated without a restart.
+pub trait TlsControl: Send + Sync + std::fmt::Debug {
+    /// Rebuilds from the values about to be set. An error rejects the change.
+    fn reload(&self, values: &ConfigValues) -> Result<(), String>;
+}
+
+/// A host name or IP address with a port, e.g. the master of a replica. Host names are
+/// resolved on every connection, so a server that moved to another address is found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HostPort {
+    pub host: String,
+    pub port: u16,
+}
+
+impl HostPort {
+    pub fn new(host: impl Into<String>, port: u16) -> Self {
+        Self {
+            host: host.into(),
+            port,
+        }
+    }
+
+    /// Returns the first address the host resolves to.
+    pub async fn resolve(&self) -> std::io::Result<SocketAddr> {
+        tokio::net::lookup_host((self.host.as_str(), self.port))
+            .await?
+            .next()
+            .ok_or_else(|| {
+                let msg = format!("No address found for {}", self.host);
+                std::io::Error::new(std::io::ErrorKind::NotFound, msg)
+            })
+    }
+}
+
+impl FromStr for HostPort {
+    type Err = String;
+
+    /// Parses `host port`, as given to `replicaof`, or `host:port` with IPv6 addresses in
+    /// brackets.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split_whitespace().collect();
+        let (host, port) = match parts[..] {
+            [host, port] => (host, port),
+            [addr] => {
+                let (host, port) = addr
+                    .rsplit_once(':')
+                    .ok_or_else(|| format!("'{addr}' must be host:port or 'host port'"))?;
+                let host = host
+                    .strip_prefix('[')
+                    .and_then(|host| host.strip_suffix(']'))
+                    .unwrap_or(host);
+                (host, port)
+            }
+            _ => return Err(format!("'{s}' must be host:port or 'host port'")),
+        };
+        let port = port.parse().map_err(|_| format!("Invalid port '{port}'"))?;
+        if host.is_empty() {
+            return Err(format!("'{s}' has no host"));
+        }
+        Ok(Self::new(host, port))
+    }
+}
+
+impl std::fmt::Display for HostPort {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.host.contains(':') {
+            write!(f, "[{}]:{}", self.host, self.port)
+        } else {
+            write!(f, "{}:{}", self.host, self.port)
+        }
+    }
+}
+
+impl From<SocketAddr> for HostPort {
+    fn from(addr: SocketAddr) -> Self {
+        Self::new(addr.ip().to_string(), addr.port())
+    }
+}
+
+/// Query buffer limit of clients unless configured, 1 GiB like Redis.
+pub const DEFAULT_QUERY_BUFFER_LIMIT: u64 = 1024 * 1024 * 1024;
+
+/// Priority of a replica that didn't set replica-priority.
+pub const DEFAULT_REPLICA_PRIORITY: u32 = 100;
+
+/// All tunables of the server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigValues {
+    /// Port the server listens to.
+    pub port: u16,
+
+    /// Addresses the server listens on.
+    pub bind: Vec<IpAddr>,
+
+    /// Address of the master if this server is a replica.
+    pub replica_of: Option<HostPort>,
+
+    /// Preference for promoting this server when it is a replica, lower first. 0 means it
+    /// is never promoted.
+    pub replica_priority: u32,
+
+    /// Close the connection after a client is idle for this many seconds, 0 to disable.
+    pub timeout: u64,
+
+    /// Frequency of background tasks per second.
+    pub hz: u32,
+
+    /// Number of logical databases.
+    pub databases: u32,
+
+    /// Working directory where the RDB file is stored.
+    pub dir: PathBuf,
+
+    /// Name of the RDB file inside `dir`.
+    pub dbfilename: String,
+
+    /// Whether a replica writes the RDB file of a full resync to disk before loading it.
+    pub repl_diskless_load: ReplDisklessLoad,
+
+    /// Port for TLS connections, 0 when TLS is disabled.
+    pub tls_port: u16,
+
+    /// Certificate and private key used for TLS connections.
+    pub tls_cert_file: Option<PathBuf>,
+    pub tls_key_file: Option<PathBuf>,
+
+    /// CA certificate bundle used to verify peers.
+    pub tls_ca_cert_file: Option<PathBuf>,
+
+    /// Whether TLS clients must authenticate with a certificate.
+    pub tls_auth_clients: TlsAuthClients,
+
+    /// Whether the link to the master uses TLS.
+    pub tls_replication: bool,
+
+    /// File that ACL users are loaded from and saved to.
+    pub aclfile: Option<PathBuf>,
+
+    /// Memory limit in bytes before keys are evicted, 0 for no limit.
+    pub maxmemory: u64,
+
+    /// How keys are chosen for eviction.
+    pub maxmemory_policy: EvictionPolicy,
+
+    /// Number of keys sampled for every eviction.
+    pub maxmemory_samples: u32,
+
+    /// Tunables of the access frequency counter used by LFU eviction.
+    pub lfu_log_factor: u32,
+    pub lfu_decay_time: u32,
+
+    /// Whether values removed by eviction, expiry, implicit deletes such as an overwriting
+    /// SET, and user deletes are freed on the lazy free worker.
+    pub lazyfree_lazy_eviction: bool,
+    pub lazyfree_lazy_expire: bool,
+    pub lazyfree_lazy_server_del: bool,
+    pub lazyfree_lazy_user_del: bool,
+
+    /// Limits up to which hashes and sorted sets are packed into listpacks, as the most
+    /// fields or members and the most bytes of any of them.
+    pub hash_max_listpack_entries: usize,
+    pub hash_max_listpack_value: usize,
+    pub zset_max_listpack_entries: usize,
+    pub zset_max_listpack_value: usize,
+
+    /// Size of every listpack node of a list, as entries if positive or as -1 to -5 for
+    /// 4 to 64 KiB.
+    pub list_max_listpack_size: i64,
+
+    /// Whether the server runs detached from the terminal.
+    pub daemonize: bool,
+
+    /// File holding the process ID.
+    pub pidfile: Option<PathBuf>,
+
+    /// File logs are written to, standard output if not set.
+    pub logfile: Option<PathBuf>,
+
+    /// How much is logged.
+    pub loglevel: LogLevel,
+
+    /// Most clients connected at once, further connections are refused.
+    pub maxclients: usize,
+
+    /// Seconds of idleness before TCP keepalive probes are sent to clients, 0 to disable.
+    pub tcp_keepalive: u64,
+
+    /// Milliseconds a write to a client may block before the client is considered stalled
+    /// and disconnected, 0 to disable.
+    pub write_timeout: u64,
+
+    /// Most bytes buffered for a request a client hasn't finished sending, past which the
+    /// client is disconnected.
+    pub client_query_buffer_limit: u64,
+
+    /// Size of the queue of connections waiting to be accepted.
+    pub tcp_backlog: u32,
+
+    /// Number of listeners bound to every address, each with its own accept loop.
+    pub io_threads: usize,
+
+    /// Whether expired keys are removed in the background, toggled by DEBUG SET-ACTIVE-EXPIRE.
+    pub active_expire: bool,
+
+    /// Whether the server is a node of a Redis Cluster.
+    pub cluster_enabled: bool,
+
+    /// Name of the file inside `dir` that the cluster nodes and slots are saved to.
+    pub cluster_config_file: String,
+
+    /// Milliseconds a node may go without answering pings before it is marked failed.
+    pub cluster_node_timeout: u64,
+
+    /// Port of the cluster bus.
+    pub cluster_port: u16,
+
+    /// Whether a cluster node refuses every key while some slot isn't served.
+    pub cluster_require_full_coverage: bool,
+}
+
+impl ConfigValues {
+    pub fn lfu(&self) -> LfuConfig {
+        LfuConfig {
+            log_factor: self.lfu_log_factor,
+            decay_time: self.lfu_decay_time,
+        }
+    }
+
+    pub fn list_listpack(&self) -> ListpackSize {
+        // Only valid sizes can be set.
+        ListpackSize::from_config(self.list_max_listpack_size).unwrap()
+    }
+
+    pub fn hash_listpack(&self) -> ListpackLimits {
+        ListpackLimits {
+            entries: self.hash_max_listpack_entries,
+            value: self.hash_max_listpack_value,
+        }
+    }
+
+    pub fn zset_listpack(&self) -> ListpackLimits {
+        ListpackLimits {
+            entries: self.zset_max_listpack_entries,
+            value: self.zset_max_listpack_value,
+        }
+    }
+}
+
+impl Default for ConfigValues {
+    fn default() -> Self {
+        Self {
+            port: 6379,
+            bind: vec![IpAddr::V4(Ipv4Addr::LOCALHOST)],
+            replica_of: None,
+            replica_priority: DEFAULT_REPLICA_PRIORITY,
+            timeout: 0,
+            hz: 10,
+            databases: 16,
+            dir: PathBuf::from("."),
+            dbfilename: "dump.rdb".to_string(),
+            repl_diskless_load: ReplDisklessLoad::Disabled,
+            tls_port: 0,
+            tls_cert_file: None,
+            tls_key_file: None,
+            tls_ca_cert_file: None,
+            tls_auth_clients: TlsAuthClients::No,
+            tls_replication: false,
+            aclfile: None,
+            maxmemory: 0,
+            maxmemory_policy: EvictionPolicy::NoEviction,
+            maxmemory_samples: 5,
+            lfu_log_factor: LfuConfig::default().log_factor,
+            lfu_decay_time: LfuConfig::default().decay_time,
+            lazyfree_lazy_eviction: false,
+            lazyfree_lazy_expire: false,
+            lazyfree_lazy_server_del: false,
+            lazyfree_lazy_user_del: false,
+            hash_max_listpack_entries: 128,
+            hash_max_listpack_value: 64,
+            zset_max_listpack_entries: 128,
+            zset_max_listpack_value: 64,
+            list_max_listpack_size: -2,
+            daemonize: false,
+            pidfile: None,
+            logfile: None,
+            loglevel: LogLevel::Notice,
+            maxclients: 10000,
+            tcp_keepalive: 300,
+            write_timeout: 0,
+            client_query_buffer_limit: DEFAULT_QUERY_BUFFER_LIMIT,
+            tcp_backlog: 511,
+            io_threads: 1,
+            active_expire: true,
+            cluster_enabled: false,
+            cluster_config_file: "nodes.conf".to_string(),
+            cluster_node_timeout: 15000,
+            cluster_port: 0,
+            cluster_require_full_coverage: true,
+        }
+    }
+}
+
+type Getter = fn(&ConfigValues) -> String;
+type Setter = fn(&mut ConfigValues, &str) -> Result<(), String>;
+
+/// A named parameter that can be read with CONFIG GET and, if it has a setter, updated
+/// with CONFIG SET.
+struct Parameter {
+    name: &'static str,
+    get: Getter,
+    set: Option<Setter>,
+}
+
+const PARAMETERS: &[Parameter] = &[
+    Parameter {
+        name: "port",
+        get: |v| v.port.to_string(),
+        set: None,
+    },
+    Parameter {
+        name: "bind",
+        get: |v| {
+            v.bind
+                .iter()
+                .map(|ip| ip.to_string())
+                .collect::<Vec<_>>()
+                .join(" ")
+        },
+        set: None,
+    },
+    Parameter {
+        name: "replicaof",
+        get: |v| {
+            v.replica_of
+                .as_ref()
+                .map(|master| format!("{} {}", master.host, master.port))
+                .unwrap_or_default()
+        },
+        set: None,
+    },
+    Parameter {
+        name: "replica-priority",
+        get: |v| v.replica_priority.to_string(),
+        set: Some(|v, s| {
+            v.replica_priority = parse_number(s)?;
+            Ok(())
+        }),
+    },
+    Parameter {
+        name: "timeout",
+        get: |v| v.timeout.to_string(),
+        set: Some(|v, s| {
+            v.timeout = parse_number(s)?;
+            Ok(())
+        }),
+    },
+    Parameter {
+        name: "hz",
+        get: |v| v.hz.to_string(),
+        set: Some(|v, s| {
+            v.hz = parse_in_range(s, 1, 500)?;
+            Ok(())
+        }),
+    },
+    Parameter {
+        name: "databases",
+        get: |v| v.databases.to_string(),
+        set: None,
+    },
+    Parameter {
+        name: "dir",
+        get: |v| v.dir.display().to_string(),
+        set: Some(|v, s| {
+            let dir = PathBuf::from(s);
+            if !dir.is_dir() {
+                return Err("No such file or directory".to_string());
+            }
+            v.dir = dir;
+            Ok(())
+        }),
+    },
+    Parameter {
+        name: "dbfilename",
+        get: |v| v.dbfilename.clone(),
+        set: Some(|v, s| {
+            if s.is_empty() || s.contains('/') {
+                return Err("dbfilename can't be a path, just a filename".to_string());
+            }
+            v.dbfilename = s.to_string();
+            Ok(())
+        }),
+    },
+    Parameter {
+        name: "repl-diskless-load",
+        get: |v| v.repl_diskless_load.to_string(),
+        set: Some(|v, s| {
+            v.repl_diskless_load = s.parse()?;
+            Ok(())
+        }),
+    },
+    Parameter {
+        name: "tls-port",
+        get: |v| v.tls_port.to_string(),
+        set: None,
+    },
+    Parameter {
+        name: "tls-cert-file",
+        get: |v| display_path(&v.tls_cert_file),
+        set: Some(|v, s| {
+            v.tls_cert_file = parse_path(s);
+            Ok(())
+        }),
+    },
+    Parameter {
+        name: "tls-key-file",
+        get: |v| display_path(&v.tls_key_file),
+        set: Some(|v, s| {
+            v.tls_key_file = parse_path(s);
+            Ok(())
+        }),
+    },
+    Parameter {
+        name: "tls-ca-cert-file",
+        get: |v| display_path(&v.tls_ca_cert_file),
+        set: Some(|v, s| {
+            v.tls_ca_cert_file = parse_path(s);
+            Ok(())
+        }),
+    },
+    Parameter {
+        name: "tls-auth-clients",
+        get: |v| v.tls_auth_clients.to_string(),
+        set: Some(|v, s| {
+            v.tls_auth_clients = s.parse()?;
+            Ok(())
+        }),
+    },
+    Parameter {
+        name: "tls-replication",
+        get: |v| yes_no(v.tls_replication),
+        set: None,
+    },
+    Parameter {
+        name: "aclfile",
+        get: |v| display_path(&v.aclfile),
+        set: None,
+    },
+    Parameter {
+        name: "maxmemory",
+        get: |v| v.maxmemory.to_string(),
+        set: Some(|v, s| {
+            v.maxmemory = parse_memory(s)?;
+            Ok(())
+        }),
+    },
+    Parameter {
+        name: "maxmemory-policy",
+        get: |v| v.maxmemory_policy.to_string(),
+        set: Some(|v, s| {
+            v.maxmemory_policy = s.parse()?;
+            Ok(())
+        }),
+    },
+    Parameter {
+        name: "maxmemory-samples",
+        get: |v| v.maxmemory_samples.to_string(),
+        set: Some(|v, s| {
+            v.maxmemory_samples = parse_in_range(s, 1, 64)?;
+            Ok(())
+        }),
+    },
+    Parameter {
+        name: "lfu-log-factor",
+        get: |v| v.lfu_log_factor.to_string(),
+        set: Some(|v, s| {
+            v.lfu_log_factor = parse_number(s)?;
+            Ok(())
+        }),
+    },
+    Parameter {
+        name: "lfu-decay-time",
+        get: |v| v.lfu_decay_time.to_string(),
+        set: Some(|v, s| {
+            v.lfu_decay_time = parse_number(s)?;
+            Ok(())
+        }),
+    },
+    Parameter {
+        name: "lazyfree-lazy-eviction",
+        get: |v| yes_no(v.lazyfree_lazy_eviction),
+        set: Some(|v, s| {
+            v.lazyfree_lazy_eviction = parse_yes_no(s)?;
+            Ok(())
+        }),
+    },
+    Parameter {
+        name: "lazyfree-lazy-expire",
+        get: |v| yes_no(v.lazyfree_lazy_expire),
+        set: Some(|v, s| {
+            v.lazyfree_lazy_expire = parse_yes_no(s)?;
+            Ok(())
+        }),
+    },
+    Parameter {
+        name: "lazyfree-lazy-server-del",
+        get: |v| yes_no(v.lazyfree_lazy_server_del),
+        set: Some(|v, s| {
+            v.lazyfree_lazy_server_del = parse_yes_no(s)?;
+            Ok(())
+        }),
+    },
+    Parameter {
+        name: "lazyfree-lazy-user-del",
+        get: |v| yes_no(v.lazyfree_lazy_user_del),
+        set: Some(|v, s| {
+            v.lazyfree_lazy_user_del = parse_yes_no(s)?;
+            Ok(())
+        }),
+    },
+    Parameter {
+        name: "hash-max-listpack-entries",
+        get: |v| v.hash_max_listpack_entries.to_string(),
+        set: Some(|v, s| {
+            v.hash_max_listpack_entries = parse_number(s)?;
+            Ok(())
+        }),
+    },
+    Parameter {
+        name: "hash-max-listpack-value",
+        get: |v| v.hash_max_listpack_value.to_string(),
+        set: Some(|v, s| {
+            v.hash_max_listpack_value = parse_number(s)?;
+            Ok(())
+        }),
+    },
+    Parameter {
+        name: "zset-max-listpack-entries",
+        get: |v| v.zset_max_listpack_entries.to_string(),
+        set: Some(|v, s| {
+            v.zset_max_listpack_entries = parse_number(s)?;
+            Ok(())
+        }),
+    },
+    Parameter {
+        name: "zset-max-listpack-value",
+        get: |v| v.zset_max_listpack_value.to_string(),
+        set: Some(|v, s| {
+            v.zset_max_listpack_value = parse_number(s)?;
+            Ok(())
+        }),
+    },
+    Parameter {
+        name: "list-max-listpack-size",
+        get: |v| v.list_max_listpack_size.to_string(),
+        set: Some(|v, s| {
+            let size = parse_number(s)?;
+            if ListpackSize::from_config(size).is_none() {
+                return Err("argument must be positive or between -5 and -1".to_string());
+            }
+            v.list_max_listpack_size = size;
+            Ok(())
+        }),
+    },
+    Parameter {
+        name: "daemonize",
+        get: |v| yes_no(v.daemonize),
+        set: None,
+    },
+    Parameter {
+        name: "pidfile",
+        get: |v| display_path(&v.pidfile),
+        set: None,
+    },
+    Parameter {
+        name: "logfile",
+        get: |v| display_path(&v.logfile),
+        set: None,
+    },
+    Parameter {
+        name: "loglevel",
+        get: |v| v.loglevel.to_string(),
+        set: Some(|v, s| {
+            v.loglevel = s.parse()?;
+            Ok(())
+        }),
+    },
+    Parameter {
+        name: "maxclients",
+        get: |v| v.maxclients.to_string(),
+        set: Some(|v, s| {
+            v.maxclients = parse_in_range(s, 1, usize::MAX)?;
+            Ok(())
+        }),
+    },
+    Parameter {
+        name: "tcp-keepalive",
+        get: |v| v.tcp_keepalive.to_string(),
+        set: Some(|v, s| {
+            v.tcp_keepalive = parse_number(s)?;
+            Ok(())
+        }),
+    },
+    Parameter {
+        name: "write-timeout",
+        get: |v| v.write_timeout.to_string(),
+        set: Some(|v, s| {
+            v.write_timeout = parse_number(s)?;
+            Ok(())
+        }),
+    },
+    Parameter {
+        name: "client-query-buffer-limit",
+        get: |v| v.client_query_buffer_limit.to_string(),
+        set: Some(|v, s| {
+            let limit = parse_memory(s)?;
+            // Like Redis, a limit below 1 MiB would break ordinary clients.
+            if limit < 1024 * 1024 {
+                return Err("argument must be at least 1mb".to_string());
+            }
+            v.client_query_buffer_limit = limit;
+            Ok(())
+        }),
+    },
+    Parameter {
+        name: "tcp-backlog",
+        get: |v| v.tcp_backlog.to_string(),
+        set: None,
+    },
+    Parameter {
+        name: "io-threads",
+        get: |v| v.io_threads.to_string(),
+        set: None,
+    },
+    Parameter {
+        name: "cluster-enabled",
+        get: |v| yes_no(v.cluster_enabled),
+        set: None,
+    },
+    Parameter {
+        name: "cluster-config-file",
+        get: |v| v.cluster_config_file.clone(),
+        set: None,
+    },
+    Parameter {
+        name: "cluster-node-timeout",
+        get: |v| v.cluster_node_timeout.to_string(),
+        set: Some(|v, s| {
+            v.cluster_node_timeout = parse_in_range(s, 1, u64::MAX)?;
+            Ok(())
+        }),
+    },
+    Parameter {
+        name: "cluster-port",
+        get: |v| v.cluster_port.to_string(),
+        set: None,
+    },
+    Parameter {
+        name: "cluster-require-full-coverage",
+        get: |v| yes_no(v.cluster_require_full_coverage),
+        set: Some(|v, s| {
+            v.cluster_require_full_coverage = parse_yes_no(s)?;
+            Ok(())
+        }),
+    },
+];
+
+fn yes_no(b: bool) -> String {
+    if b { "yes" } else { "no" }.to_string()
+}
+
+fn parse_yes_no(s: &str) -> Result<bool, String> {
+    match s.to_lowercase().as_str() {
+        "yes" => Ok(true),
+        "no" => Ok(false),
+        _ => Err("argument must be 'yes' or 'no'".to_string()),
+    }
+}
+
+fn display_path(path: &Option<PathBuf>) -> String {
+    path.as_ref()
+        .map(|p| p.display().to_string())
+        .unwrap_or_default()
+}
+
+/// Parses a path parameter, empty for none.
+fn parse_path(s: &str) -> Option<PathBuf> {
+    (!s.is_empty()).then(|| PathBuf::from(s))
+}
+
+fn parse_number<T: FromStr>(s: &str) -> Result<T, String> {
+    s.parse::<T>()
+        .map_err(|_| "argument couldn't be parsed into an integer".to_string())
+}
+
+/// Parses a number of bytes with an optional unit, e.g. `100mb` or `1gb`.
+pub fn parse_memory(s: &str) -> Result<u64, String> {
+    let lower = s.to_lowercase();
+    let digits_end = lower
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(lower.len());
+    let (number, unit) = lower.split_at(digits_end);
+    let multiplier = match unit {
+        "" | "b" => 1,
+        "k" => 1000,
+        "kb" => 1024,
+        "m" => 1000 * 1000,
+        "mb" => 1024 * 1024,
+        "g" => 1000 * 1000 * 1000,
+        "gb" => 1024 * 1024 * 1024,
+        _ => return Err("argument must be a memory value".to_string()),
+    };
+    let number: u64 = parse_number(number)?;
+    number
+        .checked_mul(multiplier)
+        .ok_or_else(|| "argument must be a memory value".to_string())
+}
+
+fn parse_in_range<T: FromStr + PartialOrd + std::fmt::Display>(
+    s: &str,
+    min: T,
+    max: T,
+) -> Result<T, String> {
+    let n: T = parse_number(s)?;
+    if n < min || n > max {
+        return Err(format!(
+            "argument must be between {min} and {max} inclusive"
+        ));
+    }
+    Ok(n)
+}
+
+fn find_parameter(name: &str) -> Option<&'static Parameter> {
+    PARAMETERS
+        .iter()
+        .find(|p| p.name.eq_ignore_ascii_case(name))
+}
+
+/// Server configuration store, shared between the server and command handlers.
+/// Parameters can be read and updated at runtime through CONFIG GET and CONFIG SET.
+#[derive(Debug, Default)]
+pub struct ServerConfig {
+    values: RwLock<ConfigValues>,
+
+    /// Told about every change of `loglevel`, if the server's logging can change.
+    log_control: RwLock<Option<Arc<dyn LogLevelControl>>>,
+
+    /// Asked to apply every change of the TLS parameters, if the server has TLS listeners.
+    tls_control: RwLock<Option<Arc<dyn TlsControl>>>,
+}
+
+impl ServerConfig {
+    pub fn new(values: ConfigValues) -> Self {
+        Self {
+            values: RwLock::new(values),
+            log_control: RwLock::default(),
+            tls_control: RwLock::default(),
+        }
+    }
+
+    /// Applies the current and every later `loglevel` through the control.
+    pub fn set_log_control(&self, control: Arc<dyn LogLevelControl>) {
+        control.set_level(self.read().loglevel);
+        *self.log_control.write() = Some(control);
+    }
+
+    /// Applies every later change of the TLS parameters through the control.
+    pub fn set_tls_control(&self, control: Arc<dyn TlsControl>) {
+        *self.tls_control.write() = Some(control);
+    }
+
+    /// Returns a read guard over the current values.
+    pub fn read(&self) -> RwLockReadGuard<'_, ConfigValues> {
+        self.values.read()
+    }
+
+    /// Returns the name and value of every parameter matching any of the glob patterns,
+    /// in the order the parameters are defined.
+    pub fn get(&self, patterns: &[String]) -> Vec<(String, String)> {
+        let values = self.read();
+        let patterns: Vec<String> = patterns.iter().map(|p| p.to_lowercase()).collect();
+
+        PARAMETERS
+            .iter()
+            .filter(|p| {
+                patterns
+                    .iter()
+                    .any(|pat| util::glob_match(pat.as_bytes(), p.name.as_bytes()))
+            })
+            .map(|p| (p.name.to_string(), (p.get)(&values)))
+            .collect()
+    }
+
+    pub fn set_active_expire(&self, enabled: bool) {
+        self.values.write().active_expire = enabled;
+    }
+
+    /// Sets the master followed, `None` for a master, as REPLICAOF and FAILOVER do.
+    pub fn set_replica_of(&self, master: Option<HostPort>) {
+        self.values.write().replica_of = master;
+    }
+
+    /// Sets every parameter to its new value.
+    /// Either all parameters are applied or, if any of them fails validation, none are.
+    pub fn set(&self, pairs: &[(String, String)]) -> Result<(), ConfigError> {
+        let mut values = self.values.write();
+        let mut updated = values.clone();
+        let mut seen: Vec<&str> = Vec::with_capacity(pairs.len());
+
+        for (name, value) in pairs {
+            let param = find_parameter(name).ok_or(ConfigError::UnknownParameter(name.clone()))?;
+            if seen.contains(&param.name) {
+                return Err(ConfigError::Duplicate(name.clone()));
+            }
+            seen.push(param.name);
+
+            let set = param.set.ok_or(ConfigError::Immutable(name.clone()))?;
+            set(&mut updated, value).map_err(|reason| ConfigError::InvalidValue {
+                name: name.clone(),
+                reason,
+            })?;
+        }
+
+        // The TLS files are only checked once all of them are set, so a certificate and its
+        // key can be replaced together.
+        let tls_changed = pairs
+            .iter()
+            .find(|(name, _)| name.to_lowercase().starts_with("tls-"))
+            .filter(|_| {
+                updated.tls_cert_file != values.tls_cert_file
+                    || updated.tls_key_file != values.tls_key_file
+                    || updated.tls_ca_cert_file != values.tls_ca_cert_file
+                    || updated.tls_auth_clients != values.tls_auth_clients
+            });
+        if let Some((name, _)) = tls_changed {
+            let control = self.tls_control.read();
+            if let Some(control) = control.as_ref() {
+                control
+                    .reload(&updated)
+                    .map_err(|reason| ConfigError::InvalidValue {
+                        name: name.clone(),
+                        reason,
+                    })?;
+            }
+        }
+
+        let loglevel = (updated.loglevel != values.loglevel).then_some(updated.loglevel);
+        *values = updated;
+        drop(values);
+        if let Some(level) = loglevel {
+            let control = self.log_control.read();
+            if let Some(control) = control.as_ref() {
+                control.set_level(level);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use super::*;
+
+    #[test]
+    fn parse_host_port() {
+        let parse = |s: &str| s.parse::<HostPort>();
+        assert_eq!(
+            parse("localhost 6379"),
+            Ok(HostPort::new("localhost", 6379))
+        );
+        assert_eq!(
+            parse("  10.0.0.1   6380 "),
+            Ok(HostPort::new("10.0.0.1", 6380))
+        );
+        assert_eq!(
+            parse("redis.local:6379"),
+            Ok(HostPort::new("redis.local", 6379))
+        );
+        assert_eq!(parse("[::1]:6379"), Ok(HostPort::new("::1", 6379)));
+        assert_eq!(HostPort::new("::1", 6379).to_string(), "[::1]:6379");
+
+        assert!(parse("localhost").is_err());
+        assert!(parse("localhost 70000").is_err());
+        assert!(parse(":6379").is_err());
+        assert!(parse("a b c").is_err());
+    }
+
+    #[test]
+    fn get_with_glob() {
+        let config = ServerConfig::default();
+        let params = config.get(&["TIME*".to_string(), "*port".to_string()]);
+
+        assert_eq!(
+            params,
+            vec![
+                ("port".to_string(), "6379".to_string()),
+                ("timeout".to_string(), "0".to_string()),
+                ("tls-port".to_string(), "0".to_string()),
+                ("cluster-port".to_string(), "0".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn set_is_atomic() {
+        let config = ServerConfig::default();
+
+        let err = config
+            .set(&[
+                ("timeout".to_string(), "30".to_string()),
+                ("hz".to_string(), "0".to_string()),
+            ])
+            .expect_err("Set config no error");
+        assert!(matches!(err, ConfigError::InvalidValue { .. }));
+        assert_eq!(config.read().timeout, 0);
+
+        config
+            .set(&[("timeout".to_string(), "30".to_string())])
+            .expect("Set config unexpected error");
+        assert_eq!(config.read().timeout, 30);
+    }
+
+    #[test]
+    fn listpack_limits() {
+        let config = ServerConfig::default();
+        assert_eq!(config.read().list_listpack(), ListpackSize::Bytes(8192));
+
+        config
+            .set(&[
+                ("hash-max-listpack-entries".to_string(), "4".to_string()),
+                ("list-max-listpack-size".to_string(), "16".to_string()),
+            ])
+            .expect("Set config unexpected error");
+        assert_eq!(config.read().hash_listpack().entries, 4);
+        assert_eq!(config.read().zset_listpack(), ListpackLimits::DEFAULT);
+        assert_eq!(config.read().list_listpack(), ListpackSize::Entries(16));
+
+        for size in ["0", "-6"] {
+            let err = config
+                .set(&[("list-max-listpack-size".to_string(), size.to_string())])
+                .expect_err("Set config no error");
+            assert!(matches!(err, ConfigError::InvalidValue { .. }));
+        }
+    }
+
+    #[test]
+    fn parse_memory_units() {
+        assert_eq!(parse_memory("100"), Ok(100));
+        assert_eq!(parse_memory("1kb"), Ok(1024));
+        assert_eq!(parse_memory("2MB"), Ok(2 * 1024 * 1024));
+        assert_eq!(parse_memory("1g"), Ok(1_000_000_000));
+        assert!(parse_memory("10xb").is_err());
+    }
+
+    /// Accepts any key file but `bad.key`.
+    #[derive(Debug, Default)]
+    struct ReloadedTls(parking_lot::Mutex<Vec<Option<PathBuf>>>);
+
+    impl TlsControl for ReloadedTls {
+        fn reload(&self, values: &ConfigValues) -> Result<(), String> {
+            if values.tls_key_file.as_deref() == Some(Path::new("bad.key")) {
+                return Err("Unable to update TLS configuration".to_string());
+            }
+            self.0.lock().push(values.tls_key_file.clone());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn set_tls_files_reloads_them() {
+        let config = ServerConfig::default();
+        let reloaded = Arc::new(ReloadedTls::default());
+        config.set_tls_control(reloaded.clone());
+        let set = |pairs: &[(&str, &str)]| {
+            let pairs: Vec<_> = pairs
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect();
+            config.set(&pairs)
+        };
+
+        set(&[("tls-cert-file", "new.crt"), ("tls-key-file", "new.key")])
+            .expect("Set config unexpected error");
+        let err =
+            set(&[("tls-key-file", "bad.key"), ("timeout", "5")]).expect_err("Set config no error");
+        assert!(matches!(err, ConfigError::InvalidValue { name, .. } if name == "tls-key-file"));
+        // Unchanged files aren't reloaded.
+        set(&[("tls-key-file", "new.key")]).expect("Set config unexpected error");
+
+        assert_eq!(config.read().timeout, 0);
+        assert_eq!(config.read().tls_cert_file, Some(PathBuf::from("new.crt")));
+        assert_eq!(config.read().tls_key_file, Some(PathBuf::from("new.key")));
+        assert_eq!(*reloaded.0.lock(), [Some(PathBuf::from("new.key"))]);
+    }
+
+    #[derive(Debug, Default)]
+    struct AppliedLevels(parking_lot::Mutex<Vec<LogLevel>>);
+
+    impl LogLevelControl for AppliedLevels {
+        fn set_level(&self, level: LogLevel) {
+            self.0.lock().push(level);
+        }
+    }
+
+    #[test]
+    fn set_loglevel_applies_it() {
+        let config = ServerConfig::default();
+        let applied = Arc::new(AppliedLevels::default());
+        config.set_log_control(applied.clone());
+
+        let set = |level: &str| config.set(&[("loglevel".to_string(), level.to_string())]);
+        set("WARNING").expect("Set config unexpected error");
+        set("warning").expect("Set config unexpected error");
+        assert!(matches!(set("loud"), Err(ConfigError::InvalidValue { .. })));
+        assert_eq!(
+            config.get(&["loglevel".to_string()]),
+            [("loglevel".to_string(), "warning".to_string())]
+        );
+        // The initial level, then only changes.
+        assert_eq!(*applied.0.lock(), [LogLevel::Notice, LogLevel::Warning]);
+    }
+}
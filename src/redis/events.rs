@@ -0,0 +1,93 @@
+use std::fmt;
+use std::sync::Arc;
+
+/// A change to a key, named as in keyspace notifications.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyEvent {
+    Set,
+    /// INCR, DECR, INCRBY or DECRBY changed the key.
+    IncrBy,
+    Append,
+    SetRange,
+    /// EXPIRE or one of its variants gave the key a deadline.
+    Expire,
+    Persist,
+    LPush,
+    RPush,
+    LPop,
+    RPop,
+    HSet,
+    HDel,
+    HIncrBy,
+    /// HGETEX gave fields a deadline.
+    HExpire,
+    /// HGETEX cleared the deadlines of fields.
+    HPersist,
+    SAdd,
+    SRem,
+    ZAdd,
+    /// ZADD with INCR changed the score of a member.
+    ZIncrBy,
+    ZRem,
+    Del,
+    Expired,
+    Evicted,
+}
+
+impl KeyEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Set => "set",
+            Self::IncrBy => "incrby",
+            Self::Append => "append",
+            Self::SetRange => "setrange",
+            Self::Expire => "expire",
+            Self::Persist => "persist",
+            Self::LPush => "lpush",
+            Self::RPush => "rpush",
+            Self::LPop => "lpop",
+            Self::RPop => "rpop",
+            Self::HSet => "hset",
+            Self::HDel => "hdel",
+            Self::HIncrBy => "hincrby",
+            Self::HExpire => "hexpire",
+            Self::HPersist => "hpersist",
+            Self::SAdd => "sadd",
+            Self::SRem => "srem",
+            Self::ZAdd => "zadd",
+            Self::ZIncrBy => "zincr",
+            Self::ZRem => "zrem",
+            Self::Del => "del",
+            Self::Expired => "expired",
+            Self::Evicted => "evicted",
+        }
+    }
+}
+
+/// Lets an application embedding the server react to changes of keys in-process, without
+/// subscribing to keyspace notifications.
+pub trait KeyEventListener: Send + Sync + fmt::Debug {
+    /// Called once the key of logical database `db` changed, on the task that changed it
+    /// and with no lock of the store held. Commands wait for their listeners, so anything
+    /// slow belongs on a task of its own.
+    fn on_event(&self, db: u32, key: &[u8], event: KeyEvent);
+}
+
+/// The listeners registered on a server.
+#[derive(Debug, Clone, Default)]
+pub struct KeyEvents {
+    listeners: Vec<Arc<dyn KeyEventListener>>,
+}
+
+impl KeyEvents {
+    pub fn add(&mut self, listener: Arc<dyn KeyEventListener>) {
+        self.listeners.push(listener);
+    }
+
+    /// Tells every listener about the event. Only database 0 is served so far.
+    pub fn notify(&self, key: &[u8], event: KeyEvent) {
+        for listener in &self.listeners {
+            listener.on_event(0, key, event);
+        }
+    }
+}
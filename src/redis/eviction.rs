@@ -0,0 +1,410 @@
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rand::seq::IteratorRandom;
+use rand::Rng;
+use thiserror::Error;
+
+use super::handler::StoredData;
+use super::key::Key;
+use super::memory::{entry_usage, MemoryTracker};
+use super::store::Store;
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum EvictionError {
+    #[error("command not allowed when used memory > 'maxmemory'.")]
+    OutOfMemory,
+}
+
+impl EvictionError {
+    /// Returns the error code prefixed to the RESP error reply.
+    pub fn code(&self) -> &'static str {
+        "OOM"
+    }
+}
+
+/// How keys are chosen for eviction once used memory exceeds maxmemory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EvictionPolicy {
+    /// Reject writes instead of evicting.
+    NoEviction,
+    AllKeysRandom,
+    VolatileRandom,
+    AllKeysLru,
+    VolatileLru,
+    AllKeysLfu,
+    VolatileLfu,
+    VolatileTtl,
+}
+
+impl EvictionPolicy {
+    /// Returns true if only keys with a deadline may be evicted.
+    fn volatile_only(&self) -> bool {
+        matches!(
+            self,
+            Self::VolatileRandom | Self::VolatileLru | Self::VolatileLfu | Self::VolatileTtl
+        )
+    }
+
+    /// Returns true if the policy evicts by access frequency.
+    pub fn is_lfu(&self) -> bool {
+        matches!(self, Self::AllKeysLfu | Self::VolatileLfu)
+    }
+}
+
+impl FromStr for EvictionPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "noeviction" => Ok(Self::NoEviction),
+            "allkeys-random" => Ok(Self::AllKeysRandom),
+            "volatile-random" => Ok(Self::VolatileRandom),
+            "allkeys-lru" => Ok(Self::AllKeysLru),
+            "volatile-lru" => Ok(Self::VolatileLru),
+            "allkeys-lfu" => Ok(Self::AllKeysLfu),
+            "volatile-lfu" => Ok(Self::VolatileLfu),
+            "volatile-ttl" => Ok(Self::VolatileTtl),
+            _ => Err("argument must be a valid eviction policy".to_string()),
+        }
+    }
+}
+
+impl std::fmt::Display for EvictionPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Self::NoEviction => "noeviction",
+            Self::AllKeysRandom => "allkeys-random",
+            Self::VolatileRandom => "volatile-random",
+            Self::AllKeysLru => "allkeys-lru",
+            Self::VolatileLru => "volatile-lru",
+            Self::AllKeysLfu => "allkeys-lfu",
+            Self::VolatileLfu => "volatile-lfu",
+            Self::VolatileTtl => "volatile-ttl",
+        };
+        write!(f, "{s}")
+    }
+}
+
+/// Counter value of new keys, so they aren't evicted before getting a chance to be accessed.
+const LFU_INIT_VAL: u8 = 5;
+
+/// Tunables of the logarithmic access frequency counter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LfuConfig {
+    /// The higher the factor, the more accesses are needed to increment the counter.
+    pub log_factor: u32,
+
+    /// Minutes of no access after which the counter is decremented, 0 to never decay.
+    pub decay_time: u32,
+}
+
+impl Default for LfuConfig {
+    fn default() -> Self {
+        Self {
+            log_factor: 10,
+            decay_time: 1,
+        }
+    }
+}
+
+/// Access bookkeeping of a key, updated through shared references so reads only need the
+/// read lock. It is not part of the key's data, so it is ignored when comparing.
+#[derive(Debug)]
+pub struct KeyAccess {
+    /// Unix time in milliseconds of the last access.
+    last_access_ms: AtomicU64,
+
+    /// Minutes of the last decrement in the upper 16 bits, 8-bit counter in the lower bits.
+    lfu: AtomicU32,
+}
+
+impl Default for KeyAccess {
+    fn default() -> Self {
+        Self {
+            last_access_ms: AtomicU64::new(unix_time_millis()),
+            lfu: AtomicU32::new(pack_lfu(unix_time_minutes(), LFU_INIT_VAL)),
+        }
+    }
+}
+
+impl Clone for KeyAccess {
+    fn clone(&self) -> Self {
+        Self {
+            last_access_ms: AtomicU64::new(self.last_access_ms()),
+            lfu: AtomicU32::new(self.lfu.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+impl PartialEq for KeyAccess {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for KeyAccess {}
+
+impl KeyAccess {
+    /// Records an access to the key, decaying and then incrementing the frequency counter.
+    pub fn touch(&self, lfu: LfuConfig) {
+        self.last_access_ms
+            .store(unix_time_millis(), Ordering::Relaxed);
+
+        let counter = log_incr(self.frequency(lfu.decay_time), lfu.log_factor);
+        self.lfu
+            .store(pack_lfu(unix_time_minutes(), counter), Ordering::Relaxed);
+    }
+
+    pub fn last_access_ms(&self) -> u64 {
+        self.last_access_ms.load(Ordering::Relaxed)
+    }
+
+    /// Returns the access frequency counter, decremented once for every `decay_time` minutes
+    /// since it was last decremented.
+    pub fn frequency(&self, decay_time: u32) -> u8 {
+        let packed = self.lfu.load(Ordering::Relaxed);
+        let (last_decr, counter) = ((packed >> 8) as u16, packed as u8);
+        if decay_time == 0 {
+            return counter;
+        }
+
+        let elapsed = unix_time_minutes().wrapping_sub(last_decr) as u32;
+        let periods = elapsed / decay_time;
+        counter.saturating_sub(periods.min(u8::MAX as u32) as u8)
+    }
+}
+
+/// Increments the counter with a probability that gets lower the higher the counter is,
+/// so 255 is only reached after about a million accesses with the default log factor.
+fn log_incr(counter: u8, log_factor: u32) -> u8 {
+    if counter == u8::MAX {
+        return counter;
+    }
+    let base = counter.saturating_sub(LFU_INIT_VAL) as f64;
+    let p = 1.0 / (base * log_factor as f64 + 1.0);
+    if rand::thread_rng().gen::<f64>() < p {
+        counter + 1
+    } else {
+        counter
+    }
+}
+
+fn pack_lfu(minutes: u16, counter: u8) -> u32 {
+    (minutes as u32) << 8 | counter as u32
+}
+
+/// Unix time in minutes, truncated to 16 bits.
+fn unix_time_minutes() -> u16 {
+    (unix_time_millis() / 60_000) as u16
+}
+
+fn unix_time_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Evicts keys until the tracked used memory is within `maxmemory`, returning how many were
+/// evicted.
+/// Every round samples up to `samples` candidate keys and evicts the best one for the policy.
+/// Fails if memory can't be freed, either because the policy is noeviction or no key is
+/// eligible.
+///
+/// Candidates are sampled across every shard, which are read locked in order while
+/// sampling, and only the victim's shard is write locked to remove it. Victims are then
+/// handed to `on_evict`, with the shard unlocked.
+pub fn evict(
+    store: &Store,
+    memory: &MemoryTracker,
+    maxmemory: u64,
+    policy: EvictionPolicy,
+    samples: usize,
+    lfu: LfuConfig,
+    mut on_evict: impl FnMut(Key, StoredData),
+) -> Result<usize, EvictionError> {
+    let mut evicted = 0;
+    let mut rng = rand::thread_rng();
+
+    while memory.used() > maxmemory {
+        if policy == EvictionPolicy::NoEviction {
+            return Err(EvictionError::OutOfMemory);
+        }
+
+        let shards: Vec<_> = store.shards().iter().map(|shard| shard.read()).collect();
+        let candidates = shards
+            .iter()
+            .flat_map(|shard| shard.iter())
+            .filter(|(_, data)| !policy.volatile_only() || data.deadline.is_some())
+            .choose_multiple(&mut rng, samples);
+        let victim = match policy {
+            EvictionPolicy::AllKeysLru | EvictionPolicy::VolatileLru => candidates
+                .into_iter()
+                .min_by_key(|(_, data)| data.access.last_access_ms()),
+            EvictionPolicy::AllKeysLfu | EvictionPolicy::VolatileLfu => candidates
+                .into_iter()
+                .min_by_key(|(_, data)| data.access.frequency(lfu.decay_time)),
+            EvictionPolicy::VolatileTtl => {
+                candidates.into_iter().min_by_key(|(_, data)| data.deadline)
+            }
+            _ => candidates.into_iter().next(),
+        };
+
+        let key = match victim {
+            Some((key, _)) => key.clone(),
+            None => return Err(EvictionError::OutOfMemory),
+        };
+        drop(shards);
+        let removed = store.write(&key).remove(&key);
+        if let Some(data) = removed {
+            memory.free(entry_usage(&key, &data));
+            evicted += 1;
+            on_evict(key, data);
+        }
+    }
+
+    Ok(evicted)
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::super::handler::StoredData;
+    use super::super::key::Key;
+    use super::super::memory::used_memory;
+    use super::*;
+
+    fn new_map(keys: &[(&str, Option<Duration>)]) -> Store {
+        keys.iter()
+            .map(|(key, expiry)| {
+                let deadline = expiry.map(|e| SystemTime::now() + e);
+                (Key::from(*key), StoredData::new("value".into(), deadline))
+            })
+            .collect()
+    }
+
+    fn new_tracker(map: &Store) -> MemoryTracker {
+        let memory = MemoryTracker::default();
+        memory.record(0, used_memory(map));
+        memory
+    }
+
+    fn contains(map: &Store, key: &str) -> bool {
+        map.read(key.as_bytes()).contains_key(key.as_bytes())
+    }
+
+    fn evict_all(
+        map: &Store,
+        maxmemory: u64,
+        policy: EvictionPolicy,
+        lfu: LfuConfig,
+    ) -> Result<usize, EvictionError> {
+        let memory = new_tracker(map);
+        evict(map, &memory, maxmemory, policy, 5, lfu, |_, _| ())
+    }
+
+    #[test]
+    fn noeviction_rejects_when_over_limit() {
+        let map = new_map(&[("a", None)]);
+
+        assert_eq!(
+            evict_all(&map, 1, EvictionPolicy::NoEviction, LfuConfig::default()),
+            Err(EvictionError::OutOfMemory)
+        );
+        assert_eq!(map.len(), 1);
+    }
+
+    #[test]
+    fn volatile_ttl_evicts_nearest_deadline() {
+        let map = new_map(&[
+            ("persistent", None),
+            ("soon", Some(Duration::from_secs(10))),
+            ("later", Some(Duration::from_secs(100))),
+        ]);
+        let maxmemory = used_memory(&map) - 1;
+
+        let evicted = evict_all(
+            &map,
+            maxmemory,
+            EvictionPolicy::VolatileTtl,
+            LfuConfig::default(),
+        )
+        .expect("Evict unexpected error");
+        assert_eq!(evicted, 1);
+        assert!(!contains(&map, "soon"));
+    }
+
+    #[test]
+    fn volatile_fails_without_volatile_keys() {
+        let map = new_map(&[("a", None), ("b", None)]);
+
+        assert_eq!(
+            evict_all(&map, 1, EvictionPolicy::VolatileLru, LfuConfig::default()),
+            Err(EvictionError::OutOfMemory)
+        );
+    }
+
+    #[test]
+    fn allkeys_lru_evicts_least_recently_used() {
+        let map = new_map(&[("old", None), ("new", None)]);
+        let key = b"old".as_slice();
+        map.read(key)
+            .get(key)
+            .unwrap()
+            .access
+            .last_access_ms
+            .store(0, Ordering::Relaxed);
+        let maxmemory = used_memory(&map) - 1;
+
+        evict_all(
+            &map,
+            maxmemory,
+            EvictionPolicy::AllKeysLru,
+            LfuConfig::default(),
+        )
+        .expect("Evict unexpected error");
+        assert!(!contains(&map, "old"));
+        assert!(contains(&map, "new"));
+    }
+
+    #[test]
+    fn allkeys_lfu_evicts_least_frequently_used() {
+        let map = new_map(&[("cold", None), ("hot", None)]);
+        let lfu = LfuConfig {
+            log_factor: 0,
+            decay_time: 0,
+        };
+        for _ in 0..10 {
+            let key = b"hot".as_slice();
+            map.read(key).get(key).unwrap().access.touch(lfu);
+        }
+        let maxmemory = used_memory(&map) - 1;
+
+        evict_all(&map, maxmemory, EvictionPolicy::AllKeysLfu, lfu)
+            .expect("Evict unexpected error");
+        assert!(!contains(&map, "cold"));
+        assert!(contains(&map, "hot"));
+    }
+
+    #[test]
+    fn frequency_counter_increments_and_decays() {
+        let access = KeyAccess::default();
+        let lfu = LfuConfig {
+            log_factor: 0,
+            decay_time: 0,
+        };
+        access.touch(lfu);
+        assert_eq!(access.frequency(0), LFU_INIT_VAL + 1);
+
+        // Pretend the last decrement happened three minutes ago
+        let three_minutes_ago = unix_time_minutes().wrapping_sub(3);
+        access
+            .lfu
+            .store(pack_lfu(three_minutes_ago, 10), Ordering::Relaxed);
+        assert_eq!(access.frequency(1), 7);
+        assert_eq!(access.frequency(2), 9);
+    }
+}
@@ -1,68 +1,1701 @@
 use std::{
-    collections::HashMap,
-    sync::{Arc, RwLock},
-    time::SystemTime,
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::{Instant, SystemTime},
 };
 
+#[cfg(feature = "replication")]
+use std::future::Future;
+
+use parking_lot::{Mutex, MutexGuard, RwLock};
 use thiserror::Error;
-use tracing::info;
+#[cfg(feature = "replication")]
+use tracing::error;
+use tracing::{debug, info, info_span};
 
+#[cfg(feature = "chaos")]
+use super::chaos::Chaos;
+#[cfg(feature = "persistence")]
+use super::cmd::{Bgsave, Save, SaveArg, SaveError, SaveHandler};
+use super::health::HealthSource;
+#[cfg(feature = "metrics")]
+use super::metrics::MetricsSource;
+use super::{
+    acl::{AccessControl, AclError},
+    audit::AuditLog,
+    blocking::BlockedKeys,
+    capture::Capture,
+    clients::{ClientRegistry, ClientState},
+    clock::{self, Clock},
+    cluster::{ClusterState, RedirectError},
+    cmd::{
+        table::{self, CommandSpec},
+        Acl, Append, Asking, Auth, BPop, Client, ClientArg, ClientCommandError, ClientSubcommand,
+        Cluster, ClusterCommandError, Command, Commands, CommandsError, Config, Debug, DebugError,
+        Del, Echo, Exists, Expire, Get, GetRange, HDel, HExists, HGet, HGetAll, HGetDel, HGetEx,
+        HIncrBy, HLen, HSet, Hello, HelloError, Incr, Info, Keys, LLen, LRange, ListEnd, MGet,
+        MSet, Memory, MemoryError, Object, ObjectError, ParseCommandError, Persist, Ping, Pop,
+        PopArg, Pttl, Push, SAdd, SCard, SIsMember, SMembers, SRem, Set, SetOps, SetRange, Strlen,
+        Type, Unlink, XRange, XRead, ZAdd, ZCard, ZRange, ZRank, ZRem, ZScore,
+    },
+    config::{ConfigError, ConfigValues, ServerConfig},
+    events::{KeyEvent, KeyEventListener, KeyEvents},
+    eviction::{self, EvictionError, KeyAccess},
+    hash::Hash,
+    key::Key,
+    lazyfree::LazyFree,
+    list::List,
+    memory::{self, MemoryTracker},
+    persistence::PersistenceState,
+    plugin::{CommandPlugin, CommandPlugins, PluginError},
+    replication::{FailoverState, ReplicaState, ReplicationState, Role, SharedRole},
+    reply::Reply,
+    resp::{BulkString, SimpleError, Value},
+    session::Request,
+    stats::CommandStats,
+    store::Store,
+    stream::Stream,
+    throttle::AuthThrottle,
+    tracking::{self, TrackingTable},
+    zset::SortedSet,
+};
+#[cfg(feature = "scripting")]
+use super::{
+    cmd::{Eval, FCall, Function},
+    scripting::{Functions, ScriptCall, ScriptError},
+};
+#[cfg(feature = "replication")]
 use super::{
-    cmd::{Command, Echo, Get, Info, Ping, Set},
-    resp::{BulkString, Value},
+    cmd::{
+        Failover, FailoverError, Psync, PsyncError, ReplConf, ReplConfArg, ReplConfArgConfig,
+        ReplicaOf, ReplicaOfError, Wait, WaitError,
+    },
+    config::HostPort,
+    replica::{MasterLink, Replication, ReplicationError},
+    util,
 };
 
+/// Every way a command can fail, each sent to the client as an error reply prefixed with
+/// its code, see `reply`.
 #[derive(Debug, Error)]
-pub enum HandleCommandError {}
+pub enum HandleCommandError {
+    #[error(transparent)]
+    Parse(#[from] ParseCommandError),
+
+    /// The key holds another type of value than the command works on.
+    #[error("Operation against a key holding the wrong kind of value")]
+    WrongType,
+
+    #[error("value is not an integer or out of range")]
+    NotInteger,
+
+    #[error("value is not a valid float")]
+    NotFloat,
+
+    #[error("increment or decrement would overflow")]
+    Overflow,
+
+    #[error("hash value is not an integer")]
+    HashNotInteger,
+
+    #[error("resulting score is not a number (NaN)")]
+    ScoreNaN,
+
+    #[error("syntax error")]
+    Syntax,
+
+    #[error("no such key")]
+    NoSuchKey,
+
+    #[error("index out of range")]
+    OutOfRange,
+
+    #[error("offset is out of range")]
+    OffsetOutOfRange,
+
+    #[error("string exceeds maximum allowed size (proto-max-bulk-len)")]
+    StringTooLong,
+
+    /// A client other than our master tried to write to this replica.
+    #[error("You can't write against a read only replica.")]
+    Readonly,
+
+    /// Failure made up by the fault injection, see `Chaos`.
+    #[cfg(feature = "chaos")]
+    #[error("injected fault")]
+    InjectedFault,
+
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+
+    #[error(transparent)]
+    Acl(#[from] AclError),
+
+    #[error(transparent)]
+    Eviction(#[from] EvictionError),
+
+    #[error(transparent)]
+    Memory(#[from] MemoryError),
+
+    #[error(transparent)]
+    Object(#[from] ObjectError),
+
+    #[error(transparent)]
+    Debug(#[from] DebugError),
+
+    #[error(transparent)]
+    Commands(#[from] CommandsError),
+
+    #[error(transparent)]
+    Client(#[from] ClientCommandError),
+
+    #[error(transparent)]
+    Hello(#[from] HelloError),
+
+    #[error(transparent)]
+    Cluster(#[from] ClusterCommandError),
+
+    #[error(transparent)]
+    Redirect(#[from] RedirectError),
+
+    #[error(transparent)]
+    Plugin(#[from] PluginError),
+
+    #[cfg(feature = "replication")]
+    #[error(transparent)]
+    Psync(#[from] PsyncError),
+
+    #[cfg(feature = "replication")]
+    #[error(transparent)]
+    Wait(#[from] WaitError),
+
+    #[cfg(feature = "replication")]
+    #[error(transparent)]
+    Failover(#[from] FailoverError),
+
+    #[cfg(feature = "replication")]
+    #[error(transparent)]
+    ReplicaOf(#[from] ReplicaOfError),
+
+    #[cfg(feature = "persistence")]
+    #[error(transparent)]
+    Save(#[from] SaveError),
+
+    #[cfg(feature = "scripting")]
+    #[error(transparent)]
+    Script(#[from] ScriptError),
+}
+
+impl HandleCommandError {
+    /// Returns the error code prefixed to the RESP error reply.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::WrongType => "WRONGTYPE",
+            Self::Readonly => "READONLY",
+            Self::Acl(e) => e.code(),
+            Self::Eviction(e) => e.code(),
+            Self::Redirect(e) => e.code(),
+            Self::Hello(e) => e.code(),
+            _ => "ERR",
+        }
+    }
+
+    /// Returns the error reply sent to the client, e.g. `-WRONGTYPE Operation against...`.
+    pub fn reply(&self) -> Value {
+        // Errors raised by scripts already start with their code.
+        #[cfg(feature = "scripting")]
+        if let Self::Script(ScriptError::Raised(e)) = self {
+            return Value::SimpleError(SimpleError::from(e.as_str()));
+        }
+        Value::SimpleError(SimpleError::from(format!("{} {self}", self.code())))
+    }
+}
+
+/// Most expired keys removed from a shard by one active expire cycle, which bounds how long
+/// the shard stays locked.
+pub const ACTIVE_EXPIRE_KEYS_PER_SHARD: usize = 200;
+
+/// Longest string a command may make, see `proto-max-bulk-len`.
+pub const MAX_STRING_LEN: usize = 512 * 1024 * 1024;
+
+/// Value held by a key. Commands on one type reply WRONGTYPE for keys holding another.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum StoredValue {
+    String(BulkString),
+    List(List),
+    Hash(Hash),
+    Set(HashSet<BulkString>),
+    SortedSet(SortedSet),
+    Stream(Stream),
+}
+
+impl StoredValue {
+    /// Returns the name of the type, as reported by TYPE.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Self::String(_) => "string",
+            Self::List(_) => "list",
+            Self::Hash(_) => "hash",
+            Self::Set(_) => "set",
+            Self::SortedSet(_) => "zset",
+            Self::Stream(_) => "stream",
+        }
+    }
+
+    /// Returns the value if it is a string.
+    pub fn as_string(&self) -> Option<&BulkString> {
+        match self {
+            Self::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Returns the value if it is a list.
+    pub fn as_list(&self) -> Option<&List> {
+        match self {
+            Self::List(list) => Some(list),
+            _ => None,
+        }
+    }
+
+    pub fn as_list_mut(&mut self) -> Option<&mut List> {
+        match self {
+            Self::List(list) => Some(list),
+            _ => None,
+        }
+    }
+
+    /// Returns the value if it is a hash.
+    pub fn as_hash(&self) -> Option<&Hash> {
+        match self {
+            Self::Hash(hash) => Some(hash),
+            _ => None,
+        }
+    }
+
+    pub fn as_hash_mut(&mut self) -> Option<&mut Hash> {
+        match self {
+            Self::Hash(hash) => Some(hash),
+            _ => None,
+        }
+    }
+
+    /// Returns the value if it is a set.
+    pub fn as_set(&self) -> Option<&HashSet<BulkString>> {
+        match self {
+            Self::Set(set) => Some(set),
+            _ => None,
+        }
+    }
+
+    pub fn as_set_mut(&mut self) -> Option<&mut HashSet<BulkString>> {
+        match self {
+            Self::Set(set) => Some(set),
+            _ => None,
+        }
+    }
+
+    /// Returns the value if it is a sorted set.
+    pub fn as_zset(&self) -> Option<&SortedSet> {
+        match self {
+            Self::SortedSet(zset) => Some(zset),
+            _ => None,
+        }
+    }
+
+    pub fn as_zset_mut(&mut self) -> Option<&mut SortedSet> {
+        match self {
+            Self::SortedSet(zset) => Some(zset),
+            _ => None,
+        }
+    }
+
+    /// Returns the value if it is a stream.
+    pub fn as_stream(&self) -> Option<&Stream> {
+        match self {
+            Self::Stream(stream) => Some(stream),
+            _ => None,
+        }
+    }
+
+    /// Returns the bytes of a string value, empty for other types.
+    pub fn string_bytes(&self) -> &[u8] {
+        self.as_string()
+            .and_then(BulkString::as_bytes)
+            .unwrap_or_default()
+    }
+
+    /// Returns the approximate number of bytes the value holds outside of the keyspace
+    /// entry, counting every element of a collection.
+    pub fn heap_len(&self) -> usize {
+        let len = |s: &BulkString| s.as_bytes().map_or(0, <[u8]>::len);
+        let element = std::mem::size_of::<BulkString>();
+        match self {
+            Self::String(s) => len(s),
+            Self::List(list) => list.bytes(),
+            Self::Hash(hash) => hash.bytes(),
+            Self::Set(set) => set.iter().map(|v| element + len(v)).sum(),
+            Self::SortedSet(zset) => zset.bytes(),
+            Self::Stream(stream) => stream
+                .iter()
+                .map(|(id, fields)| {
+                    std::mem::size_of_val(id)
+                        + fields
+                            .iter()
+                            .map(|(field, value)| 2 * element + len(field) + len(value))
+                            .sum::<usize>()
+                })
+                .sum(),
+        }
+    }
+
+    /// Returns how the value is stored, as reported by OBJECT ENCODING. Lists, hashes and
+    /// sorted sets are `listpack` while they are small, sets are always stored in full.
+    pub fn encoding(&self) -> &'static str {
+        match self {
+            Self::String(_) => {
+                let bytes = self.string_bytes();
+                if parse_int(bytes).is_some() {
+                    "int"
+                } else if bytes.len() <= 44 {
+                    "embstr"
+                } else {
+                    "raw"
+                }
+            }
+            Self::List(list) => list.encoding(),
+            Self::Hash(hash) => hash.encoding(),
+            Self::Set(_) => "hashtable",
+            Self::SortedSet(zset) => zset.encoding(),
+            Self::Stream(_) => "stream",
+        }
+    }
+}
+
+/// Parses the bytes as an integer written the way Redis writes it, without leading zeros
+/// or a plus sign.
+pub(super) fn parse_int(bytes: &[u8]) -> Option<i64> {
+    std::str::from_utf8(bytes)
+        .ok()
+        .and_then(|s| s.parse::<i64>().ok())
+        .filter(|n| n.to_string().len() == bytes.len())
+}
+
+impl From<BulkString> for StoredValue {
+    fn from(value: BulkString) -> Self {
+        Self::String(value)
+    }
+}
+
+impl From<&str> for StoredValue {
+    fn from(value: &str) -> Self {
+        Self::String(value.into())
+    }
+}
+
+impl From<String> for StoredValue {
+    fn from(value: String) -> Self {
+        Self::String(value.into())
+    }
+}
+
+impl From<Vec<u8>> for StoredValue {
+    fn from(value: Vec<u8>) -> Self {
+        Self::String(value.into())
+    }
+}
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct StoredData {
-    pub value: BulkString,
+    pub value: StoredValue,
     pub deadline: Option<SystemTime>,
+    pub access: KeyAccess,
+
+    /// Whether the string was last modified in place, by APPEND or SETRANGE, which Redis
+    /// stores as `raw` however short it is.
+    pub raw: bool,
 }
 
 impl StoredData {
-    /// Returns true if there is a deadline and current time is greater than deadline.
-    pub fn has_expired(&self) -> bool {
-        return self.deadline.is_some() && SystemTime::now().gt(&self.deadline.unwrap());
+    pub fn new(value: StoredValue, deadline: Option<SystemTime>) -> Self {
+        Self {
+            value,
+            deadline,
+            access: KeyAccess::default(),
+            raw: false,
+        }
+    }
+
+    /// Returns true if there is a deadline and `now` is greater than deadline, or if the
+    /// value is a hash whose every field expired.
+    pub fn expired_at(&self, now: SystemTime) -> bool {
+        self.deadline.is_some_and(|deadline| now > deadline)
+            || self
+                .value
+                .as_hash()
+                .is_some_and(|hash| hash.expired_at(now))
+    }
+
+    /// Returns how the value is stored, as reported by OBJECT ENCODING.
+    pub fn encoding(&self) -> &'static str {
+        match self.value {
+            StoredValue::String(_) if self.raw => "raw",
+            _ => self.value.encoding(),
+        }
     }
 }
 
-#[derive(Debug)]
+/// Runs commands against the shared server state. Every connection has its own clone, so
+/// commands from different connections run concurrently and only contend on the locks of
+/// the state they touch.
+#[derive(Debug, Clone)]
 pub struct CommandHandler {
-    map: Arc<RwLock<HashMap<BulkString, StoredData>>>,
-    config: CommandHandlerConfig,
-}
+    store: Arc<Store>,
+    clock: Arc<dyn Clock>,
+    config: Arc<ServerConfig>,
+    persistence: Arc<PersistenceState>,
+    memory: Arc<MemoryTracker>,
+    lazyfree: Arc<LazyFree>,
+    acl: Arc<RwLock<AccessControl>>,
+    clients: Arc<ClientRegistry>,
+    tracking: Arc<Mutex<TrackingTable>>,
+    stats: Arc<CommandStats>,
+    auth_throttle: Arc<AuthThrottle>,
+
+    /// Keys clients are blocked on by BLPOP or BRPOP, woken by pushes.
+    blocked: Arc<BlockedKeys>,
+
+    /// Side of replication this server is on, switched by REPLICAOF and FAILOVER.
+    role: SharedRole,
+
+    /// Link to our master while a replica, stopped once the role switches.
+    #[cfg(feature = "replication")]
+    master_link: Arc<MasterLink>,
+
+    /// Slot table of the cluster, `None` unless cluster mode is enabled.
+    cluster: Option<Arc<RwLock<ClusterState>>>,
+
+    /// Listeners of the embedding application, told about every change to a key.
+    events: Arc<KeyEvents>,
+
+    /// Commands added by the embedding application.
+    plugins: Arc<CommandPlugins>,
+
+    /// File every command run is appended to, if capturing.
+    capture: Option<Arc<Capture>>,
 
-#[derive(Debug)]
-pub struct CommandHandlerConfig {
-    pub is_replica: bool,
-    pub master_repl_id_and_offset: Option<(String, u64)>,
+    /// Log of write and administrative commands, if auditing.
+    audit: Option<Arc<AuditLog>>,
+
+    /// Faults injected into every command, if any.
+    #[cfg(feature = "chaos")]
+    chaos: Option<Arc<Chaos>>,
+
+    /// Libraries loaded with FUNCTION LOAD.
+    #[cfg(feature = "scripting")]
+    functions: Arc<RwLock<Functions>>,
+
+    /// Writes made by the running script, propagated to replicas in its place.
+    #[cfg(feature = "scripting")]
+    script_effects: Vec<Value>,
+
+    /// Invalidations for other clients, delivered once the current client is unlocked.
+    pending_invalidations: Vec<(u64, Vec<BulkString>)>,
 }
 
 impl CommandHandler {
     pub fn new(
-        map: Arc<RwLock<HashMap<BulkString, StoredData>>>,
-        config: CommandHandlerConfig,
+        store: Arc<Store>,
+        config: Arc<ServerConfig>,
+        persistence: Arc<PersistenceState>,
+        memory: Arc<MemoryTracker>,
+        acl: Arc<RwLock<AccessControl>>,
+        clients: Arc<ClientRegistry>,
+        replication: Option<Arc<ReplicationState>>,
     ) -> Self {
-        Self { map, config }
+        Self {
+            store,
+            clock: clock::system(),
+            config,
+            persistence,
+            memory,
+            lazyfree: Arc::new(LazyFree::new()),
+            acl,
+            clients,
+            tracking: Arc::new(Mutex::new(TrackingTable::default())),
+            stats: Arc::new(CommandStats::default()),
+            auth_throttle: Arc::new(AuthThrottle::default()),
+            blocked: Arc::new(BlockedKeys::default()),
+            role: SharedRole::new(replication.map(Role::Master)),
+            #[cfg(feature = "replication")]
+            master_link: Arc::new(MasterLink::default()),
+            cluster: None,
+            events: Arc::new(KeyEvents::default()),
+            plugins: Arc::new(CommandPlugins::default()),
+            capture: None,
+            audit: None,
+            #[cfg(feature = "chaos")]
+            chaos: None,
+            #[cfg(feature = "scripting")]
+            functions: Arc::new(RwLock::new(Functions::default())),
+            #[cfg(feature = "scripting")]
+            script_effects: Vec::new(),
+            pending_invalidations: Vec::new(),
+        }
+    }
+
+    /// Sets the clock keys expire by, e.g. a `TestClock` to expire keys without waiting.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Runs the handler in cluster mode, serving the slots of `cluster`.
+    pub fn with_cluster(mut self, cluster: Arc<RwLock<ClusterState>>) -> Self {
+        self.cluster = Some(cluster);
+        self
+    }
+
+    /// Runs the handler as a replica at the position of `replica` in its master's stream.
+    pub fn with_replica(mut self, replica: Arc<ReplicaState>) -> Self {
+        self.role = SharedRole::new(Some(Role::Replica(replica)));
+        self
+    }
+
+    /// Adds a listener to be told about sets, deletes, expirations and evictions of keys.
+    pub fn with_key_event_listener(mut self, listener: Arc<dyn KeyEventListener>) -> Self {
+        Arc::make_mut(&mut self.events).add(listener);
+        self
+    }
+
+    /// Appends every command run to the capture.
+    pub fn with_capture(mut self, capture: Arc<Capture>) -> Self {
+        self.capture = Some(capture);
+        self
+    }
+
+    /// Records every write and administrative command in the audit log.
+    pub fn with_audit(mut self, audit: Arc<AuditLog>) -> Self {
+        self.audit = Some(audit);
+        self
+    }
+
+    /// Injects the faults into every command.
+    #[cfg(feature = "chaos")]
+    pub fn with_chaos(mut self, chaos: Arc<Chaos>) -> Self {
+        self.chaos = Some(chaos);
+        self
+    }
+
+    /// Adds a command run by the plugin. Connections only see the commands registered before
+    /// the handler was cloned for them.
+    pub fn register_command(&mut self, plugin: Arc<dyn CommandPlugin>) -> Result<(), PluginError> {
+        Arc::make_mut(&mut self.plugins).register(plugin)
+    }
+
+    /// Parses the command of the request, which may be one of the plugins.
+    pub fn parse(&self, req: &Request) -> Result<Command, ParseCommandError> {
+        let value = req.value();
+        let plugin = value
+            .array()
+            .and_then(|array| array.values())
+            .and_then(|values| values.split_first())
+            .and_then(|(name, args)| {
+                let name = name.bulk_string()?.as_str()?;
+                self.plugins.parse(&name, args)
+            });
+        match plugin {
+            Some(arg) => Ok(Command::Plugin(arg?)),
+            None => Command::try_from(value),
+        }
+    }
+
+    #[cfg(feature = "chaos")]
+    pub fn chaos(&self) -> Option<&Chaos> {
+        self.chaos.as_deref()
+    }
+
+    pub fn is_capturing(&self) -> bool {
+        self.capture.is_some()
+    }
+
+    /// Appends the request to the capture, if any, as run now.
+    pub fn record(&self, req: &Request) {
+        if let Some(capture) = &self.capture {
+            capture.record(self.clock.now(), req);
+        }
+    }
+
+    /// Returns the spec of a built-in or plugin command.
+    fn spec(&self, name: &str) -> Option<&CommandSpec> {
+        table::lookup(name).or_else(|| self.plugins.lookup(name))
+    }
+
+    pub fn clock(&self) -> Arc<dyn Clock> {
+        self.clock.clone()
+    }
+
+    pub fn config(&self) -> Arc<ServerConfig> {
+        self.config.clone()
+    }
+
+    pub fn acl(&self) -> Arc<RwLock<AccessControl>> {
+        self.acl.clone()
+    }
+
+    pub fn clients(&self) -> Arc<ClientRegistry> {
+        self.clients.clone()
+    }
+
+    pub fn stats(&self) -> Arc<CommandStats> {
+        self.stats.clone()
+    }
+
+    pub fn store(&self) -> Arc<Store> {
+        self.store.clone()
+    }
+
+    pub fn persistence(&self) -> Arc<PersistenceState> {
+        self.persistence.clone()
+    }
+
+    /// Returns where this replica is in its master's stream, `None` on a master.
+    pub fn replica(&self) -> Option<Arc<ReplicaState>> {
+        self.role.replica()
+    }
+
+    /// Returns the replication state of this master, `None` on a replica.
+    fn replication(&self) -> Option<Arc<ReplicationState>> {
+        self.role.replication()
+    }
+
+    /// Returns the replication state to wait on before running the request if it is a write
+    /// paused by a FAILOVER.
+    #[cfg(feature = "replication")]
+    pub fn write_pause(&self, req: &Request) -> Option<Arc<ReplicationState>> {
+        let replication = self.replication()?;
+        if replication.failover_state() == FailoverState::NoFailover {
+            return None;
+        }
+        let name = req
+            .value()
+            .array()?
+            .values()?
+            .first()?
+            .bulk_string()?
+            .as_str()?;
+        let spec = self.spec(&name)?;
+        (spec.has_flag("write") || spec.has_flag("may_replicate")).then_some(replication)
+    }
+
+    /// Follows `master` in place of the current one, or serves as a master for `None`. The
+    /// dataset is kept along with the position it is at, so that the new master, or the
+    /// replicas of this one, can continue from there.
+    #[cfg(feature = "replication")]
+    pub fn follow(&self, master: Option<HostPort>) {
+        let position = match self.role.get() {
+            Some(Role::Master(_)) if master.is_none() => return,
+            Some(Role::Master(state)) => Some((state.repl_id().to_string(), state.offset())),
+            Some(Role::Replica(state)) => state.position(),
+            None => None,
+        };
+        info!(
+            "Switching to {}",
+            master
+                .as_ref()
+                .map_or("master".to_string(), |m| format!("replica of {m}"))
+        );
+        self.config.set_replica_of(master.clone());
+        let previous = match master {
+            Some(master) => {
+                let state = ReplicaState::new(position);
+                let previous = self.role.replace(Role::Replica(Arc::new(state)));
+                let config = self.config.clone();
+                self.spawn_link(async move { Replication::connect(master, &config).await });
+                previous
+            }
+            None => {
+                self.master_link.stop();
+                let state = match position {
+                    Some((repl_id, offset)) => ReplicationState::continue_from(repl_id, offset),
+                    None => ReplicationState::new(),
+                };
+                self.role.replace(Role::Master(Arc::new(state)))
+            }
+        };
+        if let Some(Role::Master(state)) = previous {
+            state.detach_all();
+        }
+    }
+
+    /// Replicates the master `connect` links to, stopping the link running before, if any.
+    #[cfg(feature = "replication")]
+    pub(crate) fn spawn_link(
+        &self,
+        connect: impl Future<Output = Result<Replication, ReplicationError>> + Send + 'static,
+    ) {
+        let mut stop_rx = self.master_link.replace();
+        let link_stop_rx = stop_rx.clone();
+        let handler = self.clone();
+        util::spawn_named("replication", async move {
+            let replicate = async { connect.await?.run(handler, link_stop_rx).await };
+            tokio::select! {
+                result = replicate => if let Err(e) = result {
+                    error!("Error replicating from master: {e}");
+                },
+                _ = stop_rx.changed() => (),
+            }
+        });
     }
 
-    pub fn handle(&mut self, cmd: Command) -> Result<Value, HandleCommandError> {
-        info!("Handling command {cmd:?}");
-        match cmd {
-            Command::Ping(arg) => Ok(Ping::handler().handle(arg)),
-            Command::Echo(arg) => Ok(Echo::handler().handle(arg)),
-            Command::Info(arg) => Ok(Info::handler(
-                self.config.is_replica,
-                self.config.master_repl_id_and_offset.clone(),
+    /// Stops replicating from our master, e.g. on shutdown.
+    #[cfg(feature = "replication")]
+    pub fn stop_replicating(&self) {
+        self.master_link.stop();
+    }
+
+    /// Saves the dataset to the RDB file like SAVE does.
+    #[cfg(feature = "persistence")]
+    pub fn save(&self) -> Result<(), SaveError> {
+        self.save_handler().handle(SaveArg).map(|_| ())
+    }
+
+    #[cfg(feature = "persistence")]
+    fn save_handler(&self) -> SaveHandler {
+        Save::handler(
+            self.store.clone(),
+            self.clock.clone(),
+            self.config.clone(),
+            self.persistence.clone(),
+            self.role.get(),
+        )
+    }
+
+    /// Returns what health probes report.
+    pub fn health_source(&self) -> HealthSource {
+        HealthSource {
+            persistence: self.persistence.clone(),
+            config: self.config.clone(),
+            role: self.role.clone(),
+        }
+    }
+
+    /// Returns the sources of the metrics exporter.
+    #[cfg(feature = "metrics")]
+    pub fn metrics_source(&self) -> MetricsSource {
+        MetricsSource {
+            store: self.store.clone(),
+            memory: self.memory.clone(),
+            clients: self.clients.clone(),
+            stats: self.stats.clone(),
+            config: self.config.clone(),
+            role: self.role.clone(),
+        }
+    }
+
+    /// Adds keys read from an RDB file, replacing any key with the same name.
+    pub fn load(&self, entries: impl IntoIterator<Item = (Key, StoredData)>) {
+        for (key, data) in entries {
+            let after = memory::entry_usage(&key, &data);
+            let old = self.store.write(&key).insert(key.clone(), data);
+            let before = old.map_or(0, |old| memory::entry_usage(&key, &old));
+            self.memory.record(before, after);
+        }
+    }
+
+    /// Replaces the whole dataset with the keys, e.g. those of a full resync, removing the
+    /// keys it doesn't have.
+    pub fn replace(&self, entries: impl IntoIterator<Item = (Key, StoredData)>) {
+        for shard in self.store.shards() {
+            let mut shard = shard.write();
+            let keys: Vec<Key> = shard.keys().cloned().collect();
+            for key in keys {
+                if let Some(old) = shard.remove(&key) {
+                    self.memory.free(memory::entry_usage(&key, &old));
+                }
+            }
+        }
+        self.load(entries);
+    }
+
+    /// Removes up to `ACTIVE_EXPIRE_KEYS_PER_SHARD` expired keys from every shard, earliest
+    /// deadline first, unless active expiry is turned off. Returns the number of keys removed.
+    pub fn active_expire_cycle(&self) -> usize {
+        let lazyfree = {
+            let config = self.config.read();
+            if !config.active_expire {
+                return 0;
+            }
+            config.lazyfree_lazy_expire
+        };
+
+        let now = self.clock.now();
+        let mut removed = 0;
+        for shard in self.store.shards() {
+            let expired = shard
+                .write()
+                .remove_expired(now, ACTIVE_EXPIRE_KEYS_PER_SHARD);
+            removed += expired.len();
+            for (key, data) in expired {
+                self.memory.free(memory::entry_usage(&key, &data));
+                self.events.notify(key.as_bytes(), KeyEvent::Expired);
+                if lazyfree {
+                    self.lazyfree.free(data);
+                }
+            }
+        }
+        self.stats.record_expired(removed as u64);
+        removed
+    }
+
+    /// Counts the changes a command replied with, e.g. the keys DEL removed.
+    fn incr_dirty_by(&self, resp: &Value) {
+        if let Value::Integer(changes) = resp {
+            self.persistence.incr_dirty(i64::from(changes) as u64);
+        }
+    }
+
+    /// Counts the elements LPOP or RPOP replied with, none if the key didn't exist.
+    fn incr_dirty_by_popped(&self, resp: &Value) {
+        let popped = match resp {
+            Value::BulkString(element) => element.as_bytes().is_some() as usize,
+            Value::Array(elements) => elements.values().map_or(0, <[Value]>::len),
+            _ => 0,
+        };
+        self.persistence.incr_dirty(popped as u64);
+    }
+
+    /// Pops an element for BLPOP or BRPOP, which may be long after the command returned, so
+    /// the pop is accounted for here and propagated to replicas as LPOP or RPOP.
+    fn pop_blocked(
+        &self,
+        key: &BulkString,
+        end: ListEnd,
+    ) -> Result<Option<BulkString>, HandleCommandError> {
+        let keys = [key.clone()];
+        let replication = self.replication();
+        let mut stream = replication.as_ref().map(|r| r.stream());
+        let before = memory::keys_usage(&self.store, &keys);
+        let popped = Pop::handler(self.store.clone(), self.clock.clone())
+            .with_events(self.events.clone())
+            .pop(key.as_bytes().unwrap_or_default(), 1, end)?;
+        let Some(element) = popped.and_then(|popped| popped.into_iter().next()) else {
+            return Ok(None);
+        };
+        self.memory
+            .record(before, memory::keys_usage(&self.store, &keys));
+        self.persistence.incr_dirty(1);
+        if let Some(stream) = &mut stream {
+            let arg = PopArg {
+                key: key.clone(),
+                count: None,
+                end,
+            };
+            stream.propagate(&Pop::command_value(arg));
+        }
+        Ok(Some(element))
+    }
+
+    /// Returns the lazy free worker if the toggle picked from the config is on.
+    fn lazy(&self, toggle: fn(&ConfigValues) -> bool) -> Option<Arc<LazyFree>> {
+        toggle(&self.config.read()).then(|| self.lazyfree.clone())
+    }
+
+    /// Evicts keys per the eviction policy if used memory is over maxmemory.
+    fn free_memory(&self) -> Result<(), EvictionError> {
+        let (maxmemory, policy, samples, lfu, lazy) = {
+            let config = self.config.read();
+            (
+                config.maxmemory,
+                config.maxmemory_policy,
+                config.maxmemory_samples,
+                config.lfu(),
+                config.lazyfree_lazy_eviction,
             )
-            .handle(arg)),
-            Command::ReplConf(arg) => todo!(),
-            // Clone Arc to increment reference count.
-            Command::Set(arg) => Ok(Set::handler(self.map.clone()).handle(arg)),
-            Command::Get(arg) => Ok(Get::handler(self.map.clone()).handle(arg)),
+        };
+        if maxmemory == 0 {
+            return Ok(());
+        }
+
+        let evicted = eviction::evict(
+            &self.store,
+            &self.memory,
+            maxmemory,
+            policy,
+            samples as usize,
+            lfu,
+            |key, data| {
+                self.events.notify(key.as_bytes(), KeyEvent::Evicted);
+                if lazy {
+                    self.lazyfree.free(data);
+                }
+            },
+        )?;
+        if evicted > 0 {
+            self.stats.record_evicted(evicted as u64);
+            info!("Evicted {evicted} keys to stay within maxmemory");
+        }
+        Ok(())
+    }
+
+    /// Handles the command on behalf of the client's authenticated user, generating the
+    /// whole reply even if it would be streamed to a connection.
+    pub fn handle(
+        &mut self,
+        cmd: Command,
+        client: &mut ClientState,
+    ) -> Result<Value, HandleCommandError> {
+        self.handle_reply(cmd, client).map(Reply::into_value)
+    }
+
+    /// Handles the command on behalf of the client's authenticated user, recording its
+    /// latency and outcome in the command stats.
+    ///
+    /// Large replies are streamed, so their elements are only generated as the reply is
+    /// written.
+    pub fn handle_reply(
+        &mut self,
+        cmd: Command,
+        client: &mut ClientState,
+    ) -> Result<Reply, HandleCommandError> {
+        let name = cmd.name();
+        let _span = info_span!("command", name, client = client.id()).entered();
+        debug!("Handling command {cmd:?}");
+        client.touch(name);
+        let spec = self.spec(name).copied();
+        // ASKING only applies to the command right after it.
+        let asking = std::mem::take(&mut client.flags.asking);
+        // Account for the memory of the keys the command touches, including keys that
+        // expire on access.
+        let keys: Vec<BulkString> = cmd
+            .key_args()
+            .into_iter()
+            .filter(|key| key.as_bytes().is_some())
+            .cloned()
+            .collect();
+        let audit = self
+            .audit
+            .clone()
+            .filter(|_| spec.is_some_and(|spec| spec.has_flag("write") || spec.has_flag("admin")));
+        if let Err(e) = self.admit(&cmd, client, asking) {
+            self.stats.record_rejected(name);
+            if let Some(audit) = audit {
+                audit.record(self.clock.now(), client, name, &keys, Some(&e.reply()));
+            }
+            return Err(e);
+        }
+        let is_client = matches!(cmd, Command::Client(_));
+        let is_caching = matches!(
+            cmd,
+            Command::Client(ClientArg {
+                subcommand: ClientSubcommand::Caching(_),
+            })
+        );
+
+        // Blocking commands account for what they pop themselves, as it may be long after
+        // they return.
+        let accounted = match spec {
+            Some(spec) if spec.has_flag("blocking") => &[][..],
+            _ => &keys[..],
+        };
+        let usage = |store: &Arc<Store>| memory::keys_usage(store, accounted);
+        let before = usage(&self.store);
+        let start = Instant::now();
+        let result = self.dispatch(cmd, client);
+        self.stats
+            .record_call(name, start.elapsed(), result.is_ok());
+        if let Some(audit) = audit {
+            let error = result.as_ref().err().map(HandleCommandError::reply);
+            audit.record(self.clock.now(), client, name, &keys, error.as_ref());
+        }
+        self.memory.record(before, usage(&self.store));
+
+        // Keep client-side caches in sync, remembering what tracking clients read and
+        // notifying them when it changes.
+        if is_client {
+            let bcast = client.tracking.as_ref().filter(|tracking| tracking.bcast);
+            self.tracking()
+                .set_bcast(client.id(), bcast.map(|tracking| tracking.prefixes.clone()));
+        }
+        if let (Ok(_), Some(spec)) = (&result, spec) {
+            if spec.has_flag("write") {
+                self.invalidate(client, &keys);
+            } else if spec.has_flag("readonly") {
+                self.track(client, &keys);
+            }
+        }
+        // CLIENT CACHING only applies to the command right after it.
+        if !is_caching {
+            client.caching = None;
+        }
+
+        result
+    }
+
+    /// Handles the command of the request like `handle_reply`, then sends the request to
+    /// our replicas if it is a write that succeeded.
+    ///
+    /// Writes run while holding the replication stream, so that replicas apply them in the
+    /// order they ran here. Blocking commands propagate what they pop themselves, and
+    /// scripts the writes they made, even if they failed after making them.
+    pub fn handle_request(
+        &mut self,
+        cmd: Command,
+        req: &Request,
+        client: &mut ClientState,
+    ) -> Result<Reply, HandleCommandError> {
+        let spec = self.spec(cmd.name());
+        let is_write =
+            spec.is_some_and(|spec| spec.has_flag("write") && !spec.has_flag("blocking"));
+        let is_script = spec.is_some_and(|spec| spec.has_flag("may_replicate"));
+        let replication = self.replication().filter(|_| is_write || is_script);
+        let mut stream = replication.as_ref().map(|r| r.stream());
+        let reply = self.handle_reply(cmd, client);
+        #[cfg(feature = "scripting")]
+        let effects = std::mem::take(&mut self.script_effects);
+        if let Some(stream) = &mut stream {
+            #[cfg(feature = "scripting")]
+            for effect in &effects {
+                stream.propagate(effect);
+            }
+            if is_write && reply.is_ok() {
+                stream.propagate(req.value());
+            }
+        }
+        reply
+    }
+
+    /// Checks that the command may run: every command except AUTH and HELLO with AUTH needs
+    /// the user's permission, in cluster mode its keys must be served by this node, commands
+    /// that may grow the dataset are refused if memory can't be freed, and replicas refuse
+    /// writes.
+    ///
+    /// Commands from our master must be obeyed, so it only makes them evict keys.
+    fn admit(
+        &self,
+        cmd: &Command,
+        client: &ClientState,
+        asking: bool,
+    ) -> Result<(), HandleCommandError> {
+        let spec = self.spec(cmd.name());
+        if client.flags.master {
+            if spec.is_some_and(|spec| spec.has_flag("denyoom")) {
+                let _ = self.free_memory();
+            }
+            return Ok(());
+        }
+        let authenticating = match cmd {
+            Command::Auth(_) => true,
+            Command::Hello(arg) => arg.auth.is_some(),
+            _ => false,
+        };
+        if !authenticating {
+            let categories = spec.map(|spec| spec.categories).unwrap_or_default();
+            self.acl
+                .read()
+                .check(client, cmd.name(), categories, &cmd.keys())?;
+        }
+        if let Some(cluster) = &self.cluster {
+            let require_full_coverage = self.config.read().cluster_require_full_coverage;
+            let now = self.clock.now();
+            let exists = |key: &[u8]| {
+                self.store
+                    .read(key)
+                    .get(key)
+                    .is_some_and(|data| !data.expired_at(now))
+            };
+            cluster
+                .read()
+                .route(&cmd.keys(), asking, require_full_coverage, exists)?;
+        }
+        if spec.is_some_and(|spec| spec.has_flag("denyoom")) {
+            self.free_memory()?;
         }
+        if spec.is_some_and(|spec| spec.has_flag("write"))
+            && self.config.read().replica_of.is_some()
+        {
+            return Err(HandleCommandError::Readonly);
+        }
+        Ok(())
+    }
+
+    /// Remembers the keys read by the client if it is tracking them.
+    fn track(&mut self, client: &ClientState, keys: &[BulkString]) {
+        let Some(tracking) = &client.tracking else {
+            return;
+        };
+        let wanted = if tracking.optin {
+            client.caching == Some(true)
+        } else if tracking.optout {
+            client.caching != Some(false)
+        } else {
+            !tracking.bcast
+        };
+        if wanted {
+            self.tracking().track(client.id(), keys);
+        }
+    }
+
+    /// Sends invalidation messages to the clients tracking the keys the client changed.
+    ///
+    /// The client is locked while its command runs, so messages for other clients are only
+    /// queued here and sent by `deliver_invalidations`. Locking them now could deadlock with
+    /// a client invalidating keys of this one at the same time.
+    fn invalidate(&mut self, client: &ClientState, keys: &[BulkString]) {
+        let mut targets: HashMap<u64, Vec<BulkString>> = HashMap::new();
+        {
+            let mut tracking = self.tracking();
+            for key in keys {
+                for id in tracking.invalidate(key) {
+                    targets.entry(id).or_default().push(key.clone());
+                }
+            }
+        }
+
+        for (id, keys) in targets {
+            let own = client.tracking.as_ref().filter(|_| id == client.id());
+            match own {
+                Some(tracking) if tracking.noloop => (),
+                // A RESP2 connection can only receive them as messages on the channel.
+                Some(tracking) if tracking.redirect.is_none() => {
+                    if client.resp >= 3 {
+                        client.push(tracking::invalidation_message(keys, client.resp));
+                    }
+                }
+                _ => self.pending_invalidations.push((id, keys)),
+            }
+        }
+    }
+
+    /// Sends the invalidation messages queued by the last command of client `writer`, which
+    /// must not be locked by the caller.
+    pub fn deliver_invalidations(&mut self, writer: u64) {
+        for (id, keys) in std::mem::take(&mut self.pending_invalidations) {
+            // Invalidations go to the client itself, or to the client it redirects them to.
+            let target = self.clients.get(id).and_then(|target| {
+                let target = target.lock();
+                let tracking = target.tracking.as_ref()?;
+                Some((tracking.noloop, tracking.redirect.unwrap_or(id)))
+            });
+            let Some((noloop, recipient)) = target else {
+                // The client is gone or stopped tracking.
+                self.tracking().set_bcast(id, None);
+                continue;
+            };
+            if noloop && id == writer {
+                continue;
+            }
+
+            if let Some(recipient) = self.clients.get(recipient) {
+                let recipient = recipient.lock();
+                // A RESP2 connection can only receive them as messages on the channel, so
+                // they are dropped unless it subscribed to it.
+                if recipient.resp >= 3 || recipient.channels.contains(tracking::INVALIDATE_CHANNEL)
+                {
+                    recipient.push(tracking::invalidation_message(keys, recipient.resp));
+                }
+            }
+        }
+    }
+
+    /// Runs a script for the client, giving it the commands it calls through `script_call`.
+    #[cfg(feature = "scripting")]
+    fn run_script(
+        &mut self,
+        client: &mut ClientState,
+        run: impl FnOnce(&mut ScriptCall) -> Result<Value, ScriptError>,
+    ) -> Result<Value, ScriptError> {
+        self.script_effects.clear();
+        run(&mut |argv, read_only| self.script_call(argv, read_only, client))
+    }
+
+    /// Runs a command called by a script on behalf of the client, replying errors like the
+    /// client would see them. Commands flagged `noscript` or `blocking` can't be called, and
+    /// read-only scripts can't call commands the command table flags `write`.
+    #[cfg(feature = "scripting")]
+    fn script_call(
+        &mut self,
+        argv: Vec<BulkString>,
+        read_only: bool,
+        client: &mut ClientState,
+    ) -> Value {
+        let value = Value::Array(
+            argv.into_iter()
+                .map(Value::BulkString)
+                .collect::<Vec<_>>()
+                .into(),
+        );
+        let result = self
+            .parse(&Request::from(value.clone()))
+            .map_err(HandleCommandError::from)
+            .and_then(|cmd| {
+                let spec = self.spec(cmd.name()).copied();
+                let has_flag = |flag| spec.is_some_and(|spec| spec.has_flag(flag));
+                if has_flag("noscript") || has_flag("blocking") {
+                    return Err(ScriptError::NotAllowed.into());
+                }
+                if read_only && has_flag("write") {
+                    return Err(ScriptError::WriteFromReadOnly.into());
+                }
+
+                let resp = self.handle(cmd, client)?;
+                if has_flag("write") {
+                    self.script_effects.push(value);
+                }
+                Ok(resp)
+            });
+        result.unwrap_or_else(|e| e.reply())
+    }
+
+    fn tracking(&self) -> MutexGuard<'_, TrackingTable> {
+        self.tracking.lock()
+    }
+
+    fn dispatch(
+        &mut self,
+        cmd: Command,
+        client: &mut ClientState,
+    ) -> Result<Reply, HandleCommandError> {
+        let value = match cmd {
+            Command::Ping(arg) => Ping::handler().handle(arg),
+            Command::Echo(arg) => Echo::handler().handle(arg),
+            Command::Info(arg) => Info::handler(
+                self.config.read().replica_of.is_some(),
+                self.replication()
+                    .as_ref()
+                    .map(|r| (r.repl_id().to_string(), r.offset())),
+                self.persistence.clone(),
+                self.memory.clone(),
+                self.lazyfree.clone(),
+                self.stats.clone(),
+                self.config.clone(),
+            )
+            .with_failover_state(
+                self.replication()
+                    .map_or(FailoverState::NoFailover, |r| r.failover_state()),
+            )
+            .handle(arg),
+            Command::Config(arg) => Config::handler(self.config.clone()).handle(arg)?,
+            Command::Acl(arg) => {
+                let aclfile = self.config.read().aclfile.clone();
+                Acl::handler(self.acl.clone(), aclfile).handle(arg, client)?
+            }
+            Command::Auth(arg) => Auth::handler(
+                self.acl.clone(),
+                self.auth_throttle.clone(),
+                self.clock.clone(),
+                self.stats.clone(),
+            )
+            .handle(arg, client)?,
+            Command::Hello(arg) => Hello::handler(Auth::handler(
+                self.acl.clone(),
+                self.auth_throttle.clone(),
+                self.clock.clone(),
+                self.stats.clone(),
+            ))
+            .with_replica(self.config.read().replica_of.is_some())
+            .with_cluster(self.cluster.is_some())
+            .handle(arg, client)?,
+            // Replicas acknowledge their offset without expecting a reply.
+            #[cfg(feature = "replication")]
+            Command::ReplConf(ReplConfArg {
+                config: ReplConfArgConfig::Ack(offset),
+            }) => {
+                if let Some(replication) = self.replication() {
+                    replication.ack(client.id(), offset);
+                }
+                return Ok(Reply::Nothing);
+            }
+            // Only our master asks for acknowledgements, over the link the replica handles.
+            #[cfg(feature = "replication")]
+            Command::ReplConf(ReplConfArg {
+                config: ReplConfArgConfig::GetAck,
+            }) => return Ok(Reply::Nothing),
+            #[cfg(feature = "replication")]
+            Command::ReplConf(arg) => ReplConf::handler().handle(arg, client),
+            #[cfg(feature = "replication")]
+            Command::Psync(arg) => {
+                return Ok(Psync::handler(
+                    self.replication(),
+                    self.store.clone(),
+                    self.clock.clone(),
+                )
+                .handle(arg, client)?)
+            }
+            #[cfg(feature = "replication")]
+            Command::Wait(arg) => return Ok(Wait::handler(self.replication()).handle(arg)?),
+            #[cfg(feature = "replication")]
+            Command::Failover(arg) => {
+                let handler = self.clone();
+                Failover::handler(self.replication(), self.config.clone())
+                    .with_cluster(self.cluster.is_some())
+                    .handle(arg, move |target| handler.follow(Some(target)))?
+            }
+            #[cfg(feature = "replication")]
+            Command::ReplicaOf(arg) => {
+                let failing_over = self
+                    .replication()
+                    .is_some_and(|r| r.failover_state() != FailoverState::NoFailover);
+                let master = self.config.read().replica_of.clone();
+                ReplicaOf::handler(master)
+                    .with_failing_over(failing_over)
+                    .with_cluster(self.cluster.is_some())
+                    .handle(arg, |master| self.follow(master))?
+            }
+            #[cfg(feature = "persistence")]
+            Command::Save(arg) => self.save_handler().handle(arg)?,
+            #[cfg(feature = "persistence")]
+            Command::Bgsave(arg) => Bgsave::handler(
+                self.store.clone(),
+                self.clock.clone(),
+                self.config.clone(),
+                self.persistence.clone(),
+                self.role.get(),
+            )
+            .handle(arg)?,
+            // Clone Arc to increment reference count.
+            Command::Set(arg) => {
+                let lazyfree = self.lazy(|config| config.lazyfree_lazy_server_del);
+                let mut handler = Set::handler(self.store.clone(), self.clock.clone(), lazyfree)
+                    .with_events(self.events.clone());
+                let resp = handler.handle(arg)?;
+                if handler.written() {
+                    self.persistence.incr_dirty(1);
+                }
+                resp
+            }
+            Command::Incr(arg)
+            | Command::Decr(arg)
+            | Command::IncrBy(arg)
+            | Command::DecrBy(arg) => {
+                let key = arg.key.clone();
+                let resp = Incr::handler(self.store.clone(), self.clock.clone()).handle(arg)?;
+                self.persistence.incr_dirty(1);
+                self.events
+                    .notify(key.as_bytes().unwrap_or_default(), KeyEvent::IncrBy);
+                resp
+            }
+            Command::Append(arg) => {
+                let key = arg.key.clone();
+                let resp = Append::handler(self.store.clone(), self.clock.clone()).handle(arg)?;
+                self.persistence.incr_dirty(1);
+                self.events
+                    .notify(key.as_bytes().unwrap_or_default(), KeyEvent::Append);
+                resp
+            }
+            Command::SetRange(arg) => {
+                let key = arg.key.clone();
+                let resp = SetRange::handler(self.store.clone(), self.clock.clone()).handle(arg)?;
+                self.persistence.incr_dirty(1);
+                self.events
+                    .notify(key.as_bytes().unwrap_or_default(), KeyEvent::SetRange);
+                resp
+            }
+            Command::Del(arg) => {
+                let lazyfree = self.lazy(|config| config.lazyfree_lazy_user_del);
+                let resp = Del::handler(self.store.clone(), self.clock.clone(), lazyfree)
+                    .with_events(self.events.clone())
+                    .handle(arg);
+                self.incr_dirty_by(&resp);
+                resp
+            }
+            Command::Unlink(arg) => {
+                let resp = Unlink::handler(
+                    self.store.clone(),
+                    self.clock.clone(),
+                    self.lazyfree.clone(),
+                )
+                .with_events(self.events.clone())
+                .handle(arg);
+                self.incr_dirty_by(&resp);
+                resp
+            }
+            Command::Expire(arg)
+            | Command::PExpire(arg)
+            | Command::ExpireAt(arg)
+            | Command::PExpireAt(arg) => {
+                let lazyfree = self.lazy(|config| config.lazyfree_lazy_expire);
+                let resp = Expire::handler(self.store.clone(), self.clock.clone(), lazyfree)
+                    .with_events(self.events.clone())
+                    .handle(arg)?;
+                self.incr_dirty_by(&resp);
+                resp
+            }
+            Command::Persist(arg) => {
+                let resp = Persist::handler(self.store.clone(), self.clock.clone())
+                    .with_events(self.events.clone())
+                    .handle(arg);
+                self.incr_dirty_by(&resp);
+                resp
+            }
+            Command::LPush(arg) | Command::RPush(arg) => {
+                let changes = arg.elements.len() as u64;
+                let key = arg.key.clone();
+                let size = self.config.read().list_listpack();
+                let resp = Push::handler(self.store.clone(), self.clock.clone())
+                    .with_events(self.events.clone())
+                    .with_listpack_size(size)
+                    .handle(arg)?;
+                self.persistence.incr_dirty(changes);
+                self.blocked.wake(key.as_bytes().unwrap_or_default());
+                resp
+            }
+            Command::LPop(arg) | Command::RPop(arg) => {
+                let resp = Pop::handler(self.store.clone(), self.clock.clone())
+                    .with_events(self.events.clone())
+                    .handle(arg)?;
+                self.incr_dirty_by_popped(&resp);
+                resp
+            }
+            Command::BLPop(arg) | Command::BRPop(arg) => {
+                let handler = self.clone();
+                return BPop::handler(self.clock.clone(), self.blocked.clone())
+                    .handle(arg, move |key, end| handler.pop_blocked(key, end));
+            }
+            Command::LLen(arg) => {
+                LLen::handler(self.store.clone(), self.clock.clone()).handle(arg)?
+            }
+            Command::LRange(arg) => {
+                return Ok(LRange::handler(self.store.clone(), self.clock.clone())
+                    .handle(arg)?
+                    .into())
+            }
+            Command::HSet(arg) => {
+                let changes = arg.pairs.len() as u64;
+                let limits = self.config.read().hash_listpack();
+                let resp = HSet::handler(self.store.clone(), self.clock.clone())
+                    .with_events(self.events.clone())
+                    .with_listpack_limits(limits)
+                    .handle(arg)?;
+                self.persistence.incr_dirty(changes);
+                resp
+            }
+            Command::HGet(arg) => {
+                HGet::handler(self.store.clone(), self.clock.clone()).handle(arg)?
+            }
+            Command::HDel(arg) => {
+                let resp = HDel::handler(self.store.clone(), self.clock.clone())
+                    .with_events(self.events.clone())
+                    .handle(arg)?;
+                self.incr_dirty_by(&resp);
+                resp
+            }
+            Command::HGetAll(arg) => {
+                return Ok(HGetAll::handler(self.store.clone(), self.clock.clone())
+                    .handle(arg)?
+                    .into())
+            }
+            Command::HGetDel(arg) => {
+                let (resp, changes) = HGetDel::handler(self.store.clone(), self.clock.clone())
+                    .with_events(self.events.clone())
+                    .handle_counting(arg)?;
+                self.persistence.incr_dirty(changes);
+                resp
+            }
+            Command::HGetEx(arg) => {
+                let (resp, changes) = HGetEx::handler(self.store.clone(), self.clock.clone())
+                    .with_events(self.events.clone())
+                    .handle_counting(arg)?;
+                self.persistence.incr_dirty(changes);
+                resp
+            }
+            Command::HExists(arg) => {
+                HExists::handler(self.store.clone(), self.clock.clone()).handle(arg)?
+            }
+            Command::HLen(arg) => {
+                HLen::handler(self.store.clone(), self.clock.clone()).handle(arg)?
+            }
+            Command::HIncrBy(arg) => {
+                let limits = self.config.read().hash_listpack();
+                let resp = HIncrBy::handler(self.store.clone(), self.clock.clone())
+                    .with_events(self.events.clone())
+                    .with_listpack_limits(limits)
+                    .handle(arg)?;
+                self.persistence.incr_dirty(1);
+                resp
+            }
+            Command::SAdd(arg) => {
+                let resp = SAdd::handler(self.store.clone(), self.clock.clone())
+                    .with_events(self.events.clone())
+                    .handle(arg)?;
+                self.incr_dirty_by(&resp);
+                resp
+            }
+            Command::SRem(arg) => {
+                let resp = SRem::handler(self.store.clone(), self.clock.clone())
+                    .with_events(self.events.clone())
+                    .handle(arg)?;
+                self.incr_dirty_by(&resp);
+                resp
+            }
+            Command::SMembers(arg) => {
+                return Ok(SMembers::handler(self.store.clone(), self.clock.clone())
+                    .handle(arg)?
+                    .into())
+            }
+            Command::SIsMember(arg) => {
+                SIsMember::handler(self.store.clone(), self.clock.clone()).handle(arg)?
+            }
+            Command::SCard(arg) => {
+                SCard::handler(self.store.clone(), self.clock.clone()).handle(arg)?
+            }
+            Command::SInter(arg) | Command::SUnion(arg) | Command::SDiff(arg) => {
+                SetOps::handler(self.store.clone(), self.clock.clone()).handle(arg)?
+            }
+            Command::ZAdd(arg) => {
+                let limits = self.config.read().zset_listpack();
+                let (resp, changes) = ZAdd::handler(self.store.clone(), self.clock.clone())
+                    .with_events(self.events.clone())
+                    .with_listpack_limits(limits)
+                    .handle_counting(arg)?;
+                self.persistence.incr_dirty(changes);
+                resp
+            }
+            Command::ZRange(arg) | Command::ZRangeByScore(arg) => {
+                ZRange::handler(self.store.clone(), self.clock.clone()).handle(arg)?
+            }
+            Command::ZScore(arg) => {
+                ZScore::handler(self.store.clone(), self.clock.clone()).handle(arg)?
+            }
+            Command::ZRank(arg) => {
+                ZRank::handler(self.store.clone(), self.clock.clone()).handle(arg)?
+            }
+            Command::ZRem(arg) => {
+                let resp = ZRem::handler(self.store.clone(), self.clock.clone())
+                    .with_events(self.events.clone())
+                    .handle(arg)?;
+                self.incr_dirty_by(&resp);
+                resp
+            }
+            Command::ZCard(arg) => {
+                ZCard::handler(self.store.clone(), self.clock.clone()).handle(arg)?
+            }
+            Command::XRange(arg) => {
+                XRange::handler(self.store.clone(), self.clock.clone()).handle(arg)?
+            }
+            Command::XRead(arg) => {
+                XRead::handler(self.store.clone(), self.clock.clone()).handle(arg, client.resp)?
+            }
+            Command::MSet(arg) => {
+                let lazyfree = self.lazy(|config| config.lazyfree_lazy_server_del);
+                let changes = arg.pairs.len() as u64;
+                let resp = MSet::handler(self.store.clone(), self.clock.clone(), lazyfree)
+                    .with_events(self.events.clone())
+                    .handle(arg);
+                self.persistence.incr_dirty(changes);
+                resp
+            }
+            Command::MSetNx(arg) => {
+                let lazyfree = self.lazy(|config| config.lazyfree_lazy_server_del);
+                let changes = arg.pairs.len() as u64;
+                let resp = MSet::handler(self.store.clone(), self.clock.clone(), lazyfree)
+                    .with_events(self.events.clone())
+                    .handle_nx(arg);
+                if resp == Value::Integer(1.into()) {
+                    self.persistence.incr_dirty(changes);
+                }
+                resp
+            }
+            Command::MGet(arg) => {
+                let lazyfree = self.lazy(|config| config.lazyfree_lazy_expire);
+                let lfu = self.config.read().lfu();
+                MGet::handler(self.store.clone(), self.clock.clone(), lfu, lazyfree)
+                    .with_events(self.events.clone())
+                    .handle(arg)
+            }
+            Command::Exists(arg) => {
+                let lazyfree = self.lazy(|config| config.lazyfree_lazy_expire);
+                let lfu = self.config.read().lfu();
+                Exists::handler(self.store.clone(), self.clock.clone(), lfu, lazyfree)
+                    .with_events(self.events.clone())
+                    .handle(arg)
+            }
+            Command::Strlen(arg) => {
+                Strlen::handler(self.store.clone(), self.clock.clone()).handle(arg)?
+            }
+            Command::GetRange(arg) => {
+                GetRange::handler(self.store.clone(), self.clock.clone()).handle(arg)?
+            }
+            Command::Get(arg) => {
+                let lazyfree = self.lazy(|config| config.lazyfree_lazy_expire);
+                let lfu = self.config.read().lfu();
+                let resp = Get::handler(self.store.clone(), self.clock.clone(), lfu, lazyfree)
+                    .with_events(self.events.clone())
+                    .handle(arg)?;
+                self.stats
+                    .record_keyspace(resp != Value::BulkString(BulkString::null()));
+                resp
+            }
+            Command::Memory(arg) => Memory::handler(
+                self.store.clone(),
+                self.clock.clone(),
+                self.memory.clone(),
+                self.config.clone(),
+            )
+            .handle(arg)?,
+            Command::Command(arg) => Commands::handler().handle(arg)?,
+            Command::Client(arg) => Client::handler(self.clients.clone()).handle(arg, client)?,
+            Command::Asking(_) => Asking::handler(self.cluster.is_some()).handle(client)?,
+            Command::Cluster(arg) => {
+                let offset = self.replication().map(|r| r.offset()).unwrap_or_default();
+                Cluster::handler(self.cluster.clone(), self.store.clone(), offset)
+                    .with_require_full_coverage(self.config.read().cluster_require_full_coverage)
+                    .handle(arg, client.laddr())?
+            }
+            Command::Debug(arg) => {
+                return Ok(Debug::handler(
+                    self.store.clone(),
+                    self.clock.clone(),
+                    self.config.clone(),
+                    self.clients.clone(),
+                )
+                .handle(arg)?)
+            }
+            Command::Object(arg) => {
+                Object::handler(self.store.clone(), self.clock.clone(), self.config.clone())
+                    .handle(arg)?
+            }
+            #[cfg(feature = "scripting")]
+            Command::Eval(arg) => {
+                self.run_script(client, |call| Eval::handler().handle(arg, call))?
+            }
+            #[cfg(feature = "scripting")]
+            Command::EvalRo(arg) => self.run_script(client, |call| {
+                Eval::handler().with_read_only(true).handle(arg, call)
+            })?,
+            #[cfg(feature = "scripting")]
+            Command::FCall(arg) => {
+                let fcall = FCall::handler(self.functions.clone());
+                self.run_script(client, |call| fcall.handle(arg, call))?
+            }
+            #[cfg(feature = "scripting")]
+            Command::FCallRo(arg) => {
+                let fcall = FCall::handler(self.functions.clone()).with_read_only(true);
+                self.run_script(client, |call| fcall.handle(arg, call))?
+            }
+            #[cfg(feature = "scripting")]
+            Command::Function(arg) => Function::handler(self.functions.clone()).handle(arg)?,
+            Command::Plugin(arg) => {
+                let plugin = self
+                    .plugins
+                    .get(arg.name)
+                    .expect("Parsed plugins are registered");
+                let resp = plugin.call(&self.store, &arg.args)?;
+                if plugin.spec().has_flag("write") {
+                    self.persistence.incr_dirty(1);
+                }
+                resp
+            }
+            Command::Pttl(arg) => Pttl::handler(self.store.clone(), self.clock.clone()).handle(arg),
+            Command::Type(arg) => Type::handler(self.store.clone(), self.clock.clone()).handle(arg),
+            // Streamed, since the reply can hold the whole keyspace.
+            Command::Keys(arg) => {
+                return Ok(Keys::handler(self.store.clone(), self.clock.clone())
+                    .handle(arg)
+                    .into())
+            }
+        };
+        Ok(value.into())
     }
 }
 
@@ -70,34 +1703,33 @@ impl CommandHandler {
 mod test {
     use std::{thread, time::Duration};
 
-    use super::super::cmd::{GetArg, SetArg};
-    use super::super::resp::SimpleString;
+    use super::super::acl::DEFAULT_USER;
+    use super::super::cluster::{key_hash_slot, ClusterNode};
+    use super::super::cmd::{AskingArg, AuthArg, GetArg, HelloArg, InfoArg, InfoSection, SetArg};
+    use super::super::lazyfree::LAZYFREE_THRESHOLD;
+    use super::super::plugin::FnPlugin;
+    use super::super::resp::{Array, Integer, Push, SimpleString};
+    use super::super::test_util::{
+        client_state, command, command_handler, command_handler_with_clock, request,
+    };
+    use super::super::tracking::TrackingOptions;
     use super::*;
 
-    fn new_hash_map() -> Arc<RwLock<HashMap<BulkString, StoredData>>> {
-        Arc::new(RwLock::new(HashMap::new()))
-    }
-
-    fn new_cmd_handler() -> CommandHandler {
-        CommandHandler::new(
-            new_hash_map(),
-            CommandHandlerConfig {
-                is_replica: false,
-                master_repl_id_and_offset: None,
-            },
-        )
-    }
-
     fn simple_set(handler: &mut CommandHandler, k: &str, v: &str, expiry: Option<Duration>) {
         let key = BulkString::from(k);
         let value = BulkString::from(v);
 
         let resp = handler
-            .handle(Command::Set(SetArg {
-                key,
-                value,
-                expiry: expiry.clone(),
-            }))
+            .handle(
+                Command::Set(SetArg {
+                    key,
+                    value,
+                    expiry: expiry.map(Into::into),
+                    condition: None,
+                    get: false,
+                }),
+                &mut client_state(),
+            )
             .expect("Handle set unexpected error");
         assert_eq!(resp, Value::SimpleString(SimpleString::from("OK")));
     }
@@ -106,13 +1738,218 @@ mod test {
         let key = BulkString::from(k);
 
         handler
-            .handle(Command::Get(GetArg { key }))
+            .handle(Command::Get(GetArg { key }), &mut client_state())
             .expect("Handle get unexpected error")
     }
 
+    #[test]
+    fn audit_records_write_commands() {
+        let path = std::env::temp_dir().join(format!("audit-handler-{}.log", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let audit = AuditLog::open(&path, 0, 0).expect("Open unexpected error");
+        let mut handler = command_handler().with_audit(Arc::new(audit));
+
+        simple_set(&mut handler, "key", "secret", None);
+        simple_get(&mut handler, "key");
+        let set = Command::Set(SetArg {
+            key: "other".into(),
+            value: "value".into(),
+            expiry: None,
+            condition: None,
+            get: false,
+        });
+        handler
+            .handle(set, &mut ClientState::new(2, None))
+            .expect_err("Handle set no error");
+
+        let audited = std::fs::read_to_string(&path).expect("Read unexpected error");
+        std::fs::remove_file(&path).expect("Remove unexpected error");
+        let lines: Vec<&str> = audited.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].ends_with("id=1 addr= user=default cmd=set keys=\"key\" result=ok"));
+        assert!(!lines[0].contains("secret"));
+        assert!(lines[1].contains("id=2 addr= user= cmd=set keys=\"other\" result=\"NOAUTH"));
+    }
+
+    #[cfg(feature = "chaos")]
+    #[tokio::test]
+    async fn quick_drop_replica_closes_replica_links() {
+        use super::super::cmd::{DebugArg, DebugSubcommand};
+
+        let mut handler = command_handler();
+        let replica = handler.clients().register(None, None);
+        replica.state().lock().flags.replica = true;
+        let other = handler.clients().register(None, None);
+
+        let cmd = Command::Debug(DebugArg {
+            subcommand: DebugSubcommand::QuickDropReplica,
+        });
+        handler
+            .handle(cmd, &mut client_state())
+            .expect("Handle debug unexpected error");
+        tokio::time::timeout(Duration::from_secs(1), replica.closed())
+            .await
+            .expect("Replica link not closed");
+        let closed = tokio::time::timeout(Duration::from_millis(50), other.closed()).await;
+        assert!(closed.is_err());
+    }
+
+    #[test]
+    fn lazy_server_del_frees_overwritten_values() {
+        let mut handler = command_handler();
+        handler
+            .config
+            .set(&[("lazyfree-lazy-server-del".to_string(), "yes".to_string())])
+            .expect("Set config unexpected error");
+
+        let large = "x".repeat(LAZYFREE_THRESHOLD);
+        simple_set(&mut handler, "key", &large, None);
+        simple_set(&mut handler, "key", "small", None);
+        simple_set(&mut handler, "key", "other", None);
+
+        let start = Instant::now();
+        while handler.lazyfree.freed() < 1 {
+            assert!(start.elapsed() < Duration::from_secs(5));
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(handler.lazyfree.freed(), 1);
+        assert_eq!(
+            simple_get(&mut handler, "key"),
+            Value::BulkString("other".into())
+        );
+    }
+
+    #[test]
+    fn active_expire_cycle_removes_expired_keys() {
+        let (mut handler, clock) = command_handler_with_clock();
+        simple_set(&mut handler, "short", "v", Some(Duration::from_millis(10)));
+        simple_set(&mut handler, "long", "v", Some(Duration::from_secs(60)));
+        simple_set(&mut handler, "forever", "v", None);
+        clock.advance(Duration::from_millis(20));
+
+        handler.config.set_active_expire(false);
+        assert_eq!(handler.active_expire_cycle(), 0);
+        handler.config.set_active_expire(true);
+        let used = handler.memory.used();
+        assert_eq!(handler.active_expire_cycle(), 1);
+
+        assert_eq!(handler.store.len(), 2);
+        assert!(handler.memory.used() < used);
+        assert_eq!(handler.stats.expired_keys(), 1);
+        assert!(handler.store.next_deadline().is_some());
+    }
+
+    #[derive(Debug, Default)]
+    struct RecordingListener {
+        events: Mutex<Vec<(String, KeyEvent)>>,
+    }
+
+    impl KeyEventListener for RecordingListener {
+        fn on_event(&self, db: u32, key: &[u8], event: KeyEvent) {
+            assert_eq!(db, 0);
+            let key = String::from_utf8_lossy(key).into_owned();
+            self.events.lock().push((key, event));
+        }
+    }
+
+    #[test]
+    fn key_events_reach_listeners() {
+        let listener = Arc::new(RecordingListener::default());
+        let (handler, clock) = command_handler_with_clock();
+        let mut handler = handler.with_key_event_listener(listener.clone());
+        simple_set(&mut handler, "a", "v", Some(Duration::from_millis(10)));
+        simple_set(&mut handler, "b", "v", Some(Duration::from_millis(10)));
+        simple_set(&mut handler, "c", "v", None);
+        clock.advance(Duration::from_millis(20));
+
+        assert_eq!(
+            simple_get(&mut handler, "a"),
+            Value::BulkString(BulkString::null())
+        );
+        assert_eq!(handler.active_expire_cycle(), 1);
+        handler
+            .config
+            .set(&[
+                ("maxmemory".to_string(), "1".to_string()),
+                ("maxmemory-policy".to_string(), "allkeys-random".to_string()),
+            ])
+            .expect("Set config unexpected error");
+        simple_set(&mut handler, "d", "v", None);
+
+        let events = listener.events.lock().clone();
+        let expected = [
+            ("a", KeyEvent::Set),
+            ("b", KeyEvent::Set),
+            ("c", KeyEvent::Set),
+            ("a", KeyEvent::Expired),
+            ("b", KeyEvent::Expired),
+            ("c", KeyEvent::Evicted),
+            ("d", KeyEvent::Set),
+        ]
+        .map(|(key, event)| (key.to_string(), event));
+        assert_eq!(events, expected);
+        assert!(handler
+            .stats()
+            .stats_info()
+            .contains(&"evicted_keys:1".to_string()));
+    }
+
+    #[test]
+    fn string_encodings() {
+        let encoding = |s: &str| StoredData::new(s.into(), None).encoding();
+        assert_eq!(encoding("12345"), "int");
+        assert_eq!(encoding("012"), "embstr");
+        assert_eq!(encoding(&"a".repeat(45)), "raw");
+    }
+
+    #[test]
+    fn collection_encodings() {
+        let mut handler = command_handler();
+        handler
+            .config()
+            .set(&[
+                ("list-max-listpack-size".to_string(), "2".to_string()),
+                ("hash-max-listpack-entries".to_string(), "2".to_string()),
+                ("zset-max-listpack-value".to_string(), "3".to_string()),
+            ])
+            .expect("Set config unexpected error");
+        let mut run = |args: &[&str]| {
+            let cmd = handler
+                .parse(&request(args.iter().copied()))
+                .expect("Parse unexpected error");
+            handler
+                .handle(cmd, &mut client_state())
+                .expect("Handle unexpected error")
+        };
+        let encoding = |name: &str| Value::BulkString(name.into());
+
+        // Lists are split into listpack nodes of the configured size.
+        run(&["RPUSH", "list", "a", "b"]);
+        assert_eq!(run(&["OBJECT", "ENCODING", "list"]), encoding("listpack"));
+        run(&["LPUSH", "list", "c"]);
+        assert_eq!(run(&["OBJECT", "ENCODING", "list"]), encoding("quicklist"));
+        run(&["LPOP", "list"]);
+        assert_eq!(run(&["OBJECT", "ENCODING", "list"]), encoding("listpack"));
+
+        // Hashes and sorted sets are converted once they pass their limits.
+        run(&["HSET", "hash", "f", "v", "g", "v"]);
+        assert_eq!(run(&["OBJECT", "ENCODING", "hash"]), encoding("listpack"));
+        run(&["HINCRBY", "hash", "h", "1"]);
+        assert_eq!(run(&["OBJECT", "ENCODING", "hash"]), encoding("hashtable"));
+        run(&["ZADD", "zset", "1", "abc"]);
+        assert_eq!(run(&["OBJECT", "ENCODING", "zset"]), encoding("listpack"));
+        run(&["ZADD", "zset", "2", "abcd"]);
+        assert_eq!(run(&["OBJECT", "ENCODING", "zset"]), encoding("skiplist"));
+
+        // Sets are never packed.
+        run(&["SADD", "set", "1"]);
+        assert_eq!(run(&["OBJECT", "ENCODING", "set"]), encoding("hashtable"));
+        assert_eq!(StoredValue::Stream(Stream::new()).encoding(), "stream");
+    }
+
     #[test]
     fn set_and_get() {
-        let mut handler = new_cmd_handler();
+        let mut handler = command_handler();
 
         let key = "My Key";
         let value = "My Value";
@@ -130,7 +1967,7 @@ mod test {
 
     #[test]
     fn set_expiry_and_get() {
-        let mut handler = new_cmd_handler();
+        let (mut handler, clock) = command_handler_with_clock();
 
         let key = "My Key";
         let value = "My Value";
@@ -140,7 +1977,7 @@ mod test {
         simple_set(&mut handler, key, value, Some(expiry));
 
         // Entry still exists
-        thread::sleep(Duration::from_millis(100));
+        clock.advance(Duration::from_millis(100));
         let resp = simple_get(&mut handler, key);
         assert_eq!(
             resp.bulk_string().unwrap().as_str(),
@@ -148,8 +1985,628 @@ mod test {
         );
 
         // Entry expired
-        thread::sleep(Duration::from_millis(200));
+        clock.advance(Duration::from_millis(200));
         let resp = simple_get(&mut handler, key);
         assert_eq!(resp.bulk_string().unwrap().as_str(), None);
     }
+
+    #[test]
+    fn set_increments_dirty() {
+        let mut handler = command_handler();
+
+        simple_set(&mut handler, "First", "1", None);
+        simple_set(&mut handler, "Second", "2", None);
+        simple_get(&mut handler, "First");
+
+        assert_eq!(handler.persistence.dirty(), 2);
+    }
+
+    #[test]
+    fn zadd_and_pop_count_only_changes_as_dirty() {
+        let mut handler = command_handler();
+        let mut run = |args: &[&str]| {
+            let cmd = handler
+                .parse(&request(args.iter().copied()))
+                .expect("Parse unexpected error");
+            handler
+                .handle(cmd, &mut client_state())
+                .expect("Handle unexpected error");
+            handler.persistence.dirty()
+        };
+
+        assert_eq!(run(&["ZADD", "zset", "1", "a", "2", "b"]), 2);
+        assert_eq!(run(&["ZADD", "zset", "NX", "5", "a", "3", "c"]), 3);
+        assert_eq!(run(&["ZADD", "zset", "XX", "1", "a", "3", "d"]), 3);
+        assert_eq!(run(&["LPOP", "list"]), 3);
+        assert_eq!(run(&["RPUSH", "list", "x", "y"]), 5);
+        assert_eq!(run(&["LPOP", "list", "5"]), 7);
+        assert_eq!(run(&["RPOP", "list", "5"]), 7);
+    }
+
+    #[test]
+    fn plugin_commands_run_like_built_ins() {
+        let mut handler = command_handler();
+        let strlen = FnPlugin::new(
+            CommandSpec {
+                name: "mystrlen",
+                arity: 2,
+                flags: &["readonly", "fast"],
+                first_key: 1,
+                last_key: 1,
+                key_step: 1,
+                key_specs: &[],
+                categories: &["keyspace", "read", "string", "fast"],
+                summary: "Returns the length of a string.",
+                group: "string",
+            },
+            |store, args| {
+                let key = args[0].as_bytes().unwrap_or_default();
+                let len = store
+                    .read(key)
+                    .get(key)
+                    .map(|data| data.value.string_bytes().len())
+                    .unwrap_or(0);
+                Ok(Value::Integer(Integer::new(len as i64)))
+            },
+        );
+        handler
+            .register_command(Arc::new(strlen))
+            .expect("Register command unexpected error");
+        let taken = FnPlugin::new(*table::lookup("get").unwrap(), |_, _| {
+            Err(PluginError::Command("unreachable".to_string()))
+        });
+        assert_eq!(
+            handler.register_command(Arc::new(taken)),
+            Err(PluginError::Exists("get"))
+        );
+
+        simple_set(&mut handler, "key", "value", None);
+        let cmd = handler
+            .parse(&request(["MYSTRLEN", "key"]))
+            .expect("Parse plugin unexpected error");
+        assert_eq!(cmd.keys(), vec![b"key"]);
+        let resp = handler
+            .handle(cmd, &mut client_state())
+            .expect("Handle plugin unexpected error");
+        assert_eq!(resp, Value::Integer(Integer::new(5)));
+        assert!(matches!(
+            handler.parse(&request(["mystrlen"])),
+            Err(ParseCommandError::WrongArity("mystrlen"))
+        ));
+
+        // The plugin is in the read category of the ACL.
+        handler
+            .acl()
+            .write()
+            .set_user(
+                "writer",
+                &["on", "nopass", "~*", "+@write"].map(String::from),
+            )
+            .expect("Set user unexpected error");
+        let cmd = handler.parse(&request(["mystrlen", "key"])).unwrap();
+        let err = handler
+            .handle(cmd, &mut ClientState::new(2, Some("writer".to_string())))
+            .expect_err("Handle plugin no error");
+        assert_eq!(err.code(), "NOPERM");
+    }
+
+    #[test]
+    fn acl_enforced_on_dispatch() {
+        let mut handler = command_handler();
+        handler
+            .acl()
+            .write()
+            .set_user(
+                "reader",
+                &["on", ">secret", "~public:*", "+@read"].map(String::from),
+            )
+            .expect("Set user unexpected error");
+
+        // Unauthenticated connections can only run AUTH
+        let mut client = ClientState::new(1, None);
+        let err = handler
+            .handle(
+                Command::Get(GetArg {
+                    key: "public:1".into(),
+                }),
+                &mut client,
+            )
+            .expect_err("Handle get no error");
+        assert_eq!(err.code(), "NOAUTH");
+
+        handler
+            .handle(
+                Command::Auth(AuthArg {
+                    username: Some("reader".into()),
+                    password: "secret".into(),
+                }),
+                &mut client,
+            )
+            .expect("Handle auth unexpected error");
+
+        handler
+            .handle(
+                Command::Get(GetArg {
+                    key: "public:1".into(),
+                }),
+                &mut client,
+            )
+            .expect("Handle get unexpected error");
+
+        let err = handler
+            .handle(
+                Command::Get(GetArg {
+                    key: "secret:1".into(),
+                }),
+                &mut client,
+            )
+            .expect_err("Handle get no error");
+        assert_eq!(err.code(), "NOPERM");
+
+        let err = handler
+            .handle(
+                Command::Set(SetArg {
+                    key: "public:1".into(),
+                    value: "1".into(),
+                    expiry: None,
+                    condition: None,
+                    get: false,
+                }),
+                &mut client,
+            )
+            .expect_err("Handle set no error");
+        assert_eq!(err.code(), "NOPERM");
+    }
+
+    #[test]
+    fn noeviction_rejects_writes_over_maxmemory() {
+        let mut handler = command_handler();
+        simple_set(&mut handler, "First", "1", None);
+        handler
+            .config
+            .set(&[("maxmemory".to_string(), "1".to_string())])
+            .expect("Set config unexpected error");
+
+        let err = handler
+            .handle(
+                Command::Set(SetArg {
+                    key: "Second".into(),
+                    value: "2".into(),
+                    expiry: None,
+                    condition: None,
+                    get: false,
+                }),
+                &mut client_state(),
+            )
+            .expect_err("Handle set no error");
+        assert_eq!(err.code(), "OOM");
+
+        // Reads are still allowed
+        let resp = simple_get(&mut handler, "First");
+        assert_eq!(resp.bulk_string().unwrap().as_str(), Some("1".to_string()));
+
+        let stats = handler.stats().commandstats_info();
+        assert!(stats[1].starts_with("cmdstat_set:calls=1,"));
+        assert!(stats[1].ends_with(",rejected_calls=1,failed_calls=0"));
+    }
+
+    #[test]
+    fn cluster_redirects_keys_of_other_nodes() {
+        let myself = ClusterNode::new("127.0.0.1".parse().unwrap(), 7000, 17000);
+        let other = ClusterNode::new("127.0.0.1".parse().unwrap(), 7001, 17001);
+        let slot = key_hash_slot(b"key");
+        let mut cluster = ClusterState::new(myself);
+        cluster.add_slots(&(0..16384).collect::<Vec<_>>()).unwrap();
+        cluster.assign(slot..=slot, other.clone());
+        cluster.set_importing(slot, &other.id).unwrap();
+        let mut handler = command_handler().with_cluster(Arc::new(RwLock::new(cluster)));
+        let mut client = client_state();
+        let get = || Command::Get(GetArg { key: "key".into() });
+
+        let err = handler
+            .handle(get(), &mut client)
+            .expect_err("Handle get no error");
+        assert_eq!(err.code(), "MOVED");
+        assert_eq!(err.to_string(), format!("{slot} 127.0.0.1:7001"));
+        assert_eq!(
+            simple_get(&mut handler, "other"),
+            Value::BulkString(BulkString::null())
+        );
+
+        // ASKING lets only the next command into the importing slot.
+        handler
+            .handle(Command::Asking(AskingArg), &mut client)
+            .expect("Handle asking unexpected error");
+        let resp = handler
+            .handle(get(), &mut client)
+            .expect("Handle get unexpected error");
+        assert_eq!(resp, Value::BulkString(BulkString::null()));
+        let err = handler
+            .handle(get(), &mut client)
+            .expect_err("Handle get no error");
+        assert_eq!(err.code(), "MOVED");
+    }
+
+    #[test]
+    fn info_without_section_replies_default_sections() {
+        let mut handler = command_handler();
+        let info = Command::Info(InfoArg {
+            section: InfoSection::Default,
+        });
+        let resp = handler
+            .handle(info, &mut client_state())
+            .expect("Handle info unexpected error");
+        let info = resp.bulk_string().and_then(BulkString::as_str).unwrap();
+
+        assert!(info.starts_with("# Memory\nused_memory:"));
+        for header in ["# Persistence", "# Stats", "# Replication", "# Cluster"] {
+            assert!(
+                info.contains(&format!("\n\n{header}\n")),
+                "{header} missing"
+            );
+        }
+        assert!(info.contains("role:master"));
+        assert!(!info.contains("# Commandstats"));
+    }
+
+    #[test]
+    fn info_all_replies_every_section() {
+        let mut handler = command_handler();
+        simple_get(&mut handler, "key");
+        for section in ["all", "EVERYTHING"] {
+            let info = Command::try_from(command(["INFO", section])).expect("Parse error");
+            let resp = handler
+                .handle(info, &mut client_state())
+                .expect("Handle info unexpected error");
+            let info = resp.bulk_string().and_then(BulkString::as_str).unwrap();
+
+            assert!(info.starts_with("# Memory\n"));
+            for header in ["# Replication", "# Commandstats", "# Latencystats"] {
+                assert!(
+                    info.contains(&format!("\n\n{header}\n")),
+                    "{header} missing"
+                );
+            }
+            assert!(info.contains("\ncmdstat_get:calls=1,"));
+        }
+    }
+
+    #[test]
+    fn error_replies_carry_their_code() {
+        let error = |s: &str| Value::SimpleError(s.into());
+        assert_eq!(
+            HandleCommandError::WrongType.reply(),
+            error("WRONGTYPE Operation against a key holding the wrong kind of value")
+        );
+        assert_eq!(
+            HandleCommandError::NotInteger.reply(),
+            error("ERR value is not an integer or out of range")
+        );
+        assert_eq!(
+            HandleCommandError::from(ParseCommandError::WrongArity("get")).reply(),
+            error("ERR wrong number of arguments for 'get' command")
+        );
+        assert_eq!(
+            HandleCommandError::from(EvictionError::OutOfMemory).reply(),
+            error("OOM command not allowed when used memory > 'maxmemory'.")
+        );
+    }
+
+    #[test]
+    fn replicas_refuse_writes_except_from_master() {
+        let config = ConfigValues {
+            replica_of: Some("127.0.0.1:6379".parse().unwrap()),
+            ..Default::default()
+        };
+        let mut handler = CommandHandler::new(
+            Arc::new(Store::default()),
+            Arc::new(ServerConfig::new(config)),
+            Arc::new(PersistenceState::new()),
+            Arc::new(MemoryTracker::default()),
+            Arc::new(RwLock::new(AccessControl::new())),
+            Arc::new(ClientRegistry::new()),
+            None,
+        );
+        let set = || {
+            Command::Set(SetArg {
+                key: "key".into(),
+                value: "value".into(),
+                expiry: None,
+                condition: None,
+                get: false,
+            })
+        };
+
+        let err = handler
+            .handle(set(), &mut client_state())
+            .expect_err("Handle set no error");
+        assert_eq!(
+            err.reply(),
+            Value::SimpleError("READONLY You can't write against a read only replica.".into())
+        );
+
+        let mut master = client_state();
+        master.flags.master = true;
+        handler
+            .handle(set(), &mut master)
+            .expect("Handle set unexpected error");
+        assert_eq!(
+            simple_get(&mut handler, "key"),
+            Value::BulkString("value".into())
+        );
+    }
+
+    #[test]
+    fn memory_tracks_writes_and_expiry() {
+        let (mut handler, clock) = command_handler_with_clock();
+
+        simple_set(&mut handler, "First", "1", None);
+        simple_set(&mut handler, "Second", "2", Some(Duration::from_millis(50)));
+        let used = handler.memory.used();
+        assert_eq!(used, memory::used_memory(&handler.store));
+
+        // Overwriting with a longer value grows usage, expired keys release it on access
+        simple_set(&mut handler, "First", "1111", None);
+        assert_eq!(handler.memory.used(), used + 3);
+        clock.advance(Duration::from_millis(100));
+        simple_get(&mut handler, "Second");
+        assert_eq!(handler.memory.used(), memory::used_memory(&handler.store));
+        assert!(handler.memory.peak() > handler.memory.used());
+    }
+
+    #[tokio::test]
+    async fn tracking_client_is_notified_of_writes() {
+        let mut handler = command_handler();
+        let mut reader = handler
+            .clients()
+            .register(Some(DEFAULT_USER.to_string()), None);
+        let writer = handler
+            .clients()
+            .register(Some(DEFAULT_USER.to_string()), None);
+        let hello = Command::Hello(HelloArg {
+            protover: Some(3),
+            ..Default::default()
+        });
+        handler
+            .handle(hello, &mut reader.state().lock())
+            .expect("Handle hello unexpected error");
+        reader.state().lock().tracking = Some(TrackingOptions::default());
+
+        let get = Command::Get(GetArg { key: "key".into() });
+        handler
+            .handle(get, &mut reader.state().lock())
+            .expect("Handle get unexpected error");
+        let set = Command::Set(SetArg {
+            key: "key".into(),
+            value: "value".into(),
+            expiry: None,
+            condition: None,
+            get: false,
+        });
+        handler
+            .handle(set, &mut writer.state().lock())
+            .expect("Handle set unexpected error");
+        handler.deliver_invalidations(writer.id());
+
+        assert_eq!(
+            reader
+                .take_receiver()
+                .unwrap()
+                .recv()
+                .await
+                .map(Reply::into_value),
+            Some(Value::Push(Push::new(vec![
+                Value::BulkString("invalidate".into()),
+                Value::Array(Array::new(vec![Value::BulkString("key".into())])),
+            ])))
+        );
+    }
+
+    #[tokio::test]
+    async fn invalidations_are_redirected_to_subscribed_clients() {
+        let mut handler = command_handler();
+        let reader = handler
+            .clients()
+            .register(Some(DEFAULT_USER.to_string()), None);
+        let mut target = handler
+            .clients()
+            .register(Some(DEFAULT_USER.to_string()), None);
+        let writer = handler
+            .clients()
+            .register(Some(DEFAULT_USER.to_string()), None);
+        reader.state().lock().tracking = Some(TrackingOptions {
+            redirect: Some(target.id()),
+            ..Default::default()
+        });
+        let mut receiver = target.take_receiver().unwrap();
+        let write = |handler: &mut CommandHandler| {
+            let get = Command::Get(GetArg { key: "key".into() });
+            handler
+                .handle(get, &mut reader.state().lock())
+                .expect("Handle get unexpected error");
+            let set = Command::Set(SetArg {
+                key: "key".into(),
+                value: "value".into(),
+                expiry: None,
+                condition: None,
+                get: false,
+            });
+            handler
+                .handle(set, &mut writer.state().lock())
+                .expect("Handle set unexpected error");
+            handler.deliver_invalidations(writer.id());
+        };
+
+        // A RESP2 target that didn't subscribe to the channel can't receive them.
+        write(&mut handler);
+        assert!(receiver.try_recv().is_err());
+
+        target
+            .state()
+            .lock()
+            .channels
+            .insert(tracking::INVALIDATE_CHANNEL.to_string());
+        write(&mut handler);
+        assert_eq!(
+            receiver.recv().await.map(Reply::into_value),
+            Some(Value::Array(Array::new(vec![
+                Value::BulkString("message".into()),
+                Value::BulkString(tracking::INVALIDATE_CHANNEL.into()),
+                Value::Array(Array::new(vec![Value::BulkString("key".into())])),
+            ])))
+        );
+
+        target.state().lock().channels.clear();
+        target.state().lock().resp = 3;
+        write(&mut handler);
+        assert_eq!(
+            receiver.recv().await.map(Reply::into_value),
+            Some(Value::Push(Push::new(vec![
+                Value::BulkString("invalidate".into()),
+                Value::Array(Array::new(vec![Value::BulkString("key".into())])),
+            ])))
+        );
+    }
+
+    #[cfg(feature = "scripting")]
+    #[test]
+    fn read_only_scripts_refuse_writes() {
+        use super::super::test_util::{bulk, error, simple};
+
+        let mut handler = command_handler();
+        let mut client = client_state();
+        let mut run = |handler: &mut CommandHandler, args: &[&str]| {
+            let cmd = handler
+                .parse(&request(args.iter().copied()))
+                .expect("Parse script unexpected error");
+            handler.handle(cmd, &mut client).map_err(|e| e.reply())
+        };
+        let set = "return redis.call('set', KEYS[1], ARGV[1])";
+        let get = "return redis.call('get', KEYS[1])";
+        let read_only = error("ERR Write commands are not allowed from read-only scripts.");
+
+        assert_eq!(
+            run(&mut handler, &["EVAL", set, "1", "key", "value"]),
+            Ok(simple("OK"))
+        );
+        assert_eq!(
+            run(&mut handler, &["EVAL_RO", set, "1", "key", "other"]),
+            Err(read_only.clone())
+        );
+        let pcall = "return redis.pcall('del', KEYS[1])";
+        assert_eq!(
+            run(&mut handler, &["EVAL_RO", pcall, "1", "key"]),
+            Ok(read_only.clone())
+        );
+        assert_eq!(
+            run(&mut handler, &["EVAL_RO", get, "1", "key"]),
+            Ok(bulk("value"))
+        );
+
+        let library = "#!lua name=lib\n\
+            redis.register_function('set', function(keys, args) \
+                return redis.call('set', keys[1], args[1]) end)\n\
+            redis.register_function{function_name='get', \
+                callback=function(keys) return redis.call('get', keys[1]) end, \
+                flags={'no-writes'}}";
+        assert_eq!(
+            run(&mut handler, &["FUNCTION", "LOAD", library]),
+            Ok(bulk("lib"))
+        );
+        assert_eq!(
+            run(&mut handler, &["FCALL_RO", "set", "1", "key", "other"]),
+            Err(error(
+                "ERR Can not execute a script with write flag using *_ro command."
+            ))
+        );
+        assert_eq!(
+            run(&mut handler, &["FCALL_RO", "get", "1", "key"]),
+            Ok(bulk("value"))
+        );
+        assert_eq!(
+            run(&mut handler, &["FCALL", "set", "1", "key", "other"]),
+            Ok(simple("OK"))
+        );
+        assert_eq!(simple_get(&mut handler, "key"), bulk("other"));
+    }
+
+    #[cfg(feature = "scripting")]
+    #[test]
+    fn replicas_run_read_only_scripts() {
+        use super::super::test_util::error;
+
+        let config = ConfigValues {
+            replica_of: Some("127.0.0.1:6379".parse().unwrap()),
+            ..Default::default()
+        };
+        let mut handler = CommandHandler::new(
+            Arc::new(Store::default()),
+            Arc::new(ServerConfig::new(config)),
+            Arc::new(PersistenceState::new()),
+            Arc::new(MemoryTracker::default()),
+            Arc::new(RwLock::new(AccessControl::new())),
+            Arc::new(ClientRegistry::new()),
+            None,
+        );
+        let mut run = |args: &[&str]| {
+            let cmd = handler
+                .parse(&request(args.iter().copied()))
+                .expect("Parse script unexpected error");
+            handler
+                .handle(cmd, &mut client_state())
+                .map_err(|e| e.reply())
+        };
+
+        assert_eq!(
+            run(&[
+                "EVAL_RO",
+                "return redis.call('exists', KEYS[1])",
+                "1",
+                "key"
+            ]),
+            Ok(Value::Integer(Integer::new(0)))
+        );
+        assert_eq!(
+            run(&["EVAL", "return redis.call('set', KEYS[1], 'v')", "1", "key"]),
+            Err(error(
+                "READONLY You can't write against a read only replica."
+            ))
+        );
+    }
+
+    #[cfg(feature = "scripting")]
+    #[tokio::test]
+    async fn scripts_propagate_their_writes() {
+        let replication = Arc::new(ReplicationState::new());
+        let clients = Arc::new(ClientRegistry::new());
+        let mut handler = CommandHandler::new(
+            Arc::new(Store::default()),
+            Arc::new(ServerConfig::new(ConfigValues::default())),
+            Arc::new(PersistenceState::new()),
+            Arc::new(MemoryTracker::default()),
+            Arc::new(RwLock::new(AccessControl::new())),
+            clients.clone(),
+            Some(replication.clone()),
+        );
+        let mut replica = clients.register(None, None);
+        let mut rx = replica.take_receiver().unwrap();
+        let link = replica.state().lock().link().unwrap();
+        assert!(replication.attach(link, None, |_| { Value::Integer(Integer::new(0)).into() }));
+        rx.recv().await.expect("Attached replica got no sync");
+
+        let script = "redis.call('set', KEYS[1], ARGV[1]) redis.call('get', KEYS[1]) \
+            return redis.call('incr', 'missing', 'extra')";
+        let req = request(["EVAL", script, "1", "key", "value"]);
+        let cmd = handler.parse(&req).expect("Parse eval unexpected error");
+        handler
+            .handle_request(cmd, &req, &mut client_state())
+            .expect_err("Handle eval no error");
+
+        // Only the write is propagated, although the script failed after it.
+        assert_eq!(
+            rx.try_recv().map(Reply::into_value),
+            Ok(command(["set", "key", "value"]))
+        );
+        assert!(rx.try_recv().is_err());
+    }
 }
@@ -0,0 +1,366 @@
+use std::collections::{hash_map, HashMap};
+use std::time::SystemTime;
+
+use super::listpack::{Listpack, ListpackLimits, Pairs};
+use super::resp::BulkString;
+
+/// Fields of a hash and their values, some of which may have a deadline of their own, like
+/// the hash field expiration of Redis 7.4. Expired fields are hidden from the methods taking
+/// `now`, and removed once the hash is written to.
+///
+/// A small hash is packed into a listpack, each field followed by its value, and converted
+/// to a hash table once it passes its `ListpackLimits`.
+#[derive(Debug, Clone, Default)]
+pub struct Hash {
+    fields: Fields,
+    deadlines: HashMap<BulkString, SystemTime>,
+}
+
+#[derive(Debug, Clone)]
+enum Fields {
+    Listpack(Listpack),
+    Table(HashMap<BulkString, BulkString>),
+}
+
+impl Default for Fields {
+    fn default() -> Self {
+        Self::Listpack(Listpack::new())
+    }
+}
+
+impl Hash {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of fields, counting those expired but not removed yet.
+    pub fn len(&self) -> usize {
+        match &self.fields {
+            Fields::Listpack(lp) => lp.len() / 2,
+            Fields::Table(table) => table.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the encoding reported by OBJECT ENCODING.
+    pub fn encoding(&self) -> &'static str {
+        match &self.fields {
+            Fields::Listpack(_) => "listpack",
+            Fields::Table(_) => "hashtable",
+        }
+    }
+
+    /// Returns the number of bytes taken by the fields and values.
+    pub fn bytes(&self) -> usize {
+        let element = std::mem::size_of::<BulkString>();
+        let deadlines = self.deadlines.len() * (element + std::mem::size_of::<SystemTime>());
+        let fields = match &self.fields {
+            Fields::Listpack(lp) => std::mem::size_of::<Listpack>() + lp.bytes(),
+            Fields::Table(table) => table
+                .iter()
+                .map(|(field, value)| 2 * element + bytes(field).len() + bytes(value).len())
+                .sum(),
+        };
+        fields + deadlines
+    }
+
+    /// Returns the value of the field, even if it expired.
+    pub fn get(&self, field: &BulkString) -> Option<BulkString> {
+        match &self.fields {
+            Fields::Listpack(lp) => {
+                let i = position(lp, field)?;
+                lp.get(2 * i + 1).map(|value| value.to_vec().into())
+            }
+            Fields::Table(table) => table.get(field).cloned(),
+        }
+    }
+
+    /// Returns the value of the field unless it expired by `now`.
+    pub fn get_at(&self, field: &BulkString, now: SystemTime) -> Option<BulkString> {
+        self.get(field)
+            .filter(|_| !self.field_expired_at(field, now))
+    }
+
+    /// Returns whether the hash has the field, even if it expired.
+    pub fn contains(&self, field: &BulkString) -> bool {
+        match &self.fields {
+            Fields::Listpack(lp) => position(lp, field).is_some(),
+            Fields::Table(table) => table.contains_key(field),
+        }
+    }
+
+    /// Returns the number of fields that haven't expired by `now`.
+    pub fn len_at(&self, now: SystemTime) -> usize {
+        let expired = self
+            .deadlines
+            .values()
+            .filter(|&&deadline| now > deadline)
+            .count();
+        self.len() - expired
+    }
+
+    /// Iterates over every field and its value, including expired ones.
+    pub fn iter(&self) -> Iter<'_> {
+        match &self.fields {
+            Fields::Listpack(lp) => Iter::Listpack(lp.pairs()),
+            Fields::Table(table) => Iter::Table(table.iter()),
+        }
+    }
+
+    /// Iterates over the fields that haven't expired by `now`.
+    pub fn iter_at(&self, now: SystemTime) -> impl Iterator<Item = (BulkString, BulkString)> + '_ {
+        self.iter()
+            .filter(move |(field, _)| !self.field_expired_at(field, now))
+    }
+
+    /// Sets the field to the value, clearing any deadline it had like HSET does. Returns the
+    /// previous value.
+    ///
+    /// A packed hash is converted first if the field or the value is longer than `limits`
+    /// allow, or if it would have too many fields.
+    pub fn insert(
+        &mut self,
+        field: BulkString,
+        value: BulkString,
+        limits: ListpackLimits,
+    ) -> Option<BulkString> {
+        self.deadlines.remove(&field);
+        if let Fields::Listpack(lp) = &self.fields {
+            let len = self.len() + usize::from(position(lp, &field).is_none());
+            if !limits.allows(len, &[bytes(&field), bytes(&value)]) {
+                self.convert();
+            }
+        }
+
+        match &mut self.fields {
+            Fields::Listpack(lp) => match position(lp, &field) {
+                Some(i) => lp.replace(2 * i + 1, bytes(&value)).map(Into::into),
+                None => {
+                    lp.push_back(bytes(&field));
+                    lp.push_back(bytes(&value));
+                    None
+                }
+            },
+            Fields::Table(table) => table.insert(field, value),
+        }
+    }
+
+    /// Removes the field along with its deadline, returning its value.
+    pub fn remove(&mut self, field: &BulkString) -> Option<BulkString> {
+        self.deadlines.remove(field);
+        match &mut self.fields {
+            Fields::Listpack(lp) => {
+                let i = position(lp, field)?;
+                lp.remove(2 * i);
+                lp.remove(2 * i).map(Into::into)
+            }
+            Fields::Table(table) => table.remove(field),
+        }
+    }
+
+    /// Returns the deadline of the field, if it has one.
+    pub fn deadline(&self, field: &BulkString) -> Option<SystemTime> {
+        self.deadlines.get(field).copied()
+    }
+
+    /// Sets or clears the deadline of an existing field, returning whether the field exists.
+    pub fn set_deadline(&mut self, field: &BulkString, deadline: Option<SystemTime>) -> bool {
+        if !self.contains(field) {
+            return false;
+        }
+        match deadline {
+            Some(deadline) => {
+                self.deadlines.insert(field.clone(), deadline);
+            }
+            None => {
+                self.deadlines.remove(field);
+            }
+        }
+        true
+    }
+
+    /// Removes the fields that expired by `now`, returning how many there were.
+    pub fn remove_expired(&mut self, now: SystemTime) -> usize {
+        let expired: Vec<_> = self
+            .deadlines
+            .iter()
+            .filter(|(_, &deadline)| now > deadline)
+            .map(|(field, _)| field.clone())
+            .collect();
+        for field in &expired {
+            self.remove(field);
+        }
+        expired.len()
+    }
+
+    /// Returns whether every field expired by `now`, which makes the whole key count as
+    /// expired.
+    pub fn expired_at(&self, now: SystemTime) -> bool {
+        !self.is_empty()
+            && self.deadlines.len() == self.len()
+            && self.deadlines.values().all(|&deadline| now > deadline)
+    }
+
+    fn field_expired_at(&self, field: &BulkString, now: SystemTime) -> bool {
+        self.deadlines
+            .get(field)
+            .is_some_and(|&deadline| now > deadline)
+    }
+
+    /// Moves the fields of a packed hash into a hash table.
+    fn convert(&mut self) {
+        if let Fields::Listpack(lp) = &self.fields {
+            let table = lp
+                .pairs()
+                .map(|(field, value)| (field.to_vec().into(), value.to_vec().into()))
+                .collect();
+            self.fields = Fields::Table(table);
+        }
+    }
+}
+
+/// Two hashes are equal if they have the same fields and deadlines, however they are
+/// encoded.
+impl PartialEq for Hash {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len()
+            && self.deadlines == other.deadlines
+            && self
+                .iter()
+                .all(|(field, value)| other.get(&field) == Some(value))
+    }
+}
+
+impl Eq for Hash {}
+
+/// Returns the index of the pair of the field in a packed hash.
+fn position(lp: &Listpack, field: &BulkString) -> Option<usize> {
+    lp.pairs().position(|(f, _)| f == bytes(field))
+}
+
+fn bytes(s: &BulkString) -> &[u8] {
+    s.as_bytes().unwrap_or_default()
+}
+
+/// Iterates over the fields of a hash and their values.
+#[derive(Debug)]
+pub enum Iter<'a> {
+    Listpack(Pairs<'a>),
+    Table(hash_map::Iter<'a, BulkString, BulkString>),
+}
+
+impl Iterator for Iter<'_> {
+    type Item = (BulkString, BulkString);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Self::Listpack(pairs) => pairs
+                .next()
+                .map(|(field, value)| (field.to_vec().into(), value.to_vec().into())),
+            Self::Table(iter) => iter
+                .next()
+                .map(|(field, value)| (field.clone(), value.clone())),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self {
+            Self::Listpack(pairs) => pairs.size_hint(),
+            Self::Table(iter) => iter.size_hint(),
+        }
+    }
+}
+
+impl FromIterator<(BulkString, BulkString)> for Hash {
+    fn from_iter<I: IntoIterator<Item = (BulkString, BulkString)>>(iter: I) -> Self {
+        let mut hash = Self::new();
+        for (field, value) in iter {
+            hash.insert(field, value, ListpackLimits::DEFAULT);
+        }
+        hash
+    }
+}
+
+impl<const N: usize> From<[(BulkString, BulkString); N]> for Hash {
+    fn from(pairs: [(BulkString, BulkString); N]) -> Self {
+        pairs.into_iter().collect()
+    }
+}
+
+impl From<Hash> for HashMap<BulkString, BulkString> {
+    fn from(hash: Hash) -> Self {
+        match hash.fields {
+            Fields::Table(table) => table,
+            Fields::Listpack(_) => hash.iter().collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::*;
+
+    #[test]
+    fn fields_expire_on_their_own() {
+        let now = UNIX_EPOCH + Duration::from_secs(100);
+        let later = now + Duration::from_secs(10);
+        let mut hash = Hash::from([("a".into(), "1".into()), ("b".into(), "2".into())]);
+
+        assert!(hash.set_deadline(&"a".into(), Some(now)));
+        assert!(!hash.set_deadline(&"c".into(), Some(now)));
+        assert_eq!(hash.get_at(&"a".into(), now), Some("1".into()));
+        assert_eq!(hash.get_at(&"a".into(), later), None);
+        assert_eq!(hash.len_at(later), 1);
+        assert_eq!(hash.iter_at(later).count(), 1);
+        assert!(!hash.expired_at(later));
+
+        // HSET clears the deadline.
+        hash.insert("a".into(), "3".into(), ListpackLimits::DEFAULT);
+        assert_eq!(hash.deadline(&"a".into()), None);
+
+        hash.set_deadline(&"a".into(), Some(now));
+        hash.set_deadline(&"b".into(), Some(now));
+        assert!(hash.expired_at(later));
+        assert_eq!(hash.remove_expired(later), 2);
+        assert!(hash.is_empty());
+        assert!(!hash.expired_at(later));
+    }
+
+    #[test]
+    fn converted_past_the_listpack_limits() {
+        let limits = ListpackLimits {
+            entries: 2,
+            value: 4,
+        };
+        let mut hash = Hash::new();
+        assert_eq!(hash.insert("a".into(), "1".into(), limits), None);
+        hash.insert("b".into(), "2".into(), limits);
+        // Replacing a value doesn't add a field.
+        assert_eq!(
+            hash.insert("a".into(), "3".into(), limits),
+            Some("1".into())
+        );
+        assert_eq!(hash.encoding(), "listpack");
+        let packed = hash.clone();
+
+        hash.insert("c".into(), "4".into(), limits);
+        assert_eq!(hash.encoding(), "hashtable");
+        assert_eq!(hash.get(&"a".into()), Some("3".into()));
+        // Removing fields doesn't pack the hash again.
+        assert_eq!(hash.remove(&"c".into()), Some("4".into()));
+        assert_eq!(hash.encoding(), "hashtable");
+        assert_eq!(hash, packed);
+
+        let mut hash = Hash::new();
+        hash.insert("a".into(), "four".into(), limits);
+        assert_eq!(hash.encoding(), "listpack");
+        hash.insert("a".into(), "fives".into(), limits);
+        assert_eq!(hash.encoding(), "hashtable");
+        assert_eq!(hash.len(), 1);
+    }
+}
@@ -0,0 +1,191 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::watch;
+use tracing::{error, info};
+
+use super::config::ServerConfig;
+use super::persistence::PersistenceState;
+use super::replication::SharedRole;
+
+/// Longest line a probe may send, anything longer closes the connection.
+const MAX_PROBE_LEN: u64 = 64;
+
+/// How long a probe connection may stay idle before it is closed.
+const PROBE_IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Everything a health probe reports, shared with the command handler.
+#[derive(Debug, Clone)]
+pub struct HealthSource {
+    pub persistence: Arc<PersistenceState>,
+
+    /// Tells the role, which REPLICAOF and FAILOVER may switch.
+    pub config: Arc<ServerConfig>,
+
+    /// Tells whether the link of a replica to its master is up.
+    pub role: SharedRole,
+}
+
+impl HealthSource {
+    /// Returns the readiness details as `field:value` lines, along with the error code
+    /// to answer with if the node can't serve clients: `LOADING` while loading, and
+    /// `MASTERDOWN` on a replica until it is synced with its master.
+    pub fn report(&self) -> (Option<&'static str>, Vec<String>) {
+        let loading = self.persistence.loading();
+        let is_replica = self.config.read().replica_of.is_some();
+        let link_up = self.role.replica().is_some_and(|state| state.link_up());
+        let unready = if loading {
+            Some("LOADING")
+        } else if is_replica && !link_up {
+            Some("MASTERDOWN")
+        } else {
+            None
+        };
+        let status = match unready {
+            Some("LOADING") => "loading",
+            Some(_) => "down",
+            None => "ok",
+        };
+        let mut info = vec![
+            format!("status:{status}"),
+            format!("loading:{}", loading as u8),
+            format!("role:{}", if is_replica { "slave" } else { "master" }),
+        ];
+        if is_replica {
+            let link = if link_up { "up" } else { "down" };
+            info.push(format!("master_link_status:{link}"));
+        }
+        info.push(format!(
+            "aof_enabled:{}",
+            self.persistence.aof_enabled() as u8
+        ));
+        (unready, info)
+    }
+}
+
+/// Answers health probes until `stop_rx` changes. Probes are inline commands, one per
+/// line and without authentication: `PING` is answered `+PONG`, and `HEALTH` with the
+/// readiness report as a bulk string, or as an error if the node isn't ready.
+pub async fn serve(
+    listener: TcpListener,
+    source: HealthSource,
+    mut stop_rx: watch::Receiver<bool>,
+) -> std::io::Result<()> {
+    info!("Serving health probes on {}...", listener.local_addr()?);
+    loop {
+        let (stream, _) = tokio::select! {
+            accepted = listener.accept() => accepted?,
+            _ = stop_rx.changed() => return Ok(()),
+        };
+        let source = source.clone();
+        tokio::spawn(async move {
+            if let Err(e) = respond(stream, &source).await {
+                error!("Error answering health probe: {e}");
+            }
+        });
+    }
+}
+
+/// Answers probes until the peer closes the connection, sends a line that is too long or
+/// stays idle.
+async fn respond(stream: TcpStream, source: &HealthSource) -> std::io::Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+    let mut line = String::new();
+    loop {
+        line.clear();
+        let mut probe = (&mut reader).take(MAX_PROBE_LEN);
+        match tokio::time::timeout(PROBE_IDLE_TIMEOUT, probe.read_line(&mut line)).await {
+            Ok(Ok(0)) | Err(_) => break,
+            Ok(Ok(_)) if !line.ends_with('\n') => break,
+            Ok(Ok(_)) => (),
+            Ok(Err(e)) => return Err(e),
+        }
+
+        let reply = match line.trim().to_ascii_lowercase().as_str() {
+            "" => continue,
+            "ping" => "+PONG\r\n".to_string(),
+            "health" => match source.report() {
+                (None, info) => {
+                    let body = info.join("\r\n");
+                    format!("${}\r\n{body}\r\n", body.len())
+                }
+                (Some(code), info) => format!("-{code} {}\r\n", info.join(" ")),
+            },
+            _ => "-ERR unknown probe, expected PING or HEALTH\r\n".to_string(),
+        };
+        writer.write_all(reply.as_bytes()).await?;
+    }
+    writer.shutdown().await
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::config::ConfigValues;
+    use super::super::replication::{ReplicaState, Role};
+    use super::*;
+
+    #[tokio::test]
+    async fn answer_probes() {
+        let persistence = Arc::new(PersistenceState::new());
+        let replica = Arc::new(ReplicaState::new(None));
+        let role = SharedRole::default();
+        role.replace(Role::Replica(replica.clone()));
+        let source = HealthSource {
+            persistence: persistence.clone(),
+            config: Arc::new(ServerConfig::new(ConfigValues {
+                replica_of: Some("127.0.0.1:6379".parse().unwrap()),
+                ..Default::default()
+            })),
+            role,
+        };
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (_stop_tx, stop_rx) = watch::channel(false);
+        tokio::spawn(serve(listener, source, stop_rx));
+
+        // A replica isn't ready until it is synced with its master.
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(b"HEALTH\r\n").await.unwrap();
+        stream.shutdown().await.unwrap();
+        let mut reply = String::new();
+        stream.read_to_string(&mut reply).await.unwrap();
+        assert_eq!(
+            reply,
+            "-MASTERDOWN status:down loading:0 role:slave master_link_status:down aof_enabled:0\r\n"
+        );
+
+        replica.set_link_up(true);
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(b"PING\r\nhealth\nquit\r\n").await.unwrap();
+        stream.shutdown().await.unwrap();
+        let mut reply = String::new();
+        stream.read_to_string(&mut reply).await.unwrap();
+
+        let body = "status:ok\r\nloading:0\r\nrole:slave\r\nmaster_link_status:up\r\naof_enabled:0";
+        assert_eq!(
+            reply,
+            format!(
+                "+PONG\r\n${}\r\n{body}\r\n-ERR unknown probe, expected PING or HEALTH\r\n",
+                body.len()
+            )
+        );
+
+        // A line longer than any probe closes the connection.
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(&[b'x'; 100]).await.unwrap();
+        let mut reply = Vec::new();
+        stream.read_to_end(&mut reply).await.unwrap();
+        assert!(reply.is_empty());
+
+        persistence.set_loading(true);
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(b"HEALTH\r\n").await.unwrap();
+        stream.shutdown().await.unwrap();
+        let mut reply = String::new();
+        stream.read_to_string(&mut reply).await.unwrap();
+        assert!(reply.starts_with("-LOADING status:loading loading:1 "));
+    }
+}
@@ -0,0 +1,50 @@
+use tokio::sync::watch;
+use tracing::{info_span, Instrument};
+
+use super::client::RedisClient;
+use super::config::HostPort;
+use super::handler::CommandHandler;
+use super::replica::{Replication, ReplicationError};
+use super::TlsConnector;
+
+/// Copies the dataset of another server, e.g. a stock Redis being migrated from, by syncing
+/// with it like a replica: its RDB file is loaded, then every write it replicates is
+/// applied until the link closes.
+///
+/// Clients are served all along. Keys already on this server are only replaced by keys of
+/// the same name, and commands this server doesn't implement are skipped with a warning.
+pub struct Import {
+    master_addr: HostPort,
+    listening_port: u16,
+    tls: Option<TlsConnector>,
+}
+
+impl Import {
+    pub(crate) fn new(
+        master_addr: HostPort,
+        listening_port: u16,
+        tls: Option<TlsConnector>,
+    ) -> Self {
+        Self {
+            master_addr,
+            listening_port,
+            tls,
+        }
+    }
+
+    /// Syncs with the master until it closes the link or `stop_rx` changes.
+    pub(crate) async fn run(
+        self,
+        handler: CommandHandler,
+        stop_rx: watch::Receiver<bool>,
+    ) -> Result<(), ReplicationError> {
+        let span = info_span!("import", master = %self.master_addr);
+        async {
+            let mut client = RedisClient::connect_host(self.master_addr, self.tls).await?;
+            Replication::handshake(&mut client, self.listening_port, None).await?;
+            Replication::sync(client, handler, None, stop_rx).await
+        }
+        .instrument(span)
+        .await
+    }
+}
@@ -0,0 +1,517 @@
+use std::io;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::client::{ClientError, RedisClient};
+use super::cmd::{ExpireTime, ListEnd};
+use super::resp::BulkString;
+
+/// Version of the file format, bumped on changes older versions can't read.
+pub const FORMAT_VERSION: u32 = 1;
+
+#[derive(Debug, Error)]
+pub enum JsonError {
+    #[error("unsupported dump version {0}")]
+    UnsupportedVersion(u32),
+
+    /// Keys holding types a dump can't hold, each with its type, so that none is left out
+    /// unnoticed.
+    #[error("unsupported types of keys {}", .0.join(", "))]
+    UnsupportedTypes(Vec<String>),
+
+    #[error(transparent)]
+    Client(#[from] ClientError),
+
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+/// The keyspace of a server as JSON, a backup people can read and edit, and that doesn't
+/// depend on the RDB format.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Dump {
+    pub version: u32,
+    pub keys: Vec<Entry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    pub key: Text,
+
+    #[serde(flatten)]
+    pub value: EntryValue,
+
+    /// Unix time in milliseconds the key expires at, as in RDB files.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at_ms: Option<u64>,
+}
+
+/// The value of a key, written along with its type as reported by TYPE. Members of sets and
+/// fields of hashes are sorted, so that dumps of the same keys compare equal.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value", rename_all = "lowercase")]
+pub enum EntryValue {
+    String(Text),
+    List(Vec<Text>),
+    Set(Vec<Text>),
+    Hash(Vec<(Text, Text)>),
+
+    /// Members with their scores in rank order. Scores are written like ZRANGE WITHSCORES
+    /// replies with them, as JSON numbers can't be infinite.
+    Zset(Vec<(Text, String)>),
+}
+
+/// Bytes written as a JSON string if they are UTF-8, and as an array of bytes otherwise.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Text {
+    Utf8(String),
+    Binary(Vec<u8>),
+}
+
+impl From<BulkString> for Text {
+    fn from(bs: BulkString) -> Self {
+        let bytes = bs.as_bytes().unwrap_or_default().to_vec();
+        match String::from_utf8(bytes) {
+            Ok(s) => Self::Utf8(s),
+            Err(e) => Self::Binary(e.into_bytes()),
+        }
+    }
+}
+
+impl Text {
+    pub fn to_bulk_string(&self) -> BulkString {
+        match self {
+            Self::Utf8(s) => s.as_str().into(),
+            Self::Binary(bytes) => bytes.clone().into(),
+        }
+    }
+}
+
+impl EntryValue {
+    /// Reads the value of the key, of the type TYPE reported, `None` if the key is gone.
+    /// Fails if the key doesn't hold such a value.
+    async fn read(
+        client: &mut RedisClient,
+        key: &BulkString,
+        kind: &str,
+    ) -> Result<Option<Self>, JsonError> {
+        let texts = |values: Vec<BulkString>| -> Vec<Text> {
+            let mut texts: Vec<Text> = values.into_iter().map(Text::from).collect();
+            texts.sort();
+            texts
+        };
+        let value = match kind {
+            "string" => client
+                .get(key.clone())
+                .await?
+                .map(|v| Self::String(v.into())),
+            "list" => {
+                let list = client.lrange(key.clone(), 0, -1).await?;
+                let list: Vec<Text> = list.into_iter().map(Text::from).collect();
+                (!list.is_empty()).then_some(Self::List(list))
+            }
+            "set" => {
+                let members = texts(client.smembers(key.clone()).await?);
+                (!members.is_empty()).then_some(Self::Set(members))
+            }
+            "hash" => {
+                let mut fields: Vec<(Text, Text)> = client
+                    .hgetall(key.clone())
+                    .await?
+                    .into_iter()
+                    .map(|(field, value)| (field.into(), value.into()))
+                    .collect();
+                fields.sort();
+                (!fields.is_empty()).then_some(Self::Hash(fields))
+            }
+            "zset" => {
+                let members: Vec<(Text, String)> = client
+                    .zrange(key.clone(), 0, -1)
+                    .await?
+                    .into_iter()
+                    .map(|(member, score)| (member.into(), score.to_string()))
+                    .collect();
+                (!members.is_empty()).then_some(Self::Zset(members))
+            }
+            _ => return Err(ClientError::InvalidResponse.into()),
+        };
+        Ok(value)
+    }
+
+    /// Writes the value to the key, replacing whatever it held.
+    async fn write(&self, client: &mut RedisClient, key: BulkString) -> Result<(), JsonError> {
+        let texts = |texts: &[Text]| texts.iter().map(Text::to_bulk_string).collect::<Vec<_>>();
+        // SET replaces any value, the other types would be added to it.
+        if !matches!(self, Self::String(_)) {
+            client.del([key.clone()]).await?;
+        }
+        match self {
+            Self::String(value) => {
+                client.set(key, value.to_bulk_string()).await?;
+            }
+            Self::List(list) => {
+                client.push(key, ListEnd::Right, texts(list)).await?;
+            }
+            Self::Set(members) => {
+                client.sadd(key, texts(members)).await?;
+            }
+            Self::Hash(fields) => {
+                let fields = fields
+                    .iter()
+                    .map(|(field, value)| (field.to_bulk_string(), value.to_bulk_string()));
+                client.hset(key, fields).await?;
+            }
+            Self::Zset(members) => {
+                let mut args = vec![BulkString::from("ZADD"), key];
+                for (member, score) in members {
+                    args.push(score.as_str().into());
+                    args.push(member.to_bulk_string());
+                }
+                client.command(args).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Types TYPE reports that a dump holds.
+const TYPES: [&str; 5] = ["string", "list", "set", "hash", "zset"];
+
+impl Dump {
+    /// Reads every key of the server the client is connected to, with its expiry. Keys that
+    /// expire or are deleted while they are read are left out. Fails, naming them, if keys
+    /// hold a type a dump can't hold, like streams, rather than leaving them out.
+    ///
+    /// Deadlines of hash fields aren't read, as no command returns them.
+    pub async fn read(client: &mut RedisClient) -> Result<Self, JsonError> {
+        let mut keys = vec![];
+        let mut unsupported = vec![];
+        for key in client.keys("*").await? {
+            let reply = client
+                .command([BulkString::from("TYPE"), key.clone()])
+                .await?;
+            let kind = reply
+                .simple_string()
+                .ok_or(ClientError::InvalidResponse)?
+                .as_str()
+                .to_string();
+            if kind == "none" {
+                continue;
+            }
+            if !TYPES.contains(&kind.as_str()) {
+                let name = String::from_utf8_lossy(key.as_bytes().unwrap_or_default());
+                unsupported.push(format!("{name} ({kind})"));
+                continue;
+            }
+            let Some(value) = EntryValue::read(client, &key, &kind).await? else {
+                continue;
+            };
+            let expires_at_ms = match client.pttl(key.clone()).await? {
+                -2 => continue,
+                ttl if ttl < 0 => None,
+                ttl => Some(unix_time_ms(SystemTime::now()) + ttl as u64),
+            };
+
+            keys.push(Entry {
+                key: key.into(),
+                value,
+                expires_at_ms,
+            });
+        }
+        if !unsupported.is_empty() {
+            unsupported.sort();
+            return Err(JsonError::UnsupportedTypes(unsupported));
+        }
+        // Sorted, so that dumps of the same keys compare equal.
+        keys.sort_by(|a, b| a.key.cmp(&b.key));
+
+        Ok(Self {
+            version: FORMAT_VERSION,
+            keys,
+        })
+    }
+
+    /// Writes every key into the server the client is connected to, replacing keys that
+    /// exist. Keys that have expired since the dump are skipped, like when loading an RDB
+    /// file. Returns the number of keys written.
+    ///
+    /// Nothing is written if the dump has a version that isn't supported. A type that isn't
+    /// supported already fails parsing it.
+    pub async fn write(&self, client: &mut RedisClient) -> Result<usize, JsonError> {
+        if self.version != FORMAT_VERSION {
+            return Err(JsonError::UnsupportedVersion(self.version));
+        }
+
+        let mut written = 0;
+        for entry in &self.keys {
+            let key = entry.key.to_bulk_string();
+            let now = unix_time_ms(SystemTime::now());
+            match (&entry.value, entry.expires_at_ms) {
+                (_, Some(at)) if at <= now => continue,
+                (EntryValue::String(value), Some(at)) => {
+                    client
+                        .set_px(key, value.to_bulk_string(), Duration::from_millis(at - now))
+                        .await?
+                }
+                (value, at) => {
+                    value.write(client, key.clone()).await?;
+                    if let Some(at) = at {
+                        client
+                            .expire(key, ExpireTime::UnixMillis(at as i64))
+                            .await?;
+                    }
+                }
+            }
+            written += 1;
+        }
+
+        Ok(written)
+    }
+
+    /// Parses a dump from JSON.
+    pub fn from_reader(reader: impl io::Read) -> Result<Self, JsonError> {
+        Ok(serde_json::from_reader(reader)?)
+    }
+
+    /// Writes the dump as indented JSON.
+    pub fn to_writer(&self, mut writer: impl io::Write) -> Result<(), JsonError> {
+        serde_json::to_writer_pretty(&mut writer, self)?;
+        writeln!(writer)?;
+        Ok(())
+    }
+}
+
+fn unix_time_ms(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::{Redis, RedisConfig, ServerHandle};
+    use super::*;
+
+    #[test]
+    fn text_round_trip() {
+        let dump = Dump {
+            version: FORMAT_VERSION,
+            keys: vec![Entry {
+                key: BulkString::from("key").into(),
+                value: EntryValue::String(BulkString::from(vec![0xff, 0x00]).into()),
+                expires_at_ms: Some(1_700_000_000_000),
+            }],
+        };
+
+        let mut json = vec![];
+        dump.to_writer(&mut json).expect("Write unexpected error");
+        let json = String::from_utf8(json).expect("JSON not UTF-8");
+        assert!(json.contains(r#""key": "key""#));
+        assert!(json.contains(r#""type": "string""#));
+        assert!(json.contains(r#""expires_at_ms": 1700000000000"#));
+
+        let read = Dump::from_reader(json.as_bytes()).expect("Read unexpected error");
+        assert_eq!(read, dump);
+        assert_eq!(
+            read.keys[0].value,
+            EntryValue::String(Text::Binary(vec![0xff, 0x00]))
+        );
+    }
+
+    async fn connect(server: &ServerHandle) -> RedisClient {
+        RedisClient::connect(server.addr())
+            .await
+            .expect("Connect unexpected error")
+    }
+
+    /// Dumps the keys of a server with the keys `fill` writes, one of them expiring, then
+    /// loads them into another server. Returns that server, with a client of it.
+    async fn round_trip(
+        kind: &str,
+        fill: impl AsyncFn(&mut RedisClient),
+    ) -> (ServerHandle, RedisClient) {
+        let source = Redis::spawn(RedisConfig::default())
+            .await
+            .expect("Spawn unexpected error");
+        let mut client = connect(&source).await;
+        fill(&mut client).await;
+        client
+            .expire("expiring", ExpireTime::Seconds(60))
+            .await
+            .expect("Expire error");
+
+        let dump = Dump::read(&mut client)
+            .await
+            .expect("Dump unexpected error");
+        let keys: Vec<_> = dump.keys.iter().map(|e| e.key.to_bulk_string()).collect();
+        assert_eq!(keys, vec!["expiring".into(), "plain".into()]);
+        for entry in &dump.keys {
+            let json = serde_json::to_string(entry).expect("JSON unexpected error");
+            assert!(json.contains(&format!(r#""type":"{kind}""#)), "{json}");
+        }
+
+        let target = Redis::spawn(RedisConfig::default())
+            .await
+            .expect("Spawn unexpected error");
+        let mut client = connect(&target).await;
+        // Keys that exist are replaced, not added to.
+        fill(&mut client).await;
+        assert_eq!(dump.write(&mut client).await.expect("Load error"), 2);
+        // Expiries are read relative to now, so only the values compare equal.
+        let values = |dump: Dump| dump.keys.into_iter().map(|entry| (entry.key, entry.value));
+        let loaded = Dump::read(&mut client).await.expect("Dump error");
+        assert!(values(loaded).eq(values(dump)));
+
+        let ttl = client.pttl("expiring").await.expect("Pttl error");
+        assert!((59_000..=60_000).contains(&ttl), "{ttl}");
+        assert_eq!(client.pttl("plain").await.expect("Pttl error"), -1);
+        (target, client)
+    }
+
+    #[tokio::test]
+    async fn dump_and_load_strings() {
+        let (_target, mut client) = round_trip("string", async |client: &mut RedisClient| {
+            client.set("plain", "value").await.expect("Set error");
+            client
+                .set("expiring", vec![0xff, 0xfe])
+                .await
+                .expect("Set error");
+        })
+        .await;
+        assert_eq!(
+            client.get("expiring").await.expect("Get error"),
+            Some(vec![0xff, 0xfe].into())
+        );
+    }
+
+    #[tokio::test]
+    async fn dump_and_load_lists() {
+        let (_target, mut client) = round_trip("list", async |client: &mut RedisClient| {
+            for key in ["plain", "expiring"] {
+                client
+                    .push(key, ListEnd::Right, ["b", "a", "b"])
+                    .await
+                    .expect("Push error");
+            }
+        })
+        .await;
+        assert_eq!(
+            client.lrange("plain", 0, -1).await.expect("Lrange error"),
+            vec!["b".into(), "a".into(), "b".into()]
+        );
+    }
+
+    #[tokio::test]
+    async fn dump_and_load_sets() {
+        let (_target, mut client) = round_trip("set", async |client: &mut RedisClient| {
+            for key in ["plain", "expiring"] {
+                client.sadd(key, ["a", "b"]).await.expect("Sadd error");
+            }
+        })
+        .await;
+        let mut members = client.smembers("plain").await.expect("Smembers error");
+        members.sort();
+        assert_eq!(members, vec!["a".into(), "b".into()]);
+    }
+
+    #[tokio::test]
+    async fn dump_and_load_hashes() {
+        let (_target, mut client) = round_trip("hash", async |client: &mut RedisClient| {
+            for key in ["plain", "expiring"] {
+                client
+                    .hset(key, [("f", "1"), ("g", "2")])
+                    .await
+                    .expect("Hset error");
+            }
+        })
+        .await;
+        assert_eq!(
+            client.hget("plain", "g").await.expect("Hget error"),
+            Some("2".into())
+        );
+    }
+
+    #[tokio::test]
+    async fn dump_and_load_sorted_sets() {
+        let (_target, mut client) = round_trip("zset", async |client: &mut RedisClient| {
+            for key in ["plain", "expiring"] {
+                client
+                    .zadd(key, [(1.5, "a"), (f64::INFINITY, "b"), (-2.0, "c")])
+                    .await
+                    .expect("Zadd error");
+            }
+        })
+        .await;
+        assert_eq!(
+            client.zrange("plain", 0, -1).await.expect("Zrange error"),
+            vec![
+                ("c".into(), -2.0),
+                ("a".into(), 1.5),
+                ("b".into(), f64::INFINITY)
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn dump_fails_on_streams() {
+        use super::super::handler::{StoredData, StoredValue};
+        use super::super::rdb;
+        use super::super::stream::{Stream, StreamId};
+
+        // Streams can only come from an RDB file.
+        let dir = std::env::temp_dir().join(format!("json-stream-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("Create dir unexpected error");
+        let mut stream = Stream::new();
+        stream.insert(StreamId { ms: 1, seq: 0 }, vec![("f".into(), "v".into())]);
+        let entries = [
+            (
+                "events".into(),
+                StoredData::new(StoredValue::Stream(stream), None),
+            ),
+            (
+                "plain".into(),
+                StoredData::new(StoredValue::String("v".into()), None),
+            ),
+        ];
+        let rdb = rdb::encode(entries, None, SystemTime::now());
+        std::fs::write(dir.join("dump.rdb"), rdb).expect("Write unexpected error");
+        let server = Redis::spawn(RedisConfig {
+            dir: dir.clone(),
+            ..RedisConfig::default()
+        })
+        .await
+        .expect("Spawn unexpected error");
+
+        let err = Dump::read(&mut connect(&server).await)
+            .await
+            .expect_err("Dump no error");
+        assert_eq!(err.to_string(), "unsupported types of keys events (stream)");
+        std::fs::remove_dir_all(&dir).expect("Remove dir unexpected error");
+    }
+
+    #[tokio::test]
+    async fn load_rejects_unsupported_types() {
+        let server = Redis::spawn(RedisConfig::default())
+            .await
+            .expect("Spawn unexpected error");
+        let mut client = connect(&server).await;
+        let json = r#"{"version": 1, "keys": [
+            {"key": "a", "type": "string", "value": "1"},
+            {"key": "b", "type": "stream", "value": []}
+        ]}"#;
+
+        let err = Dump::from_reader(json.as_bytes()).expect_err("Read no error");
+        assert!(matches!(err, JsonError::Json(_)));
+        let json = r#"{"version": 2, "keys": [{"key": "a", "type": "string", "value": "1"}]}"#;
+        let dump = Dump::from_reader(json.as_bytes()).expect("Read unexpected error");
+        let err = dump.write(&mut client).await.expect_err("Load no error");
+        assert!(matches!(err, JsonError::UnsupportedVersion(2)));
+        assert_eq!(client.keys("*").await.expect("Keys error"), vec![]);
+    }
+}
@@ -0,0 +1,149 @@
+use std::borrow::Borrow;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+use std::sync::Arc;
+
+use super::resp::BulkString;
+
+/// Longest key stored inline, which keeps a key as small as a fat pointer plus a tag.
+pub const INLINE_LEN: usize = 22;
+
+/// A key of the keyspace. Short keys live inline without a heap allocation, longer ones are
+/// reference counted so clones are cheap.
+///
+/// Hashes, compares and orders like its bytes, so maps keyed by it can be looked up with
+/// `&[u8]`.
+#[derive(Clone)]
+pub struct Key(Repr);
+
+#[derive(Clone)]
+enum Repr {
+    Inline { len: u8, bytes: [u8; INLINE_LEN] },
+    Heap(Arc<[u8]>),
+}
+
+impl Key {
+    pub fn new(bytes: &[u8]) -> Self {
+        if bytes.len() <= INLINE_LEN {
+            let mut inline = [0; INLINE_LEN];
+            inline[..bytes.len()].copy_from_slice(bytes);
+            Self(Repr::Inline {
+                len: bytes.len() as u8,
+                bytes: inline,
+            })
+        } else {
+            Self(Repr::Heap(bytes.into()))
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        match &self.0 {
+            Repr::Inline { len, bytes } => &bytes[..*len as usize],
+            Repr::Heap(bytes) => bytes,
+        }
+    }
+
+    /// Returns the bytes allocated on the heap for the key, 0 if it is inline.
+    pub fn heap_len(&self) -> usize {
+        match &self.0 {
+            Repr::Inline { .. } => 0,
+            Repr::Heap(bytes) => bytes.len(),
+        }
+    }
+}
+
+impl Deref for Key {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
+impl Borrow<[u8]> for Key {
+    fn borrow(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
+impl PartialEq for Key {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_bytes() == other.as_bytes()
+    }
+}
+
+impl Eq for Key {}
+
+impl PartialOrd for Key {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Key {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.as_bytes().cmp(other.as_bytes())
+    }
+}
+
+impl Hash for Key {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_bytes().hash(state)
+    }
+}
+
+impl fmt::Debug for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", String::from_utf8_lossy(self.as_bytes()))
+    }
+}
+
+impl From<&[u8]> for Key {
+    fn from(bytes: &[u8]) -> Self {
+        Self::new(bytes)
+    }
+}
+
+impl From<&str> for Key {
+    fn from(s: &str) -> Self {
+        Self::new(s.as_bytes())
+    }
+}
+
+impl From<&BulkString> for Key {
+    fn from(bs: &BulkString) -> Self {
+        Self::new(bs.as_bytes().unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[test]
+    fn short_keys_are_inline() {
+        assert_eq!(std::mem::size_of::<Key>(), 24);
+
+        let short = Key::from("user:1000");
+        assert_eq!(short.as_bytes(), b"user:1000");
+        assert_eq!(short.heap_len(), 0);
+
+        let long = Key::new(&[b'k'; INLINE_LEN + 1]);
+        assert_eq!(long.len(), INLINE_LEN + 1);
+        assert_eq!(long.heap_len(), INLINE_LEN + 1);
+    }
+
+    #[test]
+    fn lookup_by_bytes() {
+        let mut map = HashMap::new();
+        map.insert(Key::from("short"), 1);
+        map.insert(Key::new(&[b'k'; 100]), 2);
+
+        assert_eq!(map.get(b"short".as_slice()), Some(&1));
+        assert_eq!(map.get([b'k'; 100].as_slice()), Some(&2));
+        assert_eq!(map.get(b"missing".as_slice()), None);
+    }
+}
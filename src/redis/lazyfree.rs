@@ -0,0 +1,118 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+
+use tracing::error;
+
+use super::handler::StoredData;
+
+/// Values smaller than this are dropped right away, since handing them over costs about as
+/// much as freeing them.
+pub const LAZYFREE_THRESHOLD: usize = 64 * 1024;
+
+/// Values that can wait for the worker. Once it is this far behind, values are freed by the
+/// caller instead, so a burst of deletes can't pile up memory waiting to be freed.
+pub const LAZYFREE_QUEUE_LEN: usize = 1024;
+
+/// Hands values removed from the keyspace to a worker thread that drops them, so freeing a
+/// large value doesn't hold up the command that removed it.
+///
+/// The worker exits once this is dropped and every queued value is freed.
+#[derive(Debug)]
+pub struct LazyFree {
+    tx: mpsc::SyncSender<StoredData>,
+    counters: Arc<Counters>,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    pending: AtomicU64,
+    freed: AtomicU64,
+}
+
+impl Default for LazyFree {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LazyFree {
+    /// Starts the worker thread.
+    pub fn new() -> Self {
+        let (tx, rx) = mpsc::sync_channel::<StoredData>(LAZYFREE_QUEUE_LEN);
+        let counters = Arc::new(Counters::default());
+
+        let worker = counters.clone();
+        let spawned = thread::Builder::new()
+            .name("lazyfree".to_string())
+            .spawn(move || {
+                for data in rx {
+                    drop(data);
+                    worker.pending.fetch_sub(1, Ordering::Relaxed);
+                    worker.freed.fetch_add(1, Ordering::Relaxed);
+                }
+            });
+        // Without a worker the receiver is gone, so values are dropped by `free` instead.
+        if let Err(e) = spawned {
+            error!("Error spawning lazyfree worker: {e}");
+        }
+
+        Self { tx, counters }
+    }
+
+    /// Frees the value on the worker if it is large enough to be worth it and the worker
+    /// isn't too far behind, otherwise drops it in place.
+    pub fn free(&self, data: StoredData) {
+        if data.value.heap_len() < LAZYFREE_THRESHOLD {
+            return;
+        }
+
+        self.counters.pending.fetch_add(1, Ordering::Relaxed);
+        if self.tx.try_send(data).is_err() {
+            self.counters.pending.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Returns the number of values waiting to be freed.
+    pub fn pending(&self) -> u64 {
+        self.counters.pending.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of values freed by the worker so far.
+    pub fn freed(&self) -> u64 {
+        self.counters.freed.load(Ordering::Relaxed)
+    }
+
+    /// Returns the lazy free fields of the memory section of INFO.
+    pub fn info(&self) -> Vec<String> {
+        vec![
+            format!("lazyfree_pending_objects:{}", self.pending()),
+            format!("lazyfreed_objects:{}", self.freed()),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use super::*;
+
+    #[test]
+    fn frees_large_values_on_worker() {
+        let lazyfree = LazyFree::new();
+        lazyfree.free(StoredData::new("small".into(), None));
+        for _ in 0..3 {
+            let value = vec![b'x'; LAZYFREE_THRESHOLD].into();
+            lazyfree.free(StoredData::new(value, None));
+        }
+
+        let start = Instant::now();
+        while lazyfree.freed() < 3 {
+            assert!(start.elapsed() < Duration::from_secs(5));
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(lazyfree.freed(), 3);
+        assert_eq!(lazyfree.pending(), 0);
+    }
+}
@@ -0,0 +1,201 @@
+use std::collections::{vec_deque, VecDeque};
+use std::iter::Flatten;
+use std::ops::RangeInclusive;
+
+use super::listpack::{Listpack, ListpackSize};
+use super::resp::BulkString;
+
+/// Size of the listpack nodes of lists made without a config, the default of
+/// `list-max-listpack-size`.
+pub const DEFAULT_NODE_SIZE: ListpackSize = ListpackSize::Bytes(8192);
+
+/// Elements of a list, packed into listpack nodes like the quicklist of Redis. Elements are
+/// pushed to the node at their end until it grows past `list-max-listpack-size`, after
+/// which a new node is started, so a short list is a single listpack.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct List {
+    nodes: VecDeque<Listpack>,
+    len: usize,
+}
+
+impl List {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the number of bytes taken by the nodes and the elements packed in them.
+    pub fn bytes(&self) -> usize {
+        let node = std::mem::size_of::<Listpack>();
+        self.nodes.iter().map(|lp| node + lp.bytes()).sum()
+    }
+
+    /// Returns the encoding reported by OBJECT ENCODING, `listpack` while the list fits in
+    /// one node and `quicklist` once it takes more.
+    pub fn encoding(&self) -> &'static str {
+        if self.nodes.len() <= 1 {
+            "listpack"
+        } else {
+            "quicklist"
+        }
+    }
+
+    pub fn push_front(&mut self, element: &[u8], size: ListpackSize) {
+        match self.nodes.front_mut() {
+            Some(node) if size.allows(node, element) => node.push_front(element),
+            _ => self.nodes.push_front([element].into_iter().collect()),
+        }
+        self.len += 1;
+    }
+
+    pub fn push_back(&mut self, element: &[u8], size: ListpackSize) {
+        match self.nodes.back_mut() {
+            Some(node) if size.allows(node, element) => node.push_back(element),
+            _ => self.nodes.push_back([element].into_iter().collect()),
+        }
+        self.len += 1;
+    }
+
+    pub fn pop_front(&mut self) -> Option<BulkString> {
+        let node = self.nodes.front_mut()?;
+        let element = node.pop_front()?;
+        if node.is_empty() {
+            self.nodes.pop_front();
+        }
+        self.len -= 1;
+        Some(element.into())
+    }
+
+    pub fn pop_back(&mut self) -> Option<BulkString> {
+        let node = self.nodes.back_mut()?;
+        let element = node.pop_back()?;
+        if node.is_empty() {
+            self.nodes.pop_back();
+        }
+        self.len -= 1;
+        Some(element.into())
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &[u8]> {
+        self.nodes.iter().flat_map(Listpack::iter)
+    }
+
+    /// Iterates over the elements at the indexes of the range, skipping the nodes before it
+    /// without walking their elements.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range ends past the end of the list.
+    pub fn range(&self, range: RangeInclusive<usize>) -> impl Iterator<Item = &[u8]> {
+        assert!(*range.end() < self.len, "range end out of bounds");
+        let (mut first, mut skip) = (0, *range.start());
+        while skip >= self.nodes[first].len() {
+            skip -= self.nodes[first].len();
+            first += 1;
+        }
+        self.nodes
+            .range(first..)
+            .flat_map(Listpack::iter)
+            .skip(skip)
+            .take(range.count())
+    }
+
+    /// Returns a new list of the elements at the indexes of the range, e.g. to keep them
+    /// once this one is unlocked. Panics like `range`.
+    pub fn slice(&self, range: RangeInclusive<usize>) -> List {
+        let mut list = List::new();
+        for element in self.range(range) {
+            list.push_back(element, DEFAULT_NODE_SIZE);
+        }
+        list
+    }
+}
+
+impl IntoIterator for List {
+    type Item = Vec<u8>;
+    type IntoIter = Flatten<vec_deque::IntoIter<Listpack>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.nodes.into_iter().flatten()
+    }
+}
+
+impl FromIterator<BulkString> for List {
+    fn from_iter<I: IntoIterator<Item = BulkString>>(iter: I) -> Self {
+        let mut list = Self::new();
+        for element in iter {
+            list.push_back(element.as_bytes().unwrap_or_default(), DEFAULT_NODE_SIZE);
+        }
+        list
+    }
+}
+
+impl From<Vec<BulkString>> for List {
+    fn from(elements: Vec<BulkString>) -> Self {
+        elements.into_iter().collect()
+    }
+}
+
+impl<const N: usize> From<[BulkString; N]> for List {
+    fn from(elements: [BulkString; N]) -> Self {
+        elements.into_iter().collect()
+    }
+}
+
+impl From<List> for VecDeque<BulkString> {
+    fn from(list: List) -> Self {
+        list.iter().map(|element| element.to_vec().into()).collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn nodes_split_at_the_size_limit() {
+        let size = ListpackSize::Entries(2);
+        let mut list = List::new();
+        list.push_back(b"b", size);
+        list.push_front(b"a", size);
+        assert_eq!(list.encoding(), "listpack");
+
+        list.push_back(b"c", size);
+        list.push_back(b"d", size);
+        list.push_back(b"e", size);
+        assert_eq!(list.nodes.len(), 3);
+        assert_eq!(list.encoding(), "quicklist");
+        assert_eq!(list.len(), 5);
+        assert_eq!(
+            list.iter().rev().collect::<Vec<_>>(),
+            [b"e", b"d", b"c", b"b", b"a"]
+        );
+        assert_eq!(list.range(1..=3).collect::<Vec<_>>(), [b"b", b"c", b"d"]);
+        assert_eq!(list.range(4..=4).collect::<Vec<_>>(), [b"e"]);
+        assert_eq!(
+            list.slice(2..=4).into_iter().collect::<Vec<_>>(),
+            [b"c", b"d", b"e"]
+        );
+
+        // Emptied nodes are dropped.
+        assert_eq!(list.pop_back(), Some("e".into()));
+        assert_eq!(list.nodes.len(), 2);
+        assert_eq!(list.pop_front(), Some("a".into()));
+        assert_eq!(list.pop_front(), Some("b".into()));
+        assert_eq!(list.encoding(), "listpack");
+        assert_eq!(list.len(), 2);
+
+        // An element over a byte limit gets a node of its own.
+        let mut list = List::new();
+        list.push_back(&[b'x'; 100], ListpackSize::Bytes(64));
+        list.push_back(b"y", ListpackSize::Bytes(64));
+        assert_eq!(list.nodes.len(), 2);
+    }
+}
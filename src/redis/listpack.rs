@@ -0,0 +1,447 @@
+use std::ops::Range;
+
+/// A list of byte strings packed back to back into one allocation, which is what the nodes
+/// of a list are, and what small hashes and sorted sets are stored as.
+///
+/// Every entry is its length, its bytes and the number of bytes taken by both, so the
+/// list can be walked from either end. Lengths are varints, so a short entry only costs
+/// two extra bytes. Finding an entry takes a scan, which is why a list starts a new node
+/// once one grows past `list-max-listpack-size`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Listpack {
+    buf: Vec<u8>,
+    len: usize,
+}
+
+impl Listpack {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of entries.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the number of bytes used by the entries.
+    pub fn bytes(&self) -> usize {
+        self.buf.len()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&[u8]> {
+        self.iter().nth(index)
+    }
+
+    pub fn iter(&self) -> Iter<'_> {
+        Iter {
+            buf: &self.buf,
+            front: 0,
+            back: self.buf.len(),
+            len: self.len,
+        }
+    }
+
+    /// Iterates over the entries two at a time, for listpacks holding pairs like the fields
+    /// and values of a hash.
+    pub fn pairs(&self) -> Pairs<'_> {
+        Pairs(self.iter())
+    }
+
+    pub fn push_back(&mut self, entry: &[u8]) {
+        self.buf.extend_from_slice(&encode_entry(entry));
+        self.len += 1;
+    }
+
+    pub fn push_front(&mut self, entry: &[u8]) {
+        self.insert(0, entry);
+    }
+
+    pub fn pop_front(&mut self) -> Option<Vec<u8>> {
+        self.remove(0)
+    }
+
+    pub fn pop_back(&mut self) -> Option<Vec<u8>> {
+        self.len.checked_sub(1).and_then(|last| self.remove(last))
+    }
+
+    /// Inserts the entry before the one at `index`, or at the end if `index` is the length.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is greater than the length.
+    pub fn insert(&mut self, index: usize, entry: &[u8]) {
+        assert!(index <= self.len, "insert index out of bounds");
+        let offset = self.offset_of(index);
+        self.buf.splice(offset..offset, encode_entry(entry));
+        self.len += 1;
+    }
+
+    /// Removes and returns the entry at `index`.
+    pub fn remove(&mut self, index: usize) -> Option<Vec<u8>> {
+        if index >= self.len {
+            return None;
+        }
+        let offset = self.offset_of(index);
+        let (data, next) = entry_at(&self.buf, offset);
+        let entry = self.buf[data].to_vec();
+        self.buf.drain(offset..next);
+        self.len -= 1;
+        Some(entry)
+    }
+
+    /// Replaces the entry at `index`, returning the old one.
+    pub fn replace(&mut self, index: usize, entry: &[u8]) -> Option<Vec<u8>> {
+        let old = self.remove(index)?;
+        self.insert(index, entry);
+        Some(old)
+    }
+
+    /// Returns the offset of the entry at `index`, or the end of the buffer.
+    fn offset_of(&self, index: usize) -> usize {
+        let mut offset = 0;
+        for _ in 0..index {
+            offset = entry_at(&self.buf, offset).1;
+        }
+        offset
+    }
+}
+
+impl<T: AsRef<[u8]>> FromIterator<T> for Listpack {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut listpack = Self::new();
+        for entry in iter {
+            listpack.push_back(entry.as_ref());
+        }
+        listpack
+    }
+}
+
+impl<'a> IntoIterator for &'a Listpack {
+    type Item = &'a [u8];
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Iter<'a> {
+        self.iter()
+    }
+}
+
+impl IntoIterator for Listpack {
+    type Item = Vec<u8>;
+    type IntoIter = IntoIter;
+
+    fn into_iter(self) -> IntoIter {
+        IntoIter {
+            listpack: self,
+            front: 0,
+        }
+    }
+}
+
+/// Iterates over the entries of a listpack it owns, from the front.
+#[derive(Debug, Clone)]
+pub struct IntoIter {
+    listpack: Listpack,
+    front: usize,
+}
+
+impl Iterator for IntoIter {
+    type Item = Vec<u8>;
+
+    fn next(&mut self) -> Option<Vec<u8>> {
+        let buf = &self.listpack.buf;
+        if self.front == buf.len() {
+            return None;
+        }
+        let (data, next) = entry_at(buf, self.front);
+        self.front = next;
+        Some(buf[data].to_vec())
+    }
+}
+
+/// Iterates over the entries of a listpack from either end.
+#[derive(Debug, Clone)]
+pub struct Iter<'a> {
+    buf: &'a [u8],
+    front: usize,
+    back: usize,
+    len: usize,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<&'a [u8]> {
+        if self.len == 0 {
+            return None;
+        }
+        let (data, next) = entry_at(self.buf, self.front);
+        self.front = next;
+        self.len -= 1;
+        Some(&self.buf[data])
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl DoubleEndedIterator for Iter<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+        let (entry_len, backlen_len) = read_backlen(self.buf, self.back);
+        let start = self.back - backlen_len - entry_len;
+        let (data, _) = entry_at(self.buf, start);
+        self.back = start;
+        self.len -= 1;
+        Some(&self.buf[data])
+    }
+}
+
+impl ExactSizeIterator for Iter<'_> {}
+
+/// Iterates over the entries of a listpack in pairs, from either end.
+#[derive(Debug, Clone)]
+pub struct Pairs<'a>(Iter<'a>);
+
+impl<'a> Iterator for Pairs<'a> {
+    type Item = (&'a [u8], &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let first = self.0.next()?;
+        Some((first, self.0.next()?))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.0.len() / 2;
+        (len, Some(len))
+    }
+}
+
+impl DoubleEndedIterator for Pairs<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let second = self.0.next_back()?;
+        Some((self.0.next_back()?, second))
+    }
+}
+
+impl ExactSizeIterator for Pairs<'_> {}
+
+/// Size limit of every listpack node of a list, see `list-max-listpack-size`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListpackSize {
+    Entries(usize),
+    Bytes(usize),
+}
+
+impl ListpackSize {
+    /// Parses the config value, where a positive number limits the entries and -1 to -5
+    /// limit the bytes to 4, 8, 16, 32 or 64 KiB.
+    pub fn from_config(size: i64) -> Option<Self> {
+        match size {
+            1.. => Some(Self::Entries(size as usize)),
+            -5..=-1 => Some(Self::Bytes(4096 << (-size - 1))),
+            _ => None,
+        }
+    }
+
+    /// Returns true if the listpack can take the entry without going over the limit.
+    pub fn allows(&self, listpack: &Listpack, entry: &[u8]) -> bool {
+        match *self {
+            Self::Entries(max) => listpack.len() < max,
+            Self::Bytes(max) => listpack.bytes() + encoded_len(entry.len()) <= max,
+        }
+    }
+}
+
+/// Limits up to which a hash or a sorted set is a single listpack, see
+/// `hash-max-listpack-entries` and `hash-max-listpack-value` and their `zset` versions.
+/// Once a limit is passed the collection is converted to its full encoding for good.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ListpackLimits {
+    /// Most fields or members.
+    pub entries: usize,
+
+    /// Most bytes of any field, value or member.
+    pub value: usize,
+}
+
+impl ListpackLimits {
+    /// The limits Redis has by default.
+    pub const DEFAULT: Self = Self {
+        entries: 128,
+        value: 64,
+    };
+
+    /// Returns true if a collection of `len` entries, of which none are longer than
+    /// allowed, can take the given values.
+    pub fn allows(&self, len: usize, values: &[&[u8]]) -> bool {
+        len <= self.entries && values.iter().all(|value| value.len() <= self.value)
+    }
+}
+
+fn encode_entry(entry: &[u8]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(encoded_len(entry.len()));
+    write_varint(&mut buf, entry.len());
+    buf.extend_from_slice(entry);
+    // The back length is written reversed so it can be read starting from its last byte.
+    let mut backlen = Vec::with_capacity(2);
+    write_varint(&mut backlen, buf.len());
+    buf.extend(backlen.iter().rev());
+    buf
+}
+
+/// Returns the bytes taken by an entry of `len` bytes.
+fn encoded_len(len: usize) -> usize {
+    let inner = varint_len(len) + len;
+    inner + varint_len(inner)
+}
+
+/// Returns the range of the data of the entry starting at `offset`, and the offset of the
+/// next entry.
+fn entry_at(buf: &[u8], offset: usize) -> (Range<usize>, usize) {
+    let (len, len_len) = read_varint(&buf[offset..]);
+    let start = offset + len_len;
+    let end = start + len;
+    (start..end, end + varint_len(end - offset))
+}
+
+/// Reads the back length ending at `end`, returning it and the number of bytes it took.
+fn read_backlen(buf: &[u8], end: usize) -> (usize, usize) {
+    let mut value = 0;
+    let mut read = 0;
+    loop {
+        let byte = buf[end - read - 1];
+        value |= ((byte & 0x7f) as usize) << (7 * read);
+        read += 1;
+        if byte & 0x80 == 0 {
+            return (value, read);
+        }
+    }
+}
+
+fn write_varint(buf: &mut Vec<u8>, mut n: usize) {
+    while n >= 0x80 {
+        buf.push((n as u8) | 0x80);
+        n >>= 7;
+    }
+    buf.push(n as u8);
+}
+
+fn read_varint(buf: &[u8]) -> (usize, usize) {
+    let mut value = 0;
+    for (i, byte) in buf.iter().enumerate() {
+        value |= ((byte & 0x7f) as usize) << (7 * i);
+        if byte & 0x80 == 0 {
+            return (value, i + 1);
+        }
+    }
+    unreachable!("listpack entry length is cut short")
+}
+
+fn varint_len(n: usize) -> usize {
+    (usize::BITS - n.leading_zeros()).max(1).div_ceil(7) as usize
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn push_pop_both_ends() {
+        let mut lp = Listpack::new();
+        lp.push_back(b"b");
+        lp.push_back(&[b'c'; 200]);
+        lp.push_front(b"a");
+        lp.push_back(b"");
+
+        assert_eq!(lp.len(), 4);
+        assert_eq!(lp.get(1), Some(b"b".as_slice()));
+        assert_eq!(
+            lp.iter().rev().collect::<Vec<_>>(),
+            vec![b"".as_slice(), &[b'c'; 200], b"b", b"a"]
+        );
+
+        assert_eq!(lp.pop_back(), Some(vec![]));
+        assert_eq!(lp.pop_front(), Some(b"a".to_vec()));
+        assert_eq!(lp.pop_back(), Some(vec![b'c'; 200]));
+        assert_eq!(lp.pop_back(), Some(b"b".to_vec()));
+        assert_eq!(lp.pop_back(), None);
+        assert!(lp.is_empty());
+        assert_eq!(lp.bytes(), 0);
+    }
+
+    #[test]
+    fn insert_remove_replace() {
+        let mut lp: Listpack = ["a", "c", "d"].into_iter().collect();
+        lp.insert(1, b"b");
+        lp.insert(4, b"e");
+        assert_eq!(lp.remove(3), Some(b"d".to_vec()));
+        assert_eq!(lp.replace(0, &[b'z'; 300]), Some(b"a".to_vec()));
+        assert_eq!(lp.remove(4), None);
+
+        let entries: Vec<_> = lp.iter().collect();
+        assert_eq!(entries, vec![&[b'z'; 300][..], b"b", b"c", b"e"]);
+        let mut iter = lp.iter();
+        assert_eq!(iter.next(), Some(&[b'z'; 300][..]));
+        assert_eq!(iter.next_back(), Some(b"e".as_slice()));
+        assert_eq!(iter.len(), 2);
+    }
+
+    #[test]
+    fn smaller_than_separate_allocations() {
+        let lp: Listpack = (0..100).map(|i| format!("field:{i}")).collect();
+
+        // Two bytes of overhead per entry, against a Vec header plus an allocation.
+        let separate = 100 * (std::mem::size_of::<Vec<u8>>() + 16);
+        assert_eq!(
+            lp.bytes(),
+            (0..100)
+                .map(|i| format!("field:{i}").len() + 2)
+                .sum::<usize>()
+        );
+        assert!(lp.bytes() * 3 < separate);
+    }
+
+    #[test]
+    fn limits() {
+        assert_eq!(
+            ListpackSize::from_config(-2),
+            Some(ListpackSize::Bytes(8192))
+        );
+        assert_eq!(ListpackSize::from_config(5), Some(ListpackSize::Entries(5)));
+        assert_eq!(ListpackSize::from_config(0), None);
+        assert_eq!(ListpackSize::from_config(-6), None);
+
+        let lp: Listpack = ["a", "b"].into_iter().collect();
+        assert!(!ListpackSize::Entries(2).allows(&lp, b"c"));
+        assert!(ListpackSize::Bytes(9).allows(&lp, b"c"));
+        assert!(!ListpackSize::Bytes(8).allows(&lp, b"c"));
+
+        let limits = ListpackLimits {
+            entries: 2,
+            value: 3,
+        };
+        assert!(limits.allows(2, &[b"abc"]));
+        assert!(!limits.allows(3, &[b"a"]));
+        assert!(!limits.allows(1, &[b"a", b"abcd"]));
+    }
+
+    #[test]
+    fn pairs_from_both_ends() {
+        let lp: Listpack = ["a", "1", "b", "2", "c", "3"].into_iter().collect();
+        let mut pairs = lp.pairs();
+        assert_eq!(pairs.len(), 3);
+        assert_eq!(pairs.next(), Some((b"a".as_slice(), b"1".as_slice())));
+        assert_eq!(pairs.next_back(), Some((b"c".as_slice(), b"3".as_slice())));
+        assert_eq!(
+            pairs.collect::<Vec<_>>(),
+            [(b"b".as_slice(), b"2".as_slice())]
+        );
+    }
+}
@@ -0,0 +1,130 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use super::eviction::EvictionPolicy;
+use super::handler::StoredData;
+use super::key::Key;
+use super::resp::BulkString;
+use super::store::Store;
+
+/// Approximate memory used by a key and its data, including the map entry overhead. Short
+/// keys are stored in the entry itself.
+pub fn entry_usage(key: &Key, data: &StoredData) -> u64 {
+    (std::mem::size_of::<(Key, StoredData)>() + key.heap_len() + data.value.heap_len()) as u64
+}
+
+/// Approximate memory used by the keys that exist in the store.
+pub fn keys_usage(store: &Store, keys: &[BulkString]) -> u64 {
+    keys.iter()
+        .map(|key| {
+            let key = key.as_bytes().unwrap_or_default();
+            store
+                .read(key)
+                .get_key_value(key)
+                .map_or(0, |(key, data)| entry_usage(key, data))
+        })
+        .sum()
+}
+
+/// Approximate memory used by every key in the store.
+pub fn used_memory(store: &Store) -> u64 {
+    store
+        .shards()
+        .iter()
+        .map(|shard| {
+            let shard = shard.read();
+            shard
+                .iter()
+                .map(|(key, data)| entry_usage(key, data))
+                .sum::<u64>()
+        })
+        .sum()
+}
+
+/// Formats bytes the way INFO does, e.g. `1.50K`.
+pub fn human_bytes(bytes: u64) -> String {
+    const UNITS: &[(u64, &str)] = &[
+        (1 << 40, "T"),
+        (1 << 30, "G"),
+        (1 << 20, "M"),
+        (1 << 10, "K"),
+    ];
+    UNITS
+        .iter()
+        .find(|(size, _)| bytes >= *size)
+        .map(|(size, unit)| format!("{:.2}{unit}", bytes as f64 / *size as f64))
+        .unwrap_or_else(|| format!("{bytes}B"))
+}
+
+/// Running total of the memory used by the dataset, shared between the command handler and
+/// INFO. It is kept up to date by recording the usage of keys before and after every change.
+#[derive(Debug, Default)]
+pub struct MemoryTracker {
+    used: AtomicU64,
+    peak: AtomicU64,
+}
+
+impl MemoryTracker {
+    pub fn used(&self) -> u64 {
+        self.used.load(Ordering::Relaxed)
+    }
+
+    /// Returns the highest used memory seen so far.
+    pub fn peak(&self) -> u64 {
+        self.peak.load(Ordering::Relaxed)
+    }
+
+    /// Records that some keys went from using `before` to `after` bytes.
+    pub fn record(&self, before: u64, after: u64) {
+        if after >= before {
+            let used = self.used.fetch_add(after - before, Ordering::Relaxed) + (after - before);
+            self.peak.fetch_max(used, Ordering::Relaxed);
+        } else {
+            self.free(before - after);
+        }
+    }
+
+    /// Records that `bytes` were released, e.g. by evicting a key.
+    pub fn free(&self, bytes: u64) {
+        let _ = self
+            .used
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+                Some(used.saturating_sub(bytes))
+            });
+    }
+
+    /// Returns the memory section of INFO as `field:value` lines.
+    pub fn info(&self, maxmemory: u64, policy: EvictionPolicy) -> Vec<String> {
+        vec![
+            format!("used_memory:{}", self.used()),
+            format!("used_memory_human:{}", human_bytes(self.used())),
+            format!("used_memory_peak:{}", self.peak()),
+            format!("used_memory_peak_human:{}", human_bytes(self.peak())),
+            format!("maxmemory:{maxmemory}"),
+            format!("maxmemory_human:{}", human_bytes(maxmemory)),
+            format!("maxmemory_policy:{policy}"),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn record_tracks_peak() {
+        let memory = MemoryTracker::default();
+        memory.record(0, 100);
+        memory.record(100, 40);
+        memory.free(10);
+
+        assert_eq!(memory.used(), 30);
+        assert_eq!(memory.peak(), 100);
+    }
+
+    #[test]
+    fn human_bytes_units() {
+        assert_eq!(human_bytes(512), "512B");
+        assert_eq!(human_bytes(1536), "1.50K");
+        assert_eq!(human_bytes(3 * 1024 * 1024), "3.00M");
+    }
+}
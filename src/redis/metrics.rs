@@ -0,0 +1,237 @@
+use std::fmt::Write;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::watch;
+use tracing::{error, info};
+
+use super::clients::ClientRegistry;
+use super::config::ServerConfig;
+use super::memory::MemoryTracker;
+use super::replication::SharedRole;
+use super::stats::CommandStats;
+use super::store::Store;
+
+/// How long a scrape may take to send its request before the connection is closed.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Everything the exporter reads metrics from, shared with the command handler.
+#[derive(Debug, Clone)]
+pub struct MetricsSource {
+    pub store: Arc<Store>,
+    pub memory: Arc<MemoryTracker>,
+    pub clients: Arc<ClientRegistry>,
+    pub stats: Arc<CommandStats>,
+    pub config: Arc<ServerConfig>,
+
+    /// Role of the server, whose replication offset is exported while a master.
+    pub role: SharedRole,
+}
+
+impl MetricsSource {
+    /// Returns the metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, value: u64| {
+            let _ = writeln!(out, "# HELP {name} {help}");
+            let _ = writeln!(out, "# TYPE {name} {kind}");
+            let _ = writeln!(out, "{name} {value}");
+        };
+
+        metric(
+            "redis_connected_clients",
+            "gauge",
+            "Number of client connections.",
+            self.clients.len() as u64,
+        );
+        metric(
+            "redis_connections_received_total",
+            "counter",
+            "Total number of connections accepted.",
+            self.clients.total_registered(),
+        );
+        metric(
+            "redis_rejected_connections_total",
+            "counter",
+            "Number of connections refused because of maxclients.",
+            self.clients.total_rejected(),
+        );
+        metric(
+            "redis_commands_processed_total",
+            "counter",
+            "Total number of commands processed.",
+            self.stats.total_calls(),
+        );
+        metric(
+            "redis_keyspace_hits_total",
+            "counter",
+            "Number of successful key lookups.",
+            self.stats.keyspace_hits(),
+        );
+        metric(
+            "redis_keyspace_misses_total",
+            "counter",
+            "Number of failed key lookups.",
+            self.stats.keyspace_misses(),
+        );
+        metric(
+            "redis_expired_keys_total",
+            "counter",
+            "Number of keys removed by the active expire cycle.",
+            self.stats.expired_keys(),
+        );
+        metric(
+            "redis_evicted_keys_total",
+            "counter",
+            "Number of keys removed to stay within maxmemory.",
+            self.stats.evicted_keys(),
+        );
+        metric(
+            "redis_db_keys",
+            "gauge",
+            "Number of keys in the database.",
+            self.store.len() as u64,
+        );
+        metric(
+            "redis_memory_used_bytes",
+            "gauge",
+            "Approximate memory used by the dataset.",
+            self.memory.used(),
+        );
+        metric(
+            "redis_memory_peak_bytes",
+            "gauge",
+            "Highest memory used by the dataset.",
+            self.memory.peak(),
+        );
+        metric(
+            "redis_memory_max_bytes",
+            "gauge",
+            "Configured maxmemory, 0 for no limit.",
+            self.config.read().maxmemory,
+        );
+        if let Some(replication) = self.role.replication() {
+            let offset = replication.offset();
+            metric(
+                "redis_master_repl_offset",
+                "gauge",
+                "Replication offset of the master.",
+                offset,
+            );
+
+            let name = "redis_replica_lag_bytes";
+            let _ = writeln!(
+                out,
+                "# HELP {name} Bytes a replica has yet to acknowledge with REPLCONF ACK."
+            );
+            let _ = writeln!(out, "# TYPE {name} gauge");
+            let mut acks = replication.stream().replica_acks();
+            acks.sort();
+            for (addr, ack) in acks {
+                let lag = offset.saturating_sub(ack);
+                let _ = writeln!(out, "{name}{{addr=\"{addr}\"}} {lag}");
+            }
+        }
+
+        out
+    }
+}
+
+/// Serves the metrics over HTTP at `/metrics` until `stop_rx` changes.
+pub async fn serve(
+    listener: TcpListener,
+    source: MetricsSource,
+    mut stop_rx: watch::Receiver<bool>,
+) -> std::io::Result<()> {
+    info!("Serving metrics on {}...", listener.local_addr()?);
+    loop {
+        let (stream, _) = tokio::select! {
+            accepted = listener.accept() => accepted?,
+            _ = stop_rx.changed() => return Ok(()),
+        };
+        let source = source.clone();
+        tokio::spawn(async move {
+            if let Err(e) = respond(stream, &source).await {
+                error!("Error serving metrics: {e}");
+            }
+        });
+    }
+}
+
+/// Answers a single HTTP request and closes the connection, or closes it if no request
+/// arrives in time.
+async fn respond(mut stream: TcpStream, source: &MetricsSource) -> std::io::Result<()> {
+    // Only the request line matters, the headers are ignored.
+    let mut buf = [0u8; 1024];
+    let Ok(read) = tokio::time::timeout(REQUEST_TIMEOUT, stream.read(&mut buf)).await else {
+        return stream.shutdown().await;
+    };
+    let n = read?;
+    let request = String::from_utf8_lossy(&buf[..n]);
+    let target = request.lines().next().unwrap_or_default();
+
+    let (status, body) = if target.starts_with("GET /metrics ") {
+        ("200 OK", source.render())
+    } else {
+        ("404 Not Found", "Not Found\n".to_string())
+    };
+    let response = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: text/plain; version=0.0.4\r\n\
+         Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::replication::{ReplicationState, Role};
+    use super::super::resp::Value;
+    use super::*;
+
+    #[tokio::test]
+    async fn serve_metrics_over_http() {
+        let replication = Arc::new(ReplicationState::new());
+        let source = MetricsSource {
+            store: Arc::new(Store::default()),
+            memory: Arc::new(MemoryTracker::default()),
+            clients: Arc::new(ClientRegistry::new()),
+            stats: Arc::new(CommandStats::default()),
+            config: Arc::new(ServerConfig::default()),
+            role: SharedRole::new(Some(Role::Master(replication.clone()))),
+        };
+        let mut replica = source.clients.register(None, None);
+        let _rx = replica.take_receiver().unwrap();
+        let link = replica.state().lock().link().unwrap();
+        let replica_addr = "127.0.0.1:6380".parse().unwrap();
+        assert!(
+            replication.attach(link, Some(replica_addr), |_| Value::SimpleString(
+                "OK".into()
+            )
+            .into())
+        );
+        let ping = Value::Array(vec![Value::BulkString("PING".into())].into());
+        replication.stream().propagate(&ping);
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (_stop_tx, stop_rx) = watch::channel(false);
+        tokio::spawn(serve(listener, source, stop_rx));
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("\nredis_connected_clients 1\n"));
+        assert!(response.contains("\nredis_master_repl_offset 14\n"));
+        assert!(response.contains("\nredis_replica_lag_bytes{addr=\"127.0.0.1:6380\"} 14\n"));
+    }
+}
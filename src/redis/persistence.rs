@@ -0,0 +1,159 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Bookkeeping for persistence, shared between the command handler and INFO.
+#[derive(Debug)]
+pub struct PersistenceState {
+    /// Number of changes to the dataset since the last successful save.
+    dirty: AtomicU64,
+
+    /// Unix time in seconds of the last successful save.
+    last_save_time: AtomicU64,
+
+    /// Whether the last background save succeeded.
+    last_bgsave_ok: AtomicBool,
+
+    /// Whether a background save is currently running.
+    bgsave_in_progress: AtomicBool,
+
+    /// Whether the append only file is enabled.
+    aof_enabled: AtomicBool,
+
+    /// Whether a dataset is being loaded, e.g. the RDB file sent by the master to import.
+    loading: AtomicBool,
+
+    /// Whether the RDB file loaded on startup had data left out, which saving over it would
+    /// lose.
+    partial_load: AtomicBool,
+}
+
+impl Default for PersistenceState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PersistenceState {
+    /// Returns a new state with no pending changes, treating the startup time as the last save.
+    pub fn new() -> Self {
+        Self {
+            dirty: AtomicU64::new(0),
+            last_save_time: AtomicU64::new(unix_time_secs()),
+            last_bgsave_ok: AtomicBool::new(true),
+            bgsave_in_progress: AtomicBool::new(false),
+            aof_enabled: AtomicBool::new(false),
+            loading: AtomicBool::new(false),
+            partial_load: AtomicBool::new(false),
+        }
+    }
+
+    /// Records `changes` modifications to the dataset.
+    pub fn incr_dirty(&self, changes: u64) {
+        self.dirty.fetch_add(changes, Ordering::Relaxed);
+    }
+
+    /// Returns the number of changes since the last successful save.
+    pub fn dirty(&self) -> u64 {
+        self.dirty.load(Ordering::Relaxed)
+    }
+
+    /// Marks a save as completed, subtracting the changes that were captured by it.
+    /// Changes made while the save was running are kept.
+    pub fn mark_saved(&self, dirty_at_start: u64) {
+        self.dirty.fetch_sub(dirty_at_start, Ordering::Relaxed);
+        self.last_save_time
+            .store(unix_time_secs(), Ordering::Relaxed);
+    }
+
+    /// Returns the unix time in seconds of the last successful save.
+    pub fn last_save_time(&self) -> u64 {
+        self.last_save_time.load(Ordering::Relaxed)
+    }
+
+    /// Marks a background save as running, returning false if one already is.
+    pub fn start_bgsave(&self) -> bool {
+        self.bgsave_in_progress
+            .compare_exchange(false, true, Ordering::Relaxed, Ordering::Relaxed)
+            .is_ok()
+    }
+
+    pub fn set_bgsave_in_progress(&self, in_progress: bool) {
+        self.bgsave_in_progress
+            .store(in_progress, Ordering::Relaxed);
+    }
+
+    pub fn bgsave_in_progress(&self) -> bool {
+        self.bgsave_in_progress.load(Ordering::Relaxed)
+    }
+
+    pub fn set_last_bgsave_ok(&self, ok: bool) {
+        self.last_bgsave_ok.store(ok, Ordering::Relaxed);
+    }
+
+    pub fn last_bgsave_ok(&self) -> bool {
+        self.last_bgsave_ok.load(Ordering::Relaxed)
+    }
+
+    pub fn set_aof_enabled(&self, enabled: bool) {
+        self.aof_enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    pub fn aof_enabled(&self) -> bool {
+        self.aof_enabled.load(Ordering::Relaxed)
+    }
+
+    pub fn set_loading(&self, loading: bool) {
+        self.loading.store(loading, Ordering::Relaxed);
+    }
+
+    pub fn loading(&self) -> bool {
+        self.loading.load(Ordering::Relaxed)
+    }
+
+    pub fn set_partial_load(&self, partial: bool) {
+        self.partial_load.store(partial, Ordering::Relaxed);
+    }
+
+    pub fn partial_load(&self) -> bool {
+        self.partial_load.load(Ordering::Relaxed)
+    }
+
+    /// Returns the persistence section of INFO as `field:value` lines.
+    pub fn info(&self) -> Vec<String> {
+        let status = |ok: bool| if ok { "ok" } else { "err" };
+        vec![
+            format!("loading:{}", self.loading() as u8),
+            format!("rdb_changes_since_last_save:{}", self.dirty()),
+            format!("rdb_bgsave_in_progress:{}", self.bgsave_in_progress() as u8),
+            format!("rdb_last_save_time:{}", self.last_save_time()),
+            format!("rdb_last_bgsave_status:{}", status(self.last_bgsave_ok())),
+            format!("aof_enabled:{}", self.aof_enabled() as u8),
+            "aof_rewrite_in_progress:0".to_string(),
+            "aof_last_bgrewrite_status:ok".to_string(),
+        ]
+    }
+}
+
+fn unix_time_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn mark_saved_keeps_changes_made_during_save() {
+        let state = PersistenceState::new();
+        state.incr_dirty(3);
+
+        let dirty_at_start = state.dirty();
+        state.incr_dirty(2);
+        state.mark_saved(dirty_at_start);
+
+        assert_eq!(state.dirty(), 2);
+    }
+}
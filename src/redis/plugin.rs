@@ -0,0 +1,155 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+use thiserror::Error;
+
+use super::cmd::table::{self, CommandSpec};
+use super::cmd::ParseCommandError;
+use super::resp::{BulkString, Value};
+use super::store::Store;
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum PluginError {
+    #[error("Command '{0}' already exists")]
+    Exists(&'static str),
+
+    #[error("Command name '{0}' must be lowercase")]
+    InvalidName(&'static str),
+
+    /// Failure reported by the command itself, sent to the client after the `ERR` code.
+    #[error("{0}")]
+    Command(String),
+}
+
+/// A call of a plugin command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PluginArg {
+    pub name: &'static str,
+
+    /// Arguments after the command name.
+    pub args: Vec<BulkString>,
+
+    /// Positions of the keys, counting the command name as 0.
+    key_positions: Vec<usize>,
+}
+
+impl PluginArg {
+    pub fn key_args(&self) -> Vec<&BulkString> {
+        self.key_positions
+            .iter()
+            .filter_map(|pos| self.args.get(pos - 1))
+            .collect()
+    }
+}
+
+/// A command added by the application embedding the server.
+///
+/// Plugins are parsed against their spec like built-in commands: calls with the wrong
+/// arity are refused, the key arguments are checked against the ACL key patterns and
+/// cluster slots, and the flags decide whether the command may evict keys (`denyoom`)
+/// and how it affects client-side caching (`write` and `readonly`).
+pub trait CommandPlugin: Send + Sync + fmt::Debug {
+    /// Describes the command, its name being lowercase.
+    fn spec(&self) -> &CommandSpec;
+
+    /// Runs the command on the arguments after its name.
+    fn call(&self, store: &Store, args: &[BulkString]) -> Result<Value, PluginError>;
+}
+
+/// Type of the closure run by a `FnPlugin`.
+pub type PluginFn = dyn Fn(&Store, &[BulkString]) -> Result<Value, PluginError> + Send + Sync;
+
+/// A plugin running a closure, for commands that don't need a type of their own.
+pub struct FnPlugin {
+    spec: CommandSpec,
+    call: Box<PluginFn>,
+}
+
+impl FnPlugin {
+    pub fn new(
+        spec: CommandSpec,
+        call: impl Fn(&Store, &[BulkString]) -> Result<Value, PluginError> + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            spec,
+            call: Box::new(call),
+        }
+    }
+}
+
+impl fmt::Debug for FnPlugin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FnPlugin")
+            .field("spec", &self.spec)
+            .finish_non_exhaustive()
+    }
+}
+
+impl CommandPlugin for FnPlugin {
+    fn spec(&self) -> &CommandSpec {
+        &self.spec
+    }
+
+    fn call(&self, store: &Store, args: &[BulkString]) -> Result<Value, PluginError> {
+        (self.call)(store, args)
+    }
+}
+
+/// The plugins registered on a server, by name.
+#[derive(Debug, Clone, Default)]
+pub struct CommandPlugins {
+    plugins: HashMap<&'static str, Arc<dyn CommandPlugin>>,
+}
+
+impl CommandPlugins {
+    /// Adds the plugin, unless a built-in command or another plugin has its name.
+    pub fn register(&mut self, plugin: Arc<dyn CommandPlugin>) -> Result<(), PluginError> {
+        let name = plugin.spec().name;
+        if name.chars().any(|c| c.is_ascii_uppercase()) {
+            return Err(PluginError::InvalidName(name));
+        }
+        if table::lookup(name).is_some() || self.plugins.contains_key(name) {
+            return Err(PluginError::Exists(name));
+        }
+        self.plugins.insert(name, plugin);
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Arc<dyn CommandPlugin>> {
+        self.plugins.get(name)
+    }
+
+    /// Returns the spec of the plugin, matching the name case insensitively.
+    pub fn lookup(&self, name: &str) -> Option<&CommandSpec> {
+        self.plugins
+            .get(name.to_lowercase().as_str())
+            .map(|plugin| plugin.spec())
+    }
+
+    /// Parses a call of a plugin from the command name and the arguments after it, returning
+    /// `None` if no plugin has the name.
+    pub fn parse(
+        &self,
+        name: &str,
+        args: &[Value],
+    ) -> Option<Result<PluginArg, ParseCommandError>> {
+        let spec = self.lookup(name)?;
+        if !spec.arity_matches(args.len() + 1) {
+            return Some(Err(ParseCommandError::WrongArity(spec.name)));
+        }
+        let args = args
+            .iter()
+            .map(|arg| {
+                arg.bulk_string()
+                    .cloned()
+                    .ok_or_else(|| ParseCommandError::InvalidArgument(arg.clone()))
+            })
+            .collect::<Result<Vec<_>, _>>();
+        Some(args.map(|args| PluginArg {
+            name: spec.name,
+            key_positions: spec.key_positions(args.len() + 1),
+            args,
+        }))
+    }
+}
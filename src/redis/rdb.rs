@@ -0,0 +1,1220 @@
+mod packed;
+
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use thiserror::Error;
+
+use super::handler::{StoredData, StoredValue};
+use super::hash::Hash;
+use super::key::Key;
+use super::listpack::ListpackLimits;
+use super::resp::BulkString;
+use super::stream::{Stream, StreamFields, StreamId};
+use super::zset::SortedSet;
+
+use self::packed::ListpackWriter;
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum RdbError {
+    #[error("Not an RDB file")]
+    InvalidMagic,
+
+    #[error("RDB file ends early")]
+    UnexpectedEof,
+
+    #[error("Invalid length encoding in RDB file")]
+    InvalidLength,
+
+    #[error("Invalid LZF compressed string in RDB file")]
+    InvalidLzf,
+
+    #[error("Invalid ziplist, listpack or intset in RDB file")]
+    InvalidPacked,
+
+    #[error("Unsupported RDB opcode {0:#04x}")]
+    UnsupportedOpcode(u8),
+
+    #[error("Unsupported RDB value type {0}")]
+    UnsupportedType(u8),
+}
+
+const OPCODE_SLOT_INFO: u8 = 0xf4;
+const OPCODE_FUNCTION2: u8 = 0xf5;
+const OPCODE_IDLE: u8 = 0xf8;
+const OPCODE_FREQ: u8 = 0xf9;
+const OPCODE_AUX: u8 = 0xfa;
+const OPCODE_RESIZEDB: u8 = 0xfb;
+const OPCODE_EXPIRETIME_MS: u8 = 0xfc;
+const OPCODE_EXPIRETIME: u8 = 0xfd;
+const OPCODE_SELECTDB: u8 = 0xfe;
+const OPCODE_EOF: u8 = 0xff;
+
+const TYPE_STRING: u8 = 0;
+const TYPE_LIST: u8 = 1;
+const TYPE_SET: u8 = 2;
+const TYPE_ZSET: u8 = 3;
+const TYPE_HASH: u8 = 4;
+const TYPE_ZSET_2: u8 = 5;
+const TYPE_HASH_ZIPMAP: u8 = 9;
+const TYPE_LIST_ZIPLIST: u8 = 10;
+const TYPE_SET_INTSET: u8 = 11;
+const TYPE_ZSET_ZIPLIST: u8 = 12;
+const TYPE_HASH_ZIPLIST: u8 = 13;
+const TYPE_LIST_QUICKLIST: u8 = 14;
+const TYPE_STREAM_LISTPACKS: u8 = 15;
+const TYPE_HASH_LISTPACK: u8 = 16;
+const TYPE_ZSET_LISTPACK: u8 = 17;
+const TYPE_LIST_QUICKLIST_2: u8 = 18;
+/// Stream with its first ID, the last ID deleted and the number of entries ever added.
+const TYPE_STREAM_LISTPACKS_2: u8 = 19;
+const TYPE_SET_LISTPACK: u8 = 20;
+/// Stream whose consumers also have the time they were last active.
+const TYPE_STREAM_LISTPACKS_3: u8 = 21;
+/// Hash with deadlines on some fields, each as an offset from the earliest of them.
+const TYPE_HASH_METADATA: u8 = 24;
+/// Hash with deadlines on some fields packed into a listpack, after the earliest of them.
+const TYPE_HASH_LISTPACK_EX: u8 = 25;
+
+/// Container of a node of a quicklist of listpacks that holds a single large element as is.
+const QUICKLIST_NODE_PLAIN: usize = 1;
+
+/// Entries written to a listpack node of a stream, the default of `stream-node-max-entries`.
+const STREAM_NODE_ENTRIES: usize = 100;
+
+/// Flags of a stream entry in a listpack node.
+const STREAM_ITEM_DELETED: i64 = 1;
+const STREAM_ITEM_SAMEFIELDS: i64 = 2;
+
+/// Version written by `encode`, which Redis 7.0 and later can load.
+const RDB_VERSION: &[u8] = b"0011";
+
+/// Version written instead when a hash has fields with deadlines, which only Redis 7.4 and
+/// later can load.
+const RDB_VERSION_FIELD_TTL: &[u8] = b"0012";
+
+/// The keys read from an RDB file.
+#[derive(Debug, Default)]
+pub struct Dataset {
+    /// Keys of database 0 that haven't expired.
+    pub entries: Vec<(Key, StoredData)>,
+
+    /// Keys left out, since they belong to another database.
+    pub skipped: usize,
+
+    /// Function libraries left out, since they aren't loaded from RDB files.
+    pub functions: usize,
+
+    /// Replication ID of the master the data came from, from the `repl-id` aux field, for
+    /// a restarted replica to ask for a partial resync with.
+    pub repl_id: Option<String>,
+
+    /// Offset in the replication stream the data reflects, from the `repl-offset` aux field.
+    pub repl_offset: Option<u64>,
+}
+
+impl Dataset {
+    /// Returns whether something of the file was left out, which saving the dataset over it
+    /// would lose for good.
+    pub fn is_partial(&self) -> bool {
+        self.skipped > 0 || self.functions > 0
+    }
+}
+
+/// Reads the keys of an RDB file, as written by SAVE or sent by a master for a full resync.
+/// Keys whose deadline is before `now` are dropped. The checksum at the end isn't verified.
+pub fn parse(bytes: &[u8], now: SystemTime) -> Result<Dataset, RdbError> {
+    let mut reader = Reader { bytes, pos: 0 };
+    if reader.take(5)? != b"REDIS" {
+        return Err(RdbError::InvalidMagic);
+    }
+    reader.take(4)?;
+
+    let mut dataset = Dataset::default();
+    let mut db = 0;
+    let mut deadline = None;
+    loop {
+        match reader.byte()? {
+            OPCODE_EOF => return Ok(dataset),
+            OPCODE_SELECTDB => db = reader.length()?,
+            OPCODE_RESIZEDB => {
+                reader.length()?;
+                reader.length()?;
+            }
+            OPCODE_AUX => {
+                let name = reader.string()?;
+                let value = reader.string()?;
+                let value = String::from_utf8_lossy(&value);
+                match &name[..] {
+                    b"repl-id" => dataset.repl_id = Some(value.into_owned()),
+                    b"repl-offset" => dataset.repl_offset = value.parse().ok(),
+                    _ => (),
+                }
+            }
+            OPCODE_SLOT_INFO => {
+                for _ in 0..3 {
+                    reader.length()?;
+                }
+            }
+            OPCODE_FUNCTION2 => {
+                reader.string()?;
+                dataset.functions += 1;
+            }
+            OPCODE_IDLE => {
+                reader.length()?;
+            }
+            OPCODE_FREQ => {
+                reader.byte()?;
+            }
+            OPCODE_EXPIRETIME_MS => {
+                let ms = u64::from_le_bytes(reader.array()?);
+                deadline = Some(UNIX_EPOCH + Duration::from_millis(ms));
+            }
+            OPCODE_EXPIRETIME => {
+                let secs = u32::from_le_bytes(reader.array()?);
+                deadline = Some(UNIX_EPOCH + Duration::from_secs(secs.into()));
+            }
+            opcode @ 0xf0.. => return Err(RdbError::UnsupportedOpcode(opcode)),
+            value_type => {
+                let key = reader.string()?;
+                let deadline = deadline.take();
+                if db != 0 {
+                    reader.skip_value(value_type)?;
+                    dataset.skipped += 1;
+                    continue;
+                }
+                let data = StoredData::new(reader.value(value_type)?, deadline);
+                if !data.expired_at(now) {
+                    dataset.entries.push((Key::new(&key), data));
+                }
+            }
+        }
+    }
+}
+
+/// Writes the keys into an RDB file, as a master sends for a full resync. The replication
+/// ID and offset the keys reflect are written as aux fields, if given. Expired keys are left
+/// out, and the checksum is left zero, which tells readers not to verify it.
+pub fn encode(
+    entries: impl IntoIterator<Item = (Key, StoredData)>,
+    repl: Option<(&str, u64)>,
+    now: SystemTime,
+) -> Vec<u8> {
+    let entries: Vec<_> = entries
+        .into_iter()
+        .filter(|(_, data)| !data.expired_at(now))
+        .collect();
+    let field_ttl = entries.iter().any(|(_, data)| {
+        let hash = data.value.as_hash();
+        hash.is_some_and(|hash| field_deadlines(hash, now).next().is_some())
+    });
+    let mut writer = Writer {
+        bytes: Vec::new(),
+        now,
+    };
+    writer.bytes.extend_from_slice(b"REDIS");
+    writer.bytes.extend_from_slice(if field_ttl {
+        RDB_VERSION_FIELD_TTL
+    } else {
+        RDB_VERSION
+    });
+    writer.aux("redis-ver", "7.2.0");
+    writer.aux("redis-bits", "64");
+    if let Some((repl_id, repl_offset)) = repl {
+        writer.aux("repl-id", repl_id);
+        writer.aux("repl-offset", &repl_offset.to_string());
+    }
+
+    writer.bytes.push(OPCODE_SELECTDB);
+    writer.length(0);
+    writer.bytes.push(OPCODE_RESIZEDB);
+    writer.length(entries.len());
+    writer.length(entries.iter().filter(|(_, d)| d.deadline.is_some()).count());
+    for (key, data) in &entries {
+        if let Some(deadline) = data.deadline {
+            let ms = deadline
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as u64;
+            writer.bytes.push(OPCODE_EXPIRETIME_MS);
+            writer.bytes.extend_from_slice(&ms.to_le_bytes());
+        }
+        writer.value(key, &data.value);
+    }
+
+    writer.bytes.push(OPCODE_EOF);
+    writer.bytes.extend_from_slice(&[0; 8]);
+    writer.bytes
+}
+
+/// Writes the keys into the RDB file at `path` with `encode`. The file is written under a
+/// temporary name first and then renamed, so a save that fails leaves the last one intact.
+pub fn save(
+    entries: impl IntoIterator<Item = (Key, StoredData)>,
+    repl: Option<(&str, u64)>,
+    path: &Path,
+    now: SystemTime,
+) -> io::Result<()> {
+    let rdb = encode(entries, repl, now);
+    let temp = path.with_file_name(format!("temp-{}.rdb", std::process::id()));
+    let written = File::create(&temp).and_then(|mut file| {
+        file.write_all(&rdb)?;
+        file.sync_all()
+    });
+    match written {
+        Ok(()) => fs::rename(&temp, path),
+        Err(e) => {
+            let _ = fs::remove_file(&temp);
+            Err(e)
+        }
+    }
+}
+
+/// Returns the deadlines of the fields of the hash that haven't expired by `now`.
+fn field_deadlines(hash: &Hash, now: SystemTime) -> impl Iterator<Item = SystemTime> + '_ {
+    hash.iter_at(now)
+        .filter_map(|(field, _)| hash.deadline(&field))
+}
+
+fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+struct Writer {
+    bytes: Vec<u8>,
+    /// Fields of hashes that expired by then are left out.
+    now: SystemTime,
+}
+
+impl Writer {
+    /// Writes the length in as few bytes as `Reader::encoded_length` reads.
+    fn length(&mut self, len: usize) {
+        match len {
+            0..=0x3f => self.bytes.push(len as u8),
+            0x40..=0x3fff => self
+                .bytes
+                .extend_from_slice(&(0x4000 | len as u16).to_be_bytes()),
+            _ => match u32::try_from(len) {
+                Ok(len) => {
+                    self.bytes.push(0x80);
+                    self.bytes.extend_from_slice(&len.to_be_bytes());
+                }
+                Err(_) => {
+                    self.bytes.push(0x81);
+                    self.bytes.extend_from_slice(&(len as u64).to_be_bytes());
+                }
+            },
+        }
+    }
+
+    fn string(&mut self, s: &[u8]) {
+        self.length(s.len());
+        self.bytes.extend_from_slice(s);
+    }
+
+    fn bulk_string(&mut self, s: &BulkString) {
+        self.string(s.as_bytes().unwrap_or_default());
+    }
+
+    /// Writes the type, the key and the value. Collections are written in their plain
+    /// encodings, which every version of Redis reads. Streams only have listpack encodings,
+    /// and are written like Redis 7.2 does.
+    fn value(&mut self, key: &Key, value: &StoredValue) {
+        let value_type = match value {
+            StoredValue::String(_) => TYPE_STRING,
+            StoredValue::List(_) => TYPE_LIST,
+            StoredValue::Set(_) => TYPE_SET,
+            StoredValue::Hash(hash) if field_deadlines(hash, self.now).next().is_some() => {
+                TYPE_HASH_METADATA
+            }
+            StoredValue::Hash(_) => TYPE_HASH,
+            StoredValue::SortedSet(_) => TYPE_ZSET_2,
+            StoredValue::Stream(_) => TYPE_STREAM_LISTPACKS_3,
+        };
+        self.bytes.push(value_type);
+        self.string(key.as_bytes());
+        match value {
+            StoredValue::String(s) => self.bulk_string(s),
+            StoredValue::List(list) => {
+                self.length(list.len());
+                list.iter().for_each(|v| self.string(v));
+            }
+            StoredValue::Set(set) => {
+                self.length(set.len());
+                set.iter().for_each(|v| self.bulk_string(v));
+            }
+            StoredValue::Hash(hash) => {
+                let min = field_deadlines(hash, self.now).min().map(unix_millis);
+                if let Some(min) = min {
+                    self.bytes.extend_from_slice(&min.to_le_bytes());
+                }
+                self.length(hash.len_at(self.now));
+                for (field, value) in hash.iter_at(self.now) {
+                    // Deadlines are written as 1 more than their offset from the earliest,
+                    // 0 standing for none.
+                    if let Some(min) = min {
+                        let ttl = hash
+                            .deadline(&field)
+                            .map_or(0, |deadline| unix_millis(deadline) - min + 1);
+                        self.length(ttl as usize);
+                    }
+                    self.bulk_string(&field);
+                    self.bulk_string(&value);
+                }
+            }
+            StoredValue::SortedSet(zset) => {
+                self.length(zset.len());
+                for (member, score) in zset.iter() {
+                    self.bulk_string(&member);
+                    self.bytes.extend_from_slice(&score.to_le_bytes());
+                }
+            }
+            StoredValue::Stream(stream) => self.stream(stream),
+        }
+    }
+
+    /// Writes the entries into listpack nodes keyed by the ID of their first entry, which
+    /// the other IDs are written relative to. Entries with the same field names as the first
+    /// only have their values written. Then come the last ID and the other metadata of the
+    /// stream, with no consumer groups.
+    fn stream(&mut self, stream: &Stream) {
+        let entries: Vec<_> = stream.iter().collect();
+        let nodes = entries.chunks(STREAM_NODE_ENTRIES);
+        self.length(nodes.len());
+        for node in nodes {
+            let (master, master_fields) = node[0];
+            let mut lp = ListpackWriter::default();
+            lp.int(node.len() as i64).int(0);
+            lp.int(master_fields.len() as i64);
+            for (field, _) in master_fields {
+                lp.string(field.as_bytes().unwrap_or_default());
+            }
+            lp.int(0);
+            for (id, fields) in node {
+                let same_fields = fields.len() == master_fields.len()
+                    && fields
+                        .iter()
+                        .zip(master_fields)
+                        .all(|((a, _), (b, _))| a == b);
+                lp.int(if same_fields {
+                    STREAM_ITEM_SAMEFIELDS
+                } else {
+                    0
+                });
+                lp.int(id.ms.wrapping_sub(master.ms) as i64);
+                lp.int(id.seq.wrapping_sub(master.seq) as i64);
+                if !same_fields {
+                    lp.int(fields.len() as i64);
+                }
+                for (field, value) in fields.iter() {
+                    if !same_fields {
+                        lp.string(field.as_bytes().unwrap_or_default());
+                    }
+                    lp.string(value.as_bytes().unwrap_or_default());
+                }
+                // How many listpack entries the entry took, for walking the node backwards.
+                let len = if same_fields {
+                    3 + fields.len()
+                } else {
+                    4 + 2 * fields.len()
+                };
+                lp.int(len as i64);
+            }
+            self.string(&stream_id_key(master));
+            self.string(&lp.finish());
+        }
+
+        self.length(stream.len());
+        let last = stream.last_id();
+        let first = entries.first().map_or(StreamId::MIN, |(id, _)| **id);
+        // The last ID deleted is unknown, and every entry there is counts as added.
+        for len in [last.ms, last.seq, first.ms, first.seq, 0, 0] {
+            self.length(len as usize);
+        }
+        self.length(stream.len());
+        self.length(0);
+    }
+
+    fn aux(&mut self, name: &str, value: &str) {
+        self.bytes.push(OPCODE_AUX);
+        self.string(name.as_bytes());
+        self.string(value.as_bytes());
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+/// How a length-encoded field is stored.
+enum Length {
+    Len(usize),
+
+    /// A string stored specially, e.g. as an integer.
+    Encoded(u8),
+}
+
+impl Reader<'_> {
+    fn take(&mut self, n: usize) -> Result<&[u8], RdbError> {
+        let end = self.pos.checked_add(n).ok_or(RdbError::UnexpectedEof)?;
+        let taken = self
+            .bytes
+            .get(self.pos..end)
+            .ok_or(RdbError::UnexpectedEof)?;
+        self.pos = end;
+        Ok(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], RdbError> {
+        Ok(self.take(N)?.try_into().expect("Took N bytes"))
+    }
+
+    fn byte(&mut self) -> Result<u8, RdbError> {
+        Ok(self.take(1)?[0])
+    }
+
+    /// The two high bits of the first byte tell how the length is stored: in the 6 low bits,
+    /// in 14 bits, in the next 4 or 8 bytes, or not at all for special encodings.
+    fn encoded_length(&mut self) -> Result<Length, RdbError> {
+        let first = self.byte()?;
+        let len = match first >> 6 {
+            0 => (first & 0x3f).into(),
+            1 => usize::from(first & 0x3f) << 8 | usize::from(self.byte()?),
+            2 if first == 0x80 => u32::from_be_bytes(self.array()?) as usize,
+            2 if first == 0x81 => u64::from_be_bytes(self.array()?)
+                .try_into()
+                .map_err(|_| RdbError::InvalidLength)?,
+            2 => return Err(RdbError::InvalidLength),
+            _ => return Ok(Length::Encoded(first & 0x3f)),
+        };
+        Ok(Length::Len(len))
+    }
+
+    fn length(&mut self) -> Result<usize, RdbError> {
+        match self.encoded_length()? {
+            Length::Len(len) => Ok(len),
+            Length::Encoded(_) => Err(RdbError::InvalidLength),
+        }
+    }
+
+    /// Reads a string, which may be stored as an integer or compressed with LZF.
+    fn string(&mut self) -> Result<Vec<u8>, RdbError> {
+        match self.encoded_length()? {
+            Length::Len(len) => Ok(self.take(len)?.to_vec()),
+            Length::Encoded(0) => Ok((self.byte()? as i8).to_string().into_bytes()),
+            Length::Encoded(1) => Ok(i16::from_le_bytes(self.array()?).to_string().into_bytes()),
+            Length::Encoded(2) => Ok(i32::from_le_bytes(self.array()?).to_string().into_bytes()),
+            Length::Encoded(3) => {
+                let compressed_len = self.length()?;
+                let len = self.length()?;
+                lzf_decompress(self.take(compressed_len)?, len)
+            }
+            Length::Encoded(_) => Err(RdbError::InvalidLength),
+        }
+    }
+
+    /// Reads a value, whether stored in a plain encoding or packed into a ziplist, listpack,
+    /// intset or quicklist like Redis does for small collections. Values no store holds,
+    /// like those of modules, fail with `UnsupportedType`.
+    fn value(&mut self, value_type: u8) -> Result<StoredValue, RdbError> {
+        let value = match value_type {
+            TYPE_STRING => StoredValue::String(self.string()?.into()),
+            TYPE_LIST => StoredValue::List(self.strings()?.into()),
+            TYPE_SET => StoredValue::Set(self.strings()?.into_iter().collect()),
+            TYPE_HASH => {
+                let mut hash = Hash::new();
+                for _ in 0..self.length()? {
+                    hash.insert(
+                        self.string()?.into(),
+                        self.string()?.into(),
+                        ListpackLimits::DEFAULT,
+                    );
+                }
+                StoredValue::Hash(hash)
+            }
+            TYPE_HASH_METADATA => {
+                let min = u64::from_le_bytes(self.array()?);
+                let mut hash = Hash::new();
+                for _ in 0..self.length()? {
+                    let ttl = self.length()? as u64;
+                    let field = BulkString::from(self.string()?);
+                    hash.insert(
+                        field.clone(),
+                        self.string()?.into(),
+                        ListpackLimits::DEFAULT,
+                    );
+                    if ttl > 0 {
+                        let deadline = UNIX_EPOCH + Duration::from_millis(min + ttl - 1);
+                        hash.set_deadline(&field, Some(deadline));
+                    }
+                }
+                StoredValue::Hash(hash)
+            }
+            TYPE_ZSET | TYPE_ZSET_2 => {
+                let mut zset = SortedSet::new();
+                for _ in 0..self.length()? {
+                    let member = self.string()?.into();
+                    let score = match value_type {
+                        TYPE_ZSET => self.double_string()?,
+                        _ => f64::from_le_bytes(self.array()?),
+                    };
+                    zset.insert(member, score, ListpackLimits::DEFAULT);
+                }
+                StoredValue::SortedSet(zset)
+            }
+            TYPE_LIST_ZIPLIST => StoredValue::List(bulk_strings(packed::ziplist(&self.string()?)?)),
+            TYPE_LIST_QUICKLIST => {
+                let mut elements = Vec::new();
+                for _ in 0..self.length()? {
+                    elements.extend(packed::ziplist(&self.string()?)?);
+                }
+                StoredValue::List(bulk_strings(elements))
+            }
+            TYPE_LIST_QUICKLIST_2 => {
+                let mut elements = Vec::new();
+                for _ in 0..self.length()? {
+                    let container = self.length()?;
+                    let node = self.string()?;
+                    if container == QUICKLIST_NODE_PLAIN {
+                        elements.push(node);
+                    } else {
+                        elements.extend(packed::listpack(&node)?);
+                    }
+                }
+                StoredValue::List(bulk_strings(elements))
+            }
+            TYPE_SET_INTSET => StoredValue::Set(bulk_strings(packed::intset(&self.string()?)?)),
+            TYPE_SET_LISTPACK => StoredValue::Set(bulk_strings(packed::listpack(&self.string()?)?)),
+            TYPE_HASH_ZIPLIST | TYPE_HASH_LISTPACK => {
+                let entries = match value_type {
+                    TYPE_HASH_ZIPLIST => packed::ziplist(&self.string()?)?,
+                    _ => packed::listpack(&self.string()?)?,
+                };
+                let pairs = packed_tuples::<2>(entries)?;
+                StoredValue::Hash(pairs.map(|[f, v]| (f.into(), v.into())).collect())
+            }
+            // Every field is followed by its value and its deadline in unix milliseconds, 0
+            // standing for none.
+            TYPE_HASH_LISTPACK_EX => {
+                self.take(8)?;
+                let mut hash = Hash::new();
+                for [field, value, deadline] in
+                    packed_tuples::<3>(packed::listpack(&self.string()?)?)?
+                {
+                    let field = BulkString::from(field);
+                    hash.insert(field.clone(), value.into(), ListpackLimits::DEFAULT);
+                    let deadline = std::str::from_utf8(&deadline)
+                        .ok()
+                        .and_then(|ms| ms.parse::<u64>().ok())
+                        .ok_or(RdbError::InvalidPacked)?;
+                    if deadline > 0 {
+                        let deadline = UNIX_EPOCH + Duration::from_millis(deadline);
+                        hash.set_deadline(&field, Some(deadline));
+                    }
+                }
+                StoredValue::Hash(hash)
+            }
+            TYPE_ZSET_ZIPLIST | TYPE_ZSET_LISTPACK => {
+                let entries = match value_type {
+                    TYPE_ZSET_ZIPLIST => packed::ziplist(&self.string()?)?,
+                    _ => packed::listpack(&self.string()?)?,
+                };
+                let mut zset = SortedSet::new();
+                for [member, score] in packed_tuples::<2>(entries)? {
+                    let score = std::str::from_utf8(&score)
+                        .ok()
+                        .and_then(|s| s.parse::<f64>().ok())
+                        .filter(|score| !score.is_nan())
+                        .ok_or(RdbError::InvalidPacked)?;
+                    zset.insert(member.into(), score, ListpackLimits::DEFAULT);
+                }
+                StoredValue::SortedSet(zset)
+            }
+            TYPE_STREAM_LISTPACKS | TYPE_STREAM_LISTPACKS_2 | TYPE_STREAM_LISTPACKS_3 => {
+                let (stream, groups) = self.stream(value_type)?;
+                // Consumer groups aren't supported, and dropping them would lose what was
+                // delivered to whom.
+                if groups > 0 {
+                    return Err(RdbError::UnsupportedType(value_type));
+                }
+                StoredValue::Stream(stream)
+            }
+            _ => return Err(RdbError::UnsupportedType(value_type)),
+        };
+        Ok(value)
+    }
+
+    /// Reads a stream, returning how many consumer groups it had, which are skipped.
+    fn stream(&mut self, value_type: u8) -> Result<(Stream, usize), RdbError> {
+        let mut stream = Stream::new();
+        for _ in 0..self.length()? {
+            let master = self.string()?;
+            let master = <[u8; 16]>::try_from(master).map_err(|_| RdbError::InvalidPacked)?;
+            let master = StreamId {
+                ms: u64::from_be_bytes(master[..8].try_into().expect("8 bytes")),
+                seq: u64::from_be_bytes(master[8..].try_into().expect("8 bytes")),
+            };
+            for (id, fields) in stream_node(master, packed::listpack(&self.string()?)?)? {
+                if !stream.insert(id, fields) {
+                    return Err(RdbError::InvalidPacked);
+                }
+            }
+        }
+        self.length()?;
+        stream.raise_last_id(StreamId {
+            ms: self.length()? as u64,
+            seq: self.length()? as u64,
+        });
+        // The first ID, the last ID deleted and the number of entries ever added.
+        if value_type != TYPE_STREAM_LISTPACKS {
+            for _ in 0..5 {
+                self.length()?;
+            }
+        }
+
+        let groups = self.length()?;
+        for _ in 0..groups {
+            // The name, the last ID delivered, and how many entries were read since v2.
+            self.string()?;
+            self.length()?;
+            self.length()?;
+            if value_type != TYPE_STREAM_LISTPACKS {
+                self.length()?;
+            }
+            // Entries pending, with when they were delivered and how many times.
+            for _ in 0..self.length()? {
+                self.take(16 + 8)?;
+                self.length()?;
+            }
+            // Consumers, with when they were seen, active since v3, and their pending IDs.
+            for _ in 0..self.length()? {
+                self.string()?;
+                self.take(if value_type == TYPE_STREAM_LISTPACKS_3 {
+                    16
+                } else {
+                    8
+                })?;
+                let pending = self.length()?;
+                self.take(pending.checked_mul(16).ok_or(RdbError::InvalidLength)?)?;
+            }
+        }
+        Ok((stream, groups))
+    }
+
+    /// Reads a length and that many strings.
+    fn strings(&mut self) -> Result<Vec<BulkString>, RdbError> {
+        (0..self.length()?)
+            .map(|_| self.string().map(BulkString::from))
+            .collect()
+    }
+
+    /// Reads a double written as a string after its length byte, where the lengths 254 and
+    /// 255 stand for infinity and negative infinity. 253 stands for NaN, which isn't a valid
+    /// score.
+    fn double_string(&mut self) -> Result<f64, RdbError> {
+        match self.byte()? {
+            253 => Err(RdbError::InvalidLength),
+            254 => Ok(f64::INFINITY),
+            255 => Ok(f64::NEG_INFINITY),
+            len => std::str::from_utf8(self.take(len.into())?)
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .ok_or(RdbError::InvalidLength),
+        }
+    }
+
+    /// Skips a value of another database. Modules can't be skipped without knowing them, and
+    /// streams are parsed whole.
+    fn skip_value(&mut self, value_type: u8) -> Result<(), RdbError> {
+        let skip_strings = |reader: &mut Self, n: usize| -> Result<(), RdbError> {
+            (0..n).try_for_each(|_| reader.string().map(drop))
+        };
+        match value_type {
+            TYPE_LIST | TYPE_SET | TYPE_LIST_QUICKLIST => {
+                let len = self.length()?;
+                skip_strings(self, len)
+            }
+            // Sorted set with doubles as strings, whose length byte has special values.
+            TYPE_ZSET => {
+                for _ in 0..self.length()? {
+                    self.string()?;
+                    let len = self.byte()?;
+                    if len < 253 {
+                        self.take(len.into())?;
+                    }
+                }
+                Ok(())
+            }
+            TYPE_HASH => {
+                let len = self.length()?;
+                skip_strings(self, 2 * len)
+            }
+            // Hash with field deadlines, after the earliest of them.
+            TYPE_HASH_METADATA => {
+                self.take(8)?;
+                for _ in 0..self.length()? {
+                    self.length()?;
+                    skip_strings(self, 2)?;
+                }
+                Ok(())
+            }
+            TYPE_ZSET_2 => {
+                for _ in 0..self.length()? {
+                    self.string()?;
+                    self.take(8)?;
+                }
+                Ok(())
+            }
+            // Strings, and encodings packed into a single string.
+            TYPE_STRING | TYPE_HASH_ZIPMAP | TYPE_LIST_ZIPLIST | TYPE_SET_INTSET
+            | TYPE_ZSET_ZIPLIST | TYPE_HASH_ZIPLIST | TYPE_HASH_LISTPACK | TYPE_ZSET_LISTPACK
+            | TYPE_SET_LISTPACK => skip_strings(self, 1),
+            TYPE_HASH_LISTPACK_EX => {
+                self.take(8)?;
+                skip_strings(self, 1)
+            }
+            // Every node has its container first.
+            TYPE_LIST_QUICKLIST_2 => {
+                for _ in 0..self.length()? {
+                    self.length()?;
+                    self.string()?;
+                }
+                Ok(())
+            }
+            TYPE_STREAM_LISTPACKS | TYPE_STREAM_LISTPACKS_2 | TYPE_STREAM_LISTPACKS_3 => {
+                self.stream(value_type).map(drop)
+            }
+            _ => Err(RdbError::UnsupportedType(value_type)),
+        }
+    }
+}
+
+fn bulk_strings<T: FromIterator<BulkString>>(entries: Vec<Vec<u8>>) -> T {
+    entries.into_iter().map(BulkString::from).collect()
+}
+
+/// Groups the entries of a packed hash or sorted set, e.g. into fields and their values.
+fn packed_tuples<const N: usize>(
+    entries: Vec<Vec<u8>>,
+) -> Result<impl Iterator<Item = [Vec<u8>; N]>, RdbError> {
+    if !entries.len().is_multiple_of(N) {
+        return Err(RdbError::InvalidPacked);
+    }
+    let mut entries = entries.into_iter();
+    Ok(std::iter::from_fn(move || {
+        let tuple: Vec<_> = entries.by_ref().take(N).collect();
+        tuple.try_into().ok()
+    }))
+}
+
+/// Returns the key of a listpack node of a stream, the ID of its first entry in big endian
+/// so that the keys sort like the IDs.
+fn stream_id_key(id: &StreamId) -> [u8; 16] {
+    let mut key = [0; 16];
+    key[..8].copy_from_slice(&id.ms.to_be_bytes());
+    key[8..].copy_from_slice(&id.seq.to_be_bytes());
+    key
+}
+
+/// Returns the entries of a listpack node of a stream, leaving out deleted ones. The node
+/// starts with how many entries it has, how many of them are deleted and the fields of
+/// the first entry. Every entry is its flags, its ID relative to `master`, its fields
+/// unless the same as the first entry's, its values and how many listpack entries it took.
+fn stream_node(
+    master: StreamId,
+    entries: Vec<Vec<u8>>,
+) -> Result<Vec<(StreamId, StreamFields)>, RdbError> {
+    let mut entries = entries.into_iter();
+    let mut next = || entries.next().ok_or(RdbError::InvalidPacked);
+    let int = |entry: Vec<u8>| {
+        std::str::from_utf8(&entry)
+            .ok()
+            .and_then(|int| int.parse::<i64>().ok())
+            .ok_or(RdbError::InvalidPacked)
+    };
+    let count = int(next()?)?.saturating_add(int(next()?)?);
+    let master_fields = (0..int(next()?)?)
+        .map(|_| next())
+        .collect::<Result<Vec<_>, _>>()?;
+    int(next()?)?;
+
+    let mut node = Vec::new();
+    for _ in 0..count {
+        let flags = int(next()?)?;
+        let id = StreamId {
+            ms: master.ms.wrapping_add(int(next()?)? as u64),
+            seq: master.seq.wrapping_add(int(next()?)? as u64),
+        };
+        let fields: StreamFields = if flags & STREAM_ITEM_SAMEFIELDS != 0 {
+            master_fields
+                .iter()
+                .map(|field| Ok((field.clone().into(), next()?.into())))
+                .collect::<Result<_, RdbError>>()?
+        } else {
+            (0..int(next()?)?)
+                .map(|_| Ok((next()?.into(), next()?.into())))
+                .collect::<Result<_, RdbError>>()?
+        };
+        int(next()?)?;
+        if flags & STREAM_ITEM_DELETED == 0 {
+            node.push((id, fields));
+        }
+    }
+    Ok(node)
+}
+
+/// Decompresses LZF data into `len` bytes. Every control byte starts either a run of up to
+/// 32 literal bytes or a back reference copying bytes already written.
+fn lzf_decompress(input: &[u8], len: usize) -> Result<Vec<u8>, RdbError> {
+    let mut out = Vec::with_capacity(len);
+    let mut iter = input.iter().copied();
+    while let Some(ctrl) = iter.next() {
+        let ctrl = usize::from(ctrl);
+        if ctrl < 32 {
+            for _ in 0..=ctrl {
+                out.push(iter.next().ok_or(RdbError::InvalidLzf)?);
+            }
+            continue;
+        }
+
+        let mut run = ctrl >> 5;
+        if run == 7 {
+            run += usize::from(iter.next().ok_or(RdbError::InvalidLzf)?);
+        }
+        let low = usize::from(iter.next().ok_or(RdbError::InvalidLzf)?);
+        let back = ((ctrl & 0x1f) << 8 | low) + 1;
+        let start = out.len().checked_sub(back).ok_or(RdbError::InvalidLzf)?;
+        // The copy may overlap the bytes it writes, so it goes byte by byte.
+        for i in start..start + run + 2 {
+            out.push(out[i]);
+        }
+    }
+    if out.len() != len {
+        return Err(RdbError::InvalidLzf);
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn rdb(body: &[u8]) -> Vec<u8> {
+        let mut bytes = b"REDIS0011".to_vec();
+        bytes.extend_from_slice(b"\xfa\x09redis-ver\x057.2.0");
+        bytes.extend_from_slice(body);
+        bytes.push(OPCODE_EOF);
+        bytes.extend_from_slice(&[0; 8]);
+        bytes
+    }
+
+    fn entries(dataset: &Dataset) -> Vec<(&[u8], &[u8], Option<SystemTime>)> {
+        dataset
+            .entries
+            .iter()
+            .map(|(key, data)| (key.as_bytes(), data.value.string_bytes(), data.deadline))
+            .collect()
+    }
+
+    #[test]
+    fn parse_string_keys() {
+        let later = UNIX_EPOCH + Duration::from_millis(0x1_0000_0000);
+        let bytes = rdb(&[
+            b"\xfe\x00\xfb\x05\x02".as_slice(),
+            b"\x00\x03key\x05value",
+            // Integer encodings.
+            b"\x00\x03int\xc0\x7b",
+            b"\x00\x03neg\xc1\x18\xfc",
+            // 10 bytes compressed with LZF into a literal and a back reference.
+            b"\x00\x03lzf\xc3\x05\x0a\x00a\xe0\x00\x00",
+            b"\xfc\x00\x00\x00\x00\x01\x00\x00\x00\x00\x07expires\x01x",
+            b"\xfc\x00\x00\x00\x00\x00\x00\x00\x00\x00\x07expired\x01x",
+        ]
+        .concat());
+
+        let dataset = parse(&bytes, UNIX_EPOCH + Duration::from_secs(1)).unwrap();
+        assert_eq!(
+            entries(&dataset),
+            [
+                (&b"key"[..], &b"value"[..], None),
+                (b"int", b"123", None),
+                (b"neg", b"-1000", None),
+                (b"lzf", b"aaaaaaaaaa", None),
+                (b"expires", b"x", Some(later)),
+            ]
+        );
+        assert_eq!(dataset.skipped, 0);
+        assert!(!dataset.is_partial());
+    }
+
+    #[test]
+    fn skip_other_databases() {
+        let bytes = rdb(&[
+            // A list, a listpack hash, a quicklist and a string of database 1.
+            b"\xfe\x01\x01\x04list\x02\x01a\x01b".as_slice(),
+            b"\x10\x04hash\x03abc",
+            b"\x12\x05quick\x01\x02\x03abc",
+            b"\x00\x03key\x05value",
+            b"\xfe\x00\x00\x03key\x05other",
+        ]
+        .concat());
+
+        let dataset = parse(&bytes, UNIX_EPOCH).unwrap();
+        assert_eq!(entries(&dataset), [(&b"key"[..], &b"other"[..], None)]);
+        assert_eq!(dataset.skipped, 4);
+        assert!(dataset.is_partial());
+    }
+
+    #[test]
+    fn parse_collections() {
+        let bytes = rdb(&[
+            b"\x01\x04list\x02\x01a\x01b".as_slice(),
+            b"\x02\x03set\x01\x01a",
+            b"\x04\x04hash\x01\x01f\x01v",
+            // Scores as strings, with infinity as a special length.
+            b"\x03\x04zset\x02\x01a\x031.5\x01b\xfe",
+        ]
+        .concat());
+
+        let dataset = parse(&bytes, UNIX_EPOCH).unwrap();
+        let values: Vec<_> = dataset.entries.into_iter().map(|(_, d)| d.value).collect();
+        assert_eq!(
+            values,
+            [
+                StoredValue::List(["a".into(), "b".into()].into()),
+                StoredValue::Set(["a".into()].into()),
+                StoredValue::Hash([("f".into(), "v".into())].into()),
+                StoredValue::SortedSet(SortedSet::from_iter([
+                    ("a".into(), 1.5),
+                    ("b".into(), f64::INFINITY)
+                ])),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_packed_collections() {
+        let later = UNIX_EPOCH + Duration::from_millis(1000);
+        let bytes = rdb(&[
+            // A quicklist with a listpack node of "a" and 1, and a plain node.
+            b"\x12\x04list\x02\x02\x0c\x00\x00\x00\x00\x02\x00\x81a\x02\x01\x01\xff".as_slice(),
+            b"\x01\x04long",
+            // An intset of 16 bit integers.
+            b"\x0b\x03set\x0c\x02\x00\x00\x00\x02\x00\x00\x00\x01\x00\xff\xff",
+            // A listpack hash, and a ziplist one.
+            b"\x10\x04hash\x0d\x00\x00\x00\x00\x02\x00\x81f\x02\x81v\x02\xff",
+            b"\x0d\x03old\x10\x00\x00\x00\x00\x00\x00\x00\x00\x02\x00\x00\x01f\x03\xf2\xff",
+            // A listpack sorted set, its scores as a string and an integer.
+            b"\x11\x04zset\x14\x00\x00\x00\x00\x04\x00\x81a\x02\x831.5\x04\x81b\x02\x07\x01\xff",
+            // A listpack hash with a field expiring at 1000 ms, and one that doesn't.
+            b"\x19\x03ttl\xe8\x03\x00\x00\x00\x00\x00\x00",
+            b"\x18\x00\x00\x00\x00\x06\x00\x81a\x02\x811\x02\xc3\xe8\x02\x81b\x02\x812\x02\x00\x01\xff",
+        ]
+        .concat());
+
+        let dataset = parse(&bytes, UNIX_EPOCH).unwrap();
+        let values: Vec<_> = dataset.entries.into_iter().map(|(_, d)| d.value).collect();
+        let mut ttl = Hash::from([("a".into(), "1".into()), ("b".into(), "2".into())]);
+        ttl.set_deadline(&"a".into(), Some(later));
+        assert_eq!(
+            values,
+            [
+                StoredValue::List(["a".into(), "1".into(), "long".into()].into()),
+                StoredValue::Set(["1".into(), "-1".into()].into()),
+                StoredValue::Hash([("f".into(), "v".into())].into()),
+                StoredValue::Hash([("f".into(), "1".into())].into()),
+                StoredValue::SortedSet(SortedSet::from_iter([
+                    ("a".into(), 1.5),
+                    ("b".into(), 7.0)
+                ])),
+                StoredValue::Hash(ttl),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_replication_aux_fields() {
+        let dataset = parse(&rdb(b""), UNIX_EPOCH).unwrap();
+        assert_eq!((dataset.repl_id, dataset.repl_offset), (None, None));
+
+        let bytes = rdb(&[
+            b"\xfa\x07repl-id\x288e41d8ba49ba98a6d0a2f1a3f1b9c1bd4c4d8e51".as_slice(),
+            // The offset is written as an integer encoded string.
+            b"\xfa\x0brepl-offset\xc1\x39\x30",
+        ]
+        .concat());
+        let dataset = parse(&bytes, UNIX_EPOCH).unwrap();
+        assert_eq!(
+            dataset.repl_id.as_deref(),
+            Some("8e41d8ba49ba98a6d0a2f1a3f1b9c1bd4c4d8e51")
+        );
+        assert_eq!(dataset.repl_offset, Some(12345));
+    }
+
+    #[test]
+    fn encode_round_trips() {
+        let now = UNIX_EPOCH + Duration::from_secs(1);
+        let later = UNIX_EPOCH + Duration::from_millis(0x1_0000_0000);
+        let long = vec![b'x'; 20_000];
+        let keys = [
+            (Key::from("key"), StoredData::new("value".into(), None)),
+            (
+                Key::from("expires"),
+                StoredData::new("x".into(), Some(later)),
+            ),
+            (
+                Key::from("expired"),
+                StoredData::new("x".into(), Some(UNIX_EPOCH)),
+            ),
+            (
+                Key::from("long"),
+                StoredData::new(long.clone().into(), None),
+            ),
+        ];
+
+        let bytes = encode(keys, Some(("8e41d8ba49ba98a6d0a2", 42)), now);
+        let dataset = parse(&bytes, now).unwrap();
+        assert_eq!(
+            entries(&dataset),
+            [
+                (&b"key"[..], &b"value"[..], None),
+                (b"expires", b"x", Some(later)),
+                (b"long", &long[..], None),
+            ]
+        );
+        assert_eq!(dataset.repl_id.as_deref(), Some("8e41d8ba49ba98a6d0a2"));
+        assert_eq!(dataset.repl_offset, Some(42));
+
+        let dataset = parse(&encode([], None, now), now).unwrap();
+        assert!(dataset.entries.is_empty());
+        assert_eq!(dataset.repl_id, None);
+    }
+
+    #[test]
+    fn encode_collections_round_trips() {
+        let values = [
+            StoredValue::List(["a".into(), "b".into()].into()),
+            StoredValue::Set(["a".into(), "b".into()].into()),
+            StoredValue::Hash([("f".into(), "v".into())].into()),
+            StoredValue::SortedSet(SortedSet::from_iter([
+                ("a".into(), -2.5),
+                ("b".into(), f64::NEG_INFINITY),
+            ])),
+        ];
+        let keys = values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| (Key::new(&[i as u8]), StoredData::new(value.clone(), None)));
+
+        let dataset = parse(&encode(keys, None, UNIX_EPOCH), UNIX_EPOCH).unwrap();
+        let parsed: Vec<_> = dataset.entries.into_iter().map(|(_, d)| d.value).collect();
+        assert_eq!(parsed, values);
+    }
+
+    #[test]
+    fn encode_stream_round_trips() {
+        // Enough entries for a second node, some with fields other than the first's, and a
+        // last ID past the last entry.
+        let mut stream = Stream::new();
+        for i in 0..150u64 {
+            let id = StreamId {
+                ms: 1_700_000_000_000 + i / 3,
+                seq: i % 3,
+            };
+            let fields = match i % 4 {
+                0 => vec![("a".into(), i.to_string().into())],
+                _ => vec![("a".into(), "x".into()), ("b".into(), "".into())],
+            };
+            assert!(stream.insert(id, fields));
+        }
+        stream.raise_last_id(StreamId {
+            ms: u64::MAX,
+            seq: 5,
+        });
+        let keys = [
+            (
+                Key::from("stream"),
+                StoredData::new(StoredValue::Stream(stream.clone()), None),
+            ),
+            (
+                Key::from("empty"),
+                StoredData::new(StoredValue::Stream(Stream::new()), None),
+            ),
+        ];
+
+        let dataset = parse(&encode(keys, None, UNIX_EPOCH), UNIX_EPOCH).unwrap();
+        let parsed: Vec<_> = dataset.entries.into_iter().map(|(_, d)| d.value).collect();
+        assert_eq!(
+            parsed,
+            [
+                StoredValue::Stream(stream),
+                StoredValue::Stream(Stream::new())
+            ]
+        );
+    }
+
+    #[test]
+    fn encode_field_deadlines_round_trips() {
+        let now = UNIX_EPOCH + Duration::from_secs(10);
+        let later = now + Duration::from_millis(1500);
+        let mut hash = Hash::from([
+            ("a".into(), "1".into()),
+            ("b".into(), "2".into()),
+            ("c".into(), "3".into()),
+        ]);
+        hash.set_deadline(&"a".into(), Some(later));
+        hash.set_deadline(&"c".into(), Some(UNIX_EPOCH));
+        let keys = [(
+            Key::from("hash"),
+            StoredData::new(StoredValue::Hash(hash), None),
+        )];
+
+        let bytes = encode(keys, None, now);
+        assert_eq!(&bytes[5..9], RDB_VERSION_FIELD_TTL);
+        let dataset = parse(&bytes, now).unwrap();
+        let hash = dataset.entries[0].1.value.as_hash().unwrap();
+        assert_eq!(hash.len(), 2);
+        assert_eq!(hash.deadline(&"a".into()), Some(later));
+        assert_eq!(hash.get(&"b".into()), Some("2".into()));
+        assert_eq!(hash.deadline(&"b".into()), None);
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(
+            parse(b"RESID0011\xff", UNIX_EPOCH).unwrap_err(),
+            RdbError::InvalidMagic
+        );
+        assert_eq!(
+            parse(b"REDIS0011\x00\x03key\x05val", UNIX_EPOCH).unwrap_err(),
+            RdbError::UnexpectedEof
+        );
+        assert_eq!(
+            // A stream with a consumer group.
+            parse(
+                &rdb(b"\x0f\x06stream\x00\x00\x00\x00\x01\x01g\x00\x00\x00\x00"),
+                UNIX_EPOCH
+            )
+            .unwrap_err(),
+            RdbError::UnsupportedType(15)
+        );
+        assert_eq!(
+            parse(&rdb(b"\x00\x03lzf\xc3\x02\x05\x20\x00"), UNIX_EPOCH).unwrap_err(),
+            RdbError::InvalidLzf
+        );
+        // Packed collections fail rather than load partly, as do zipmaps of Redis 2.4.
+        assert_eq!(
+            parse(&rdb(b"\x10\x04hash\x03abc"), UNIX_EPOCH).unwrap_err(),
+            RdbError::InvalidPacked
+        );
+        assert_eq!(
+            parse(&rdb(b"\x09\x06zipmap\x02\x00\xff"), UNIX_EPOCH).unwrap_err(),
+            RdbError::UnsupportedType(9)
+        );
+    }
+}
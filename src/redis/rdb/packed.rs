@@ -0,0 +1,342 @@
+//! Decoding of the encodings Redis packs small collections into before writing them to an
+//! RDB file as a single string: ziplists up to Redis 6, listpacks from Redis 7, and intsets
+//! for sets of integers. Integers come out as their decimal strings, as Redis returns them.
+//!
+//! Listpacks are also written, for the nodes of streams, which have no other encoding.
+
+use super::RdbError;
+
+/// Returns the entries of a ziplist.
+///
+/// ```text
+/// <zlbytes u32> <zltail u32> <zllen u16> <entry> ... <0xff>
+/// ```
+///
+/// where every entry is the length of the previous one, its encoding and its data.
+pub(super) fn ziplist(bytes: &[u8]) -> Result<Vec<Vec<u8>>, RdbError> {
+    let mut reader = Packed::new(bytes, 10)?;
+    let mut entries = Vec::new();
+    loop {
+        let prevlen = reader.byte()?;
+        if prevlen == 0xff {
+            return Ok(entries);
+        }
+        if prevlen == 0xfe {
+            reader.take(4)?;
+        }
+        let encoding = reader.byte()?;
+        let entry = match encoding >> 6 {
+            0 => reader.take((encoding & 0x3f).into())?.to_vec(),
+            1 => {
+                let len = usize::from(encoding & 0x3f) << 8 | usize::from(reader.byte()?);
+                reader.take(len)?.to_vec()
+            }
+            2 => {
+                let len = u32::from_be_bytes(reader.array()?);
+                reader.take(len as usize)?.to_vec()
+            }
+            _ => {
+                let int = match encoding {
+                    0xc0 => reader.int(2)?,
+                    0xd0 => reader.int(4)?,
+                    0xe0 => reader.int(8)?,
+                    0xf0 => reader.int(3)?,
+                    0xfe => reader.int(1)?,
+                    // The value is in the low 4 bits, from 1 for 0 to 13 for 12.
+                    0xf1..=0xfd => i64::from(encoding & 0x0f) - 1,
+                    _ => return Err(RdbError::InvalidPacked),
+                };
+                int.to_string().into_bytes()
+            }
+        };
+        entries.push(entry);
+    }
+}
+
+/// Returns the entries of a listpack.
+///
+/// ```text
+/// <total bytes u32> <count u16> <entry> ... <0xff>
+/// ```
+///
+/// where every entry is its encoding, its data and the length of both, so it can also be
+/// walked backwards.
+pub(super) fn listpack(bytes: &[u8]) -> Result<Vec<Vec<u8>>, RdbError> {
+    let mut reader = Packed::new(bytes, 6)?;
+    let mut entries = Vec::new();
+    loop {
+        let start = reader.pos;
+        let encoding = reader.byte()?;
+        let entry = match encoding {
+            0xff => return Ok(entries),
+            0x00..=0x7f => i64::from(encoding).to_string().into_bytes(),
+            0x80..=0xbf => reader.take((encoding & 0x3f).into())?.to_vec(),
+            0xc0..=0xdf => {
+                // A 13 bit integer in two's complement.
+                let int = i64::from(encoding & 0x1f) << 8 | i64::from(reader.byte()?);
+                let int = if int >= 1 << 12 { int - (1 << 13) } else { int };
+                int.to_string().into_bytes()
+            }
+            0xe0..=0xef => {
+                let len = usize::from(encoding & 0x0f) << 8 | usize::from(reader.byte()?);
+                reader.take(len)?.to_vec()
+            }
+            0xf0 => {
+                let len = u32::from_le_bytes(reader.array()?);
+                reader.take(len as usize)?.to_vec()
+            }
+            0xf1 => reader.int(2)?.to_string().into_bytes(),
+            0xf2 => reader.int(3)?.to_string().into_bytes(),
+            0xf3 => reader.int(4)?.to_string().into_bytes(),
+            0xf4 => reader.int(8)?.to_string().into_bytes(),
+            _ => return Err(RdbError::InvalidPacked),
+        };
+        reader.take(backlen_len(reader.pos - start))?;
+        entries.push(entry);
+    }
+}
+
+/// Writes entries into a listpack, see `listpack`, integers in the fewest bytes that hold
+/// them.
+#[derive(Debug, Default)]
+pub(super) struct ListpackWriter {
+    body: Vec<u8>,
+    count: usize,
+}
+
+impl ListpackWriter {
+    pub(super) fn int(&mut self, int: i64) -> &mut Self {
+        let mut entry = Vec::with_capacity(9);
+        match int {
+            0..=0x7f => entry.push(int as u8),
+            -0x1000..=0xfff => {
+                let int = int as u16 & 0x1fff;
+                entry.extend_from_slice(&[0xc0 | (int >> 8) as u8, int as u8]);
+            }
+            _ => {
+                let (encoding, len) = match int {
+                    -0x8000..=0x7fff => (0xf1, 2),
+                    -0x80_0000..=0x7f_ffff => (0xf2, 3),
+                    -0x8000_0000..=0x7fff_ffff => (0xf3, 4),
+                    _ => (0xf4, 8),
+                };
+                entry.push(encoding);
+                entry.extend_from_slice(&int.to_le_bytes()[..len]);
+            }
+        }
+        self.entry(entry)
+    }
+
+    pub(super) fn string(&mut self, s: &[u8]) -> &mut Self {
+        let mut entry = Vec::with_capacity(s.len() + 5);
+        match s.len() {
+            len @ 0..=0x3f => entry.push(0x80 | len as u8),
+            len @ 0x40..=0xfff => entry.extend_from_slice(&[0xe0 | (len >> 8) as u8, len as u8]),
+            len => {
+                entry.push(0xf0);
+                entry.extend_from_slice(&(len as u32).to_le_bytes());
+            }
+        }
+        entry.extend_from_slice(s);
+        self.entry(entry)
+    }
+
+    /// Returns the listpack, with its header and terminator.
+    pub(super) fn finish(&self) -> Vec<u8> {
+        let total = 6 + self.body.len() + 1;
+        let mut bytes = Vec::with_capacity(total);
+        bytes.extend_from_slice(&(total as u32).to_le_bytes());
+        // Counts that don't fit are left for readers to count themselves.
+        bytes.extend_from_slice(&u16::try_from(self.count).unwrap_or(u16::MAX).to_le_bytes());
+        bytes.extend_from_slice(&self.body);
+        bytes.push(0xff);
+        bytes
+    }
+
+    /// Appends the encoded entry, followed by its length in 7 bit groups, the highest
+    /// first, with the high bit set on all but the first.
+    fn entry(&mut self, entry: Vec<u8>) -> &mut Self {
+        let len = entry.len();
+        self.body.extend_from_slice(&entry);
+        let groups = backlen_len(len);
+        for group in (0..groups).rev() {
+            let bits = (len >> (7 * group)) as u8 & 0x7f;
+            let first = group + 1 == groups;
+            self.body.push(if first { bits } else { bits | 0x80 });
+        }
+        self.count += 1;
+        self
+    }
+}
+
+/// Returns how many bytes the length of a listpack entry takes after it.
+fn backlen_len(len: usize) -> usize {
+    match len {
+        0..=127 => 1,
+        128..16383 => 2,
+        16383..2_097_151 => 3,
+        2_097_151..268_435_455 => 4,
+        _ => 5,
+    }
+}
+
+/// Returns the integers of an intset.
+///
+/// ```text
+/// <width u32> <count u32> <integer> ...
+/// ```
+///
+/// where every integer takes the width, 2, 4 or 8 bytes.
+pub(super) fn intset(bytes: &[u8]) -> Result<Vec<Vec<u8>>, RdbError> {
+    let mut reader = Packed::new(bytes, 0)?;
+    let width = u32::from_le_bytes(reader.array()?) as usize;
+    if !matches!(width, 2 | 4 | 8) {
+        return Err(RdbError::InvalidPacked);
+    }
+    let count = u32::from_le_bytes(reader.array()?);
+    (0..count)
+        .map(|_| Ok(reader.int(width)?.to_string().into_bytes()))
+        .collect()
+}
+
+/// Reads a packed encoding, failing with `InvalidPacked` rather than running past its end.
+struct Packed<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Packed<'a> {
+    /// Starts reading after the header.
+    fn new(bytes: &'a [u8], header: usize) -> Result<Self, RdbError> {
+        if bytes.len() < header {
+            return Err(RdbError::InvalidPacked);
+        }
+        Ok(Self { bytes, pos: header })
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8], RdbError> {
+        let end = self.pos.checked_add(n).ok_or(RdbError::InvalidPacked)?;
+        let taken = self
+            .bytes
+            .get(self.pos..end)
+            .ok_or(RdbError::InvalidPacked)?;
+        self.pos = end;
+        Ok(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], RdbError> {
+        Ok(self.take(N)?.try_into().expect("Took N bytes"))
+    }
+
+    fn byte(&mut self) -> Result<u8, RdbError> {
+        Ok(self.take(1)?[0])
+    }
+
+    /// Reads a little endian signed integer of `n` bytes.
+    fn int(&mut self, n: usize) -> Result<i64, RdbError> {
+        let mut buf = [0; 8];
+        buf[..n].copy_from_slice(self.take(n)?);
+        // Shifting the bytes to the top and back extends the sign.
+        let shift = 64 - 8 * n as u32;
+        Ok(i64::from_le_bytes(buf) << shift >> shift)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn strings(entries: &[&str]) -> Vec<Vec<u8>> {
+        entries
+            .iter()
+            .map(|entry| entry.as_bytes().to_vec())
+            .collect()
+    }
+
+    #[test]
+    fn decode_ziplist() {
+        let bytes = [
+            b"\x00\x00\x00\x00\x00\x00\x00\x00\x05\x00".as_slice(),
+            // A string, an immediate, and integers of 1, 2 and 3 bytes.
+            b"\x00\x02ab",
+            b"\x04\xf4",
+            b"\x02\xfe\x85",
+            b"\x03\xc0\x39\x30",
+            b"\x04\xf0\xff\xff\xff",
+            b"\xff",
+        ]
+        .concat();
+        assert_eq!(
+            ziplist(&bytes).unwrap(),
+            strings(&["ab", "3", "-123", "12345", "-1"])
+        );
+        assert_eq!(ziplist(&bytes[..12]), Err(RdbError::InvalidPacked));
+    }
+
+    #[test]
+    fn decode_listpack() {
+        let bytes = [
+            b"\x00\x00\x00\x00\x05\x00".as_slice(),
+            // A string, 7 and 13 bit integers, a 12 bit string length and a 16 bit integer.
+            b"\x82ab\x03",
+            b"\x05\x01",
+            b"\xdf\xff\x02",
+            b"\xe0\x01x\x03",
+            b"\xf1\x39\x30\x03",
+            b"\xff",
+        ]
+        .concat();
+        assert_eq!(
+            listpack(&bytes).unwrap(),
+            strings(&["ab", "5", "-1", "x", "12345"])
+        );
+        assert_eq!(
+            listpack(b"\x00\x00\x00\x00\x01\x00\xf5"),
+            Err(RdbError::InvalidPacked)
+        );
+    }
+
+    #[test]
+    fn encode_listpack() {
+        let long = vec![b'x'; 200];
+        let ints = [
+            0,
+            127,
+            -1,
+            4095,
+            -4096,
+            32767,
+            -8_388_608,
+            2_147_483_647,
+            i64::MIN,
+        ];
+        let mut writer = ListpackWriter::default();
+        for int in ints {
+            writer.int(int);
+        }
+        writer.string(b"ab").string(&long);
+        let bytes = writer.finish();
+        assert_eq!(
+            u32::from_le_bytes(bytes[..4].try_into().unwrap()) as usize,
+            bytes.len()
+        );
+        assert_eq!(&bytes[4..6], &[11, 0]);
+
+        let mut expected: Vec<Vec<u8>> = ints.iter().map(|i| i.to_string().into()).collect();
+        expected.extend([b"ab".to_vec(), long]);
+        assert_eq!(listpack(&bytes).unwrap(), expected);
+        // A 13 bit integer, then the 200 byte string with its length in two bytes.
+        assert_eq!(&bytes[10..13], b"\xdf\xff\x02");
+        assert_eq!(&bytes[bytes.len() - 3..], b"\x01\xca\xff");
+    }
+
+    #[test]
+    fn decode_intset() {
+        let bytes = b"\x02\x00\x00\x00\x02\x00\x00\x00\xff\xff\x07\x00";
+        assert_eq!(intset(bytes).unwrap(), strings(&["-1", "7"]));
+        assert_eq!(
+            intset(b"\x03\x00\x00\x00\x00\x00\x00\x00"),
+            Err(RdbError::InvalidPacked)
+        );
+    }
+}
@@ -1,15 +1,35 @@
-use std::net::SocketAddr;
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
 
+use parking_lot::Mutex;
 use thiserror::Error;
-use tokio::net::TcpStream;
+use tokio::sync::watch;
+use tracing::{debug, info, info_span, warn, Instrument};
 
+#[cfg(feature = "tls")]
+use super::tls::{self, TlsError, TlsFiles};
 use super::{
-    client::ClientError,
-    cmd::{ping::PingArg, Ping, ReplConf, ReplConfArg, ReplConfArgConfig},
-    session::Session,
+    client::{ClientError, RedisClient, Resync},
+    cmd::{Command, ReplConf, ReplConfArg, ReplConfArgConfig},
+    config::{ConfigValues, HostPort, ReplDisklessLoad, ServerConfig, DEFAULT_REPLICA_PRIORITY},
+    handler::CommandHandler,
+    rdb::{self, RdbError},
+    replication::ReplicaState,
+    resp::{BulkString, Value},
+    session::{Request, Session, SessionError},
+    TlsConnector,
 };
 
-pub struct Replication {}
+/// How often the processed offset is acknowledged, since a master drops replicas that stay
+/// silent for longer than its `repl-timeout`.
+const ACK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Link of a replica to its master, handshaken and ready to sync.
+pub struct Replication {
+    client: RedisClient,
+}
 
 #[derive(Debug, Error)]
 pub enum ReplicationError {
@@ -19,49 +39,386 @@ pub enum ReplicationError {
     #[error(transparent)]
     Client(#[from] ClientError),
 
+    #[error(transparent)]
+    Session(#[from] SessionError),
+
+    #[error(transparent)]
+    Rdb(#[from] RdbError),
+
+    #[cfg(feature = "tls")]
+    #[error(transparent)]
+    Tls(#[from] TlsError),
+
+    #[error("TLS is not available, rebuild with the `tls` feature")]
+    TlsUnavailable,
+
     #[error(transparent)]
     TokioIo(#[from] tokio::io::Error),
 }
 
 impl Replication {
-    pub async fn init(
-        master_addr: SocketAddr,
+    /// Connects to the master, over TLS if a connector is given, and performs the handshake.
+    /// The master's name is resolved with retries, so it may come up after this server.
+    pub(crate) async fn init(
+        master_addr: HostPort,
         listening_port: u16,
+        priority: Option<u32>,
+        tls: Option<TlsConnector>,
     ) -> Result<Self, ReplicationError> {
-        Self::connect_to_master(master_addr, listening_port).await?;
+        let span = info_span!("replication.init", master = %master_addr);
+        async {
+            let mut client = RedisClient::connect_host(master_addr, tls).await?;
+            Self::handshake(&mut client, listening_port, priority).await?;
+            info!("Completed handshake with master");
 
-        Ok(Self {})
+            Ok(Self { client })
+        }
+        .instrument(span)
+        .await
     }
 
-    async fn connect_to_master(
-        master_addr: SocketAddr,
-        listening_port: u16,
+    /// Connects to the master like `init`, with the port, replica priority and TLS settings
+    /// of the config, for a master followed while serving.
+    pub(crate) async fn connect(
+        master_addr: HostPort,
+        config: &ServerConfig,
+    ) -> Result<Self, ReplicationError> {
+        let (port, priority, tls) = {
+            let config = config.read();
+            let priority = config.replica_priority;
+            let priority = (priority != DEFAULT_REPLICA_PRIORITY).then_some(priority);
+            (config.port, priority, tls_connector(&config)?)
+        };
+        Self::init(master_addr, port, priority, tls).await
+    }
+
+    /// Asks the replica to stop replicating and serve as a master, the last step of a
+    /// FAILOVER before following it.
+    pub(crate) async fn promote(
+        replica_addr: HostPort,
+        config: &ServerConfig,
     ) -> Result<(), ReplicationError> {
-        let stream = TcpStream::connect(master_addr).await?;
-        let mut session = Session::new(stream);
+        let tls = tls_connector(&config.read())?;
+        let mut client = RedisClient::connect_host(replica_addr, tls).await?;
+        client.command(["REPLICAOF", "NO", "ONE"]).await?;
+        Ok(())
+    }
 
+    /// Introduces this server to the master as a replica listening on `listening_port`, with
+    /// the replica priority if given.
+    pub(crate) async fn handshake(
+        client: &mut RedisClient,
+        listening_port: u16,
+        priority: Option<u32>,
+    ) -> Result<(), ReplicationError> {
         // First handshake
         // PING
-        let _ = Ping::client(&mut session)
-            .ping(PingArg { msg: None })
-            .await?;
+        client.ping().await?;
 
         // Second handshake
         // REPLCONF listening-port <PORT>
-        let mut replconf_client = ReplConf::client(&mut session);
-        let _ = replconf_client
+        client
             .replconf(ReplConfArg {
                 config: ReplConfArgConfig::ListeningPort(listening_port),
             })
             .await?;
 
         // REPLCONF capa psync2
-        let _ = replconf_client
+        client
             .replconf(ReplConfArg {
                 config: ReplConfArgConfig::Capabilities("psync2".into()),
             })
             .await?;
 
+        // REPLCONF priority <PRIORITY>, which masters other than this server don't know.
+        if let Some(priority) = priority {
+            let priority = client
+                .replconf(ReplConfArg {
+                    config: ReplConfArgConfig::Priority(priority),
+                })
+                .await;
+            match priority {
+                Ok(()) | Err(ClientError::Server(_) | ClientError::InvalidResponse) => (),
+                Err(e) => return Err(e.into()),
+            }
+        }
+
         Ok(())
     }
+
+    /// Syncs with the master, continuing from where the replica state of the handler is
+    /// if the master still can, then keeps applying what it replicates until it closes the
+    /// link or `stop_rx` changes.
+    pub(crate) async fn run(
+        self,
+        handler: CommandHandler,
+        stop_rx: watch::Receiver<bool>,
+    ) -> Result<(), ReplicationError> {
+        let state = handler.replica().unwrap_or_default();
+        Self::sync(self.client, handler, Some(state), stop_rx).await
+    }
+
+    /// Asks the master to continue from the position of `state`, or for a full resync
+    /// without one, then applies every write it replicates until it closes the link or
+    /// `stop_rx` changes. The position is kept in `state` all along.
+    ///
+    /// A full resync replaces the dataset of a replica with the master's. Without `state`,
+    /// e.g. for an import, it only replaces the keys the master has.
+    pub(crate) async fn sync(
+        mut client: RedisClient,
+        handler: CommandHandler,
+        state: Option<Arc<ReplicaState>>,
+        stop_rx: watch::Receiver<bool>,
+    ) -> Result<(), ReplicationError> {
+        let position = state.as_ref().and_then(|state| state.position());
+        let resync = client
+            .psync(position.as_ref().map(|(id, offset)| (id.as_str(), *offset)))
+            .await?;
+
+        let mut session = client.into_session().expect("Session after command");
+        let offset = match resync {
+            Resync::Continue(repl_id) => {
+                let (_, offset) = position.expect("Continued from a position");
+                info!("Partial resync with replication ID {repl_id} from offset {offset}");
+                if let Some(state) = &state {
+                    state.sync(&repl_id, offset, || ());
+                }
+                offset
+            }
+            Resync::Full(repl_id, offset) => {
+                info!("Full resync with replication ID {repl_id} at offset {offset}");
+                let persistence = handler.persistence();
+                persistence.set_loading(true);
+                let loaded = Self::receive_dataset(&mut session, &handler)
+                    .await
+                    .map(|dataset| match &state {
+                        Some(state) => {
+                            state.sync(&repl_id, offset, || handler.replace(dataset.entries))
+                        }
+                        None => handler.load(dataset.entries),
+                    });
+                persistence.set_loading(false);
+                loaded?;
+                offset
+            }
+        };
+
+        // The link is up from the end of the sync until the stream ends.
+        if let Some(state) = &state {
+            state.set_link_up(true);
+        }
+        let applied = Self::apply_stream(session, handler, state.clone(), offset, stop_rx).await;
+        if let Some(state) = &state {
+            state.set_link_up(false);
+        }
+        applied
+    }
+
+    /// Receives the RDB file of the full resync and parses its keys.
+    async fn receive_dataset(
+        session: &mut Session,
+        handler: &CommandHandler,
+    ) -> Result<rdb::Dataset, ReplicationError> {
+        let rdb = Self::receive_rdb(session, handler).await?;
+        let dataset = rdb::parse(&rdb, handler.clock().now())?;
+        info!(
+            "Loaded {} keys from the master, skipped {}",
+            dataset.entries.len(),
+            dataset.skipped
+        );
+        Ok(dataset)
+    }
+
+    /// Receives the RDB file of the full resync, through `dbfilename` unless
+    /// repl-diskless-load says to load it straight from the link. Either way it is parsed
+    /// in full before any key is loaded, so a transfer that fails leaves the keys as they
+    /// were.
+    async fn receive_rdb(
+        session: &mut Session,
+        handler: &CommandHandler,
+    ) -> Result<Vec<u8>, ReplicationError> {
+        let (mode, path) = {
+            let config = handler.config();
+            let config = config.read();
+            (
+                config.repl_diskless_load,
+                config.dir.join(&config.dbfilename),
+            )
+        };
+        let diskless = match mode {
+            ReplDisklessLoad::Disabled => false,
+            ReplDisklessLoad::OnEmptyDb => handler.store().is_empty(),
+            ReplDisklessLoad::Swapdb => true,
+        };
+        if diskless {
+            debug!("Loading the RDB file from the link");
+            return Ok(session.receive_payload().await?);
+        }
+        Self::receive_rdb_file(session, &path).await?;
+        Ok(tokio::fs::read(&path).await?)
+    }
+
+    /// Writes the RDB file to a temporary file next to `path`, renamed to `path` once
+    /// complete so a partial transfer never replaces the previous file.
+    async fn receive_rdb_file(session: &mut Session, path: &Path) -> Result<(), ReplicationError> {
+        let temp = path.with_file_name(format!("temp-{}.rdb", std::process::id()));
+        let mut file = tokio::fs::File::create(&temp).await?;
+        let received = match session.receive_payload_to(&mut file).await {
+            Ok(len) => file.sync_all().await.map(|_| len).map_err(Into::into),
+            Err(e) => Err(e),
+        };
+        drop(file);
+        match received {
+            Ok(len) => {
+                tokio::fs::rename(&temp, path).await?;
+                debug!("Received {len} bytes of RDB file into {}", path.display());
+                Ok(())
+            }
+            Err(e) => {
+                let _ = tokio::fs::remove_file(&temp).await;
+                Err(e.into())
+            }
+        }
+    }
+
+    /// Runs the writes the master replicates, acknowledging the offset they reach, and
+    /// recording it in `state` if given.
+    async fn apply_stream(
+        mut session: Session,
+        mut handler: CommandHandler,
+        state: Option<Arc<ReplicaState>>,
+        mut offset: u64,
+        mut stop_rx: watch::Receiver<bool>,
+    ) -> Result<(), ReplicationError> {
+        // Commands run as the master client, which neither the ACL nor maxmemory refuse.
+        let master = handler.clients().register(None, None);
+        master.state().lock().flags.master = true;
+        let mut db = 0;
+        let mut warned = HashSet::new();
+        let mut ack = tokio::time::interval(ACK_INTERVAL);
+        loop {
+            let (resp, len) = tokio::select! {
+                resp = session.receive_response_with_len() => match resp {
+                    Ok(resp) => resp,
+                    Err(SessionError::NoResponse) => {
+                        info!("Master closed the link at offset {offset}");
+                        return Ok(());
+                    }
+                    Err(e) => return Err(e.into()),
+                },
+                _ = ack.tick() => {
+                    session.send_request(Self::ack(offset)).await?;
+                    continue;
+                }
+                _ = stop_rx.changed() => return Ok(()),
+            };
+
+            let value = Value::from(resp);
+            // Enough of the request to tell the ones handled here apart.
+            let args: Vec<String> = value
+                .array()
+                .and_then(|array| array.values())
+                .unwrap_or_default()
+                .iter()
+                .take(3)
+                .map(|arg| {
+                    let arg = arg.bulk_string().and_then(BulkString::as_bytes);
+                    String::from_utf8_lossy(arg.unwrap_or_default()).to_lowercase()
+                })
+                .collect();
+            // Returns whether the master asked for an acknowledgement.
+            let apply = || match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+                // Only GETACK is sent by masters once synced.
+                ["replconf", ..] => matches!(
+                    handler.parse(&value.into()),
+                    Ok(Command::ReplConf(ReplConfArg {
+                        config: ReplConfArgConfig::GetAck,
+                    }))
+                ),
+                ["select", index] => {
+                    db = index.parse().unwrap_or(db);
+                    false
+                }
+                // Only database 0 is served.
+                _ if db != 0 => false,
+                _ => match handler.parse(&value.into()) {
+                    Ok(cmd) => {
+                        let client = master.state();
+                        let result = handler.handle(cmd, &mut client.lock());
+                        if let Err(e) = result {
+                            debug!("Error applying command from master: {e}");
+                        }
+                        handler.deliver_invalidations(master.id());
+                        false
+                    }
+                    Err(e) => {
+                        let name = args.first().cloned().unwrap_or_default();
+                        if warned.insert(name.clone()) {
+                            warn!("Skipping '{name}' commands from master: {e}");
+                        }
+                        false
+                    }
+                },
+            };
+            // GETACK is answered with the offset before it, like Redis, and counted after.
+            let before = offset;
+            offset += len as u64;
+            let getack = match &state {
+                Some(state) => state.apply(offset, apply),
+                None => apply(),
+            };
+            if getack {
+                session.send_request(Self::ack(before)).await?;
+            }
+        }
+    }
+
+    fn ack(offset: u64) -> Request {
+        ReplConf::command_value(ReplConfArg {
+            config: ReplConfArgConfig::Ack(offset),
+        })
+        .into()
+    }
+}
+
+/// Stops the link a replica runs to its master, so that REPLICAOF and FAILOVER can replace it
+/// with a link to another master or end it.
+#[derive(Debug, Default)]
+pub struct MasterLink {
+    stop: Mutex<Option<watch::Sender<bool>>>,
+}
+
+impl MasterLink {
+    /// Stops the running link, if any, returning what stops the next one.
+    pub fn replace(&self) -> watch::Receiver<bool> {
+        let (stop_tx, stop_rx) = watch::channel(false);
+        if let Some(running) = self.stop.lock().replace(stop_tx) {
+            let _ = running.send(true);
+        }
+        stop_rx
+    }
+
+    /// Stops the running link, if any.
+    pub fn stop(&self) {
+        if let Some(running) = self.stop.lock().take() {
+            let _ = running.send(true);
+        }
+    }
+}
+
+/// Returns the connector for links to other servers if `tls-replication` is on.
+#[cfg(feature = "tls")]
+fn tls_connector(config: &ConfigValues) -> Result<Option<TlsConnector>, ReplicationError> {
+    if !config.tls_replication {
+        return Ok(None);
+    }
+    Ok(Some(tls::client_connector(TlsFiles::from_values(config))?))
+}
+
+#[cfg(not(feature = "tls"))]
+fn tls_connector(config: &ConfigValues) -> Result<Option<TlsConnector>, ReplicationError> {
+    if config.tls_replication {
+        return Err(ReplicationError::TlsUnavailable);
+    }
+    Ok(None)
 }
@@ -0,0 +1,515 @@
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use parking_lot::{Mutex, MutexGuard, RwLock};
+use tokio::sync::{watch, Notify};
+
+use super::clients::ClientLink;
+use super::reply::Reply;
+use super::resp::Value;
+use super::store::{Snapshot, Store};
+use super::util;
+
+/// Length of a replication ID.
+const REPL_ID_LEN: usize = 40;
+
+/// Bytes of the replication stream kept for replicas to continue from, the default of
+/// `repl-backlog-size`.
+const BACKLOG_SIZE: usize = 1024 * 1024;
+
+/// Replication state of a master: the ID of the history of its dataset, how far into its
+/// replication stream it is, and the replicas the stream is sent to. Replicas sync from it
+/// with PSYNC, which gives them the ID and offset along with the dataset.
+#[derive(Debug)]
+pub struct ReplicationState {
+    repl_id: String,
+
+    /// Bytes of the replication stream produced so far, only changed while holding
+    /// `stream`.
+    offset: AtomicU64,
+
+    /// Whether a replica ever attached. Writes are only propagated, and kept in the backlog,
+    /// from then on, like Redis only keeps a backlog once it has replicas.
+    streaming: AtomicBool,
+
+    stream: Mutex<Stream>,
+
+    /// Woken whenever a replica acknowledges an offset.
+    acked: Notify,
+
+    /// Progress of the FAILOVER handing the master role over, if any. Writes are paused
+    /// until it ends.
+    failover: watch::Sender<FailoverState>,
+}
+
+/// Progress of a FAILOVER, reported by INFO as `master_failover_state`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FailoverState {
+    #[default]
+    NoFailover,
+
+    /// Waiting for the target replica to acknowledge every write.
+    WaitingForSync,
+
+    /// Promoting the target replica, then following it.
+    InProgress,
+}
+
+impl FailoverState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::NoFailover => "no-failover",
+            Self::WaitingForSync => "waiting-for-sync",
+            Self::InProgress => "failover-in-progress",
+        }
+    }
+}
+
+/// Replicas attached to the stream, by client id, and the last `BACKLOG_SIZE` bytes of the
+/// stream, which end at the current offset.
+#[derive(Debug, Default)]
+struct Stream {
+    replicas: HashMap<u64, Replica>,
+    backlog: VecDeque<u8>,
+}
+
+#[derive(Debug)]
+struct Replica {
+    link: ClientLink,
+
+    /// Address the replica listens to, `None` if it didn't tell its port.
+    addr: Option<SocketAddr>,
+
+    /// Last offset the replica acknowledged with `REPLCONF ACK`.
+    ack_offset: u64,
+}
+
+impl Default for ReplicationState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ReplicationState {
+    /// Starts a new history with a random ID, at offset 0.
+    pub fn new() -> Self {
+        Self {
+            repl_id: util::generate_random_alphanumeric_string(REPL_ID_LEN),
+            offset: AtomicU64::new(0),
+            streaming: AtomicBool::new(false),
+            stream: Mutex::new(Stream::default()),
+            acked: Notify::new(),
+            failover: watch::Sender::new(FailoverState::default()),
+        }
+    }
+
+    /// Continues the history of the master this server replicated up to `offset`, once
+    /// promoted, so that replicas at the same position can continue from there.
+    pub fn continue_from(repl_id: String, offset: u64) -> Self {
+        Self {
+            repl_id,
+            offset: AtomicU64::new(offset),
+            ..Self::new()
+        }
+    }
+
+    pub fn repl_id(&self) -> &str {
+        &self.repl_id
+    }
+
+    pub fn offset(&self) -> u64 {
+        self.offset.load(Ordering::Acquire)
+    }
+
+    /// Returns whether writes are propagated, i.e. a replica attached at some point.
+    pub fn is_streaming(&self) -> bool {
+        self.streaming.load(Ordering::Acquire)
+    }
+
+    /// Locks the stream. Holding it while a write runs keeps other writes from being
+    /// propagated in between, so replicas apply writes in the order they ran.
+    pub fn stream(&self) -> StreamGuard<'_> {
+        StreamGuard {
+            state: self,
+            stream: self.stream.lock(),
+        }
+    }
+
+    /// Attaches a replica listening to `addr` to the stream, first sending it what `sync`
+    /// returns for the stream as it is, e.g. the dataset at the current offset or the backlog
+    /// past the replica's. Nothing is propagated in between. Returns false if the replica is
+    /// gone.
+    pub fn attach(
+        &self,
+        link: ClientLink,
+        addr: Option<SocketAddr>,
+        sync: impl FnOnce(&StreamGuard) -> Reply,
+    ) -> bool {
+        let mut stream = self.stream();
+        if !link.send(sync(&stream)) {
+            return false;
+        }
+        // The replica has the dataset up to the offset it syncs at.
+        let offset = stream.offset();
+        stream.stream.replicas.insert(
+            link.id(),
+            Replica {
+                link,
+                addr,
+                ack_offset: offset,
+            },
+        );
+        self.streaming.store(true, Ordering::Release);
+        true
+    }
+
+    /// Records the offset a replica acknowledged, waking whoever waits for it.
+    pub fn ack(&self, id: u64, offset: u64) {
+        if let Some(replica) = self.stream().stream.replicas.get_mut(&id) {
+            replica.ack_offset = replica.ack_offset.max(offset);
+        }
+        self.acked.notify_waiters();
+    }
+
+    /// Waits until `numreplicas` replicas acknowledged `offset`, or until the timeout if
+    /// any. Returns how many did.
+    pub async fn wait(&self, numreplicas: usize, offset: u64, timeout: Option<Duration>) -> usize {
+        let acked = self.wait_until(timeout, |stream| {
+            let count = stream.acked(offset);
+            (count >= numreplicas).then_some(count)
+        });
+        match acked.await {
+            Some(count) => count,
+            None => self.stream().acked(offset),
+        }
+    }
+
+    /// Waits until the replica listening to `target`, or any replica that told its address
+    /// if `None`, acknowledged `offset`. Returns the address of the replica, or `None` once
+    /// the timeout passed.
+    pub async fn wait_for_replica(
+        &self,
+        target: Option<SocketAddr>,
+        offset: u64,
+        timeout: Option<Duration>,
+    ) -> Option<SocketAddr> {
+        self.wait_until(timeout, |stream| stream.acked_replica(target, offset))
+            .await
+    }
+
+    /// Waits until `check` returns a value for the stream, checking it again whenever a
+    /// replica acknowledges an offset. Returns `None` once the timeout passed.
+    async fn wait_until<T>(
+        &self,
+        timeout: Option<Duration>,
+        check: impl Fn(&StreamGuard) -> Option<T>,
+    ) -> Option<T> {
+        let deadline = timeout.map(|timeout| tokio::time::Instant::now() + timeout);
+        loop {
+            // Listen before checking, so that an ack in between isn't missed.
+            let acked = self.acked.notified();
+            tokio::pin!(acked);
+            acked.as_mut().enable();
+            if let Some(value) = check(&self.stream()) {
+                return Some(value);
+            }
+            match deadline {
+                Some(deadline) => tokio::select! {
+                    _ = acked => (),
+                    _ = tokio::time::sleep_until(deadline) => return check(&self.stream()),
+                },
+                None => acked.await,
+            }
+        }
+    }
+
+    pub fn failover_state(&self) -> FailoverState {
+        *self.failover.borrow()
+    }
+
+    /// Moves the failover on from `from` to `to`, e.g. from `NoFailover` to start one, which
+    /// pauses writes until it is back to `NoFailover`. Returns false if it wasn't at `from`,
+    /// e.g. because it was aborted meanwhile.
+    pub fn switch_failover(&self, from: FailoverState, to: FailoverState) -> bool {
+        self.failover.send_if_modified(|state| {
+            let switch = *state == from;
+            if switch {
+                *state = to;
+            }
+            switch
+        })
+    }
+
+    /// Waits until no failover is in progress, e.g. for a paused write to run.
+    pub async fn failover_ended(&self) {
+        let mut state = self.failover.subscribe();
+        let _ = state
+            .wait_for(|state| *state == FailoverState::NoFailover)
+            .await;
+    }
+
+    /// Detaches every replica, closing its connection, e.g. once this server stops being a
+    /// master.
+    pub fn detach_all(&self) {
+        for (_, replica) in self.stream().stream.replicas.drain() {
+            replica.link.close();
+        }
+    }
+}
+
+/// Where a replica is in the replication stream of its master: the master's replication ID
+/// and the offset its writes were applied up to. RDB files record it, so that the replica
+/// can ask to continue from there once restarted.
+#[derive(Debug, Default)]
+pub struct ReplicaState {
+    position: Mutex<Option<(String, u64)>>,
+
+    /// Whether the link to the master is synced and streaming, reported by health probes.
+    link_up: AtomicBool,
+}
+
+impl ReplicaState {
+    /// Starts at the position of a dataset loaded from an RDB file, if it has one.
+    pub fn new(position: Option<(String, u64)>) -> Self {
+        Self {
+            position: Mutex::new(position),
+            link_up: AtomicBool::new(false),
+        }
+    }
+
+    pub fn set_link_up(&self, up: bool) {
+        self.link_up.store(up, Ordering::Release);
+    }
+
+    pub fn link_up(&self) -> bool {
+        self.link_up.load(Ordering::Acquire)
+    }
+
+    pub fn position(&self) -> Option<(String, u64)> {
+        self.position.lock().clone()
+    }
+
+    /// Runs `load` to sync with the master, e.g. loading its dataset, then records the
+    /// position the dataset is at. Saves wait meanwhile, like for `apply`.
+    pub fn sync<T>(&self, repl_id: &str, offset: u64, load: impl FnOnce() -> T) -> T {
+        let mut position = self.position.lock();
+        let loaded = load();
+        *position = Some((repl_id.to_string(), offset));
+        loaded
+    }
+
+    /// Runs a write from the master, then records the offset its stream reached. Saves wait
+    /// meanwhile, so the offset they record always matches the dataset.
+    pub fn apply<T>(&self, offset: u64, write: impl FnOnce() -> T) -> T {
+        let mut position = self.position.lock();
+        let applied = write();
+        if let Some((_, position)) = position.as_mut() {
+            *position = offset;
+        }
+        applied
+    }
+}
+
+/// The side of replication a server is on, which decides the replication ID and offset its
+/// RDB files record.
+#[derive(Debug, Clone)]
+pub enum Role {
+    Master(Arc<ReplicationState>),
+    Replica(Arc<ReplicaState>),
+}
+
+impl Role {
+    /// Takes a snapshot of the store along with the replication ID and offset it reflects:
+    /// those of this master's stream, or of its master's for a replica. Writes wait
+    /// meanwhile, so the two always match.
+    pub fn snapshot(&self, store: &Store) -> (Snapshot, Option<(String, u64)>) {
+        match self {
+            Self::Master(state) => {
+                let stream = state.stream();
+                let snapshot = store.snapshot();
+                (snapshot, Some((state.repl_id.clone(), stream.offset())))
+            }
+            Self::Replica(state) => {
+                let position = state.position.lock();
+                (store.snapshot(), position.clone())
+            }
+        }
+    }
+}
+
+/// The role of a server, shared by its command handlers so that REPLICAOF and FAILOVER can
+/// switch it while serving. Handlers outside a server may have none.
+#[derive(Debug, Clone, Default)]
+pub struct SharedRole(Arc<RwLock<Option<Role>>>);
+
+impl SharedRole {
+    pub fn new(role: Option<Role>) -> Self {
+        Self(Arc::new(RwLock::new(role)))
+    }
+
+    pub fn get(&self) -> Option<Role> {
+        self.0.read().clone()
+    }
+
+    /// Switches to `role`, returning the previous one.
+    pub fn replace(&self, role: Role) -> Option<Role> {
+        self.0.write().replace(role)
+    }
+
+    /// Returns the replication state if this server is a master.
+    pub fn replication(&self) -> Option<Arc<ReplicationState>> {
+        match &*self.0.read() {
+            Some(Role::Master(state)) => Some(state.clone()),
+            _ => None,
+        }
+    }
+
+    /// Returns where this server is in its master's stream if it is a replica.
+    pub fn replica(&self) -> Option<Arc<ReplicaState>> {
+        match &*self.0.read() {
+            Some(Role::Replica(state)) => Some(state.clone()),
+            _ => None,
+        }
+    }
+}
+
+/// The replication stream, locked with `ReplicationState::stream`.
+pub struct StreamGuard<'a> {
+    state: &'a ReplicationState,
+    stream: MutexGuard<'a, Stream>,
+}
+
+impl StreamGuard<'_> {
+    pub fn offset(&self) -> u64 {
+        self.state.offset()
+    }
+
+    /// Sends the command to every replica, advancing the offset by its encoded length.
+    /// Replicas that are gone or can't keep up are detached. Nothing is propagated until a
+    /// replica attached.
+    pub fn propagate(&mut self, value: &Value) {
+        if !self.state.is_streaming() {
+            return;
+        }
+        let mut buf = Vec::new();
+        if value.encode(&mut buf).is_err() {
+            return;
+        }
+        self.state
+            .offset
+            .fetch_add(buf.len() as u64, Ordering::AcqRel);
+        let backlog = &mut self.stream.backlog;
+        backlog.extend(&buf);
+        backlog.drain(..backlog.len().saturating_sub(BACKLOG_SIZE));
+        self.stream
+            .replicas
+            .retain(|_, replica| replica.link.send(value.clone().into()));
+    }
+
+    /// Returns the stream past `offset`, for a replica that has it up to there to continue
+    /// from, or `None` if the backlog doesn't reach back to it.
+    pub fn backlog(&self, offset: u64) -> Option<Vec<u8>> {
+        let backlog = &self.stream.backlog;
+        let start = self.offset().checked_sub(offset)?;
+        let start = backlog.len().checked_sub(usize::try_from(start).ok()?)?;
+        Some(backlog.range(start..).copied().collect())
+    }
+
+    /// Returns how many connected replicas acknowledged the offset.
+    pub fn acked(&self, offset: u64) -> usize {
+        self.stream
+            .replicas
+            .values()
+            .filter(|replica| replica.ack_offset >= offset && !replica.link.is_closed())
+            .count()
+    }
+
+    /// Returns the address of a connected replica that acknowledged the offset, the one
+    /// listening to `target` if given.
+    pub fn acked_replica(&self, target: Option<SocketAddr>, offset: u64) -> Option<SocketAddr> {
+        self.stream
+            .replicas
+            .values()
+            .filter(|replica| replica.ack_offset >= offset && !replica.link.is_closed())
+            .filter_map(|replica| replica.addr)
+            .find(|addr| target.is_none_or(|target| target == *addr))
+    }
+
+    /// Returns whether a connected replica listens to `addr`.
+    pub fn has_replica(&self, addr: SocketAddr) -> bool {
+        self.stream
+            .replicas
+            .values()
+            .any(|replica| replica.addr == Some(addr) && !replica.link.is_closed())
+    }
+
+    /// Returns the address and the last acknowledged offset of every connected replica that
+    /// told the port it listens to.
+    pub fn replica_acks(&self) -> Vec<(SocketAddr, u64)> {
+        self.stream
+            .replicas
+            .values()
+            .filter(|replica| !replica.link.is_closed())
+            .filter_map(|replica| Some((replica.addr?, replica.ack_offset)))
+            .collect()
+    }
+
+    /// Returns how many replicas are attached.
+    pub fn replicas(&self) -> usize {
+        self.stream.replicas.len()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use super::super::clients::ClientRegistry;
+    use super::*;
+
+    #[tokio::test]
+    async fn propagate_and_wait_for_acks() {
+        let registry = Arc::new(ClientRegistry::new());
+        let mut replica = registry.register(None, None);
+        let mut rx = replica.take_receiver().unwrap();
+        let link = replica.state().lock().link().unwrap();
+
+        let state = ReplicationState::new();
+        assert!(!state.is_streaming());
+        assert!(state.attach(link, None, |stream| {
+            Value::Integer((stream.offset() as i64).into()).into()
+        }));
+        assert!(state.is_streaming());
+
+        let ping = Value::Array(vec![Value::BulkString("PING".into())].into());
+        state.stream().propagate(&ping);
+        assert_eq!(state.offset(), 14);
+        assert_eq!(
+            state.stream().backlog(0),
+            Some(b"*1\r\n$4\r\nPING\r\n".to_vec())
+        );
+        assert_eq!(state.stream().backlog(12), Some(b"\r\n".to_vec()));
+        assert_eq!(state.stream().backlog(14), Some(Vec::new()));
+        assert_eq!(state.stream().backlog(15), None);
+        assert!(matches!(
+            rx.recv().await,
+            Some(Reply::Value(Value::Integer(_)))
+        ));
+        assert!(matches!(rx.recv().await, Some(Reply::Value(value)) if value == ping));
+
+        let timeout = Some(Duration::from_millis(10));
+        assert_eq!(state.wait(1, 14, timeout).await, 0);
+        state.ack(replica.id(), 14);
+        assert_eq!(state.wait(1, 14, timeout).await, 1);
+
+        // A replica that disconnected no longer counts, and is detached.
+        drop(rx);
+        assert_eq!(state.wait(1, 14, timeout).await, 0);
+        let mut stream = state.stream();
+        stream.propagate(&ping);
+        assert_eq!(stream.replicas(), 0);
+    }
+}
@@ -0,0 +1,189 @@
+use std::future::Future;
+use std::pin::Pin;
+
+use super::resp::{Array, BulkString, EncodeError, Map, Set, Sink, Value};
+
+/// A reply queued for a connection.
+#[derive(Debug)]
+pub enum Reply {
+    Value(Value),
+    Stream(StreamedArray),
+
+    /// A value followed by a payload sent like a bulk string but without the trailing CRLF,
+    /// which is how a master sends its RDB file after `FULLRESYNC`.
+    Payload(Value, Vec<u8>),
+
+    /// A value followed by bytes of the replication stream written as they are, which is how
+    /// a master sends what a replica missed after `CONTINUE`.
+    Backlog(Value, Vec<u8>),
+
+    /// Nothing is written, for commands that get no reply like a replica's acknowledgements.
+    Nothing,
+
+    /// A value only known later, like how many replicas acknowledged writes for WAIT. The
+    /// connection waits for it before reading its next request, and queues the value.
+    Deferred(Deferred),
+}
+
+impl Reply {
+    /// Returns the reply as a single value, generating every element of a streamed array.
+    /// A payload or backlog isn't a value, so it is left out, and no reply is a null bulk string. A
+    /// deferred value can't be waited for here, so it is a null bulk string too.
+    pub fn into_value(self) -> Value {
+        match self {
+            Self::Value(value) | Self::Payload(value, _) | Self::Backlog(value, _) => value,
+            Self::Stream(stream) => match stream.kind {
+                Aggregate::Array => Value::Array(Array::new(stream.items.collect())),
+                Aggregate::Map => {
+                    let mut items = stream.items;
+                    let pairs = std::iter::from_fn(|| Some((items.next()?, items.next()?)));
+                    Value::Map(Map::new(pairs.collect()))
+                }
+                Aggregate::Set => Value::Set(Set::new(stream.items.collect())),
+            },
+            Self::Nothing | Self::Deferred(_) => Value::BulkString(BulkString::null()),
+        }
+    }
+
+    /// Converts the values of the reply for a connection speaking RESP version `protover`,
+    /// see `Value::into_protocol`.
+    pub fn into_protocol(self, protover: u8) -> Self {
+        match self {
+            Self::Value(value) => Self::Value(value.into_protocol(protover)),
+            Self::Stream(stream) => Self::Stream(stream.into_protocol(protover)),
+            Self::Deferred(deferred) => Self::Deferred(Deferred::new(async move {
+                deferred.value().await.into_protocol(protover)
+            })),
+            // Only replicas get payloads and backlogs, always in RESP2.
+            Self::Payload(..) | Self::Backlog(..) | Self::Nothing => self,
+        }
+    }
+}
+
+/// A value being waited for, see `Reply::Deferred`.
+pub struct Deferred(Pin<Box<dyn Future<Output = Value> + Send>>);
+
+impl Deferred {
+    pub fn new(value: impl Future<Output = Value> + Send + 'static) -> Self {
+        Self(Box::pin(value))
+    }
+
+    /// Waits for the value.
+    pub async fn value(self) -> Value {
+        self.0.await
+    }
+}
+
+impl std::fmt::Debug for Deferred {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Deferred").finish_non_exhaustive()
+    }
+}
+
+impl From<Value> for Reply {
+    fn from(value: Value) -> Self {
+        Self::Value(value)
+    }
+}
+
+impl From<StreamedArray> for Reply {
+    fn from(stream: StreamedArray) -> Self {
+        Self::Stream(stream)
+    }
+}
+
+/// What a streamed array is sent as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Aggregate {
+    Array,
+    Map,
+    Set,
+}
+
+/// An array whose elements are generated while it is written, so that replies like the
+/// whole keyspace are sent in chunks instead of being held in memory at once. It may also
+/// be a RESP3 map, the elements then being each key followed by its value, or a RESP3 set.
+///
+/// The length goes out first, so the generator must yield exactly `len` elements, or
+/// `len` pairs of them for a map.
+pub struct StreamedArray {
+    len: usize,
+    kind: Aggregate,
+    items: Box<dyn Iterator<Item = Value> + Send>,
+}
+
+impl StreamedArray {
+    pub fn new(len: usize, items: impl Iterator<Item = Value> + Send + 'static) -> Self {
+        Self {
+            len,
+            kind: Aggregate::Array,
+            items: Box::new(items.take(len)),
+        }
+    }
+
+    /// Returns a map of `len` pairs, the generator yielding each key followed by its value.
+    pub fn map(len: usize, items: impl Iterator<Item = Value> + Send + 'static) -> Self {
+        Self {
+            len,
+            kind: Aggregate::Map,
+            items: Box::new(items.take(len * 2)),
+        }
+    }
+
+    /// Returns a set of `len` elements.
+    pub fn set(len: usize, items: impl Iterator<Item = Value> + Send + 'static) -> Self {
+        Self {
+            kind: Aggregate::Set,
+            ..Self::new(len, items)
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Encodes the array header, to be followed by every element.
+    pub fn encode_header(&self, buf: &mut impl Sink) -> Result<(), EncodeError> {
+        let kind = match self.kind {
+            Aggregate::Array => '*',
+            Aggregate::Map => '%',
+            Aggregate::Set => '~',
+        };
+        write!(buf, "{kind}{}\r\n", self.len)?;
+        Ok(())
+    }
+
+    /// Converts the elements for a connection speaking RESP version `protover` as they are
+    /// generated. RESP2 has neither maps nor sets, so a map is sent as an array of every
+    /// key followed by its value and a set as an array.
+    pub fn into_protocol(self, protover: u8) -> Self {
+        let (len, kind) = match self.kind {
+            Aggregate::Map if protover < 3 => (self.len * 2, Aggregate::Array),
+            Aggregate::Set if protover < 3 => (self.len, Aggregate::Array),
+            kind => (self.len, kind),
+        };
+        Self {
+            len,
+            kind,
+            items: Box::new(self.items.map(move |value| value.into_protocol(protover))),
+        }
+    }
+
+    /// Returns the next element, which is only generated now.
+    pub fn next_item(&mut self) -> Option<Value> {
+        self.items.next()
+    }
+}
+
+impl std::fmt::Debug for StreamedArray {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StreamedArray")
+            .field("len", &self.len)
+            .field("kind", &self.kind)
+            .finish_non_exhaustive()
+    }
+}
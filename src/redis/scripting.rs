@@ -0,0 +1,575 @@
+//! Lua scripts run by EVAL and the function libraries run by FCALL.
+//!
+//! Every call runs in a fresh Lua state, which only has the table, string, math and utf8
+//! libraries, and reaches the server through `redis.call` and `redis.pcall`.
+
+use std::collections::HashMap;
+
+use mlua::{Function, IntoLuaMulti, Lua, LuaOptions, StdLib, Table, Value as LuaValue, Variadic};
+use thiserror::Error;
+
+use super::resp::{BulkString, Integer, SimpleError, SimpleString, Value};
+
+/// Key of the Lua registry table `redis.register_function` adds functions to.
+const REGISTERED_FUNCTIONS: &str = "registered_functions";
+
+/// The parts of the `redis` library written in Lua. `redis.call` raises the errors that
+/// `redis.pcall` returns.
+const PRELUDE: &str = r#"
+function redis.call(...)
+    local reply = redis.pcall(...)
+    if type(reply) == "table" and reply.err then
+        error(reply, 0)
+    end
+    return reply
+end
+
+function redis.status_reply(status)
+    return { ok = status }
+end
+
+function redis.error_reply(err)
+    return { err = err }
+end
+"#;
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ScriptError {
+    #[error("Error compiling script: {0}")]
+    Compile(String),
+
+    /// Error raised by the script, replied to the client as is since it has its own code.
+    #[error("{0}")]
+    Raised(String),
+
+    #[error("This Redis command is not allowed from script")]
+    NotAllowed,
+
+    #[error("Write commands are not allowed from read-only scripts.")]
+    WriteFromReadOnly,
+
+    #[error("Can not execute a script with write flag using *_ro command.")]
+    WriteFunction,
+
+    #[error("Function not found")]
+    NoSuchFunction,
+
+    #[error("Library not found")]
+    NoSuchLibrary,
+
+    #[error("Library '{0}' already exists")]
+    LibraryExists(String),
+
+    #[error("Function {0} already exists")]
+    FunctionExists(String),
+
+    #[error("Missing library metadata")]
+    MissingMetadata,
+
+    #[error("No functions registered")]
+    NoFunctions,
+}
+
+/// Runs a command called by a script, refusing writes if the script is read-only, and
+/// replies errors as `Value::SimpleError` like a client would see them.
+pub type ScriptCall<'a> = dyn FnMut(Vec<BulkString>, bool) -> Value + 'a;
+
+/// Libraries loaded with FUNCTION LOAD, shared by every connection.
+#[derive(Debug, Default)]
+pub struct Functions {
+    libraries: HashMap<String, Library>,
+}
+
+#[derive(Debug, Clone)]
+struct Library {
+    code: Vec<u8>,
+
+    /// Functions the library registers, and whether they are flagged `no-writes`.
+    functions: HashMap<String, bool>,
+}
+
+/// A function registered by a library.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoadedFunction {
+    /// Code of the library, which is run again to call the function.
+    pub code: Vec<u8>,
+
+    /// Whether the function is flagged `no-writes`, so that FCALL_RO may call it.
+    pub no_writes: bool,
+}
+
+impl Functions {
+    /// Loads the library, whose code starts with `#!lua name=<library>`, returning its name.
+    /// Only a library of that name is replaced, and only with `replace`.
+    pub fn load(&mut self, code: &[u8], replace: bool) -> Result<String, ScriptError> {
+        let name = library_name(code)?;
+        if !replace && self.libraries.contains_key(&name) {
+            return Err(ScriptError::LibraryExists(name));
+        }
+
+        let functions = register_functions(code)?;
+        if functions.is_empty() {
+            return Err(ScriptError::NoFunctions);
+        }
+        for function in functions.keys() {
+            let exists = self.libraries.iter().any(|(library, loaded)| {
+                *library != name && loaded.functions.contains_key(function)
+            });
+            if exists {
+                return Err(ScriptError::FunctionExists(function.clone()));
+            }
+        }
+
+        let library = Library {
+            code: code.to_vec(),
+            functions,
+        };
+        self.libraries.insert(name.clone(), library);
+        Ok(name)
+    }
+
+    pub fn delete(&mut self, library: &str) -> Result<(), ScriptError> {
+        self.libraries
+            .remove(library)
+            .map(|_| ())
+            .ok_or(ScriptError::NoSuchLibrary)
+    }
+
+    pub fn flush(&mut self) {
+        self.libraries.clear();
+    }
+
+    /// Returns the function registered by one of the libraries.
+    pub fn function(&self, name: &str) -> Option<LoadedFunction> {
+        self.libraries.values().find_map(|library| {
+            let no_writes = *library.functions.get(name)?;
+            Some(LoadedFunction {
+                code: library.code.clone(),
+                no_writes,
+            })
+        })
+    }
+}
+
+/// Runs the script with the keys in `KEYS` and the other arguments in `ARGV`, replying what
+/// it returns.
+pub fn eval(
+    script: &[u8],
+    keys: &[BulkString],
+    args: &[BulkString],
+    read_only: bool,
+    call: &mut ScriptCall,
+) -> Result<Value, ScriptError> {
+    with_lua(read_only, call, |lua| {
+        let globals = lua.globals();
+        globals.set("KEYS", strings(lua, keys)?)?;
+        globals.set("ARGV", strings(lua, args)?)?;
+        match lua.load(script).set_name("=user_script").into_function() {
+            Ok(script) => protected_call(lua, script, ()),
+            Err(e) => Ok(Err(ScriptError::Compile(error_message(&e)))),
+        }
+    })?
+}
+
+/// Runs the library, then calls its function with the keys and the other arguments.
+pub fn fcall(
+    code: &[u8],
+    function: &str,
+    keys: &[BulkString],
+    args: &[BulkString],
+    read_only: bool,
+    call: &mut ScriptCall,
+) -> Result<Value, ScriptError> {
+    with_lua(read_only, call, |lua| {
+        let functions = match load_library(lua, code)? {
+            Ok(functions) => functions,
+            Err(e) => return Ok(Err(e)),
+        };
+        let Some(function) = functions.get::<_, Option<Table>>(function)? else {
+            return Ok(Err(ScriptError::NoSuchFunction));
+        };
+        let callback: Function = function.get("callback")?;
+        protected_call(lua, callback, (strings(lua, keys)?, strings(lua, args)?))
+    })?
+}
+
+/// Runs the library, returning the functions it registers and whether they are flagged
+/// `no-writes`. Libraries may not call commands while they are loaded.
+fn register_functions(code: &[u8]) -> Result<HashMap<String, bool>, ScriptError> {
+    let mut call = |_, _| {
+        Value::SimpleError(SimpleError::from(
+            "ERR redis.call is not allowed while loading a library",
+        ))
+    };
+    with_lua(true, &mut call, |lua| {
+        let functions = match load_library(lua, code)? {
+            Ok(functions) => functions,
+            Err(e) => return Ok(Err(e)),
+        };
+        functions
+            .pairs::<String, Table>()
+            .map(|pair| {
+                let (name, function) = pair?;
+                Ok((name, function.get("no_writes")?))
+            })
+            .collect::<mlua::Result<_>>()
+            .map(Ok)
+    })?
+}
+
+/// Returns the name of the library from the `#!lua name=<library>` line it starts with.
+fn library_name(code: &[u8]) -> Result<String, ScriptError> {
+    let line = code.split(|&b| b == b'\n').next().unwrap_or_default();
+    let line = std::str::from_utf8(line).map_err(|_| ScriptError::MissingMetadata)?;
+    let mut parts = line.split_whitespace();
+    if parts.next() != Some("#!lua") {
+        return Err(ScriptError::MissingMetadata);
+    }
+    parts
+        .find_map(|part| part.strip_prefix("name="))
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+        .ok_or(ScriptError::MissingMetadata)
+}
+
+/// Runs `f` in a fresh Lua state whose `redis` library runs commands with `call`.
+fn with_lua<R>(
+    read_only: bool,
+    call: &mut ScriptCall,
+    f: impl FnOnce(&Lua) -> mlua::Result<R>,
+) -> Result<R, ScriptError> {
+    let libs = StdLib::TABLE | StdLib::STRING | StdLib::MATH | StdLib::UTF8;
+    let lua = Lua::new_with(libs, LuaOptions::new()).map_err(engine_error)?;
+    lua.scope(|scope| {
+        let redis = lua.create_table()?;
+        let pcall = scope.create_function_mut(|lua, args: Variadic<LuaValue>| {
+            to_lua(lua, call(command_args(args)?, read_only))
+        })?;
+        redis.set("pcall", pcall)?;
+        redis.set("register_function", lua.create_function(register_function)?)?;
+
+        let globals = lua.globals();
+        globals.set("redis", redis)?;
+        // The base library can read files.
+        for name in ["dofile", "loadfile"] {
+            globals.set(name, LuaValue::Nil)?;
+        }
+        lua.load(PRELUDE).exec()?;
+        f(&lua)
+    })
+    .map_err(engine_error)
+}
+
+/// Runs the library without its `#!lua` line, returning the functions it registers.
+fn load_library<'lua>(
+    lua: &'lua Lua,
+    code: &[u8],
+) -> mlua::Result<Result<Table<'lua>, ScriptError>> {
+    lua.set_named_registry_value(REGISTERED_FUNCTIONS, lua.create_table()?)?;
+    // Keep the newline, so that errors point at the right lines.
+    let body = code
+        .iter()
+        .position(|&b| b == b'\n')
+        .map_or(&[][..], |end| &code[end..]);
+    let library = match lua.load(body).set_name("=user_function").into_function() {
+        Ok(library) => library,
+        Err(e) => return Ok(Err(ScriptError::Compile(error_message(&e)))),
+    };
+    if let Err(e) = protected_call(lua, library, ())? {
+        return Ok(Err(e));
+    }
+    lua.named_registry_value(REGISTERED_FUNCTIONS).map(Ok)
+}
+
+/// `redis.register_function(name, callback)`, or with a table of `function_name`,
+/// `callback` and `flags`.
+fn register_function<'lua>(lua: &'lua Lua, args: Variadic<LuaValue<'lua>>) -> mlua::Result<()> {
+    let (name, callback, flags) = match args.as_slice() {
+        [LuaValue::String(name), LuaValue::Function(callback)] => {
+            (name.to_str()?.to_string(), callback.clone(), None)
+        }
+        [LuaValue::Table(options)] => (
+            options.get("function_name")?,
+            options.get("callback")?,
+            options.get::<_, Option<Table>>("flags")?,
+        ),
+        _ => {
+            return Err(mlua::Error::RuntimeError(
+                "wrong arguments given to redis.register_function".to_string(),
+            ))
+        }
+    };
+    let mut no_writes = false;
+    if let Some(flags) = flags {
+        for flag in flags.sequence_values::<String>() {
+            no_writes |= flag? == "no-writes";
+        }
+    }
+
+    let function = lua.create_table()?;
+    function.set("callback", callback)?;
+    function.set("no_writes", no_writes)?;
+    lua.named_registry_value::<Table>(REGISTERED_FUNCTIONS)?
+        .set(name, function)
+}
+
+/// Calls the function with Lua's `pcall`, so that errors raised as `{ err = ... }` tables
+/// keep their message.
+fn protected_call<'lua>(
+    lua: &'lua Lua,
+    function: Function<'lua>,
+    args: impl IntoLuaMulti<'lua>,
+) -> mlua::Result<Result<Value, ScriptError>> {
+    let pcall: Function = lua.globals().get("pcall")?;
+    let mut results = pcall
+        .call::<_, Variadic<LuaValue>>((function, args))?
+        .into_iter();
+    let ok = matches!(results.next(), Some(LuaValue::Boolean(true)));
+    let result = results.next().unwrap_or(LuaValue::Nil);
+    if ok {
+        return from_lua(result).map(Ok);
+    }
+
+    let message = match result {
+        LuaValue::Table(error) => match error.get::<_, Option<String>>("err")? {
+            Some(err) => err,
+            None => "ERR the script raised a table without err".to_string(),
+        },
+        LuaValue::Error(e) => format!("ERR {}", error_message(&e)),
+        error => format!(
+            "ERR {}",
+            lua.coerce_string(error)?.map_or_else(
+                || "unknown error".to_string(),
+                |s| s.to_string_lossy().into_owned(),
+            )
+        ),
+    };
+    Ok(Err(ScriptError::Raised(message)))
+}
+
+/// Converts what a script returned to a reply: numbers are truncated to integers, true is
+/// 1, false and nil are null, and tables are arrays unless they are `{ ok = ... }` or
+/// `{ err = ... }`.
+fn from_lua(value: LuaValue) -> mlua::Result<Value> {
+    let value = match value {
+        LuaValue::Integer(n) => Value::Integer(Integer::new(n)),
+        LuaValue::Number(n) => Value::Integer(Integer::new(n as i64)),
+        LuaValue::Boolean(true) => Value::Integer(Integer::new(1)),
+        LuaValue::String(s) => Value::BulkString(BulkString::new(s.as_bytes().to_vec())),
+        LuaValue::Table(table) => {
+            if let Some(err) = table.raw_get::<_, Option<String>>("err")? {
+                Value::SimpleError(SimpleError::from(err))
+            } else if let Some(ok) = table.raw_get::<_, Option<String>>("ok")? {
+                Value::SimpleString(SimpleString::from(ok))
+            } else {
+                let values = table
+                    .sequence_values::<LuaValue>()
+                    .map(|value| from_lua(value?))
+                    .collect::<mlua::Result<Vec<_>>>()?;
+                Value::Array(values.into())
+            }
+        }
+        _ => Value::BulkString(BulkString::null()),
+    };
+    Ok(value)
+}
+
+/// Converts the reply of a command to what `redis.call` returns: integers and strings as
+/// they are, null as false, arrays as tables and status and error replies as `{ ok = ... }`
+/// and `{ err = ... }` tables.
+fn to_lua<'lua>(lua: &'lua Lua, value: Value) -> mlua::Result<LuaValue<'lua>> {
+    let sequence = |values: Vec<Value>| -> mlua::Result<LuaValue> {
+        let table = lua.create_table_with_capacity(values.len(), 0)?;
+        for value in values {
+            table.raw_push(to_lua(lua, value)?)?;
+        }
+        Ok(LuaValue::Table(table))
+    };
+
+    match value {
+        Value::Integer(n) => Ok(LuaValue::Integer(i64::from(&n))),
+        Value::BulkString(s) => match s.as_bytes() {
+            Some(bytes) => Ok(LuaValue::String(lua.create_string(bytes)?)),
+            None => Ok(LuaValue::Boolean(false)),
+        },
+        Value::SimpleString(s) => Ok(LuaValue::Table(
+            lua.create_table_from([("ok", s.as_str())])?,
+        )),
+        Value::SimpleError(e) => Ok(LuaValue::Table(
+            lua.create_table_from([("err", e.as_str())])?,
+        )),
+        Value::Array(array) => match array.values() {
+            Some(values) => sequence(values.to_vec()),
+            None => Ok(LuaValue::Boolean(false)),
+        },
+        Value::Null(_) => Ok(LuaValue::Boolean(false)),
+        Value::Push(push) => sequence(push.values().to_vec()),
+        Value::Set(set) => sequence(set.values().to_vec()),
+        Value::Map(map) => sequence(
+            map.pairs()
+                .iter()
+                .flat_map(|(field, value)| [field.clone(), value.clone()])
+                .collect(),
+        ),
+    }
+}
+
+/// Returns a table of the strings, as `KEYS` and `ARGV` are.
+fn strings<'lua>(lua: &'lua Lua, values: &[BulkString]) -> mlua::Result<Table<'lua>> {
+    let table = lua.create_table_with_capacity(values.len(), 0)?;
+    for value in values {
+        table.raw_push(lua.create_string(value.as_bytes().unwrap_or_default())?)?;
+    }
+    Ok(table)
+}
+
+/// Converts the arguments of `redis.call` to a command, which must be strings or numbers.
+fn command_args(args: Variadic<LuaValue>) -> mlua::Result<Vec<BulkString>> {
+    if args.is_empty() {
+        return Err(mlua::Error::RuntimeError(
+            "Please specify at least one argument for this redis lib call".to_string(),
+        ));
+    }
+    args.iter()
+        .map(|arg| match arg {
+            LuaValue::String(s) => Ok(BulkString::new(s.as_bytes().to_vec())),
+            LuaValue::Integer(n) => Ok(BulkString::from(n.to_string())),
+            LuaValue::Number(n) => Ok(BulkString::from(n.to_string())),
+            _ => Err(mlua::Error::RuntimeError(
+                "Lua redis lib command arguments must be strings or integers".to_string(),
+            )),
+        })
+        .collect()
+}
+
+/// Returns the message of the error, without the traceback of Rust callbacks.
+fn error_message(e: &mlua::Error) -> String {
+    match e {
+        mlua::Error::CallbackError { cause, .. } => error_message(cause),
+        mlua::Error::RuntimeError(message) => message.clone(),
+        mlua::Error::SyntaxError { message, .. } => message.clone(),
+        e => e.to_string(),
+    }
+}
+
+fn engine_error(e: mlua::Error) -> ScriptError {
+    ScriptError::Raised(format!("ERR {}", error_message(&e)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::resp::Array;
+    use super::*;
+
+    /// Replies to every command with its arguments, or an error for `fail`.
+    fn echo(argv: Vec<BulkString>, _read_only: bool) -> Value {
+        if argv[0] == BulkString::from("fail") {
+            return Value::SimpleError(SimpleError::from("WRONGTYPE failed"));
+        }
+        Value::Array(Array::new(
+            argv.into_iter().map(Value::BulkString).collect(),
+        ))
+    }
+
+    #[test]
+    fn eval_converts_replies() {
+        let keys = [BulkString::from("key")];
+        let args = [BulkString::from("arg")];
+        let script = b"return {redis.call('get', KEYS[1], ARGV[1], 2), 1.5, true, false, \
+            redis.status_reply('DONE')}";
+
+        assert_eq!(
+            eval(script, &keys, &args, false, &mut echo),
+            Ok(Value::Array(Array::new(vec![
+                Value::Array(Array::new(vec![
+                    Value::BulkString("get".into()),
+                    Value::BulkString("key".into()),
+                    Value::BulkString("arg".into()),
+                    Value::BulkString("2".into()),
+                ])),
+                Value::Integer(Integer::new(1)),
+                Value::Integer(Integer::new(1)),
+                Value::BulkString(BulkString::null()),
+                Value::SimpleString(SimpleString::from("DONE")),
+            ])))
+        );
+    }
+
+    #[test]
+    fn eval_errors() {
+        assert_eq!(
+            eval(b"return redis.call('fail')", &[], &[], false, &mut echo),
+            Err(ScriptError::Raised("WRONGTYPE failed".to_string()))
+        );
+        assert_eq!(
+            eval(b"return redis.pcall('fail')", &[], &[], false, &mut echo),
+            Ok(Value::SimpleError(SimpleError::from("WRONGTYPE failed")))
+        );
+        assert!(matches!(
+            eval(b"return (", &[], &[], false, &mut echo),
+            Err(ScriptError::Compile(_))
+        ));
+        assert_eq!(
+            eval(b"error('oops')", &[], &[], false, &mut echo),
+            Err(ScriptError::Raised("ERR user_script:1: oops".to_string()))
+        );
+        assert!(matches!(
+            eval(b"return dofile('/etc/passwd')", &[], &[], false, &mut echo),
+            Err(ScriptError::Raised(_))
+        ));
+    }
+
+    #[test]
+    fn load_and_call_functions() {
+        let mut functions = Functions::default();
+        let code = b"#!lua name=lib\n\
+            redis.register_function('echo', function(keys, args) \
+                return redis.call('echo', keys[1], args[1]) end)\n\
+            redis.register_function{function_name='ro', callback=function() return 1 end, \
+                flags={'no-writes'}}";
+
+        assert_eq!(functions.load(code, false), Ok("lib".to_string()));
+        assert_eq!(
+            functions.load(code, false),
+            Err(ScriptError::LibraryExists("lib".to_string()))
+        );
+        assert_eq!(
+            functions.load(b"return 1", false),
+            Err(ScriptError::MissingMetadata)
+        );
+        assert_eq!(
+            functions.load(b"#!lua name=empty\nreturn 1", false),
+            Err(ScriptError::NoFunctions)
+        );
+        assert_eq!(
+            functions.load(
+                b"#!lua name=other\nredis.register_function('ro', print)",
+                false
+            ),
+            Err(ScriptError::FunctionExists("ro".to_string()))
+        );
+
+        let function = functions.function("echo").unwrap();
+        assert!(!function.no_writes);
+        assert!(functions.function("ro").unwrap().no_writes);
+        assert_eq!(
+            fcall(
+                &function.code,
+                "echo",
+                &["key".into()],
+                &["arg".into()],
+                false,
+                &mut echo
+            ),
+            Ok(Value::Array(Array::new(vec![
+                Value::BulkString("echo".into()),
+                Value::BulkString("key".into()),
+                Value::BulkString("arg".into()),
+            ])))
+        );
+
+        functions.delete("lib").unwrap();
+        assert_eq!(functions.function("echo"), None);
+        assert_eq!(functions.delete("lib"), Err(ScriptError::NoSuchLibrary));
+    }
+}
@@ -1,15 +1,13 @@
 use async_trait::async_trait;
+use bytes::{Buf, BufMut, BytesMut};
 use thiserror::Error;
-use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
-    net::TcpStream,
-};
-use tracing::debug;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadHalf, WriteHalf};
+use tracing::{debug, trace};
 
 use super::{
     cmd::{Command, ParseCommandError},
+    reply::Reply,
     resp::{Array, BulkString, DecodeError, EncodeError, Value},
-    util,
 };
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -28,8 +26,12 @@ impl Request {
         encode_value(&self.0)
     }
 
-    pub fn as_command(&self) -> Result<Command, ParseCommandError> {
-        Command::try_from(self.0.clone())
+    pub fn value(&self) -> &Value {
+        &self.0
+    }
+
+    pub fn into_command(self) -> Result<Command, ParseCommandError> {
+        Command::try_from(self.0)
     }
 }
 
@@ -39,9 +41,9 @@ impl From<Value> for Request {
     }
 }
 
-impl Into<Value> for Request {
-    fn into(self) -> Value {
-        self.0
+impl From<Request> for Value {
+    fn from(val: Request) -> Self {
+        val.0
     }
 }
 
@@ -89,19 +91,16 @@ impl From<Value> for Response {
     }
 }
 
-impl Into<Value> for Response {
-    fn into(self) -> Value {
-        self.0
+impl From<Response> for Value {
+    fn from(val: Response) -> Self {
+        val.0
     }
 }
 
 fn encode_value(val: &Value) -> Result<Vec<u8>, EncodeError> {
-    let mut write_buf = Vec::new();
-    let mut buf = util::Buffer::new(&mut write_buf);
+    let mut buf = Vec::new();
     val.encode(&mut buf)?;
-
-    let count = buf.count;
-    Ok(buf.inner[..count].to_vec())
+    Ok(buf)
 }
 
 #[async_trait]
@@ -109,9 +108,36 @@ pub trait Responder {
     async fn respond(&mut self, req: Request) -> Result<Response, SessionError>;
 }
 
+/// Any bidirectional byte stream a Session can run over, e.g. a plain TCP stream or a TLS stream.
+pub trait Stream: AsyncRead + AsyncWrite + Unpin + Send + std::fmt::Debug {}
+
+impl<T> Stream for T where T: AsyncRead + AsyncWrite + Unpin + Send + std::fmt::Debug {}
+
+/// Space reserved for every read.
+const READ_BUF_LEN: usize = 4096;
+
+/// Replies are written out whenever this much of them is encoded, so a large reply doesn't
+/// have to be buffered whole.
+const WRITE_CHUNK_LEN: usize = 16 * 1024;
+
+/// Write buffers that grew past this for a large reply are given back after use, rather
+/// than being kept for the rest of the connection.
+const MAX_IDLE_BUF_LEN: usize = 64 * 1024;
+
+/// Connection buffers, reused for every request and reply so that the request path doesn't
+/// allocate them every time.
+#[derive(Debug, Default)]
+struct Buffers {
+    read: BytesMut,
+    write: BytesMut,
+}
+
+/// Requests and replies over a stream, by default any boxed `Stream`. Other streams, like
+/// io_uring sockets that can't leave their thread, are used as they are.
 #[derive(Debug)]
-pub struct Session {
-    stream: TcpStream,
+pub struct Session<S = Box<dyn Stream>> {
+    stream: S,
+    bufs: Buffers,
 }
 
 #[derive(Debug, Error)]
@@ -127,45 +153,305 @@ pub enum SessionError {
 
     #[error(transparent)]
     TokioIo(#[from] tokio::io::Error),
+
+    #[error("Query buffer of {0} bytes reached the limit")]
+    QueryBufferLimit(usize),
 }
 
 impl Session {
-    pub fn new(stream: TcpStream) -> Self {
-        Self { stream }
+    pub fn new(stream: impl Stream + 'static) -> Self {
+        Self::from_stream(Box::new(stream))
     }
+}
 
-    pub async fn receive_request(&mut self) -> Result<Option<Request>, SessionError> {
-        let mut buf = [0u8; 512];
-        let bytes_read = self.stream.read(&mut buf).await?;
-        if bytes_read == 0 {
-            return Ok(None);
+impl<S: AsyncRead + AsyncWrite + Unpin> Session<S> {
+    /// Returns a session over the stream as it is, without boxing it.
+    pub fn from_stream(stream: S) -> Self {
+        Self {
+            stream,
+            bufs: Buffers::default(),
         }
+    }
 
-        debug!("Received {:?}", &buf[..bytes_read]);
-        Ok(Some(Request::decode(&buf[..bytes_read])?))
+    pub async fn receive_request(&mut self) -> Result<Option<Request>, SessionError> {
+        read_request(&mut self.stream, &mut self.bufs.read, usize::MAX).await
     }
 
     pub async fn send_response(&mut self, resp: Response) -> Result<(), SessionError> {
-        let buf = resp.encode()?;
-        self.stream.write(&buf).await?;
+        write_replies(&mut self.stream, &mut self.bufs.write, [resp.0.into()]).await
+    }
 
-        Ok(())
+    /// Splits the session into halves that can be used from different tasks, so that
+    /// responses can be written while waiting for the next request.
+    pub fn split(self) -> (SessionReader<S>, SessionWriter<S>) {
+        let (reader, writer) = tokio::io::split(self.stream);
+        (
+            SessionReader {
+                stream: reader,
+                buf: self.bufs.read,
+                limit: usize::MAX,
+            },
+            SessionWriter {
+                stream: writer,
+                buf: self.bufs.write,
+            },
+        )
     }
 
     pub async fn send_request_and_wait_reply(
         &mut self,
         req: Request,
     ) -> Result<Response, SessionError> {
+        self.send_request(req).await?;
+        self.receive_response().await
+    }
+
+    /// Sends the request without waiting for a reply, e.g. an acknowledgement to a master.
+    pub async fn send_request(&mut self, req: Request) -> Result<(), SessionError> {
         let buf = req.encode()?;
-        self.stream.write(&buf).await?;
+        self.stream.write_all(&buf).await?;
+        Ok(())
+    }
+
+    /// Reads the next response, e.g. a message pushed to a subscribed client. Responses
+    /// spanning several reads are put together, and bytes read past the response are kept
+    /// for the next one.
+    pub async fn receive_response(&mut self) -> Result<Response, SessionError> {
+        Ok(self.receive_response_with_len().await?.0)
+    }
+
+    /// Reads the next response like `receive_response`, along with the number of bytes it
+    /// took on the wire. Cancelling it loses nothing, as read bytes stay buffered.
+    pub async fn receive_response_with_len(&mut self) -> Result<(Response, usize), SessionError> {
+        let buf = &mut self.bufs.read;
+        loop {
+            match Value::decode_with_len(buf) {
+                Ok((value, len)) => {
+                    buf.advance(len);
+                    trace!("Received reply {}", value.to_pretty_string());
+                    return Ok((Response(value), len));
+                }
+                Err(DecodeError::Incomplete) => (),
+                Err(e) => return Err(e.into()),
+            }
+
+            buf.reserve(READ_BUF_LEN);
+            if self.stream.read_buf(buf).await? == 0 {
+                return Err(SessionError::NoResponse);
+            }
+        }
+    }
+
+    /// Reads a payload sent like a bulk string but without the trailing CRLF, which is how a
+    /// master sends its RDB file after `FULLRESYNC`. Newlines sent before it to keep the link
+    /// alive while the file is being made are skipped.
+    pub async fn receive_payload(&mut self) -> Result<Vec<u8>, SessionError> {
+        let len = self.receive_payload_len().await?;
+        let buf = &mut self.bufs.read;
+        while buf.len() < len {
+            buf.reserve(len - buf.len());
+            if self.stream.read_buf(buf).await? == 0 {
+                return Err(SessionError::NoResponse);
+            }
+        }
+        Ok(buf.split_to(len).to_vec())
+    }
+
+    /// Reads a payload like `receive_payload`, writing it out as it arrives instead of
+    /// holding all of it in memory. Returns the length of the payload.
+    pub async fn receive_payload_to(
+        &mut self,
+        out: &mut (impl AsyncWrite + Unpin),
+    ) -> Result<usize, SessionError> {
+        let len = self.receive_payload_len().await?;
+        let buf = &mut self.bufs.read;
+        let mut left = len;
+        while left > 0 {
+            if buf.is_empty() {
+                buf.reserve(left.min(MAX_IDLE_BUF_LEN));
+                if self.stream.read_buf(buf).await? == 0 {
+                    return Err(SessionError::NoResponse);
+                }
+            }
+            let chunk = buf.split_to(buf.len().min(left));
+            out.write_all(&chunk).await?;
+            left -= chunk.len();
+        }
+        out.flush().await?;
+        Ok(len)
+    }
+
+    /// Reads the `$<len>\r\n` header of a payload.
+    async fn receive_payload_len(&mut self) -> Result<usize, SessionError> {
+        let buf = &mut self.bufs.read;
+        loop {
+            while buf.first() == Some(&b'\n') {
+                buf.advance(1);
+            }
+            if let Some(end) = buf.windows(2).position(|w| w == b"\r\n") {
+                let header = std::str::from_utf8(&buf[..end]).ok();
+                let len = header
+                    .and_then(|header| header.strip_prefix('$'))
+                    .and_then(|len| len.parse::<usize>().ok())
+                    .ok_or(DecodeError::InvalidFormat)?;
+                buf.advance(end + 2);
+                return Ok(len);
+            }
+            buf.reserve(READ_BUF_LEN);
+            if self.stream.read_buf(buf).await? == 0 {
+                return Err(SessionError::NoResponse);
+            }
+        }
+    }
+}
+
+/// Reading half of a split Session.
+#[derive(Debug)]
+pub struct SessionReader<S = Box<dyn Stream>> {
+    stream: ReadHalf<S>,
+    buf: BytesMut,
+    limit: usize,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> SessionReader<S> {
+    /// Limits the bytes buffered for a request that isn't complete yet, like
+    /// client-query-buffer-limit, so a client can't make the server buffer without end.
+    pub fn set_query_buffer_limit(&mut self, limit: u64) {
+        self.limit = usize::try_from(limit).unwrap_or(usize::MAX);
+    }
+
+    /// Reads the next request, failing with `QueryBufferLimit` once the buffered bytes reach
+    /// the limit before the request is complete.
+    pub async fn receive_request(&mut self) -> Result<Option<Request>, SessionError> {
+        read_request(&mut self.stream, &mut self.buf, self.limit).await
+    }
+}
+
+/// Writing half of a split Session.
+#[derive(Debug)]
+pub struct SessionWriter<S = Box<dyn Stream>> {
+    stream: WriteHalf<S>,
+    buf: BytesMut,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> SessionWriter<S> {
+    pub async fn send_response(&mut self, resp: Response) -> Result<(), SessionError> {
+        write_replies(&mut self.stream, &mut self.buf, [resp.0.into()]).await
+    }
+
+    /// Writes the responses with a single write, e.g. the replies to pipelined commands.
+    pub async fn send_responses(
+        &mut self,
+        resps: impl IntoIterator<Item = Response>,
+    ) -> Result<(), SessionError> {
+        let replies = resps.into_iter().map(|resp| resp.0.into());
+        write_replies(&mut self.stream, &mut self.buf, replies).await
+    }
+
+    /// Writes the replies, generating streamed arrays as they go out.
+    pub async fn send_replies(
+        &mut self,
+        replies: impl IntoIterator<Item = Reply>,
+    ) -> Result<(), SessionError> {
+        write_replies(&mut self.stream, &mut self.buf, replies).await
+    }
+}
+
+/// Reads the next request. Requests spanning several reads are put together, and bytes read
+/// past the request, like further pipelined requests, are kept for the next call. Returns
+/// none once the peer closes the connection, dropping a request it didn't finish sending.
+async fn read_request(
+    stream: &mut (impl AsyncRead + Unpin),
+    buf: &mut BytesMut,
+    limit: usize,
+) -> Result<Option<Request>, SessionError> {
+    loop {
+        if !buf.is_empty() {
+            match Value::decode_with_len(buf) {
+                Ok((value, len)) => {
+                    buf.advance(len);
+                    let request = Request(value);
+                    debug!("Received {}", request.value().to_pretty_string());
+                    return Ok(Some(request));
+                }
+                Err(DecodeError::Incomplete) => (),
+                Err(e) => return Err(e.into()),
+            }
+        }
+        if buf.len() >= limit {
+            return Err(SessionError::QueryBufferLimit(buf.len()));
+        }
+
+        buf.reserve(READ_BUF_LEN);
+        if stream.read_buf(buf).await? == 0 {
+            return Ok(None);
+        }
+    }
+}
 
-        let mut buf = [0u8; 512];
-        let bytes_read = self.stream.read(&mut buf).await?;
-        if bytes_read == 0 {
-            return Err(SessionError::NoResponse);
+/// Encodes the replies into the buffer, writing it out every `WRITE_CHUNK_LEN` bytes.
+async fn write_replies(
+    stream: &mut (impl AsyncWrite + Unpin),
+    buf: &mut BytesMut,
+    replies: impl IntoIterator<Item = Reply>,
+) -> Result<(), SessionError> {
+    buf.clear();
+    for reply in replies {
+        match reply {
+            Reply::Value(value) => {
+                trace!("Sending {}", value.to_pretty_string());
+                value.encode(&mut buf.writer())?;
+            }
+            Reply::Stream(mut array) => {
+                array.encode_header(&mut buf.writer())?;
+                while let Some(value) = array.next_item() {
+                    value.encode(&mut buf.writer())?;
+                    if buf.len() >= WRITE_CHUNK_LEN {
+                        stream.write_all(buf).await?;
+                        buf.clear();
+                    }
+                }
+            }
+            Reply::Payload(value, payload) => {
+                trace!(
+                    "Sending {} and {} bytes",
+                    value.to_pretty_string(),
+                    payload.len()
+                );
+                value.encode(&mut buf.writer())?;
+                buf.put_slice(format!("${}\r\n", payload.len()).as_bytes());
+                stream.write_all(buf).await?;
+                buf.clear();
+                stream.write_all(&payload).await?;
+            }
+            Reply::Backlog(value, backlog) => {
+                trace!(
+                    "Sending {} and {} bytes of backlog",
+                    value.to_pretty_string(),
+                    backlog.len()
+                );
+                value.encode(&mut buf.writer())?;
+                stream.write_all(buf).await?;
+                buf.clear();
+                stream.write_all(&backlog).await?;
+            }
+            // Deferred values are waited for before they are queued.
+            Reply::Nothing | Reply::Deferred(_) => (),
         }
+        if buf.len() >= WRITE_CHUNK_LEN {
+            stream.write_all(buf).await?;
+            buf.clear();
+        }
+    }
+    stream.write_all(buf).await?;
+    release_if_large(buf);
+
+    Ok(())
+}
 
-        Ok(Response::decode(&buf[..bytes_read])?)
+fn release_if_large(buf: &mut BytesMut) {
+    if buf.capacity() > MAX_IDLE_BUF_LEN {
+        *buf = BytesMut::new();
     }
 }
 
@@ -177,16 +463,262 @@ impl Responder for Session {
 }
 
 #[cfg(test)]
-pub struct MockResponder {
-    pub expected_req: Request,
-    pub returned_resp: Response,
-}
+mod test {
+    use super::super::reply::StreamedArray;
+    use super::super::resp::Map;
+    use super::*;
+
+    #[tokio::test]
+    async fn send_responses_writes_all_in_order() {
+        let (client, server) = tokio::io::duplex(1024);
+        let (_, mut writer) = Session::new(server).split();
+
+        writer
+            .send_responses([
+                Response::new(Value::SimpleString("OK".into())),
+                Response::new(Value::BulkString("value".into())),
+            ])
+            .await
+            .expect("Send responses unexpected error");
+        drop(writer);
+
+        let mut buf = Vec::new();
+        let mut client = client;
+        client
+            .read_to_end(&mut buf)
+            .await
+            .expect("Read unexpected error");
+        assert_eq!(buf, b"+OK\r\n$5\r\nvalue\r\n");
+    }
 
-#[cfg(test)]
-#[async_trait]
-impl Responder for MockResponder {
-    async fn respond(&mut self, req: Request) -> Result<Response, SessionError> {
-        assert_eq!(req, self.expected_req);
-        Ok(self.returned_resp.clone())
+    #[tokio::test]
+    async fn unfinished_request_past_limit_fails() {
+        let (mut client, server) = tokio::io::duplex(64 * 1024);
+        let (mut reader, _) = Session::new(server).split();
+        reader.set_query_buffer_limit(16 * 1024);
+
+        // A request within the limit is read as usual.
+        client
+            .write_all(b"*1\r\n$4\r\nPING\r\n")
+            .await
+            .expect("Write unexpected error");
+        assert!(reader
+            .receive_request()
+            .await
+            .expect("Receive request unexpected error")
+            .is_some());
+
+        // A bulk string that would never fit is refused once the limit is reached.
+        client
+            .write_all(b"*1\r\n$1000000\r\n")
+            .await
+            .expect("Write unexpected error");
+        client
+            .write_all(&[b'x'; 32 * 1024])
+            .await
+            .expect("Write unexpected error");
+        assert!(matches!(
+            reader.receive_request().await,
+            Err(SessionError::QueryBufferLimit(len)) if len >= 16 * 1024
+        ));
+    }
+
+    #[tokio::test]
+    async fn streamed_array_is_written_in_chunks() {
+        let (mut client, server) = tokio::io::duplex(1024);
+        let (_, mut writer) = Session::new(server).split();
+        let items = (0..10_000).map(|i| Value::BulkString(format!("key:{i}").into()));
+        let pair = [Value::BulkString("a".into()), Value::BulkString("1".into())];
+        let drain = tokio::spawn(async move {
+            let mut buf = Vec::new();
+            client.read_to_end(&mut buf).await.map(|_| buf)
+        });
+
+        writer
+            .send_replies([
+                Reply::from(StreamedArray::new(10_000, items.clone())),
+                Reply::from(Value::SimpleString("OK".into())),
+                Reply::from(StreamedArray::map(1, pair.clone().into_iter())),
+            ])
+            .await
+            .expect("Send replies unexpected error");
+        // Only a chunk is buffered at a time. Buffering the whole reply would have grown the
+        // buffer enough for it to be released.
+        assert!((1..=MAX_IDLE_BUF_LEN).contains(&writer.buf.capacity()));
+        drop(writer);
+
+        let buf = drain
+            .await
+            .expect("Join unexpected error")
+            .expect("Read unexpected error");
+        let mut expected = Vec::new();
+        Value::Array(Array::new(items.collect()))
+            .encode(&mut expected)
+            .expect("Encode unexpected error");
+        expected.extend_from_slice(b"+OK\r\n");
+        let [key, value] = pair;
+        Value::Map(Map::new(vec![(key, value)]))
+            .encode(&mut expected)
+            .expect("Encode unexpected error");
+        assert_eq!(buf, expected);
+    }
+
+    #[tokio::test]
+    async fn receive_response_across_reads() {
+        let (mut server, client) = tokio::io::duplex(1024);
+        let mut session = Session::new(client);
+
+        let large = Value::BulkString(vec![b'x'; 3 * READ_BUF_LEN].into());
+        let mut buf = Vec::new();
+        large.encode(&mut buf).expect("Encode unexpected error");
+        buf.extend_from_slice(b"+OK\r\n:1");
+        let write = tokio::spawn(async move {
+            server.write_all(&buf).await?;
+            server.write_all(b"\r\n").await?;
+            Ok::<_, tokio::io::Error>(server)
+        });
+
+        let resp = session
+            .receive_response()
+            .await
+            .expect("Receive unexpected error");
+        assert!(resp.is(large));
+        assert!(session
+            .receive_response()
+            .await
+            .expect("Receive unexpected error")
+            .is_simple_string("OK"));
+        assert!(session
+            .receive_response()
+            .await
+            .expect("Receive unexpected error")
+            .is(Value::Integer(1.into())));
+
+        drop(write.await.expect("Join unexpected error"));
+        let err = session
+            .receive_response()
+            .await
+            .expect_err("Receive no error");
+        assert!(matches!(err, SessionError::NoResponse));
+    }
+
+    #[tokio::test]
+    async fn receive_pipelined_requests() {
+        let (mut client, server) = tokio::io::duplex(1024);
+        let (mut reader, _) = Session::new(server).split();
+
+        let large = Value::BulkString(vec![b'x'; 2 * READ_BUF_LEN].into());
+        let mut buf = b"*1\r\n$4\r\nPING\r\n*2\r\n$4\r\nECHO\r\n".to_vec();
+        large.encode(&mut buf).expect("Encode unexpected error");
+        buf.extend_from_slice(b"*1\r\n$4\r\nPI");
+        let write = tokio::spawn(async move {
+            client.write_all(&buf).await?;
+            client.write_all(b"NG\r\n*1\r\n$3\r\nGE").await
+        });
+
+        let ping = Value::Array(Array::new(vec![Value::BulkString("PING".into())]));
+        let echo = Value::Array(Array::new(vec![Value::BulkString("ECHO".into()), large]));
+        for expected in [ping.clone(), echo, ping] {
+            let req = reader
+                .receive_request()
+                .await
+                .expect("Receive unexpected error");
+            assert_eq!(req, Some(Request::new(expected)));
+        }
+
+        // The connection closing in the middle of a request drops it.
+        write
+            .await
+            .expect("Join unexpected error")
+            .expect("Write unexpected error");
+        let req = reader
+            .receive_request()
+            .await
+            .expect("Receive unexpected error");
+        assert_eq!(req, None);
+    }
+
+    #[tokio::test]
+    async fn receive_payload_without_crlf() {
+        let (mut server, client) = tokio::io::duplex(1024);
+        let mut session = Session::new(client);
+        let write = tokio::spawn(async move {
+            server.write_all(b"\n\n$5\r\nREDIS").await?;
+            server.write_all(b"*1\r\n$4\r\nPING\r\n").await
+        });
+
+        let payload = session
+            .receive_payload()
+            .await
+            .expect("Receive payload unexpected error");
+        assert_eq!(payload, b"REDIS");
+        let (resp, len) = session
+            .receive_response_with_len()
+            .await
+            .expect("Receive unexpected error");
+        assert!(resp.is_bulk_string_array(vec!["PING".into()]));
+        assert_eq!(len, 14);
+        write
+            .await
+            .expect("Join unexpected error")
+            .expect("Write unexpected error");
+    }
+
+    #[tokio::test]
+    async fn receive_payload_to_writer() {
+        let (mut server, client) = tokio::io::duplex(1024);
+        let mut session = Session::new(client);
+        let payload = vec![b'x'; 5000];
+        let mut stream = b"\n$5000\r\n".to_vec();
+        stream.extend_from_slice(&payload);
+        stream.extend_from_slice(b"*1\r\n$4\r\nPING\r\n");
+        let write = tokio::spawn(async move { server.write_all(&stream).await });
+
+        let mut out = Vec::new();
+        let len = session
+            .receive_payload_to(&mut out)
+            .await
+            .expect("Receive payload unexpected error");
+        assert_eq!(len, 5000);
+        assert_eq!(out, payload);
+        let (resp, _) = session
+            .receive_response_with_len()
+            .await
+            .expect("Receive unexpected error");
+        assert!(resp.is_bulk_string_array(vec!["PING".into()]));
+        write
+            .await
+            .expect("Join unexpected error")
+            .expect("Write unexpected error");
+    }
+
+    #[tokio::test]
+    async fn write_buffer_is_kept_unless_large() {
+        let (mut client, server) = tokio::io::duplex(1024);
+        let (_, mut writer) = Session::new(server).split();
+        let drain = tokio::spawn(async move {
+            let mut buf = Vec::new();
+            client.read_to_end(&mut buf).await.map(|_| buf.len())
+        });
+
+        writer
+            .send_response(Response::new(Value::SimpleString("OK".into())))
+            .await
+            .expect("Send response unexpected error");
+        assert!(writer.buf.capacity() > 0);
+
+        let large = vec![b'x'; MAX_IDLE_BUF_LEN];
+        writer
+            .send_response(Response::new(Value::BulkString(large.into())))
+            .await
+            .expect("Send response unexpected error");
+        assert_eq!(writer.buf.capacity(), 0);
+
+        drop(writer);
+        let read = drain
+            .await
+            .expect("Join unexpected error")
+            .expect("Read unexpected error");
+        assert!(read > MAX_IDLE_BUF_LEN);
     }
 }
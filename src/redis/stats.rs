@@ -0,0 +1,257 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use parking_lot::Mutex;
+
+/// Percentiles reported by the latencystats section of INFO.
+const PERCENTILES: &[f64] = &[50.0, 99.0, 99.9];
+
+/// Number of buckets per power of two in the latency histogram.
+const SUB_BUCKETS: u64 = 4;
+
+/// Histogram of latencies in microseconds, with buckets of a quarter power of two, so every
+/// value is reported within 25% of its actual value.
+#[derive(Debug, Clone, Default)]
+struct LatencyHistogram {
+    buckets: Vec<u64>,
+    count: u64,
+}
+
+impl LatencyHistogram {
+    fn bucket(usec: u64) -> usize {
+        if usec < SUB_BUCKETS {
+            return usec as usize;
+        }
+        let exp = 63 - usec.leading_zeros() as u64;
+        let sub = (usec >> (exp - 2)) & (SUB_BUCKETS - 1);
+        ((exp - 1) * SUB_BUCKETS + sub) as usize
+    }
+
+    /// Returns the largest value that falls into the bucket.
+    fn bucket_max(bucket: usize) -> u64 {
+        let bucket = bucket as u64;
+        if bucket < SUB_BUCKETS {
+            return bucket;
+        }
+        let exp = bucket / SUB_BUCKETS + 1;
+        let sub = bucket % SUB_BUCKETS;
+        ((SUB_BUCKETS + sub + 1) << (exp - 2)) - 1
+    }
+
+    fn record(&mut self, usec: u64) {
+        let bucket = Self::bucket(usec);
+        if self.buckets.len() <= bucket {
+            self.buckets.resize(bucket + 1, 0);
+        }
+        self.buckets[bucket] += 1;
+        self.count += 1;
+    }
+
+    /// Returns the latency below which `percentile` percent of the calls fall.
+    fn percentile(&self, percentile: f64) -> u64 {
+        let rank = ((percentile / 100.0) * self.count as f64).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        for (bucket, count) in self.buckets.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return Self::bucket_max(bucket);
+            }
+        }
+        0
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct CommandStat {
+    calls: u64,
+    usec: u64,
+
+    /// Calls refused before running, e.g. for lack of permission or memory.
+    rejected_calls: u64,
+
+    /// Calls that ran but returned an error.
+    failed_calls: u64,
+
+    latency: LatencyHistogram,
+}
+
+/// Per-command call counts and latencies, shared between the command handler and INFO.
+#[derive(Debug, Default)]
+pub struct CommandStats {
+    stats: Mutex<BTreeMap<&'static str, CommandStat>>,
+    keyspace_hits: AtomicU64,
+    keyspace_misses: AtomicU64,
+    expired_keys: AtomicU64,
+    evicted_keys: AtomicU64,
+    auth_failures: AtomicU64,
+    auth_throttled: AtomicU64,
+}
+
+impl CommandStats {
+    /// Records a call to the command that took `elapsed` and failed if `ok` is false.
+    pub fn record_call(&self, command: &'static str, elapsed: Duration, ok: bool) {
+        let usec = elapsed.as_micros() as u64;
+        let mut stats = self.stats.lock();
+        let stat = stats.entry(command).or_default();
+        stat.calls += 1;
+        stat.usec += usec;
+        stat.latency.record(usec);
+        if !ok {
+            stat.failed_calls += 1;
+        }
+    }
+
+    /// Records a call to the command that was refused before running.
+    pub fn record_rejected(&self, command: &'static str) {
+        let mut stats = self.stats.lock();
+        stats.entry(command).or_default().rejected_calls += 1;
+    }
+
+    /// Records a key lookup that found the key if `hit` is true.
+    pub fn record_keyspace(&self, hit: bool) {
+        let counter = if hit {
+            &self.keyspace_hits
+        } else {
+            &self.keyspace_misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn keyspace_hits(&self) -> u64 {
+        self.keyspace_hits.load(Ordering::Relaxed)
+    }
+
+    pub fn keyspace_misses(&self) -> u64 {
+        self.keyspace_misses.load(Ordering::Relaxed)
+    }
+
+    /// Records keys removed by the active expire cycle.
+    pub fn record_expired(&self, keys: u64) {
+        self.expired_keys.fetch_add(keys, Ordering::Relaxed);
+    }
+
+    pub fn expired_keys(&self) -> u64 {
+        self.expired_keys.load(Ordering::Relaxed)
+    }
+
+    /// Records keys removed to stay within maxmemory.
+    pub fn record_evicted(&self, keys: u64) {
+        self.evicted_keys.fetch_add(keys, Ordering::Relaxed);
+    }
+
+    pub fn evicted_keys(&self) -> u64 {
+        self.evicted_keys.load(Ordering::Relaxed)
+    }
+
+    /// Records an AUTH with a wrong password, or refused as the address failed too often if
+    /// `throttled` is true.
+    pub fn record_auth_failure(&self, throttled: bool) {
+        let counter = if throttled {
+            &self.auth_throttled
+        } else {
+            &self.auth_failures
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Returns the number of calls to every command.
+    pub fn total_calls(&self) -> u64 {
+        let stats = self.stats.lock();
+        stats.values().map(|stat| stat.calls).sum()
+    }
+
+    /// Returns the stats section of INFO as `field:value` lines.
+    pub fn stats_info(&self) -> Vec<String> {
+        vec![
+            format!("total_commands_processed:{}", self.total_calls()),
+            format!("expired_keys:{}", self.expired_keys()),
+            format!("evicted_keys:{}", self.evicted_keys()),
+            format!("keyspace_hits:{}", self.keyspace_hits()),
+            format!("keyspace_misses:{}", self.keyspace_misses()),
+            format!(
+                "acl_access_denied_auth:{}",
+                self.auth_failures.load(Ordering::Relaxed)
+            ),
+            format!(
+                "auth_throttled_calls:{}",
+                self.auth_throttled.load(Ordering::Relaxed)
+            ),
+        ]
+    }
+
+    /// Returns the commandstats section of INFO as `field:value` lines.
+    pub fn commandstats_info(&self) -> Vec<String> {
+        let stats = self.stats.lock();
+        stats
+            .iter()
+            .map(|(command, stat)| {
+                let per_call = if stat.calls == 0 {
+                    0.0
+                } else {
+                    stat.usec as f64 / stat.calls as f64
+                };
+                format!(
+                    "cmdstat_{command}:calls={},usec={},usec_per_call={per_call:.2},\
+                     rejected_calls={},failed_calls={}",
+                    stat.calls, stat.usec, stat.rejected_calls, stat.failed_calls
+                )
+            })
+            .collect()
+    }
+
+    /// Returns the latencystats section of INFO as `field:value` lines.
+    pub fn latencystats_info(&self) -> Vec<String> {
+        let stats = self.stats.lock();
+        stats
+            .iter()
+            .filter(|(_, stat)| stat.latency.count > 0)
+            .map(|(command, stat)| {
+                let percentiles: Vec<String> = PERCENTILES
+                    .iter()
+                    .map(|p| format!("p{p}={:.3}", stat.latency.percentile(*p) as f64))
+                    .collect();
+                format!(
+                    "latency_percentiles_usec_{command}:{}",
+                    percentiles.join(",")
+                )
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn histogram_buckets_bound_values() {
+        for usec in [0, 1, 3, 4, 7, 8, 9, 100, 1000, 123_456, u64::MAX / 2] {
+            let bucket = LatencyHistogram::bucket(usec);
+            assert!(LatencyHistogram::bucket_max(bucket) >= usec);
+            assert!(LatencyHistogram::bucket_max(bucket) as f64 <= usec as f64 * 1.25 + 1.0);
+        }
+    }
+
+    #[test]
+    fn info_reports_calls_and_percentiles() {
+        let stats = CommandStats::default();
+        for usec in 1..=100 {
+            stats.record_call("get", Duration::from_micros(usec), true);
+        }
+        stats.record_call("set", Duration::from_micros(10), false);
+        stats.record_rejected("set");
+
+        assert_eq!(
+            stats.commandstats_info(),
+            vec![
+                "cmdstat_get:calls=100,usec=5050,usec_per_call=50.50,rejected_calls=0,failed_calls=0",
+                "cmdstat_set:calls=1,usec=10,usec_per_call=10.00,rejected_calls=1,failed_calls=1",
+            ]
+        );
+        assert_eq!(
+            stats.latencystats_info()[0],
+            "latency_percentiles_usec_get:p50=55.000,p99=111.000,p99.9=111.000"
+        );
+    }
+}
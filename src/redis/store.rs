@@ -0,0 +1,663 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashSet, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::ops::{Bound, Deref};
+use std::time::{Duration, SystemTime};
+
+use im::{HashMap, OrdSet};
+use parking_lot::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use super::cluster::key_hash_slot;
+use super::handler::{StoredData, StoredValue};
+use super::key::Key;
+use super::resp::BulkString;
+use super::stream::Stream;
+use super::zset::SortedSet;
+
+/// Number of shards used by `Store::default`.
+pub const DEFAULT_SHARDS: usize = 16;
+
+/// The entries of one shard, along with their deadlines in order so the keys that expire
+/// next are found without going through every entry.
+///
+/// Reads go through the map it derefs to, while changes must use `insert`, `remove` and
+/// `set_deadline` to keep the deadlines, and the slots if indexed, in sync. Only the value
+/// of an entry may be changed in place, through `value_mut`.
+#[derive(Debug, Clone, Default)]
+pub struct Shard {
+    entries: HashMap<Key, StoredData>,
+    deadlines: OrdSet<(SystemTime, Key)>,
+
+    /// The keys ordered by hash slot, only kept in cluster mode.
+    slots: Option<OrdSet<(u16, Key)>>,
+}
+
+impl Shard {
+    /// Returns an empty shard that keeps its keys indexed by hash slot.
+    fn with_slot_index() -> Self {
+        Self {
+            slots: Some(OrdSet::new()),
+            ..Self::default()
+        }
+    }
+
+    /// Inserts the entry, returning the data it replaced.
+    pub fn insert(&mut self, key: Key, data: StoredData) -> Option<StoredData> {
+        let deadline = data.deadline;
+        let old = self.entries.insert(key.clone(), data);
+        if let Some(old_deadline) = old.as_ref().and_then(|old| old.deadline) {
+            self.deadlines.remove(&(old_deadline, key.clone()));
+        }
+        if let (Some(slots), None) = (&mut self.slots, &old) {
+            slots.insert((key_hash_slot(&key), key.clone()));
+        }
+        if let Some(deadline) = deadline {
+            self.deadlines.insert((deadline, key));
+        }
+        old
+    }
+
+    pub fn remove(&mut self, key: &[u8]) -> Option<StoredData> {
+        let (key, data) = self.entries.remove_with_key(key)?;
+        if let Some(slots) = &mut self.slots {
+            slots.remove(&(key_hash_slot(&key), key.clone()));
+        }
+        if let Some(deadline) = data.deadline {
+            self.deadlines.remove(&(deadline, key));
+        }
+        Some(data)
+    }
+
+    /// Returns the value of the entry to change in place, e.g. to push to a list without
+    /// copying it.
+    pub fn value_mut(&mut self, key: &[u8]) -> Option<&mut StoredValue> {
+        self.entries.get_mut(key).map(|data| &mut data.value)
+    }
+
+    /// Changes the deadline of the entry, returning false if there is none.
+    pub fn set_deadline(&mut self, key: &[u8], deadline: Option<SystemTime>) -> bool {
+        let Some((key, data)) = self.entries.get_key_value(key) else {
+            return false;
+        };
+        let key = key.clone();
+        if let Some(old_deadline) = data.deadline {
+            self.deadlines.remove(&(old_deadline, key.clone()));
+        }
+        if let Some(deadline) = deadline {
+            self.deadlines.insert((deadline, key.clone()));
+        }
+        if let Some(data) = self.entries.get_mut(&key) {
+            data.deadline = deadline;
+        }
+        true
+    }
+
+    /// Returns the keys hashing to the slot, or none if the slots aren't indexed.
+    pub fn keys_in_slot(&self, slot: u16) -> impl Iterator<Item = &Key> {
+        let range = (
+            Bound::Included((slot, Key::from(""))),
+            Bound::Excluded((slot.saturating_add(1), Key::from(""))),
+        );
+        self.slots
+            .iter()
+            .flat_map(move |slots| slots.range(range.clone()))
+            .map(|(_, key)| key)
+    }
+
+    /// Returns the earliest deadline of any key.
+    pub fn next_deadline(&self) -> Option<SystemTime> {
+        self.deadlines.get_min().map(|(deadline, _)| *deadline)
+    }
+
+    /// Removes and returns up to `limit` keys whose deadline is before `now`, earliest
+    /// first.
+    pub fn remove_expired(&mut self, now: SystemTime, limit: usize) -> Vec<(Key, StoredData)> {
+        let mut expired = Vec::new();
+        while expired.len() < limit {
+            let key = match self.deadlines.get_min() {
+                Some((deadline, key)) if *deadline < now => key.clone(),
+                _ => break,
+            };
+            match self.remove(&key) {
+                Some(data) => expired.push((key, data)),
+                // Can't happen as long as the deadlines are in sync.
+                None => break,
+            }
+        }
+        expired
+    }
+}
+
+impl Deref for Shard {
+    type Target = HashMap<Key, StoredData>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.entries
+    }
+}
+
+/// The keyspace, split into shards that each have their own lock so that commands on
+/// unrelated keys don't wait on each other.
+///
+/// A key always maps to the same shard. Commands that touch several keys lock one shard
+/// at a time, so they must not hold a guard while locking another, unless they lock every
+/// shard they need at once with `write_many` or `read_many`.
+///
+/// The locks don't poison, and are fair so a stream of readers can't starve a writer. They
+/// block the calling thread, which is fine as long as nothing is awaited while holding one.
+///
+/// Shards are persistent maps, so a snapshot shares their entries and a write after it only
+/// copies the path to the entry it changes.
+#[derive(Debug)]
+pub struct Store {
+    shards: Box<[RwLock<Shard>]>,
+}
+
+impl Default for Store {
+    fn default() -> Self {
+        Self::new(DEFAULT_SHARDS)
+    }
+}
+
+impl Store {
+    /// Returns an empty store with `shards` shards, at least one.
+    pub fn new(shards: usize) -> Self {
+        Self {
+            shards: (0..shards.max(1))
+                .map(|_| RwLock::new(Shard::default()))
+                .collect(),
+        }
+    }
+
+    /// Returns an empty store with `shards` shards that keeps its keys indexed by hash slot,
+    /// for the slot queries of cluster mode.
+    pub fn with_slot_index(shards: usize) -> Self {
+        Self {
+            shards: (0..shards.max(1))
+                .map(|_| RwLock::new(Shard::with_slot_index()))
+                .collect(),
+        }
+    }
+
+    /// Read locks the shard holding the key.
+    pub fn read(&self, key: &[u8]) -> RwLockReadGuard<'_, Shard> {
+        self.shard(key).read()
+    }
+
+    /// Write locks the shard holding the key.
+    pub fn write(&self, key: &[u8]) -> RwLockWriteGuard<'_, Shard> {
+        self.shard(key).write()
+    }
+
+    /// Write locks the shards holding the keys, in the order of the shards so that two
+    /// callers can't each hold a lock the other waits for.
+    pub fn write_many<'k>(&self, keys: impl IntoIterator<Item = &'k [u8]>) -> ShardsWriteGuard<'_> {
+        ShardsWriteGuard {
+            guards: self
+                .shard_indexes(keys)
+                .into_iter()
+                .map(|index| (index, self.shards[index].write()))
+                .collect(),
+            shards: self.shards.len(),
+        }
+    }
+
+    /// Read locks the shards holding the keys like `write_many`, so that they are seen as
+    /// they were at one point in time.
+    pub fn read_many<'k>(&self, keys: impl IntoIterator<Item = &'k [u8]>) -> ShardsReadGuard<'_> {
+        ShardsReadGuard {
+            guards: self
+                .shard_indexes(keys)
+                .into_iter()
+                .map(|index| (index, self.shards[index].read()))
+                .collect(),
+            shards: self.shards.len(),
+        }
+    }
+
+    /// Returns the indexes of the shards holding the keys, sorted and without duplicates.
+    fn shard_indexes<'k>(&self, keys: impl IntoIterator<Item = &'k [u8]>) -> Vec<usize> {
+        let mut indexes: Vec<_> = keys
+            .into_iter()
+            .map(|key| shard_index(key, self.shards.len()))
+            .collect();
+        indexes.sort_unstable();
+        indexes.dedup();
+        indexes
+    }
+
+    /// Returns every shard, e.g. to visit the whole keyspace one shard at a time.
+    pub fn shards(&self) -> &[RwLock<Shard>] {
+        &self.shards
+    }
+
+    /// Returns the number of keys, including expired keys that haven't been removed yet.
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|shard| shard.read().len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Iterates over the keys that haven't expired as of now, with their values and time to
+    /// live. The keys are those of a snapshot taken when this is called, so writers aren't
+    /// held up while iterating, and don't affect what is yielded.
+    pub fn snapshot_iter(&self) -> impl Iterator<Item = SnapshotEntry> + Send {
+        self.snapshot().export(SystemTime::now())
+    }
+
+    /// Returns the number of keys hashing to the slot, including expired keys that haven't
+    /// been removed yet. Always 0 unless the store indexes slots.
+    pub fn count_keys_in_slot(&self, slot: u16) -> usize {
+        self.shards
+            .iter()
+            .map(|shard| shard.read().keys_in_slot(slot).count())
+            .sum()
+    }
+
+    /// Returns up to `count` keys hashing to the slot, e.g. to migrate them to another node.
+    /// Always empty unless the store indexes slots.
+    pub fn keys_in_slot(&self, slot: u16, count: usize) -> Vec<Key> {
+        let mut keys = Vec::new();
+        for shard in self.shards.iter() {
+            let left = count - keys.len();
+            if left == 0 {
+                break;
+            }
+            keys.extend(shard.read().keys_in_slot(slot).take(left).cloned());
+        }
+        keys
+    }
+
+    /// Returns the earliest deadline of any key.
+    pub fn next_deadline(&self) -> Option<SystemTime> {
+        self.shards
+            .iter()
+            .filter_map(|shard| shard.read().next_deadline())
+            .min()
+    }
+
+    /// Returns the keyspace as it is now, e.g. to save it while commands keep running.
+    ///
+    /// Writers only wait while every shard is read locked to copy its root, and the copy
+    /// doesn't clone any key or value.
+    pub fn snapshot(&self) -> Snapshot {
+        let guards: Vec<_> = self.shards.iter().map(|shard| shard.read()).collect();
+        Snapshot {
+            shards: guards.iter().map(|shard| (*shard).clone()).collect(),
+        }
+    }
+
+    fn shard(&self, key: &[u8]) -> &RwLock<Shard> {
+        &self.shards[shard_index(key, self.shards.len())]
+    }
+}
+
+/// The shards locked by `Store::write_many`.
+pub struct ShardsWriteGuard<'a> {
+    guards: Vec<(usize, RwLockWriteGuard<'a, Shard>)>,
+    shards: usize,
+}
+
+impl ShardsWriteGuard<'_> {
+    /// Returns the shard holding the key.
+    ///
+    /// # Panics
+    ///
+    /// If the key wasn't one of those the shards were locked for.
+    pub fn shard(&mut self, key: &[u8]) -> &mut Shard {
+        let index = shard_index(key, self.shards);
+        let position = self
+            .guards
+            .binary_search_by_key(&index, |(index, _)| *index)
+            .expect("Shard of the key isn't locked");
+        &mut self.guards[position].1
+    }
+}
+
+/// The shards locked by `Store::read_many`.
+pub struct ShardsReadGuard<'a> {
+    guards: Vec<(usize, RwLockReadGuard<'a, Shard>)>,
+    shards: usize,
+}
+
+impl ShardsReadGuard<'_> {
+    /// Returns the shard holding the key.
+    ///
+    /// # Panics
+    ///
+    /// If the key wasn't one of those the shards were locked for.
+    pub fn shard(&self, key: &[u8]) -> &Shard {
+        let index = shard_index(key, self.shards);
+        let position = self
+            .guards
+            .binary_search_by_key(&index, |(index, _)| *index)
+            .expect("Shard of the key isn't locked");
+        &self.guards[position].1
+    }
+}
+
+fn shard_index(key: &[u8], shards: usize) -> usize {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    hasher.finish() as usize % shards
+}
+
+impl FromIterator<(Key, StoredData)> for Store {
+    fn from_iter<I: IntoIterator<Item = (Key, StoredData)>>(iter: I) -> Self {
+        let store = Self::default();
+        for (key, data) in iter {
+            store.write(&key).insert(key, data);
+        }
+        store
+    }
+}
+
+/// A value as exported from a snapshot. More types may be added as the store learns to hold
+/// them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SnapshotValue {
+    String(BulkString),
+    List(VecDeque<BulkString>),
+    Hash(std::collections::HashMap<BulkString, BulkString>),
+    Set(HashSet<BulkString>),
+    SortedSet(SortedSet),
+    Stream(Stream),
+}
+
+impl From<StoredValue> for SnapshotValue {
+    fn from(value: StoredValue) -> Self {
+        match value {
+            StoredValue::String(s) => Self::String(s),
+            StoredValue::List(list) => Self::List(list.into()),
+            StoredValue::Hash(hash) => Self::Hash(hash.into()),
+            StoredValue::Set(set) => Self::Set(set),
+            StoredValue::SortedSet(zset) => Self::SortedSet(zset),
+            StoredValue::Stream(stream) => Self::Stream(stream),
+        }
+    }
+}
+
+/// A key of a snapshot, e.g. for exports or backups other than RDB and JSON.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnapshotEntry {
+    pub key: Key,
+    pub value: SnapshotValue,
+
+    /// Time left before the key expires, `None` if it doesn't.
+    pub ttl: Option<Duration>,
+}
+
+/// A point in time view of the store, unaffected by later writes.
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    shards: Box<[Shard]>,
+}
+
+impl Snapshot {
+    /// Returns the number of keys, including expired keys that weren't removed yet.
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|shard| shard.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, key: &[u8]) -> Option<&StoredData> {
+        self.shards[shard_index(key, self.shards.len())].get(key)
+    }
+
+    /// Iterates over every entry, shard by shard.
+    pub fn iter(&self) -> impl Iterator<Item = (&Key, &StoredData)> {
+        self.shards.iter().flat_map(|shard| shard.iter())
+    }
+
+    /// Iterates over the entries that haven't expired at `now`, with their time to live then.
+    pub fn export(self, now: SystemTime) -> impl Iterator<Item = SnapshotEntry> + Send {
+        self.into_entries().filter_map(move |(key, data)| {
+            let ttl = match data.deadline {
+                Some(deadline) => Some(deadline.duration_since(now).ok()?),
+                None => None,
+            };
+            Some(SnapshotEntry {
+                key,
+                value: data.value.into(),
+                ttl,
+            })
+        })
+    }
+
+    /// Iterates over every entry, shard by shard, without borrowing the snapshot, e.g. to
+    /// generate a reply while it is being written.
+    pub fn into_entries(self) -> impl Iterator<Item = (Key, StoredData)> + Send {
+        self.shards
+            .into_vec()
+            .into_iter()
+            .flat_map(|shard| shard.entries)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn keys_are_spread_across_shards() {
+        let store: Store = (0..100)
+            .map(|i| {
+                (
+                    Key::from(format!("key:{i}").as_str()),
+                    StoredData::new("value".into(), None),
+                )
+            })
+            .collect();
+
+        assert_eq!(store.len(), 100);
+        let used = store
+            .shards()
+            .iter()
+            .filter(|shard| !shard.read().is_empty())
+            .count();
+        assert!(used > 1);
+
+        let key = b"key:42".as_slice();
+        assert!(store.read(key).contains_key(key));
+    }
+
+    #[test]
+    fn concurrent_writers() {
+        let store = Arc::new(Store::default());
+
+        let writers: Vec<_> = (0..4)
+            .map(|t| {
+                let store = store.clone();
+                thread::spawn(move || {
+                    for i in 0..250 {
+                        let key = Key::from(format!("{t}:{i}").as_str());
+                        store
+                            .write(&key)
+                            .insert(key, StoredData::new("value".into(), None));
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        assert_eq!(store.len(), 1000);
+    }
+
+    #[test]
+    fn write_many_locks_each_shard_once() {
+        let store = Store::new(4);
+        let keys: Vec<String> = (0..20).map(|i| format!("key:{i}")).collect();
+        let mut guard = store.write_many(keys.iter().map(|key| key.as_bytes()));
+        for key in &keys {
+            guard
+                .shard(key.as_bytes())
+                .insert(Key::from(key.as_str()), StoredData::new("v".into(), None));
+        }
+        drop(guard);
+
+        assert_eq!(store.len(), 20);
+
+        let guard = store.read_many(keys.iter().map(|key| key.as_bytes()));
+        for key in &keys {
+            assert!(guard.shard(key.as_bytes()).get(key.as_bytes()).is_some());
+        }
+    }
+
+    #[test]
+    fn snapshot_is_unaffected_by_writes() {
+        let store: Store = (0..10)
+            .map(|i| {
+                (
+                    Key::from(format!("key:{i}").as_str()),
+                    StoredData::new("old".into(), None),
+                )
+            })
+            .collect();
+
+        let snapshot = store.snapshot();
+        for i in 0..10 {
+            let key = Key::from(format!("key:{i}").as_str());
+            store
+                .write(&key)
+                .insert(key, StoredData::new("new".into(), None));
+        }
+        let key = b"key:0".as_slice();
+        store.write(key).remove(key);
+        let key = Key::from("added");
+        store
+            .write(&key)
+            .insert(key, StoredData::new("new".into(), None));
+
+        assert_eq!(snapshot.len(), 10);
+        assert!(snapshot.iter().all(|(_, data)| data.value == "old".into()));
+        assert!(snapshot.get(b"key:0").is_some());
+        assert!(snapshot.get(b"added").is_none());
+        assert_eq!(store.len(), 10);
+        assert!(store.snapshot().get(b"added").is_some());
+    }
+
+    #[test]
+    fn export_skips_expired_keys() {
+        let now = SystemTime::now();
+        let store: Store = [
+            ("a", None),
+            ("b", Some(now + Duration::from_secs(10))),
+            ("c", Some(now - Duration::from_secs(1))),
+        ]
+        .into_iter()
+        .map(|(key, deadline)| (Key::from(key), StoredData::new("v".into(), deadline)))
+        .collect();
+
+        let snapshot = store.snapshot();
+        store.write(b"a").remove(b"a");
+        let mut entries: Vec<_> = snapshot.export(now).collect();
+        entries.sort_by(|a, b| a.key.cmp(&b.key));
+        let entry = |key, ttl| SnapshotEntry {
+            key: Key::from(key),
+            value: SnapshotValue::String("v".into()),
+            ttl,
+        };
+        assert_eq!(
+            entries,
+            [entry("a", None), entry("b", Some(Duration::from_secs(10)))]
+        );
+        assert_eq!(store.snapshot_iter().count(), 1);
+    }
+
+    #[test]
+    fn deadlines_follow_writes() {
+        let now = SystemTime::now();
+        let at = |secs| now + Duration::from_secs(secs);
+        let mut shard = Shard::default();
+        shard.insert(Key::from("a"), StoredData::new("v".into(), Some(at(30))));
+        shard.insert(Key::from("b"), StoredData::new("v".into(), Some(at(10))));
+        shard.insert(Key::from("c"), StoredData::new("v".into(), Some(at(20))));
+        shard.insert(Key::from("d"), StoredData::new("v".into(), None));
+        assert_eq!(shard.next_deadline(), Some(at(10)));
+
+        // Overwriting with the same or no deadline, and removing, update the index
+        shard.insert(Key::from("b"), StoredData::new("v".into(), Some(at(10))));
+        shard.insert(Key::from("c"), StoredData::new("v".into(), None));
+        shard.remove(b"a");
+        assert_eq!(shard.deadlines.len(), 1);
+
+        // As does changing the deadline in place
+        assert!(shard.set_deadline(b"d", Some(at(5))));
+        assert_eq!(shard.next_deadline(), Some(at(5)));
+        assert!(shard.set_deadline(b"d", None));
+        assert!(!shard.set_deadline(b"a", Some(at(5))));
+        assert_eq!(shard.deadlines.len(), 1);
+
+        let expired = shard.remove_expired(at(15), 10);
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].0, Key::from("b"));
+        assert_eq!(shard.next_deadline(), None);
+        assert_eq!(shard.len(), 2);
+    }
+
+    #[test]
+    fn slot_index_follows_writes() {
+        let store = Store::with_slot_index(4);
+        // Keys sharing a hash tag share a slot.
+        let slot = key_hash_slot(b"user");
+        for key in ["{user}:1", "{user}:2", "{user}:3", "other"] {
+            let key = Key::from(key);
+            store
+                .write(&key)
+                .insert(key, StoredData::new("v".into(), None));
+        }
+        let key = Key::from("{user}:1");
+        store
+            .write(&key)
+            .insert(key, StoredData::new("new".into(), None));
+        store.write(b"{user}:2").remove(b"{user}:2");
+
+        assert_eq!(store.count_keys_in_slot(slot), 2);
+        let mut keys = store.keys_in_slot(slot, 10);
+        keys.sort();
+        assert_eq!(keys, ["{user}:1", "{user}:3"].map(Key::from).to_vec());
+        assert_eq!(store.keys_in_slot(slot, 1).len(), 1);
+        assert_eq!(store.count_keys_in_slot(key_hash_slot(b"other")), 1);
+
+        let store: Store = [(Key::from("{user}:1"), StoredData::new("v".into(), None))]
+            .into_iter()
+            .collect();
+        assert_eq!(store.count_keys_in_slot(slot), 0);
+    }
+
+    #[test]
+    fn remove_expired_stops_at_limit() {
+        let now = SystemTime::now();
+        let mut shard = Shard::default();
+        for i in 0..10 {
+            let deadline = now - Duration::from_secs(10 - i);
+            shard.insert(
+                Key::from(format!("key:{i}").as_str()),
+                StoredData::new("v".into(), Some(deadline)),
+            );
+        }
+
+        let expired: Vec<_> = shard
+            .remove_expired(now, 4)
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect();
+        assert_eq!(
+            expired,
+            ["key:0", "key:1", "key:2", "key:3"].map(Key::from).to_vec()
+        );
+        assert_eq!(shard.len(), 6);
+    }
+}
@@ -0,0 +1,125 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::Bound;
+
+use super::resp::BulkString;
+
+/// ID of a stream entry: the milliseconds it was added at, and a sequence number telling
+/// apart entries added in the same millisecond.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StreamId {
+    pub ms: u64,
+    pub seq: u64,
+}
+
+impl StreamId {
+    pub const MIN: Self = Self { ms: 0, seq: 0 };
+    pub const MAX: Self = Self {
+        ms: u64::MAX,
+        seq: u64::MAX,
+    };
+}
+
+impl fmt::Display for StreamId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.ms, self.seq)
+    }
+}
+
+/// Field and value pairs of a stream entry, in the order they were added.
+pub type StreamFields = Vec<(BulkString, BulkString)>;
+
+/// Entries in increasing ID order. The last ID is kept apart from the entries, since new
+/// IDs must be greater than every ID ever added, even once entries are removed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Stream {
+    entries: BTreeMap<StreamId, StreamFields>,
+    last_id: StreamId,
+}
+
+impl Stream {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of entries.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn last_id(&self) -> StreamId {
+        self.last_id
+    }
+
+    /// Adds the entry, unless its ID isn't greater than the last one. Returns whether it
+    /// was added.
+    pub fn insert(&mut self, id: StreamId, fields: StreamFields) -> bool {
+        if id <= self.last_id {
+            return false;
+        }
+        self.entries.insert(id, fields);
+        self.last_id = id;
+        true
+    }
+
+    /// Raises the last ID to `id` if it is greater, e.g. to that of a stream whose newest
+    /// entries were removed before it was saved, so their IDs aren't given out again.
+    pub fn raise_last_id(&mut self, id: StreamId) {
+        self.last_id = self.last_id.max(id);
+    }
+
+    /// Iterates over the entries with IDs from `start` to `end`, both inclusive.
+    pub fn range(
+        &self,
+        start: StreamId,
+        end: StreamId,
+    ) -> impl DoubleEndedIterator<Item = (&StreamId, &StreamFields)> {
+        // A start after the end would make the map panic.
+        let range = (start <= end).then(|| self.entries.range(start..=end));
+        range.into_iter().flatten()
+    }
+
+    /// Iterates over the entries with IDs greater than `id`.
+    pub fn after(
+        &self,
+        id: StreamId,
+    ) -> impl DoubleEndedIterator<Item = (&StreamId, &StreamFields)> {
+        self.entries.range((Bound::Excluded(id), Bound::Unbounded))
+    }
+
+    /// Iterates over the entries from the oldest.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (&StreamId, &StreamFields)> {
+        self.entries.iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn ids_only_increase() {
+        let id = |ms, seq| StreamId { ms, seq };
+        let mut stream = Stream::new();
+        assert!(!stream.insert(id(0, 0), vec![]));
+        assert!(stream.insert(id(1, 0), vec![("f".into(), "v".into())]));
+        assert!(stream.insert(id(1, 1), vec![]));
+        assert!(!stream.insert(id(1, 1), vec![]));
+        assert!(!stream.insert(id(0, 5), vec![]));
+
+        assert_eq!(stream.len(), 2);
+        assert_eq!(stream.last_id().to_string(), "1-1");
+        let ids: Vec<_> = stream.iter().map(|(id, _)| *id).collect();
+        assert_eq!(ids, vec![id(1, 0), id(1, 1)]);
+
+        let range: Vec<_> = stream.range(id(1, 1), StreamId::MAX).collect();
+        assert_eq!(range, vec![(&id(1, 1), &vec![])]);
+        assert_eq!(stream.range(id(1, 1), id(1, 0)).count(), 0);
+        let after: Vec<_> = stream.after(id(1, 0)).map(|(id, _)| *id).collect();
+        assert_eq!(after, vec![id(1, 1)]);
+    }
+}
@@ -0,0 +1,186 @@
+//! Helpers for exercising commands, handlers and clients without real sockets.
+//!
+//! Available to the crate's own tests, and to other crates with the `test-util` feature.
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use parking_lot::RwLock;
+
+use super::{
+    acl::{AccessControl, DEFAULT_USER},
+    clients::{ClientRegistry, ClientState},
+    clock::TestClock,
+    config::ServerConfig,
+    handler::CommandHandler,
+    memory::MemoryTracker,
+    persistence::PersistenceState,
+    resp::{Array, BulkString, SimpleError, Value},
+    session::{Request, Responder, Response, Session, SessionError},
+    store::Store,
+};
+
+/// Bytes an in-memory session buffers before writes wait for the other end to read.
+const DUPLEX_BUF_LEN: usize = 64 * 1024;
+
+/// A Responder that expects a fixed script of requests, answering each with its response.
+///
+/// Panics on a request other than the next expected one, and fails with
+/// `SessionError::NoResponse` once the script has run out.
+#[derive(Debug, Default)]
+pub struct ScriptedResponder {
+    script: VecDeque<(Request, Response)>,
+}
+
+impl ScriptedResponder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `req` to the script, to be answered with `resp`.
+    pub fn expect(mut self, req: impl Into<Request>, resp: impl Into<Response>) -> Self {
+        self.script.push_back((req.into(), resp.into()));
+        self
+    }
+
+    /// Returns true once every expected request was made.
+    pub fn is_done(&self) -> bool {
+        self.script.is_empty()
+    }
+}
+
+#[async_trait]
+impl Responder for ScriptedResponder {
+    async fn respond(&mut self, req: Request) -> Result<Response, SessionError> {
+        let (expected_req, resp) = self.script.pop_front().ok_or(SessionError::NoResponse)?;
+        assert_eq!(req, expected_req);
+        Ok(resp)
+    }
+}
+
+/// Returns both ends of an in-memory connection, e.g. one for a client and one played by
+/// the test as the server.
+pub fn session_pair() -> (Session, Session) {
+    let (a, b) = tokio::io::duplex(DUPLEX_BUF_LEN);
+    (Session::new(a), Session::new(b))
+}
+
+/// Returns a command as a client sends it, an array of bulk strings.
+pub fn command<'a>(args: impl IntoIterator<Item = &'a str>) -> Value {
+    let args = args
+        .into_iter()
+        .map(|arg| Value::BulkString(arg.into()))
+        .collect();
+    Value::Array(Array::new(args))
+}
+
+/// Returns a command as a request to a Responder.
+pub fn request<'a>(args: impl IntoIterator<Item = &'a str>) -> Request {
+    Request::new(command(args))
+}
+
+pub fn simple(s: &str) -> Value {
+    Value::SimpleString(s.into())
+}
+
+pub fn bulk(s: &str) -> Value {
+    Value::BulkString(BulkString::from(s))
+}
+
+pub fn error(s: &str) -> Value {
+    Value::SimpleError(SimpleError::from(s.to_string()))
+}
+
+/// Returns a handler over an empty store with the default configuration.
+pub fn command_handler() -> CommandHandler {
+    CommandHandler::new(
+        Arc::new(Store::default()),
+        Arc::new(ServerConfig::default()),
+        Arc::new(PersistenceState::new()),
+        Arc::new(MemoryTracker::default()),
+        Arc::new(RwLock::new(AccessControl::new())),
+        Arc::new(ClientRegistry::new()),
+        None,
+    )
+}
+
+/// Returns a handler like `command_handler`, whose keys expire by the returned clock.
+pub fn command_handler_with_clock() -> (CommandHandler, Arc<TestClock>) {
+    let clock = Arc::new(TestClock::default());
+    (command_handler().with_clock(clock.clone()), clock)
+}
+
+/// Returns a client logged in as the default user.
+pub fn client_state() -> ClientState {
+    ClientState::new(1, Some(DEFAULT_USER.to_string()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::cmd::Command;
+    use super::*;
+
+    #[tokio::test]
+    async fn scripted_responder_follows_script() {
+        let mut responder = ScriptedResponder::new()
+            .expect(request(["PING"]), simple("PONG"))
+            .expect(
+                request(["GET", "key"]),
+                Value::BulkString(BulkString::null()),
+            );
+
+        let resp = responder.respond(request(["PING"])).await;
+        assert!(resp
+            .expect("Respond unexpected error")
+            .is_simple_string("PONG"));
+        let resp = responder.respond(request(["GET", "key"])).await;
+        assert!(resp
+            .expect("Respond unexpected error")
+            .is(Value::BulkString(BulkString::null())));
+        assert!(responder.is_done());
+        assert!(matches!(
+            responder.respond(request(["PING"])).await,
+            Err(SessionError::NoResponse)
+        ));
+    }
+
+    #[tokio::test]
+    async fn session_pair_connects_both_ends() {
+        let (mut client, mut server) = session_pair();
+        let serve = tokio::spawn(async move {
+            let req = server
+                .receive_request()
+                .await
+                .expect("Receive unexpected error")
+                .expect("Request expected");
+            let resp = match req.into_command().expect("Parse unexpected error") {
+                Command::Ping(_) => simple("PONG"),
+                _ => error("ERR unexpected command"),
+            };
+            server.send_response(resp.into()).await
+        });
+
+        let resp = client
+            .send_request_and_wait_reply(request(["PING"]))
+            .await
+            .expect("Send unexpected error");
+        assert!(resp.is_simple_string("PONG"));
+        serve
+            .await
+            .expect("Join unexpected error")
+            .expect("Send response unexpected error");
+    }
+
+    #[test]
+    fn command_handler_handles_commands() {
+        let mut handler = command_handler();
+        let set = Command::try_from(command(["SET", "key", "value"])).expect("Parse error");
+        let get = Command::try_from(command(["GET", "key"])).expect("Parse error");
+
+        let resp = handler.handle(set, &mut client_state());
+        assert_eq!(resp.expect("Handle set unexpected error"), simple("OK"));
+        let resp = handler.handle(get, &mut client_state());
+        assert_eq!(resp.expect("Handle get unexpected error"), bulk("value"));
+    }
+}
@@ -0,0 +1,102 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::{Duration, SystemTime};
+
+use parking_lot::Mutex;
+
+/// Failed attempts an address gets before it has to wait between attempts.
+pub const AUTH_FREE_ATTEMPTS: u32 = 3;
+
+/// Wait after the first failure past the free ones, doubled on every further failure.
+pub const AUTH_BASE_DELAY: Duration = Duration::from_secs(1);
+
+/// Longest wait, which amounts to a temporary ban of the address.
+pub const AUTH_MAX_DELAY: Duration = Duration::from_secs(300);
+
+#[derive(Debug, Clone, Copy)]
+struct Failures {
+    count: u32,
+
+    /// Time before which attempts are refused without checking the password.
+    blocked_until: SystemTime,
+}
+
+/// Failed AUTH attempts per client address, making brute-force guessing of passwords slow.
+///
+/// After `AUTH_FREE_ATTEMPTS` failures an address has to wait before trying again, the
+/// wait doubling with every failure up to `AUTH_MAX_DELAY`. Attempts made while waiting
+/// are refused outright, and a successful one forgets the failures.
+#[derive(Debug, Default)]
+pub struct AuthThrottle {
+    failures: Mutex<HashMap<IpAddr, Failures>>,
+}
+
+impl AuthThrottle {
+    /// Returns how long the address still has to wait before it can try again, if at all.
+    pub fn blocked_for(&self, addr: IpAddr, now: SystemTime) -> Option<Duration> {
+        let failures = self.failures.lock();
+        let blocked_until = failures.get(&addr)?.blocked_until;
+        blocked_until
+            .duration_since(now)
+            .ok()
+            .filter(|wait| !wait.is_zero())
+    }
+
+    /// Records a failed attempt from the address, blocking it if it failed too often.
+    pub fn record_failure(&self, addr: IpAddr, now: SystemTime) {
+        let mut failures = self.failures.lock();
+        // Forget addresses that stopped trying, or every address ever seen would be kept.
+        failures.retain(|_, f| now < f.blocked_until + AUTH_MAX_DELAY);
+        let f = failures.entry(addr).or_insert(Failures {
+            count: 0,
+            blocked_until: now,
+        });
+        f.count = f.count.saturating_add(1);
+        if let Some(excess) = f.count.checked_sub(AUTH_FREE_ATTEMPTS + 1) {
+            let delay = AUTH_BASE_DELAY
+                .checked_mul(1 << excess.min(31))
+                .map_or(AUTH_MAX_DELAY, |delay| delay.min(AUTH_MAX_DELAY));
+            f.blocked_until = now + delay;
+        } else {
+            f.blocked_until = now;
+        }
+    }
+
+    /// Forgets the failures of the address after it authenticated.
+    pub fn record_success(&self, addr: IpAddr) {
+        self.failures.lock().remove(&addr);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn delays_escalate_after_free_attempts() {
+        let throttle = AuthThrottle::default();
+        let addr: IpAddr = "10.0.0.1".parse().unwrap();
+        let other: IpAddr = "10.0.0.2".parse().unwrap();
+        let now = SystemTime::UNIX_EPOCH;
+
+        for _ in 0..AUTH_FREE_ATTEMPTS {
+            throttle.record_failure(addr, now);
+            assert_eq!(throttle.blocked_for(addr, now), None);
+        }
+        throttle.record_failure(addr, now);
+        assert_eq!(throttle.blocked_for(addr, now), Some(AUTH_BASE_DELAY));
+        throttle.record_failure(addr, now);
+        assert_eq!(throttle.blocked_for(addr, now), Some(AUTH_BASE_DELAY * 2));
+        assert_eq!(throttle.blocked_for(other, now), None);
+
+        for _ in 0..20 {
+            throttle.record_failure(addr, now);
+        }
+        assert_eq!(throttle.blocked_for(addr, now), Some(AUTH_MAX_DELAY));
+        assert_eq!(throttle.blocked_for(addr, now + AUTH_MAX_DELAY), None);
+
+        throttle.record_success(addr);
+        throttle.record_failure(addr, now);
+        assert_eq!(throttle.blocked_for(addr, now), None);
+    }
+}
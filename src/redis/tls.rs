@@ -0,0 +1,167 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use parking_lot::RwLock;
+use thiserror::Error;
+use tokio_rustls::rustls::{
+    self,
+    pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer},
+    server::WebPkiClientVerifier,
+    RootCertStore,
+};
+
+pub use tokio_rustls::{TlsAcceptor, TlsConnector};
+
+use super::config::{ConfigValues, TlsAuthClients, TlsControl};
+
+#[derive(Debug, Error)]
+pub enum TlsError {
+    #[error("Missing TLS option {0}")]
+    MissingOption(&'static str),
+
+    #[error("Unable to load {path:?}: {source}")]
+    Pem {
+        path: PathBuf,
+        source: rustls::pki_types::pem::Error,
+    },
+
+    #[error(transparent)]
+    Rustls(#[from] rustls::Error),
+
+    #[error(transparent)]
+    ClientVerifier(#[from] rustls::server::VerifierBuilderError),
+}
+
+/// Files needed to serve or open TLS connections.
+#[derive(Debug, Clone)]
+pub struct TlsFiles<'a> {
+    pub cert_file: Option<&'a Path>,
+    pub key_file: Option<&'a Path>,
+    pub ca_cert_file: Option<&'a Path>,
+}
+
+impl<'a> TlsFiles<'a> {
+    pub fn from_values(values: &'a ConfigValues) -> Self {
+        Self {
+            cert_file: values.tls_cert_file.as_deref(),
+            key_file: values.tls_key_file.as_deref(),
+            ca_cert_file: values.tls_ca_cert_file.as_deref(),
+        }
+    }
+}
+
+/// The TLS side of the listeners, which can be rebuilt while serving so certificates are
+/// rotated without a restart. Connections get the config current when they are accepted.
+#[derive(Debug)]
+pub struct ServerTls {
+    config: RwLock<Arc<rustls::ServerConfig>>,
+}
+
+impl ServerTls {
+    pub fn new(files: TlsFiles<'_>, auth_clients: TlsAuthClients) -> Result<Self, TlsError> {
+        Ok(Self {
+            config: RwLock::new(server_config(files, auth_clients)?),
+        })
+    }
+
+    /// Returns an acceptor with the current config.
+    pub fn acceptor(&self) -> TlsAcceptor {
+        TlsAcceptor::from(self.config.read().clone())
+    }
+
+    /// Replaces the config for later connections. The current one is kept if the files
+    /// can't be loaded.
+    pub fn reload(
+        &self,
+        files: TlsFiles<'_>,
+        auth_clients: TlsAuthClients,
+    ) -> Result<(), TlsError> {
+        let config = server_config(files, auth_clients)?;
+        *self.config.write() = config;
+        Ok(())
+    }
+}
+
+impl TlsControl for ServerTls {
+    fn reload(&self, values: &ConfigValues) -> Result<(), String> {
+        ServerTls::reload(self, TlsFiles::from_values(values), values.tls_auth_clients)
+            .map_err(|e| format!("Unable to update TLS configuration: {e}"))
+    }
+}
+
+/// Builds the config of an acceptor that wraps accepted TCP streams in TLS with the given
+/// certificate and key. Unless `auth_clients` is `No`, clients must present a certificate signed
+/// by the CA, which is verified during the handshake before the connection can issue any command.
+fn server_config(
+    files: TlsFiles<'_>,
+    auth_clients: TlsAuthClients,
+) -> Result<Arc<rustls::ServerConfig>, TlsError> {
+    let cert_file = files
+        .cert_file
+        .ok_or(TlsError::MissingOption("tls-cert-file"))?;
+    let key_file = files
+        .key_file
+        .ok_or(TlsError::MissingOption("tls-key-file"))?;
+
+    let builder = rustls::ServerConfig::builder();
+    let builder = match auth_clients {
+        TlsAuthClients::No => builder.with_no_client_auth(),
+        TlsAuthClients::Yes | TlsAuthClients::Optional => {
+            let ca_cert_file = files
+                .ca_cert_file
+                .ok_or(TlsError::MissingOption("tls-ca-cert-file"))?;
+            let verifier = WebPkiClientVerifier::builder(Arc::new(load_roots(ca_cert_file)?));
+            let verifier = if auth_clients == TlsAuthClients::Optional {
+                verifier.allow_unauthenticated().build()?
+            } else {
+                verifier.build()?
+            };
+            builder.with_client_cert_verifier(verifier)
+        }
+    };
+    let config = builder.with_single_cert(load_certs(cert_file)?, load_key(key_file)?)?;
+
+    Ok(Arc::new(config))
+}
+
+/// Builds a connector that verifies the server against the CA. If a certificate and key are
+/// given they are presented to the server for mutual authentication.
+pub fn client_connector(files: TlsFiles<'_>) -> Result<TlsConnector, TlsError> {
+    let ca_cert_file = files
+        .ca_cert_file
+        .ok_or(TlsError::MissingOption("tls-ca-cert-file"))?;
+
+    let builder = rustls::ClientConfig::builder().with_root_certificates(load_roots(ca_cert_file)?);
+    let config = match (files.cert_file, files.key_file) {
+        (Some(cert_file), Some(key_file)) => {
+            builder.with_client_auth_cert(load_certs(cert_file)?, load_key(key_file)?)?
+        }
+        _ => builder.with_no_client_auth(),
+    };
+
+    Ok(TlsConnector::from(Arc::new(config)))
+}
+
+fn load_roots(path: &Path) -> Result<RootCertStore, TlsError> {
+    let mut roots = RootCertStore::empty();
+    for cert in load_certs(path)? {
+        roots.add(cert)?;
+    }
+    Ok(roots)
+}
+
+fn load_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>, TlsError> {
+    CertificateDer::pem_file_iter(path)
+        .and_then(|certs| certs.collect())
+        .map_err(|source| TlsError::Pem {
+            path: path.to_path_buf(),
+            source,
+        })
+}
+
+fn load_key(path: &Path) -> Result<PrivateKeyDer<'static>, TlsError> {
+    PrivateKeyDer::from_pem_file(path).map_err(|source| TlsError::Pem {
+        path: path.to_path_buf(),
+        source,
+    })
+}
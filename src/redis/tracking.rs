@@ -0,0 +1,120 @@
+use std::collections::{HashMap, HashSet};
+
+use super::resp::{Array, BulkString, Push, Value};
+
+/// Channel that invalidation messages are published to for clients redirecting them to a
+/// RESP2 connection.
+pub const INVALIDATE_CHANNEL: &str = "__redis__:invalidate";
+
+/// Options a client enabled CLIENT TRACKING with.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct TrackingOptions {
+    /// Id of the client invalidation messages are sent to instead.
+    pub redirect: Option<u64>,
+
+    /// Notify on changes to any key matching the prefixes rather than only keys read.
+    pub bcast: bool,
+    pub prefixes: Vec<BulkString>,
+
+    /// Only track keys read right after CLIENT CACHING yes.
+    pub optin: bool,
+
+    /// Track keys read unless right after CLIENT CACHING no.
+    pub optout: bool,
+
+    /// Don't notify about keys the client changed itself.
+    pub noloop: bool,
+}
+
+/// Keys the server promised to send invalidation messages for, and to which clients.
+#[derive(Debug, Default)]
+pub struct TrackingTable {
+    /// Clients that read each key, for clients in the default mode.
+    keys: HashMap<BulkString, HashSet<u64>>,
+
+    /// Prefixes of every client in BCAST mode, no prefixes match every key.
+    bcast: HashMap<u64, Vec<BulkString>>,
+}
+
+impl TrackingTable {
+    /// Remembers that the client read the keys.
+    pub fn track(&mut self, id: u64, keys: &[BulkString]) {
+        for key in keys {
+            self.keys.entry(key.clone()).or_default().insert(id);
+        }
+    }
+
+    /// Registers the client as tracking the prefixes in BCAST mode, or unregisters it.
+    pub fn set_bcast(&mut self, id: u64, prefixes: Option<Vec<BulkString>>) {
+        match prefixes {
+            Some(prefixes) => self.bcast.insert(id, prefixes),
+            None => self.bcast.remove(&id),
+        };
+    }
+
+    /// Returns the clients to notify about a change to the key. Clients that read the key
+    /// are notified only once, until they read it again.
+    pub fn invalidate(&mut self, key: &BulkString) -> HashSet<u64> {
+        let mut ids = self.keys.remove(key).unwrap_or_default();
+        let bytes = key.as_bytes().unwrap_or_default();
+        for (id, prefixes) in &self.bcast {
+            let matches = prefixes.is_empty()
+                || prefixes
+                    .iter()
+                    .any(|prefix| bytes.starts_with(prefix.as_bytes().unwrap_or_default()));
+            if matches {
+                ids.insert(*id);
+            }
+        }
+        ids
+    }
+
+    /// Returns the number of keys tracked for clients in the default mode.
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+}
+
+/// Returns the invalidation message for the keys, as a push for RESP3 connections or as a
+/// message published to the invalidation channel otherwise.
+pub fn invalidation_message(keys: Vec<BulkString>, resp: u8) -> Value {
+    let keys = Value::Array(Array::new(
+        keys.into_iter().map(Value::BulkString).collect(),
+    ));
+    if resp >= 3 {
+        Value::Push(Push::new(vec![
+            Value::BulkString("invalidate".into()),
+            keys,
+        ]))
+    } else {
+        Value::Array(Array::new(vec![
+            Value::BulkString("message".into()),
+            Value::BulkString(INVALIDATE_CHANNEL.into()),
+            keys,
+        ]))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn invalidate_notifies_readers_once_and_bcast_prefixes() {
+        let mut table = TrackingTable::default();
+        table.track(1, &["user:1".into(), "other".into()]);
+        table.set_bcast(2, Some(vec!["user:".into()]));
+        table.set_bcast(3, Some(vec![]));
+
+        assert_eq!(table.invalidate(&"user:1".into()), HashSet::from([1, 2, 3]));
+        assert_eq!(table.invalidate(&"user:1".into()), HashSet::from([2, 3]));
+        assert_eq!(table.invalidate(&"other".into()), HashSet::from([1, 3]));
+
+        table.set_bcast(3, None);
+        assert_eq!(table.invalidate(&"nope".into()), HashSet::new());
+    }
+}
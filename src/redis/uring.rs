@@ -0,0 +1,184 @@
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{ready, Context, Poll};
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::TcpStream;
+use tokio_uring::BufResult;
+
+/// Size of the buffer reads are made into.
+const BUF_SIZE: usize = 16 * 1024;
+
+type Op<T> = Pin<Box<dyn Future<Output = BufResult<T, Vec<u8>>>>>;
+
+/// A socket read and written through io_uring, which sessions run over like any other
+/// stream. io_uring takes owned buffers, so a read fills a buffer of its own that is then
+/// handed out, and a write accepts the bytes right away and sends them before the next one.
+///
+/// Must be used from a task spawned with `tokio::task::spawn_local` inside
+/// `tokio_uring::start`.
+pub(crate) struct UringStream {
+    socket: Rc<tokio_uring::net::TcpStream>,
+    read: Option<Op<usize>>,
+    read_buf: Vec<u8>,
+    read_pos: usize,
+    write: Option<Op<()>>,
+    write_buf: Vec<u8>,
+}
+
+impl UringStream {
+    pub(crate) fn new(stream: TcpStream) -> io::Result<Self> {
+        // io_uring waits for readiness itself, a nonblocking socket would fail with EAGAIN.
+        let stream = stream.into_std()?;
+        stream.set_nonblocking(false)?;
+        Ok(Self {
+            socket: Rc::new(tokio_uring::net::TcpStream::from_std(stream)),
+            read: None,
+            read_buf: Vec::with_capacity(BUF_SIZE),
+            read_pos: 0,
+            write: None,
+            write_buf: Vec::new(),
+        })
+    }
+
+    /// Waits for the write in flight, if any, getting its buffer back.
+    fn poll_written(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let Some(op) = &mut self.write else {
+            return Poll::Ready(Ok(()));
+        };
+        let (res, buf) = ready!(op.as_mut().poll(cx));
+        self.write = None;
+        self.write_buf = buf;
+        Poll::Ready(res)
+    }
+}
+
+impl AsyncRead for UringStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            let unread = &this.read_buf[this.read_pos..];
+            if !unread.is_empty() {
+                let n = unread.len().min(buf.remaining());
+                buf.put_slice(&unread[..n]);
+                this.read_pos += n;
+                return Poll::Ready(Ok(()));
+            }
+
+            // A read cancelled by the caller stays in flight, and is picked up by the next one.
+            let op = this.read.get_or_insert_with(|| {
+                let socket = this.socket.clone();
+                let mut read_buf = std::mem::take(&mut this.read_buf);
+                read_buf.clear();
+                this.read_pos = 0;
+                Box::pin(async move { socket.read(read_buf).await })
+            });
+            let (res, read_buf) = ready!(op.as_mut().poll(cx));
+            this.read = None;
+            this.read_buf = read_buf;
+            if res? == 0 {
+                return Poll::Ready(Ok(()));
+            }
+        }
+    }
+}
+
+impl AsyncWrite for UringStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        ready!(this.poll_written(cx))?;
+
+        let socket = this.socket.clone();
+        let mut write_buf = std::mem::take(&mut this.write_buf);
+        write_buf.clear();
+        write_buf.extend_from_slice(buf);
+        this.write = Some(Box::pin(async move { socket.write_all(write_buf).await }));
+        // Polling submits the write, which finishes on its own. Only an error right away is
+        // reported for these bytes, a later one for the next write or flush.
+        match this.poll_written(cx) {
+            Poll::Ready(Err(e)) => Poll::Ready(Err(e)),
+            _ => Poll::Ready(Ok(buf.len())),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.get_mut().poll_written(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_written(cx))?;
+        Poll::Ready(this.socket.shutdown(std::net::Shutdown::Write))
+    }
+}
+
+impl Drop for UringStream {
+    fn drop(&mut self) {
+        // A pending read holds the socket open until it completes, which shutting down the
+        // reading side makes it do. A write in flight still goes out before the socket closes.
+        let _ = self.socket.shutdown(std::net::Shutdown::Read);
+    }
+}
+
+impl fmt::Debug for UringStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UringStream")
+            .field("reading", &self.read.is_some())
+            .field("writing", &self.write.is_some())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    use super::super::resp::Value;
+    use super::super::session::{Request, Response, Session};
+    use super::*;
+
+    #[test]
+    fn session_runs_over_io_uring() {
+        tokio_uring::start(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let mut client = TcpStream::connect(listener.local_addr().unwrap())
+                .await
+                .unwrap();
+            let (accepted, _) = listener.accept().await.unwrap();
+            let mut session = Session::from_stream(UringStream::new(accepted).unwrap());
+
+            client.write_all(b"*1\r\n$4\r\nPING\r\n").await.unwrap();
+            let request = session.receive_request().await.unwrap();
+            assert_eq!(
+                request,
+                Some(Request::new(Value::Array(
+                    vec![Value::BulkString("PING".into())].into()
+                )))
+            );
+
+            session
+                .send_response(Response::new(Value::SimpleString("PONG".into())))
+                .await
+                .unwrap();
+            let mut buf = [0; 7];
+            client.read_exact(&mut buf).await.unwrap();
+            assert_eq!(&buf, b"+PONG\r\n");
+
+            // Closing the session closes the socket
+            drop(session);
+            assert_eq!(client.read(&mut buf).await.unwrap(), 0);
+        });
+    }
+}
@@ -0,0 +1,352 @@
+use std::cmp::Ordering;
+use std::collections::{btree_set, BTreeSet, HashMap};
+
+use super::listpack::{Listpack, ListpackLimits, Pairs};
+use super::resp::BulkString;
+
+/// Score of a sorted set member. Ordered with `f64::total_cmp` so that it can key ordered
+/// collections, which is fine since NaN scores are never stored.
+#[derive(Debug, Clone, Copy)]
+pub struct Score(pub f64);
+
+impl PartialEq for Score {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Score {}
+
+impl PartialOrd for Score {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Score {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+/// Members ordered by score, and by their bytes for equal scores.
+///
+/// A small sorted set is packed into a listpack, in order, each member followed by its
+/// score as 8 bytes. Once it passes its `ListpackLimits` it is converted for good to a map
+/// looking up the score of a member and an ordered set to walk ranges in, like the dict and
+/// skiplist of Redis.
+#[derive(Debug, Clone, Default)]
+pub struct SortedSet {
+    members: Members,
+}
+
+#[derive(Debug, Clone)]
+enum Members {
+    Listpack(Listpack),
+    Skiplist {
+        scores: HashMap<BulkString, Score>,
+        ordered: BTreeSet<(Score, BulkString)>,
+    },
+}
+
+impl Default for Members {
+    fn default() -> Self {
+        Self::Listpack(Listpack::new())
+    }
+}
+
+impl SortedSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of members.
+    pub fn len(&self) -> usize {
+        match &self.members {
+            Members::Listpack(lp) => lp.len() / 2,
+            Members::Skiplist { scores, .. } => scores.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the encoding reported by OBJECT ENCODING.
+    pub fn encoding(&self) -> &'static str {
+        match &self.members {
+            Members::Listpack(_) => "listpack",
+            Members::Skiplist { .. } => "skiplist",
+        }
+    }
+
+    /// Returns the number of bytes taken by the members and their scores.
+    pub fn bytes(&self) -> usize {
+        match &self.members {
+            Members::Listpack(lp) => std::mem::size_of::<Listpack>() + lp.bytes(),
+            // Every member is held by both the map and the ordered set.
+            Members::Skiplist { scores, .. } => {
+                let element = std::mem::size_of::<BulkString>();
+                scores
+                    .keys()
+                    .map(|member| 2 * element + 2 * bytes(member).len() + 16)
+                    .sum()
+            }
+        }
+    }
+
+    pub fn score(&self, member: &BulkString) -> Option<f64> {
+        match &self.members {
+            Members::Listpack(lp) => position(lp, member).map(|(_, score)| score),
+            Members::Skiplist { scores, .. } => scores.get(member).map(|score| score.0),
+        }
+    }
+
+    /// Sets the score of the member, returning its previous score if it was in the set.
+    ///
+    /// A packed set is converted first if the member is longer than `limits` allow, or if
+    /// the set would have too many members.
+    pub fn insert(
+        &mut self,
+        member: BulkString,
+        score: f64,
+        limits: ListpackLimits,
+    ) -> Option<f64> {
+        // Adding 0 turns -0 into 0, which would otherwise order before it.
+        let score = Score(score + 0.0);
+        if let Members::Listpack(lp) = &self.members {
+            let len = self.len() + usize::from(position(lp, &member).is_none());
+            if !limits.allows(len, &[bytes(&member)]) {
+                self.convert();
+            }
+        }
+
+        match &mut self.members {
+            Members::Listpack(lp) => {
+                let old = position(lp, &member).map(|(i, old)| {
+                    lp.remove(2 * i);
+                    lp.remove(2 * i);
+                    old
+                });
+                let member = bytes(&member);
+                let i = lp
+                    .pairs()
+                    .position(|(m, s)| (read_score(s), m) > (score, member))
+                    .unwrap_or(lp.len() / 2);
+                lp.insert(2 * i, member);
+                lp.insert(2 * i + 1, &score.0.to_le_bytes());
+                old
+            }
+            Members::Skiplist { scores, ordered } => {
+                let old = scores.insert(member.clone(), score);
+                if let Some(old) = old {
+                    ordered.remove(&(old, member.clone()));
+                }
+                ordered.insert((score, member));
+                old.map(|old| old.0)
+            }
+        }
+    }
+
+    /// Removes the member, returning its score if it was in the set.
+    pub fn remove(&mut self, member: &BulkString) -> Option<f64> {
+        match &mut self.members {
+            Members::Listpack(lp) => {
+                let (i, score) = position(lp, member)?;
+                lp.remove(2 * i);
+                lp.remove(2 * i);
+                Some(score)
+            }
+            Members::Skiplist { scores, ordered } => {
+                let (member, score) = scores.remove_entry(member)?;
+                ordered.remove(&(score, member));
+                Some(score.0)
+            }
+        }
+    }
+
+    /// Returns the 0-based position of the member from the lowest score. The members before
+    /// it are counted one by one, so this takes time linear in the rank rather than the
+    /// logarithmic time of the skiplist of Redis.
+    pub fn rank(&self, member: &BulkString) -> Option<usize> {
+        match &self.members {
+            Members::Listpack(lp) => position(lp, member).map(|(i, _)| i),
+            Members::Skiplist { scores, ordered } => {
+                let (member, score) = scores.get_key_value(member)?;
+                Some(ordered.range(..(*score, member.clone())).count())
+            }
+        }
+    }
+
+    /// Iterates over the members from the lowest score.
+    pub fn iter(&self) -> Iter<'_> {
+        match &self.members {
+            Members::Listpack(lp) => Iter::Listpack(lp.pairs()),
+            Members::Skiplist { ordered, .. } => Iter::Skiplist(ordered.iter()),
+        }
+    }
+
+    /// Moves the members of a packed set into the map and the ordered set.
+    fn convert(&mut self) {
+        if let Members::Listpack(lp) = &self.members {
+            let mut scores = HashMap::with_capacity(lp.len() / 2);
+            let mut ordered = BTreeSet::new();
+            for (member, score) in lp.pairs() {
+                let member = BulkString::from(member.to_vec());
+                scores.insert(member.clone(), read_score(score));
+                ordered.insert((read_score(score), member));
+            }
+            self.members = Members::Skiplist { scores, ordered };
+        }
+    }
+}
+
+/// Two sorted sets are equal if they have the same members with the same scores, however
+/// they are encoded.
+impl PartialEq for SortedSet {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+impl Eq for SortedSet {}
+
+/// Returns the index of the pair of the member in a packed set, and its score.
+fn position(lp: &Listpack, member: &BulkString) -> Option<(usize, f64)> {
+    lp.pairs()
+        .enumerate()
+        .find(|(_, (m, _))| *m == bytes(member))
+        .map(|(i, (_, score))| (i, read_score(score).0))
+}
+
+fn read_score(bytes: &[u8]) -> Score {
+    Score(f64::from_le_bytes(
+        bytes.try_into().expect("Packed scores are 8 bytes"),
+    ))
+}
+
+fn bytes(s: &BulkString) -> &[u8] {
+    s.as_bytes().unwrap_or_default()
+}
+
+/// Iterates over the members of a sorted set and their scores, from either end.
+#[derive(Debug)]
+pub enum Iter<'a> {
+    Listpack(Pairs<'a>),
+    Skiplist(btree_set::Iter<'a, (Score, BulkString)>),
+}
+
+impl Iterator for Iter<'_> {
+    type Item = (BulkString, f64);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Self::Listpack(pairs) => pairs
+                .next()
+                .map(|(member, score)| (member.to_vec().into(), read_score(score).0)),
+            Self::Skiplist(iter) => iter.next().map(|(score, member)| (member.clone(), score.0)),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self {
+            Self::Listpack(pairs) => pairs.size_hint(),
+            Self::Skiplist(iter) => iter.size_hint(),
+        }
+    }
+}
+
+impl DoubleEndedIterator for Iter<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        match self {
+            Self::Listpack(pairs) => pairs
+                .next_back()
+                .map(|(member, score)| (member.to_vec().into(), read_score(score).0)),
+            Self::Skiplist(iter) => iter
+                .next_back()
+                .map(|(score, member)| (member.clone(), score.0)),
+        }
+    }
+}
+
+impl FromIterator<(BulkString, f64)> for SortedSet {
+    fn from_iter<I: IntoIterator<Item = (BulkString, f64)>>(iter: I) -> Self {
+        let mut zset = Self::new();
+        for (member, score) in iter {
+            zset.insert(member, score, ListpackLimits::DEFAULT);
+        }
+        zset
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn ordered_by_score_then_member() {
+        let skiplist = ListpackLimits {
+            entries: 0,
+            value: 64,
+        };
+        for limits in [ListpackLimits::DEFAULT, skiplist] {
+            let mut zset = SortedSet::new();
+            for (member, score) in [("b", 1.0), ("a", 1.0), ("c", -0.0), ("d", f64::INFINITY)] {
+                zset.insert(member.into(), score, limits);
+            }
+            assert_eq!(zset.insert("d".into(), 0.0, limits), Some(f64::INFINITY));
+            assert_eq!(zset.remove(&"b".into()), Some(1.0));
+            assert_eq!(zset.remove(&"b".into()), None);
+
+            let members: Vec<_> = zset
+                .iter()
+                .map(|(member, score)| (member.as_str().unwrap(), score))
+                .collect();
+            assert_eq!(
+                members,
+                vec![
+                    ("c".to_string(), 0.0),
+                    ("d".to_string(), 0.0),
+                    ("a".to_string(), 1.0)
+                ]
+            );
+            assert_eq!(zset.iter().next_back(), Some(("a".into(), 1.0)));
+            assert_eq!(zset.score(&"a".into()), Some(1.0));
+            assert_eq!(zset.rank(&"d".into()), Some(1));
+            assert_eq!(zset.rank(&"b".into()), None);
+            assert_eq!(zset.len(), 3);
+        }
+    }
+
+    #[test]
+    fn converted_past_the_listpack_limits() {
+        let limits = ListpackLimits {
+            entries: 2,
+            value: 4,
+        };
+        let mut zset = SortedSet::new();
+        zset.insert("b".into(), 2.0, limits);
+        zset.insert("a".into(), 1.0, limits);
+        // Updating a member doesn't add one.
+        zset.insert("a".into(), 3.0, limits);
+        assert_eq!(zset.encoding(), "listpack");
+        let packed = zset.clone();
+
+        zset.insert("c".into(), 0.0, limits);
+        assert_eq!(zset.encoding(), "skiplist");
+        assert_eq!(
+            zset.iter().map(|(_, score)| score).collect::<Vec<_>>(),
+            [0.0, 2.0, 3.0]
+        );
+        // Removing members doesn't pack the set again.
+        zset.remove(&"c".into());
+        assert_eq!(zset.encoding(), "skiplist");
+        assert_eq!(zset, packed);
+
+        let mut zset = SortedSet::new();
+        zset.insert("long".into(), 1.0, limits);
+        assert_eq!(zset.encoding(), "listpack");
+        zset.insert("longer".into(), 1.0, limits);
+        assert_eq!(zset.encoding(), "skiplist");
+    }
+}
@@ -0,0 +1,149 @@
+use std::io;
+use std::time::Duration;
+
+use thiserror::Error;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt};
+use tokio::time::Instant;
+
+use crate::cli::split_args;
+use crate::redis::client::{ClientError, RedisClient};
+
+#[derive(Debug, Error)]
+pub enum ReplayError {
+    #[error("Invalid capture line {0}")]
+    InvalidLine(usize),
+
+    #[error(transparent)]
+    Client(#[from] ClientError),
+
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+/// Counts of a finished replay.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Replayed {
+    pub commands: usize,
+
+    /// Commands the server replied to with an error, which the captured run may have too.
+    pub errors: usize,
+}
+
+/// Sends the commands of a capture made with `--capture-file` to the server, keeping the
+/// time between them divided by `speed`: 1 for the original pace, 2 for twice as fast, and
+/// 0 to send them without waiting.
+pub async fn run(
+    client: &mut RedisClient,
+    input: impl AsyncBufRead + Unpin,
+    speed: f64,
+) -> Result<Replayed, ReplayError> {
+    let mut lines = input.lines();
+    let mut replayed = Replayed::default();
+    // When the first command was captured and when it was replayed.
+    let mut start: Option<(Duration, Instant)> = None;
+    let mut line_no = 0;
+    while let Some(line) = lines.next_line().await? {
+        line_no += 1;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (at, args) = parse_line(&line).ok_or(ReplayError::InvalidLine(line_no))?;
+
+        match start {
+            Some((first_at, started)) if speed > 0.0 => {
+                let offset = at.saturating_sub(first_at).div_f64(speed);
+                tokio::time::sleep_until(started + offset).await;
+            }
+            Some(_) => (),
+            None => start = Some((at, Instant::now())),
+        }
+        match client.command(args).await {
+            Ok(_) => (),
+            Err(ClientError::Server(_)) => replayed.errors += 1,
+            Err(e) => return Err(e.into()),
+        }
+        replayed.commands += 1;
+    }
+    Ok(replayed)
+}
+
+/// Parses a line of a capture into the time of the command and its arguments.
+fn parse_line(line: &str) -> Option<(Duration, Vec<Vec<u8>>)> {
+    let (micros, args) = line.split_once(' ')?;
+    let args = split_args(args).ok()?;
+    if args.is_empty() {
+        return None;
+    }
+    Some((Duration::from_micros(micros.parse().ok()?), args))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::redis::{Redis, RedisConfig};
+
+    use super::*;
+
+    #[test]
+    fn parse_capture_lines() {
+        let (at, args) = parse_line(r#"1500 "SET" "a key" "x\ny""#).expect("Parse failed");
+        assert_eq!(at, Duration::from_micros(1500));
+        assert_eq!(args, [&b"SET"[..], b"a key", b"x\ny"]);
+
+        assert_eq!(parse_line("1500"), None);
+        assert_eq!(parse_line("soon \"GET\" \"key\""), None);
+        assert_eq!(parse_line("1500 \"GET"), None);
+    }
+
+    #[tokio::test]
+    async fn replay_capture() {
+        let path = std::env::temp_dir().join(format!("capture-test-{}.log", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let captured = Redis::spawn(RedisConfig {
+            capture_file: Some(path.clone()),
+            ..RedisConfig::default()
+        })
+        .await
+        .expect("Spawn unexpected error");
+        let mut client = RedisClient::connect(captured.addr())
+            .await
+            .expect("Connect unexpected error");
+        client
+            .set("key", "a\r\nb")
+            .await
+            .expect("Set unexpected error");
+        client
+            .command(["config", "set", "nosuchparameter", "1"])
+            .await
+            .expect_err("Config set no error");
+        client
+            .command(["nosuchcommand"])
+            .await
+            .expect_err("Command no error");
+        captured
+            .shutdown()
+            .await
+            .expect("Shutdown unexpected error");
+
+        let server = Redis::spawn(RedisConfig::default())
+            .await
+            .expect("Spawn unexpected error");
+        let mut client = RedisClient::connect(server.addr())
+            .await
+            .expect("Connect unexpected error");
+        let capture = std::fs::read(&path).expect("Read unexpected error");
+        std::fs::remove_file(&path).expect("Remove unexpected error");
+        let replayed = run(&mut client, &capture[..], 0.0)
+            .await
+            .expect("Replay unexpected error");
+        // The unknown command isn't captured.
+        assert_eq!(
+            replayed,
+            Replayed {
+                commands: 2,
+                errors: 1
+            }
+        );
+        let value = client.get("key").await.expect("Get unexpected error");
+        assert_eq!(value, Some("a\r\nb".into()));
+    }
+}
@@ -0,0 +1,146 @@
+use std::env;
+use std::io;
+use std::net::TcpListener;
+#[cfg(unix)]
+use std::ops::Range;
+#[cfg(unix)]
+use std::os::unix::io::RawFd;
+
+/// First file descriptor systemd passes sockets from, after stdin, stdout and stderr.
+#[cfg(unix)]
+const LISTEN_FDS_START: RawFd = 3;
+
+/// Takes the sockets systemd passed to the process when started by a socket unit, as
+/// announced by `LISTEN_PID` and `LISTEN_FDS`, and returns them as listeners. Returns none
+/// if the process wasn't socket activated.
+///
+/// The variables are removed so child processes don't take the sockets too, which makes
+/// this safe to call only once and before any threads are started.
+#[cfg(unix)]
+pub fn listen_fds() -> io::Result<Vec<TcpListener>> {
+    use std::os::unix::io::FromRawFd;
+
+    let fds = passed_fds(
+        env::var("LISTEN_PID").ok().as_deref(),
+        env::var("LISTEN_FDS").ok().as_deref(),
+        std::process::id(),
+    );
+    env::remove_var("LISTEN_PID");
+    env::remove_var("LISTEN_FDS");
+    env::remove_var("LISTEN_FDNAMES");
+
+    let mut listeners = Vec::new();
+    for fd in fds.unwrap_or_default() {
+        // SAFETY: systemd passed the file descriptor for the process to own, and nothing
+        // else in the process took it as the variables announcing it were just removed.
+        let socket = unsafe { socket2::Socket::from_raw_fd(fd) };
+        socket.set_cloexec(true)?;
+        let is_inet = socket.local_addr()?.as_socket().is_some();
+        if socket.r#type()? != socket2::Type::STREAM || !is_inet {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("passed file descriptor {fd} isn't a TCP socket"),
+            ));
+        }
+        listeners.push(socket.into());
+    }
+    Ok(listeners)
+}
+
+#[cfg(not(unix))]
+pub fn listen_fds() -> io::Result<Vec<TcpListener>> {
+    Ok(Vec::new())
+}
+
+/// Returns the file descriptors passed to the process `pid`, or none if the variables are
+/// missing, invalid or meant for another process.
+#[cfg(unix)]
+fn passed_fds(
+    listen_pid: Option<&str>,
+    listen_fds: Option<&str>,
+    pid: u32,
+) -> Option<Range<RawFd>> {
+    if listen_pid?.parse::<u32>().ok()? != pid {
+        return None;
+    }
+    let count: RawFd = listen_fds?.parse().ok()?;
+    Some(LISTEN_FDS_START..LISTEN_FDS_START.checked_add(count)?)
+}
+
+/// Tells systemd about the state of the service, e.g. `READY=1` once it accepts
+/// connections or `STOPPING=1` when it shuts down. Returns false if the service wasn't
+/// started with `Type=notify`, which leaves nothing to tell.
+pub fn notify(state: &str) -> io::Result<bool> {
+    match env::var_os("NOTIFY_SOCKET") {
+        Some(path) => notify_socket(&path, state).map(|_| true),
+        None => Ok(false),
+    }
+}
+
+/// Sends the state to the datagram socket at `path`, which is in the abstract namespace if
+/// it starts with `@`.
+#[cfg(unix)]
+fn notify_socket(path: &std::ffi::OsStr, state: &str) -> io::Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::net::UnixDatagram;
+
+    let socket = UnixDatagram::unbound()?;
+    match path.as_bytes().strip_prefix(b"@") {
+        #[cfg(target_os = "linux")]
+        Some(name) => {
+            use std::os::linux::net::SocketAddrExt;
+
+            let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+            socket.send_to_addr(state.as_bytes(), &addr)?;
+        }
+        #[cfg(not(target_os = "linux"))]
+        Some(_) => {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "abstract sockets are only supported on linux",
+            ))
+        }
+        None => {
+            socket.send_to(state.as_bytes(), path)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn notify_socket(_path: &std::ffi::OsStr, _state: &str) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "notifying systemd is only supported on unix",
+    ))
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use std::os::unix::net::UnixDatagram;
+
+    use super::*;
+
+    #[test]
+    fn passed_fds_for_own_pid() {
+        assert_eq!(passed_fds(Some("42"), Some("2"), 42), Some(3..5));
+        assert_eq!(passed_fds(Some("42"), Some("0"), 42), Some(3..3));
+        assert_eq!(passed_fds(Some("41"), Some("2"), 42), None);
+        assert_eq!(passed_fds(None, Some("2"), 42), None);
+        assert_eq!(passed_fds(Some("42"), None, 42), None);
+        assert_eq!(passed_fds(Some("42"), Some("two"), 42), None);
+    }
+
+    #[test]
+    fn notify_sends_state() {
+        let path = env::temp_dir().join(format!("notify-test-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let socket = UnixDatagram::bind(&path).expect("Bind unexpected error");
+
+        notify_socket(path.as_os_str(), "READY=1").expect("Notify unexpected error");
+        let mut buf = [0; 16];
+        let len = socket.recv(&mut buf).expect("Recv unexpected error");
+        assert_eq!(&buf[..len], b"READY=1");
+        std::fs::remove_file(&path).expect("Remove unexpected error");
+    }
+}
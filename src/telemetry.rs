@@ -0,0 +1,46 @@
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_otlp::{ExporterBuildError, SpanExporter};
+use opentelemetry_sdk::trace::{SdkTracer, SdkTracerProvider};
+use opentelemetry_sdk::Resource;
+use tracing::{error, Subscriber};
+use tracing_opentelemetry::OpenTelemetryLayer;
+use tracing_subscriber::registry::LookupSpan;
+
+/// Flushes the spans that are still buffered when dropped, so spans from the last moments
+/// before exiting are exported too.
+pub struct OtelGuard {
+    provider: SdkTracerProvider,
+}
+
+impl Drop for OtelGuard {
+    fn drop(&mut self) {
+        if let Err(e) = self.provider.shutdown() {
+            error!("Shut down OpenTelemetry error: {e}");
+        }
+    }
+}
+
+/// Returns a layer exporting spans to an OTLP collector over HTTP. The exporter is
+/// configured by the standard `OTEL_*` environment variables, e.g.
+/// `OTEL_EXPORTER_OTLP_ENDPOINT` and `OTEL_SERVICE_NAME`.
+pub fn layer<S>() -> Result<(OpenTelemetryLayer<S, SdkTracer>, OtelGuard), ExporterBuildError>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    let exporter = SpanExporter::builder().with_http().build()?;
+
+    let mut resource = Resource::builder();
+    if std::env::var_os("OTEL_SERVICE_NAME").is_none() {
+        resource = resource.with_service_name("redis");
+    }
+    let provider = SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(resource.build())
+        .build();
+
+    let tracer = provider.tracer("redis");
+    Ok((
+        tracing_opentelemetry::layer().with_tracer(tracer),
+        OtelGuard { provider },
+    ))
+}
@@ -1,35 +1,108 @@
 use rand::distributions::DistString;
 
-/// Buffer is a wrapper for io::Write.
-pub struct Buffer<W> {
-    pub inner: W,
-    pub count: usize,
-}
-
-impl<W> Buffer<W>
+/// Spawns `future` as a task named `name`, so it can be told apart in tokio-console.
+///
+/// Names need the `console` feature and `--cfg tokio_unstable`, otherwise this is `tokio::spawn`.
+#[track_caller]
+pub fn spawn_named<F>(name: &str, future: F) -> tokio::task::JoinHandle<F::Output>
 where
-    W: std::io::Write,
+    F: std::future::Future + Send + 'static,
+    F::Output: Send + 'static,
 {
-    pub fn new(inner: W) -> Self {
-        Self { inner, count: 0 }
+    #[cfg(all(feature = "console", tokio_unstable))]
+    {
+        tokio::task::Builder::new()
+            .name(name)
+            .spawn(future)
+            .expect("Spawn task error")
+    }
+    #[cfg(not(all(feature = "console", tokio_unstable)))]
+    {
+        let _ = name;
+        tokio::spawn(future)
     }
 }
 
-impl<W> std::io::Write for Buffer<W>
-where
-    W: std::io::Write,
-{
-    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
-        let len = self.inner.write(buf)?;
-        self.count += len;
-        Ok(len)
-    }
+pub fn generate_random_alphanumeric_string(len: usize) -> String {
+    rand::distributions::Alphanumeric.sample_string(&mut rand::thread_rng(), len)
+}
+
+/// Matches `s` against a Redis style glob `pattern`.
+///
+/// Supports `*` (any sequence), `?` (any single byte), `[abc]`/`[a-z]` classes with optional
+/// leading `^` for negation, and `\` to escape the next byte.
+pub fn glob_match(pattern: &[u8], s: &[u8]) -> bool {
+    match pattern.first() {
+        None => s.is_empty(),
+        Some(b'*') => {
+            // Try to match the rest of the pattern at every position.
+            let rest = &pattern[1..];
+            if rest.is_empty() {
+                return true;
+            }
+            (0..=s.len()).any(|i| glob_match(rest, &s[i..]))
+        }
+        Some(b'?') => !s.is_empty() && glob_match(&pattern[1..], &s[1..]),
+        Some(b'[') => {
+            let Some(&c) = s.first() else {
+                return false;
+            };
+            let mut i = 1;
+            let negate = pattern.get(i) == Some(&b'^');
+            if negate {
+                i += 1;
+            }
 
-    fn flush(&mut self) -> std::io::Result<()> {
-        self.inner.flush()
+            let mut matched = false;
+            while i < pattern.len() && pattern[i] != b']' {
+                if pattern[i] == b'\\' && i + 1 < pattern.len() {
+                    matched |= pattern[i + 1] == c;
+                    i += 2;
+                } else if i + 2 < pattern.len() && pattern[i + 1] == b'-' && pattern[i + 2] != b']'
+                {
+                    let (lo, hi) = (
+                        pattern[i].min(pattern[i + 2]),
+                        pattern[i].max(pattern[i + 2]),
+                    );
+                    matched |= lo <= c && c <= hi;
+                    i += 3;
+                } else {
+                    matched |= pattern[i] == c;
+                    i += 1;
+                }
+            }
+
+            // Unterminated class is treated as matching up to the end of the pattern.
+            let rest = if i < pattern.len() {
+                &pattern[i + 1..]
+            } else {
+                &pattern[i..]
+            };
+            matched != negate && glob_match(rest, &s[1..])
+        }
+        Some(b'\\') if pattern.len() > 1 => {
+            s.first() == Some(&pattern[1]) && glob_match(&pattern[2..], &s[1..])
+        }
+        Some(&p) => s.first() == Some(&p) && glob_match(&pattern[1..], &s[1..]),
     }
 }
 
-pub fn generate_random_alphanumeric_string(len: usize) -> String {
-    rand::distributions::Alphanumeric.sample_string(&mut rand::thread_rng(), len)
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn glob_match_patterns() {
+        assert!(glob_match(b"*", b"anything"));
+        assert!(glob_match(b"max*", b"maxmemory"));
+        assert!(glob_match(b"h?llo", b"hello"));
+        assert!(glob_match(b"h[ae]llo", b"hallo"));
+        assert!(glob_match(b"h[^e]llo", b"hallo"));
+        assert!(glob_match(b"h[a-c]llo", b"hbllo"));
+        assert!(glob_match(b"h\\*llo", b"h*llo"));
+
+        assert!(!glob_match(b"max*", b"port"));
+        assert!(!glob_match(b"h[^e]llo", b"hello"));
+        assert!(!glob_match(b"h?llo", b"hllo"));
+    }
 }
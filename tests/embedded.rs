@@ -0,0 +1,93 @@
+use std::time::Duration;
+
+use redis_starter_rust::redis::resp::BulkString;
+use redis_starter_rust::redis::store::SnapshotValue;
+use redis_starter_rust::redis::{Redis, RedisConfig};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+async fn round_trip(stream: &mut TcpStream, request: &[u8], reply_len: usize) -> Vec<u8> {
+    stream
+        .write_all(request)
+        .await
+        .expect("Write unexpected error");
+    let mut reply = vec![0; reply_len];
+    stream
+        .read_exact(&mut reply)
+        .await
+        .expect("Read unexpected error");
+    reply
+}
+
+#[tokio::test]
+async fn spawn_serves_clients_until_shutdown() {
+    let server = Redis::spawn(RedisConfig::default())
+        .await
+        .expect("Spawn unexpected error");
+    let addr = server.addr();
+    assert!(addr.ip().is_loopback());
+    assert_ne!(addr.port(), 0);
+
+    let mut stream = TcpStream::connect(addr)
+        .await
+        .expect("Connect unexpected error");
+    let set = b"*3\r\n$3\r\nSET\r\n$3\r\nkey\r\n$5\r\nvalue\r\n";
+    assert_eq!(round_trip(&mut stream, set, 5).await, b"+OK\r\n");
+    let get = b"*2\r\n$3\r\nGET\r\n$3\r\nkey\r\n";
+    assert_eq!(round_trip(&mut stream, get, 11).await, b"$5\r\nvalue\r\n");
+    drop(stream);
+
+    server.shutdown().await.expect("Shutdown unexpected error");
+    assert!(TcpStream::connect(addr).await.is_err());
+}
+
+#[tokio::test]
+async fn spawned_servers_are_independent() {
+    let first = Redis::spawn(RedisConfig::default())
+        .await
+        .expect("Spawn unexpected error");
+    let second = Redis::spawn(RedisConfig::default())
+        .await
+        .expect("Spawn unexpected error");
+    assert_ne!(first.addr(), second.addr());
+
+    let mut stream = TcpStream::connect(first.addr())
+        .await
+        .expect("Connect unexpected error");
+    let set = b"*3\r\n$3\r\nSET\r\n$3\r\nkey\r\n$5\r\nvalue\r\n";
+    round_trip(&mut stream, set, 5).await;
+
+    let mut stream = TcpStream::connect(second.addr())
+        .await
+        .expect("Connect unexpected error");
+    let get = b"*2\r\n$3\r\nGET\r\n$3\r\nkey\r\n";
+    assert_eq!(round_trip(&mut stream, get, 5).await, b"$-1\r\n");
+}
+
+#[tokio::test]
+async fn snapshot_iter_exports_keyspace() {
+    let server = Redis::spawn(RedisConfig::default())
+        .await
+        .expect("Spawn unexpected error");
+    let mut stream = TcpStream::connect(server.addr())
+        .await
+        .expect("Connect unexpected error");
+    let set = b"*3\r\n$3\r\nSET\r\n$3\r\nkey\r\n$5\r\nvalue\r\n";
+    round_trip(&mut stream, set, 5).await;
+    let set = b"*5\r\n$3\r\nSET\r\n$4\r\ntemp\r\n$1\r\nx\r\n$2\r\nPX\r\n$6\r\n100000\r\n";
+    round_trip(&mut stream, set, 5).await;
+
+    let mut entries: Vec<_> = server.store().snapshot_iter().collect();
+    entries.sort_by(|a, b| a.key.cmp(&b.key));
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].key.as_bytes(), b"key");
+    assert_eq!(
+        entries[0].value,
+        SnapshotValue::String(BulkString::from("value"))
+    );
+    assert_eq!(entries[0].ttl, None);
+    assert_eq!(entries[1].key.as_bytes(), b"temp");
+    assert!(entries[1]
+        .ttl
+        .is_some_and(|ttl| ttl <= Duration::from_secs(100)));
+}
@@ -0,0 +1,41 @@
+use std::time::Duration;
+
+use redis_starter_rust::redis::cmd::{Command, Ping, PingArg};
+use redis_starter_rust::redis::resp::{BulkString, Value};
+use redis_starter_rust::redis::test_util::{self, ScriptedResponder};
+
+#[tokio::test]
+async fn client_against_scripted_responder() {
+    let mut responder =
+        ScriptedResponder::new().expect(test_util::request(["PING"]), test_util::simple("PONG"));
+
+    Ping::client(&mut responder)
+        .ping(PingArg { msg: None })
+        .await
+        .expect("Ping unexpected error");
+    assert!(responder.is_done());
+}
+
+#[test]
+fn handler_without_sockets() {
+    let (mut handler, clock) = test_util::command_handler_with_clock();
+    let mut client = test_util::client_state();
+    let mut handle = |args: &[&str]| {
+        let cmd = Command::try_from(test_util::command(args.iter().copied()))
+            .expect("Parse unexpected error");
+        handler
+            .handle(cmd, &mut client)
+            .expect("Handle unexpected error")
+    };
+
+    assert_eq!(
+        handle(&["SET", "key", "value", "PX", "100"]),
+        test_util::simple("OK")
+    );
+    assert_eq!(handle(&["GET", "key"]), test_util::bulk("value"));
+    clock.advance(Duration::from_millis(200));
+    assert_eq!(
+        handle(&["GET", "key"]),
+        Value::BulkString(BulkString::null())
+    );
+}